@@ -0,0 +1,44 @@
+//! Benchmarks of the software renderer blitting primitives.
+//!
+//! Run with `cargo bench --bench blit`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// The crate has no library target, the module is self-contained.
+#[allow(dead_code)]
+#[path = "../src/graphics/render/software/blit.rs"]
+mod blit;
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+
+/// Mix of transparent runs and opaque pixels.
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len)
+        .map(|i| {
+            let v = (i * 7) % 23;
+            if v < 9 || (i / 40) % 3 == 0 { 0 } else { v as u8 * 11 }
+        })
+        .collect()
+}
+
+fn copy_keyed_1080p(c: &mut Criterion) {
+    let src = pattern(WIDTH * HEIGHT);
+    let mut dst = vec![1; WIDTH * HEIGHT];
+
+    let mut group = c.benchmark_group("copy_keyed_1080p");
+    group.bench_function("scalar", |b| b.iter(|| {
+        for (d, s) in dst.chunks_mut(WIDTH).zip(src.chunks(WIDTH)) {
+            blit::copy_keyed_scalar(d, black_box(s));
+        }
+    }));
+    group.bench_function("simd", |b| b.iter(|| {
+        for (d, s) in dst.chunks_mut(WIDTH).zip(src.chunks(WIDTH)) {
+            blit::copy_keyed(d, black_box(s));
+        }
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, copy_keyed_1080p);
+criterion_main!(benches);
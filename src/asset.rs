@@ -1,21 +1,78 @@
+pub mod acm;
+pub mod death_ending;
+pub mod endgame;
 pub mod font;
 pub mod frame;
+pub mod holodisk;
 pub mod map;
 pub mod message;
+pub mod mve;
 pub mod palette;
 pub mod proto;
+pub mod quest;
 pub mod script;
+pub mod sve;
 
 use enum_map_derive::Enum;
 use enum_primitive_derive::Primitive;
-use std::collections::HashMap;
+use log::*;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Error, ErrorKind};
 use std::io::prelude::*;
 use enumflags2::bitflags;
 
+use crate::error::{self, ResultExt};
+use crate::fs::FileSystem;
 use crate::graphics::EPoint;
 use crate::graphics::geometry::hex::Direction;
 
+/// Game whose data files are used.
+///
+/// Fallout 1 data can only be browsed, hence its `fallout1-browse` name: the DAT1 archives,
+/// `fallout.cfg`, the critter prototypes without the unarmed damage kind and the dude art are
+/// read. The map list is built into the Fallout 1 executable so map exits don't work, and the map,
+/// script and world map format differences aren't handled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Game {
+    Fallout1,
+    Fallout2,
+}
+
+impl Game {
+    /// Values of the `--game` option.
+    pub const NAMES: &'static [&'static str] = &["fallout1-browse", "fallout2"];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match &s.to_ascii_lowercase()[..] {
+            "fallout1-browse" => Self::Fallout1,
+            "fallout2" => Self::Fallout2,
+            _ => return None,
+        })
+    }
+
+    /// Name of the config file in the resource dir.
+    pub fn config_file(self) -> &'static str {
+        match self {
+            Self::Fallout1 => "fallout.cfg",
+            Self::Fallout2 => "fallout2.cfg",
+        }
+    }
+
+    /// Base name of the dude's critter art shown when no armor is worn.
+    pub fn dude_naked_art(self) -> &'static str {
+        match self {
+            Self::Fallout1 => "hmjmps",
+            Self::Fallout2 => "hmwarr",
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::Fallout2
+    }
+}
+
 #[derive(Clone, Copy, Debug, Enum, Eq, PartialEq, Ord, PartialOrd, Primitive)]
 pub enum EntityKind {
     Item = 0x0,
@@ -197,6 +254,62 @@ impl CritterAnim {
         self >= Self::FallBack && self <= Self::FallFrontBlood ||
             self >= Self::FallBackSf && self <= Self::FallFrontBloodSf
     }
+
+    /// Whether this animation exists only for critters holding a weapon.
+    pub fn is_weapon(self) -> bool {
+        self >= Self::TakeOut && self <= Self::FireContinuous
+    }
+
+    /// Single-frame variant of the knockdown or death animation showing the critter lying on
+    /// the ground.
+    pub fn single_frame(self) -> Option<Self> {
+        use CritterAnim::*;
+        Some(match self {
+            FallBack => FallBackSf,
+            FallFront => FallFrontSf,
+            BadLanding => BadLandingSf,
+            BigHole => BigHoleSf,
+            CharredBody => CharredBodySf,
+            ChunksOfFlesh => ChunksOfFleshSf,
+            DancingAutofire => DancingAutofireSf,
+            Electrify => ElectrifySf,
+            SlicedInHalf => SlicedInHalfSf,
+            BurnedToNothing => BurnedToNothingSf,
+            ElectrifiedToNothing => ElectrifiedToNothingSf,
+            ExplodedToNothing => ExplodedToNothingSf,
+            MeltedToNothing => MeltedToNothingSf,
+            FallBackBlood => FallBackBloodSf,
+            FallFrontBlood => FallFrontBloodSf,
+            FireDance => return None,
+            _ if self.is_prone() => self,
+            _ => return None,
+        })
+    }
+
+    /// Animation to use when the critter doesn't have this one. Following the fallbacks
+    /// repeatedly always terminates.
+    pub fn fallback(self) -> Option<Self> {
+        use CritterAnim::*;
+        Some(match self {
+            Running => Walk,
+            HitFromBack => HitFromFront,
+            KickLeg => ThrowPunch,
+            SwingAnim => ThrustAnim,
+            FireBurst | FireContinuous => FireSingle,
+
+            FallFrontBlood => FallFront,
+            BadLanding | BigHole | CharredBody | ChunksOfFlesh | DancingAutofire | Electrify
+                | SlicedInHalf | BurnedToNothing | ElectrifiedToNothing | ExplodedToNothing
+                | MeltedToNothing | FireDance | FallBackBlood => FallBack,
+
+            FallFrontBloodSf => FallFrontSf,
+            BadLandingSf | BigHoleSf | CharredBodySf | ChunksOfFleshSf | DancingAutofireSf
+                | ElectrifySf | SlicedInHalfSf | BurnedToNothingSf | ElectrifiedToNothingSf
+                | ExplodedToNothingSf | MeltedToNothingSf | FallBackBloodSf => FallBackSf,
+
+            _ => return None,
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug, Enum, Eq, PartialEq, Primitive)]
@@ -717,6 +830,62 @@ pub fn read_lst(rd: &mut impl BufRead) -> io::Result<Vec<LstEntry>> {
     Ok(r)
 }
 
+/// Reads LST file at `path` and the additional LST files put next to it by mods. Entries are
+/// keyed by index starting from `first`. Entries with index above `max` are dropped.
+///
+/// Each mod LST file gives the index of its first entry in the file name: entries of
+/// `mymod.5000.lst` get indexes from 5000 on. This keeps the indexes stable no matter which other
+/// mods are installed. Files without the index are ignored. Entries overlapping the ones already
+/// read are an error.
+pub fn read_lst_ext(fs: &FileSystem, path: &str, first: u32, max: u32)
+    -> error::Result<BTreeMap<u32, LstEntry>>
+{
+    let mut r = BTreeMap::new();
+    let read = |path: &str| -> error::Result<Vec<LstEntry>> {
+        read_lst(&mut fs.reader(path)?).context(|| format!("reading {}", path))
+    };
+    let mut insert = |entries: Vec<LstEntry>, first: u32, path: &str| -> error::Result<()> {
+        for (idx, entry) in (first..).zip(entries) {
+            if idx > max {
+                warn!("{}: too many LST entries, ignoring the ones above {}", path, max);
+                break;
+            }
+            if r.insert(idx, entry).is_some() {
+                return Err(error::Error::invalid_data(
+                    format!("LST entry {} is already used", idx))
+                    .context(format!("reading {}", path)));
+            }
+        }
+        Ok(())
+    };
+    insert(read(path)?, first, path)?;
+
+    let (dir, _) = path.split_at(path.rfind('/').unwrap_or(0));
+    for ext_path in fs.loose_files(dir, "lst") {
+        if ext_path.eq_ignore_ascii_case(path) {
+            continue;
+        }
+        let ext_first = if let Some(v) = lst_ext_first_idx(&ext_path) {
+            v
+        } else {
+            warn!("{}: no first entry index in the file name (like `mymod.5000.lst`), ignoring",
+                ext_path);
+            continue;
+        };
+        info!("adding LST entries from {} starting at {}", ext_path, ext_first);
+        insert(read(&ext_path)?, ext_first, &ext_path)?;
+    }
+
+    Ok(r)
+}
+
+/// Parses index of the first entry from the mod LST file name: `mymod.5000.lst` -> 5000.
+fn lst_ext_first_idx(path: &str) -> Option<u32> {
+    let name = &path[path.rfind('/').map(|i| i + 1).unwrap_or(0)..];
+    let stem = &name[..name.rfind('.')?];
+    stem[stem.rfind('.')? + 1..].parse().ok()
+}
+
 pub fn read_gam(rd: &mut impl BufRead, tag: &str) -> io::Result<Vec<i32>> {
     let mut r = Vec::new();
     let mut lines = rd.lines();
@@ -804,6 +973,16 @@ mod test {
     use super::*;
     use std::io::{Cursor, BufReader};
 
+    #[test]
+    fn lst_ext_first_idx_() {
+        assert_eq!(lst_ext_first_idx("proto/items/mymod.5000.lst"), Some(5000));
+        assert_eq!(lst_ext_first_idx("art/items/MYMOD.0010.LST"), Some(10));
+        assert_eq!(lst_ext_first_idx("mymod.5000.lst"), Some(5000));
+        assert_eq!(lst_ext_first_idx("proto/items/mymod.lst"), None);
+        assert_eq!(lst_ext_first_idx("proto/items/mymod.x.lst"), None);
+        assert_eq!(lst_ext_first_idx("proto/it.500/mymod.lst"), None);
+    }
+
     #[test]
     fn read_game_global_vars_() {
         let s = "
@@ -880,4 +1059,30 @@ key2
         let act = read_ini(&mut BufReader::new(Cursor::new(inp))).unwrap();
         assert_eq!(act, exp_map);
     }
+
+    #[test]
+    fn critter_anim_fallback() {
+        use num_traits::cast::FromPrimitive;
+
+        assert_eq!(CritterAnim::Running.fallback(), Some(CritterAnim::Walk));
+        assert_eq!(CritterAnim::FireContinuous.fallback(), Some(CritterAnim::FireSingle));
+        assert_eq!(CritterAnim::MeltedToNothingSf.fallback(), Some(CritterAnim::FallBackSf));
+        assert_eq!(CritterAnim::Stand.fallback(), None);
+        assert_eq!(CritterAnim::ChunksOfFlesh.single_frame(), Some(CritterAnim::ChunksOfFleshSf));
+        assert_eq!(CritterAnim::FallFrontBloodSf.single_frame(),
+            Some(CritterAnim::FallFrontBloodSf));
+        assert_eq!(CritterAnim::FireDance.single_frame(), None);
+        assert_eq!(CritterAnim::Walk.single_frame(), None);
+
+        for i in 0..=CritterAnim::CalledShotPic as u8 {
+            let mut anim = CritterAnim::from_u8(i).unwrap();
+            let mut n = 0;
+            while let Some(a) = anim.fallback() {
+                assert_eq!(a.is_weapon(), anim.is_weapon());
+                anim = a;
+                n += 1;
+                assert!(n < 5);
+            }
+        }
+    }
 }
@@ -0,0 +1,460 @@
+//! Interplay ACM sounds (`sound/sfx/*.acm`, `sound/speech/*/*.acm`).
+//!
+//! The samples are coded in blocks of `rows` x `2^levels` values. Each column of a block is
+//! packed with one of the fillers below, then the block is run through the inverse subband
+//! transform (`juggle_block()`) which produces interleaved 16-bit PCM samples.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::cmp;
+use std::io::{self, Error, ErrorKind, prelude::*};
+use std::time::Duration;
+
+const SIGNATURE: u32 = 0x0103_2897;
+
+/// How many zero bytes are read past the end of the stream before it's considered truncated.
+const MAX_EOF_PADDING: u32 = 64;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Header {
+    /// Total number of samples of all channels.
+    pub sample_count: u32,
+    pub channels: u16,
+    pub sample_rate: u16,
+    /// Number of bits per subband level.
+    pub levels: u8,
+    /// Number of rows in a block.
+    pub rows: u16,
+}
+
+impl Header {
+    pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        if rd.read_u32::<LittleEndian>()? != SIGNATURE {
+            return Err(Error::new(ErrorKind::InvalidData, "not an ACM file"));
+        }
+        let sample_count = rd.read_u32::<LittleEndian>()?;
+        let channels = rd.read_u16::<LittleEndian>()?;
+        let sample_rate = rd.read_u16::<LittleEndian>()?;
+        let packed = rd.read_u16::<LittleEndian>()?;
+        let levels = (packed & 0xf) as u8;
+        let rows = packed >> 4;
+        if channels == 0 || sample_rate == 0 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("bad ACM format: {} channels at {} Hz", channels, sample_rate)));
+        }
+        Ok(Self {
+            sample_count,
+            channels,
+            sample_rate,
+            levels,
+            rows,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        let frames = self.sample_count as u64 / self.channels as u64;
+        Duration::from_micros(frames * 1_000_000 / self.sample_rate as u64)
+    }
+}
+
+/// Reads the bits least significant first.
+struct BitReader<R> {
+    rd: R,
+    data: u32,
+    avail: u32,
+    eof_padding: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    fn new(rd: R) -> Self {
+        Self {
+            rd,
+            data: 0,
+            avail: 0,
+            eof_padding: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> io::Result<u32> {
+        debug_assert!(bits <= 16);
+        while self.avail < bits {
+            let mut byte = [0];
+            if self.rd.read(&mut byte)? == 0 {
+                // The last block can end past the end of the stream.
+                self.eof_padding += 1;
+                if self.eof_padding > MAX_EOF_PADDING {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "truncated ACM file"));
+                }
+            }
+            self.data |= (byte[0] as u32) << self.avail;
+            self.avail += 8;
+        }
+        let r = self.data & ((1 << bits) - 1);
+        self.data >>= bits;
+        self.avail -= bits;
+        Ok(r)
+    }
+}
+
+const MAP_1BIT: [i32; 2] = [-1, 1];
+const MAP_2BIT_NEAR: [i32; 4] = [-2, -1, 1, 2];
+const MAP_2BIT_FAR: [i32; 4] = [-3, -2, 2, 3];
+const MAP_3BIT: [i32; 8] = [-4, -3, -2, -1, 1, 2, 3, 4];
+
+/// Decodes ACM stream into interleaved signed 16-bit samples.
+// libacm decode.c
+pub struct Decoder<R> {
+    rd: BitReader<R>,
+    header: Header,
+    block: Vec<i32>,
+    wrap_buf: Vec<i32>,
+    /// Scale of the current block: the value of the unit step.
+    step: i32,
+    /// Position of the next sample in `block`. Equals `block.len()` when the block is used up.
+    pos: usize,
+    /// Number of samples left in the stream.
+    left: u32,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(mut rd: R) -> io::Result<Self> {
+        let header = Header::read(&mut rd)?;
+        let cols = 1usize << header.levels;
+        let block_len = header.rows as usize * cols;
+        if block_len == 0 && header.sample_count > 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "bad ACM format: empty blocks"));
+        }
+        Ok(Self {
+            rd: BitReader::new(rd),
+            header,
+            block: vec![0; block_len],
+            wrap_buf: vec![0; 2 * cols - 2],
+            step: 0,
+            pos: block_len,
+            left: header.sample_count,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Decodes samples into `buf`. Returns the number of samples decoded which is zero if
+    /// the end of the stream is reached.
+    pub fn read(&mut self, buf: &mut [i16]) -> io::Result<usize> {
+        let mut count = 0;
+        while count < buf.len() && self.left > 0 {
+            if self.pos == self.block.len() {
+                self.read_block()?;
+            }
+            let n = cmp::min(cmp::min(buf.len() - count, self.block.len() - self.pos),
+                self.left as usize);
+            let src = &self.block[self.pos..self.pos + n];
+            for (dst, &src) in buf[count..count + n].iter_mut().zip(src) {
+                *dst = (src >> self.header.levels) as i16;
+            }
+            self.pos += n;
+            self.left -= n as u32;
+            count += n;
+        }
+        Ok(count)
+    }
+
+    /// Decodes all remaining samples.
+    pub fn read_to_end(&mut self) -> io::Result<Vec<i16>> {
+        // Don't trust the header with the preallocation.
+        let mut r = Vec::with_capacity(cmp::min(self.left as usize, 1 << 20));
+        let mut buf = [0; 4096];
+        loop {
+            let n = self.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            r.extend_from_slice(&buf[..n]);
+        }
+        Ok(r)
+    }
+
+    fn read_block(&mut self) -> io::Result<()> {
+        let _pwr = self.rd.read(4)?;
+        self.step = self.rd.read(16)? as i32;
+        for col in 0..1 << self.header.levels {
+            let filler = self.rd.read(5)?;
+            self.fill_column(filler, col)?;
+        }
+        self.juggle_block();
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn set(&mut self, row: usize, col: usize, value: i32) {
+        self.block[(row << self.header.levels) + col] = value.wrapping_mul(self.step);
+    }
+
+    /// Reads `rows` values of the column `col` packed with `filler`.
+    fn fill_column(&mut self, filler: u32, col: usize) -> io::Result<()> {
+        let rows = self.header.rows as usize;
+        let mut row = 0;
+        // Sets values to the next rows. The column can end in the middle of the values.
+        macro_rules! put {
+            ($($v:expr),+) => {{
+                $(
+                    if row < rows {
+                        let v = $v;
+                        self.set(row, col, v);
+                        row += 1;
+                    }
+                )+
+            }};
+        }
+        match filler {
+            // Zero.
+            0 => {
+                for row in 0..rows {
+                    self.set(row, col, 0);
+                }
+            }
+            // Linear.
+            3..=16 => {
+                let middle = 1 << (filler - 1);
+                while row < rows {
+                    put!(self.rd.read(filler)? as i32 - middle);
+                }
+            }
+            // k13, k24, k35, k45: 0 is two zeros, 10 is one zero.
+            17 | 20 | 23 | 26 => while row < rows {
+                if self.rd.read(1)? == 0 {
+                    put!(0, 0);
+                } else if self.rd.read(1)? == 0 {
+                    put!(0);
+                } else {
+                    let v = match filler {
+                        17 => MAP_1BIT[self.rd.read(1)? as usize],
+                        20 => MAP_2BIT_NEAR[self.rd.read(2)? as usize],
+                        23 => if self.rd.read(1)? == 0 {
+                            MAP_1BIT[self.rd.read(1)? as usize]
+                        } else {
+                            MAP_2BIT_FAR[self.rd.read(2)? as usize]
+                        },
+                        _ => MAP_3BIT[self.rd.read(3)? as usize],
+                    };
+                    put!(v);
+                }
+            }
+            // k12, k23, k34, k44: 0 is one zero.
+            18 | 21 | 24 | 27 => while row < rows {
+                if self.rd.read(1)? == 0 {
+                    put!(0);
+                } else {
+                    let v = match filler {
+                        18 => MAP_1BIT[self.rd.read(1)? as usize],
+                        21 => MAP_2BIT_NEAR[self.rd.read(2)? as usize],
+                        24 => if self.rd.read(1)? == 0 {
+                            MAP_1BIT[self.rd.read(1)? as usize]
+                        } else {
+                            MAP_2BIT_FAR[self.rd.read(2)? as usize]
+                        },
+                        _ => MAP_3BIT[self.rd.read(3)? as usize],
+                    };
+                    put!(v);
+                }
+            }
+            // t15: three values of [-1, 1] in 5 bits.
+            19 => while row < rows {
+                let b = self.rd.read(5)? as i32;
+                if b >= 3 * 3 * 3 {
+                    return Err(bad_filler(filler));
+                }
+                put!(b % 3 - 1, b / 3 % 3 - 1, b / 9 - 1);
+            }
+            // t27: three values of [-2, 2] in 7 bits.
+            22 => while row < rows {
+                let b = self.rd.read(7)? as i32;
+                if b >= 5 * 5 * 5 {
+                    return Err(bad_filler(filler));
+                }
+                put!(b % 5 - 2, b / 5 % 5 - 2, b / 25 - 2);
+            }
+            // t37: two values of [-5, 5] in 7 bits.
+            29 => while row < rows {
+                let b = self.rd.read(7)? as i32;
+                if b >= 11 * 11 {
+                    return Err(bad_filler(filler));
+                }
+                put!(b % 11 - 5, b / 11 - 5);
+            }
+            _ => return Err(bad_filler(filler)),
+        }
+        Ok(())
+    }
+
+    /// Inverse subband transform of the block.
+    fn juggle_block(&mut self) {
+        let levels = self.header.levels as usize;
+        if levels == 0 {
+            return;
+        }
+        let cols = 1 << levels;
+        let step_rows = if levels > 9 {
+            1
+        } else {
+            (2048 >> levels) - 2
+        };
+
+        let mut rows_left = self.header.rows as usize;
+        let mut block_start = 0;
+        loop {
+            let mut wrap_start = 0;
+            let mut sub_len = cols / 2;
+            let mut sub_count = cmp::min(step_rows, rows_left) * 2;
+            let block = &mut self.block[block_start..];
+
+            juggle(&mut self.wrap_buf[wrap_start..], block, sub_len, sub_count);
+            wrap_start += sub_len * 2;
+            for i in 0..sub_count {
+                block[i * sub_len] = block[i * sub_len].wrapping_add(1);
+            }
+
+            while sub_len > 1 {
+                sub_len /= 2;
+                sub_count *= 2;
+                juggle(&mut self.wrap_buf[wrap_start..], block, sub_len, sub_count);
+                wrap_start += sub_len * 2;
+            }
+
+            if rows_left <= step_rows {
+                break;
+            }
+            block_start += step_rows << levels;
+            rows_left -= step_rows;
+        }
+    }
+}
+
+fn juggle(wrap: &mut [i32], block: &mut [i32], sub_len: usize, sub_count: usize) {
+    for i in 0..sub_len {
+        let mut r0 = wrap[i * 2];
+        let mut r1 = wrap[i * 2 + 1];
+        let mut p = i;
+        for _ in 0..sub_count / 2 {
+            let r2 = block[p];
+            block[p] = r1.wrapping_mul(2).wrapping_add(r0.wrapping_add(r2));
+            p += sub_len;
+            let r3 = block[p];
+            block[p] = r2.wrapping_mul(2).wrapping_sub(r1.wrapping_add(r3));
+            p += sub_len;
+            r0 = r2;
+            r1 = r3;
+        }
+        wrap[i * 2] = r0;
+        wrap[i * 2 + 1] = r1;
+    }
+}
+
+fn bad_filler(filler: u32) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("bad ACM filler: {}", filler))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Writes the bits least significant first.
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        bits: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u32) -> &mut Self {
+            for i in 0..bits {
+                let shift = self.bits % 8;
+                if shift == 0 {
+                    self.data.push(0);
+                }
+                *self.data.last_mut().unwrap() |= (((value >> i) & 1) as u8) << shift;
+                self.bits += 1;
+            }
+            self
+        }
+    }
+
+    fn acm(sample_count: u32, levels: u32, rows: u32, blocks: &BitWriter) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.write(SIGNATURE, 16).write(SIGNATURE >> 16, 16)
+            .write(sample_count, 16).write(sample_count >> 16, 16)
+            .write(1, 16)
+            .write(22050, 16)
+            .write(levels, 4)
+            .write(rows, 12);
+        [w.data, blocks.data.clone()].concat()
+    }
+
+    #[test]
+    fn read_header() {
+        let data = b"\x97\x28\x03\x01\x20\x4e\x00\x00\x02\x00\x22\x56\x07\x01";
+        let h = Header::read(&mut Cursor::new(&data[..])).unwrap();
+        assert_eq!(h, Header {
+            sample_count: 20000,
+            channels: 2,
+            sample_rate: 22050,
+            levels: 7,
+            rows: 16,
+        });
+        assert_eq!(h.duration(), Duration::from_micros(453514));
+
+        assert_eq!(Header::read(&mut Cursor::new(&b"RIFF\0\0\0\0"[..])).unwrap_err().kind(),
+            ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode() {
+        // Single column, linear filler.
+        let mut w = BitWriter::default();
+        w.write(0, 4).write(100, 16)
+            .write(3, 5).write(7, 3).write(1, 3).write(4, 3);
+        let mut d = Decoder::new(Cursor::new(acm(3, 0, 3, &w))).unwrap();
+        assert_eq!(d.header().rows, 3);
+        assert_eq!(d.read_to_end().unwrap(), vec![300, -300, 0]);
+
+        // Two columns: the second is transformed from the first.
+        let mut w = BitWriter::default();
+        w.write(0, 4).write(10, 16)
+            // k12: -1
+            .write(18, 5).write(1, 1).write(0, 1)
+            // k44: +3
+            .write(27, 5).write(1, 1).write(6, 3);
+        let mut d = Decoder::new(Cursor::new(acm(2, 1, 1, &w))).unwrap();
+        // a = -10, b = 30: (a + 1) >> 1, (2a - b + 1) >> 1
+        assert_eq!(d.read_to_end().unwrap(), vec![-5, -25]);
+
+        // Sample count ends in the middle of the second block, t15 sets three rows at once.
+        let mut w = BitWriter::default();
+        for &b in &[26, 0] {
+            w.write(0, 4).write(1, 16).write(19, 5).write(b, 5);
+        }
+        let mut d = Decoder::new(Cursor::new(acm(4, 0, 2, &w))).unwrap();
+        let mut buf = [0; 3];
+        assert_eq!(d.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf, [1, 1, -1]);
+        assert_eq!(d.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], -1);
+        assert_eq!(d.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn decode_malformed() {
+        let mut w = BitWriter::default();
+        w.write(0, 4).write(1, 16).write(1, 5);
+        let mut d = Decoder::new(Cursor::new(acm(1, 0, 1, &w))).unwrap();
+        assert_eq!(d.read_to_end().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let mut d = Decoder::new(Cursor::new(acm(1_000_000, 0, 1, &BitWriter::default())))
+            .unwrap();
+        assert_eq!(d.read_to_end().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        assert_eq!(Decoder::new(Cursor::new(acm(1, 0, 0, &BitWriter::default())))
+            .err().unwrap().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+
+/// Narrator used when no death ending from `data/enddeath.txt` applies.
+pub const DEFAULT_NARRATOR: &str = "nar_5";
+
+/// Death ending definition from `data/enddeath.txt`. Picks the narration played on the death
+/// screen.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeathEnding {
+    /// The ending applies while the global var is less than `value`.
+    pub global_var: Option<usize>,
+    pub value: i32,
+    /// The ending applies only if this world area is known.
+    pub area_known: Option<u32>,
+    /// The ending applies only if this world area isn't known.
+    pub area_not_known: Option<u32>,
+    /// Minimum dude level.
+    pub min_level: u32,
+    /// Weight of the ending when picking among the applicable ones.
+    pub percentage: u32,
+    /// Base name of the narration speech and subtitles file.
+    pub narrator: String,
+}
+
+impl DeathEnding {
+    /// Checks the ending conditions. There's no world map yet so the area conditions aren't
+    /// checked.
+    pub fn is_applicable(&self, global_vars: &[i32], level: u32) -> bool {
+        if let Some(global_var) = self.global_var {
+            if global_vars.get(global_var).copied().unwrap_or(0) >= self.value {
+                return false;
+            }
+        }
+        level >= self.min_level
+    }
+}
+
+/// Returns the sum of percentages of the applicable `endings`. The `chance` passed to `pick()`
+/// must be in [0..=total] range.
+pub fn total_percentage(endings: &[&DeathEnding]) -> u32 {
+    endings.iter().map(|e| e.percentage).sum()
+}
+
+/// Picks one of the applicable `endings` by the random `chance`.
+// endgameSetupDeathEnding
+pub fn pick<'a>(endings: &[&'a DeathEnding], chance: u32) -> Option<&'a DeathEnding> {
+    let mut acc = 0;
+    for &ending in endings {
+        acc += ending.percentage;
+        if chance <= acc {
+            return Some(ending);
+        }
+    }
+    None
+}
+
+// endgameDeathEndingInit
+pub fn read_death_endings(rd: &mut impl BufRead) -> io::Result<Vec<DeathEnding>> {
+    let mut r = Vec::new();
+    for l in rd.lines() {
+        let l = l?;
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = l.split(',').map(|s| s.trim()).collect();
+        if fields.len() != 7 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("invalid number of fields in death ending definition: `{}`", l)));
+        }
+        let int = |i: usize| -> io::Result<i32> {
+            fields[i].parse().map_err(|_| Error::new(ErrorKind::InvalidData,
+                format!("couldn't parse death ending definition field: `{}`", fields[i])))
+        };
+        let opt = |i| int(i).map(|v| if v < 0 { None } else { Some(v) });
+        let narrator = fields[6];
+        if narrator.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("missing narrator in death ending definition: `{}`", l)));
+        }
+        r.push(DeathEnding {
+            global_var: opt(0)?.map(|v| v as usize),
+            value: int(1)?,
+            area_known: opt(2)?.map(|v| v as u32),
+            area_not_known: opt(3)?.map(|v| v as u32),
+            min_level: opt(4)?.unwrap_or(0) as u32,
+            percentage: opt(5)?.unwrap_or(0) as u32,
+            narrator: narrator.into(),
+        });
+    }
+    Ok(r)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn read_death_endings_() {
+        let s = "\
+# gvar, value, area known, area not known, min level, percentage, narrator
+-1, 0, -1, -1, 0, 50, nar_5
+
+308, 2, 12, -1, 3, 50, nar_21";
+        let endings = read_death_endings(&mut BufReader::new(Cursor::new(s))).unwrap();
+        assert_eq!(endings, vec![
+            DeathEnding {
+                global_var: None,
+                value: 0,
+                area_known: None,
+                area_not_known: None,
+                min_level: 0,
+                percentage: 50,
+                narrator: "nar_5".into(),
+            },
+            DeathEnding {
+                global_var: Some(308),
+                value: 2,
+                area_known: Some(12),
+                area_not_known: None,
+                min_level: 3,
+                percentage: 50,
+                narrator: "nar_21".into(),
+            },
+        ]);
+
+        assert!(read_death_endings(&mut BufReader::new(Cursor::new("1, 2, 3"))).is_err());
+        assert!(read_death_endings(&mut BufReader::new(Cursor::new("x,0,-1,-1,0,1,a"))).is_err());
+    }
+
+    #[test]
+    fn pick_() {
+        let ending = |global_var, min_level, percentage, narrator: &str| DeathEnding {
+            global_var,
+            value: 2,
+            area_known: None,
+            area_not_known: None,
+            min_level,
+            percentage,
+            narrator: narrator.into(),
+        };
+        let endings = vec![
+            ending(None, 0, 30, "a"),
+            ending(Some(1), 0, 20, "b"),
+            ending(None, 5, 50, "c"),
+        ];
+
+        let global_vars = &[0, 1];
+        let applicable: Vec<_> = endings.iter()
+            .filter(|e| e.is_applicable(global_vars, 1))
+            .collect();
+        assert_eq!(applicable.iter().map(|e| &e.narrator[..]).collect::<Vec<_>>(), &["a", "b"]);
+        assert_eq!(total_percentage(&applicable), 50);
+        assert_eq!(pick(&applicable, 0).unwrap().narrator, "a");
+        assert_eq!(pick(&applicable, 30).unwrap().narrator, "a");
+        assert_eq!(pick(&applicable, 31).unwrap().narrator, "b");
+        assert_eq!(pick(&applicable, 50).unwrap().narrator, "b");
+        assert_eq!(pick(&applicable, 51), None);
+
+        assert!(!endings[1].is_applicable(&[0, 2], 10));
+        assert!(endings[2].is_applicable(&[], 5));
+        assert_eq!(pick(&[], 0), None);
+    }
+}
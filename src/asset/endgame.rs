@@ -0,0 +1,129 @@
+use bstring::BString;
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+
+/// Interface art that is wider than the screen and is panned while shown.
+pub const PANNING_ART: u32 = 327;
+
+/// Endgame slide definition from `data/endgame.txt`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ending {
+    /// The slide is shown if the global var equals `value`.
+    pub global_var: usize,
+    pub value: i32,
+    /// Interface art index of the slide image.
+    pub art: u32,
+    /// Base name of the narration speech and subtitles file.
+    pub narrator: String,
+    /// Panning direction of `PANNING_ART`: negative pans from the right edge to the left.
+    pub direction: i32,
+}
+
+impl Ending {
+    pub fn is_applicable(&self, global_vars: &[i32]) -> bool {
+        global_vars.get(self.global_var).copied().unwrap_or(0) == self.value
+    }
+
+    pub fn is_panning(&self) -> bool {
+        self.art == PANNING_ART
+    }
+}
+
+// endgameEndingInit
+pub fn read_endings(rd: &mut impl BufRead) -> io::Result<Vec<Ending>> {
+    let mut r = Vec::new();
+    for l in rd.lines() {
+        let l = l?;
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = l.split(',').map(|s| s.trim()).collect();
+        if fields.len() < 4 || fields.len() > 5 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("invalid number of fields in ending definition: `{}`", l)));
+        }
+        let int = |i: usize| -> io::Result<i32> {
+            fields[i].parse().map_err(|_| Error::new(ErrorKind::InvalidData,
+                format!("couldn't parse ending definition field: `{}`", fields[i])))
+        };
+        let global_var = int(0)?;
+        let art = int(2)?;
+        if global_var < 0 || art < 0 || fields[3].is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("invalid ending definition: `{}`", l)));
+        }
+        r.push(Ending {
+            global_var: global_var as usize,
+            value: int(1)?,
+            art: art as u32,
+            narrator: fields[3].into(),
+            direction: if fields.len() > 4 { int(4)? } else { 1 },
+        });
+    }
+    Ok(r)
+}
+
+/// Reads the narration subtitles `text/<language>/cuts/<narrator>.txt`. Each line is
+/// `<number>:<text>`, the number isn't used. Lines without `:` are skipped.
+// endgameEndingSubtitlesLoad
+pub fn read_subtitles(rd: &mut impl BufRead) -> io::Result<Vec<BString>> {
+    let mut r = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if rd.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        while line.last().map(|&c| c == b'\n' || c == b'\r').unwrap_or(false) {
+            line.pop();
+        }
+        if let Some(i) = line.iter().position(|&c| c == b':') {
+            r.push(line[i + 1..].into());
+        }
+    }
+    Ok(r)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn read_endings_() {
+        let s = "\
+# Arroyo
+ 53, 1, 327, nar_ar1, -1
+
+54,2,328,nar_ar2";
+        let endings = read_endings(&mut BufReader::new(Cursor::new(s))).unwrap();
+        assert_eq!(endings, vec![
+            Ending { global_var: 53, value: 1, art: 327, narrator: "nar_ar1".into(),
+                direction: -1 },
+            Ending { global_var: 54, value: 2, art: 328, narrator: "nar_ar2".into(),
+                direction: 1 },
+        ]);
+        assert!(endings[0].is_panning());
+        assert!(!endings[1].is_panning());
+
+        let mut global_vars = vec![0; 55];
+        global_vars[53] = 1;
+        assert!(endings[0].is_applicable(&global_vars));
+        assert!(!endings[1].is_applicable(&global_vars));
+        assert!(!endings[1].is_applicable(&[]));
+
+        assert!(read_endings(&mut BufReader::new(Cursor::new("53, 1, 327"))).is_err());
+        assert!(read_endings(&mut BufReader::new(Cursor::new("-1, 1, 327, a"))).is_err());
+    }
+
+    #[test]
+    fn read_subtitles_() {
+        let subs = read_subtitles(&mut Cursor::new(
+            &b"1:First line.\r\nno colon\n2:Second: line"[..])).unwrap();
+        assert_eq!(subs, vec![
+            BString::from(&b"First line."[..]),
+            BString::from(&b"Second: line"[..]),
+        ]);
+    }
+}
@@ -1,8 +1,11 @@
+pub mod ttf;
+
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use log::*;
 use std::cmp;
 use std::io::{self, Error, ErrorKind, prelude::*};
 
+use crate::asset::message::encoding::Encoding;
 use crate::fs::FileSystem;
 use crate::graphics::font::{Font, Glyph, FontKey, Fonts};
 use crate::graphics::render::TextureFactory;
@@ -106,8 +109,13 @@ fn read_fon(rd: &mut impl Read, texture_factory: &TextureFactory) -> io::Result<
     })
 }
 
-pub fn load_fonts(fs: &FileSystem, texture_factory: &TextureFactory) -> Fonts {
+/// Loads the original FON and AAF fonts and then applies `ttf_fonts` on top. The TTF glyphs are
+/// mapped to bytes using `encoding`.
+pub fn load_fonts(fs: &FileSystem, texture_factory: &TextureFactory, ttf_fonts: &[ttf::TtfFont],
+    encoding: Encoding) -> Fonts
+{
     let mut fonts = Fonts::new();
+    fonts.set_encoding(encoding);
 
     let load_fon = |name: &str| {
         let mut rd = fs.reader(name)?;
@@ -143,5 +151,17 @@ pub fn load_fonts(fs: &FileSystem, texture_factory: &TextureFactory) -> Fonts {
         }
     }
 
+    for ttf_font in ttf_fonts {
+        match ttf::load(ttf_font, fs, encoding, texture_factory) {
+            Ok(font) => {
+                let base = fonts.remove(ttf_font.key);
+                fonts.insert(ttf_font.key, ttf::merge(base, font, ttf_font.mode));
+            }
+            Err(e) => {
+                warn!("couldn't load TTF font `{}`: {}", ttf_font.path, e);
+            }
+        }
+    }
+
     fonts
 }
@@ -0,0 +1,224 @@
+//! TrueType fonts rasterized into the game font format. Can be used to substitute the original
+//! fonts or to supplement them with glyphs they're missing (for example for languages the
+//! original fonts weren't made for).
+//!
+//! Configured in the `[fonts]` section of `fallout2.cfg`, one entry per font:
+//!
+//! ```ini
+//! [fonts]
+//! ; <aaf|fon><id>=<path>[,<point size>[,substitute|supplement]]
+//! aaf1=fonts/DejaVuSans.ttf,12,supplement
+//! fon0=fonts/DejaVuSansMono.ttf
+//! ```
+//!
+//! Rendering requires the `ttf` crate feature.
+
+use ini::Ini;
+use log::*;
+use std::io::{self, Error, ErrorKind};
+
+use crate::asset::message::encoding::Encoding;
+use crate::fs::FileSystem;
+use crate::graphics::font::{Font, FontKey, Glyph};
+use crate::graphics::render::TextureFactory;
+
+const SECTION: &str = "fonts";
+const DEFAULT_SIZE: u16 = 12;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Replace all glyphs of the original font.
+    Substitute,
+    /// Only replace glyphs missing in the original font. If the original font doesn't exist
+    /// this is the same as `Substitute`.
+    Supplement,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TtfFont {
+    pub key: FontKey,
+    pub path: String,
+    pub size: u16,
+    pub mode: Mode,
+}
+
+/// Reads TTF font entries from the `[fonts]` section. Malformed entries are logged and skipped.
+pub fn read_config(ini: &Ini) -> Vec<TtfFont> {
+    let mut r = Vec::new();
+    if let Some(section) = ini.section(Some(SECTION)) {
+        for (k, v) in section.iter() {
+            match parse_entry(k, v) {
+                Some(font) => r.push(font),
+                None => warn!("malformed TTF font config entry: {}={}", k, v),
+            }
+        }
+    }
+    r
+}
+
+fn parse_entry(key: &str, value: &str) -> Option<TtfFont> {
+    let key = key.trim().to_ascii_lowercase();
+    let key = if let Some(id) = key.strip_prefix("aaf") {
+        FontKey::antialiased(id.parse().ok()?)
+    } else if let Some(id) = key.strip_prefix("fon") {
+        FontKey::non_antialiased(id.parse().ok()?)
+    } else {
+        return None;
+    };
+
+    let mut fields = value.split(',').map(|s| s.trim());
+    let path = fields.next().filter(|s| !s.is_empty())?.to_owned();
+    let size = match fields.next() {
+        Some(s) => s.parse().ok().filter(|&v| v > 0)?,
+        None => DEFAULT_SIZE,
+    };
+    let mode = match fields.next().map(|s| s.to_ascii_lowercase()) {
+        None => Mode::Substitute,
+        Some(s) if s == "substitute" => Mode::Substitute,
+        Some(s) if s == "supplement" => Mode::Supplement,
+        Some(_) => return None,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(TtfFont {
+        key,
+        path,
+        size,
+        mode,
+    })
+}
+
+fn is_missing(glyph: &Glyph) -> bool {
+    glyph.width == 0 || glyph.height == 0
+}
+
+/// Combines the original font `base` with the rasterized TTF font according to `mode`.
+pub fn merge(base: Option<Font>, ttf: Font, mode: Mode) -> Font {
+    match (base, mode) {
+        (Some(base), Mode::Supplement) => {
+            let mut glyphs = Vec::from(base.glyphs);
+            for (c, ttf_glyph) in Vec::from(ttf.glyphs).into_iter().enumerate() {
+                // Space has no pixels but isn't missing.
+                if c == b' ' as usize {
+                    continue;
+                }
+                match glyphs.get_mut(c) {
+                    Some(glyph) => if is_missing(glyph) {
+                        *glyph = ttf_glyph;
+                    }
+                    None => glyphs.push(ttf_glyph),
+                }
+            }
+            Font {
+                glyphs: glyphs.into_boxed_slice(),
+                ..base
+            }
+        }
+        _ => ttf,
+    }
+}
+
+/// Rasterizes all 256 glyphs of the TTF font. Glyph for every byte is the character it decodes
+/// to in `encoding`.
+#[cfg(feature = "ttf")]
+pub fn load(font: &TtfFont, fs: &FileSystem, encoding: Encoding,
+    texture_factory: &TextureFactory) -> io::Result<Font>
+{
+    use sdl2::pixels::{Color, PixelFormatEnum};
+    use sdl2::rwops::RWops;
+    use std::io::prelude::*;
+
+    let other = |e: String| Error::new(ErrorKind::Other, e);
+
+    let mut data = Vec::new();
+    fs.reader(&font.path)?.read_to_end(&mut data)?;
+
+    let ctx = sdl2::ttf::init().map_err(|e| other(e.to_string()))?;
+    let rwops = RWops::from_bytes(&data).map_err(other)?;
+    let ttf = ctx.load_font_from_rwops(rwops, font.size).map_err(other)?;
+    let height = ttf.height();
+
+    let mut glyphs = Vec::with_capacity(256);
+    for c in 0..=255 {
+        let ch = encoding.decode_char(c);
+        let renderable = !ch.is_control() && ch != '\u{fffd}' && ttf.find_glyph(ch).is_some();
+        if !renderable {
+            glyphs.push(Glyph {
+                width: 0,
+                height: 0,
+                texture: texture_factory.new_texture(0, 0, Vec::new().into_boxed_slice()),
+            });
+            continue;
+        }
+
+        let surface = ttf.render_char(ch)
+            .blended(Color::RGBA(255, 255, 255, 255))
+            .map_err(|e| other(e.to_string()))?
+            .convert_format(PixelFormatEnum::RGBA32)
+            .map_err(other)?;
+        let width = surface.width() as usize;
+        let glyph_height = surface.height() as usize;
+        let pitch = surface.pitch() as usize;
+        let mut mask = Vec::with_capacity(width * glyph_height);
+        surface.with_lock(|pixels| {
+            for y in 0..glyph_height {
+                for x in 0..width {
+                    let alpha = pixels[y * pitch + x * 4 + 3] as u32;
+                    // Mask values are in 0..=7 range.
+                    mask.push(((alpha * 7 + 127) / 255) as u8);
+                }
+            }
+        });
+        glyphs.push(Glyph {
+            width: width as i32,
+            height: glyph_height as i32,
+            texture: texture_factory.new_texture(width as i32, glyph_height as i32,
+                mask.into_boxed_slice()),
+        });
+    }
+
+    info!("loaded TTF font {} ({}pt) as {:?}", font.path, font.size, font.key);
+
+    Ok(Font {
+        height,
+        horz_spacing: 0,
+        vert_spacing: 0,
+        glyphs: glyphs.into_boxed_slice(),
+    })
+}
+
+#[cfg(not(feature = "ttf"))]
+pub fn load(font: &TtfFont, _fs: &FileSystem, _encoding: Encoding,
+    _texture_factory: &TextureFactory) -> io::Result<Font>
+{
+    Err(Error::new(ErrorKind::Other,
+        format!("can't load {}: built without TTF support (`ttf` feature)", font.path)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_entry_() {
+        assert_eq!(parse_entry("aaf1", "fonts/a.ttf, 14, supplement"), Some(TtfFont {
+            key: FontKey::antialiased(1),
+            path: "fonts/a.ttf".into(),
+            size: 14,
+            mode: Mode::Supplement,
+        }));
+        assert_eq!(parse_entry("FON3", "b.ttf"), Some(TtfFont {
+            key: FontKey::non_antialiased(3),
+            path: "b.ttf".into(),
+            size: DEFAULT_SIZE,
+            mode: Mode::Substitute,
+        }));
+        assert_eq!(parse_entry("ttf1", "a.ttf"), None);
+        assert_eq!(parse_entry("aaf", "a.ttf"), None);
+        assert_eq!(parse_entry("aaf1", ""), None);
+        assert_eq!(parse_entry("aaf1", "a.ttf,0"), None);
+        assert_eq!(parse_entry("aaf1", "a.ttf,12,replace"), None);
+        assert_eq!(parse_entry("aaf1", "a.ttf,12,supplement,x"), None);
+    }
+}
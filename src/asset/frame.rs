@@ -1,12 +1,15 @@
 mod db;
 mod id;
+pub mod export;
+pub mod import;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use enum_map::EnumMap;
 use std::io::{self, prelude::*};
+use std::rc::Rc;
 
-pub use id::{FrameId, Idx};
-pub use db::FrameDb;
+pub use id::{FrameId, Idx, MAX_IDX};
+pub use db::{critter_anim_codes, FrameDb};
 
 use crate::graphics::Point;
 use crate::graphics::geometry::hex::Direction;
@@ -14,85 +17,242 @@ use crate::graphics::render::TextureFactory;
 use crate::graphics::sprite::*;
 use crate::util::EnumExt;
 
-pub fn read_frm(rd: &mut impl Read, texture_factory: &TextureFactory) -> io::Result<FrameSet> {
-    let _version = rd.read_u32::<BigEndian>()?;
-
-    let fps = rd.read_u16::<BigEndian>()?;
-    let fps = if fps == 0 {
-        10
-    } else {
-        fps
-    };
-
-    let action_frame = rd.read_u16::<BigEndian>()?;
-    let frames_per_direction = rd.read_u16::<BigEndian>()? as usize;
-    assert!(frames_per_direction > 0);
-
-    let mut centers_x = EnumMap::new();
-    for dir in Direction::iter() {
-        centers_x[dir] = rd.read_i16::<BigEndian>()? as i32;
-    }
-    let mut centers_y = EnumMap::new();
-    for dir in Direction::iter() {
-        centers_y[dir] = rd.read_i16::<BigEndian>()? as i32;
-    }
+/// Frame as stored in FRM file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrmFrame {
+    pub shift: Point,
+    pub width: i32,
+    pub height: i32,
+    /// Palette color indices, row by row. Index 0 is transparent.
+    pub pixels: Box<[u8]>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FrmFrameList {
+    pub center: Point,
+    pub frames: Vec<FrmFrame>,
+}
+
+/// Contents of FRM file. Unlike `FrameSet` the frame pixels are kept in memory.
+#[derive(Clone, Debug)]
+pub struct Frm {
+    /// FPS as stored in the file, 0 means default.
+    pub fps: u16,
+    pub action_frame: u16,
+    /// Directions that share the frame list in the file point to the same list.
+    pub frame_lists: EnumMap<Direction, Rc<FrmFrameList>>,
+}
+
+impl Frm {
+    pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let _version = rd.read_u32::<BigEndian>()?;
+
+        let fps = rd.read_u16::<BigEndian>()?;
+        let action_frame = rd.read_u16::<BigEndian>()?;
+        let frames_per_direction = rd.read_u16::<BigEndian>()? as usize;
+        assert!(frames_per_direction > 0);
+
+        let mut centers_x = EnumMap::new();
+        for dir in Direction::iter() {
+            centers_x[dir] = rd.read_i16::<BigEndian>()? as i32;
+        }
+        let mut centers_y = EnumMap::new();
+        for dir in Direction::iter() {
+            centers_y[dir] = rd.read_i16::<BigEndian>()? as i32;
+        }
 
-    let mut frame_offsets = EnumMap::new();
-    for dir in Direction::iter() {
-        frame_offsets[dir] = rd.read_u32::<BigEndian>()?;
+        let mut frame_offsets = EnumMap::new();
+        for dir in Direction::iter() {
+            frame_offsets[dir] = rd.read_u32::<BigEndian>()?;
+        }
+
+        let _data_len = rd.read_u32::<BigEndian>()?;
+
+        let mut loaded_offsets: EnumMap<Direction, Option<u32>> = EnumMap::new();
+        let mut frame_lists: EnumMap<Direction, Option<Rc<FrmFrameList>>> = EnumMap::new();
+        for dir in Direction::iter() {
+            let offset = frame_offsets[dir];
+            let already_loaded_dir = loaded_offsets
+                .iter()
+                .filter_map(|(d, o)| o.filter(|&o| o == offset).map(|_| d))
+                .next();
+            if let Some(already_loaded_dir) = already_loaded_dir {
+                frame_lists[dir] = frame_lists[already_loaded_dir].clone();
+                continue;
+            }
+
+            loaded_offsets[dir] = Some(offset);
+
+            let mut frames = Vec::with_capacity(frames_per_direction);
+            for _ in 0..frames_per_direction {
+                let width = rd.read_i16::<BigEndian>()? as i32;
+                let height = rd.read_i16::<BigEndian>()? as i32;
+                let _len = rd.read_u32::<BigEndian>()?;
+                let shift = Point::new(
+                    rd.read_i16::<BigEndian>()? as i32,
+                    rd.read_i16::<BigEndian>()? as i32,
+                );
+
+                let len = (width * height) as usize;
+                let mut pixels = vec![0; len].into_boxed_slice();
+                rd.read_exact(&mut pixels)?;
+
+                frames.push(FrmFrame {
+                    shift,
+                    width,
+                    height,
+                    pixels,
+                });
+            }
+            frame_lists[dir] = Some(Rc::new(FrmFrameList {
+                center: Point::new(centers_x[dir], centers_y[dir]),
+                frames,
+            }));
+        }
+
+        Ok(Self {
+            fps,
+            action_frame,
+            frame_lists: EnumMap::from(|k| frame_lists[k].take().unwrap()),
+        })
     }
 
-    let _data_len = rd.read_u32::<BigEndian>()?;
-
-    let mut loaded_offsets: EnumMap<Direction, Option<u32>> = EnumMap::new();
-    let mut frame_lists: EnumMap<Direction, Option<FrameList>> = EnumMap::new();
-    for dir in Direction::iter() {
-        let offset = frame_offsets[dir];
-        let already_loaded_dir = loaded_offsets
-            .iter()
-            .filter_map(|(d, o)| o.filter(|&o| o == offset).map(|_| d))
-            .next();
-        if let Some(already_loaded_dir) = already_loaded_dir {
-            frame_lists[dir] = frame_lists[already_loaded_dir].clone();
-            continue;
+    /// Writes FRM file. Directions sharing the frame list are written once.
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let frames_per_direction = self.frame_lists[Direction::NE].frames.len();
+        assert!(frames_per_direction > 0);
+
+        let mut offsets: EnumMap<Direction, u32> = EnumMap::new();
+        let mut data_len = 0;
+        for dir in Direction::iter() {
+            let frml = &self.frame_lists[dir];
+            assert_eq!(frml.frames.len(), frames_per_direction);
+            let already_written_dir = Direction::iter()
+                .take_while(|&d| d != dir)
+                .find(|&d| Rc::ptr_eq(&self.frame_lists[d], frml));
+            offsets[dir] = if let Some(already_written_dir) = already_written_dir {
+                offsets[already_written_dir]
+            } else {
+                let offset = data_len;
+                data_len += frml.frames.iter()
+                    .map(|f| 12 + f.pixels.len() as u32)
+                    .sum::<u32>();
+                offset
+            };
         }
 
-        loaded_offsets[dir] = Some(offset);
-
-        let mut frames = Vec::with_capacity(frames_per_direction);
-        for _ in 0..frames_per_direction {
-            let width = rd.read_i16::<BigEndian>()? as i32;
-            let height = rd.read_i16::<BigEndian>()? as i32;
-            let _len = rd.read_u32::<BigEndian>()?;
-            let shift = Point::new(
-                rd.read_i16::<BigEndian>()? as i32,
-                rd.read_i16::<BigEndian>()? as i32,
-            );
-
-            let len = (width * height) as usize;
-            let mut pixels = vec![0; len].into_boxed_slice();
-            rd.read_exact(&mut pixels)?;
-
-            let mask = Mask::new(width, &pixels);
-            let texture = texture_factory.new_texture(width, height, pixels);
-
-            frames.push(Frame {
-                shift,
-                width,
-                height,
-                texture,
-                mask,
+        // Version.
+        w.write_u32::<BigEndian>(4)?;
+        w.write_u16::<BigEndian>(self.fps)?;
+        w.write_u16::<BigEndian>(self.action_frame)?;
+        w.write_u16::<BigEndian>(frames_per_direction as u16)?;
+        for dir in Direction::iter() {
+            w.write_i16::<BigEndian>(self.frame_lists[dir].center.x as i16)?;
+        }
+        for dir in Direction::iter() {
+            w.write_i16::<BigEndian>(self.frame_lists[dir].center.y as i16)?;
+        }
+        for dir in Direction::iter() {
+            w.write_u32::<BigEndian>(offsets[dir])?;
+        }
+        w.write_u32::<BigEndian>(data_len)?;
+
+        for dir in Direction::iter() {
+            if Direction::iter().take_while(|&d| d != dir).any(|d| offsets[d] == offsets[dir]) {
+                continue;
+            }
+            for f in &self.frame_lists[dir].frames {
+                assert_eq!(f.pixels.len(), (f.width * f.height) as usize);
+                w.write_i16::<BigEndian>(f.width as i16)?;
+                w.write_i16::<BigEndian>(f.height as i16)?;
+                w.write_u32::<BigEndian>(f.pixels.len() as u32)?;
+                w.write_i16::<BigEndian>(f.shift.x as i16)?;
+                w.write_i16::<BigEndian>(f.shift.y as i16)?;
+                w.write_all(&f.pixels)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_frame_set(&self, texture_factory: &TextureFactory) -> FrameSet {
+        let fps = if self.fps == 0 {
+            10
+        } else {
+            self.fps
+        };
+        let mut frame_lists: EnumMap<Direction, Option<FrameList>> = EnumMap::new();
+        for dir in Direction::iter() {
+            let frml = &self.frame_lists[dir];
+            let already_loaded_dir = Direction::iter()
+                .take_while(|&d| d != dir)
+                .find(|&d| Rc::ptr_eq(&self.frame_lists[d], frml));
+            if let Some(already_loaded_dir) = already_loaded_dir {
+                frame_lists[dir] = frame_lists[already_loaded_dir].clone();
+                continue;
+            }
+            frame_lists[dir] = Some(FrameList {
+                center: frml.center,
+                frames: frml.frames.iter()
+                    .map(|f| Frame {
+                        shift: f.shift,
+                        width: f.width,
+                        height: f.height,
+                        texture: texture_factory.new_texture(f.width, f.height,
+                            f.pixels.clone()),
+                        mask: Mask::new(f.width, &f.pixels),
+                    })
+                    .collect(),
             });
         }
-        frame_lists[dir] = Some(FrameList {
-            center: Point::new(centers_x[dir], centers_y[dir]),
-            frames,
-        });
+        FrameSet {
+            fps,
+            action_frame: self.action_frame,
+            frame_lists: EnumMap::from(|k| frame_lists[k].take().unwrap()),
+        }
     }
+}
 
-    Ok(FrameSet {
-        fps,
-        action_frame,
-        frame_lists: EnumMap::from(|k| frame_lists[k].take().unwrap()),
-    })
-}
\ No newline at end of file
+pub fn read_frm(rd: &mut impl Read, texture_factory: &TextureFactory) -> io::Result<FrameSet> {
+    Ok(Frm::read(rd)?.to_frame_set(texture_factory))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let frame = |shift: (i32, i32), width: i32, height: i32| FrmFrame {
+            shift: shift.into(),
+            width,
+            height,
+            pixels: (0..width * height).map(|i| i as u8).collect(),
+        };
+        let ne = Rc::new(FrmFrameList {
+            center: Point::new(1, -2),
+            frames: vec![frame((0, 0), 2, 3), frame((-1, 2), 1, 1)],
+        });
+        let e = Rc::new(FrmFrameList {
+            center: Point::new(0, 0),
+            frames: vec![frame((3, 0), 0, 0), frame((0, 0), 4, 2)],
+        });
+        let frm = Frm {
+            fps: 12,
+            action_frame: 1,
+            frame_lists: EnumMap::from(|d| if d == Direction::E { e.clone() } else { ne.clone() }),
+        };
+
+        let mut data = Vec::new();
+        frm.write(&mut data).unwrap();
+        assert_eq!(data.len(), 62 + 4 * 12 + 6 + 1 + 8);
+
+        let act = Frm::read(&mut &data[..]).unwrap();
+        assert_eq!((act.fps, act.action_frame), (12, 1));
+        for dir in Direction::iter() {
+            assert_eq!(act.frame_lists[dir], frm.frame_lists[dir]);
+        }
+        assert!(Rc::ptr_eq(&act.frame_lists[Direction::NE], &act.frame_lists[Direction::W]));
+        assert!(!Rc::ptr_eq(&act.frame_lists[Direction::NE], &act.frame_lists[Direction::E]));
+    }
+}
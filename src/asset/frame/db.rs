@@ -1,40 +1,97 @@
 use enum_map::EnumMap;
+use log::*;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{self, Error, ErrorKind, prelude::*};
 use std::rc::Rc;
 
 use super::*;
-use crate::asset::{CritterAnim, EntityKind, LstEntry, read_lst, WeaponKind};
+use super::id::Critter;
+use crate::asset::{CritterAnim, EntityKind, LstEntry, read_lst_ext, WeaponKind};
+use crate::error::{self, ResultExt};
 use crate::fs::FileSystem;
+use crate::fs::watch;
 use crate::graphics::sprite::FrameSet;
 use crate::util::EnumExt;
+use crate::util::profile;
 
 pub struct FrameDb {
     fs: Rc<FileSystem>,
     language: Option<String>,
-    lst: EnumMap<EntityKind, Vec<LstEntry>>,
+    /// LST entries keyed by the FID index.
+    lst: EnumMap<EntityKind, BTreeMap<u32, LstEntry>>,
     frms: RefCell<HashMap<FrameId, Rc<FrameSet>>>,
+    critter_anims: RefCell<HashMap<Critter, Option<Critter>>>,
     texture_factory: TextureFactory,
 }
 
 impl FrameDb {
     pub fn new(fs: Rc<FileSystem>, language: &str, texture_factory: TextureFactory)
-        -> io::Result<Self>
+        -> error::Result<Self>
     {
         let language = Some(language)
             .filter(|s| !s.eq_ignore_ascii_case("english"))
             .map(|s| s.to_owned());
-        let lst = Self::read_lst_files(&fs)?;
+        let lst = Self::read_lst_files(&fs).context(|| "reading art lists")?;
         Ok(Self {
             fs,
             language,
             lst,
             frms: RefCell::new(HashMap::new()),
+            critter_anims: RefCell::new(HashMap::new()),
             texture_factory,
         })
     }
 
+    /// Database without any LST entries.
+    #[cfg(test)]
+    pub fn mock(fs: Rc<FileSystem>, texture_factory: TextureFactory) -> Self {
+        Self {
+            fs,
+            language: None,
+            lst: EnumMap::new(),
+            frms: RefCell::new(HashMap::new()),
+            critter_anims: RefCell::new(HashMap::new()),
+            texture_factory,
+        }
+    }
+
+    /// Caches single frame of `width` x `height` pixels of `color` as if it was read for `fid`.
+    /// The bottom center of the frame is at the hex center in all directions.
+    #[cfg(test)]
+    pub fn mock_frame(&self, fid: FrameId, width: i32, height: i32, color: u8) {
+        self.mock_frames(fid, 1, width, height, color);
+    }
+
+    /// Same as `mock_frame()` but caches `count` identical frames in each direction.
+    #[cfg(test)]
+    pub fn mock_frames(&self, fid: FrameId, count: usize, width: i32, height: i32, color: u8) {
+        use crate::graphics::Point;
+
+        let frml = Rc::new(FrmFrameList {
+            center: Point::new(0, 0),
+            frames: (0..count)
+                .map(|_| FrmFrame {
+                    shift: Point::new(0, 0),
+                    width,
+                    height,
+                    pixels: vec![color; (width * height) as usize].into(),
+                })
+                .collect(),
+        });
+        let frm = Frm {
+            fps: 0,
+            action_frame: 0,
+            frame_lists: EnumMap::from(|_| frml.clone()),
+        };
+        let fid = self.normalize_fid(fid);
+        self.frms.borrow_mut().insert(fid, Rc::new(frm.to_frame_set(&self.texture_factory)));
+    }
+
+    pub fn texture_factory(&self) -> &TextureFactory {
+        &self.texture_factory
+    }
+
     // art_get_name()
     /// Returns .frm or .frN file name without path.
     pub fn name(&self, fid: FrameId) -> Option<String> {
@@ -42,6 +99,12 @@ impl FrameDb {
         self.name_no_normalize(fid)
     }
 
+    // art_copy_file_name()
+    /// Returns LST base name of `fid`. Unlike `name()` doesn't resolve critter aliases.
+    pub fn base_name(&self, fid: FrameId) -> Option<&str> {
+        self.lst[fid.kind()].get(&u32::from(fid.idx())).map(|e| e.fields[0].as_str())
+    }
+
     //  art_exists()
     pub fn exists(&self, fid: FrameId) -> bool {
         let fid = self.normalize_fid(fid);
@@ -54,23 +117,102 @@ impl FrameDb {
         Ok(if frms.contains_key(&fid) {
             frms[&fid].clone()
         } else {
-            let frm = Rc::new(read_frm(&mut self.read(fid)?, &self.texture_factory)?);
+            let _profile = profile::scope("asset load");
+            let frm = read_frm(&mut self.read(fid)?, &self.texture_factory)
+                .context(|| format!("reading frame set {:?}", fid))?;
+            let frm = Rc::new(frm);
             frms.insert(fid, frm.clone());
             frm
         })
     }
 
+    /// Reads FRM file of the `fid` keeping the frame pixels in memory. Unlike `get()` the result
+    /// is not cached.
+    pub fn read_frm(&self, fid: FrameId) -> io::Result<Frm> {
+        let fid = self.normalize_fid(fid);
+        Frm::read(&mut self.read(fid)?)
+    }
+
+    /// Drops cached frame sets read from the resource `path` so they're read again on next
+    /// access. Returns `true` if the `path` is an art file. Changes to LST files are not picked
+    /// up.
+    pub fn reload(&self, path: &str) -> bool {
+        let path = watch::normalize(path);
+        if !path.starts_with("art/") || path.ends_with(".lst") {
+            return false;
+        }
+        self.frms.borrow_mut().retain(|&fid, _| {
+            self.name_no_normalize(fid)
+                .map(|name| {
+                    let lang_path = Self::full_path(fid.kind(), &name, self.language.as_ref());
+                    let base_path = Self::full_path(fid.kind(), &name, None);
+                    watch::normalize(&lang_path) != path && watch::normalize(&base_path) != path
+                })
+                .unwrap_or(true)
+        });
+        // Changes in existence of the files affect the fallbacks.
+        self.critter_anims.borrow_mut().clear();
+        true
+    }
+
+    /// Whether the critter has its own `anim` animation for its current weapon. Fallbacks are not
+    /// considered, see `critter_anim_or_fallback()` for that.
+    pub fn has_critter_anim(&self, fid: Critter, anim: CritterAnim) -> bool {
+        self.exists(fid.with_anim(anim).into())
+    }
+
+    /// Returns `fid` if it exists, otherwise the first existing FID found by following
+    /// `CritterAnim::fallback()` chain. Non-weapon animations are also looked up for unarmed
+    /// critter. Returns `None` if none of the candidates exist.
+    pub fn critter_anim_or_fallback(&self, fid: Critter) -> Option<Critter> {
+        let direction = fid.direction();
+        let key = fid.with_direction(None);
+        if let Some(&r) = self.critter_anims.borrow().get(&key) {
+            return r.map(|r| r.with_direction(direction));
+        }
+
+        let mut weapons = vec![key.weapon()];
+        if key.weapon() != WeaponKind::Unarmed && !key.anim().is_weapon() {
+            weapons.push(WeaponKind::Unarmed);
+        }
+        let mut r = None;
+        'outer: for &weapon in &weapons {
+            let mut anim = Some(key.anim());
+            while let Some(a) = anim {
+                let candidate = key.with_weapon(weapon).with_anim(a);
+                if self.exists(candidate.into()) {
+                    r = Some(candidate);
+                    break 'outer;
+                }
+                anim = a.fallback();
+            }
+        }
+        if r != Some(key) {
+            debug!("critter {:?} {:?}/{:?} doesn't exist, using {:?}",
+                key, key.weapon(), key.anim(), r.map(|r| (r.weapon(), r.anim())));
+        }
+        self.critter_anims.borrow_mut().insert(key, r);
+        r.map(|r| r.with_direction(direction))
+    }
+
+    /// Returns IDs and base names of the `kind` entries which base names start with `prefix`.
+    pub fn find_ids_with_prefix(&self, kind: EntityKind, prefix: &str) -> Vec<(u16, String)> {
+        self.lst[kind].iter()
+            .filter(|(_, e)| e.fields[0].get(..prefix.len())
+                .map(|s| s.eq_ignore_ascii_case(prefix))
+                .unwrap_or(false))
+            .map(|(&i, e)| (i as u16, e.fields[0].clone()))
+            .collect()
+    }
+
     /// Looks for `base_name` and returns its ID if found.
     /// Note the `base_name` format depends on the `kind`. For example for `Critter` it's
     /// just a part of the `.fr_` filename like `hapowr`, and for `Interface` it's a full
     /// filename like `combat.frm`.
     pub fn find_id(&self, kind: EntityKind, base_name: &str) -> Option<u16> {
-        for (i, e) in self.lst[kind].iter().enumerate() {
-            if e.fields[0].eq_ignore_ascii_case(base_name) {
-                return Some(i as u16);
-            }
-        }
-        None
+        self.lst[kind].iter()
+            .find(|(_, e)| e.fields[0].eq_ignore_ascii_case(base_name))
+            .map(|(&i, _)| i as u16)
     }
 
     // art_alias_fid()
@@ -92,7 +234,8 @@ impl FrameDb {
                 | CalledShotPic
                 => {
                     // TODO replace unwraps with logging
-                    let alias = self.lst[EntityKind::Critter].get(critter_fid.idx() as usize).unwrap()
+                    let alias = self.lst[EntityKind::Critter]
+                        .get(&u32::from(critter_fid.idx())).unwrap()
                         .fields.get(1).unwrap();
                     // TODO parse this once during Self::new().
                     let alias = alias.parse().unwrap();
@@ -120,11 +263,13 @@ impl FrameDb {
         self.read_by_name(fid.kind(), &name)
     }
 
-    fn read_lst_files(fs: &FileSystem) -> io::Result<EnumMap<EntityKind, Vec<LstEntry>>> {
+    fn read_lst_files(fs: &FileSystem)
+        -> io::Result<EnumMap<EntityKind, BTreeMap<u32, LstEntry>>>
+    {
         let mut lst = EnumMap::new();
         for kind in EntityKind::iter() {
             let path = Self::full_path(kind, &format!("{}.lst", kind.dir()), None);
-            lst[kind] = read_lst(&mut fs.reader(&path)?)?;
+            lst[kind] = read_lst_ext(fs, &path, 0, MAX_IDX.into())?;
         }
         Ok(lst)
     }
@@ -138,7 +283,7 @@ impl FrameDb {
     }
 
     fn name_no_normalize(&self, fid: FrameId) -> Option<String> {
-        let base_name = &self.lst[fid.kind()].get(fid.idx() as usize)?.fields[0];
+        let base_name = &self.lst[fid.kind()].get(&u32::from(fid.idx()))?.fields[0];
 
         Some(match fid {
             FrameId::Critter(fid) => {
@@ -181,7 +326,7 @@ impl FrameDb {
         } else {
             Self::full_path(kind, name, None)
         };
-        self.fs.reader(&path)
+        Ok(self.fs.reader(&path)?)
     }
 
     fn exists_no_normalize(&self, fid: FrameId) -> bool {
@@ -193,7 +338,9 @@ impl FrameDb {
     }
 }
 
-fn critter_anim_codes(weapon_kind: WeaponKind, anim: CritterAnim) -> Option<(char, char)> {
+// art_get_code()
+/// Returns the two letter code identifying critter animation in the file name.
+pub fn critter_anim_codes(weapon_kind: WeaponKind, anim: CritterAnim) -> Option<(char, char)> {
     use self::WeaponKind::*;
     use self::CritterAnim::*;
     Some(match anim {
@@ -0,0 +1,286 @@
+//! Conversion of FRM files to PNG sprite sheets and animations. Used by the `frm export`
+//! subcommand.
+//!
+//! The sprite sheet has a row per distinct frame list and a column per frame, each frame placed
+//! at the top left corner of its cell. The sidecar JSON describes the cell positions, frame
+//! sizes and offsets needed to reconstruct the FRM.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::*;
+use crate::asset::proto::dump::json_str;
+use crate::graphics::Rect;
+use crate::graphics::color::{Color8, Rgb24};
+use crate::graphics::color::palette::Palette;
+use crate::graphics::png;
+
+/// Palette color index that is drawn transparent.
+const TRANSPARENT: u8 = 0;
+
+/// Returns direction whose frame list is shared by the `dir`. This is the `dir` itself if it
+/// doesn't share the list with any of the preceding directions.
+fn same_as(frm: &Frm, dir: Direction) -> Direction {
+    Direction::iter()
+        .find(|&d| Rc::ptr_eq(&frm.frame_lists[d], &frm.frame_lists[dir]))
+        .unwrap()
+}
+
+/// Returns directions that don't share frame list with any preceding direction.
+fn unique_directions(frm: &Frm) -> Vec<Direction> {
+    Direction::iter().filter(|&d| same_as(frm, d) == d).collect()
+}
+
+/// Returns the maximum frame width and height.
+fn cell_size(frm: &Frm) -> (i32, i32) {
+    frm.frame_lists.iter()
+        .flat_map(|(_, l)| l.frames.iter())
+        .fold((0, 0), |(w, h), f| (w.max(f.width), h.max(f.height)))
+}
+
+/// Copies `frame` pixels into `dst` image of `dst_width` width at the `pos`.
+fn blit(frame: &FrmFrame, dst: &mut [u8], dst_width: i32, pos: Point) {
+    let width = frame.width as usize;
+    for y in 0..frame.height {
+        let src_start = (y * frame.width) as usize;
+        let dst_start = ((pos.y + y) * dst_width + pos.x) as usize;
+        dst[dst_start..dst_start + width]
+            .copy_from_slice(&frame.pixels[src_start..src_start + width]);
+    }
+}
+
+/// Returns sprite sheet width, height and pixels.
+fn sprite_sheet(frm: &Frm) -> (i32, i32, Vec<u8>) {
+    let (cell_width, cell_height) = cell_size(frm);
+    let dirs = unique_directions(frm);
+    let frame_count = frm.frame_lists[Direction::NE].frames.len() as i32;
+    let width = cell_width * frame_count;
+    let height = cell_height * dirs.len() as i32;
+    let mut pixels = vec![TRANSPARENT; (width * height) as usize];
+    for (row, &dir) in dirs.iter().enumerate() {
+        for (col, frame) in frm.frame_lists[dir].frames.iter().enumerate() {
+            let pos = Point::new(col as i32 * cell_width, row as i32 * cell_height);
+            blit(frame, &mut pixels, width, pos);
+        }
+    }
+    (width, height, pixels)
+}
+
+/// Returns screen bounds of each frame relative to the object position. The frame shifts are
+/// accumulated the same way as when the object animation is played.
+fn frame_bounds(list: &FrmFrameList) -> Vec<Rect> {
+    let mut shift = Point::new(0, 0);
+    list.frames.iter()
+        .map(|f| {
+            shift += f.shift;
+            let p = shift + list.center;
+            Rect::with_size(p.x - f.width / 2, p.y - f.height + 1, f.width, f.height)
+        })
+        .collect()
+}
+
+/// Animation frames of a frame list placed on a common canvas.
+struct Animation {
+    width: i32,
+    height: i32,
+    /// Position of the object on the canvas.
+    origin: Point,
+    frames: Vec<Vec<u8>>,
+}
+
+fn animation(list: &FrmFrameList) -> Animation {
+    let bounds = frame_bounds(list);
+    let union = bounds.iter().skip(1).fold(bounds[0], |u, r| Rect {
+        left: u.left.min(r.left),
+        top: u.top.min(r.top),
+        right: u.right.max(r.right),
+        bottom: u.bottom.max(r.bottom),
+    });
+    let origin = Point::new(-union.left, -union.top);
+    let frames = list.frames.iter().zip(&bounds)
+        .map(|(f, r)| {
+            let mut pixels = vec![TRANSPARENT; (union.width() * union.height()) as usize];
+            blit(f, &mut pixels, union.width(), r.top_left() + origin);
+            pixels
+        })
+        .collect();
+    Animation {
+        width: union.width(),
+        height: union.height(),
+        origin,
+        frames,
+    }
+}
+
+fn palette_rgb(palette: &Palette) -> Vec<Rgb24> {
+    (0..=255).map(|i| palette.rgb::<Color8>(i)).collect()
+}
+
+fn fps(frm: &Frm) -> u16 {
+    if frm.fps == 0 {
+        10
+    } else {
+        frm.fps
+    }
+}
+
+fn animation_file_name(base_name: &str, dir: Direction) -> String {
+    format!("{}_{}.png", base_name, dir as u8)
+}
+
+/// Writes the sidecar JSON describing the sprite sheet named `image` and optionally
+/// animations written with `animation_file_name()`.
+fn write_meta(frm: &Frm, image: &str, animations: Option<(&str, &[Animation])>,
+    out: &mut impl Write) -> io::Result<()>
+{
+    let (cell_width, cell_height) = cell_size(frm);
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"image\": {},", json_str(image))?;
+    writeln!(out, "  \"fps\": {},", fps(frm))?;
+    writeln!(out, "  \"action_frame\": {},", frm.action_frame)?;
+    writeln!(out, "  \"cell_width\": {},", cell_width)?;
+    writeln!(out, "  \"cell_height\": {},", cell_height)?;
+    writeln!(out, "  \"directions\": [")?;
+    let dirs = unique_directions(frm);
+    for dir in Direction::iter() {
+        write!(out, "    {{\"direction\": {}, ", dir as u8)?;
+        let base = same_as(frm, dir);
+        if base != dir {
+            write!(out, "\"same_as\": {}}}", base as u8)?;
+        } else {
+            let row = dirs.iter().position(|&d| d == dir).unwrap();
+            let list = &frm.frame_lists[dir];
+            writeln!(out, "\"center_x\": {}, \"center_y\": {},",
+                list.center.x, list.center.y)?;
+            if let Some((base_name, anims)) = animations {
+                let anim = &anims[row];
+                writeln!(out, "     \"animation\": {{\"image\": {}, \"origin_x\": {}, \
+                    \"origin_y\": {}}},",
+                    json_str(&animation_file_name(base_name, dir)),
+                    anim.origin.x, anim.origin.y)?;
+            }
+            writeln!(out, "     \"frames\": [")?;
+            for (col, f) in list.frames.iter().enumerate() {
+                let sep = if col + 1 < list.frames.len() { "," } else { "" };
+                writeln!(out, "      {{\"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \
+                    \"shift_x\": {}, \"shift_y\": {}}}{}",
+                    col as i32 * cell_width, row as i32 * cell_height, f.width, f.height,
+                    f.shift.x, f.shift.y, sep)?;
+            }
+            write!(out, "    ]}}")?;
+        }
+        let sep = if dir as usize + 1 < Direction::len() { "," } else { "" };
+        writeln!(out, "{}", sep)?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+/// Exports the `frm` into `out_dir` as `<base_name>.png` sprite sheet and `<base_name>.json`
+/// sidecar. If `animated` is `true` also writes animated PNG per distinct frame list as
+/// `<base_name>_<direction>.png`.
+pub fn export(frm: &Frm, palette: &Palette, out_dir: &Path, base_name: &str, animated: bool)
+    -> io::Result<()>
+{
+    let palette = palette_rgb(palette);
+    let image = format!("{}.png", base_name);
+
+    let (width, height, pixels) = sprite_sheet(frm);
+    let mut wr = BufWriter::new(File::create(out_dir.join(&image))?);
+    png::write_indexed(&mut wr, width as u32, height as u32, &palette, Some(TRANSPARENT),
+        &pixels)?;
+    wr.flush()?;
+
+    let animations = if animated {
+        let dirs = unique_directions(frm);
+        let mut anims = Vec::with_capacity(dirs.len());
+        for dir in dirs {
+            let anim = animation(&frm.frame_lists[dir]);
+            let frames: Vec<_> = anim.frames.iter().map(|f| &f[..]).collect();
+            let path = out_dir.join(animation_file_name(base_name, dir));
+            let mut wr = BufWriter::new(File::create(path)?);
+            png::write_indexed_animated(&mut wr, anim.width as u32, anim.height as u32,
+                &palette, Some(TRANSPARENT), &frames, (1, fps(frm)))?;
+            wr.flush()?;
+            anims.push(anim);
+        }
+        Some(anims)
+    } else {
+        None
+    };
+
+    let mut wr = BufWriter::new(File::create(out_dir.join(format!("{}.json", base_name)))?);
+    write_meta(frm, &image, animations.as_ref().map(|a| (base_name, &a[..])), &mut wr)?;
+    wr.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(shift: (i32, i32), width: i32, height: i32, color: u8) -> FrmFrame {
+        FrmFrame {
+            shift: shift.into(),
+            width,
+            height,
+            pixels: vec![color; (width * height) as usize].into(),
+        }
+    }
+
+    fn frm() -> Frm {
+        let ne = Rc::new(FrmFrameList {
+            center: Point::new(0, 2),
+            frames: vec![frame((0, 0), 2, 3, 1), frame((1, -1), 3, 1, 2)],
+        });
+        let e = Rc::new(FrmFrameList {
+            center: Point::new(1, 0),
+            frames: vec![frame((0, 0), 1, 1, 3), frame((0, 0), 1, 1, 4)],
+        });
+        Frm {
+            fps: 0,
+            action_frame: 1,
+            frame_lists: EnumMap::from(|d| if d == Direction::E { e.clone() } else { ne.clone() }),
+        }
+    }
+
+    #[test]
+    fn sprite_sheet_() {
+        let frm = frm();
+        assert_eq!(unique_directions(&frm), vec![Direction::NE, Direction::E]);
+        assert_eq!(same_as(&frm, Direction::NW), Direction::NE);
+
+        let (width, height, pixels) = sprite_sheet(&frm);
+        assert_eq!((width, height), (6, 6));
+        assert_eq!(pixels, &[
+            1, 1, 0, 2, 2, 2,
+            1, 1, 0, 0, 0, 0,
+            1, 1, 0, 0, 0, 0,
+            3, 0, 0, 4, 0, 0,
+            0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0,
+        ][..]);
+    }
+
+    #[test]
+    fn animation_() {
+        let frm = frm();
+        let list = &frm.frame_lists[Direction::NE];
+        assert_eq!(frame_bounds(list), vec![
+            Rect::with_size(-1, 0, 2, 3),
+            Rect::with_size(0, 1, 3, 1),
+        ]);
+
+        let anim = animation(list);
+        assert_eq!((anim.width, anim.height), (4, 3));
+        assert_eq!(anim.origin, Point::new(1, 0));
+        assert_eq!(anim.frames, vec![
+            vec![1, 1, 0, 0,
+                 1, 1, 0, 0,
+                 1, 1, 0, 0],
+            vec![0, 0, 0, 0,
+                 0, 2, 2, 2,
+                 0, 0, 0, 0],
+        ]);
+    }
+}
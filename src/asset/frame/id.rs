@@ -11,6 +11,9 @@ use crate::graphics::geometry::hex::Direction;
 
 pub type Idx = u16;
 
+/// Maximum FID index that fits into the packed form.
+pub const MAX_IDX: Idx = 0xfff;
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub enum FrameId {
     Critter(Critter),
@@ -404,7 +407,7 @@ struct Parts {
 
 impl Parts {
     fn pack(self) -> Option<u32> {
-        if self.sub_anim > 15 || self.idx > 4095 {
+        if self.sub_anim > 15 || self.idx > MAX_IDX {
             return None;
         }
         Some(self.direction.map(|d| d as u32 + 1).unwrap_or(0) << 28
@@ -0,0 +1,231 @@
+//! Conversion of PNG sprite sheets back to FRM files. Used by the `frm import` subcommand.
+//!
+//! The input is the sidecar JSON in the format written by `export` and the sprite sheet it
+//! refers to. The sheet may be edited freely as long as the frame rectangles in the JSON are
+//! kept in sync. Pixels with alpha below 128 become transparent, the rest are quantized to the
+//! game palette. Indexed images that use the game palette keep their color indices as is.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::path::Path;
+
+use super::*;
+use crate::graphics::color::{Color8, Rgb24};
+use crate::graphics::color::palette::Palette;
+use crate::graphics::png::{self, Image, Pixels};
+use crate::util::json::{self, Value};
+
+/// Palette color index that is drawn transparent.
+const TRANSPARENT: u8 = 0;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn int(v: &Value, key: &str, min: i64, max: i64) -> io::Result<i64> {
+    v.get(key)
+        .and_then(|v| v.as_i64())
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| invalid(format!("missing or invalid `{}`", key)))
+}
+
+fn i16_(v: &Value, key: &str) -> io::Result<i32> {
+    Ok(int(v, key, i16::MIN as i64, i16::MAX as i64)? as i32)
+}
+
+struct Quantizer<'a> {
+    palette: &'a Palette,
+    /// Whether the image is indexed and its palette matches the game palette.
+    direct: bool,
+}
+
+impl<'a> Quantizer<'a> {
+    fn new(image: &Image, palette: &'a Palette) -> Self {
+        let direct = if let Pixels::Indexed { palette: p, .. } = &image.pixels {
+            p.iter().enumerate().all(|(i, &c)| c == palette.rgb::<Color8>(i as u8))
+        } else {
+            false
+        };
+        Self {
+            palette,
+            direct,
+        }
+    }
+
+    fn color_idx(&self, image: &Image, x: u32, y: u32) -> u8 {
+        let (rgb, alpha) = image.rgba(x, y);
+        if alpha < 128 {
+            return TRANSPARENT;
+        }
+        if self.direct {
+            if let Pixels::Indexed { pixels, .. } = &image.pixels {
+                return pixels[(y * image.width + x) as usize];
+            }
+        }
+        self.quantize(rgb)
+    }
+
+    /// Returns the closest palette color that is not transparent.
+    fn quantize(&self, rgb: Rgb24) -> u8 {
+        let idx = self.palette.color_idx(rgb);
+        if idx != TRANSPARENT {
+            return idx;
+        }
+        let dist = |i: u8| {
+            let c = self.palette.rgb::<Color8>(i);
+            let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            d(c.r(), rgb.r()) + d(c.g(), rgb.g()) + d(c.b(), rgb.b())
+        };
+        (1..=255).min_by_key(|&i| dist(i)).unwrap()
+    }
+}
+
+fn frame(v: &Value, image: &Image, quantizer: &Quantizer) -> io::Result<FrmFrame> {
+    let x = int(v, "x", 0, image.width as i64)? as u32;
+    let y = int(v, "y", 0, image.height as i64)? as u32;
+    let width = int(v, "width", 0, (image.width - x) as i64)? as i32;
+    let height = int(v, "height", 0, (image.height - y) as i64)? as i32;
+    let shift = Point::new(i16_(v, "shift_x")?, i16_(v, "shift_y")?);
+
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for fy in 0..height as u32 {
+        for fx in 0..width as u32 {
+            pixels.push(quantizer.color_idx(image, x + fx, y + fy));
+        }
+    }
+
+    Ok(FrmFrame {
+        shift,
+        width,
+        height,
+        pixels: pixels.into(),
+    })
+}
+
+/// Builds FRM from the sidecar JSON `meta` and the sprite sheet `image` it describes.
+pub fn import(meta: &Value, image: &Image, palette: &Palette) -> io::Result<Frm> {
+    let quantizer = Quantizer::new(image, palette);
+
+    let fps = int(meta, "fps", 0, u16::MAX as i64)? as u16;
+    let action_frame = int(meta, "action_frame", 0, u16::MAX as i64)? as u16;
+
+    let mut frame_lists: EnumMap<Direction, Option<Rc<FrmFrameList>>> = EnumMap::new();
+    let dirs = meta.get("directions")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| invalid("missing or invalid `directions`".into()))?;
+    for v in dirs {
+        let dir = Direction::from_ordinal(
+            int(v, "direction", 0, Direction::len() as i64 - 1)? as usize);
+        if frame_lists[dir].is_some() {
+            return Err(invalid(format!("duplicate direction {}", dir as u8)));
+        }
+        frame_lists[dir] = Some(if v.get("same_as").is_some() {
+            let base = Direction::from_ordinal(
+                int(v, "same_as", 0, Direction::len() as i64 - 1)? as usize);
+            frame_lists[base].clone()
+                .ok_or_else(|| invalid(format!(
+                    "direction {} refers to direction {} that is not defined before it",
+                    dir as u8, base as u8)))?
+        } else {
+            let frames = v.get("frames")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| invalid("missing or invalid `frames`".into()))?
+                .iter()
+                .map(|v| frame(v, image, &quantizer))
+                .collect::<io::Result<Vec<_>>>()?;
+            Rc::new(FrmFrameList {
+                center: Point::new(i16_(v, "center_x")?, i16_(v, "center_y")?),
+                frames,
+            })
+        });
+    }
+
+    let frame_count = frame_lists[Direction::NE].as_ref().map(|l| l.frames.len());
+    for (dir, l) in frame_lists.iter() {
+        let l = l.as_ref().ok_or_else(|| invalid(format!("direction {} is missing", dir as u8)))?;
+        if l.frames.is_empty() || Some(l.frames.len()) != frame_count {
+            return Err(invalid(
+                "all directions must have the same non-zero number of frames".into()));
+        }
+    }
+
+    Ok(Frm {
+        fps,
+        action_frame,
+        frame_lists: EnumMap::from(|d| frame_lists[d].take().unwrap()),
+    })
+}
+
+/// Reads the sidecar JSON at `meta_path` and the sprite sheet it refers to and builds FRM.
+/// The sprite sheet path is relative to the JSON file directory.
+pub fn import_file(meta_path: &Path, palette: &Palette) -> io::Result<Frm> {
+    let meta = json::parse(&fs::read_to_string(meta_path)?)?;
+    let image = meta.get("image")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| invalid("missing or invalid `image`".into()))?;
+    let image_path = meta_path.parent().unwrap_or_else(|| Path::new("")).join(image);
+    let image = png::read(&mut BufReader::new(File::open(image_path)?))?;
+    import(&meta, &image, palette)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test::ungz;
+
+    fn palette() -> Palette {
+        let data = ungz(include_bytes!("../../graphics/color/color.pal.gz"));
+        crate::asset::palette::read_palette(&mut std::io::Cursor::new(&data[..])).unwrap()
+    }
+
+    #[test]
+    fn import_() {
+        let palette = palette();
+        let opaque = palette.color_idx(Rgb24::new(0xff, 0, 0));
+        assert_ne!(opaque, TRANSPARENT);
+        let red = palette.rgb::<Color8>(opaque);
+
+        let mut rgba = Vec::new();
+        for &(c, a) in &[(red, 0xff), (red, 0), (red, 0x80), (Rgb24::new(0, 0, 0), 0xff)] {
+            rgba.extend_from_slice(&[c.r(), c.g(), c.b(), a]);
+        }
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: Pixels::Rgba(rgba),
+        };
+        let meta = json::parse(r#"{"fps": 10, "action_frame": 0, "directions": [
+            {"direction": 0, "center_x": 1, "center_y": -1, "frames": [
+                {"x": 0, "y": 0, "width": 2, "height": 2, "shift_x": 0, "shift_y": 0},
+                {"x": 1, "y": 1, "width": 1, "height": 1, "shift_x": 2, "shift_y": -3}]},
+            {"direction": 1, "same_as": 0},
+            {"direction": 2, "same_as": 0},
+            {"direction": 3, "same_as": 0},
+            {"direction": 4, "same_as": 0},
+            {"direction": 5, "same_as": 0}]}"#).unwrap();
+
+        let frm = import(&meta, &image, &palette).unwrap();
+        assert_eq!((frm.fps, frm.action_frame), (10, 0));
+        let l = &frm.frame_lists[Direction::NE];
+        assert!(Rc::ptr_eq(l, &frm.frame_lists[Direction::NW]));
+        assert_eq!(l.center, Point::new(1, -1));
+        assert_eq!(l.frames.len(), 2);
+        let black = l.frames[0].pixels[3];
+        assert_ne!(black, TRANSPARENT);
+        assert_eq!(&l.frames[0].pixels[..], &[opaque, TRANSPARENT, opaque, black]);
+        assert_eq!(l.frames[1].shift, Point::new(2, -3));
+        assert_eq!(&l.frames[1].pixels[..], &[black]);
+
+        let missing = json::parse(r#"{"fps": 10, "action_frame": 0, "directions": [
+            {"direction": 0, "center_x": 0, "center_y": 0, "frames": [
+                {"x": 0, "y": 0, "width": 1, "height": 1, "shift_x": 0, "shift_y": 0}]}]}"#)
+            .unwrap();
+        assert!(import(&missing, &image, &palette).is_err());
+
+        let out_of_bounds = json::parse(r#"{"fps": 10, "action_frame": 0, "directions": [
+            {"direction": 0, "center_x": 0, "center_y": 0, "frames": [
+                {"x": 1, "y": 0, "width": 2, "height": 1, "shift_x": 0, "shift_y": 0}]}]}"#)
+            .unwrap();
+        assert!(import(&out_of_bounds, &image, &palette).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use bstring::BString;
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+
+use crate::asset::message::{MessageId, Messages};
+use crate::asset::proto::ProtoId;
+
+const END_PARAGRAPH: &[u8] = b"**END-PAR**";
+const END_DISK: &[u8] = b"**END-DISK**";
+
+/// Holodisk definition from `data/holodisk.txt`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Holodisk {
+    /// Global variable that is non-zero once the holodisk is added to the Pip-Boy.
+    pub global_var: usize,
+    /// Holodisk name message in `pipboy.msg`.
+    pub name: MessageId,
+    /// First text message in `pipboy.msg`.
+    pub text: MessageId,
+    /// Item that adds this holodisk when used. This is an optional fourth field not present in
+    /// the original files where it's the item script that sets the global var.
+    pub item: Option<ProtoId>,
+}
+
+impl Holodisk {
+    pub fn is_added(&self, global_vars: &[i32]) -> bool {
+        global_vars.get(self.global_var).copied().unwrap_or(0) != 0
+    }
+
+    /// Reads the holodisk text from `msgs` as a list of paragraphs.
+    pub fn paragraphs(&self, msgs: &Messages) -> Vec<BString> {
+        let mut r = Vec::new();
+        let mut para = BString::new();
+        for id in self.text.. {
+            let msg = if let Some(v) = msgs.get(id) {
+                v
+            } else {
+                break;
+            };
+            let text = msg.text.as_bytes();
+            if text == END_DISK {
+                break;
+            }
+            if text == END_PARAGRAPH {
+                r.push(std::mem::replace(&mut para, BString::new()));
+                continue;
+            }
+            if !para.as_bytes().is_empty() && !text.is_empty() {
+                para.push(b' ');
+            }
+            for &c in text {
+                para.push(c);
+            }
+        }
+        if !para.as_bytes().is_empty() {
+            r.push(para);
+        }
+        r
+    }
+}
+
+// pipboy_holodisk_init
+pub fn read_holodisks(rd: &mut impl BufRead) -> io::Result<Vec<Holodisk>> {
+    let mut r = Vec::new();
+    for l in rd.lines() {
+        let l = l?;
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let mut fields = l.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty());
+        let mut field = |required| -> io::Result<Option<i64>> {
+            let s = if let Some(s) = fields.next() {
+                s
+            } else if required {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("missing field in holodisk definition: `{}`", l)));
+            } else {
+                return Ok(None);
+            };
+            let v = if let Some(hex) = s.strip_prefix("0x") {
+                i64::from_str_radix(hex, 16)
+            } else {
+                s.parse()
+            };
+            v.map(Some).map_err(|_| Error::new(ErrorKind::InvalidData,
+                format!("couldn't parse holodisk definition field: `{}`", s)))
+        };
+        let global_var = field(true)?.unwrap();
+        let name = field(true)?.unwrap() as MessageId;
+        let text = field(true)?.unwrap() as MessageId;
+        let item = field(false)?;
+        if global_var < 0 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("invalid global var in holodisk definition: `{}`", l)));
+        }
+        let item = if let Some(item) = item {
+            let item = ProtoId::from_packed(item as u32)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData,
+                    format!("invalid item PID in holodisk definition: `{}`", l)))?;
+            Some(item)
+        } else {
+            None
+        };
+        r.push(Holodisk {
+            global_var: global_var as usize,
+            name,
+            text,
+            item,
+        });
+    }
+    Ok(r)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn read_holodisks_() {
+        let s = "\
+# Vault 13
+400, 1000, 1100
+
+401,1001,1200, 0x00000238";
+        let disks = read_holodisks(&mut BufReader::new(Cursor::new(s))).unwrap();
+        assert_eq!(disks, vec![
+            Holodisk { global_var: 400, name: 1000, text: 1100, item: None },
+            Holodisk { global_var: 401, name: 1001, text: 1200,
+                item: Some(ProtoId::from_packed(0x238).unwrap()) },
+        ]);
+        let mut global_vars = vec![0; 402];
+        global_vars[401] = 1;
+        assert!(!disks[0].is_added(&global_vars));
+        assert!(disks[1].is_added(&global_vars));
+        assert!(!disks[1].is_added(&[]));
+
+        assert!(read_holodisks(&mut BufReader::new(Cursor::new("400, 1000"))).is_err());
+    }
+
+    #[test]
+    fn paragraphs() {
+        let msgs = Messages::read(&mut Cursor::new(&b"\
+{100}{}{Line one}
+{101}{}{line two.}
+{102}{}{**END-PAR**}
+{103}{}{Second paragraph.}
+{104}{}{**END-DISK**}
+{105}{}{Not read.}"[..])).unwrap();
+        let disk = Holodisk { global_var: 0, name: 0, text: 100, item: None };
+        assert_eq!(disk.paragraphs(&msgs), vec![
+            BString::from(&b"Line one line two."[..]),
+            BString::from(&b"Second paragraph."[..]),
+        ]);
+    }
+}
@@ -13,6 +13,7 @@ use crate::asset::*;
 use crate::asset::frame::{FrameId, FrameDb};
 use crate::asset::proto::{MapExit, ProtoId, ProtoDb, SubItem, TargetMap};
 use crate::asset::script::ProgramId;
+use crate::error;
 use crate::game::object::{self, *};
 use crate::game::script::*;
 use crate::graphics::{EPoint, Point};
@@ -23,6 +24,9 @@ use crate::util::array2d::Array2d;
 
 pub const ELEVATION_COUNT: u32 = 3;
 
+/// Map versions this reader understands: 19 is used by Fallout 1 and 20 by Fallout 2.
+pub const VERSIONS: [u32; 2] = [19, 20];
+
 struct ScriptInfo {
     sid: ScriptIid,
     program_id: ProgramId,
@@ -56,6 +60,8 @@ pub struct Map {
     pub entrance_direction: Direction,
     pub sqr_tiles: SqrTiles,
     pub map_vars: Box<[i32]>,
+    /// Top-level objects in the order they're stored in the map file.
+    pub objects: Vec<Handle>,
 }
 
 pub struct MapReader<'a, R: 'a> {
@@ -64,25 +70,91 @@ pub struct MapReader<'a, R: 'a> {
     pub proto_db: &'a ProtoDb,
     pub frm_db: &'a FrameDb,
     pub scripts: &'a mut Scripts,
+    /// If set, errors that don't prevent reading the rest of the map (unloadable frames and
+    /// scripts, dangling script references) are collected here instead of failing the read.
+    pub recoverable_errors: Option<&'a mut Vec<Error>>,
+    /// If set, the deviations from the format the reader would otherwise work around (unknown
+    /// version, unknown flag bits, mismatching object counts, trailing data) fail the read.
+    pub strict: bool,
 }
 
 impl<'a, R: 'a + Read> MapReader<'a, R> {
-    pub fn read(&mut self) -> io::Result<Map> {
+    /// Reads the map. Errors are prefixed with the file offset and the index of the object
+    /// being read.
+    pub fn read(&mut self) -> error::Result<Map> {
         debug_time!("MapReader::read()");
+        let mut parser = Parser {
+            reader: OffsetReader {
+                inner: &mut *self.reader,
+                offset: 0,
+            },
+            objects: &mut *self.objects,
+            proto_db: self.proto_db,
+            frm_db: self.frm_db,
+            scripts: &mut *self.scripts,
+            recoverable_errors: self.recoverable_errors.as_mut().map(|v| &mut **v),
+            strict: self.strict,
+            section: "header",
+            object_path: Vec::new(),
+        };
+        parser.read().map_err(|e| parser.located(e))
+    }
+}
+
+/// Keeps track of the number of bytes read for diagnostics.
+struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let r = self.inner.read(buf)?;
+        self.offset += r as u64;
+        Ok(r)
+    }
+}
+
+struct Parser<'a, R> {
+    reader: OffsetReader<&'a mut R>,
+    objects: &'a mut Objects,
+    proto_db: &'a ProtoDb,
+    frm_db: &'a FrameDb,
+    scripts: &'a mut Scripts,
+    recoverable_errors: Option<&'a mut Vec<Error>>,
+    strict: bool,
+    /// Part of the file being read.
+    section: &'static str,
+    /// Index and start offset of the object being read followed by the same for the nested
+    /// inventory items. Left as is on error so the error can be located.
+    object_path: Vec<(usize, u64)>,
+}
+
+impl<'a, R: 'a + Read> Parser<'a, R> {
+    fn read(&mut self) -> io::Result<Map> {
         // header
 
         let version = self.reader.read_u32::<BigEndian>()?;
+        if !VERSIONS.contains(&version) {
+            self.tolerate(invalid_data("version",
+                format!("unsupported version {}, reading as version 20", version)))?;
+        }
 
         let mut name = [0; 16];
-        self.reader.read_exact(&mut name[..]).unwrap();
+        self.reader.read_exact(&mut name[..])?;
 
         let entrance_pos_lin = self.reader.read_i32::<BigEndian>()?;
         let entrance_pos = TileGrid::default().linear_to_rect_inv(entrance_pos_lin as u32);
         debug!("entrance_pos={} ({:?})", entrance_pos_lin, entrance_pos);
         let entrance_elevation = self.reader.read_u32::<BigEndian>()?;
-        assert!(entrance_elevation <= ELEVATION_COUNT);
-        let entrance_direction = Direction::from_u32(self.reader.read_u32::<BigEndian>()?)
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid entrance direction"))?;
+        if entrance_elevation >= ELEVATION_COUNT {
+            return Err(invalid_data("entrance elevation",
+                format!("invalid elevation {}", entrance_elevation)));
+        }
+        let entrance_direction = self.reader.read_u32::<BigEndian>()?;
+        let entrance_direction = Direction::from_u32(entrance_direction)
+            .ok_or_else(|| invalid_data("entrance direction",
+                format!("invalid direction {}", entrance_direction)))?;
         let local_var_count = cmp::max(self.reader.read_i32::<BigEndian>()?, 0) as usize;
 
         let program_id = self.read_program_id(0)?;
@@ -94,7 +166,9 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
 
         let _ = self.reader.read_i32::<BigEndian>()?;
         let map_var_count = cmp::max(self.reader.read_i32::<BigEndian>()?, 0) as usize;
-        let id = self.reader.read_i32::<BigEndian>()?.try_into().unwrap();
+        let id = self.reader.read_i32::<BigEndian>()?;
+        let id = id.try_into()
+            .map_err(|_| invalid_data("map ID", format!("negative map ID {}", id)))?;
         let _time = self.reader.read_u32::<BigEndian>()?;
 
         self.reader.read_exact(&mut [0; 44 * 4][..])?;
@@ -113,14 +187,26 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
             local_vars.push(self.reader.read_i32::<BigEndian>()?);
         }
 
+        self.section = "square tiles";
         let sqr_tiles = self.read_sqr_tiles(flags)?;
+        self.section = "scripts";
         self.read_scripts(&local_vars, savegame)?;
 
         if let Some(program_id) = program_id {
-            self.make_map_script(program_id)?;
+            if let Err(e) = self.make_map_script(program_id) {
+                self.recover(e)?;
+            }
         }
 
-        self.read_objects(version)?;
+        self.section = "objects";
+        let objects = self.read_objects(version)?;
+
+        let mut trailing = Vec::new();
+        self.reader.read_to_end(&mut trailing)?;
+        if !trailing.is_empty() {
+            self.tolerate(invalid_data("end of file",
+                format!("{} bytes of unexpected data after the objects", trailing.len())))?;
+        }
 
         Ok(Map {
             id,
@@ -132,9 +218,46 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
             entrance_direction,
             sqr_tiles,
             map_vars: map_vars.into(),
+            objects,
         })
     }
 
+    /// Collects the recoverable error `e` if requested, otherwise returns it.
+    fn recover(&mut self, e: Error) -> io::Result<()> {
+        if self.recoverable_errors.is_some() {
+            let e: Error = self.located(e).into();
+            self.recoverable_errors.as_mut().unwrap().push(e);
+            Ok(())
+        } else {
+            Err(e)
+        }
+    }
+
+    /// Handles the problem `e` the reader can work around. Returns the error in strict mode,
+    /// otherwise collects it if requested or logs it.
+    fn tolerate(&mut self, e: Error) -> io::Result<()> {
+        if self.strict {
+            return Err(e);
+        }
+        let e: Error = self.located(e).into();
+        if let Some(errors) = &mut self.recoverable_errors {
+            errors.push(e);
+        } else {
+            warn!("{}", e);
+        }
+        Ok(())
+    }
+
+    /// Adds the current offset, section and object path to the error.
+    fn located(&self, e: Error) -> error::Error {
+        let mut loc = format!("at offset 0x{:x}", self.reader.offset);
+        for (i, &(idx, offset)) in self.object_path.iter().enumerate() {
+            let what = if i == 0 { "object" } else { "inventory item" };
+            loc += &format!(", {} {} (at 0x{:x})", what, idx, offset);
+        }
+        error::Error::from(e).context(format!("{}, in {}", loc, self.section))
+    }
+
     fn read_scripts(&mut self, local_vars: &[i32], savegame: bool) -> io::Result<()> {
         for script_kind in ScriptKind::iter() {
             debug!("reading {:?} scripts", script_kind);
@@ -167,7 +290,11 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                         } else {
                             None
                         };
-                        self.scripts.instantiate(script.sid, script.program_id, local_vars)?;
+                        if let Err(e) = self.scripts.instantiate(script.sid, script.program_id,
+                            local_vars)
+                        {
+                            self.recover(e)?;
+                        }
                     }
                 }
             }
@@ -179,7 +306,7 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
         // Maps contain garbage in unused slots but the exact size of the data to skip depends
         // on the script kinds.
 
-        let sid = ScriptIid::read(self.reader);
+        let sid = ScriptIid::read(&mut self.reader);
         let sid = match sid {
             Ok(sid) => sid,
             Err(ref e) if e.kind() == ErrorKind::InvalidData => {
@@ -234,7 +361,8 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
         }
     }
 
-    fn read_objects(&mut self, version: u32) -> io::Result<()> {
+    fn read_objects(&mut self, version: u32) -> io::Result<Vec<Handle>> {
+        let mut r = Vec::new();
         let total_obj_count = self.reader.read_i32::<BigEndian>()?;
         debug!("object count: {}", total_obj_count);
         for elev in 0..ELEVATION_COUNT {
@@ -242,18 +370,42 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
             debug!("object count at elevation {}: {}", elev, obj_count);
 
             for _ in 0..obj_count {
+                self.object_path.push((r.len(), self.reader.offset));
                 let obj = self.read_object(version != 19)?;
                 let script = obj.script;
                 let objh = self.objects.insert(obj);
                 if let Some((sid, _)) = script {
-                    self.scripts.attach_to_object(sid, objh);
+                    if self.scripts.get(sid).is_some() {
+                        self.scripts.attach_to_object(sid, objh);
+                    } else {
+                        self.recover(invalid_data("script",
+                            format!("object {:?} refers to missing script {:?}", objh, sid)))?;
+                    }
                 }
+                self.object_path.pop();
+                r.push(objh);
             }
         }
+        if total_obj_count != r.len() as i32 {
+            // Some mods add objects without updating the total count.
+            self.tolerate(invalid_data("object count",
+                format!("total object count is {} but {} objects found",
+                    total_obj_count, r.len())))?;
+        }
+        Ok(r)
+    }
+
+    /// Checks that `bits` read from the `field` has only the `known` bits set.
+    fn check_flags(&mut self, field: &str, bits: u32, known: u32) -> io::Result<()> {
+        if bits != known {
+            self.tolerate(invalid_data(field,
+                format!("unknown bits 0x{:x}, ignoring", bits & !known)))?;
+        }
         Ok(())
     }
 
     fn read_object(&mut self, f2: bool) -> io::Result<Object> {
+        self.section = "object";
         let id = self.reader.read_u32::<BigEndian>()?;
 
         trace!("object ID {}", id);
@@ -271,21 +423,23 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
         let frame_idx = cmp::max(self.reader.read_i32::<BigEndian>()?, 0) as usize;
         let direction = self.reader.read_u32::<BigEndian>()?;
         let direction = Direction::from_u32(direction)
-            .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                format!("invalid object direction: {}", direction)))?;
-        let fid = FrameId::read(self.reader)?;
+            .ok_or_else(|| invalid_data("direction",
+                format!("invalid direction {}", direction)))?;
+        let fid = FrameId::read(&mut self.reader)?;
         trace!("{:?}", fid);
 
-        self.frm_db.get(fid)?;
+        if let Err(e) = self.frm_db.get(fid) {
+            self.recover(Error::new(e.kind(), format!("couldn't load frame {:?}: {}", fid, e)))?;
+        }
 
-        let flags = self.reader.read_u32::<BigEndian>()?;
-        let flags = BitFlags::from_bits(flags)
-            .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                format!("unknown object flags: {:x}", flags)))?;
+        let flags_u32 = self.reader.read_u32::<BigEndian>()?;
+        let flags = BitFlags::<Flag>::from_bits_truncate(flags_u32);
+        self.check_flags("flags", flags_u32, flags.bits())?;
 
         let elevation = self.reader.read_u32::<BigEndian>()?;
-        let pid = ProtoId::read(self.reader)?;
-        let proto = self.proto_db.proto(pid)?;
+        let pid = ProtoId::read(&mut self.reader)?;
+        let proto = self.proto_db.proto(pid)
+            .map_err(|e| Error::new(e.kind(), format!("pid: {:?}: {}", pid, e)))?;
         trace!("{:?} {:?}", pid, proto.borrow().name());
         let _cid = self.reader.read_u32::<BigEndian>()?;
         let light_emitter = LightEmitter {
@@ -299,14 +453,14 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
 
         // proto update data
 
+        self.section = "proto update data";
         let inventory_len = usize::try_from(self.reader.read_u32::<BigEndian>()?).unwrap();
         let _inventory_capacity = self.reader.read_i32::<BigEndian>()? as usize;
         let _ = self.reader.read_u32::<BigEndian>()?;
 
-        let updated_flags = self.reader.read_u32::<BigEndian>()?;
-        let updated_flags = BitFlags::from_bits(updated_flags)
-            .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                    format!("unknown updated flags: {:x}", updated_flags)))?;
+        let updated_flags_u32 = self.reader.read_u32::<BigEndian>()?;
+        let updated_flags = BitFlags::<UpdatedFlag>::from_bits_truncate(updated_flags_u32);
+        self.check_flags("updated flags", updated_flags_u32, updated_flags.bits())?;
         trace!("updated_flags: {:?}", updated_flags);
 
         let sub = if pid.kind() == EntityKind::Critter {
@@ -315,10 +469,9 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
             let _combat_state = self.reader.read_u32::<BigEndian>()?;
             let _action_points = self.reader.read_u32::<BigEndian>()?;
 
-            let damage_flags = self.reader.read_u32::<BigEndian>()?;
-            let damage_flags = BitFlags::from_bits(damage_flags)
-                .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                    format!("unknown damage flags: {:x}", damage_flags)))?;
+            let damage_flags_u32 = self.reader.read_u32::<BigEndian>()?;
+            let damage_flags = BitFlags::<DamageFlag>::from_bits_truncate(damage_flags_u32);
+            self.check_flags("damage flags", damage_flags_u32, damage_flags.bits())?;
 
             let ai_packet = self.reader.read_i32::<BigEndian>()?;
             let team_id = self.reader.read_i32::<BigEndian>()?;
@@ -336,6 +489,7 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                     ai_packet,
                     team_id,
                     who_hit_me,
+                    enemy: None,
                 },
                 dude: None,
             })
@@ -364,7 +518,10 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                             SubObject::Item(object::Item { ammo_count, ammo_proto })
                         }
                         SubItem::Ammo(_) => {
-                            let ammo_count = self.reader.read_i32::<BigEndian>()?.try_into().unwrap();
+                            let ammo_count = self.reader.read_i32::<BigEndian>()?;
+                            let ammo_count = ammo_count.try_into()
+                                .map_err(|_| invalid_data("ammo count",
+                                    format!("negative count {}", ammo_count)))?;
                             SubObject::Item(object::Item { ammo_count, ammo_proto: None })
                         }
                         SubItem::Misc(ref proto) => {
@@ -387,10 +544,9 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                     let kind = k.scenery().unwrap();
                     match kind {
                         SceneryKind::Door => {
-                            let flags = self.reader.read_u32::<BigEndian>()?;
-                            let flags = BitFlags::from_bits(flags)
-                                .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                                    format!("unknown door flags: {:x}", flags)))?;
+                            let flags_u32 = self.reader.read_u32::<BigEndian>()?;
+                            let flags = BitFlags::<DoorFlag>::from_bits_truncate(flags_u32);
+                            self.check_flags("door flags", flags_u32, flags.bits())?;
                             trace!("door flags: {:?}", flags);
                             SubObject::Scenery(object::Scenery::Door(object::Door { flags }))
                         }
@@ -398,8 +554,8 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                             let location = self.reader.read_u32::<BigEndian>()?;
                             let map = self.reader.read_i32::<BigEndian>()?;
                             let exit = MapExit::decode(map, location)
-                                .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                                    format!("invalid map exit: map={} location={}", map, location)))?;
+                                .ok_or_else(|| invalid_data("stairs exit",
+                                    format!("invalid map={} location={}", map, location)))?;
                             SubObject::Scenery(object::Scenery::Stairs(exit))
                         }
                         SceneryKind::Elevator => {
@@ -415,8 +571,8 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                             };
                             let location = self.reader.read_u32::<BigEndian>()?;
                             let exit = MapExit::decode(map, location)
-                                .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                                    format!("invalid map exit: map={} location={}", map, location)))?;
+                                .ok_or_else(|| invalid_data("ladder exit",
+                                    format!("invalid map={} location={}", map, location)))?;
                             SubObject::Scenery(object::Scenery::Ladder(exit))
                         }
                         SceneryKind::Misc => SubObject::None,
@@ -427,10 +583,13 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                         // Exit area.
                         let map = self.reader.read_i32::<BigEndian>()?;
                         trace!("map={}", map);
-                        assert!(map >= 0 || fid.idx() >= 33);
+                        if map < 0 && fid.idx() < 33 {
+                            self.tolerate(invalid_data("exit target map", format!(
+                                "special target {} with non-marker frame {:?}", map, fid)))?;
+                        }
                         let map = TargetMap::decode(map)
-                            .ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                                format!("invalid exit target map: {}", map)))?;
+                            .ok_or_else(|| invalid_data("exit target map",
+                                format!("invalid map {}", map)))?;
                         /* if charges <= 0
     //          {
     //            v7 = obj->art_fid & 0xFFF;
@@ -441,8 +600,10 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
                         let pos = TileGrid::default().linear_to_rect_inv(pos);
                         let elevation = self.reader.read_u32::<BigEndian>()?;
                         let pos = pos.elevated(elevation);
-                        let direction = Direction::from_u32(self.reader.read_u32::<BigEndian>()?)
-                            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid exit direction"))?;
+                        let direction = self.reader.read_u32::<BigEndian>()?;
+                        let direction = Direction::from_u32(direction)
+                            .ok_or_else(|| invalid_data("exit direction",
+                                format!("invalid direction {}", direction)))?;
                         let exit = MapExit {
                             map,
                             pos,
@@ -465,9 +626,14 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
         };
         for i in 0..inventory_len {
             trace!("loading inventory item {}/{}", i, inventory_len);
-            let count = self.reader.read_i32::<BigEndian>()?.try_into().unwrap();
+            self.object_path.push((i, self.reader.offset));
+            self.section = "inventory";
+            let count = self.reader.read_i32::<BigEndian>()?;
+            let count = count.try_into()
+                .map_err(|_| invalid_data("item count", format!("negative count {}", count)))?;
             trace!("item count: {}", count);
             let object = self.read_object(f2)?;
+            self.object_path.pop();
             let object = self.objects.insert(object);
             inventory.items.push(InventoryItem {
                 object,
@@ -492,14 +658,15 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
     }
 
     fn read_obj_script(&mut self) -> io::Result<Option<(ScriptIid, ProgramId)>> {
-        let sid = ScriptIid::read_opt(self.reader)?;
+        let sid = ScriptIid::read_opt(&mut self.reader)?;
         trace!("sid: {:?}", sid);
 
         let program_id = self.read_program_id(1)?;
         trace!("program_id: {:?}", program_id);
 
         if sid.is_some() != program_id.is_some() {
-            warn!("bad sid/program_id pair in object");
+            self.tolerate(invalid_data("script",
+                format!("bad sid/program_id pair: {:?}/{:?}, ignoring", sid, program_id)))?;
             return Ok(None);
         }
 
@@ -512,9 +679,8 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
 
     fn read_outline(&mut self) -> io::Result<Option<Outline>> {
         let flags_u32 = self.reader.read_u32::<BigEndian>()?;
-        let flags = &mut BitFlags::from_bits(flags_u32)
-            .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                format!("unknown object outline flags: {:x}", flags_u32)))?;
+        let flags: &mut BitFlags<OutlineFlag> = &mut BitFlags::from_bits_truncate(flags_u32);
+        self.check_flags("outline flags", flags_u32, flags.bits())?;
 
         fn take_bit(flags: &mut BitFlags<OutlineFlag>, flag: OutlineFlag) -> bool {
             let r = flags.contains(flag);
@@ -534,7 +700,8 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
             else if take_bit(flags, OutlineFlag::Brown) { OutlineStyle::Brown }
             else { return Ok(None) };
         if !flags.is_empty() {
-            warn!("mutually exclusive outline flags present: 0x{:x}", flags_u32);
+            self.tolerate(invalid_data("outline flags",
+                format!("mutually exclusive flags present: 0x{:x}", flags_u32)))?;
             return Ok(Some(Outline {
                 style: OutlineStyle::Purple,
                 translucent: false,
@@ -588,3 +755,7 @@ impl<'a, R: 'a + Read> MapReader<'a, R> {
         Ok(sqr_tiles)
     }
 }
+
+fn invalid_data(field: &str, msg: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {}", field, msg))
+}
@@ -1,6 +1,9 @@
+use log::*;
 use std::collections::HashMap;
-use std::io::{self, BufRead};
+use std::io::{BufRead, ErrorKind};
 
+use crate::asset::Game;
+use crate::error::{Error, Result, ResultExt};
 use crate::fs::FileSystem;
 use crate::graphics::EPoint;
 use crate::graphics::geometry::hex::TileGrid;
@@ -24,11 +27,21 @@ pub struct MapDb {
 }
 
 impl MapDb {
-    pub fn new(fs: &FileSystem) -> io::Result<Self> {
-        Self::read(&mut fs.reader("data/maps.txt")?)
+    pub fn new(fs: &FileSystem) -> Result<Self> {
+        match fs.reader("data/maps.txt") {
+            Ok(mut rd) => Self::read(&mut rd).context(|| "reading data/maps.txt"),
+            // Fallout 1 has the map list built into the executable.
+            Err(e) if e.kind() == ErrorKind::NotFound && fs.game() == Game::Fallout1 => {
+                warn!("data/maps.txt not found, map exits won't work");
+                Ok(Self {
+                    maps: Vec::new(),
+                })
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    fn read(rd: &mut impl BufRead) -> io::Result<Self> {
+    fn read(rd: &mut impl BufRead) -> Result<Self> {
         let ini = crate::asset::read_ini(rd)?;
         let mut maps = Vec::new();
         for i in 0..1000 {
@@ -43,101 +56,126 @@ impl MapDb {
             } else {
                 break;
             };
-            let name = section.get("map_name").map(|v| v.to_owned()).expect("missing map_name");
-            let music = section.get("music").map(|v| v.to_owned()).to_owned();
-
-            let ambient_sfx = if let Some(ambient_sfx) = section.get("ambient_sfx") {
-                ambient_sfx.split(',')
-                    .map(|s| {
-                        let mut parts = s.splitn(2, ':');
-                        let sfx = parts.next().unwrap().trim();
-                        let val = parts.next().unwrap().trim();
-
-                        // Handle bad input:
-                        // ambient_sfx=water:40, water1:25, animal:15 animal:10, pebble:5, pebble1:5
-                        //                                           ^
-                        let val = val.split(' ').next().unwrap();
-
-                        let val = val.parse().unwrap();
-                        (sfx.into(), val)
-                    })
-                    .collect()
-            } else {
-                vec![]
-            };
+            maps.push(Self::read_map(lookup_name, section).context(|| format!("in [{}]", n))?);
+        }
+        Ok(Self {
+            maps,
+        })
+    }
 
-            fn parse_bool(s: &str) -> bool {
-                match s.to_ascii_lowercase().as_str() {
-                    "no" => false,
-                    "yes" => true,
-                    _ => panic!("expected yes/no but found: {}", s),
-                }
+    fn read_map(lookup_name: String, section: &HashMap<String, String>) -> Result<MapDef> {
+        let name = section.get("map_name").map(|v| v.to_owned())
+            .ok_or_else(|| Error::invalid_data("missing map_name"))?;
+        let music = section.get("music").map(|v| v.to_owned()).to_owned();
+
+        let ambient_sfx = if let Some(ambient_sfx) = section.get("ambient_sfx") {
+            ambient_sfx.split(',')
+                .map(|s| -> Result<(String, u32)> {
+                    let mut parts = s.splitn(2, ':');
+                    let sfx = parts.next().unwrap().trim();
+                    let val = parts.next()
+                        .ok_or_else(|| Error::invalid_data(
+                            format!("ambient_sfx: missing frequency of {}", sfx)))?
+                        .trim();
+
+                    // Handle bad input:
+                    // ambient_sfx=water:40, water1:25, animal:15 animal:10, pebble:5, pebble1:5
+                    //                                           ^
+                    let val = val.split(' ').next().unwrap();
+
+                    let val = val.parse()
+                        .map_err(|_| Error::invalid_data(
+                            format!("ambient_sfx: invalid frequency of {}: {}", sfx, val)))?;
+                    Ok((sfx.into(), val))
+                })
+                .collect::<Result<_>>()?
+        } else {
+            vec![]
+        };
+
+        fn parse_bool(s: &str) -> Result<bool> {
+            match s.trim().to_ascii_lowercase().as_str() {
+                "no" => Ok(false),
+                "yes" => Ok(true),
+                _ => Err(Error::invalid_data(format!("expected yes/no but found: {}", s))),
             }
+        }
 
-            fn get_bool(m: &HashMap<String, String>, key: &str) -> Option<bool> {
-                let s = m.get(key)?;
-                let s = s.trim();
-                Some(parse_bool(s))
-            }
+        fn get_bool(m: &HashMap<String, String>, key: &str) -> Result<Option<bool>> {
+            m.get(key)
+                .map(|s| parse_bool(s).context(|| key))
+                .transpose()
+        }
 
-            let saved = get_bool(section, "saved").unwrap_or(true);
-            let dead_bodies_age = get_bool(section, "dead_bodies_age").unwrap_or(true);
-            let pipboy_active = get_bool(section, "pipboy_active").unwrap_or(true);
+        let saved = get_bool(section, "saved")?.unwrap_or(true);
+        let dead_bodies_age = get_bool(section, "dead_bodies_age")?.unwrap_or(true);
+        let pipboy_active = get_bool(section, "pipboy_active")?.unwrap_or(true);
+
+        let can_rest_here = if let Some(s) = section.get("can_rest_here") {
+            s.split(',')
+                .map(parse_bool)
+                .collect::<Result<Vec<_>>>()
+                .context(|| "can_rest_here")?
+        } else {
+            vec![true, true, true]
+        };
+        if can_rest_here.len() != 3 {
+            return Err(Error::invalid_data(format!(
+                "can_rest_here: expected 3 values but found {}", can_rest_here.len())));
+        }
 
-            let can_rest_here = if let Some(s) = section.get("can_rest_here") {
-                s.split(',')
-                    .map(parse_bool)
-                    .collect()
+        let mut random_start_points = Vec::new();
+        for i in 0..15 {
+            let key = format!("random_start_point_{}", i);
+            if let Some(s) = section.get(&key) {
+                let pos = Self::parse_start_point(s).context(|| key)?;
+                random_start_points.push(pos);
             } else {
-                vec![true, true, true]
-            };
-            assert_eq!(can_rest_here.len(), 3);
-
-            let mut random_start_points = Vec::new();
-            for i in 0..15 {
-                if let Some(s) = section.get(&format!("random_start_point_{}", i)) {
-                    let mut elev: Option<u32> = None;
-                    let mut tile_num: Option<u32> = None;
-                    for s in s.split(',') {
-                        let mut parts = s.splitn(2, ':');
-                        let k = parts.next().unwrap().trim();
-                        let v = parts.next().unwrap().trim();
-                        match k {
-                            "elev" if elev.is_none() => elev = Some(v.parse().unwrap()),
-                            "tile_num" if tile_num.is_none() => tile_num = Some(v.parse().unwrap()),
-                            _ => panic!("unknown or duplicated key '{}'", k),
-                        }
-                    }
-                    let elev = elev.unwrap();
-                    let tile_num = tile_num.unwrap();
-                    let pos = EPoint::new(elev, TileGrid::default().linear_to_rect_inv(tile_num));
-
-                    random_start_points.push(pos);
-                } else {
-                    break;
-                }
+                break;
             }
-
-            maps.push(MapDef {
-                lookup_name,
-                name,
-                music,
-                ambient_sfx,
-                saved,
-                dead_bodies_age,
-                can_rest_here,
-                pipboy_active,
-                random_start_points,
-            })
         }
-        Ok(Self {
-            maps,
+
+        Ok(MapDef {
+            lookup_name,
+            name,
+            music,
+            ambient_sfx,
+            saved,
+            dead_bodies_age,
+            can_rest_here,
+            pipboy_active,
+            random_start_points,
         })
     }
 
+    fn parse_start_point(s: &str) -> Result<EPoint> {
+        let mut elev: Option<u32> = None;
+        let mut tile_num: Option<u32> = None;
+        for s in s.split(',') {
+            let mut parts = s.splitn(2, ':');
+            let k = parts.next().unwrap().trim();
+            let v = parts.next().unwrap_or("").trim();
+            let v: Result<u32> = v.parse()
+                .map_err(|_| Error::invalid_data(format!("invalid value of {}: {}", k, v)));
+            match k {
+                "elev" if elev.is_none() => elev = Some(v?),
+                "tile_num" if tile_num.is_none() => tile_num = Some(v?),
+                _ => return Err(Error::invalid_data(
+                    format!("unknown or duplicated key '{}'", k))),
+            }
+        }
+        let elev = elev.ok_or_else(|| Error::invalid_data("missing elev"))?;
+        let tile_num = tile_num.ok_or_else(|| Error::invalid_data("missing tile_num"))?;
+        Ok(EPoint::new(elev, TileGrid::default().linear_to_rect_inv(tile_num)))
+    }
+
     pub fn get(&self, id: u32) -> Option<&MapDef> {
         self.maps.get(id as usize)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item=&MapDef> {
+        self.maps.iter()
+    }
 }
 
 #[cfg(test)]
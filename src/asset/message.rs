@@ -1,11 +1,19 @@
+pub mod encoding;
+pub mod format;
+pub mod localization;
+
 use bstring::BString;
 use byteorder::ReadBytesExt;
-use std::io::{self, Error, ErrorKind, prelude::*};
+use std::io::{self, Cursor, Error, ErrorKind, prelude::*};
 use std::str;
 use std::collections::HashMap;
 
+use crate::error::{self, ResultExt};
 use crate::fs::FileSystem;
 
+use encoding::Encoding;
+use format::{Arg, Plural};
+
 /// Bullet character used in message panel.
 pub const BULLET: u8 = b'\x95';
 pub const BULLET_STR: &[u8] = b"\x95";
@@ -15,13 +23,33 @@ pub type MessageId = i32;
 #[derive(Debug, Default)]
 pub struct Messages {
     map: HashMap<MessageId, Message>,
+    encoding: Encoding,
+    plural: Plural,
 }
 
 impl Messages {
+    /// Reads messages in the default `Cp1252` encoding (or UTF-8 if detected).
     pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        Self::read_with_encoding(rd, Encoding::default())
+    }
+
+    /// Reads messages in the specified codepage `encoding`. If the file turns out to be UTF-8
+    /// (has BOM or contains valid multibyte sequences) it is decoded as UTF-8 instead and the
+    /// `Message::text` is converted to `encoding`.
+    pub fn read_with_encoding(rd: &mut impl Read, encoding: Encoding) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        rd.read_to_end(&mut buf)?;
+        let utf8 = encoding::is_utf8(&buf);
+        let start = if buf.starts_with(encoding::UTF8_BOM) {
+            encoding::UTF8_BOM.len()
+        } else {
+            0
+        };
+        let rd = &mut Cursor::new(&buf[start..]);
+
         let mut map = HashMap::new();
         loop {
-            match Message::read(rd) {
+            match Message::read(rd, encoding, utf8) {
                 Ok(Some(m)) => map.insert(m.id, m),
                 Ok(None) => break,
                 Err(e) => return Err(e),
@@ -29,37 +57,78 @@ impl Messages {
         }
         Ok(Self {
             map,
+            encoding,
+            plural: Plural::default(),
         })
     }
 
-    pub fn read_file(fs: &FileSystem, language: &str, path: &str) -> io::Result<Self> {
+    /// Reads `text/{language}/{path}` using encoding and plural rule of the `language`.
+    pub fn read_file(fs: &FileSystem, language: &str, path: &str) -> error::Result<Self> {
         let path = format!("text/{}/{}", language, path);
-        Self::read(&mut fs.reader(&path)?)
+        let mut r = Self::read_with_encoding(&mut fs.reader(&path)?,
+            Encoding::for_language(language))
+            .context(|| format!("reading {}", path))?;
+        r.plural = Plural::for_language(language);
+        Ok(r)
     }
 
     pub fn get(&self, id: MessageId) -> Option<&Message> {
         self.map.get(&id)
     }
+
+    /// Returns text of the message `id` with `args` substituted. See `format::format()`.
+    pub fn format(&self, id: MessageId, args: &[Arg]) -> Option<BString> {
+        self.get(id).map(|m| format::format(&m.text, args))
+    }
+
+    /// Returns the message for the number `n` from the messages with the grammatical forms of
+    /// the same text: `ids[0]` is singular, the rest are the plural forms of the language. If the
+    /// language has more forms than given, the last one is used.
+    pub fn get_plural(&self, ids: &[MessageId], n: i64) -> Option<&Message> {
+        let i = self.plural.form(n).min(ids.len().checked_sub(1)?);
+        self.get(ids[i])
+    }
+
+    /// Codepage of the `Message::text`.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn plural(&self) -> Plural {
+        self.plural
+    }
 }
 
 #[derive(Debug)]
 pub struct Message {
     pub id: MessageId,
     pub audio: BString,
+    /// Text in the codepage of the language. This is what the game fonts can render.
     pub text: BString,
+    /// Decoded text.
+    pub string: String,
 }
 
 impl Message {
-    fn read(rd: &mut impl Read) -> io::Result<Option<Self>> {
+    fn read(rd: &mut impl Read, encoding: Encoding, utf8: bool) -> io::Result<Option<Self>> {
         let id = maybe_read_field(rd)?;
         Ok(if let Some(id) = id {
             let id = id.parse().map_err(|_| Error::new(ErrorKind::InvalidData, "error reading ID field"))?;
             let audio = read_field(rd)?;
             let text = read_field(rd)?;
+            let (text, string) = if utf8 {
+                let string = String::from_utf8(text.as_bytes().to_vec())
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed UTF-8 text"))?;
+                (encoding.encode(&string), string)
+            } else {
+                let string = encoding.decode(text.as_bytes());
+                (text, string)
+            };
             Some(Self {
                 id,
                 audio,
                 text,
+                string,
             })
         } else {
             None
@@ -105,4 +174,35 @@ fn read_field(rd: &mut impl Read) -> io::Result<BString> {
         Ok(None) => Err(Error::new(ErrorKind::InvalidData, "unexpected eof")),
         Err(e) => Err(e),
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_encoding() {
+        let msgs = Messages::read_with_encoding(
+            &mut Cursor::new(&b"{100}{}{\x8f\xe0\xa8\xa2\xa5\xe2}\n{101}{v}{ok}"[..]),
+            Encoding::Cp866).unwrap();
+        assert_eq!(msgs.get(100).unwrap().string, "Привет");
+        assert_eq!(msgs.get(100).unwrap().text, BString::from(&b"\x8f\xe0\xa8\xa2\xa5\xe2"[..]));
+        assert_eq!(msgs.get(101).unwrap().audio, BString::from(&b"v"[..]));
+
+        let msgs = Messages::read_with_encoding(
+            &mut Cursor::new("\u{feff}{100}{}{Привет}".as_bytes()), Encoding::Cp866).unwrap();
+        assert_eq!(msgs.get(100).unwrap().string, "Привет");
+        assert_eq!(msgs.get(100).unwrap().text, BString::from(&b"\x8f\xe0\xa8\xa2\xa5\xe2"[..]));
+    }
+
+    #[test]
+    fn format_and_plural() {
+        let msgs = Messages::read(&mut Cursor::new(
+            &b"{540}{}{It weighs %d pounds.}{541}{}{It weighs %d pound.}"[..])).unwrap();
+        let msg = msgs.get_plural(&[541, 540], 1).unwrap();
+        assert_eq!(msg.id, 541);
+        let msg = msgs.get_plural(&[541, 540], 3).unwrap();
+        assert_eq!(msg.string, "It weighs %d pounds.");
+        assert_eq!(msgs.format(540, &[3.into()]).unwrap(), BString::from(&b"It weighs 3 pounds."[..]));
+        assert!(msgs.get_plural(&[], 1).is_none());
+    }
+}
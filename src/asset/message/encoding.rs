@@ -0,0 +1,192 @@
+//! Single-byte codepages used by the message files.
+
+use bstring::BString;
+
+/// Codepage of the message files. The game fonts are indexed by the codepage bytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Encoding {
+    /// Western European, used by the english, french, german, italian and spanish versions.
+    Cp1252,
+    /// Cyrillic, used by some russian translations.
+    Cp1251,
+    /// Cyrillic DOS codepage, used by most russian community translations.
+    Cp866,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Cp1252
+    }
+}
+
+/// Language (as in `system.language` of `fallout2.cfg`) to encoding. Unlisted languages fall
+/// back to `Cp1252`.
+const LANGUAGES: &[(&str, Encoding)] = &[
+    ("english", Encoding::Cp1252),
+    ("french", Encoding::Cp1252),
+    ("german", Encoding::Cp1252),
+    ("deutsch", Encoding::Cp1252),
+    ("italian", Encoding::Cp1252),
+    ("spanish", Encoding::Cp1252),
+    ("russian", Encoding::Cp866),
+    ("russian_cp866", Encoding::Cp866),
+    ("russian_cp1251", Encoding::Cp1251),
+];
+
+// Characters 0x80..=0xff. '\u{fffd}' marks undefined ones.
+const CP1252_HIGH: [char; 128] = [
+    '\u{20ac}', '\u{fffd}', '\u{201a}', '\u{0192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{fffd}', '\u{017d}', '\u{fffd}',
+    '\u{fffd}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}', '\u{0153}', '\u{fffd}', '\u{017e}', '\u{0178}',
+    '\u{00a0}', '\u{00a1}', '\u{00a2}', '\u{00a3}', '\u{00a4}', '\u{00a5}', '\u{00a6}', '\u{00a7}',
+    '\u{00a8}', '\u{00a9}', '\u{00aa}', '\u{00ab}', '\u{00ac}', '\u{00ad}', '\u{00ae}', '\u{00af}',
+    '\u{00b0}', '\u{00b1}', '\u{00b2}', '\u{00b3}', '\u{00b4}', '\u{00b5}', '\u{00b6}', '\u{00b7}',
+    '\u{00b8}', '\u{00b9}', '\u{00ba}', '\u{00bb}', '\u{00bc}', '\u{00bd}', '\u{00be}', '\u{00bf}',
+    '\u{00c0}', '\u{00c1}', '\u{00c2}', '\u{00c3}', '\u{00c4}', '\u{00c5}', '\u{00c6}', '\u{00c7}',
+    '\u{00c8}', '\u{00c9}', '\u{00ca}', '\u{00cb}', '\u{00cc}', '\u{00cd}', '\u{00ce}', '\u{00cf}',
+    '\u{00d0}', '\u{00d1}', '\u{00d2}', '\u{00d3}', '\u{00d4}', '\u{00d5}', '\u{00d6}', '\u{00d7}',
+    '\u{00d8}', '\u{00d9}', '\u{00da}', '\u{00db}', '\u{00dc}', '\u{00dd}', '\u{00de}', '\u{00df}',
+    '\u{00e0}', '\u{00e1}', '\u{00e2}', '\u{00e3}', '\u{00e4}', '\u{00e5}', '\u{00e6}', '\u{00e7}',
+    '\u{00e8}', '\u{00e9}', '\u{00ea}', '\u{00eb}', '\u{00ec}', '\u{00ed}', '\u{00ee}', '\u{00ef}',
+    '\u{00f0}', '\u{00f1}', '\u{00f2}', '\u{00f3}', '\u{00f4}', '\u{00f5}', '\u{00f6}', '\u{00f7}',
+    '\u{00f8}', '\u{00f9}', '\u{00fa}', '\u{00fb}', '\u{00fc}', '\u{00fd}', '\u{00fe}', '\u{00ff}',
+];
+
+const CP1251_HIGH: [char; 128] = [
+    '\u{0402}', '\u{0403}', '\u{201a}', '\u{0453}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{20ac}', '\u{2030}', '\u{0409}', '\u{2039}', '\u{040a}', '\u{040c}', '\u{040b}', '\u{040f}',
+    '\u{0452}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{fffd}', '\u{2122}', '\u{0459}', '\u{203a}', '\u{045a}', '\u{045c}', '\u{045b}', '\u{045f}',
+    '\u{00a0}', '\u{040e}', '\u{045e}', '\u{0408}', '\u{00a4}', '\u{0490}', '\u{00a6}', '\u{00a7}',
+    '\u{0401}', '\u{00a9}', '\u{0404}', '\u{00ab}', '\u{00ac}', '\u{00ad}', '\u{00ae}', '\u{0407}',
+    '\u{00b0}', '\u{00b1}', '\u{0406}', '\u{0456}', '\u{0491}', '\u{00b5}', '\u{00b6}', '\u{00b7}',
+    '\u{0451}', '\u{2116}', '\u{0454}', '\u{00bb}', '\u{0458}', '\u{0405}', '\u{0455}', '\u{0457}',
+    '\u{0410}', '\u{0411}', '\u{0412}', '\u{0413}', '\u{0414}', '\u{0415}', '\u{0416}', '\u{0417}',
+    '\u{0418}', '\u{0419}', '\u{041a}', '\u{041b}', '\u{041c}', '\u{041d}', '\u{041e}', '\u{041f}',
+    '\u{0420}', '\u{0421}', '\u{0422}', '\u{0423}', '\u{0424}', '\u{0425}', '\u{0426}', '\u{0427}',
+    '\u{0428}', '\u{0429}', '\u{042a}', '\u{042b}', '\u{042c}', '\u{042d}', '\u{042e}', '\u{042f}',
+    '\u{0430}', '\u{0431}', '\u{0432}', '\u{0433}', '\u{0434}', '\u{0435}', '\u{0436}', '\u{0437}',
+    '\u{0438}', '\u{0439}', '\u{043a}', '\u{043b}', '\u{043c}', '\u{043d}', '\u{043e}', '\u{043f}',
+    '\u{0440}', '\u{0441}', '\u{0442}', '\u{0443}', '\u{0444}', '\u{0445}', '\u{0446}', '\u{0447}',
+    '\u{0448}', '\u{0449}', '\u{044a}', '\u{044b}', '\u{044c}', '\u{044d}', '\u{044e}', '\u{044f}',
+];
+
+const CP866_HIGH: [char; 128] = [
+    '\u{0410}', '\u{0411}', '\u{0412}', '\u{0413}', '\u{0414}', '\u{0415}', '\u{0416}', '\u{0417}',
+    '\u{0418}', '\u{0419}', '\u{041a}', '\u{041b}', '\u{041c}', '\u{041d}', '\u{041e}', '\u{041f}',
+    '\u{0420}', '\u{0421}', '\u{0422}', '\u{0423}', '\u{0424}', '\u{0425}', '\u{0426}', '\u{0427}',
+    '\u{0428}', '\u{0429}', '\u{042a}', '\u{042b}', '\u{042c}', '\u{042d}', '\u{042e}', '\u{042f}',
+    '\u{0430}', '\u{0431}', '\u{0432}', '\u{0433}', '\u{0434}', '\u{0435}', '\u{0436}', '\u{0437}',
+    '\u{0438}', '\u{0439}', '\u{043a}', '\u{043b}', '\u{043c}', '\u{043d}', '\u{043e}', '\u{043f}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255d}', '\u{255c}', '\u{255b}', '\u{2510}',
+    '\u{2514}', '\u{2534}', '\u{252c}', '\u{251c}', '\u{2500}', '\u{253c}', '\u{255e}', '\u{255f}',
+    '\u{255a}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256c}', '\u{2567}',
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256b}',
+    '\u{256a}', '\u{2518}', '\u{250c}', '\u{2588}', '\u{2584}', '\u{258c}', '\u{2590}', '\u{2580}',
+    '\u{0440}', '\u{0441}', '\u{0442}', '\u{0443}', '\u{0444}', '\u{0445}', '\u{0446}', '\u{0447}',
+    '\u{0448}', '\u{0449}', '\u{044a}', '\u{044b}', '\u{044c}', '\u{044d}', '\u{044e}', '\u{044f}',
+    '\u{0401}', '\u{0451}', '\u{0404}', '\u{0454}', '\u{0407}', '\u{0457}', '\u{040e}', '\u{045e}',
+    '\u{00b0}', '\u{2219}', '\u{00b7}', '\u{221a}', '\u{2116}', '\u{00a4}', '\u{25a0}', '\u{00a0}',
+];
+
+impl Encoding {
+    pub fn for_language(language: &str) -> Self {
+        LANGUAGES.iter()
+            .find(|&&(l, _)| l.eq_ignore_ascii_case(language))
+            .map(|&(_, e)| e)
+            .unwrap_or_default()
+    }
+
+    fn high(self) -> &'static [char; 128] {
+        match self {
+            Encoding::Cp1252 => &CP1252_HIGH,
+            Encoding::Cp1251 => &CP1251_HIGH,
+            Encoding::Cp866 => &CP866_HIGH,
+        }
+    }
+
+    pub fn decode_char(self, c: u8) -> char {
+        if c < 0x80 {
+            c as char
+        } else {
+            self.high()[c as usize - 0x80]
+        }
+    }
+
+    pub fn decode(self, s: &[u8]) -> String {
+        s.iter().map(|&c| self.decode_char(c)).collect()
+    }
+
+    /// Returns `None` if `c` is not representable in this encoding.
+    pub fn encode_char(self, c: char) -> Option<u8> {
+        if (c as u32) < 0x80 {
+            Some(c as u8)
+        } else if c == '\u{fffd}' {
+            None
+        } else {
+            self.high().iter().position(|&h| h == c).map(|i| i as u8 + 0x80)
+        }
+    }
+
+    /// Encodes `s` replacing characters not representable in this encoding with `?`.
+    pub fn encode(self, s: &str) -> BString {
+        let mut r = BString::new();
+        for c in s.chars() {
+            r.push(self.encode_char(c).unwrap_or(b'?'));
+        }
+        r
+    }
+}
+
+/// Detects whether `s` is UTF-8 rather than a single-byte codepage. Plain ASCII isn't treated
+/// as UTF-8 since it's the same in every supported encoding.
+pub fn is_utf8(s: &[u8]) -> bool {
+    s.starts_with(UTF8_BOM) || (s.iter().any(|&c| c >= 0x80) && std::str::from_utf8(s).is_ok())
+}
+
+pub const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn for_language() {
+        assert_eq!(Encoding::for_language("english"), Encoding::Cp1252);
+        assert_eq!(Encoding::for_language("German"), Encoding::Cp1252);
+        assert_eq!(Encoding::for_language("russian"), Encoding::Cp866);
+        assert_eq!(Encoding::for_language("russian_cp1251"), Encoding::Cp1251);
+        assert_eq!(Encoding::for_language("klingon"), Encoding::Cp1252);
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(Encoding::Cp1252.decode(b"Gr\xfc\xdfe \x95 \x80"), "Grüße • €");
+        assert_eq!(Encoding::Cp1251.decode(b"\xcf\xf0\xe8\xe2\xe5\xf2 \xa8"), "Привет Ё");
+        assert_eq!(Encoding::Cp866.decode(b"\x8f\xe0\xa8\xa2\xa5\xe2 \xf0"), "Привет Ё");
+        assert_eq!(Encoding::Cp1252.decode(b"\x81"), "\u{fffd}");
+    }
+
+    #[test]
+    fn encode() {
+        for &e in &[Encoding::Cp1252, Encoding::Cp1251, Encoding::Cp866] {
+            for c in 0..=255 {
+                let ch = e.decode_char(c);
+                if ch != '\u{fffd}' {
+                    assert_eq!(e.encode_char(ch), Some(c), "{:?} {:x}", e, c);
+                }
+            }
+        }
+        assert_eq!(Encoding::Cp866.encode("Ёж €"), BString::from(&b"\xf0\xa6 ?"[..]));
+    }
+
+    #[test]
+    fn is_utf8_() {
+        assert!(!is_utf8(b"abc"));
+        assert!(is_utf8(b"\xef\xbb\xbfabc"));
+        assert!(is_utf8("Привет".as_bytes()));
+        assert!(!is_utf8(b"\xcf\xf0\xe8\xe2\xe5\xf2"));
+    }
+}
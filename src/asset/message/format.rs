@@ -0,0 +1,199 @@
+//! Substitution of arguments into the message texts and selection of plural forms.
+//!
+//! Message texts use the printf-like specs the original engine and scripts rely on: `%s` and
+//! `%d` take the next argument and `%%` is the percent sign. Translations that need a different
+//! word order can refer to the arguments by position: `%2$s hits %1$s`.
+
+use bstring::{bstr, BString};
+use bstring::bfmt::ToBString;
+use log::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Arg<'a> {
+    Str(&'a bstr),
+    Int(i64),
+}
+
+impl<'a> From<&'a bstr> for Arg<'a> {
+    fn from(v: &'a bstr) -> Self {
+        Arg::Str(v)
+    }
+}
+
+impl<'a> From<&'a BString> for Arg<'a> {
+    fn from(v: &'a BString) -> Self {
+        Arg::Str(v)
+    }
+}
+
+impl<'a> From<&'a str> for Arg<'a> {
+    fn from(v: &'a str) -> Self {
+        Arg::Str(v.into())
+    }
+}
+
+impl From<i32> for Arg<'_> {
+    fn from(v: i32) -> Self {
+        Arg::Int(v as i64)
+    }
+}
+
+impl From<u32> for Arg<'_> {
+    fn from(v: u32) -> Self {
+        Arg::Int(v as i64)
+    }
+}
+
+impl From<i64> for Arg<'_> {
+    fn from(v: i64) -> Self {
+        Arg::Int(v)
+    }
+}
+
+impl Arg<'_> {
+    fn push_to(self, s: &mut BString) {
+        match self {
+            Arg::Str(v) => s.push_str(v),
+            Arg::Int(v) => s.push_str(v.to_bstring()),
+        }
+    }
+}
+
+/// Substitutes `args` into `fmt`. Sequential (`%s`, `%d`) and positional (`%1$s`) specs can be
+/// mixed, the sequential ones take the arguments following the last used one. Malformed specs
+/// and specs referring to missing arguments are logged and copied to the output as is.
+pub fn format(fmt: &bstr, args: &[Arg]) -> BString {
+    let b = fmt.as_bytes();
+    let mut r = BString::with_capacity(b.len());
+    let mut next_arg = 0;
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i];
+        i += 1;
+        if c != b'%' {
+            r.push(c);
+            continue;
+        }
+        if b.get(i) == Some(&b'%') {
+            r.push(b'%');
+            i += 1;
+            continue;
+        }
+
+        let start = i - 1;
+        let digits = b[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+        let pos = if digits > 0 && b.get(i + digits) == Some(&b'$') {
+            let pos = btoi::btoi::<usize>(&b[i..i + digits]).ok().filter(|&v| v > 0);
+            i += digits + 1;
+            pos.map(|v| v - 1)
+        } else {
+            Some(next_arg)
+        };
+        let conv = b.get(i).copied();
+        if conv.is_some() {
+            i += 1;
+        }
+        match (conv, pos.and_then(|pos| args.get(pos).map(|&a| (pos, a)))) {
+            (Some(b's' | b'd' | b'i' | b'u'), Some((pos, arg))) => {
+                arg.push_to(&mut r);
+                next_arg = pos + 1;
+            }
+            _ => {
+                let spec: &bstr = b[start..i].into();
+                warn!("bad format spec `{}` in `{}` with {} args",
+                    spec.display(), fmt.display(), args.len());
+                r.push_str(spec);
+            }
+        }
+    }
+    r
+}
+
+/// Rule selecting the grammatical form of a word for a number.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Plural {
+    /// Singular for 1, plural otherwise. Used by english, german, italian and spanish.
+    OneOther,
+    /// Singular for 0 and 1, plural otherwise. Used by french.
+    ZeroOneOther,
+    /// Forms for 1, 21, 31...; for 2-4, 22-24...; and for the rest. Used by russian.
+    Slavic,
+}
+
+impl Default for Plural {
+    fn default() -> Self {
+        Plural::OneOther
+    }
+}
+
+/// Language (as in `system.language` of `fallout2.cfg`) to plural rule. Unlisted languages fall
+/// back to `OneOther`.
+const LANGUAGES: &[(&str, Plural)] = &[
+    ("french", Plural::ZeroOneOther),
+    ("russian", Plural::Slavic),
+    ("russian_cp866", Plural::Slavic),
+    ("russian_cp1251", Plural::Slavic),
+];
+
+impl Plural {
+    pub fn for_language(language: &str) -> Self {
+        LANGUAGES.iter()
+            .find(|&&(l, _)| l.eq_ignore_ascii_case(language))
+            .map(|&(_, p)| p)
+            .unwrap_or_default()
+    }
+
+    /// Index of the form to use for `n`: 0 is singular, the following are the plural forms.
+    pub fn form(self, n: i64) -> usize {
+        let n = n.unsigned_abs();
+        match self {
+            Plural::OneOther => if n == 1 { 0 } else { 1 },
+            Plural::ZeroOneOther => if n <= 1 { 0 } else { 1 },
+            Plural::Slavic => {
+                let (n10, n100) = (n % 10, n % 100);
+                if n10 == 1 && n100 != 11 {
+                    0
+                } else if (2..=4).contains(&n10) && !(12..=14).contains(&n100) {
+                    1
+                } else {
+                    2
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn f(fmt: &str, args: &[Arg]) -> String {
+        format(fmt.into(), args).display().to_string()
+    }
+
+    #[test]
+    fn format_() {
+        assert_eq!(f("", &[]), "");
+        assert_eq!(f("no args", &["x".into()]), "no args");
+        assert_eq!(f("%s hits for %d", &["Sulik".into(), 12.into()]), "Sulik hits for 12");
+        assert_eq!(f("%2$d durch %1$s", &["Sulik".into(), 12.into()]), "12 durch Sulik");
+        assert_eq!(f("%2$s %s", &["a".into(), "b".into(), "c".into()]), "b c");
+        assert_eq!(f("100%% %s", &["done".into()]), "100% done");
+        assert_eq!(f("%s %s", &["a".into()]), "a %s");
+        assert_eq!(f("%3$s", &["a".into()]), "%3$s");
+        assert_eq!(f("%0$s", &["a".into()]), "%0$s");
+        assert_eq!(f("%x", &[1.into()]), "%x");
+        assert_eq!(f("trailing %", &[]), "trailing %");
+    }
+
+    #[test]
+    fn plural() {
+        let forms = |p: Plural, ns: &[i64]| ns.iter().map(|&n| p.form(n)).collect::<Vec<_>>();
+        assert_eq!(forms(Plural::OneOther, &[0, 1, 2, 21]), vec![1, 0, 1, 1]);
+        assert_eq!(forms(Plural::ZeroOneOther, &[0, 1, 2]), vec![0, 0, 1]);
+        assert_eq!(forms(Plural::Slavic, &[1, 2, 5, 11, 12, 21, 22, 25, 111, 104]),
+            vec![0, 1, 2, 2, 2, 0, 1, 2, 2, 1]);
+        assert_eq!(Plural::for_language("Russian"), Plural::Slavic);
+        assert_eq!(Plural::for_language("klingon"), Plural::OneOther);
+    }
+}
@@ -0,0 +1,197 @@
+use bstring::BString;
+use log::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error;
+use crate::fs::{watch, FileSystem};
+use super::{MessageId, Messages};
+use super::encoding::Encoding;
+
+/// Message in one of the msg files of the language: `text/<language>/<file>`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MessageKey {
+    pub file: &'static str,
+    pub id: MessageId,
+}
+
+impl MessageKey {
+    pub const fn new(file: &'static str, id: MessageId) -> Self {
+        Self {
+            file,
+            id,
+        }
+    }
+}
+
+/// Loads the msg files of the current language on demand and caches them.
+/// The language can be changed at runtime. Holders of the loaded `Messages` should compare
+/// `generation()` to know when to load them again.
+pub struct Localization {
+    fs: Rc<FileSystem>,
+    language: RefCell<String>,
+    files: RefCell<HashMap<String, Rc<Messages>>>,
+    generation: Cell<u32>,
+}
+
+impl Localization {
+    /// File checked to exist when switching the language.
+    const PROBE_FILE: &'static str = "game/misc.msg";
+
+    pub fn new(fs: Rc<FileSystem>, language: &str) -> Self {
+        Self {
+            fs,
+            language: RefCell::new(language.into()),
+            files: RefCell::new(HashMap::new()),
+            generation: Cell::new(0),
+        }
+    }
+
+    pub fn fs(&self) -> &Rc<FileSystem> {
+        &self.fs
+    }
+
+    pub fn language(&self) -> String {
+        self.language.borrow().clone()
+    }
+
+    /// Codepage of the current language.
+    pub fn encoding(&self) -> Encoding {
+        Encoding::for_language(&self.language.borrow())
+    }
+
+    /// Incremented each time the language is changed.
+    pub fn generation(&self) -> u32 {
+        self.generation.get()
+    }
+
+    /// Returns the messages of the msg `file` (e.g. `game/misc.msg`) of the current language.
+    pub fn messages(&self, file: &str) -> error::Result<Rc<Messages>> {
+        if let Some(msgs) = self.files.borrow().get(file) {
+            return Ok(msgs.clone());
+        }
+        let msgs = Rc::new(Messages::read_file(&self.fs, &self.language.borrow(), file)?);
+        self.files.borrow_mut().insert(file.into(), msgs.clone());
+        Ok(msgs)
+    }
+
+    /// Returns text of the message. Missing files and messages are logged.
+    pub fn get(&self, key: MessageKey) -> Option<BString> {
+        match self.messages(key.file) {
+            Ok(msgs) => {
+                let r = msgs.get(key.id).map(|m| m.text.clone());
+                if r.is_none() {
+                    warn!("no message {} in {}", key.id, key.file);
+                }
+                r
+            }
+            Err(e) => {
+                warn!("couldn't load {}: {}", key.file, e);
+                None
+            }
+        }
+    }
+
+    /// Switches to the `language`. The cached msg files are dropped. Fails if the language
+    /// has no msg files, in which case the current language is kept.
+    pub fn set_language(&self, language: &str) -> error::Result<()> {
+        if self.language.borrow().eq_ignore_ascii_case(language) {
+            return Ok(());
+        }
+        let probe = Messages::read_file(&self.fs, language, Self::PROBE_FILE)?;
+        info!("switching language from {} to {}", self.language.borrow(), language);
+        *self.language.borrow_mut() = language.into();
+        let mut files = self.files.borrow_mut();
+        files.clear();
+        files.insert(Self::PROBE_FILE.into(), Rc::new(probe));
+        self.generation.set(self.generation.get() + 1);
+        Ok(())
+    }
+
+    /// Drops the cached msg file if `path` refers to one. Returns `false` if it doesn't.
+    pub fn reload(&self, path: &str) -> bool {
+        let path = watch::normalize(path);
+        let prefix = format!("text/{}/", self.language.borrow().to_lowercase());
+        let file = if let Some(v) = path.strip_prefix(&prefix) {
+            v.to_owned()
+        } else {
+            return false;
+        };
+        self.files.borrow_mut().remove(&file).is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn write_msg(root: &Path, language: &str, file: &str, text: &str) {
+        let path = root.join("text").join(language).join("game").join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("{{100}}{{}}{{{}}}\n", text)).unwrap();
+    }
+
+    fn new(name: &str) -> (PathBuf, Localization) {
+        let root = std::env::temp_dir()
+            .join(format!("vault13_localization_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        write_msg(&root, "english", "misc.msg", "Hello");
+        write_msg(&root, "german", "misc.msg", "Hallo");
+        let mut fs = FileSystem::mock();
+        fs.register_provider(crate::fs::stdfs::new_provider(&root).unwrap());
+        (root, Localization::new(Rc::new(fs), "english"))
+    }
+
+    const HELLO: MessageKey = MessageKey::new("game/misc.msg", 100);
+
+    #[test]
+    fn set_language() {
+        let (root, loc) = new("set_language");
+
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hello"));
+        assert_eq!(loc.generation(), 0);
+
+        loc.set_language("german").unwrap();
+        assert_eq!(loc.language(), "german");
+        assert_eq!(loc.generation(), 1);
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hallo"));
+
+        // Same language.
+        loc.set_language("GERMAN").unwrap();
+        assert_eq!(loc.generation(), 1);
+
+        // The language without msg files is rejected.
+        assert!(loc.set_language("klingon").is_err());
+        assert_eq!(loc.language(), "german");
+        assert_eq!(loc.generation(), 1);
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hallo"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        let (root, loc) = new("reload");
+
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hello"));
+        write_msg(&root, "english", "misc.msg", "Hi");
+
+        // Cached until reloaded.
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hello"));
+        assert!(!loc.reload("text/german/game/misc.msg"));
+        assert!(!loc.reload("art/intrface/iface.frm"));
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hello"));
+
+        assert!(loc.reload("TEXT\\ENGLISH\\GAME\\MISC.MSG"));
+        assert_eq!(loc.get(HELLO).unwrap(), BString::from("Hi"));
+
+        // Not loaded.
+        assert!(!loc.reload("text/english/game/inventry.msg"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Interplay MVE movies (`art/cuts/*.mve`).
+//!
+//! Only the stream structure is read: the frame timer and the number of displayed frames. This is
+//! enough to time the movie and its subtitles. The video and audio data is skipped.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Error, ErrorKind, prelude::*};
+use std::time::Duration;
+
+const SIGNATURE: &[u8] = b"Interplay MVE File\x1a\0";
+const MAGIC: [u16; 3] = [0x001a, 0x0100, 0x1133];
+
+const OP_END_OF_STREAM: u8 = 0x00;
+const OP_END_OF_CHUNK: u8 = 0x01;
+const OP_CREATE_TIMER: u8 = 0x02;
+const OP_SEND_BUFFER: u8 = 0x07;
+
+// gmovie_list
+/// Movies played by `play_gmovie` in the order of the movie index.
+pub const GAME_MOVIES: &[&str] = &[
+    "iplogo",
+    "intro",
+    "elder",
+    "vsuit",
+    "afailed",
+    "adestroy",
+    "car",
+    "cartucci",
+    "timeout",
+    "tanker",
+    "enclave",
+    "derrick",
+    "artimer1",
+    "artimer2",
+    "artimer3",
+    "artimer4",
+    "credits",
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MveInfo {
+    /// How long each frame is shown.
+    pub frame_duration: Duration,
+    /// Number of frames sent to the display.
+    pub frame_count: u32,
+}
+
+impl MveInfo {
+    pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let mut signature = [0; 20];
+        rd.read_exact(&mut signature)?;
+        if signature != SIGNATURE {
+            return Err(Error::new(ErrorKind::InvalidData, "not an MVE file"));
+        }
+        for &magic in &MAGIC {
+            if rd.read_u16::<LittleEndian>()? != magic {
+                return Err(Error::new(ErrorKind::InvalidData, "not an MVE file"));
+            }
+        }
+
+        let mut frame_duration = None;
+        let mut frame_count = 0;
+        'chunks: loop {
+            let mut chunk_len = rd.read_u16::<LittleEndian>()? as u64;
+            let _chunk_kind = rd.read_u16::<LittleEndian>()?;
+            while chunk_len >= 4 {
+                let len = rd.read_u16::<LittleEndian>()? as u64;
+                let kind = rd.read_u8()?;
+                let _version = rd.read_u8()?;
+                chunk_len = chunk_len.saturating_sub(4 + len);
+                match kind {
+                    OP_END_OF_STREAM => break 'chunks,
+                    OP_END_OF_CHUNK => {
+                        skip(rd, len)?;
+                        break;
+                    }
+                    OP_CREATE_TIMER => {
+                        if len < 6 {
+                            return Err(Error::new(ErrorKind::InvalidData,
+                                "malformed create timer opcode"));
+                        }
+                        let rate = rd.read_u32::<LittleEndian>()? as u64;
+                        let subdivision = rd.read_u16::<LittleEndian>()? as u64;
+                        frame_duration = Some(Duration::from_micros(rate * subdivision));
+                        skip(rd, len - 6)?;
+                    }
+                    OP_SEND_BUFFER => {
+                        frame_count += 1;
+                        skip(rd, len)?;
+                    }
+                    _ => skip(rd, len)?,
+                }
+            }
+            skip(rd, chunk_len)?;
+        }
+
+        let frame_duration = frame_duration
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "MVE file has no frame timer"))?;
+        Ok(Self {
+            frame_duration,
+            frame_count,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frame_duration * self.frame_count
+    }
+}
+
+fn skip(rd: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut rd.take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated MVE file"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::Cursor;
+
+    fn opcode(kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut r = Vec::new();
+        r.write_u16::<LittleEndian>(data.len() as u16).unwrap();
+        r.push(kind);
+        r.push(0);
+        r.extend_from_slice(data);
+        r
+    }
+
+    fn chunk(opcodes: &[Vec<u8>]) -> Vec<u8> {
+        let data = opcodes.concat();
+        let mut r = Vec::new();
+        r.write_u16::<LittleEndian>(data.len() as u16).unwrap();
+        r.write_u16::<LittleEndian>(3).unwrap();
+        r.extend_from_slice(&data);
+        r
+    }
+
+    fn header() -> Vec<u8> {
+        let mut r = SIGNATURE.to_vec();
+        for &v in &MAGIC {
+            r.write_u16::<LittleEndian>(v).unwrap();
+        }
+        r
+    }
+
+    #[test]
+    fn read() {
+        let mut timer = Vec::new();
+        timer.write_u32::<LittleEndian>(8333).unwrap();
+        timer.write_u16::<LittleEndian>(8).unwrap();
+        let frame = || opcode(OP_SEND_BUFFER, &[1, 2, 3, 4, 5, 6]);
+        let data = [
+            header(),
+            chunk(&[opcode(OP_CREATE_TIMER, &timer), opcode(0x05, &[0; 8]),
+                opcode(OP_END_OF_CHUNK, &[])]),
+            chunk(&[opcode(0x11, &[0; 100]), frame(), opcode(OP_END_OF_CHUNK, &[])]),
+            chunk(&[frame(), opcode(OP_END_OF_CHUNK, &[])]),
+            chunk(&[opcode(OP_END_OF_STREAM, &[])]),
+        ].concat();
+
+        let info = MveInfo::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(info, MveInfo {
+            frame_duration: Duration::from_micros(66664),
+            frame_count: 2,
+        });
+        assert_eq!(info.duration(), Duration::from_micros(133328));
+    }
+
+    #[test]
+    fn read_malformed() {
+        let e = MveInfo::read(&mut Cursor::new(b"Interplay MVE File\x1a\0\0\0".to_vec()));
+        assert_eq!(e.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let data = [header(), chunk(&[opcode(OP_SEND_BUFFER, &[])])].concat();
+        assert_eq!(MveInfo::read(&mut Cursor::new(data)).unwrap_err().kind(),
+            ErrorKind::UnexpectedEof);
+
+        let data = [header(), chunk(&[opcode(OP_END_OF_STREAM, &[])])].concat();
+        assert_eq!(MveInfo::read(&mut Cursor::new(data)).unwrap_err().kind(),
+            ErrorKind::InvalidData);
+    }
+}
@@ -1,4 +1,6 @@
 mod db;
+pub mod dump;
+mod ext;
 mod id;
 
 use bstring::{bstr, BString};
@@ -20,9 +22,18 @@ use crate::util::{enum_iter, EnumIter, RangeInclusive};
 /// "The doorway seems to be blocked."
 pub const MSG_DOORWAY_SEEMS_TO_BE_BLOCKED: MessageId = 597;
 
+/// "That does nothing."
+pub const MSG_THAT_DOES_NOTHING: MessageId = 582;
+
 /// "You see: %s."
 pub const MSG_YOU_SEE_X: MessageId = 480;
 
+/// "%s is on."
+pub const MSG_X_IS_ON: MessageId = 69;
+
+/// "%s is off."
+pub const MSG_X_IS_OFF: MessageId = 70;
+
 pub type ProtoRef = std::rc::Rc<std::cell::RefCell<Proto>>;
 
 #[derive(Debug)]
@@ -48,6 +59,63 @@ impl Proto {
         self.id
     }
 
+    /// Critter prototype with the `base_stats` and everything else empty.
+    #[cfg(test)]
+    pub fn mock_critter(id: ProtoId, base_stats: EnumMap<Stat, i32>) -> Self {
+        assert_eq!(id.kind(), EntityKind::Critter);
+        Self {
+            id,
+            name: None,
+            description: None,
+            fid: FrameId::new(EntityKind::Critter, None, 0, 0, 0).unwrap(),
+            light_radius: 0,
+            light_intensity: 0,
+            flags: BitFlags::empty(),
+            flags_ext: BitFlags::empty(),
+            script: None,
+            sub: SubProto::Critter(Critter {
+                flags: BitFlags::empty(),
+                base_stats,
+                bonus_stats: EnumMap::new(),
+                skills: EnumMap::new(),
+                body_kind: BodyKind::Biped,
+                experience: 0,
+                kill_kind: CritterKillKind::Man,
+                damage_kind: DamageKind::Melee,
+                head_fid: None,
+                ai_packet: 0,
+                team_id: 0,
+                wander_radius: 0,
+            }),
+        }
+    }
+
+    /// Item prototype of the `sub` kind with everything else empty.
+    #[cfg(test)]
+    pub fn mock_item(id: ProtoId, sub: SubItem) -> Self {
+        assert_eq!(id.kind(), EntityKind::Item);
+        Self {
+            id,
+            name: None,
+            description: None,
+            fid: FrameId::new(EntityKind::Item, None, 0, 0, 0).unwrap(),
+            light_radius: 0,
+            light_intensity: 0,
+            flags: BitFlags::empty(),
+            flags_ext: BitFlags::empty(),
+            script: None,
+            sub: SubProto::Item(Item {
+                material: Material::Metal,
+                size: 1,
+                weight: 0,
+                price: 0,
+                inventory_fid: None,
+                sound_id: 0,
+                sub,
+            }),
+        }
+    }
+
     // critter_name
     // item_name
     pub fn name(&self) -> Option<&bstr> {
@@ -315,6 +383,9 @@ pub struct Critter {
     pub head_fid: Option<FrameId>,
     pub ai_packet: i32,
     pub team_id: i32,
+    /// Radius in hexes the critter wanders within when idle. 0 means it stands still.
+    /// Not present in the PRO files, set in the `proto_ext` definitions.
+    pub wander_radius: u32,
 }
 
 #[bitflags]
@@ -1,34 +1,61 @@
-use bstring::bstr;
-use byteorder::{BigEndian, ReadBytesExt};
+use bstring::BString;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use enum_map::{enum_map, EnumMap};
+use log::*;
 use num_traits::FromPrimitive;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::collections::hash_map::{self, HashMap};
 use std::convert::{TryFrom, TryInto};
-use std::io::{self, Error, ErrorKind, prelude::*};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, prelude::*};
+use std::path::Path;
 use std::rc::Rc;
 use std::str;
 
 use super::*;
+use super::ext;
 use crate::asset::frame::*;
 use crate::asset::message::{MessageId, Messages};
+use crate::asset::message::encoding::Encoding;
+use crate::error::{self, ResultExt};
 use crate::game::script::ScriptPid;
-use crate::fs::FileSystem;
+use crate::fs::{watch, FileSystem};
+use crate::fs::manifest::Manifest;
 use crate::util::RangeInclusive;
 
+const INDEX_FILE_NAME: &str = "proto_index.bin";
+const INDEX_MAGIC: &[u8; 8] = b"V13PIDX1";
+
 pub struct ProtoDb {
     fs: Rc<FileSystem>,
+    language: RefCell<String>,
     lst: Lst,
-    messages: Messages,
-    entity_messages: EnumMap<EntityKind, Messages>,
+    /// `game/proto.msg`.
+    messages: RefCell<Rc<Messages>>,
+    /// Messages with prototype names and descriptions, loaded on first use.
+    entity_messages: RefCell<EnumMap<EntityKind, Option<Messages>>>,
     protos: RefCell<HashMap<ProtoId, ProtoRef>>,
+    /// IDs of the prototypes defined in `proto_ext` files, sorted.
+    ext_ids: Vec<ProtoId>,
 }
 
 impl ProtoDb {
-    pub fn new(fs: Rc<FileSystem>, language: &str) -> io::Result<Self> {
-        let lst = Lst::read(&fs)?;
+    pub fn new(fs: Rc<FileSystem>, language: &str) -> error::Result<Self> {
+        Self::with_cache_dir(fs, language, None)
+    }
+
+    /// Same as `new()` but reuses the LST index stored in `cache_dir` if it's up to date with
+    /// the resource providers, and stores it there otherwise.
+    pub fn with_cache_dir(fs: Rc<FileSystem>, language: &str, cache_dir: Option<&Path>)
+        -> error::Result<Self>
+    {
+        let lst = if let Some(cache_dir) = cache_dir {
+            Lst::read_cached(&fs, &cache_dir.join(INDEX_FILE_NAME))
+        } else {
+            Lst::read(&fs)
+        }.context(|| "reading prototype lists")?;
         let messages = Messages::read_file(&fs, language, "game/proto.msg")?;
-        let entity_messages = Self::read_entity_messages(&fs, language)?;
 
         let mut protos = HashMap::new();
         protos.insert(ProtoId::DUDE, Rc::new(RefCell::new(Proto {
@@ -52,25 +79,51 @@ impl ProtoDb {
                 damage_kind: DamageKind::Melee,
                 head_fid: None,
                 ai_packet: 0,
-                team_id: 0
+                team_id: 0,
+                wander_radius: 0,
             }),
         })));
 
-        Ok(Self {
+        let mut r = Self {
             fs,
+            language: RefCell::new(language.into()),
             lst,
-            messages,
-            entity_messages,
+            messages: RefCell::new(Rc::new(messages)),
+            entity_messages: RefCell::new(EnumMap::new()),
             protos: RefCell::new(protos),
-        })
+            ext_ids: Vec::new(),
+        };
+        r.load_ext();
+        Ok(r)
+    }
+
+    /// Database without any LST entries and messages.
+    #[cfg(test)]
+    pub fn mock(fs: Rc<FileSystem>) -> Self {
+        Self {
+            fs,
+            language: RefCell::new("english".into()),
+            lst: Lst { lst: EnumMap::new() },
+            messages: Default::default(),
+            entity_messages: RefCell::new(EnumMap::from(|_| Some(Messages::default()))),
+            protos: RefCell::new(HashMap::new()),
+            ext_ids: Vec::new(),
+        }
     }
 
     pub fn len(&self, kind: EntityKind) -> usize {
         self.lst.len(kind)
     }
 
-    pub fn messages(&self) -> &Messages {
-        &self.messages
+    /// Returns IDs of all prototypes of the `kind` listed in the LST file followed by the ones
+    /// defined in `proto_ext` files.
+    pub fn ids(&self, kind: EntityKind) -> impl Iterator<Item=ProtoId> + '_ {
+        self.lst.ids(kind).map(move |id| ProtoId::new(kind, id).unwrap())
+            .chain(self.ext_ids.iter().filter(move |pid| pid.kind() == kind).cloned())
+    }
+
+    pub fn messages(&self) -> Rc<Messages> {
+        self.messages.borrow().clone()
     }
 
     pub fn proto(&self, pid: ProtoId) -> io::Result<ProtoRef> {
@@ -78,10 +131,9 @@ impl ProtoDb {
         match protos.entry(pid) {
             hash_map::Entry::Occupied(e) => Ok(e.get().clone()),
             hash_map::Entry::Vacant(e) => {
-                let file_name = self.lst.get(pid)
+                let path = self.proto_path(pid)
                     .ok_or_else(|| Error::new(ErrorKind::InvalidData,
                         format!("can't find proto file name for {:?}", pid)))?;
-                let path = format!("proto/{}/{}", pid.kind().dir(), file_name);
 
                 let proto = Rc::new(RefCell::new(self.read_proto_file(&path)?));
                 e.insert(proto.clone());
@@ -94,20 +146,178 @@ impl ProtoDb {
         self.protos.borrow().get(&ProtoId::DUDE).unwrap().clone()
     }
 
-    fn read_entity_messages(fs: &FileSystem, language: &str)
-        -> io::Result<EnumMap<EntityKind, Messages>>
-    {
-        let mut map = EnumMap::new();
-        for k in proto_entity_kinds() {
-            let path = format!("game/pro_{}.msg", &k.dir()[..4]);
-            map[k] = Messages::read_file(fs, language, &path)?;
+    /// Re-reads the cached prototypes affected by change of the file at `path`.
+    /// Handles the PRO files, the `pro_*.msg` files with names and descriptions and
+    /// `proto.msg`. Returns `false` if the file is not a prototype asset. On error nothing is
+    /// changed.
+    pub fn reload(&self, path: &str) -> io::Result<bool> {
+        let path = watch::normalize(path);
+        let msg_dir = format!("text/{}/game/", self.language.borrow().to_lowercase());
+        if path == format!("{}proto.msg", msg_dir) {
+            let messages = Messages::read_file(&self.fs, &self.language.borrow(),
+                "game/proto.msg")?;
+            *self.messages.borrow_mut() = Rc::new(messages);
+            return Ok(true);
         }
-        Ok(map)
+        let pids: Vec<ProtoId> = if path.starts_with("proto/") && !path.ends_with(".lst") {
+            self.protos.borrow().keys()
+                .filter(|&&pid| pid != ProtoId::DUDE && self.proto_path(pid)
+                    .map(|p| watch::normalize(&p) == path)
+                    .unwrap_or(false))
+                .cloned()
+                .collect()
+        } else if path.starts_with(&msg_dir) {
+            let kind = proto_entity_kinds()
+                .find(|k| path[msg_dir.len()..] == format!("pro_{}.msg", &k.dir()[..4]));
+            if let Some(kind) = kind {
+                // Protos are read with the new messages, the old ones are restored on error.
+                let old_msgs = self.entity_messages.borrow_mut()[kind].take();
+                let pids: Vec<_> = self.protos.borrow().keys()
+                    .filter(|&&pid| pid.kind() == kind && self.proto_path(pid).is_some())
+                    .cloned()
+                    .collect();
+                match self.read_protos(&pids) {
+                    Ok(protos) => {
+                        self.replace_protos(protos);
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        self.entity_messages.borrow_mut()[kind] = old_msgs;
+                        return Err(e);
+                    }
+                }
+            } else {
+                return Ok(false);
+            }
+        } else {
+            return Ok(false);
+        };
+        let protos = self.read_protos(&pids)?;
+        self.replace_protos(protos);
+        Ok(true)
     }
 
-    fn read_proto_file(&self, path: &str) -> io::Result<Proto> {
+    /// Switches `proto.msg` and the prototype names and descriptions to the `language`.
+    /// The cached prototypes are re-read with the new messages, except the ones defined in
+    /// `proto_ext` files. On error nothing is changed.
+    pub fn set_language(&self, language: &str) -> io::Result<()> {
+        let messages = Messages::read_file(&self.fs, language, "game/proto.msg")?;
+        let old_language = self.language.replace(language.into());
+        let old_entity_msgs = self.entity_messages.replace(EnumMap::new());
+        let pids: Vec<_> = self.protos.borrow().keys()
+            .filter(|&&pid| self.proto_path(pid).is_some())
+            .cloned()
+            .collect();
+        match self.read_protos(&pids) {
+            Ok(protos) => {
+                self.replace_protos(protos);
+                *self.messages.borrow_mut() = Rc::new(messages);
+                Ok(())
+            }
+            Err(e) => {
+                *self.language.borrow_mut() = old_language;
+                *self.entity_messages.borrow_mut() = old_entity_msgs;
+                Err(e)
+            }
+        }
+    }
+
+    fn read_protos(&self, pids: &[ProtoId]) -> io::Result<Vec<(ProtoId, Proto)>> {
+        pids.iter()
+            .map(|&pid| -> io::Result<_> {
+                Ok((pid, self.read_proto_file(&self.proto_path(pid).unwrap())?))
+            })
+            .collect()
+    }
+
+    fn replace_protos(&self, protos: Vec<(ProtoId, Proto)>) {
+        for (pid, new) in protos {
+            let proto = self.protos.borrow()[&pid].clone();
+            *proto.borrow_mut() = new;
+        }
+    }
+
+    /// Loads prototype definitions from the `proto_ext` files. Errors are logged and the
+    /// offending files skipped.
+    fn load_ext(&mut self) {
+        let files = ext::find_files(self.fs.loose_dirs()).unwrap_or_else(|e| {
+            warn!("couldn't list prototype definition files: {}", e);
+            Vec::new()
+        });
+        for path in files {
+            let result = std::fs::read_to_string(&path)
+                .and_then(|s| ext::parse(&s))
+                .and_then(|defs| self.add_ext(&defs));
+            match result {
+                Ok(count) => info!("loaded {} prototypes from {}", count, path.display()),
+                Err(e) => warn!("couldn't load prototypes from {}: {}", path.display(), e),
+            }
+        }
+        self.ext_ids.sort();
+    }
+
+    fn add_ext(&mut self, defs: &[ext::ProtoDef]) -> io::Result<usize> {
+        let encoding = Encoding::for_language(&self.language.borrow());
+        let mut new = Vec::with_capacity(defs.len());
+        for def in defs {
+            let kind = def.base.kind();
+            let id = if let Some(id) = def.id {
+                if self.lst.get(ProtoId::new(kind, id).unwrap()).is_some() {
+                    return Err(Error::new(ErrorKind::InvalidData,
+                        format!("id {} of {:?} prototype is already used in the LST file",
+                            id, kind)));
+                }
+                id
+            } else {
+                self.ext_ids.iter().chain(new.iter().map(|(pid, _)| pid))
+                    .filter(|pid| pid.kind() == kind)
+                    .map(|pid| pid.id())
+                    .max()
+                    .unwrap_or(0)
+                    .max(self.lst.max_id(kind)) + 1
+            };
+            let pid = ProtoId::new(kind, id)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData,
+                    format!("no free ids left for {:?} prototypes", kind)))?;
+            if self.ext_ids.contains(&pid) || new.iter().any(|&(p, _)| p == pid) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("duplicate prototype {:?}", pid)));
+            }
+            let path = self.proto_path(def.base)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData,
+                    format!("base prototype {:?} is not listed in the LST file", def.base)))?;
+            let mut proto = self.read_proto_file(&path)?;
+            proto.id = pid;
+            def.apply(&mut proto, encoding)
+                .map_err(|e| Error::new(ErrorKind::InvalidData,
+                    format!("error in definition of {:?}: {}", pid, e)))?;
+            new.push((pid, proto));
+        }
+        let count = new.len();
+        let mut protos = self.protos.borrow_mut();
+        for (pid, proto) in new {
+            self.ext_ids.push(pid);
+            protos.insert(pid, Rc::new(RefCell::new(proto)));
+        }
+        Ok(count)
+    }
+
+    fn proto_path(&self, pid: ProtoId) -> Option<String> {
+        self.lst.get(pid)
+            .map(|file_name| format!("proto/{}/{}", pid.kind().dir(), file_name))
+    }
+
+    fn read_entity_messages(&self, kind: EntityKind) -> io::Result<Messages> {
+        let path = format!("game/pro_{}.msg", &kind.dir()[..4]);
+        Ok(Messages::read_file(&self.fs, &self.language.borrow(), &path)?)
+    }
+
+    fn read_proto_file(&self, path: &str) -> error::Result<Proto> {
         let rd = &mut self.fs.reader(path)?;
+        self.read_proto(rd).context(|| format!("reading {}", path))
+    }
 
+    fn read_proto(&self, rd: &mut impl Read) -> io::Result<Proto> {
         let pid = ProtoId::read(rd)?;
         let message_id = rd.read_i32::<BigEndian>()?;
         let fid = FrameId::read(rd)?;
@@ -146,7 +356,7 @@ impl ProtoDb {
 
         let sub = match kind {
             EntityKind::Item => SubProto::Item(Self::read_item(rd, &mut flags_ext)?),
-            EntityKind::Critter => SubProto::Critter(Self::read_critter(rd)?),
+            EntityKind::Critter => SubProto::Critter(Self::read_critter(rd, self.fs.game())?),
             EntityKind::Scenery => SubProto::Scenery(Self::read_scenery(rd)?),
             EntityKind::Wall => SubProto::Wall(Self::read_wall(rd)?),
             EntityKind::SqrTile => SubProto::SqrTile(Self::read_sqr_tile(&mut flags_ext)?),
@@ -160,11 +370,9 @@ impl ProtoDb {
         };
 
         // proto_name()
-        let name = self.msg(pid.kind(), message_id, 0)?
-            .map(|s| s.to_owned());
+        let name = self.msg(pid.kind(), message_id, 0)?;
         // proto_description()
-        let description = self.msg(pid.kind(), message_id, 1)?
-            .map(|s| s.to_owned());
+        let description = self.msg(pid.kind(), message_id, 1)?;
 
         Ok(Proto {
             id: pid,
@@ -184,7 +392,7 @@ impl ProtoDb {
         let item_kind = read_enum(rd, "invalid item kind")?;
         let material = read_enum(rd, "invalid item material")?;
         let size = rd.read_i32::<BigEndian>()?;
-        let weight = rd.read_i32::<BigEndian>()?.try_into().unwrap();
+        let weight = read_count(rd, "invalid item weight")?;
         let price = rd.read_i32::<BigEndian>()?;
         let inventory_fid = FrameId::read_opt(rd)?;
         let sound_id = rd.read_u8()?;
@@ -347,7 +555,7 @@ impl ProtoDb {
         let burst_bullet_count = rd.read_i32::<BigEndian>()?;
         let caliber = rd.read_u32::<BigEndian>()?;
         let ammo_proto_id = ProtoId::read_opt(rd)?;
-        let max_ammo_count = rd.read_i32::<BigEndian>()?.try_into().unwrap();
+        let max_ammo_count = read_count(rd, "invalid weapon max ammo count")?;
         let sound_id = rd.read_u8()?;
 
         Ok(Weapon {
@@ -371,7 +579,7 @@ impl ProtoDb {
 
     fn read_ammo(rd: &mut impl Read) -> io::Result<Ammo> {
         let caliber = rd.read_u32::<BigEndian>()?;
-        let max_ammo_count = rd.read_i32::<BigEndian>()?.try_into().unwrap();
+        let max_ammo_count = read_count(rd, "invalid ammo max count")?;
         let ac_modifier = rd.read_i32::<BigEndian>()?;
         let dr_modifier = rd.read_i32::<BigEndian>()?;
         let damage_mult = rd.read_i32::<BigEndian>()?;
@@ -404,7 +612,7 @@ impl ProtoDb {
         })
     }
 
-    fn read_critter(rd: &mut impl Read) -> io::Result<Critter> {
+    fn read_critter(rd: &mut impl Read, game: Game) -> io::Result<Critter> {
         let head_fid = FrameId::read_opt(rd)?;
         let ai_packet = rd.read_i32::<BigEndian>()?;
         let team_id = rd.read_i32::<BigEndian>()?;
@@ -412,7 +620,7 @@ impl ProtoDb {
         let v = rd.read_u32::<BigEndian>()?;
         let flags = BitFlags::from_bits(v)
             .ok().ok_or_else(|| Error::new(ErrorKind::InvalidData,
-                format!("invalid critter proto flags: {:x}", v))).unwrap();
+                format!("invalid critter proto flags: {:x}", v)))?;
 
         let mut base_stats = EnumMap::new();
         for stat in 0..35 {
@@ -429,7 +637,11 @@ impl ProtoDb {
         let body_kind = read_enum(rd, "invalid body kind in critter proto")?;
         let experience = rd.read_i32::<BigEndian>()?;
         let kill_kind = read_enum(rd, "invalid kill kind in critter proto")?;
-        let damage_kind = read_enum(rd, "invalid damage kind in critter proto")?;
+        // Fallout 1 critters don't have the unarmed damage kind.
+        let damage_kind = match game {
+            Game::Fallout1 => DamageKind::Melee,
+            Game::Fallout2 => read_enum(rd, "invalid damage kind in critter proto")?,
+        };
 
         Ok(Critter {
             flags,
@@ -443,6 +655,7 @@ impl ProtoDb {
             head_fid,
             ai_packet,
             team_id,
+            wander_radius: 0,
         })
     }
 
@@ -532,15 +745,20 @@ impl ProtoDb {
     }
 
     fn msg(&self, kind: EntityKind, msg_id: MessageId, base: MessageId)
-        -> io::Result<Option<&bstr>>
+        -> io::Result<Option<BString>>
     {
-        Ok(self.entity_messages[kind].get(base + msg_id)
-            .map(|m| m.text.as_ref()))
+        let mut msgs = self.entity_messages.borrow_mut();
+        if msgs[kind].is_none() {
+            msgs[kind] = Some(self.read_entity_messages(kind)?);
+        }
+        Ok(msgs[kind].as_ref().unwrap().get(base + msg_id)
+            .map(|m| m.text.clone()))
     }
 }
 
 struct Lst {
-    lst: EnumMap<EntityKind, Vec<LstEntry>>,
+    /// LST entries keyed by the PID id.
+    lst: EnumMap<EntityKind, BTreeMap<u32, LstEntry>>,
 }
 
 impl Lst {
@@ -558,18 +776,105 @@ impl Lst {
         self.lst[kind].len()
     }
 
+    pub fn ids(&self, kind: EntityKind) -> impl Iterator<Item=u32> + '_ {
+        self.lst[kind].keys().cloned()
+    }
+
+    /// Returns the largest id of the `kind` or 0 if there are no entries.
+    pub fn max_id(&self, kind: EntityKind) -> u32 {
+        self.lst[kind].keys().next_back().cloned().unwrap_or(0)
+    }
+
     pub fn get(&self, pid: ProtoId) -> Option<&str> {
-        let id = pid.id() as usize;
-        if id > 0 {
-            self.lst[pid.kind()].get(id - 1).map(|e| e.fields[0].as_ref())
-        } else {
-            None
+        self.lst[pid.kind()].get(&pid.id()).map(|e| e.fields[0].as_ref())
+    }
+
+    /// Reads the index from `path` if it was built with the same resource providers as the
+    /// current ones. Otherwise reads the LST files and writes the index to `path`. Failures to
+    /// read or write the index are only logged.
+    pub fn read_cached(fs: &FileSystem, path: &Path) -> io::Result<Self> {
+        // Only the prototype lists and files invalidate the index.
+        let manifest = fs.manifest_of(&|p| p.starts_with("proto/"));
+        let cached = File::open(path)
+            .and_then(|f| Self::read_index(&mut BufReader::new(f), &manifest));
+        match cached {
+            Ok(Some(r)) => {
+                debug!("using proto index {}", path.display());
+                return Ok(r);
+            }
+            Ok(None) => info!("proto index {} is out of date", path.display()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => warn!("couldn't read proto index {}: {}", path.display(), e),
+        }
+
+        let r = Self::read(fs)?;
+        let write = || -> io::Result<()> {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let mut w = BufWriter::new(File::create(path)?);
+            r.write_index(&mut w, &manifest)?;
+            w.flush()
+        };
+        if let Err(e) = write() {
+            warn!("couldn't write proto index {}: {}", path.display(), e);
+        }
+        Ok(r)
+    }
+
+    /// Returns `None` if the index was built with resource providers other than `manifest`.
+    fn read_index(rd: &mut impl Read, manifest: &Manifest) -> io::Result<Option<Self>> {
+        let mut magic = [0; 8];
+        rd.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a proto index file"));
+        }
+        if Manifest::read(rd)? != *manifest {
+            return Ok(None);
+        }
+        let mut lst = EnumMap::new();
+        for k in proto_entity_kinds() {
+            let count = rd.read_u32::<BigEndian>()?;
+            let mut entries = BTreeMap::new();
+            for _ in 0..count {
+                let id = rd.read_u32::<BigEndian>()?;
+                let len = rd.read_u16::<BigEndian>()?;
+                let mut name = vec![0; len as usize];
+                rd.read_exact(&mut name)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData,
+                        "malformed proto index entry name"))?;
+                entries.insert(id, LstEntry { fields: vec![name] });
+            }
+            lst[k] = entries;
+        }
+        Ok(Some(Self {
+            lst,
+        }))
+    }
+
+    /// Writes ids and file names of the entries along with the `manifest` they were read with.
+    fn write_index(&self, w: &mut impl Write, manifest: &Manifest) -> io::Result<()> {
+        w.write_all(INDEX_MAGIC)?;
+        manifest.write(w)?;
+        for k in proto_entity_kinds() {
+            w.write_u32::<BigEndian>(self.lst[k].len() as u32)?;
+            for (&id, e) in &self.lst[k] {
+                let name = e.fields[0].as_bytes();
+                if name.len() > u16::MAX as usize {
+                    return Err(Error::new(ErrorKind::InvalidInput, "LST entry name too long"));
+                }
+                w.write_u32::<BigEndian>(id)?;
+                w.write_u16::<BigEndian>(name.len() as u16)?;
+                w.write_all(name)?;
+            }
         }
+        Ok(())
     }
 
-    fn read_lst_file(fs: &FileSystem, kind: EntityKind) -> io::Result<Vec<LstEntry>> {
+    fn read_lst_file(fs: &FileSystem, kind: EntityKind) -> io::Result<BTreeMap<u32, LstEntry>> {
         let path = format!("proto/{0}/{0}.lst", kind.dir());
-        read_lst(&mut fs.reader(&path)?)
+        Ok(read_lst_ext(fs, &path, 1, ProtoId::MAX_ID)?)
     }
 }
 
@@ -593,3 +898,83 @@ fn read_enum<T: FromPrimitive>(rd: &mut impl Read, err: &str) -> io::Result<T> {
 fn read_opt_enum<T: FromPrimitive>(rd: &mut impl Read, err: &str) -> io::Result<Option<T>> {
     get_opt_enum(rd.read_i32::<BigEndian>()?, err)
 }
+
+fn read_count<T: TryFrom<i32>>(rd: &mut impl Read, err: &str) -> io::Result<T> {
+    let v = rd.read_i32::<BigEndian>()?;
+    T::try_from(v)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("{}: {}", err, v)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::manifest::ManifestEntry;
+
+    #[test]
+    fn index() {
+        let mut lst = Lst { lst: EnumMap::new() };
+        lst.lst[EntityKind::Item].insert(1, LstEntry { fields: vec!["00000001.pro".into()] });
+        lst.lst[EntityKind::Item].insert(500, LstEntry { fields: vec!["00000500.pro".into()] });
+        lst.lst[EntityKind::Wall].insert(3, LstEntry { fields: vec!["00000003.pro".into()] });
+        let manifest = Manifest {
+            entries: vec![ManifestEntry { name: "master.dat".into(), hash: 42 }],
+        };
+        let mut data = Vec::new();
+        lst.write_index(&mut data, &manifest).unwrap();
+
+        let act = Lst::read_index(&mut &data[..], &manifest).unwrap().unwrap();
+        assert_eq!(act.ids(EntityKind::Item).collect::<Vec<_>>(), vec![1, 500]);
+        assert_eq!(act.get(ProtoId::new(EntityKind::Item, 500).unwrap()), Some("00000500.pro"));
+        assert_eq!(act.get(ProtoId::new(EntityKind::Wall, 3).unwrap()), Some("00000003.pro"));
+        assert_eq!(act.len(EntityKind::Critter), 0);
+
+        assert!(Lst::read_index(&mut &data[..], &Manifest::default()).unwrap().is_none());
+        assert!(Lst::read_index(&mut &b"garbage!"[..], &manifest).is_err());
+    }
+
+    fn scenery_data(kind: SceneryKind, material: Material, sound_id: u8, rest: &[i32]) -> Vec<u8> {
+        let mut r = Vec::new();
+        r.extend_from_slice(&(kind as u32).to_be_bytes());
+        r.extend_from_slice(&(material as u32).to_be_bytes());
+        r.push(sound_id);
+        for v in rest {
+            r.extend_from_slice(&v.to_be_bytes());
+        }
+        r
+    }
+
+    #[test]
+    fn read_scenery() {
+        // Wooden door.
+        let s = ProtoDb::read_scenery(&mut &scenery_data(SceneryKind::Door, Material::Wood, b'A',
+            &[0, -1])[..]).unwrap();
+        assert_eq!(s.material, Material::Wood);
+        assert_eq!(s.sound_id, b'A');
+        let d = s.sub.as_door().unwrap();
+        assert!(d.flags.is_empty());
+        assert_eq!(d.key_id, -1);
+
+        // Locked metal door with a key.
+        let s = ProtoDb::read_scenery(&mut &scenery_data(SceneryKind::Door, Material::Metal, b'M',
+            &[DoorFlag::Locked as i32, 17])[..]).unwrap();
+        assert_eq!(s.sound_id, b'M');
+        let d = s.sub.as_door().unwrap();
+        assert!(d.flags.contains(DoorFlag::Locked));
+        assert_eq!(d.key_id, 17);
+
+        // Door without a sound id.
+        let s = ProtoDb::read_scenery(&mut &scenery_data(SceneryKind::Door, Material::Stone, 0,
+            &[0, -1])[..]).unwrap();
+        assert_eq!(s.sound_id, 0);
+        assert!(s.sub.as_door().is_some());
+
+        // Misc scenery keeps the sound id too.
+        let s = ProtoDb::read_scenery(&mut &scenery_data(SceneryKind::Misc, Material::Wood, b'B',
+            &[0])[..]).unwrap();
+        assert_eq!(s.sound_id, b'B');
+        assert_eq!(s.sub.kind(), SceneryKind::Misc);
+
+        assert!(ProtoDb::read_scenery(&mut &scenery_data(SceneryKind::Door, Material::Wood, b'A',
+            &[0x10, -1])[..]).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! Dumping of prototype fields as CSV or JSON. Used by the `dump protos` subcommand.
+
+use enum_map::{Enum, EnumMap};
+use enumflags2::{BitFlag, BitFlags};
+use log::*;
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+    Null,
+}
+
+/// Field names and values of a single prototype in the order they're dumped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Record(pub Vec<(String, Value)>);
+
+impl Record {
+    fn int(&mut self, name: impl Into<String>, v: impl Into<i64>) {
+        self.0.push((name.into(), Value::Int(v.into())));
+    }
+
+    fn str(&mut self, name: impl Into<String>, v: impl Into<String>) {
+        self.0.push((name.into(), Value::Str(v.into())));
+    }
+
+    fn debug(&mut self, name: impl Into<String>, v: impl Debug) {
+        self.str(name, format!("{:?}", v));
+    }
+
+    fn opt(&mut self, name: impl Into<String>, v: Option<Value>) {
+        self.0.push((name.into(), v.unwrap_or(Value::Null)));
+    }
+
+    fn opt_debug(&mut self, name: impl Into<String>, v: Option<impl Debug>) {
+        self.opt(name, v.map(|v| Value::Str(format!("{:?}", v))));
+    }
+
+    fn flags<T: BitFlag + Debug>(&mut self, name: impl Into<String>, v: BitFlags<T>) {
+        let s = v.iter().map(|f| format!("{:?}", f)).collect::<Vec<_>>().join("|");
+        self.str(name, s);
+    }
+
+    fn enum_map<K: Enum<i32> + Debug>(&mut self, name: &str, v: &EnumMap<K, i32>) {
+        for (k, &v) in v.iter() {
+            self.int(format!("{}.{:?}", name, k), v);
+        }
+    }
+
+    fn exit(&mut self, exit: Option<&MapExit>) {
+        let map = exit.map(|e| Value::Str(match e.map {
+            TargetMap::Map { map_id } => map_id.to_string(),
+            TargetMap::CurrentMap => "current".into(),
+            TargetMap::WorldMap(WorldMapKind::Town) => "town_map".into(),
+            TargetMap::WorldMap(WorldMapKind::World) => "world_map".into(),
+        }));
+        self.opt("exit.map", map);
+        self.opt("exit.x", exit.map(|e| Value::Int(e.pos.point.x.into())));
+        self.opt("exit.y", exit.map(|e| Value::Int(e.pos.point.y.into())));
+        self.opt("exit.elevation", exit.map(|e| Value::Int(e.pos.elevation.into())));
+        self.opt_debug("exit.direction", exit.map(|e| e.direction));
+    }
+}
+
+fn pid_value(pid: Option<ProtoId>) -> Option<Value> {
+    pid.map(|pid| Value::Str(format!("0x{:08x}", pid.pack())))
+}
+
+fn fid_value(fid: Option<FrameId>) -> Option<Value> {
+    fid.map(|fid| Value::Str(format!("0x{:08x}", fid.packed())))
+}
+
+/// Returns all fields of the `proto`.
+pub fn fields(proto: &Proto) -> Record {
+    let mut r = Record::default();
+    r.opt("pid", pid_value(Some(proto.id())));
+    r.debug("kind", proto.id().kind());
+    r.opt("subkind", match proto.kind() {
+        ExactEntityKind::Item(k) => Some(Value::Str(format!("{:?}", k))),
+        ExactEntityKind::Scenery(k) => Some(Value::Str(format!("{:?}", k))),
+        _ => None,
+    });
+    r.int("id", proto.id().id());
+    r.opt("name", proto.name().map(|s| Value::Str(s.display().to_string())));
+    r.opt("description", proto.description().map(|s| Value::Str(s.display().to_string())));
+    r.opt("fid", fid_value(Some(proto.fid)));
+    r.int("light_radius", proto.light_radius);
+    r.int("light_intensity", proto.light_intensity);
+    r.flags("flags", proto.flags);
+    r.flags("flags_ext", proto.flags_ext);
+    r.opt("script", proto.script.map(|s| Value::Int(s.program_id().val().into())));
+
+    match &proto.sub {
+        SubProto::Item(item) => {
+            r.debug("material", item.material);
+            r.int("size", item.size);
+            r.int("weight", item.weight);
+            r.int("price", item.price);
+            r.opt("inventory_fid", fid_value(item.inventory_fid));
+            r.int("sound_id", item.sound_id);
+            item_fields(&mut r, &item.sub);
+        }
+        SubProto::Critter(c) => {
+            r.flags("critter_flags", c.flags);
+            r.enum_map("base_stats", &c.base_stats);
+            r.enum_map("bonus_stats", &c.bonus_stats);
+            r.enum_map("skills", &c.skills);
+            r.debug("body_kind", c.body_kind);
+            r.int("experience", c.experience);
+            r.debug("kill_kind", c.kill_kind);
+            r.debug("damage_kind", c.damage_kind);
+            r.opt("head_fid", fid_value(c.head_fid));
+            r.int("ai_packet", c.ai_packet);
+            r.int("team_id", c.team_id);
+        }
+        SubProto::Scenery(s) => {
+            r.debug("material", s.material);
+            r.int("sound_id", s.sound_id);
+            match &s.sub {
+                SubScenery::Door(d) => {
+                    r.flags("door_flags", d.flags);
+                    r.int("key_id", d.key_id);
+                }
+                SubScenery::Stairs(s) => r.exit(s.exit.as_ref()),
+                SubScenery::Elevator(e) => {
+                    r.int("elevator_kind", e.kind);
+                    r.int("elevator_level", e.level);
+                }
+                SubScenery::Ladder(l) => r.exit(l.exit.as_ref()),
+                SubScenery::Misc => {}
+            }
+        }
+        SubProto::Wall(w) => r.debug("material", w.material),
+        SubProto::SqrTile(t) => r.debug("material", t.material),
+        SubProto::Misc => {}
+    }
+
+    r
+}
+
+fn item_fields(r: &mut Record, item: &SubItem) {
+    match item {
+        SubItem::Armor(a) => {
+            r.int("armor_class", a.armor_class);
+            r.enum_map("damage_resistance", &a.damage_resistance);
+            r.enum_map("damage_threshold", &a.damage_threshold);
+            r.opt_debug("perk", a.perk);
+            r.int("male_fidx", a.male_fidx);
+            r.int("female_fidx", a.female_fidx);
+        }
+        SubItem::Container(c) => {
+            r.int("capacity", c.capacity);
+            r.flags("container_flags", c.flags);
+        }
+        SubItem::Drug(d) => {
+            for (i, e) in d.effects.iter().enumerate() {
+                r.int(format!("effects.{}.delay", i), e.delay);
+                r.debug(format!("effects.{}.stat", i), e.stat);
+                match e.modifier {
+                    DrugEffectModifier::Fixed(v) => r.int(format!("effects.{}.modifier", i), v),
+                    DrugEffectModifier::Random(min, max) =>
+                        r.str(format!("effects.{}.modifier", i), format!("{}..={}", min, max)),
+                }
+            }
+            r.int("addiction.chance", d.addiction.chance);
+            r.opt_debug("addiction.perk", d.addiction.perk);
+            r.int("addiction.delay", d.addiction.delay);
+        }
+        SubItem::Weapon(w) => {
+            for (k, v) in w.attack_kinds.iter() {
+                r.debug(format!("attack_kinds.{:?}", k), v);
+            }
+            r.debug("weapon_kind", w.kind);
+            r.int("damage_min", w.damage.start);
+            r.int("damage_max", w.damage.end);
+            r.debug("damage_kind", w.damage_kind);
+            r.enum_map("max_ranges", &w.max_ranges);
+            r.opt("projectile_pid", pid_value(w.projectile_pid));
+            r.int("min_strength", w.min_strength);
+            r.enum_map("ap_costs", &w.ap_costs);
+            r.int("crit_failure_table", w.crit_failure_table);
+            r.opt_debug("perk", w.perk);
+            r.int("burst_bullet_count", w.burst_bullet_count);
+            r.int("caliber", w.caliber);
+            r.opt("ammo_pid", pid_value(w.ammo_proto_id));
+            r.int("max_ammo_count", w.max_ammo_count);
+            r.int("weapon_sound_id", w.sound_id);
+        }
+        SubItem::Ammo(a) => {
+            r.int("caliber", a.caliber);
+            r.int("max_ammo_count", a.max_ammo_count);
+            r.int("ac_modifier", a.ac_modifier);
+            r.int("dr_modifier", a.dr_modifier);
+            r.int("damage_mult", a.damage_mult);
+            r.int("damage_div", a.damage_div);
+        }
+        SubItem::Misc(m) => {
+            r.opt("ammo_pid", pid_value(m.ammo_proto_id));
+            r.int("ammo_kind", m.ammo_kind);
+            r.int("max_ammo_count", m.max_ammo_count);
+        }
+        SubItem::Key(k) => r.int("key_id", k.id),
+    }
+}
+
+/// Reads all prototypes of the `kinds` from the `db` and writes their fields to `out`.
+/// Prototypes that fail to load are logged and skipped.
+pub fn dump(db: &ProtoDb, kinds: &[EntityKind], format: Format, out: &mut impl Write)
+    -> io::Result<()>
+{
+    let mut records = Vec::new();
+    for &kind in kinds {
+        for pid in db.ids(kind) {
+            match db.proto(pid) {
+                Ok(proto) => records.push(fields(&proto.borrow())),
+                Err(e) => warn!("couldn't load proto {:?}: {}", pid, e),
+            }
+        }
+    }
+    match format {
+        Format::Csv => write_csv(&records, out),
+        Format::Json => write_json(&records, out),
+    }
+}
+
+/// Writes `records` as CSV with the header listing all field names in the order of appearance.
+/// Fields missing in a record are left empty.
+pub fn write_csv(records: &[Record], out: &mut impl Write) -> io::Result<()> {
+    let mut columns: Vec<&str> = Vec::new();
+    for r in records {
+        for (name, _) in &r.0 {
+            if !columns.contains(&&name[..]) {
+                columns.push(name);
+            }
+        }
+    }
+    writeln!(out, "{}", columns.iter().map(|c| csv_str(c)).collect::<Vec<_>>().join(","))?;
+    for r in records {
+        let row: Vec<_> = columns.iter()
+            .map(|&c| match r.0.iter().find(|(name, _)| name == c).map(|(_, v)| v) {
+                Some(Value::Int(v)) => v.to_string(),
+                Some(Value::Str(v)) => csv_str(v),
+                Some(Value::Null) | None => String::new(),
+            })
+            .collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes `records` as JSON array of objects, one object per line.
+pub fn write_json(records: &[Record], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, r) in records.iter().enumerate() {
+        let fields: Vec<_> = r.0.iter()
+            .map(|(name, v)| format!("{}:{}", json_str(name), match v {
+                Value::Int(v) => v.to_string(),
+                Value::Str(v) => json_str(v),
+                Value::Null => "null".into(),
+            }))
+            .collect();
+        let sep = if i + 1 < records.len() { "," } else { "" };
+        writeln!(out, "{{{}}}{}", fields.join(","), sep)?;
+    }
+    writeln!(out, "]")
+}
+
+fn csv_str(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.into()
+    }
+}
+
+pub fn json_str(s: &str) -> String {
+    let mut r = String::with_capacity(s.len() + 2);
+    r.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => r.push_str("\\\""),
+            '\\' => r.push_str("\\\\"),
+            '\n' => r.push_str("\\n"),
+            '\r' => r.push_str("\\r"),
+            '\t' => r.push_str("\\t"),
+            c if (c as u32) < 0x20 => r.push_str(&format!("\\u{:04x}", c as u32)),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        let mut r1 = Record::default();
+        r1.str("pid", "0x00000001");
+        r1.int("id", 1);
+        r1.str("name", "Leather Jacket, \"used\"");
+        let mut r2 = Record::default();
+        r2.str("pid", "0x01000002");
+        r2.opt("name", None);
+        r2.int("team_id", -3);
+        vec![r1, r2]
+    }
+
+    #[test]
+    fn write_csv_() {
+        let mut out = Vec::new();
+        write_csv(&records(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "pid,id,name,team_id\n\
+             0x00000001,1,\"Leather Jacket, \"\"used\"\"\",\n\
+             0x01000002,,,-3\n");
+    }
+
+    #[test]
+    fn write_json_() {
+        let mut out = Vec::new();
+        write_json(&records(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "[\n\
+             {\"pid\":\"0x00000001\",\"id\":1,\"name\":\"Leather Jacket, \\\"used\\\"\"},\n\
+             {\"pid\":\"0x01000002\",\"name\":null,\"team_id\":-3}\n\
+             ]\n");
+    }
+
+    #[test]
+    fn json_str_() {
+        assert_eq!(json_str("a\\b\n\u{1}"), r#""a\\b\n\u0001""#);
+    }
+}
@@ -0,0 +1,375 @@
+//! Prototypes defined in JSON files in the `proto_ext` subdirectory of the loose data
+//! directories. This allows mods to add new prototypes without a binary proto editor.
+//!
+//! Each `*.json` file holds an array of definitions. A definition copies all fields of the
+//! `base` prototype (which must be listed in the LST file) and overrides the given fields.
+//! Field names are the same as in the `dump protos` JSON output. PIDs and FIDs can be given
+//! either as numbers or as hex strings:
+//!
+//! ```text
+//! [
+//!     {
+//!         "base": "0x00000029",
+//!         "id": 1000,
+//!         "name": "Gold coin",
+//!         "description": "Worth more than a bottle cap.",
+//!         "price": 10
+//!     }
+//! ]
+//! ```
+//!
+//! `id` is optional and defaults to the next free id of the base prototype kind. Explicit ids
+//! must not be used by the LST file entries.
+//!
+//! Critters can also be given fields that don't exist in the PRO format:
+//! `wander_radius` - radius in hexes the critter wanders within around its position on the map.
+//!
+//! `sound_id` of items and scenery is given as a single character string (`"A"`) or a number.
+
+use log::*;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::*;
+use crate::asset::message::encoding::Encoding;
+use crate::asset::script::ProgramId;
+use crate::game::script::{ScriptKind, ScriptPid};
+use crate::util::json::{self, Value};
+
+pub const DIR: &str = "proto_ext";
+const EXTENSION: &str = "json";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProtoDef {
+    pub base: ProtoId,
+    pub id: Option<u32>,
+    /// Overridden fields in the order of appearance.
+    pub fields: Vec<(String, Value)>,
+}
+
+impl ProtoDef {
+    /// Overrides the fields of `proto` with the ones from this definition.
+    pub fn apply(&self, proto: &mut Proto, encoding: Encoding) -> io::Result<()> {
+        for (name, v) in &self.fields {
+            apply_field(proto, name, v, encoding)
+                .map_err(|e| Error::new(ErrorKind::InvalidData,
+                    format!("invalid value of field `{}`: {}", name, e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses array of prototype definitions.
+pub fn parse(s: &str) -> io::Result<Vec<ProtoDef>> {
+    let root = json::parse(s)?;
+    let defs = root.as_array()
+        .ok_or_else(|| error("expected array of prototype definitions"))?;
+    defs.iter().map(parse_def).collect()
+}
+
+/// Returns definition files found in the `proto_ext` subdirectories of `dirs` sorted by the
+/// file name within each directory.
+pub fn find_files(dirs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut r = Vec::new();
+    for dir in dirs {
+        r.extend(find_files_in(&dir.join(DIR))?);
+    }
+    Ok(r)
+}
+
+fn find_files_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut r = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map(|e| e == EXTENSION).unwrap_or(false) {
+            r.push(path);
+        }
+    }
+    r.sort();
+    debug!("found {} prototype definition files in {}", r.len(), dir.display());
+    Ok(r)
+}
+
+fn parse_def(v: &Value) -> io::Result<ProtoDef> {
+    let members = if let Value::Object(v) = v {
+        v
+    } else {
+        return Err(error("expected prototype definition object"));
+    };
+    let mut base = None;
+    let mut id = None;
+    let mut fields = Vec::new();
+    for (name, v) in members {
+        match name.as_str() {
+            "base" => base = Some(int(v)
+                .and_then(|v| ProtoId::from_packed(v as u32))
+                .ok_or_else(|| error("invalid base PID"))?),
+            "id" => id = Some(int(v)
+                .filter(|&v| v > 0 && v <= i64::from(ProtoId::MAX_ID))
+                .ok_or_else(|| error("invalid id"))? as u32),
+            _ => fields.push((name.clone(), v.clone())),
+        }
+    }
+    Ok(ProtoDef {
+        base: base.ok_or_else(|| error("missing base PID"))?,
+        id,
+        fields,
+    })
+}
+
+fn apply_field(proto: &mut Proto, name: &str, v: &Value, encoding: Encoding)
+    -> Result<(), String>
+{
+    let kind = proto.kind();
+    let unsupported = || format!("not supported for {:?} prototypes", kind);
+    match name {
+        "name" => proto.name = Some(encoding.encode(str(v)?)),
+        "description" => proto.description = Some(encoding.encode(str(v)?)),
+        "fid" => proto.fid = fid(v)?,
+        "light_radius" => proto.light_radius = i32(v)?,
+        "light_intensity" => proto.light_intensity = i32(v)?,
+        "script" => proto.script = if *v == Value::Null {
+            None
+        } else {
+            let program_id = ProgramId::new(i32(v)? as u32).ok_or("invalid program id")?;
+            let script_kind = if proto.id.kind() == EntityKind::Critter {
+                ScriptKind::Critter
+            } else {
+                ScriptKind::Item
+            };
+            Some(ScriptPid::new(script_kind, program_id))
+        },
+        _ => match &mut proto.sub {
+            SubProto::Item(item) => match name {
+                "size" => item.size = i32(v)?,
+                "weight" => item.weight = i32(v)?.try_into().map_err(|_| "negative weight")?,
+                "price" => item.price = i32(v)?,
+                "inventory_fid" => item.inventory_fid = opt_fid(v)?,
+                "sound_id" => item.sound_id = sound_id(v)?,
+                _ => match (&mut item.sub, name) {
+                    (SubItem::Armor(a), "armor_class") => a.armor_class = i32(v)?,
+                    (SubItem::Container(c), "capacity") => c.capacity = i32(v)?,
+                    (SubItem::Weapon(w), "damage_min") => w.damage.start = i32(v)?,
+                    (SubItem::Weapon(w), "damage_max") => w.damage.end = i32(v)?,
+                    (SubItem::Weapon(w), "min_strength") => w.min_strength = i32(v)?,
+                    (SubItem::Weapon(w), "max_ammo_count") => w.max_ammo_count = u32(v)?,
+                    (SubItem::Ammo(a), "max_ammo_count") => a.max_ammo_count = u32(v)?,
+                    (SubItem::Misc(m), "max_ammo_count") => m.max_ammo_count = u32(v)?,
+                    (SubItem::Key(k), "key_id") => k.id = i32(v)?,
+                    _ => return Err(unsupported()),
+                }
+            }
+            SubProto::Critter(c) => match name {
+                "experience" => c.experience = i32(v)?,
+                "ai_packet" => c.ai_packet = i32(v)?,
+                "team_id" => c.team_id = i32(v)?,
+                "wander_radius" => c.wander_radius = u32(v)?,
+                "head_fid" => c.head_fid = opt_fid(v)?,
+                _ => {
+                    let mut parts = name.splitn(2, '.');
+                    let map = parts.next().unwrap();
+                    let key = parts.next().ok_or_else(unsupported)?;
+                    let field = match map {
+                        "base_stats" => enum_map_field(&mut c.base_stats, key),
+                        "bonus_stats" => enum_map_field(&mut c.bonus_stats, key),
+                        "skills" => enum_map_field(&mut c.skills, key),
+                        _ => None,
+                    };
+                    *field.ok_or_else(unsupported)? = i32(v)?;
+                }
+            }
+            SubProto::Scenery(s) => match (&mut s.sub, name) {
+                (_, "sound_id") => s.sound_id = sound_id(v)?,
+                (SubScenery::Door(d), "key_id") => d.key_id = i32(v)?,
+                _ => return Err(unsupported()),
+            }
+            SubProto::Wall(_)
+            | SubProto::SqrTile(_)
+            | SubProto::Misc
+            => return Err(unsupported()),
+        }
+    }
+    Ok(())
+}
+
+fn enum_map_field<'a, K>(map: &'a mut EnumMap<K, i32>, key: &str) -> Option<&'a mut i32>
+    where K: enum_map::Enum<i32> + std::fmt::Debug
+{
+    map.iter_mut().find(|(k, _)| format!("{:?}", k) == key).map(|(_, v)| v)
+}
+
+/// Accepts numbers as well as hex strings prefixed with `0x`.
+fn int(v: &Value) -> Option<i64> {
+    match v {
+        Value::String(s) if s.starts_with("0x") => i64::from_str_radix(&s[2..], 16).ok(),
+        _ => v.as_i64(),
+    }
+}
+
+fn i32(v: &Value) -> Result<i32, String> {
+    int(v).and_then(|v| v.try_into().ok()).ok_or_else(|| "expected integer".into())
+}
+
+fn u32(v: &Value) -> Result<u32, String> {
+    int(v).and_then(|v| v.try_into().ok()).ok_or_else(|| "expected non-negative integer".into())
+}
+
+/// Sound ids are characters appended to the sound file names.
+fn sound_id(v: &Value) -> Result<u8, String> {
+    match v {
+        Value::String(s) if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        Value::String(_) => Err("expected single character".into()),
+        _ => int(v).and_then(|v| v.try_into().ok()).ok_or_else(|| "expected sound id".into()),
+    }
+}
+
+fn str(v: &Value) -> Result<&str, String> {
+    v.as_str().ok_or_else(|| "expected string".into())
+}
+
+fn fid(v: &Value) -> Result<FrameId, String> {
+    FrameId::from_packed(u32(v)?).ok_or_else(|| "malformed FID".into())
+}
+
+fn opt_fid(v: &Value) -> Result<Option<FrameId>, String> {
+    if *v == Value::Null {
+        Ok(None)
+    } else {
+        fid(v).map(Some)
+    }
+}
+
+fn error(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_() {
+        let defs = parse(r#"[
+            {"base": "0x01000003", "id": 2000, "name": "Guard", "base_stats.Strength": 8},
+            {"base": 41, "price": 10}
+        ]"#).unwrap();
+        assert_eq!(defs, vec![
+            ProtoDef {
+                base: ProtoId::from_packed(0x1000003).unwrap(),
+                id: Some(2000),
+                fields: vec![
+                    ("name".into(), Value::String("Guard".into())),
+                    ("base_stats.Strength".into(), Value::Number(8.0)),
+                ],
+            },
+            ProtoDef {
+                base: ProtoId::BOTTLE_CAPS,
+                id: None,
+                fields: vec![("price".into(), Value::Number(10.0))],
+            },
+        ]);
+
+        assert!(parse(r#"{"base": 41}"#).is_err());
+        assert!(parse(r#"[{"price": 10}]"#).is_err());
+        assert!(parse(r#"[{"base": 41, "id": 0}]"#).is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let mut proto = Proto {
+            id: ProtoId::new(EntityKind::Critter, 2000).unwrap(),
+            name: None,
+            description: None,
+            fid: FrameId::new(EntityKind::Critter, None, 0, 0, 0).unwrap(),
+            light_radius: 0,
+            light_intensity: 0,
+            flags: BitFlags::empty(),
+            flags_ext: BitFlags::empty(),
+            script: None,
+            sub: SubProto::Critter(Critter {
+                flags: BitFlags::empty(),
+                base_stats: EnumMap::new(),
+                bonus_stats: EnumMap::new(),
+                skills: EnumMap::new(),
+                body_kind: BodyKind::Biped,
+                experience: 0,
+                kill_kind: CritterKillKind::Man,
+                damage_kind: DamageKind::Melee,
+                head_fid: None,
+                ai_packet: 0,
+                team_id: 0,
+                wander_radius: 0,
+            }),
+        };
+        let def = &parse(r#"[{
+            "base": "0x01000003",
+            "name": "Guard",
+            "fid": "0x01000002",
+            "script": 12,
+            "experience": 50,
+            "base_stats.Strength": 8,
+            "skills.SmallGuns": 75
+        }]"#).unwrap()[0];
+        def.apply(&mut proto, Encoding::Cp1252).unwrap();
+
+        assert_eq!(proto.name().unwrap().as_bytes(), b"Guard");
+        assert_eq!(proto.fid.packed(), 0x01000002);
+        assert_eq!(proto.script, Some(ScriptPid::new(ScriptKind::Critter,
+            ProgramId::new(12).unwrap())));
+        let c = proto.sub.as_critter().unwrap();
+        assert_eq!(c.experience, 50);
+        assert_eq!(c.base_stats[Stat::Strength], 8);
+        assert_eq!(c.skills[Skill::SmallGuns], 75);
+
+        let def = &parse(r#"[{"base": 41, "price": 10}]"#).unwrap()[0];
+        assert!(def.apply(&mut proto, Encoding::Cp1252).is_err());
+    }
+
+    #[test]
+    fn apply_door() {
+        let mut proto = Proto {
+            id: ProtoId::new(EntityKind::Scenery, 2000).unwrap(),
+            name: None,
+            description: None,
+            fid: FrameId::new(EntityKind::Scenery, None, 0, 0, 0).unwrap(),
+            light_radius: 0,
+            light_intensity: 0,
+            flags: BitFlags::empty(),
+            flags_ext: BitFlags::empty(),
+            script: None,
+            sub: SubProto::Scenery(Scenery {
+                material: Material::Wood,
+                sound_id: b'A',
+                sub: SubScenery::Door(Door {
+                    flags: BitFlags::empty(),
+                    key_id: -1,
+                }),
+            }),
+        };
+        let def = &parse(r#"[{
+            "base": "0x02000001",
+            "sound_id": "M",
+            "key_id": 17
+        }]"#).unwrap()[0];
+        def.apply(&mut proto, Encoding::Cp1252).unwrap();
+
+        let s = proto.sub.as_scenery().unwrap();
+        assert_eq!(s.sound_id, b'M');
+        let d = s.sub.as_door().unwrap();
+        assert_eq!(d.key_id, 17);
+
+        for def in &[
+            r#"[{"base": 1, "sound_id": "AB"}]"#,
+            r#"[{"base": 1, "open_fps": 15}]"#,
+            r#"[{"base": 1, "wander_radius": 1}]"#,
+        ] {
+            let def = &parse(def).unwrap()[0];
+            assert!(def.apply(&mut proto, Encoding::Cp1252).is_err());
+        }
+    }
+}
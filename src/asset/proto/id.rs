@@ -20,15 +20,33 @@ impl ProtoId {
     pub const EXIT_AREA_LAST: Self = unsafe { Self::from_packed_unchecked(0x5000017) };
     pub const RADIOACTIVE_GOO_FIRST: Self = unsafe { Self::from_packed_unchecked(0x20003D9) };
     pub const RADIOACTIVE_GOO_LAST: Self = unsafe { Self::from_packed_unchecked(0x20003DC) };
+    pub const DYNAMITE: Self = unsafe { Self::from_packed_unchecked(51) };
+    pub const PLASTIC_EXPLOSIVE: Self = unsafe { Self::from_packed_unchecked(85) };
     pub const ACTIVE_FLARE: Self = unsafe { Self::from_packed_unchecked(0xCD) };
     pub const ACTIVE_DYNAMITE: Self = unsafe { Self::from_packed_unchecked(0xCE) };
     pub const ACTIVE_PLASTIC_EXPLOSIVE: Self = unsafe { Self::from_packed_unchecked(0xD1) };
     pub const SCROLL_BLOCKER: Self = unsafe { Self::from_packed_unchecked(0x0500000c) };
     pub const BOTTLE_CAPS: Self = unsafe { Self::from_packed_unchecked(0x29) };
     pub const SOLAR_SCORCHER: Self = unsafe { Self::from_packed_unchecked(390) };
+    pub const FIRST_AID_KIT: Self = unsafe { Self::from_packed_unchecked(47) };
+    pub const MULTI_TOOL: Self = unsafe { Self::from_packed_unchecked(75) };
+    pub const ELECTRONIC_LOCKPICKS: Self = unsafe { Self::from_packed_unchecked(77) };
+    pub const LOCKPICKS: Self = unsafe { Self::from_packed_unchecked(84) };
+    pub const DOCTORS_BAG: Self = unsafe { Self::from_packed_unchecked(91) };
+    pub const SUPER_TOOL_KIT: Self = unsafe { Self::from_packed_unchecked(308) };
+    pub const FIELD_MEDIC_FIRST_AID_KIT: Self = unsafe { Self::from_packed_unchecked(408) };
+    pub const PARAMEDICS_BAG: Self = unsafe { Self::from_packed_unchecked(409) };
+    pub const EXPANDED_LOCKPICK_SET: Self = unsafe { Self::from_packed_unchecked(410) };
+    pub const ELECTRONIC_LOCKPICKS_MK2: Self = unsafe { Self::from_packed_unchecked(411) };
+    pub const GEIGER_COUNTER: Self = unsafe { Self::from_packed_unchecked(52) };
+    pub const ACTIVE_GEIGER_COUNTER: Self = unsafe { Self::from_packed_unchecked(207) };
+    pub const MOTION_SENSOR: Self = unsafe { Self::from_packed_unchecked(59) };
+
+    /// Maximum id that fits into the packed form.
+    pub const MAX_ID: u32 = 0xffffff;
 
     pub fn new(kind: EntityKind, id: u32) -> Option<Self> {
-        if id <= 0xffffff {
+        if id <= Self::MAX_ID {
             Some(Self((kind as u32) << 24 | id))
         } else {
             None
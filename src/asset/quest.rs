@@ -0,0 +1,108 @@
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+
+use crate::asset::message::MessageId;
+
+/// Quest definition from `data/quests.txt`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Quest {
+    /// Town name message in `map.msg`.
+    pub location: MessageId,
+    /// Quest description message in `quests.msg`.
+    pub description: MessageId,
+    /// Global variable that tracks the quest progress.
+    pub global_var: usize,
+    /// The quest is shown once the global var reaches this value.
+    pub display_threshold: i32,
+    /// The quest is completed once the global var reaches this value.
+    pub completed_threshold: i32,
+}
+
+impl Quest {
+    pub fn is_displayed(&self, global_vars: &[i32]) -> bool {
+        self.value(global_vars) >= self.display_threshold
+    }
+
+    pub fn is_completed(&self, global_vars: &[i32]) -> bool {
+        self.value(global_vars) >= self.completed_threshold
+    }
+
+    fn value(&self, global_vars: &[i32]) -> i32 {
+        global_vars.get(self.global_var).copied().unwrap_or(0)
+    }
+}
+
+// quest_init
+pub fn read_quests(rd: &mut impl BufRead) -> io::Result<Vec<Quest>> {
+    let mut r = Vec::new();
+    for l in rd.lines() {
+        let l = l?;
+        let l = l.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let mut fields = l.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty());
+        let mut field = || -> io::Result<i32> {
+            let s = fields.next().ok_or_else(|| Error::new(ErrorKind::InvalidData,
+                format!("missing field in quest definition: `{}`", l)))?;
+            s.parse().map_err(|_| Error::new(ErrorKind::InvalidData,
+                format!("couldn't parse quest definition field as i32: `{}`", s)))
+        };
+        let location = field()? as MessageId;
+        let description = field()? as MessageId;
+        let global_var = field()?;
+        if global_var < 0 {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("invalid global var in quest definition: `{}`", l)));
+        }
+        let display_threshold = field()?;
+        let completed_threshold = field()?;
+        r.push(Quest {
+            location,
+            description,
+            global_var: global_var as usize,
+            display_threshold,
+            completed_threshold,
+        });
+    }
+    Ok(r)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn read_quests_() {
+        let s = "\
+# Arroyo
+1500, 100, 9, 1, 2
+
+\t1500,101,10,2,3   # trailing comment
+1501 102 11 1 2";
+        let quests = read_quests(&mut BufReader::new(Cursor::new(s))).unwrap();
+        assert_eq!(quests, vec![
+            Quest { location: 1500, description: 100, global_var: 9,
+                display_threshold: 1, completed_threshold: 2 },
+            Quest { location: 1500, description: 101, global_var: 10,
+                display_threshold: 2, completed_threshold: 3 },
+            Quest { location: 1501, description: 102, global_var: 11,
+                display_threshold: 1, completed_threshold: 2 },
+        ]);
+
+        assert!(read_quests(&mut BufReader::new(Cursor::new("1500, 100, 9"))).is_err());
+    }
+
+    #[test]
+    fn thresholds() {
+        let quest = Quest { location: 1500, description: 100, global_var: 1,
+            display_threshold: 1, completed_threshold: 3 };
+        assert!(!quest.is_displayed(&[5, 0]));
+        assert!(quest.is_displayed(&[0, 1]));
+        assert!(!quest.is_completed(&[0, 2]));
+        assert!(quest.is_completed(&[0, 3]));
+        assert!(!quest.is_displayed(&[]));
+    }
+}
@@ -5,7 +5,7 @@ use std::rc::Rc;
 
 use super::ProgramId;
 use crate::asset::message::Messages;
-use crate::fs::FileSystem;
+use crate::fs::{watch, FileSystem};
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct ScriptInfo {
@@ -52,9 +52,33 @@ impl ScriptDb {
         Ok(&self.messages[&program_id])
     }
 
+    /// Switches the dialog messages to `language`. The cached messages are dropped.
+    pub fn set_language(&mut self, language: &str) {
+        self.language = language.into();
+        self.messages.clear();
+    }
+
+    /// Drops cached dialog messages if `path` refers to one of the dialog MSG files.
+    /// Returns `false` if the file is not a dialog asset.
+    pub fn reload(&mut self, path: &str) -> bool {
+        let path = watch::normalize(path);
+        let prefix = format!("text/{}/dialog/", self.language.to_lowercase());
+        let name = if let Some(name) = path.strip_prefix(&prefix)
+            .and_then(|s| s.strip_suffix(".msg"))
+        {
+            name
+        } else {
+            return false;
+        };
+        let infos = &self.infos;
+        self.messages.retain(|pid, _| infos.get(pid.index()).map(|i| i.name != name)
+            .unwrap_or(true));
+        true
+    }
+
     fn load_messages(&self, program_id: ProgramId) -> io::Result<Messages> {
         let info = self.info_ok(program_id)?;
-        Messages::read_file(&self.fs, &self.language, &format!("dialog/{}.msg", info.name))
+        Ok(Messages::read_file(&self.fs, &self.language, &format!("dialog/{}.msg", info.name))?)
     }
 
     fn info_ok(&self, program_id: ProgramId) -> io::Result<&ScriptInfo> {
@@ -0,0 +1,117 @@
+//! SVE subtitle files (`text/<language>/cuts/*.sve`).
+//!
+//! Each line is `<frame>:<text>`. The text is shown from the frame until the frame of the next
+//! subtitle.
+
+use bstring::{bstr, BString};
+use std::io::{self, prelude::*};
+
+use crate::fs::FileSystem;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Subtitle {
+    pub frame: u32,
+    pub text: BString,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Subtitles {
+    /// Sorted by `frame`.
+    entries: Vec<Subtitle>,
+}
+
+impl Subtitles {
+    pub fn new(mut entries: Vec<Subtitle>) -> Self {
+        entries.sort_by_key(|s| s.frame);
+        Self { entries }
+    }
+
+    // movieLoadSubtitles
+    /// Malformed lines are skipped.
+    pub fn read(rd: &mut impl BufRead) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if rd.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            while line.last().map(|&c| c == b'\n' || c == b'\r').unwrap_or(false) {
+                line.pop();
+            }
+            let colon = if let Some(i) = line.iter().position(|&c| c == b':') {
+                i
+            } else {
+                continue;
+            };
+            let frame = if let Ok(v) = btoi::btoi::<u32>(trim(&line[..colon])) {
+                v
+            } else {
+                continue;
+            };
+            entries.push(Subtitle {
+                frame,
+                text: line[colon + 1..].into(),
+            });
+        }
+        Ok(Self::new(entries))
+    }
+
+    /// Reads `text/{language}/{path}`.
+    pub fn read_file(fs: &FileSystem, language: &str, path: &str) -> io::Result<Self> {
+        let path = format!("text/{}/{}", language, path);
+        Self::read(&mut fs.reader(&path)?)
+    }
+
+    pub fn entries(&self) -> &[Subtitle] {
+        &self.entries
+    }
+
+    /// Returns index of the subtitle shown at `frame`.
+    pub fn index_at(&self, frame: u32) -> Option<usize> {
+        self.entries.iter().rposition(|s| s.frame <= frame)
+    }
+
+    /// Returns subtitle text shown at `frame`.
+    pub fn at(&self, frame: u32) -> Option<&bstr> {
+        self.index_at(frame).map(|i| &*self.entries[i].text)
+    }
+
+    /// Frame of the last subtitle.
+    pub fn last_frame(&self) -> Option<u32> {
+        self.entries.last().map(|s| s.frame)
+    }
+}
+
+fn trim(s: &[u8]) -> &[u8] {
+    let start = s.iter().position(|c| !c.is_ascii_whitespace()).unwrap_or(s.len());
+    let end = s.iter().rposition(|c| !c.is_ascii_whitespace()).map(|i| i + 1).unwrap_or(start);
+    &s[start..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read() {
+        let subs = Subtitles::read(&mut Cursor::new(&b"\
+150:Second
+ 10 :First\r
+garbage
+x:bad frame
+300:"[..])).unwrap();
+        assert_eq!(subs.entries(), &[
+            Subtitle { frame: 10, text: BString::from(&b"First"[..]) },
+            Subtitle { frame: 150, text: BString::from(&b"Second"[..]) },
+            Subtitle { frame: 300, text: BString::new() },
+        ]);
+        assert_eq!(subs.at(0), None);
+        assert_eq!(subs.at(10), Some(<&bstr>::from(&b"First"[..])));
+        assert_eq!(subs.at(149), Some(<&bstr>::from(&b"First"[..])));
+        assert_eq!(subs.at(150), Some(<&bstr>::from(&b"Second"[..])));
+        assert_eq!(subs.at(1000), Some(<&bstr>::from(&b""[..])));
+        assert_eq!(subs.last_frame(), Some(300));
+    }
+}
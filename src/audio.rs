@@ -0,0 +1,189 @@
+//! Sound output through SDL audio.
+
+use log::*;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::asset::acm;
+use crate::fs::FileSystem;
+use crate::game::sfx::{self, Sound, MAX_PAN, MAX_VOLUME};
+
+/// Output sample rate. The game sounds are recorded at this rate so usually they don't need to
+/// be resampled.
+const SAMPLE_RATE: i32 = 22050;
+
+/// Maximum number of sounds playing at once. Sounds started while all voices are busy are
+/// dropped.
+const MAX_VOICES: usize = 16;
+
+/// Decoded sound file.
+struct Clip {
+    channels: usize,
+    sample_rate: u32,
+    /// Interleaved samples of all channels.
+    samples: Box<[i16]>,
+}
+
+impl Clip {
+    fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    /// Returns left and right samples of the frame `i`. Mono sound is played in both channels.
+    fn frame(&self, i: usize) -> (i32, i32) {
+        let i = i * self.channels;
+        let left = self.samples[i] as i32;
+        let right = if self.channels > 1 {
+            self.samples[i + 1] as i32
+        } else {
+            left
+        };
+        (left, right)
+    }
+}
+
+struct Voice {
+    clip: Arc<Clip>,
+    /// Position in the clip frames as 16.16 fixed point number.
+    pos: u64,
+    /// Increment of `pos` per output frame.
+    step: u64,
+    /// Gains of the left and right channels in range `[0, MAX_VOLUME]`.
+    gains: (i32, i32),
+}
+
+impl Voice {
+    fn frame_idx(&self) -> usize {
+        (self.pos >> 16) as usize
+    }
+}
+
+/// Mixes the playing sounds. Runs in the SDL audio thread.
+struct Mixer {
+    channels: usize,
+    sample_rate: u32,
+    voices: Vec<Voice>,
+}
+
+impl AudioCallback for Mixer {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        out.fill(0);
+        for voice in &mut self.voices {
+            for frame in out.chunks_exact_mut(self.channels) {
+                let i = voice.frame_idx();
+                if i >= voice.clip.frame_count() {
+                    break;
+                }
+                let (left, right) = voice.clip.frame(i);
+                let left = left * voice.gains.0 / MAX_VOLUME as i32;
+                let right = right * voice.gains.1 / MAX_VOLUME as i32;
+                if let [l, r, ..] = frame {
+                    *l = mix(*l, left);
+                    *r = mix(*r, right);
+                } else {
+                    frame[0] = mix(frame[0], (left + right) / 2);
+                }
+                voice.pos += voice.step;
+            }
+        }
+        self.voices.retain(|v| v.frame_idx() < v.clip.frame_count());
+    }
+}
+
+fn mix(dst: i16, src: i32) -> i16 {
+    (dst as i32 + src).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+pub struct Audio {
+    fs: Rc<FileSystem>,
+    device: AudioDevice<Mixer>,
+    /// Decoded sound effects keyed by path. `None` if the file couldn't be decoded.
+    sfx_clips: HashMap<String, Option<Arc<Clip>>>,
+}
+
+impl Audio {
+    pub fn new(sdl: &sdl2::Sdl, fs: Rc<FileSystem>) -> Result<Self, String> {
+        let audio = sdl.audio()?;
+        info!("Using audio driver: {}", audio.current_audio_driver());
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let device = audio.open_playback(None, &desired, |spec| Mixer {
+            channels: spec.channels as usize,
+            sample_rate: spec.freq as u32,
+            voices: Vec::new(),
+        })?;
+        device.resume();
+        Ok(Self {
+            fs,
+            device,
+            sfx_clips: HashMap::new(),
+        })
+    }
+
+    /// Starts playing `sound` mixed with the sounds already playing.
+    pub fn play(&mut self, sound: &Sound) {
+        let clip = if let Some(v) = self.clip(&sound.path) {
+            v
+        } else {
+            return;
+        };
+        let volume = sound.volume.min(MAX_VOLUME) as i32;
+        let pan = sound.pan.clamp(-MAX_PAN, MAX_PAN);
+        let gains = (
+            volume * (MAX_PAN - pan.max(0)) / MAX_PAN,
+            volume * (MAX_PAN + pan.min(0)) / MAX_PAN,
+        );
+
+        let mut mixer = self.device.lock();
+        if mixer.voices.len() >= MAX_VOICES {
+            debug!("no free voice to play {}", sound.path);
+            return;
+        }
+        let step = ((clip.sample_rate as u64) << 16) / mixer.sample_rate as u64;
+        mixer.voices.push(Voice {
+            clip,
+            pos: 0,
+            step,
+            gains,
+        });
+    }
+
+    /// Stops all playing sounds.
+    pub fn stop(&mut self) {
+        self.device.lock().voices.clear();
+    }
+
+    fn clip(&mut self, path: &str) -> Option<Arc<Clip>> {
+        // The speech and narration are rarely repeated so only the sound effects are cached.
+        let cache = path.starts_with(sfx::SFX_DIR);
+        if cache {
+            if let Some(clip) = self.sfx_clips.get(path) {
+                return clip.clone();
+            }
+        }
+        let clip = self.fs.reader(path)
+            .and_then(|rd| {
+                let mut decoder = acm::Decoder::new(rd)?;
+                let samples = decoder.read_to_end()?;
+                let header = decoder.header();
+                Ok(Arc::new(Clip {
+                    channels: header.channels as usize,
+                    sample_rate: header.sample_rate as u32,
+                    samples: samples.into(),
+                }))
+            })
+            .map_err(|e| warn!("couldn't read sound {}: {}", path, e))
+            .ok();
+        if cache {
+            self.sfx_clips.insert(path.into(), clip.clone());
+        }
+        clip
+    }
+}
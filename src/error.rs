@@ -0,0 +1,157 @@
+//! Crate-wide error type of the asset loading. Keeps the file path, the archive the file was
+//! read from, the offset of the bad data and the chain of what was being loaded, so the message
+//! tells the user what to fix.
+//!
+//! Converts to and from `io::Error` so it can be used in the code still returning `io::Result`.
+//! The conversion to `io::Error` preserves the error kind and the conversion back restores the
+//! original error.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// File isn't present in any of the archives or data dirs.
+    #[error("file not found: {path}")]
+    NotFound {
+        path: String,
+    },
+
+    /// Error reading `path` from the `archive` or data dir.
+    #[error("{path} (in {}): {source}", .archive.display())]
+    File {
+        path: String,
+        archive: PathBuf,
+        source: io::Error,
+    },
+
+    /// Malformed data at `offset` (if known) of the file being read.
+    #[error("{}{message}", at_offset(.offset))]
+    InvalidData {
+        offset: Option<u64>,
+        message: String,
+    },
+
+    /// What was being done when the `source` error occurred.
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<Error>,
+    },
+
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl Error {
+    pub fn file(path: &str, archive: &Path, source: io::Error) -> Self {
+        Self::File {
+            path: path.into(),
+            archive: archive.into(),
+            source,
+        }
+    }
+
+    pub fn invalid_data(message: impl Into<String>) -> Self {
+        Self::InvalidData {
+            offset: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_data_at(offset: u64, message: impl Into<String>) -> Self {
+        Self::InvalidData {
+            offset: Some(offset),
+            message: message.into(),
+        }
+    }
+
+    pub fn context(self, context: impl Into<String>) -> Self {
+        Self::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Returns `io::ErrorKind` matching the error so the code checking for `NotFound` works the
+    /// same with `io::Error` and this error.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::NotFound { .. } => io::ErrorKind::NotFound,
+            Self::File { source, .. } => source.kind(),
+            Self::InvalidData { .. } => io::ErrorKind::InvalidData,
+            Self::Context { source, .. } => source.kind(),
+            Self::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().map(|e| e.is::<Error>()).unwrap_or(false) {
+            *e.into_inner().unwrap().downcast::<Error>().unwrap()
+        } else {
+            Self::Io(e)
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
+
+fn at_offset(offset: &Option<u64>) -> String {
+    offset.map(|v| format!("at offset 0x{:x}: ", v)).unwrap_or_default()
+}
+
+pub trait ResultExt<T> {
+    /// Wraps the error with the context returned by `f`.
+    fn context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| Into::<Error>::into(e).context(f()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_() {
+        let e = Error::file("art/critters/hmjmpsaa.frm", Path::new("critter.dat"),
+            io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file"));
+        let e = e.context("loading frame set HMJMPSAA");
+        assert_eq!(e.to_string(), "loading frame set HMJMPSAA: art/critters/hmjmpsaa.frm \
+            (in critter.dat): unexpected end of file");
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        let e: Result<()> = Err(Error::invalid_data_at(0x1a, "bad flags"));
+        let e = e.context(|| "reading maps/artemple.map").unwrap_err();
+        assert_eq!(e.to_string(), "reading maps/artemple.map: at offset 0x1a: bad flags");
+        assert_eq!(Error::invalid_data("bad flags").to_string(), "bad flags");
+    }
+
+    #[test]
+    fn io_roundtrip() {
+        let e = Error::NotFound { path: "color.pal".into() };
+        let e = io::Error::from(e);
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert_eq!(e.to_string(), "file not found: color.pal");
+        let e = Error::from(e);
+        assert!(matches!(e, Error::NotFound { ref path } if path == "color.pal"));
+
+        let e = Error::from(io::Error::new(io::ErrorKind::Other, "other"));
+        assert!(matches!(e, Error::Io(_)));
+    }
+}
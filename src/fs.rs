@@ -1,14 +1,22 @@
 use std::{
-    io::{BufRead, Error, ErrorKind, Result},
+    collections::{BTreeSet, HashMap},
+    io::{self, BufRead, ErrorKind, Read, Result},
     path::{Path, PathBuf},
 };
 
 use ini::Ini;
-use log::info;
+use log::{info, warn};
 
 pub mod dat;
+pub mod detect;
 pub mod inifile;
+pub mod manifest;
 pub mod stdfs;
+pub mod watch;
+
+use manifest::{Manifest, ManifestEntry};
+use crate::asset::Game;
+use crate::error::{self, Error, ResultExt};
 
 #[derive(Clone, Debug)]
 pub struct Metadata {
@@ -23,70 +31,151 @@ impl Metadata {
 
 pub struct FileSystem {
     providers: Vec<Box<dyn Provider>>,
+    /// Whether the provider at the same index lists its files in the `index`.
+    listed: Vec<bool>,
+    /// Normalized paths of the files of the listed providers mapped to the index of the highest
+    /// priority provider that has the file.
+    index: HashMap<String, usize>,
     properties_providers: Vec<Box<dyn PropertiesProvider>>,
+    /// Directories of the loose file providers.
+    loose_dirs: Vec<PathBuf>,
+    /// Resource dirs in priority order.
+    root_dirs: Vec<PathBuf>,
+    game: Game,
 }
 
 impl FileSystem {
-    pub fn new(args: &clap::ArgMatches) -> Self {
+    pub fn new(args: &clap::ArgMatches) -> error::Result<Self> {
+        let game = args.value_of("game").and_then(Game::parse).unwrap_or_default();
+        if game == Game::Fallout1 {
+            warn!("Fallout 1 data can only be browsed: map exits, scripts and the world map \
+                may not work");
+        }
         let mut result = FileSystem {
             providers: Vec::new(),
+            listed: Vec::new(),
+            index: HashMap::new(),
             properties_providers: Vec::new(),
+            loose_dirs: Vec::new(),
+            root_dirs: Vec::new(),
+            game,
         };
-        result.setup_file_system(Path::new(args.value_of("RESOURCE_DIR").unwrap()));
-        return result;
+        let root_dirs = args.value_of("RESOURCE_DIR")
+            .map(|v| detect::resource_dirs(v, game))
+            .unwrap_or_default();
+        if root_dirs.is_empty() {
+            return Err(Error::from(io::Error::new(ErrorKind::InvalidInput,
+                "resource dir is not specified or couldn't be detected")));
+        }
+        // Providers are searched in the registration order so the dirs are set up from the
+        // highest priority one.
+        for root_dir in &root_dirs {
+            result.setup_file_system(root_dir)?;
+        }
+        result.root_dirs = root_dirs;
+        info!("File providers from the highest priority:");
+        for (i, provider) in result.providers.iter().enumerate() {
+            info!("  {}: {}", i, provider.path().display());
+        }
+        Ok(result)
     }
 
-    fn setup_file_system(&mut self, root_dir: &Path) {
-        info!("Using resources dir: {}", root_dir.display());
+    /// Resource dirs in priority order.
+    pub fn root_dirs(&self) -> &[PathBuf] {
+        &self.root_dirs
+    }
+
+    /// The game the data files are of.
+    pub fn game(&self) -> Game {
+        self.game
+    }
+
+    fn setup_file_system(&mut self, root_dir: &Path) -> error::Result<()> {
+        if !root_dir.is_dir() {
+            return Err(Error::from(io::Error::new(ErrorKind::NotFound,
+                format!("resource dir {} doesn't exist", root_dir.display()))));
+        }
+        info!("Using {:?} resources dir: {}", self.game, root_dir.display());
 
         let mut dat_files = Vec::new();
         let mut ini_files = Vec::new();
 
-        for file in &["fallout2.cfg", "f2_res.ini", "ddraw.ini"] {
-            let path: PathBuf = [root_dir, Path::new(file)].iter().collect();
-            if path.is_file() {
+        let ini_names: &[&str] = match self.game {
+            Game::Fallout1 => &["fallout.cfg"],
+            Game::Fallout2 => &["fallout2.cfg", "f2_res.ini", "ddraw.ini"],
+        };
+        for file in ini_names {
+            if let Some(path) = detect::find_file(root_dir, file).filter(|p| p.is_file()) {
                 info!("Found {}", file);
                 ini_files.push(path);
             }
         }
 
-        // Add patchXXX.dat files.
-        for i in 0..999 {
-            let file = format!("patch{:03}.dat", i);
-            let path: PathBuf = [root_dir, Path::new(&file)].iter().collect();
-            if path.is_file() {
-                info!("Found {}", file);
-                dat_files.push(path)
-            } else {
-                break;
-            }
+        // patchXXX.dat files override master.dat and critter.dat, the higher numbered first.
+        for path in detect::patch_files(root_dir) {
+            info!("Found {}", path.file_name().unwrap().to_string_lossy());
+            dat_files.push(path);
         }
-        dat_files.reverse();
 
         for file in &["master.dat", "critter.dat"] {
-            let path: PathBuf = [root_dir, Path::new(file)].iter().collect();
-            if path.is_file() {
+            if let Some(path) = detect::find_file(root_dir, file).filter(|p| p.is_file()) {
                 info!("Found {}", file);
                 dat_files.push(path);
             }
         }
 
         for ini_file in ini_files.iter() {
-            self.register_properties_provider(inifile::new_provider(ini_file).unwrap());
+            self.register_properties_provider(inifile::new_provider(ini_file)
+                .context(|| format!("reading {}", ini_file.display()))?);
         }
 
-        let data_dir: PathBuf = [root_dir, Path::new("data")].iter().collect();
-        if data_dir.is_dir() {
+        if let Some(data_dir) = detect::find_file(root_dir, "data").filter(|p| p.is_dir()) {
             info!("Found `data` dir");
-            self.register_provider(stdfs::new_provider(data_dir).unwrap());
+            self.register_provider(stdfs::new_provider(&data_dir)?);
+            self.loose_dirs.push(data_dir);
+        }
+
+        // Reading the file lists is mostly waiting for the disk so the archives are read in
+        // parallel.
+        let game = self.game;
+        let providers: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = dat_files.iter()
+                .map(|dat_file| s.spawn(move || match game {
+                    Game::Fallout1 => dat::v1::new_provider(dat_file),
+                    Game::Fallout2 => dat::v2::new_provider(dat_file),
+                }))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for (provider, dat_file) in providers.into_iter().zip(&dat_files) {
+            self.register_provider(provider
+                .context(|| format!("opening archive {}", dat_file.display()))?);
         }
 
-        for dat_file in dat_files.iter().rev() {
-            self.register_provider(dat::v2::new_provider(dat_file).unwrap());
+        Ok(())
+    }
+
+    /// File system without any providers.
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        Self {
+            providers: Vec::new(),
+            listed: Vec::new(),
+            index: HashMap::new(),
+            properties_providers: Vec::new(),
+            loose_dirs: Vec::new(),
+            root_dirs: Vec::new(),
+            game: Game::Fallout2,
         }
     }
 
     pub fn register_provider(&mut self, provider: Box<dyn Provider>) {
+        let i = self.providers.len();
+        let files = provider.files();
+        self.listed.push(files.is_some());
+        for path in files.into_iter().flatten() {
+            self.index.entry(path.into()).or_insert(i);
+        }
         self.providers.push(provider);
     }
 
@@ -94,71 +183,246 @@ impl FileSystem {
         self.properties_providers.push(provider);
     }
 
-    pub fn properties(&self, path: &str) -> Result<Box<&Ini>> {
-        let ref this = self;
-        let path = path;
-        let mut error: Option<Error> = None;
-        for provider in &this.properties_providers {
+    pub fn properties(&self, path: &str) -> error::Result<Box<&Ini>> {
+        for provider in &self.properties_providers {
             match provider.as_ref().reader(path) {
                 Ok(r) => return Ok(r),
-                Err(e) => {
-                    if e.kind() == ErrorKind::NotFound {
-                        error = None;
-                        continue;
-                    }
-                    if error.is_none() {
-                        error = Some(e);
-                    }
-                    break;
-                }
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::from(e).context(format!("reading {}", path))),
             }
         }
-        Err(error.unwrap_or_else(|| {
-            Error::new(ErrorKind::NotFound, format!("file not found: {}", path))
-        }))
+        Err(Error::NotFound { path: path.into() })
+    }
+
+    /// Returns value of the `key` in the `section` of the config file at `path`.
+    pub fn property(&self, path: &str, section: Option<&str>, key: &str) -> Option<&str> {
+        self.properties_provider(path).and_then(|p| p.get(section, key))
+    }
+
+    /// Returns the provider of the config file at `path` if the file was read successfully.
+    pub fn properties_provider(&self, path: &str) -> Option<&dyn PropertiesProvider> {
+        self.properties_providers.iter()
+            .map(|p| p.as_ref())
+            .find(|p| p.metadata(path).is_ok())
     }
 
-    pub fn reader(&self, path: &str) -> Result<Box<dyn BufRead + Send>> {
-        self.find_provider(path, |p| p.reader(path))
+    /// Returns reader of the file. Errors returned by the reader are attributed to the file and
+    /// the archive it's read from.
+    pub fn reader(&self, path: &str) -> error::Result<Box<dyn BufRead + Send>> {
+        self.find_provider(path, |p| {
+            let inner = p.reader(path)?;
+            Ok(Box::new(FileReader {
+                inner,
+                path: path.into(),
+                archive: p.path().into(),
+            }) as Box<dyn BufRead + Send>)
+        })
     }
 
-    pub fn metadata(&self, path: &str) -> Result<Metadata> {
+    pub fn metadata(&self, path: &str) -> error::Result<Metadata> {
         self.find_provider(path, |p| p.metadata(path))
     }
 
-    fn find_provider<T>(&self, path: &str, f: impl Fn(&dyn Provider) -> Result<T>) -> Result<T> {
-        let mut error: Option<Error> = None;
-        for provider in &self.providers {
+    /// Returns the result of `f` for the first provider that has the file.
+    fn find_provider<T>(&self, path: &str, f: impl Fn(&dyn Provider) -> Result<T>)
+        -> error::Result<T>
+    {
+        // Only the listed provider that has the file needs to be asked, the unlisted ones
+        // (the loose file dirs) are asked in their priority order.
+        let found = self.index.get(&dat::normalize_path(path)).copied();
+        for (i, provider) in self.providers.iter().enumerate() {
+            if self.listed[i] && found != Some(i) {
+                continue;
+            }
             match f(provider.as_ref()) {
                 Ok(r) => return Ok(r),
-                Err(e) => {
-                    if e.kind() == ErrorKind::NotFound {
-                        error = None;
-                        continue;
-                    }
-                    if error.is_none() {
-                        error = Some(e);
-                    }
-                    break;
-                }
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::file(path, provider.path(), e)),
             }
         }
-        Err(error.unwrap_or_else(|| {
-            Error::new(ErrorKind::NotFound, format!("file not found: {}", path))
-        }))
+        Err(Error::NotFound { path: path.into() })
     }
 
     pub fn exists(&self, path: &str) -> bool {
         self.metadata(path).is_ok()
     }
+
+    /// Returns directories the loose (not archived) files are read from.
+    pub fn loose_dirs(&self) -> &[PathBuf] {
+        &self.loose_dirs
+    }
+
+    /// Returns paths of the loose files in `dir` with the `extension`, sorted. Files in the
+    /// archives are not listed.
+    pub fn loose_files(&self, dir: &str, extension: &str) -> Vec<String> {
+        let suffix = format!(".{}", extension.to_lowercase());
+        let mut r = BTreeSet::new();
+        for root in &self.loose_dirs {
+            let fs_dir = dir.split(|c| c == '/' || c == '\\')
+                .fold(root.clone(), |p, s| p.join(s));
+            let entries = if let Ok(v) = std::fs::read_dir(fs_dir) {
+                v
+            } else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_file() && name.to_lowercase().ends_with(&suffix) {
+                    r.insert(format!("{}/{}", dir, name));
+                }
+            }
+        }
+        r.into_iter().collect()
+    }
+
+    /// Returns manifest of the registered providers in the priority order.
+    pub fn manifest(&self) -> Manifest {
+        self.manifest_of(&|_| true)
+    }
+
+    /// Same as `manifest()` but only fingerprints the files whose normalized paths (lower case
+    /// with `/` separators) pass the `filter`.
+    pub fn manifest_of(&self, filter: &dyn Fn(&str) -> bool) -> Manifest {
+        Manifest {
+            entries: self.providers.iter().map(|p| p.manifest_entry(filter)).collect(),
+        }
+    }
 }
 
-pub trait Provider {
+pub trait Provider: Send {
     fn reader(&self, path: &str) -> Result<Box<dyn BufRead + Send>>;
     fn metadata(&self, path: &str) -> Result<Metadata>;
+    /// Path of the archive or directory the files are read from.
+    fn path(&self) -> &Path;
+    /// Fingerprints the files whose normalized paths (lower case with `/` separators) pass the
+    /// `filter`.
+    fn manifest_entry(&self, filter: &dyn Fn(&str) -> bool) -> ManifestEntry;
+
+    /// Returns normalized paths of all the files if the file list is fixed, like in archives.
+    fn files(&self) -> Option<Vec<&str>> {
+        None
+    }
+}
+
+/// Attributes the read errors to the file and the archive it's read from.
+struct FileReader {
+    inner: Box<dyn BufRead + Send>,
+    path: String,
+    archive: PathBuf,
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let Self { inner, path, archive } = self;
+        inner.read(buf).map_err(|e| Error::file(path, archive, e).into())
+    }
+}
+
+impl BufRead for FileReader {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        let Self { inner, path, archive } = self;
+        inner.fill_buf().map_err(|e| Error::file(path, archive, e).into())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
 }
 
 pub trait PropertiesProvider {
     fn reader(&self, path: &str) -> Result<Box<&Ini>>;
     fn metadata(&self, path: &str) -> Result<Metadata>;
+    /// Path of the config file.
+    fn path(&self) -> &Path;
+    /// Returns names of the sections. Empty if the file couldn't be read.
+    fn sections(&self) -> Vec<&str>;
+    /// Returns value of the `key` in the `section` or in the general section if `None`.
+    fn get(&self, section: Option<&str>, key: &str) -> Option<&str>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct MockProvider {
+        path: PathBuf,
+        files: Vec<String>,
+        listed: bool,
+    }
+
+    impl Provider for MockProvider {
+        fn reader(&self, path: &str) -> Result<Box<dyn BufRead + Send>> {
+            self.metadata(path)?;
+            let name = self.path.to_string_lossy().into_owned();
+            Ok(Box::new(io::Cursor::new(name.into_bytes())))
+        }
+
+        fn metadata(&self, path: &str) -> Result<Metadata> {
+            if self.files.contains(&dat::normalize_path(path)) {
+                Ok(Metadata { len: 0 })
+            } else {
+                Err(io::Error::new(ErrorKind::NotFound, "file not found"))
+            }
+        }
+
+        fn path(&self) -> &Path {
+            &self.path
+        }
+
+        fn manifest_entry(&self, filter: &dyn Fn(&str) -> bool) -> ManifestEntry {
+            let mut hash = manifest::Fnv64::new();
+            for f in &self.files {
+                if filter(&f.replace('\\', "/")) {
+                    hash.write(f.as_bytes());
+                }
+            }
+            ManifestEntry {
+                name: self.path.to_string_lossy().into_owned(),
+                hash: hash.finish(),
+            }
+        }
+
+        fn files(&self) -> Option<Vec<&str>> {
+            if self.listed {
+                Some(self.files.iter().map(|s| s.as_str()).collect())
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn provider_priority() {
+        let mut fs = FileSystem::mock();
+        for &(path, files, listed) in &[
+            ("data", &["a", "art\\b.frm"][..], false),
+            ("patch000.dat", &["art\\b.frm", "art\\c.frm"][..], true),
+            ("master.dat", &["art\\c.frm", "d"][..], true),
+        ] {
+            fs.register_provider(Box::new(MockProvider {
+                path: path.into(),
+                files: files.iter().map(|&s| s.into()).collect(),
+                listed,
+            }));
+        }
+        let read = |path| {
+            let mut s = String::new();
+            fs.reader(path).unwrap().read_to_string(&mut s).unwrap();
+            s
+        };
+        assert_eq!(read("a"), "data");
+        assert_eq!(read("ART/B.FRM"), "data");
+        assert_eq!(read("art/c.frm"), "patch000.dat");
+        assert_eq!(read("d"), "master.dat");
+        assert_eq!(fs.reader("e").err().unwrap().kind(), ErrorKind::NotFound);
+
+        let names: Vec<_> = fs.manifest().entries.into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["data", "patch000.dat", "master.dat"]);
+
+        // Only the files passing the filter are fingerprinted.
+        let art = fs.manifest_of(&|p| p.starts_with("art/"));
+        let no_art = fs.manifest_of(&|p| !p.starts_with("art/"));
+        assert_ne!(art, fs.manifest());
+        assert_eq!(no_art.entries[1], fs.manifest_of(&|_| false).entries[1]);
+    }
 }
@@ -1,4 +1,8 @@
+pub mod extract;
 mod lzss;
+mod source;
 mod util;
 pub mod v1;
 pub mod v2;
+
+pub use util::normalize_path;
@@ -0,0 +1,154 @@
+//! Listing and extraction of DAT2 archive entries for the `dat` command line subcommand.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+
+use super::super::Provider;
+use super::util::normalize_path;
+use super::v2::{Dat, Entry};
+
+/// Path pattern with `*` (any sequence of characters including path separators) and `?`
+/// (any single character) wildcards. Matching is case-insensitive and treats `/` and `\`
+/// the same way.
+#[derive(Clone, Debug)]
+pub struct Pattern(Vec<char>);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        Self(normalize_path(pattern).chars().collect())
+    }
+
+    /// Pattern matching all entries.
+    pub fn any() -> Self {
+        Self(vec!['*'])
+    }
+
+    /// Matches normalized `path` against this pattern.
+    pub fn matches(&self, path: &str) -> bool {
+        let path: Vec<_> = path.chars().collect();
+        let pat = &self.0;
+
+        let (mut p, mut s) = (0, 0);
+        // Position after the last seen star and path position it was matched at.
+        let mut backtrack = None;
+        while s < path.len() {
+            if p < pat.len() && pat[p] == '*' {
+                p += 1;
+                backtrack = Some((p, s));
+            } else if p < pat.len() && (pat[p] == '?' || pat[p] == path[s]) {
+                p += 1;
+                s += 1;
+            } else if let Some((bp, bs)) = backtrack {
+                p = bp;
+                s = bs + 1;
+                backtrack = Some((bp, s));
+            } else {
+                return false;
+            }
+        }
+        pat[p..].iter().all(|&c| c == '*')
+    }
+}
+
+/// Returns entries of the `archive` matching the `pattern`.
+pub fn list(archive: &Dat, pattern: &Pattern) -> Vec<Entry> {
+    archive.entries()
+        .into_iter()
+        .filter(|e| pattern.matches(&e.path))
+        .collect()
+}
+
+/// Writes listing of the `entries` to `out`, one entry per line.
+pub fn write_list(entries: &[Entry], out: &mut impl Write) -> Result<()> {
+    for e in entries {
+        if let Some(compressed_size) = e.compressed_size {
+            writeln!(out, "{:>10} {:>10}  {}", e.size, compressed_size, e.path)?;
+        } else {
+            writeln!(out, "{:>10} {:>10}  {}", e.size, "-", e.path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts normalized archive `path` to path under the `out_dir`.
+fn out_path(out_dir: &Path, path: &str) -> Result<PathBuf> {
+    let mut r = out_dir.to_path_buf();
+    for c in path.split('\\') {
+        if c.is_empty() || c == "." || c == ".." {
+            return Err(Error::new(ErrorKind::InvalidData,
+                format!("refusing to extract entry with unsafe path: {}", path)));
+        }
+        r.push(c);
+    }
+    Ok(r)
+}
+
+/// Extracts entries of the `archive` matching the `pattern` into `out_dir` preserving the
+/// archive directory structure. Returns the number of extracted entries.
+pub fn extract(archive: &Dat, pattern: &Pattern, out_dir: &Path) -> Result<usize> {
+    let entries = list(archive, pattern);
+    for e in &entries {
+        let path = out_path(out_dir, &e.path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut wr = BufWriter::new(File::create(&path)?);
+        io::copy(&mut archive.reader(&e.path)?, &mut wr)?;
+        wr.flush()?;
+    }
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pattern_matches() {
+        let p = Pattern::new("art/critters/*.FRM");
+        assert!(p.matches("art\\critters\\hmjmpsaa.frm"));
+        assert!(!p.matches("art\\critters\\hmjmpsaa.fr0"));
+        assert!(!p.matches("art\\items\\gun.frm"));
+
+        let p = Pattern::new("*.fr?");
+        assert!(p.matches("art\\critters\\hmjmpsaa.fr0"));
+        assert!(p.matches("a.frm"));
+        assert!(!p.matches("a.frmx"));
+
+        let p = Pattern::new("*");
+        assert!(p.matches(""));
+        assert!(p.matches("maps\\artemple.map"));
+        assert!(Pattern::any().matches("maps\\artemple.map"));
+
+        let p = Pattern::new("maps\\*temple*");
+        assert!(p.matches("maps\\artemple.map"));
+        assert!(p.matches("maps\\temple"));
+        assert!(!p.matches("maps\\arcaves.map"));
+
+        let p = Pattern::new("text/english/game/pro_item.msg");
+        assert!(p.matches("text\\english\\game\\pro_item.msg"));
+        assert!(!p.matches("text\\english\\game\\pro_item.ms"));
+    }
+
+    #[test]
+    fn out_path_() {
+        let dir = Path::new("out");
+        assert_eq!(out_path(dir, "art\\a.frm").unwrap(),
+            Path::new("out").join("art").join("a.frm"));
+        assert!(out_path(dir, "..\\a.frm").is_err());
+        assert!(out_path(dir, "art\\\\a.frm").is_err());
+    }
+
+    #[test]
+    fn write_list_() {
+        let entries = vec![
+            Entry { path: "a.txt".into(), size: 10, compressed_size: None },
+            Entry { path: "b\\c.frm".into(), size: 1000, compressed_size: Some(400) },
+        ];
+        let mut out = Vec::new();
+        write_list(&entries, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "        10          -  a.txt\n      1000        400  b\\c.frm\n");
+    }
+}
@@ -0,0 +1,126 @@
+//! Access to the archive entry data. The archive is memory-mapped when the platform supports it
+//! so the uncompressed entries are read without copying and the compressed ones are inflated
+//! straight from the mapping. Otherwise the file is opened and read for every entry.
+
+#[cfg(any(unix, windows))]
+use log::warn;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+#[cfg(any(unix, windows))]
+use std::sync::Arc;
+
+#[cfg(any(unix, windows))]
+use memmap2::Mmap;
+
+#[derive(Debug)]
+pub enum Source {
+    #[cfg(any(unix, windows))]
+    Mmap(Arc<Mmap>),
+    File(PathBuf),
+}
+
+impl Source {
+    /// Maps the archive at `path` into memory falling back to the file reads if mapping fails.
+    pub fn open(path: &Path) -> Self {
+        #[cfg(any(unix, windows))]
+        {
+            // The archive must not be modified while mapped. The game data is never written to
+            // while the game is running.
+            match File::open(path).and_then(|f| unsafe { Mmap::map(&f) }) {
+                Ok(map) => return Source::Mmap(Arc::new(map)),
+                Err(e) => warn!("couldn't memory-map {}, falling back to reading the file: {}",
+                    path.display(), e),
+            }
+        }
+        Source::File(path.into())
+    }
+
+    /// Returns reader of `len` bytes at `offset`.
+    pub fn reader(&self, offset: u64, len: u64) -> Result<Box<dyn BufRead + Send>> {
+        match self {
+            #[cfg(any(unix, windows))]
+            Source::Mmap(map) => {
+                let end = offset.checked_add(len)
+                    .filter(|&end| end <= map.len() as u64)
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData,
+                        "entry data is out of the archive bounds"))?;
+                Ok(Box::new(MmapReader {
+                    map: map.clone(),
+                    pos: offset as usize,
+                    end: end as usize,
+                }))
+            }
+            Source::File(path) => {
+                let mut f = File::open(path)?;
+                f.seek(SeekFrom::Start(offset))?;
+                Ok(Box::new(BufReader::new(f.take(len))))
+            }
+        }
+    }
+}
+
+/// Reader of a range of the mapped archive. `fill_buf()` returns the mapped memory directly.
+#[cfg(any(unix, windows))]
+struct MmapReader {
+    map: Arc<Mmap>,
+    pos: usize,
+    end: usize,
+}
+
+#[cfg(any(unix, windows))]
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = (&self.map[self.pos..self.end]).read(buf)?;
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl BufRead for MmapReader {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        Ok(&self.map[self.pos..self.end])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.end);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reader() {
+        let path = std::env::temp_dir()
+            .join(format!("vault13_dat_source_test_{}", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+
+        let read = |src: &Source, offset, len| {
+            let mut r = Vec::new();
+            src.reader(offset, len).unwrap().read_to_end(&mut r).unwrap();
+            r
+        };
+        for src in &[Source::open(&path), Source::File(path.clone())] {
+            assert_eq!(read(src, 2, 3), b"234");
+            assert_eq!(read(src, 0, 10), b"0123456789");
+            assert_eq!(read(src, 10, 0), b"");
+            let mut r = src.reader(4, 4).unwrap();
+            assert_eq!(r.fill_buf().unwrap()[0], b'4');
+            r.consume(2);
+            let mut s = String::new();
+            r.read_line(&mut s).unwrap();
+            assert_eq!(s, "67");
+        }
+        #[cfg(any(unix, windows))]
+        {
+            assert!(matches!(Source::open(&path), Source::Mmap(_)));
+            assert_eq!(Source::open(&path).reader(8, 3).err().unwrap().kind(),
+                ErrorKind::InvalidData);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
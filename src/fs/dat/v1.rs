@@ -6,7 +6,9 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
 use super::lzss;
+use super::source::Source;
 use super::super::{Metadata, Provider};
+use super::super::manifest::{Fnv64, ManifestEntry};
 use super::util::{build_normalized_path, normalize_path};
 
 pub fn new_provider<P: AsRef<Path>>(path: P) -> Result<Box<dyn Provider>> {
@@ -17,6 +19,7 @@ pub fn new_provider<P: AsRef<Path>>(path: P) -> Result<Box<dyn Provider>> {
 struct Dat {
     path: PathBuf,
     files: HashMap<String, DatFile>,
+    source: Source,
 }
 
 #[derive(Debug)]
@@ -73,6 +76,7 @@ impl Dat {
         Ok(Dat {
             path: path.as_ref().to_path_buf(),
             files,
+            source: Source::open(path.as_ref()),
         })
     }
 
@@ -96,11 +100,7 @@ impl Provider for Dat {
         } else {
             dat_file.size
         };
-        let reader = BufReader::new({
-            let mut f = File::open(&self.path)?;
-            f.seek(SeekFrom::Start(dat_file.offset as u64))?;
-            f.take(read_size as u64)
-        });
+        let reader = self.source.reader(dat_file.offset as u64, read_size as u64)?;
         Ok(if dat_file.is_compressed() {
             // TODO make LzssDecoder implement BufRead
             Box::new(BufReader::new(lzss::LzssDecoder::new(reader, dat_file.size as u64)))
@@ -112,6 +112,32 @@ impl Provider for Dat {
     fn metadata(&self, path: &str) -> Result<Metadata> {
         self.file(path).map(|f| Metadata { len: f.size as u64 })
     }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn files(&self) -> Option<Vec<&str>> {
+        Some(self.files.keys().map(|s| s.as_str()).collect())
+    }
+
+    fn manifest_entry(&self, filter: &dyn Fn(&str) -> bool) -> ManifestEntry {
+        let mut files: Vec<_> = self.files.iter()
+            .filter(|(path, _)| filter(&path.replace('\\', "/")))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let mut hash = Fnv64::new();
+        for (path, f) in files {
+            hash.write(path.as_bytes());
+            hash.write_u64(f.offset as u64);
+            hash.write_u64(f.size as u64);
+            hash.write_u64(f.compressed_size as u64);
+        }
+        ManifestEntry {
+            name: self.path.file_name().unwrap().to_string_lossy().into_owned(),
+            hash: hash.finish(),
+        }
+    }
 }
 
 fn read_path<R: Read>(reader: &mut R) -> Result<String> {
@@ -6,17 +6,31 @@ use std::io::prelude::*;
 
 use std::path::{Path, PathBuf};
 
+use super::source::Source;
 use super::super::{Metadata, Provider};
+use super::super::manifest::{Fnv64, ManifestEntry};
 use super::util::{build_normalized_path, normalize_path};
 
 pub fn new_provider<P: AsRef<Path>>(path: P) -> Result<Box<dyn Provider>> {
     Ok(Box::new(Dat::new(path)?))
 }
 
+/// DAT2 archive.
 #[derive(Debug)]
-struct Dat {
+pub struct Dat {
     path: PathBuf,
     files: HashMap<String, DatFile>,
+    source: Source,
+}
+
+/// Archive entry as listed in the file list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    /// Normalized path.
+    pub path: String,
+    pub size: u32,
+    /// Size of the compressed data or `None` if the entry is stored uncompressed.
+    pub compressed_size: Option<u32>,
 }
 
 #[derive(Debug)]
@@ -72,9 +86,23 @@ impl Dat {
         Ok(Dat {
             path: path.as_ref().to_path_buf(),
             files,
+            source: Source::open(path.as_ref()),
         })
     }
 
+    /// Returns all entries sorted by path.
+    pub fn entries(&self) -> Vec<Entry> {
+        let mut r: Vec<_> = self.files.iter()
+            .map(|(path, f)| Entry {
+                path: path.clone(),
+                size: f.size,
+                compressed_size: if f.is_compressed() { Some(f.compressed_size) } else { None },
+            })
+            .collect();
+        r.sort_by(|a, b| a.path.cmp(&b.path));
+        r
+    }
+
     fn file(&self, path: &str) -> Result<&DatFile> {
         self.files.get(&normalize_path(path))
             .ok_or_else(|| Error::new(ErrorKind::NotFound, "file not found"))
@@ -95,11 +123,7 @@ impl Provider for Dat {
         } else {
             dat_file.size
         };
-        let reader = BufReader::new({
-            let mut f = File::open(&self.path)?;
-            f.seek(SeekFrom::Start(dat_file.offset as u64))?;
-            f.take(read_size as u64)
-        });
+        let reader = self.source.reader(dat_file.offset as u64, read_size as u64)?;
         Ok(if dat_file.is_compressed() {
             use flate2::bufread::ZlibDecoder;
             Box::new(BufReader::new(ZlibDecoder::new(reader)))
@@ -111,6 +135,32 @@ impl Provider for Dat {
     fn metadata(&self, path: &str) -> Result<Metadata> {
         self.file(path).map(|f| Metadata { len: f.size as u64 })
     }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn files(&self) -> Option<Vec<&str>> {
+        Some(self.files.keys().map(|s| s.as_str()).collect())
+    }
+
+    fn manifest_entry(&self, filter: &dyn Fn(&str) -> bool) -> ManifestEntry {
+        let mut files: Vec<_> = self.files.iter()
+            .filter(|(path, _)| filter(&path.replace('\\', "/")))
+            .collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        let mut hash = Fnv64::new();
+        for (path, f) in files {
+            hash.write(path.as_bytes());
+            hash.write_u64(f.offset as u64);
+            hash.write_u64(f.size as u64);
+            hash.write_u64(f.compressed_size as u64);
+        }
+        ManifestEntry {
+            name: self.path.file_name().unwrap().to_string_lossy().into_owned(),
+            hash: hash.finish(),
+        }
+    }
 }
 
 fn read_path<R: Read>(r: &mut R) -> Result<String> {
@@ -0,0 +1,144 @@
+//! Lookup of the game install dirs and of the files in them regardless of the file name case.
+
+use log::*;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::asset::Game;
+
+/// Value of the resource dir argument that requests the install dir detection.
+pub const AUTO: &str = "auto";
+
+/// Splits the resource dir argument into dirs in priority order. `auto` entries are replaced
+/// with the detected install dir.
+pub fn resource_dirs(arg: &str, game: Game) -> Vec<PathBuf> {
+    let mut r = Vec::new();
+    for dir in env::split_paths(arg) {
+        if dir.as_os_str() == AUTO {
+            if let Some(dir) = detect(game) {
+                info!("Detected {:?} install at {}", game, dir.display());
+                r.push(dir);
+            } else {
+                warn!("couldn't detect {:?} install dir", game);
+            }
+        } else if !dir.as_os_str().is_empty() {
+            r.push(dir);
+        }
+    }
+    r
+}
+
+/// Returns the first of the common install locations that contains the game data.
+pub fn detect(game: Game) -> Option<PathBuf> {
+    install_dirs(game).into_iter()
+        .find(|dir| find_file(dir, "master.dat").is_some())
+}
+
+/// Returns the common Steam and GOG install locations for the current OS.
+pub fn install_dirs(game: Game) -> Vec<PathBuf> {
+    let name = match game {
+        Game::Fallout1 => "Fallout",
+        Game::Fallout2 => "Fallout 2",
+    };
+    let mut bases: Vec<PathBuf> = Vec::new();
+    if cfg!(windows) {
+        for var in &["ProgramFiles(x86)", "ProgramFiles"] {
+            if let Some(dir) = env::var_os(var) {
+                let dir = PathBuf::from(dir);
+                bases.push(dir.join("Steam").join("steamapps").join("common"));
+                bases.push(dir.join("GOG Galaxy").join("Games"));
+                bases.push(dir.join("GOG.com"));
+            }
+        }
+        bases.push(PathBuf::from("C:\\GOG Games"));
+    } else if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        if cfg!(target_os = "macos") {
+            bases.push(home.join("Library/Application Support/Steam/steamapps/common"));
+        } else {
+            bases.push(home.join(".steam/steam/steamapps/common"));
+            bases.push(home.join(".local/share/Steam/steamapps/common"));
+            bases.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam/\
+                steamapps/common"));
+        }
+        bases.push(home.join("GOG Games"));
+        bases.push(home.join(".wine/drive_c/GOG Games"));
+        bases.push(home.join(".wine/drive_c/Program Files (x86)/Steam/steamapps/common"));
+    }
+    bases.into_iter().map(|d| d.join(name)).collect()
+}
+
+/// Returns path of the file or dir `name` in `dir` matching the name case-insensitively.
+/// The exact match is preferred.
+pub fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let exact = dir.join(name);
+    if exact.exists() {
+        return Some(exact);
+    }
+    let name = name.to_lowercase();
+    fs::read_dir(dir).ok()?
+        .flatten()
+        .find(|e| e.file_name().to_string_lossy().to_lowercase() == name)
+        .map(|e| e.path())
+}
+
+/// Returns the `patch*.dat` files in `dir` from the highest priority: numbered patches in
+/// descending numeric order (`patch001.dat` overrides `patch000.dat`) followed by the rest in
+/// name order.
+pub fn patch_files(dir: &Path) -> Vec<PathBuf> {
+    let mut r: Vec<_> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_lowercase();
+            let stem = name.strip_prefix("patch")?.strip_suffix(".dat")?;
+            let num = stem.parse::<u32>().ok();
+            Some((num, name, e.path()))
+        })
+        .collect();
+    // None orders before Some so reversing the numbers puts unnumbered patches last.
+    r.sort_by(|(n1, s1, _), (n2, s2, _)| n2.cmp(n1).then_with(|| s1.cmp(s2)));
+    r.into_iter().map(|(_, _, p)| p).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_file_() {
+        let root = env::temp_dir().join(format!("vault13_detect_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("DATA")).unwrap();
+        fs::write(root.join("MASTER.DAT"), b"").unwrap();
+
+        assert_eq!(find_file(&root, "master.dat"), Some(root.join("MASTER.DAT")));
+        assert_eq!(find_file(&root, "data"), Some(root.join("DATA")));
+        assert_eq!(find_file(&root, "critter.dat"), None);
+
+        for name in &["patch000.dat", "PATCH001.DAT", "patch010.dat", "patch_mod.dat",
+            "patch.txt"]
+        {
+            fs::write(root.join(name), b"").unwrap();
+        }
+        assert_eq!(patch_files(&root), vec![root.join("patch010.dat"), root.join("PATCH001.DAT"),
+            root.join("patch000.dat"), root.join("patch_mod.dat")]);
+
+        let arg = env::join_paths(&[root.join("a"), root.join("b")]).unwrap();
+        assert_eq!(resource_dirs(arg.to_str().unwrap(), Game::Fallout2),
+            vec![root.join("a"), root.join("b")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn install_dirs_() {
+        for dir in install_dirs(Game::Fallout1) {
+            assert!(dir.ends_with("Fallout"));
+        }
+        for dir in install_dirs(Game::Fallout2) {
+            assert!(dir.ends_with("Fallout 2"));
+        }
+    }
+}
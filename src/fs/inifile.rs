@@ -5,20 +5,23 @@ use std::{
 };
 
 use ini::Ini;
-use log::debug;
+use log::{debug, warn};
 
 use super::{Metadata, PropertiesProvider};
 
-const KNOWN_CONFIG_FILES: &'static [&'static str] = &["fallout2.cfg", "f2_res.ini", "ddraw.ini"];
+const KNOWN_CONFIG_FILES: &'static [&'static str] =
+    &["fallout.cfg", "fallout2.cfg", "f2_res.ini", "ddraw.ini"];
 
 pub fn new_provider<P: AsRef<Path>>(path: P) -> Result<Box<dyn PropertiesProvider>> {
     Ok(Box::new(Inifile::new(path)?))
 }
 
+/// Config file provider. Serves the single file at `path`. The requested paths are resolved
+/// relative to the directory of the file.
 #[derive(Debug)]
 struct Inifile {
-    name: String,
-    file_path: Box<PathBuf>,
+    root: PathBuf,
+    path: PathBuf,
     properties: Ini,
     state: State,
 }
@@ -32,69 +35,109 @@ enum State {
 
 impl Inifile {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid config file name: {}", path.display()),
+                )
+            })?
+            .trim()
+            .to_ascii_lowercase();
         let mut result = Inifile {
-            name: path
-                .as_ref()
-                .file_name()
-                .unwrap()
-                .to_os_string()
-                .into_string()
-                .unwrap()
-                .trim()
-                .to_string(),
-            file_path: Box::new(PathBuf::new()),
+            root: path.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+            path: path.to_path_buf(),
             properties: Ini::new(),
             state: State::Missing,
         };
-        if File::open(path.as_ref()).is_ok()
-            && KNOWN_CONFIG_FILES.contains(
-                &path
-                    .as_ref()
-                    .file_name()
-                    .unwrap()
-                    .to_os_string()
-                    .into_string()
-                    .unwrap()
-                    .as_str(),
-            )
-        {
-            debug!("loading ini file {}", path.as_ref().display());
-            result.file_path.push(path);
-            result.properties = Ini::read_from(&mut BufReader::new(
-                File::open(result.file_path.as_ref()).unwrap(),
-            ))
-            .unwrap_or(result.properties);
-            result.state = State::Found;
+        if !KNOWN_CONFIG_FILES.contains(&name.as_str()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown config file: {}", path.display()),
+            ));
         }
-        return Ok(result);
+        match File::open(path) {
+            Ok(file) => {
+                debug!("loading ini file {}", path.display());
+                match Ini::read_from(&mut BufReader::new(file)) {
+                    Ok(properties) => {
+                        result.properties = properties;
+                        result.state = State::Found;
+                    }
+                    Err(e) => {
+                        warn!("couldn't parse {}: {}", path.display(), e);
+                        result.state = State::Err;
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(result)
     }
 
-    fn file(&self) -> Result<&Ini> {
+    /// Resolves `path` relative to the root.
+    fn resolve(&self, path: &str) -> PathBuf {
+        let mut r = self.root.clone();
+        for s in path.split(|c| c == '/' || c == '\\') {
+            r.push(s);
+        }
+        r
+    }
+
+    /// Whether the requested `path` refers to this file. The comparison is case-insensitive
+    /// since the config files come from a case-insensitive file system.
+    fn serves(&self, path: &str) -> bool {
+        let path = self.resolve(path);
+        path.to_string_lossy().to_lowercase() == self.path.to_string_lossy().to_lowercase()
+    }
+
+    fn file(&self, path: &str) -> Result<&Ini> {
+        if !self.serves(path) {
+            return Err(Error::new(ErrorKind::NotFound, "file not found"));
+        }
         match self.state {
-            State::Found => return Ok(&self.properties),
-            State::Missing => return Err(Error::new(ErrorKind::NotFound, "file not found")),
-            State::Err => return Err(Error::new(ErrorKind::NotFound, "file not found")),
+            State::Found => Ok(&self.properties),
+            State::Missing => Err(Error::new(ErrorKind::NotFound, "file not found")),
+            State::Err => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("couldn't parse {}", self.path.display()),
+            )),
         }
     }
 }
 
 impl PropertiesProvider for Inifile {
     fn reader(&self, path: &str) -> Result<Box<&Ini>> {
-        if path.contains(self.name.as_str()) {
-            return Ok(Box::new(&self.properties));
+        self.file(path).map(Box::new)
+    }
+
+    fn metadata(&self, path: &str) -> Result<Metadata> {
+        self.file(path)?;
+        let len = self.path.metadata()?.len();
+        Ok(Metadata { len })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sections(&self) -> Vec<&str> {
+        if let State::Found = self.state {
+            self.properties.sections().flatten().collect()
         } else {
-            return Err(Error::new(ErrorKind::NotFound, "can't handle file"));
+            Vec::new()
         }
     }
 
-    fn metadata(&self, path: &str) -> Result<Metadata> {
-        // let len = self.to_fs_path(path).metadata()?.len();
-        if path.contains(self.name.as_str()) {
-            Ok(Metadata {
-                len: self.properties.len() as u64,
-            })
+    fn get(&self, section: Option<&str>, key: &str) -> Option<&str> {
+        if let State::Found = self.state {
+            self.properties.get_from(section, key)
         } else {
-            Err(Error::new(ErrorKind::InvalidInput, "can't handle file"))
+            None
         }
     }
 }
@@ -129,3 +172,38 @@ pub fn build_normalized_path(path: &mut String, c: Option<char>) {
         path.remove(l - 2);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn provider() {
+        let root = std::env::temp_dir()
+            .join(format!("vault13_inifile_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let content = "[system]\nlanguage=english\n[preferences]\nsubtitles=1\n";
+        fs::write(root.join("fallout2.cfg"), content).unwrap();
+
+        let p = new_provider(root.join("fallout2.cfg")).unwrap();
+        assert!(p.reader("fallout2.cfg").is_ok());
+        assert!(p.reader("FALLOUT2.CFG").is_ok());
+        assert_eq!(p.reader("f2_res.ini").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(p.reader("data/fallout2.cfg").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(p.metadata("fallout2.cfg").unwrap().len(), content.len() as u64);
+        assert_eq!(p.sections(), vec!["system", "preferences"]);
+        assert_eq!(p.get(Some("system"), "language"), Some("english"));
+        assert_eq!(p.get(Some("system"), "subtitles"), None);
+
+        let p = new_provider(root.join("f2_res.ini")).unwrap();
+        assert_eq!(p.reader("f2_res.ini").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(p.metadata("f2_res.ini").unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(p.sections().is_empty());
+
+        assert!(new_provider(root.join("foo.ini")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
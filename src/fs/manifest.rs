@@ -0,0 +1,187 @@
+//! Manifest of the resource providers (DAT files, data dirs) the game was started with.
+//! Stored in saves so that loading a save under a different set of mods can be detected.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::*;
+use std::io::{self, Error, ErrorKind, prelude::*};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestEntry {
+    /// Provider name, for example `patch000.dat` or `data`.
+    pub name: String,
+    /// Fingerprint of the provider content.
+    pub hash: u64,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    /// Entries in the provider priority order, highest first.
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    /// Provider was present when the save was made but is missing now.
+    Missing(ManifestEntry),
+    /// Provider is present now but wasn't when the save was made.
+    Added(ManifestEntry),
+    /// Provider is present in both but its content differs.
+    Changed {
+        name: String,
+        saved_hash: u64,
+        current_hash: u64,
+    },
+    /// Same providers but in different priority order.
+    Reordered,
+}
+
+impl Manifest {
+    pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let count = rd.read_u32::<BigEndian>()?;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = rd.read_u16::<BigEndian>()?;
+            let mut name = vec![0; len as usize];
+            rd.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "malformed manifest entry name"))?;
+            let hash = rd.read_u64::<BigEndian>()?;
+            entries.push(ManifestEntry { name, hash });
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn write(&self, wr: &mut impl Write) -> io::Result<()> {
+        wr.write_u32::<BigEndian>(self.entries.len() as u32)?;
+        for e in &self.entries {
+            let name = e.name.as_bytes();
+            if name.len() > u16::MAX as usize {
+                return Err(Error::new(ErrorKind::InvalidInput, "manifest entry name too long"));
+            }
+            wr.write_u16::<BigEndian>(name.len() as u16)?;
+            wr.write_all(name)?;
+            wr.write_u64::<BigEndian>(e.hash)?;
+        }
+        Ok(())
+    }
+
+    /// Compares manifest stored in a save (`self`) to the `current` one.
+    pub fn diff(&self, current: &Manifest) -> Vec<Mismatch> {
+        let mut r = Vec::new();
+        for saved in &self.entries {
+            match current.entries.iter().find(|e| e.name == saved.name) {
+                Some(cur) if cur.hash != saved.hash => r.push(Mismatch::Changed {
+                    name: saved.name.clone(),
+                    saved_hash: saved.hash,
+                    current_hash: cur.hash,
+                }),
+                Some(_) => {}
+                None => r.push(Mismatch::Missing(saved.clone())),
+            }
+        }
+        for cur in &current.entries {
+            if !self.entries.iter().any(|e| e.name == cur.name) {
+                r.push(Mismatch::Added(cur.clone()));
+            }
+        }
+        if r.is_empty() && self.entries != current.entries {
+            r.push(Mismatch::Reordered);
+        }
+        r
+    }
+
+    /// Logs a warning with details for every mismatch between the manifest stored in a save
+    /// (`self`) and the `current` one. Returns `true` if the manifests match.
+    pub fn check(&self, current: &Manifest) -> bool {
+        let diff = self.diff(current);
+        if diff.is_empty() {
+            return true;
+        }
+        warn!("the save was made with a different set of mods, \
+            the game may behave incorrectly:");
+        for m in &diff {
+            match m {
+                Mismatch::Missing(e) => warn!("  missing: {} ({:016x})", e.name, e.hash),
+                Mismatch::Added(e) => warn!("  added: {} ({:016x})", e.name, e.hash),
+                Mismatch::Changed { name, saved_hash, current_hash } =>
+                    warn!("  changed: {} ({:016x} -> {:016x})", name, saved_hash, current_hash),
+                Mismatch::Reordered => warn!("  load order changed"),
+            }
+        }
+        false
+    }
+}
+
+/// 64-bit FNV-1a hasher. Used instead of `DefaultHasher` because the result must be stable
+/// across builds.
+pub struct Fnv64(u64);
+
+impl Fnv64 {
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_be_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn entry(name: &str, hash: u64) -> ManifestEntry {
+        ManifestEntry { name: name.into(), hash }
+    }
+
+    #[test]
+    fn read_write() {
+        let m = Manifest {
+            entries: vec![entry("patch000.dat", 1), entry("master.dat", 0xffff_0000_1234_5678)],
+        };
+        let mut buf = Vec::new();
+        m.write(&mut buf).unwrap();
+        assert_eq!(Manifest::read(&mut Cursor::new(buf)).unwrap(), m);
+    }
+
+    #[test]
+    fn diff() {
+        let saved = Manifest {
+            entries: vec![entry("mod.dat", 1), entry("data", 2), entry("master.dat", 3)],
+        };
+        assert!(saved.diff(&saved).is_empty());
+
+        let current = Manifest {
+            entries: vec![entry("data", 5), entry("master.dat", 3), entry("other.dat", 4)],
+        };
+        assert_eq!(saved.diff(&current), vec![
+            Mismatch::Missing(entry("mod.dat", 1)),
+            Mismatch::Changed { name: "data".into(), saved_hash: 2, current_hash: 5 },
+            Mismatch::Added(entry("other.dat", 4)),
+        ]);
+
+        let reordered = Manifest {
+            entries: vec![entry("data", 2), entry("mod.dat", 1), entry("master.dat", 3)],
+        };
+        assert_eq!(saved.diff(&reordered), vec![Mismatch::Reordered]);
+    }
+
+    #[test]
+    fn fnv64() {
+        let mut h = Fnv64::new();
+        h.write(b"a");
+        assert_eq!(h.finish(), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -1,8 +1,11 @@
-use std::fs::File;
+use log::*;
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Result};
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 use super::{Metadata, Provider};
+use super::manifest::{Fnv64, ManifestEntry};
 
 pub fn new_provider<P: AsRef<Path>>(path: P) -> Result<Box<dyn Provider>> {
     Ok(Box::new(StdFileSystem::new(path)))
@@ -27,6 +30,29 @@ impl StdFileSystem {
         }
         r
     }
+
+    /// Fingerprints names, sizes and modification times of the files passing the `filter`.
+    fn hash_dir(dir: &Path, rel: &str, filter: &dyn Fn(&str) -> bool, hash: &mut Fnv64)
+        -> Result<()>
+    {
+        let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            let rel = if rel.is_empty() { name } else { format!("{}/{}", rel, name) };
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                Self::hash_dir(&entry.path(), &rel, filter, hash)?;
+            } else if filter(&rel) {
+                let mtime = meta.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
+                hash.write(rel.as_bytes());
+                hash.write_u64(meta.len());
+                hash.write_u64(mtime.as_secs());
+                hash.write_u64(mtime.subsec_nanos() as u64);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Provider for StdFileSystem {
@@ -38,4 +64,51 @@ impl Provider for StdFileSystem {
         let len = self.to_fs_path(path).metadata()?.len();
         Ok(Metadata { len })
     }
+
+    fn path(&self) -> &Path {
+        &self.root
+    }
+
+    fn manifest_entry(&self, filter: &dyn Fn(&str) -> bool) -> ManifestEntry {
+        let mut hash = Fnv64::new();
+        if let Err(e) = Self::hash_dir(&self.root, "", filter, &mut hash) {
+            warn!("error computing manifest hash of {}: {}", self.root.display(), e);
+        }
+        ManifestEntry {
+            name: self.root.file_name()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.root.display().to_string()),
+            hash: hash.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_entry_filter() {
+        let root = std::env::temp_dir()
+            .join(format!("vault13_stdfs_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("Proto")).unwrap();
+        fs::create_dir_all(root.join("art")).unwrap();
+        fs::write(root.join("Proto").join("items.lst"), b"1").unwrap();
+        fs::write(root.join("art").join("a.frm"), b"1").unwrap();
+
+        let p = StdFileSystem::new(&root);
+        let proto = |p: &StdFileSystem| p.manifest_entry(&|path| path.starts_with("proto/"));
+        let before = proto(&p);
+        let all = p.manifest_entry(&|_| true);
+
+        fs::write(root.join("art").join("a.frm"), b"12").unwrap();
+        assert_eq!(proto(&p), before);
+        assert_ne!(p.manifest_entry(&|_| true), all);
+
+        fs::write(root.join("Proto").join("items.lst"), b"12").unwrap();
+        assert_ne!(proto(&p), before);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
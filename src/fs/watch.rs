@@ -0,0 +1,173 @@
+//! Change detection for loose resource directories. Used for hot reloading of assets during
+//! development. Directories are polled for file modification times so this works the same on
+//! all platforms without extra dependencies. The polling runs on a background thread so the
+//! directory walk doesn't stall the frames.
+
+use log::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Converts resource `path` to the form returned by `Watcher::poll()`: lower case with `/`
+/// separators.
+pub fn normalize(path: &str) -> String {
+    path.to_lowercase().replace('\\', "/")
+}
+
+pub struct Watcher {
+    root: PathBuf,
+    changes: Receiver<Vec<String>>,
+}
+
+impl Watcher {
+    /// Starts watching `root`, checking it for changes every `interval`. The watching stops once
+    /// the watcher is dropped and the next change is found.
+    pub fn new(root: impl AsRef<Path>, interval: Duration) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let (tx, changes) = mpsc::channel();
+        let mut scanner = Scanner::new(root.clone());
+        thread::Builder::new()
+            .name("watch".into())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let changed = scanner.poll();
+                if !changed.is_empty() && tx.send(changed).is_err() {
+                    break;
+                }
+            })
+            .unwrap();
+        Self {
+            root,
+            changes,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns normalized paths of the files that were added, modified or removed since the
+    /// last call. The paths are sorted. Doesn't block.
+    pub fn poll(&self) -> Vec<String> {
+        let mut r: Vec<_> = self.changes.try_iter().flatten().collect();
+        r.sort();
+        r.dedup();
+        r
+    }
+}
+
+/// Walks the directory tree and compares the file modification times to the ones of the
+/// previous walk.
+struct Scanner {
+    root: PathBuf,
+    /// Modification times of the files keyed by the normalized path relative to the `root`.
+    mtimes: HashMap<String, SystemTime>,
+}
+
+impl Scanner {
+    fn new(root: PathBuf) -> Self {
+        let mtimes = Self::scan(&root);
+        Self {
+            root,
+            mtimes,
+        }
+    }
+
+    fn poll(&mut self) -> Vec<String> {
+        let mtimes = Self::scan(&self.root);
+        let mut r: Vec<_> = mtimes.iter()
+            .filter(|&(path, mtime)| self.mtimes.get(path) != Some(mtime))
+            .map(|(path, _)| path.clone())
+            .chain(self.mtimes.keys().filter(|p| !mtimes.contains_key(*p)).cloned())
+            .collect();
+        r.sort();
+        self.mtimes = mtimes;
+        r
+    }
+
+    fn scan(root: &Path) -> HashMap<String, SystemTime> {
+        let mut r = HashMap::new();
+        if let Err(e) = Self::scan_dir(root, "", &mut r) {
+            warn!("error scanning {}: {}", root.display(), e);
+        }
+        r
+    }
+
+    fn scan_dir(dir: &Path, rel: &str, mtimes: &mut HashMap<String, SystemTime>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_lowercase();
+            let rel = if rel.is_empty() { name } else { format!("{}/{}", rel, name) };
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                Self::scan_dir(&entry.path(), &rel, mtimes)?;
+            } else {
+                mtimes.insert(rel, meta.modified()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn normalize_() {
+        assert_eq!(normalize("ART\\Critters\\HMJMPSAA.FRM"), "art/critters/hmjmpsaa.frm");
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let root = std::env::temp_dir()
+            .join(format!("vault13_watch_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn scanner_poll() {
+        let root = temp_dir("scanner");
+        fs::create_dir_all(root.join("Art")).unwrap();
+        fs::write(root.join("Art").join("a.frm"), b"1").unwrap();
+        fs::write(root.join("b.msg"), b"1").unwrap();
+
+        let mut w = Scanner::new(root.clone());
+        assert!(w.poll().is_empty());
+
+        let mtime = SystemTime::now() + Duration::from_secs(10);
+        w.mtimes.insert("b.msg".into(), mtime);
+        fs::write(root.join("c.pro"), b"1").unwrap();
+        fs::remove_file(root.join("Art").join("a.frm")).unwrap();
+        assert_eq!(w.poll(), vec!["art/a.frm", "b.msg", "c.pro"]);
+        assert!(w.poll().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn watcher_poll() {
+        let root = temp_dir("watcher");
+        let w = Watcher::new(&root, Duration::from_millis(10));
+        assert!(w.poll().is_empty());
+
+        fs::write(root.join("a.pro"), b"1").unwrap();
+        let start = Instant::now();
+        let mut changed = Vec::new();
+        while changed.is_empty() && start.elapsed() < Duration::from_secs(10) {
+            thread::sleep(Duration::from_millis(10));
+            changed = w.poll();
+        }
+        assert_eq!(changed, vec!["a.pro"]);
+
+        // The removal is the last change the thread sees.
+        drop(w);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
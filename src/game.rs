@@ -1,18 +1,46 @@
+pub mod ambient_sfx;
+pub mod attack;
+pub mod automap;
+pub mod barter;
+pub mod benchmark;
+pub mod car;
+pub mod check;
+pub mod combat;
+pub mod console;
+pub mod crash;
+pub mod death;
 pub mod dialog;
+pub mod drug;
+pub mod explosive;
 pub mod fidget;
+pub mod headless;
+pub mod inspector;
 pub mod inventory;
+pub mod loot;
+pub mod map_state;
+pub mod mods;
 pub mod object;
+pub mod pipboy;
+pub mod radiation;
+pub mod reaction;
+pub mod render_map;
+pub mod rng;
 pub mod rpg;
 pub mod script;
+pub mod script_debugger;
+pub mod sfx;
 pub mod sequence;
 pub mod skilldex;
 pub mod state;
+pub mod team;
+pub mod timer;
 pub mod ui;
+pub mod wander;
 pub mod world;
 
-use crate::util::random::RollChecker;
+use crate::game::rng::{RollChecker, Stream};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct GameTime(u32);
 
 impl GameTime {
@@ -24,6 +52,11 @@ impl GameTime {
         self.0
     }
 
+    #[must_use]
+    pub fn add_decis(self, decis: u32) -> Self {
+        Self(self.0.saturating_add(decis))
+    }
+
     pub fn as_seconds(self) -> u32 {
         self.0 / 10
     }
@@ -64,8 +97,8 @@ impl GameTime {
         (self.as_decis() % 10) as u8
     }
 
-    pub fn roll_checker(self) -> RollChecker {
-        RollChecker::new(self.as_hours() >= 1)
+    pub fn roll_checker(self, stream: Stream) -> RollChecker {
+        RollChecker::new(self.as_hours() >= 1, stream)
     }
 
     fn ydm(self) -> (u16, u8, u8) {
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use crate::game::rng::{random, Stream};
+use crate::game::sfx::Sfx;
+use crate::game::world::World;
+use crate::graphics::EPoint;
+use crate::graphics::geometry::TileGridView;
+use crate::graphics::geometry::hex::Direction;
+use crate::util::EnumExt;
+
+/// Max distance in hexes from the camera center where the ambient sound can be placed.
+const MAX_DISTANCE: u32 = 16;
+
+/// Plays the map's ambient sound effects (wind, birds, machinery) at random intervals.
+pub struct AmbientSfx {
+    /// Sound effect names and their relative chances as defined in `data/maps.txt`.
+    sounds: Vec<(String, u32)>,
+    next_time: Instant,
+}
+
+impl AmbientSfx {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            sounds: Vec::new(),
+            next_time: now + Self::next_delay(),
+        }
+    }
+
+    /// Replaces the sounds with the ones of the newly entered map.
+    pub fn reset(&mut self, sounds: Vec<(String, u32)>, now: Instant) {
+        self.sounds = sounds;
+        self.next_time = now + Self::next_delay();
+    }
+
+    // gsound_sfx_q_process()
+    pub fn update(&mut self, time: Instant, world: &World, sfx: &mut Sfx) {
+        if time < self.next_time {
+            return;
+        }
+        self.next_time = time + Self::next_delay();
+
+        let total = self.sounds.iter().map(|&(_, c)| c).sum::<u32>();
+        let roll = random(Stream::Misc, 0, total as i32) as u32;
+        let name = if let Some(i) = roll_sound(&self.sounds, roll) {
+            &self.sounds[i].0
+        } else {
+            return;
+        };
+
+        let camera = world.camera();
+        let center = camera.hex().screen_to_tile(camera.viewport.center());
+        let direction = Direction::from_ordinal(
+            random(Stream::Misc, 0, Direction::len() as i32 - 1) as usize);
+        let distance = random(Stream::Misc, 0, MAX_DISTANCE as i32) as u32;
+        let pos = world.hex_grid().go_clipped(center, direction, distance);
+
+        sfx.play(name, Some(EPoint::new(world.elevation(), pos)));
+    }
+
+    fn next_delay() -> Duration {
+        Duration::from_secs(random(Stream::Misc, 15, 20) as u64)
+    }
+}
+
+// wmSfxRollNextIdx()
+/// Picks index of the sound in `sounds` hit by the `roll` in range `[0, total chance]`.
+fn roll_sound(sounds: &[(String, u32)], roll: u32) -> Option<usize> {
+    if sounds.is_empty() {
+        return None;
+    }
+    let mut roll = roll as i64;
+    for (i, &(_, chance)) in sounds.iter().enumerate() {
+        roll -= chance as i64;
+        if roll <= 0 {
+            return Some(i);
+        }
+    }
+    Some(sounds.len() - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roll_sound_() {
+        let sounds = vec![("gustwind".into(), 20), ("gustwin1".into(), 5)];
+        assert_eq!(roll_sound(&[], 0), None);
+        assert_eq!(roll_sound(&sounds, 0), Some(0));
+        assert_eq!(roll_sound(&sounds, 20), Some(0));
+        assert_eq!(roll_sound(&sounds, 21), Some(1));
+        assert_eq!(roll_sound(&sounds, 25), Some(1));
+        assert_eq!(roll_sound(&sounds, 100), Some(1));
+    }
+}
@@ -0,0 +1,186 @@
+use std::cmp;
+
+use crate::asset::{AttackGroup, AttackKind, CritterAnim, DamageKind, FlagExt, Skill, Stat,
+    WeaponKind};
+use crate::game::object::{Handle, Object, Objects};
+use crate::game::rng::{random, Stream};
+use crate::game::rpg::Rpg;
+
+/// Attack performed by a critter with the weapon in its active hand or unarmed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Attack {
+    pub weapon: Option<Handle>,
+    pub weapon_kind: WeaponKind,
+    pub group: AttackGroup,
+    pub kind: AttackKind,
+}
+
+impl Attack {
+    // item_hit_with
+    pub fn new(attacker: &Object, group: AttackGroup, objects: &Objects) -> Self {
+        if let Some(weapon) = attacker.active_weapon(objects) {
+            let weapono = objects.get(weapon);
+            let proto = weapono.proto().unwrap();
+            let w = proto.sub.as_weapon().unwrap();
+            Self {
+                weapon: Some(weapon),
+                weapon_kind: w.kind,
+                group,
+                kind: w.attack_kinds[group],
+            }
+        } else {
+            Self {
+                weapon: None,
+                weapon_kind: WeaponKind::Unarmed,
+                group,
+                kind: match group {
+                    AttackGroup::Primary => AttackKind::Punch,
+                    AttackGroup::Secondary => AttackKind::Kick,
+                },
+            }
+        }
+    }
+
+    // item_w_anim
+    pub fn anim(self) -> CritterAnim {
+        use AttackKind::*;
+        match self.kind {
+            Stand => CritterAnim::Stand,
+            Punch => CritterAnim::ThrowPunch,
+            Kick => CritterAnim::KickLeg,
+            Swing => CritterAnim::SwingAnim,
+            Thrust => CritterAnim::ThrustAnim,
+            Throw => CritterAnim::ThrowAnim,
+            FireSingle => CritterAnim::FireSingle,
+            FireBurst => CritterAnim::FireBurst,
+            FireContinuous => CritterAnim::FireContinuous,
+        }
+    }
+
+    // item_w_mp_cost
+    /// Action points the attack costs in combat.
+    pub fn action_points(self, objects: &Objects) -> i32 {
+        // TODO perks and traits affecting the cost, aimed attacks.
+        self.weapon
+            .and_then(|w| objects.get(w).proto()
+                .and_then(|p| p.sub.as_weapon().map(|w| w.ap_costs[self.group])))
+            .unwrap_or(3)
+    }
+
+    // item_w_range
+    /// Max distance in hexes to the target. Throwing range is limited by the attacker's Strength.
+    pub fn range(self, attacker: &Object, objects: &Objects, rpg: &Rpg) -> u32 {
+        let range = self.weapon
+            .and_then(|w| objects.get(w).proto()
+                .and_then(|p| p.sub.as_weapon().map(|w| w.max_ranges[self.group])))
+            .unwrap_or(1);
+        let range = if self.kind == AttackKind::Throw {
+            // TODO Heave Ho! perk.
+            range.min(3 * rpg.stat(Stat::Strength, attacker, objects))
+        } else {
+            range
+        };
+        range.max(1) as u32
+    }
+
+    // item_w_damage_type
+    pub fn damage_kind(self, objects: &Objects) -> DamageKind {
+        self.weapon
+            .and_then(|w| objects.get(w).proto()
+                .and_then(|p| p.sub.as_weapon().map(|w| w.damage_kind)))
+            .unwrap_or(DamageKind::Melee)
+    }
+
+    // item_w_skill
+    /// Skill that decides whether the attack hits.
+    pub fn skill(self, objects: &Objects) -> Skill {
+        use AttackKind::*;
+        match self.kind {
+            Stand | Punch | Kick => Skill::Unarmed,
+            Swing | Thrust => Skill::MeleeWeapons,
+            Throw => Skill::Throwing,
+            FireSingle | FireBurst | FireContinuous => {
+                let big_gun = self.weapon
+                    .and_then(|w| objects.get(w).proto()
+                        .map(|p| p.flags_ext.contains(FlagExt::BigGun)))
+                    .unwrap_or(false);
+                match self.damage_kind(objects) {
+                    _ if big_gun => Skill::BigGuns,
+                    DamageKind::Laser | DamageKind::Plasma | DamageKind::Electric
+                        => Skill::EnergyWeapons,
+                    _ => Skill::SmallGuns,
+                }
+            }
+        }
+    }
+
+    // item_w_damage
+    /// Rolls the damage of the attack before it's reduced by the target's armor. Melee attacks
+    /// add the attacker's Melee Damage.
+    pub fn roll_damage(self, attacker: &Object, objects: &Objects, rpg: &Rpg) -> i32 {
+        let melee_dmg = rpg.stat(Stat::MeleeDmg, attacker, objects);
+        let weapon_damage = self.weapon
+            .and_then(|w| objects.get(w).proto()
+                .and_then(|p| p.sub.as_weapon().map(|w| w.damage.clone())));
+        let (min, max) = match weapon_damage {
+            Some(damage) => {
+                let melee = self.kind.category().is_melee();
+                (*damage.start(), *damage.end() + if melee { melee_dmg } else { 0 })
+            }
+            None => (1, melee_dmg + 2),
+        };
+        random(Stream::Combat, min, cmp::max(min, max))
+    }
+}
+
+/// Returns `damage` of `kind` reduced by the critter's damage threshold and resistance.
+pub fn critter_damage(damage: i32, kind: DamageKind, critter: &Object, objs: &Objects, rpg: &Rpg)
+    -> i32
+{
+    let threshold = kind.thresh_stat().map(|s| rpg.stat(s, critter, objs)).unwrap_or(0);
+    let resistance = rpg.stat(kind.resist_stat(), critter, objs);
+    reduce_damage(damage, threshold, resistance)
+}
+
+fn reduce_damage(damage: i32, threshold: i32, resistance: i32) -> i32 {
+    cmp::max(damage - threshold, 0) * (100 - resistance) / 100
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::asset::frame::FrameId;
+    use crate::game::world::World;
+    use crate::graphics::{EPoint, Point};
+
+    #[test]
+    fn unarmed() {
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+        let fid = FrameId::new_critter(None, CritterAnim::Stand, WeaponKind::Unarmed, 1).unwrap();
+        let pos = EPoint::new(0, Point::new(10, 10));
+        let critter = objs.create(Some(fid), None, Some(pos), None).handle();
+        let critter = objs.get(critter);
+
+        let attack = Attack::new(&critter, AttackGroup::Primary, objs);
+        assert_eq!(attack.weapon, None);
+        assert_eq!(attack.weapon_kind, WeaponKind::Unarmed);
+        assert_eq!(attack.anim(), CritterAnim::ThrowPunch);
+        assert_eq!(attack.action_points(objs), 3);
+
+        assert_eq!(attack.skill(objs), Skill::Unarmed);
+        assert_eq!(attack.damage_kind(objs), DamageKind::Melee);
+
+        let attack = Attack::new(&critter, AttackGroup::Secondary, objs);
+        assert_eq!(attack.anim(), CritterAnim::KickLeg);
+    }
+
+    #[test]
+    fn reduce_damage_() {
+        assert_eq!(reduce_damage(40, 0, 0), 40);
+        assert_eq!(reduce_damage(40, 4, 50), 18);
+        assert_eq!(reduce_damage(3, 4, 0), 0);
+    }
+}
@@ -0,0 +1,341 @@
+//! Automap: map of the current elevation with the walls and scenery the dude has seen.
+//!
+//! With a charged motion sensor in the dude's inventory the scanner also shows the living
+//! critters. The sensor uses up a charge when the scanner is switched on and then one charge for
+//! every `CHARGE_INTERVAL` of game time while the scanner stays on.
+
+use bstring::BString;
+use log::*;
+use std::time::{Duration, Instant};
+
+use crate::asset::{EntityKind, Flag};
+use crate::asset::frame::FrameId;
+use crate::asset::message::{MessageId, Messages};
+use crate::asset::proto::ProtoId;
+use crate::game::GameTime;
+use crate::game::object::{self, Objects};
+use crate::game::ui::automap_view::{AutomapView, HEX_SIZE};
+use crate::game::world::World;
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::{GREEN, RED, Rgb15, WHITE};
+use crate::graphics::sprite::Sprite;
+use crate::ui::*;
+use crate::ui::button::Button;
+use crate::ui::command::{AutomapCommand, UiCommandData};
+
+/// Game time in deciseconds the motion sensor works on one charge.
+const CHARGE_INTERVAL: u32 = 5 * 60 * 10;
+/// How often the objects around the dude are marked as seen.
+const SEEN_INTERVAL: Duration = Duration::from_millis(500);
+/// Walls and scenery within this distance from the dude are marked as seen.
+const SEEN_RADIUS: u32 = 20;
+/// Top left corner of the map area in the window.
+const MAP_POS: Point = Point::new(60, 20);
+const SCENERY_COLOR: Rgb15 = unsafe { Rgb15::rgb15_from_packed_unchecked(0x01e0) };
+
+// misc.msg
+const MSG_NO_MOTION_SENSOR: MessageId = 17;
+const MSG_NO_CHARGES: MessageId = 18;
+
+struct State {
+    window: Handle,
+    view: Handle,
+}
+
+pub struct Automap {
+    state: Option<State>,
+    /// Whether the scenery is shown in addition to the walls.
+    detail: bool,
+    /// Whether the motion sensor scanner is on.
+    scanner: bool,
+    next_charge: GameTime,
+    next_seen: Instant,
+}
+
+impl Automap {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: None,
+            detail: true,
+            scanner: false,
+            next_charge: GameTime::from_decis(0),
+            next_seen: now,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.state.is_some()
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, world: &World) {
+        assert!(self.state.is_none());
+
+        let win_size = ui.frm_db().get(FrameId::AUTOMAP).unwrap().first().size();
+        let window = ui.new_window(Rect::with_size(
+            (640 - win_size.x) / 2, (480 - win_size.y) / 2, win_size.x, win_size.y),
+            Some(Sprite::new(FrameId::AUTOMAP)));
+        ui.widget_base_mut(window).set_modal(true);
+
+        let switch_size = ui.frm_db().get(FrameId::AUTOUP).unwrap().first().size();
+        let mut new_button = |rect, up, down, cmd| {
+            ui.new_widget(window, rect, None, None,
+                Button::new(up, down, Some(UiCommandData::Automap(cmd))));
+        };
+        new_button(Rect::with_size(277, 454, 15, 16),
+            FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN, AutomapCommand::Hide);
+        new_button(Rect::with_size(111, 454, 15, 16),
+            FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN, AutomapCommand::Scanner);
+        new_button(Rect::with_size(457, 340, switch_size.x, switch_size.y),
+            FrameId::AUTOUP, FrameId::AUTODWN, AutomapCommand::Detail);
+
+        let grid = world.hex_grid();
+        let view = ui.new_widget(window, Rect::with_size(MAP_POS.x, MAP_POS.y,
+            grid.width() * HEX_SIZE, grid.height() * HEX_SIZE), None, None, AutomapView::new());
+
+        self.state = Some(State {
+            window,
+            view,
+        });
+        self.refresh(ui, world);
+    }
+
+    pub fn hide(&mut self, ui: &mut Ui) {
+        let state = self.state.take().unwrap();
+        ui.remove(state.window);
+    }
+
+    /// Returns message for the dude if there's something to say.
+    pub fn handle(&mut self,
+        command: AutomapCommand,
+        world: &World,
+        msgs: &Messages,
+        ui: &mut Ui,
+    ) -> Option<BString> {
+        let r = match command {
+            AutomapCommand::Hide => {
+                self.hide(ui);
+                return None;
+            }
+            AutomapCommand::Show => {
+                self.show(ui, world);
+                return None;
+            }
+            AutomapCommand::Scanner => if self.scanner {
+                self.scanner = false;
+                None
+            } else {
+                self.switch_scanner_on(world.game_time, world.objects(), msgs)
+            }
+            AutomapCommand::Detail => {
+                self.detail = !self.detail;
+                None
+            }
+        };
+        self.refresh(ui, world);
+        r
+    }
+
+    /// Marks the walls and scenery around the dude as seen and uses up the motion sensor charges.
+    /// Returns message for the dude if the scanner had to be switched off.
+    pub fn update(&mut self, time: Instant, world: &World, msgs: &Messages) -> Option<BString> {
+        if time >= self.next_seen {
+            self.next_seen = time + SEEN_INTERVAL;
+            mark_seen(world);
+        }
+        if self.scanner && world.game_time >= self.next_charge {
+            self.next_charge = world.game_time.add_decis(CHARGE_INTERVAL);
+            let objs = world.objects();
+            let msg_id = match motion_sensor(objs) {
+                Some(sensor) => if use_charge(sensor, objs) {
+                    return None;
+                } else {
+                    MSG_NO_CHARGES
+                }
+                None => MSG_NO_MOTION_SENSOR,
+            };
+            debug!("motion sensor scanner switched off");
+            self.scanner = false;
+            return msgs.get(msg_id).map(|m| m.text.clone());
+        }
+        None
+    }
+
+    fn switch_scanner_on(&mut self, time: GameTime, objs: &Objects, msgs: &Messages)
+        -> Option<BString>
+    {
+        let msg_id = match motion_sensor(objs) {
+            Some(sensor) => if use_charge(sensor, objs) {
+                self.scanner = true;
+                self.next_charge = time.add_decis(CHARGE_INTERVAL);
+                return None;
+            } else {
+                MSG_NO_CHARGES
+            }
+            None => MSG_NO_MOTION_SENSOR,
+        };
+        msgs.get(msg_id).map(|m| m.text.clone())
+    }
+
+    // automap_draw
+    fn refresh(&self, ui: &mut Ui, world: &World) {
+        let state = self.state.as_ref().unwrap();
+        let objs = world.objects();
+        let elevation = world.elevation();
+        let mut cells = Vec::new();
+        for h in objs.on_elevation(elevation) {
+            let obj = objs.get(h);
+            if !obj.flags.contains(Flag::Seen) || obj.flags.contains(Flag::TurnedOff) {
+                continue;
+            }
+            let color = match obj.kind() {
+                EntityKind::Wall => GREEN,
+                EntityKind::Scenery if self.detail => SCENERY_COLOR,
+                _ => continue,
+            };
+            cells.push((obj.pos().point, color));
+        }
+        if self.scanner && motion_sensor(objs).is_some() {
+            for h in world.critters().at_elevation(elevation).alive().except(objs.dude()) {
+                cells.push((objs.get(h).pos().point, RED));
+            }
+        }
+        if let Some(pos) = objs.get(objs.dude()).try_pos().filter(|p| p.elevation == elevation) {
+            cells.push((pos.point, WHITE));
+        }
+        ui.widget_mut::<AutomapView>(state.view).set_cells(cells);
+    }
+}
+
+/// Motion sensor in the dude's inventory.
+fn motion_sensor(objs: &Objects) -> Option<object::Handle> {
+    objs.get(objs.dude()).inventory.items.iter()
+        .map(|i| i.object)
+        .find(|&h| objs.get(h).proto_id() == Some(ProtoId::MOTION_SENSOR))
+}
+
+// item_m_dec_charges
+/// Takes one charge from the motion sensor. Returns `false` if the sensor is empty.
+fn use_charge(sensor: object::Handle, objs: &Objects) -> bool {
+    let mut sensoro = objs.get_mut(sensor);
+    let item = match sensoro.sub.as_item_mut() {
+        Some(v) if v.ammo_count > 0 => v,
+        _ => return false,
+    };
+    item.ammo_count -= 1;
+    debug!("motion sensor {:?} charges left: {}", sensor, item.ammo_count);
+    true
+}
+
+// obj_process_seen
+fn mark_seen(world: &World) {
+    let objs = world.objects();
+    let pos = if let Some(pos) = objs.get(objs.dude()).try_pos() {
+        pos
+    } else {
+        return;
+    };
+    let seen: Vec<_> = world.objects_on_elevation(pos.elevation)
+        .within(pos, SEEN_RADIUS)
+        .matching(|o| !o.flags.contains(Flag::Seen)
+            && matches!(o.kind(), EntityKind::Wall | EntityKind::Scenery))
+        .collect();
+    for h in seen {
+        objs.get_mut(h).flags.insert(Flag::Seen);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use enum_map::EnumMap;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::asset::proto::{MiscItem, Proto, SubItem};
+    use crate::game::rpg::Rpg;
+
+    fn msgs() -> Messages {
+        Messages::read(&mut Cursor::new(&b"{17}{}{no sensor}{18}{}{no charges}"[..])).unwrap()
+    }
+
+    fn new_world(now: Instant, charges: Option<u32>) -> World {
+        let rpg = Rpg::mock();
+        let mut world = World::mock(now);
+        let objs = world.objects_mut();
+        let dude = Proto::mock_critter(ProtoId::DUDE, EnumMap::new());
+        let dude = objs.create(None, Some(Rc::new(RefCell::new(dude))),
+            Some((0, (50, 50)).into()), Some(&rpg)).handle();
+        if let Some(charges) = charges {
+            let sensor = Proto::mock_item(ProtoId::MOTION_SENSOR, SubItem::Misc(MiscItem {
+                ammo_proto_id: None,
+                ammo_kind: 0,
+                max_ammo_count: charges,
+            }));
+            let sensor = objs.create(None, Some(Rc::new(RefCell::new(sensor))), None, None)
+                .handle();
+            objs.move_into_inventory(dude, sensor, 1);
+        }
+        world
+    }
+
+    #[test]
+    fn motion_sensor_charges() {
+        let now = Instant::now();
+        let mut world = new_world(now, Some(2));
+        let msgs = msgs();
+        let mut automap = Automap::new(now);
+        let sensor = motion_sensor(world.objects()).unwrap();
+        let charges = |world: &World| world.objects().get(sensor).sub.as_item().unwrap()
+            .ammo_count;
+
+        assert_eq!(automap.switch_scanner_on(world.game_time, world.objects(), &msgs), None);
+        assert!(automap.scanner);
+        assert_eq!(charges(&world), 1);
+
+        // Only the game time counts.
+        assert_eq!(automap.update(now + Duration::from_secs(3600), &world, &msgs), None);
+        assert_eq!(charges(&world), 1);
+
+        world.game_time = world.game_time.add_decis(CHARGE_INTERVAL);
+        assert_eq!(automap.update(now, &world, &msgs), None);
+        assert_eq!(charges(&world), 0);
+
+        world.game_time = world.game_time.add_decis(CHARGE_INTERVAL - 1);
+        assert_eq!(automap.update(now, &world, &msgs), None);
+        assert!(automap.scanner);
+
+        world.game_time = world.game_time.add_decis(1);
+        assert_eq!(automap.update(now, &world, &msgs), Some(BString::from("no charges")));
+        assert!(!automap.scanner);
+
+        assert_eq!(automap.switch_scanner_on(world.game_time, world.objects(), &msgs),
+            Some(BString::from("no charges")));
+        assert!(!automap.scanner);
+    }
+
+    #[test]
+    fn no_motion_sensor() {
+        let now = Instant::now();
+        let world = new_world(now, None);
+        let mut automap = Automap::new(now);
+        assert_eq!(automap.switch_scanner_on(world.game_time, world.objects(), &msgs()),
+            Some(BString::from("no sensor")));
+        assert!(!automap.scanner);
+    }
+
+    #[test]
+    fn mark_seen_() {
+        let now = Instant::now();
+        let mut world = new_world(now, None);
+        let near = world.mock_object(EntityKind::Wall, Some((0, (55, 50))));
+        let far = world.mock_object(EntityKind::Scenery, Some((0, (90, 90))));
+        let other_elevation = world.mock_object(EntityKind::Wall, Some((1, (50, 51))));
+
+        mark_seen(&world);
+        let seen = |h| world.objects().get(h).flags.contains(Flag::Seen);
+        assert!(seen(near));
+        assert!(!seen(far));
+        assert!(!seen(other_elevation));
+    }
+}
@@ -0,0 +1,85 @@
+//! Barter prices.
+
+use crate::asset::{Perk, Skill};
+use crate::asset::proto::ProtoId;
+use crate::game::object::{self, Objects};
+use crate::game::reaction;
+use crate::game::rpg::Rpg;
+
+/// Price reduction in percent given by the Master Trader perk.
+const MASTER_TRADER_BONUS: i32 = 25;
+
+/// Terms of a trade with a trader.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Terms {
+    /// Best Barter skill in the dude's party.
+    pub party_skill: i32,
+    pub trader_skill: i32,
+    /// Price change in percent set by the trader's script with `gdialog_set_barter_mod`.
+    pub barter_mod: i32,
+    /// Price reduction in percent for the dude's karma and perks.
+    pub discount: i32,
+}
+
+impl Terms {
+    pub fn new(
+        trader: object::Handle,
+        party: &[object::Handle],
+        barter_mod: i32,
+        global_vars: &[i32],
+        objs: &Objects,
+        rpg: &Rpg,
+    ) -> Self {
+        let dude = objs.dude();
+        let party_skill = Some(dude).into_iter()
+            .chain(party.iter().cloned())
+            .map(|h| rpg.skill(Skill::Barter, &objs.get(h), objs))
+            .max()
+            .unwrap();
+        let trader_skill = rpg.skill(Skill::Barter, &objs.get(trader), objs);
+        let master_trader = if rpg.has_perk(Perk::MasterTrader, ProtoId::DUDE) {
+            MASTER_TRADER_BONUS
+        } else {
+            0
+        };
+        let karma = reaction::karma_modifier(reaction::karma(global_vars),
+            rpg.has_perk(Perk::KarmaBeacon, ProtoId::DUDE));
+        Self {
+            party_skill,
+            trader_skill,
+            barter_mod,
+            discount: master_trader + karma,
+        }
+    }
+
+    // barter_compute_value
+    /// Price the trader sets for the goods worth `cost`, of which `caps` is money. Money is always
+    /// taken at its face value. The rest is doubled and scaled by the ratio of the Barter skills
+    /// and by the barter mod less the discount.
+    pub fn price(&self, cost: i32, caps: i32) -> i32 {
+        let mult = ((self.barter_mod + 100 - self.discount) as f64 / 100.0).max(0.01);
+        let balanced = (160.0 + self.trader_skill as f64) / (160.0 + self.party_skill as f64)
+            * ((cost - caps) as f64 * 2.0);
+        (mult * balanced) as i32 + caps
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn price() {
+        let terms = |party_skill, trader_skill, barter_mod, discount| Terms {
+            party_skill,
+            trader_skill,
+            barter_mod,
+            discount,
+        };
+        assert_eq!(terms(40, 40, 0, 0).price(100, 0), 200);
+        assert_eq!(terms(40, 40, 0, 0).price(150, 50), 250);
+        assert_eq!(terms(40, 240, 0, 0).price(100, 0), 400);
+        assert_eq!(terms(40, 40, -20, 5).price(100, 0), 150);
+        assert_eq!(terms(40, 40, -200, 0).price(100, 10), 11);
+    }
+}
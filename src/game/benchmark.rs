@@ -0,0 +1,113 @@
+//! Scripted camera tour and combat used for benchmarking. Also usable as a looping demo.
+
+use log::*;
+use std::time::{Duration, Instant};
+
+use crate::game::state::GameState;
+use crate::graphics::Point;
+use crate::ui::Ui;
+use crate::util::telemetry::FrameTimes;
+
+/// Seed of the random generator used while benchmarking.
+pub const SEED: u64 = 13;
+
+/// Hex offsets from the dude position the camera tours through.
+const TOUR: &[(i32, i32)] = &[(0, 0), (-30, -20), (30, -20), (30, 20), (-30, 20), (0, 0)];
+const TOUR_SEGMENT_DURATION: Duration = Duration::from_secs(3);
+const COMBAT_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Status {
+    Running,
+    Done,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    Tour,
+    Combat(Instant),
+}
+
+pub struct Benchmark {
+    /// Restart instead of finishing.
+    looping: bool,
+    start: Option<Instant>,
+    /// Camera origins of the tour points.
+    origins: Vec<Point>,
+    phase: Phase,
+    frame_times: FrameTimes,
+}
+
+impl Benchmark {
+    pub fn new(looping: bool) -> Self {
+        Self {
+            looping,
+            start: None,
+            origins: Vec::new(),
+            phase: Phase::Tour,
+            frame_times: FrameTimes::new(),
+        }
+    }
+
+    pub fn record_frame(&mut self, duration: Duration) {
+        self.frame_times.record(duration);
+    }
+
+    pub fn frame_times(&self) -> &FrameTimes {
+        &self.frame_times
+    }
+
+    pub fn update(&mut self, now: Instant, state: &mut GameState, ui: &mut Ui) -> Status {
+        let start = if let Some(v) = self.start {
+            v
+        } else {
+            self.begin(now, state);
+            now
+        };
+        match self.phase {
+            Phase::Tour => {
+                let elapsed = now - start;
+                let segment = (elapsed.as_millis() / TOUR_SEGMENT_DURATION.as_millis()) as usize;
+                if segment + 1 >= self.origins.len() {
+                    debug!("benchmark: starting combat");
+                    state.start_combat(ui);
+                    self.phase = Phase::Combat(now);
+                } else {
+                    let t = (elapsed.as_millis() % TOUR_SEGMENT_DURATION.as_millis()) as i32;
+                    let total = TOUR_SEGMENT_DURATION.as_millis() as i32;
+                    let from = self.origins[segment];
+                    let to = self.origins[segment + 1];
+                    state.world().borrow_mut().camera_mut().origin =
+                        from + Point::new((to.x - from.x) * t / total, (to.y - from.y) * t / total);
+                }
+            }
+            Phase::Combat(combat_start) => {
+                if now - combat_start >= COMBAT_DURATION {
+                    state.end_combat(ui);
+                    if self.looping {
+                        self.start = None;
+                        self.phase = Phase::Tour;
+                    } else {
+                        return Status::Done;
+                    }
+                } else if state.combat().is_some() {
+                    state.end_turn(ui);
+                }
+            }
+        }
+        Status::Running
+    }
+
+    fn begin(&mut self, now: Instant, state: &mut GameState) {
+        self.start = Some(now);
+        let world = state.world().borrow();
+        let dude_pos = world.objects().dude_ref().pos().point;
+        let mut camera = world.camera().clone();
+        self.origins = TOUR.iter()
+            .map(|&(x, y)| {
+                camera.look_at(world.hex_grid().clip(dude_pos + Point::new(x, y)));
+                camera.origin
+            })
+            .collect();
+    }
+}
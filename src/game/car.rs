@@ -0,0 +1,120 @@
+//! The car (Highwayman) of the party.
+//!
+//! Scripts give the car to the party with `metarule(GIVE_CAR_TO_PARTY)` and refill it with
+//! `metarule(GIVE_CAR_GAS)`, this is what the car script does when an energy cell is used on the
+//! car. On the maps the car and its trunk are ordinary objects, they stay where they're parked
+//! with the rest of the map state. Driving happens on the world map: the car moves the party
+//! `SPEED` times faster and burns `FUEL_PER_STEP` for every step.
+
+use log::*;
+
+use crate::asset::map::MapId;
+
+// CAR_FUEL_MAX
+pub const MAX_FUEL: i32 = 80000;
+/// Fuel burnt for a world map step.
+pub const FUEL_PER_STEP: i32 = 100;
+/// World map steps the car makes for every step on foot.
+pub const SPEED: u32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct Car {
+    /// Map where the car is parked. `None` if the party doesn't have the car.
+    map: Option<MapId>,
+    fuel: i32,
+    /// Trunk animation kept for the scripts.
+    trunk_anim: i32,
+}
+
+impl Car {
+    pub fn new() -> Self {
+        Self {
+            map: None,
+            fuel: MAX_FUEL,
+            trunk_anim: 0,
+        }
+    }
+
+    pub fn is_owned(&self) -> bool {
+        self.map.is_some()
+    }
+
+    pub fn map(&self) -> Option<MapId> {
+        self.map
+    }
+
+    // wmCarGiveToParty
+    /// Gives the car parked on the `map` to the party.
+    pub fn give_to_party(&mut self, map: MapId) {
+        debug!("party got the car on map {}", map);
+        self.map = Some(map);
+    }
+
+    /// Records that the party drove the car to the `map`.
+    pub fn park(&mut self, map: MapId) {
+        assert!(self.is_owned());
+        self.map = Some(map);
+    }
+
+    pub fn fuel(&self) -> i32 {
+        self.fuel
+    }
+
+    // wmCarIsOutOfGas
+    pub fn is_out_of_fuel(&self) -> bool {
+        self.fuel <= 0
+    }
+
+    // wmCarFillGas
+    /// Adds `amount` of fuel. Negative amounts drain the tank.
+    pub fn add_fuel(&mut self, amount: i32) {
+        self.fuel = (self.fuel.saturating_add(amount)).clamp(0, MAX_FUEL);
+        debug!("car fuel: {}", self.fuel);
+    }
+
+    // wmCarUseGas
+    /// Drives up to `steps` world map steps. Returns the number of steps made before the fuel ran
+    /// out.
+    pub fn drive(&mut self, steps: u32) -> u32 {
+        let r = steps.min((self.fuel.max(0) / FUEL_PER_STEP) as u32);
+        self.fuel -= r as i32 * FUEL_PER_STEP;
+        if r < steps {
+            // What's left isn't enough for another step.
+            self.fuel = 0;
+            debug!("car is out of fuel after {} of {} steps", r, steps);
+        }
+        r
+    }
+
+    pub fn trunk_anim(&self) -> i32 {
+        self.trunk_anim
+    }
+
+    pub fn set_trunk_anim(&mut self, v: i32) {
+        self.trunk_anim = v;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuel() {
+        let mut c = Car::new();
+        assert!(!c.is_owned());
+        c.add_fuel(1000);
+        assert_eq!(c.fuel(), MAX_FUEL);
+
+        c.add_fuel(-(MAX_FUEL - FUEL_PER_STEP * 2 - 50));
+        assert_eq!(c.drive(1), 1);
+        assert_eq!(c.fuel(), FUEL_PER_STEP + 50);
+        assert_eq!(c.drive(5), 1);
+        assert_eq!(c.fuel(), 0);
+        assert!(c.is_out_of_fuel());
+        assert_eq!(c.drive(1), 0);
+
+        c.add_fuel(-1);
+        assert_eq!(c.fuel(), 0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Map validation used by the `check` subcommand. Loads maps, resolves the referenced frames,
+//! prototypes and scripts and reports the problems found.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::rc::Rc;
+
+use crate::asset::EntityKind;
+use crate::asset::frame::{FrameDb, FrameId};
+use crate::asset::map::{Map, MapReader, ELEVATION_COUNT};
+use crate::asset::map::db::MapDb;
+use crate::asset::proto::{MapExit, ProtoDb, TargetMap};
+use crate::asset::script::db::ScriptDb;
+use crate::fs::FileSystem;
+use crate::game::object::{Objects, Scenery, SubObject};
+use crate::game::script::{ScriptKind, Scripts};
+use crate::graphics::geometry::hex::TileGrid;
+use crate::vm::{PredefinedProc, Vm};
+
+pub struct Checker {
+    fs: Rc<FileSystem>,
+    language: String,
+    proto_db: Rc<ProtoDb>,
+    frm_db: Rc<FrameDb>,
+    map_db: MapDb,
+    strict: bool,
+}
+
+impl Checker {
+    /// If `strict` is set, the map format deviations the map reader can work around make the map
+    /// fail to load instead of being reported as separate problems.
+    pub fn new(fs: Rc<FileSystem>, language: &str, proto_db: Rc<ProtoDb>, frm_db: Rc<FrameDb>,
+        strict: bool) -> io::Result<Self>
+    {
+        let map_db = MapDb::new(&fs)?;
+        Ok(Self {
+            fs,
+            language: language.into(),
+            proto_db,
+            frm_db,
+            map_db,
+            strict,
+        })
+    }
+
+    /// Returns names of all maps defined in `maps.txt`.
+    pub fn map_names(&self) -> Vec<String> {
+        self.map_db.iter().map(|m| m.name.to_lowercase()).collect()
+    }
+
+    /// Loads the map and returns descriptions of the problems found. Returns error only if the
+    /// check itself can't be run.
+    pub fn check(&self, map_name: &str) -> io::Result<Vec<String>> {
+        let mut objects = Objects::new(TileGrid::default(), ELEVATION_COUNT,
+            self.frm_db.clone(), self.proto_db.clone());
+        let mut scripts = Scripts::new(self.proto_db.clone(),
+            ScriptDb::new(self.fs.clone(), &self.language)?, Vm::default());
+
+        let mut errors = Vec::new();
+        let map = self.fs.reader(&format!("maps/{}.map", map_name))
+            .and_then(|mut rd| MapReader {
+                reader: &mut rd,
+                objects: &mut objects,
+                proto_db: &self.proto_db,
+                frm_db: &self.frm_db,
+                scripts: &mut scripts,
+                recoverable_errors: Some(&mut errors),
+                strict: self.strict,
+            }.read());
+        let mut r: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+        let map = match map {
+            Ok(v) => v,
+            Err(e) => {
+                r.push(format!("couldn't load map: {}", e));
+                return Ok(r);
+            }
+        };
+
+        self.check_sqr_tiles(&map, &mut r);
+        self.check_objects(&objects, &mut r);
+        check_scripts(&scripts, &objects, &mut r);
+
+        Ok(r)
+    }
+
+    fn check_sqr_tiles(&self, map: &Map, r: &mut Vec<String>) {
+        let ids: BTreeSet<_> = map.sqr_tiles.iter()
+            .flatten()
+            .flat_map(|t| t.as_slice().iter().flat_map(|&(floor, roof)| vec![floor, roof]))
+            .collect();
+        for id in ids {
+            if let Some(fid) = FrameId::new_generic(EntityKind::SqrTile, id) {
+                if let Err(e) = self.frm_db.get(fid) {
+                    r.push(format!("couldn't load square tile {}: {}", id, e));
+                }
+            } else {
+                r.push(format!("invalid square tile ID: {}", id));
+            }
+        }
+    }
+
+    fn check_objects(&self, objects: &Objects, r: &mut Vec<String>) {
+        for h in objects.iter() {
+            let obj = objects.get(h);
+            let what = format!("{:?} {:?} at {:?}", obj.kind(), obj.proto_id(), obj.try_pos());
+            match &obj.sub {
+                SubObject::MapExit(exit) => {
+                    self.check_exit(&what, exit, r);
+                    if let Some(pos) = obj.try_pos() {
+                        if objects.has_blocker_at(pos, Some(h)) {
+                            r.push(format!("{}: exit grid is blocked", what));
+                        }
+                    }
+                }
+                | SubObject::Scenery(Scenery::Ladder(exit))
+                | SubObject::Scenery(Scenery::Stairs(exit))
+                => self.check_exit(&what, exit, r),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_exit(&self, what: &str, exit: &MapExit, r: &mut Vec<String>) {
+        if let TargetMap::Map { map_id } = exit.map {
+            if self.map_db.get(map_id).is_none() {
+                r.push(format!("{}: exit leads to unknown map {}", what, map_id));
+            }
+        }
+        if exit.pos.elevation >= ELEVATION_COUNT {
+            r.push(format!("{}: exit leads to invalid elevation {}", what, exit.pos.elevation));
+        }
+    }
+}
+
+fn check_scripts(scripts: &Scripts, objects: &Objects, r: &mut Vec<String>) {
+    for (sid, name) in scripts.instances() {
+        let what = format!("script {} ({:?})", name, sid);
+        let required = if Some(sid) == scripts.map_sid() {
+            Some(PredefinedProc::MapEnter)
+        } else {
+            match sid.kind() {
+                ScriptKind::Spatial => Some(PredefinedProc::Spatial),
+                ScriptKind::Time => Some(PredefinedProc::TimedEvent),
+                _ => None,
+            }
+        };
+        if let Some(proc) = required {
+            if !scripts.has_predefined_proc(sid, proc) {
+                r.push(format!("{}: missing procedure {}", what, proc));
+            }
+        }
+
+        if let Some(obj) = scripts.get(sid).unwrap().object {
+            let kind = objects.get(obj).kind();
+            let mismatch = match sid.kind() {
+                ScriptKind::Critter => kind != EntityKind::Critter,
+                ScriptKind::Item => kind == EntityKind::Critter,
+                _ => false,
+            };
+            if mismatch && Some(sid) != scripts.map_sid() {
+                r.push(format!("{}: {:?} script is attached to {:?} object",
+                    what, sid.kind(), kind));
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use log::*;
+
+use crate::asset::Stat;
+use crate::game::object::{self, Objects};
+use crate::game::rpg::Rpg;
+use crate::game::team;
+use crate::graphics::geometry::hex;
+
+/// Number of hexes a critter can see per point of perception.
+const PERCEPTION_RANGE_MULT: i32 = 5;
+
+/// Turn-related combat event. Used to drive the combat parts of the interface.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Event {
+    TurnStarted {
+        obj: object::Handle,
+        action_points: i32,
+    },
+    ActionPointsChanged {
+        obj: object::Handle,
+        action_points: i32,
+    },
+    Ended,
+}
+
+pub struct Combat {
+    participants: Vec<object::Handle>,
+    current: usize,
+    round: u32,
+    action_points: i32,
+}
+
+impl Combat {
+    // combat_begin
+    /// Starts combat on the elevation of the `dude` with the dude, the `initiators` and the
+    /// critters drawn in by their teams (see `team::combatants()`). The turn order is determined by
+    /// `Stat::Sequence`, the dude wins ties.
+    pub fn new(objects: &Objects, rpg: &Rpg, initiators: &[object::Handle], out: &mut Vec<Event>)
+        -> Self
+    {
+        let dude = objects.dude();
+        let elevation = objects.get(dude).pos().elevation;
+        let mut initiators = initiators.to_vec();
+        initiators.push(dude);
+        let mut participants = team::combatants(objects, elevation, &initiators);
+        participants.sort_by_key(|&h| {
+            let obj = objects.get(h);
+            (-rpg.stat(Stat::Sequence, &obj, objects), h != dude, h)
+        });
+        debug!("combat started with {} participant(s)", participants.len());
+
+        let mut r = Self {
+            participants,
+            current: 0,
+            round: 1,
+            action_points: 0,
+        };
+        r.start_turn(objects, rpg, out);
+        r
+    }
+
+    pub fn participants(&self) -> &[object::Handle] {
+        &self.participants
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    /// Whether the current turn is the first one since the combat started.
+    pub fn is_first_turn(&self) -> bool {
+        self.round == 1 && self.current == 0
+    }
+
+    /// Critter whose turn it is. `None` if there's no participants left.
+    pub fn current(&self) -> Option<object::Handle> {
+        self.participants.get(self.current).copied()
+    }
+
+    pub fn is_dude_turn(&self, objects: &Objects) -> bool {
+        self.current() == Some(objects.dude())
+    }
+
+    /// Action points left to the current critter.
+    pub fn action_points(&self) -> i32 {
+        self.action_points
+    }
+
+    /// Spends `ap` action points of the current critter. Returns `false` if there's not enough
+    /// action points left, in which case nothing is spent.
+    #[must_use]
+    pub fn spend_action_points(&mut self, ap: i32, out: &mut Vec<Event>) -> bool {
+        let obj = if let Some(v) = self.current() {
+            v
+        } else {
+            return false;
+        };
+        if ap > self.action_points {
+            return false;
+        }
+        self.action_points -= ap;
+        out.push(Event::ActionPointsChanged {
+            obj,
+            action_points: self.action_points,
+        });
+        true
+    }
+
+    /// Adds the critters that became hostile to the participants since the combat started.
+    /// They take their turns after the current participants.
+    pub fn pull_in(&mut self, objects: &Objects) {
+        let elevation = objects.get(objects.dude()).pos().elevation;
+        for h in team::combatants(objects, elevation, &self.participants) {
+            if !self.participants.contains(&h) {
+                debug!("{:?} joined combat", h);
+                self.participants.push(h);
+            }
+        }
+    }
+
+    /// Removes `obj` from the turn order, for example when it dies or leaves the map.
+    pub fn remove(&mut self, obj: object::Handle) {
+        if let Some(i) = self.participants.iter().position(|&h| h == obj) {
+            self.participants.remove(i);
+            if i < self.current {
+                self.current -= 1;
+            }
+            if self.current >= self.participants.len() {
+                self.current = 0;
+            }
+        }
+    }
+
+    // combat_should_end
+    /// Returns `true` if there's no conscious critter left that is hostile to the dude and knows
+    /// about him. See `team::is_hostile()` for who is hostile. A critter knows about the dude if
+    /// the dude is within its perception range and not hidden behind sight blockers.
+    pub fn should_end(&self, objects: &Objects, rpg: &Rpg) -> bool {
+        let dude = objects.dude();
+        if self.participants.len() <= 1 || !self.participants.contains(&dude) {
+            return true;
+        }
+        !self.participants.iter().any(|&h| {
+            if h == dude || !objects.contains(h) {
+                return false;
+            }
+            let obj = objects.get(h);
+            let hostile = obj.sub.as_critter()
+                .map(|c| c.is_active())
+                .unwrap_or(false)
+                && team::is_hostile(objects, h, dude);
+            hostile && Self::knows_about(objects, rpg, h, dude)
+        })
+    }
+
+    // is_within_perception
+    /// Whether the `target` is within the perception range of `obj` and not hidden behind sight
+    /// blockers.
+    pub fn knows_about(objects: &Objects, rpg: &Rpg, obj: object::Handle, target: object::Handle)
+        -> bool
+    {
+        let o = objects.get(obj);
+        let (pos, target_pos) = match (o.try_pos(), objects.get(target).try_pos()) {
+            (Some(p), Some(t)) if p.elevation == t.elevation => (p, t),
+            _ => return false,
+        };
+        let range = rpg.stat(Stat::Perception, &o, objects) * PERCEPTION_RANGE_MULT;
+        if objects.distance(obj, target).map(|d| d as i32 > range).unwrap_or(true) {
+            return false;
+        }
+        for p in hex::ray(pos.point, target_pos.point) {
+            if p == target_pos.point {
+                break;
+            }
+            if objects.is_sight_blocked_at(obj, p.elevated(pos.elevation)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // combat_turn
+    /// Passes the turn to the next participant.
+    pub fn end_turn(&mut self, objects: &Objects, rpg: &Rpg, out: &mut Vec<Event>) {
+        self.participants.retain(|&h| objects.contains(h)
+            && objects.get(h).sub.as_critter().map(|c| !c.is_dead()).unwrap_or(false));
+        if self.participants.is_empty() {
+            out.push(Event::Ended);
+            return;
+        }
+        self.current += 1;
+        if self.current >= self.participants.len() {
+            self.current = 0;
+            self.round += 1;
+        }
+        self.start_turn(objects, rpg, out);
+    }
+
+    fn start_turn(&mut self, objects: &Objects, rpg: &Rpg, out: &mut Vec<Event>) {
+        let obj = if let Some(v) = self.current() {
+            v
+        } else {
+            out.push(Event::Ended);
+            return;
+        };
+        self.action_points = rpg.stat(Stat::ActionPoints, &objects.get(obj), objects).max(0);
+        out.push(Event::TurnStarted {
+            obj,
+            action_points: self.action_points,
+        });
+    }
+}
@@ -0,0 +1,617 @@
+//! Developer console: a drop-down window for running debug commands against the game state.
+
+use bstring::BString;
+use sdl2::keyboard::Keycode;
+use std::collections::VecDeque;
+
+use crate::asset::proto::ProtoId;
+use crate::game::object::Dude;
+use crate::game::script::ScriptIid;
+use crate::game::ui::console::ConsoleView;
+use crate::game::world::overlay::Overlay;
+use crate::graphics::Rect;
+use crate::input::bindings::{self, Action};
+use crate::ui::command::{ConsoleCommand, TextInputCommand};
+use crate::ui::*;
+use crate::ui::text_input::TextInput;
+use crate::vm::debug::{self, Breakpoint};
+
+const HEIGHT: i32 = 200;
+const HISTORY_CAPACITY: usize = 100;
+const OUTPUT_CAPACITY: usize = 200;
+
+/// Command names with their usage.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("appearance", "appearance [<art>]"),
+    ("bind", "bind <action> [<key>[,<key>...]]"),
+    ("clear", "clear"),
+    ("debug", "debug [scripts | attach <sid> | detach | break <proc> | breakop <opcode> | clear]"),
+    ("freecam", "freecam"),
+    ("give", "give <pid> [<count>]"),
+    ("help", "help"),
+    ("inspect", "inspect"),
+    ("killall", "killall"),
+    ("language", "language [<language>]"),
+    ("name", "name <name>"),
+    ("noclip", "noclip"),
+    ("overlay", "overlay [blockers | light | sight | path]"),
+    ("reveal", "reveal"),
+    ("setgvar", "setgvar <var> <value>"),
+    ("spawn", "spawn <pid> [<tile>]"),
+    ("tp", "tp <tile> [<elevation>]"),
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Sets the dude's critter art shown without armor to `art` base name or lists the art
+    /// available for the dude's gender.
+    Appearance {
+        art: Option<String>,
+    },
+    /// Binds `keys` to the `action` replacing its current keys and saves the bindings.
+    Bind {
+        action: Action,
+        keys: Vec<Keycode>,
+    },
+    /// Clears the console output.
+    Clear,
+    Debug(DebugCommand),
+    /// Toggles the free camera mode in which the camera isn't kept near the dude and clicking a
+    /// hex teleports the dude there.
+    FreeCamera,
+    /// Puts `count` items with `pid` into the dude's inventory.
+    Give {
+        pid: ProtoId,
+        count: u32,
+    },
+    Help,
+    /// Toggles the inspect mode in which clicking an object opens the object inspector.
+    Inspect,
+    /// Kills all critters on the map except the dude.
+    KillAll,
+    /// Switches the game texts to `language` or prints the current language.
+    Language {
+        language: Option<String>,
+    },
+    /// Renames the dude.
+    Name {
+        name: String,
+    },
+    /// Toggles walking of the dude through blockers.
+    NoClip,
+    /// Toggles the world view debug `overlay` or lists the active overlays.
+    Overlay {
+        overlay: Option<Overlay>,
+    },
+    /// Removes roofs, maxes out the ambient light and marks all objects as seen.
+    Reveal,
+    SetGlobalVar {
+        var: usize,
+        value: i32,
+    },
+    /// Creates object with `pid` at `tile` or at the dude's position.
+    Spawn {
+        pid: ProtoId,
+        tile: Option<u32>,
+    },
+    /// Moves the dude to the `tile` at the `elevation` or at the current elevation.
+    Teleport {
+        tile: u32,
+        elevation: Option<u32>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DebugCommand {
+    /// Shows or hides the script debugger window.
+    Toggle,
+    /// Lists the script instances of the map.
+    Scripts,
+    Attach {
+        sid: ScriptIid,
+    },
+    Detach,
+    AddBreakpoint(Breakpoint),
+    ClearBreakpoints,
+}
+
+impl Command {
+    /// Parses the command line. Returns `None` if the line is blank.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let mut args = line.split_whitespace();
+        let name = if let Some(v) = args.next() {
+            v.to_ascii_lowercase()
+        } else {
+            return Ok(None);
+        };
+        let args: Vec<_> = args.collect();
+        let check_arg_count = |min: usize, max: usize| {
+            if args.len() < min || args.len() > max {
+                Err(format!("usage: {}", usage(&name).unwrap()))
+            } else {
+                Ok(())
+            }
+        };
+        let r = match &name[..] {
+            "appearance" => {
+                check_arg_count(0, 1)?;
+                Self::Appearance {
+                    art: args.first().map(|s| s.to_ascii_lowercase()),
+                }
+            }
+            "bind" => {
+                if args.is_empty() {
+                    return Err(format!("usage: {}", usage(&name).unwrap()));
+                }
+                // Key names can contain spaces.
+                let keys = args[1..].join(" ");
+                let (action, keys) = bindings::parse_entry(args[0], &keys)
+                    .ok_or_else(|| if Action::from_name(args[0]).is_none() {
+                        format!("unknown action: {}", args[0])
+                    } else {
+                        format!("unknown key in: {}", keys)
+                    })?;
+                Self::Bind { action, keys }
+            }
+            "clear" => {
+                check_arg_count(0, 0)?;
+                Self::Clear
+            }
+            "debug" => {
+                check_arg_count(0, 2)?;
+                let sub = args.first().map(|s| s.to_ascii_lowercase());
+                let cmd = match (sub.as_deref(), args.len()) {
+                    (None, _) => DebugCommand::Toggle,
+                    (Some("scripts"), 1) => DebugCommand::Scripts,
+                    (Some("attach"), 2) => {
+                        let v = parse_int(args[1], 0, u32::max_value() as i64)?;
+                        DebugCommand::Attach {
+                            sid: ScriptIid::from_packed(v as u32)
+                                .ok_or_else(|| format!("invalid SID: {}", args[1]))?,
+                        }
+                    }
+                    (Some("detach"), 1) => DebugCommand::Detach,
+                    (Some("break"), 2) => DebugCommand::AddBreakpoint(Breakpoint::proc(args[1])),
+                    (Some("breakop"), 2) => DebugCommand::AddBreakpoint(Breakpoint::Opcode(
+                        debug::opcode_by_name(args[1])
+                            .ok_or_else(|| format!("unknown opcode: {}", args[1]))?)),
+                    (Some("clear"), 1) => DebugCommand::ClearBreakpoints,
+                    _ => return Err(format!("usage: {}", usage(&name).unwrap())),
+                };
+                Self::Debug(cmd)
+            }
+            "freecam" => {
+                check_arg_count(0, 0)?;
+                Self::FreeCamera
+            }
+            "give" => {
+                check_arg_count(1, 2)?;
+                Self::Give {
+                    pid: parse_pid(args[0])?,
+                    count: args.get(1).map(|s| parse_int(s, 1, u32::max_value() as i64))
+                        .transpose()?.unwrap_or(1) as u32,
+                }
+            }
+            "help" => {
+                check_arg_count(0, 0)?;
+                Self::Help
+            }
+            "inspect" => {
+                check_arg_count(0, 0)?;
+                Self::Inspect
+            }
+            "killall" => {
+                check_arg_count(0, 0)?;
+                Self::KillAll
+            }
+            "language" => {
+                check_arg_count(0, 1)?;
+                Self::Language {
+                    language: args.first().map(|s| s.to_ascii_lowercase()),
+                }
+            }
+            "name" => {
+                check_arg_count(1, usize::max_value())?;
+                let name = args.join(" ");
+                if name.chars().count() > Dude::MAX_NAME_LEN {
+                    return Err(format!("name is longer than {} characters", Dude::MAX_NAME_LEN));
+                }
+                Self::Name {
+                    name,
+                }
+            }
+            "noclip" => {
+                check_arg_count(0, 0)?;
+                Self::NoClip
+            }
+            "overlay" => {
+                check_arg_count(0, 1)?;
+                Self::Overlay {
+                    overlay: args.first()
+                        .map(|s| Overlay::from_name(s)
+                            .ok_or_else(|| format!("unknown overlay: {}", s)))
+                        .transpose()?,
+                }
+            }
+            "reveal" => {
+                check_arg_count(0, 0)?;
+                Self::Reveal
+            }
+            "setgvar" => {
+                check_arg_count(2, 2)?;
+                Self::SetGlobalVar {
+                    var: parse_int(args[0], 0, i32::max_value() as i64)? as usize,
+                    value: parse_int(args[1], i32::min_value() as i64,
+                        i32::max_value() as i64)? as i32,
+                }
+            }
+            "spawn" => {
+                check_arg_count(1, 2)?;
+                Self::Spawn {
+                    pid: parse_pid(args[0])?,
+                    tile: args.get(1).map(|s| parse_int(s, 0, u32::max_value() as i64))
+                        .transpose()?.map(|v| v as u32),
+                }
+            }
+            "tp" => {
+                check_arg_count(1, 2)?;
+                Self::Teleport {
+                    tile: parse_int(args[0], 0, u32::max_value() as i64)? as u32,
+                    elevation: args.get(1).map(|s| parse_int(s, 0, u32::max_value() as i64))
+                        .transpose()?.map(|v| v as u32),
+                }
+            }
+            _ => return Err(format!("unknown command: {}", name)),
+        };
+        Ok(Some(r))
+    }
+}
+
+pub fn usage(name: &str) -> Option<&'static str> {
+    COMMANDS.iter().find(|&&(n, _)| n == name).map(|&(_, u)| u)
+}
+
+/// Returns names of the commands starting with `prefix`.
+pub fn completions(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.to_ascii_lowercase();
+    COMMANDS.iter()
+        .map(|&(n, _)| n)
+        .filter(|n| n.starts_with(&prefix[..]))
+        .collect()
+}
+
+/// Completes the command name in `line`. If there's a single candidate the line is completed
+/// with a trailing space, otherwise it's extended to the longest common prefix of the
+/// candidates. Returns the completed line and the candidates.
+pub fn complete(line: &str) -> (String, Vec<&'static str>) {
+    let prefix = line.trim_start();
+    if prefix.contains(char::is_whitespace) {
+        return (line.into(), Vec::new());
+    }
+    let candidates = completions(prefix);
+    let r = match candidates.len() {
+        0 => line.into(),
+        1 => format!("{} ", candidates[0]),
+        _ => {
+            let first = candidates[0];
+            let len = candidates[1..].iter()
+                .map(|c| first.bytes().zip(c.bytes()).take_while(|(a, b)| a == b).count())
+                .min()
+                .unwrap();
+            first[..len].into()
+        }
+    };
+    (r, candidates)
+}
+
+pub fn parse_int(s: &str, min: i64, max: i64) -> Result<i64, String> {
+    let (s, neg) = if let Some(s) = s.strip_prefix('-') {
+        (s, true)
+    } else {
+        (s, false)
+    };
+    let v = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    let v = v.map_err(|_| format!("invalid number: {}", s))?;
+    let v = if neg { -v } else { v };
+    if v < min || v > max {
+        return Err(format!("number out of range {}..={}: {}", min, max, v));
+    }
+    Ok(v)
+}
+
+fn parse_pid(s: &str) -> Result<ProtoId, String> {
+    let v = parse_int(s, 0, u32::max_value() as i64)?;
+    ProtoId::from_packed(v as u32).ok_or_else(|| format!("invalid PID: {}", s))
+}
+
+/// Command line history navigated with up and down keys.
+#[derive(Debug, Default)]
+pub struct History {
+    lines: VecDeque<String>,
+    /// Index of the line currently shown or `None` if not browsing.
+    pos: Option<usize>,
+}
+
+impl History {
+    /// Adds the line to the history unless it's blank or the same as the last one. Resets the
+    /// browsing position.
+    pub fn push(&mut self, line: &str) {
+        self.pos = None;
+        if line.trim().is_empty() || self.lines.back().map(|l| l == line).unwrap_or(false) {
+            return;
+        }
+        if self.lines.len() == HISTORY_CAPACITY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.into());
+    }
+
+    /// Moves to the older line. Stays at the oldest line when reached.
+    pub fn older(&mut self) -> Option<&str> {
+        let pos = match self.pos {
+            Some(pos) => pos.saturating_sub(1),
+            None => self.lines.len().checked_sub(1)?,
+        };
+        self.pos = Some(pos);
+        Some(&self.lines[pos])
+    }
+
+    /// Moves to the newer line. Returns `None` when moved past the newest line.
+    pub fn newer(&mut self) -> Option<&str> {
+        let pos = self.pos? + 1;
+        if pos < self.lines.len() {
+            self.pos = Some(pos);
+            Some(&self.lines[pos])
+        } else {
+            self.pos = None;
+            None
+        }
+    }
+}
+
+pub struct Console {
+    window: Option<Handle>,
+    view: Option<Handle>,
+    input: Option<Handle>,
+    history: History,
+    /// Output is kept while the console is hidden.
+    output: VecDeque<BString>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            view: None,
+            input: None,
+            history: History::default(),
+            output: VecDeque::new(),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Shows the console at the top of the screen. Pressing any of the `toggle_keys` in the
+    /// console hides it.
+    pub fn show(&mut self, ui: &mut Ui, toggle_keys: Vec<Keycode>) {
+        assert!(self.window.is_none());
+
+        let rect = Rect::with_size(0, 0, 640, HEIGHT);
+        let window = ui.new_window(rect, None);
+        ui.widget_base_mut(window).set_modal(true);
+
+        let mut view = ConsoleView::new(ui.fonts().clone());
+        for line in &self.output {
+            view.push_output(line.clone());
+        }
+        let input_rect = view.input_rect(rect);
+        let mut input = view.new_input();
+        let mut command_keys = vec![Keycode::Tab, Keycode::Up, Keycode::Down];
+        command_keys.extend(toggle_keys);
+        input.set_command_keys(command_keys);
+        let view = ui.new_widget(window, rect, None, None, view);
+        let input = ui.new_widget(window, input_rect, None, None, input);
+        ui.set_keyboard_focus(Some(input));
+
+        self.window = Some(window);
+        self.view = Some(view);
+        self.input = Some(input);
+    }
+
+    pub fn hide(&mut self, ui: &mut Ui) {
+        if let Some(window) = self.window.take() {
+            ui.remove(window);
+            self.view = None;
+            self.input = None;
+        }
+    }
+
+    /// Translates the command of the input line widget `source` to the console command.
+    /// Returns `None` if the `source` isn't the console input line.
+    pub fn input_command(&self, source: Handle, command: TextInputCommand)
+        -> Option<ConsoleCommand>
+    {
+        if self.input != Some(source) {
+            return None;
+        }
+        Some(match command {
+            TextInputCommand::Submit => ConsoleCommand::Execute,
+            TextInputCommand::Cancel => ConsoleCommand::Hide,
+            TextInputCommand::Key { keycode: Keycode::Tab } => ConsoleCommand::Complete,
+            TextInputCommand::Key { keycode: Keycode::Up } => ConsoleCommand::HistoryPrev,
+            TextInputCommand::Key { keycode: Keycode::Down } => ConsoleCommand::HistoryNext,
+            // The toggle keys.
+            TextInputCommand::Key { .. } => ConsoleCommand::Hide,
+        })
+    }
+
+    pub fn print(&mut self, line: impl Into<BString>, ui: &mut Ui) {
+        let line = line.into();
+        if self.output.len() == OUTPUT_CAPACITY {
+            self.output.pop_front();
+        }
+        self.output.push_back(line.clone());
+        if let Some(view) = self.view {
+            ui.widget_mut::<ConsoleView>(view).push_output(line);
+        }
+    }
+
+    pub fn clear(&mut self, ui: &mut Ui) {
+        self.output.clear();
+        if let Some(view) = self.view {
+            ui.widget_mut::<ConsoleView>(view).clear_output();
+        }
+    }
+
+    /// Handles the command from the console view. Returns the command line to execute.
+    pub fn handle(&mut self, command: ConsoleCommand, ui: &mut Ui) -> Option<String> {
+        let input = self.input?;
+        match command {
+            ConsoleCommand::Complete => {
+                let (line, candidates) = complete(&ui.widget_ref::<TextInput>(input).text());
+                if candidates.len() > 1 {
+                    self.print(candidates.join(" "), ui);
+                }
+                ui.widget_mut::<TextInput>(input).set_text(&line);
+            }
+            ConsoleCommand::Execute => {
+                let line = ui.widget_mut::<TextInput>(input).take_text();
+                self.history.push(&line);
+                self.print(format!("> {}", line), ui);
+                return Some(line);
+            }
+            ConsoleCommand::Hide => self.hide(ui),
+            ConsoleCommand::HistoryNext => {
+                let line = self.history.newer().unwrap_or("");
+                ui.widget_mut::<TextInput>(input).set_text(line);
+            }
+            ConsoleCommand::HistoryPrev => {
+                if let Some(line) = self.history.older() {
+                    ui.widget_mut::<TextInput>(input).set_text(line);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+
+    #[test]
+    fn input_command() {
+        let mut handles = SlotMap::<Handle, ()>::with_key();
+        let input = handles.insert(());
+        let other = handles.insert(());
+        let mut c = Console::new();
+        c.input = Some(input);
+
+        assert_eq!(c.input_command(other, TextInputCommand::Submit), None);
+        assert_eq!(c.input_command(input, TextInputCommand::Submit),
+            Some(ConsoleCommand::Execute));
+        assert_eq!(c.input_command(input, TextInputCommand::Cancel), Some(ConsoleCommand::Hide));
+        assert_eq!(c.input_command(input, TextInputCommand::Key { keycode: Keycode::Tab }),
+            Some(ConsoleCommand::Complete));
+        assert_eq!(c.input_command(input, TextInputCommand::Key { keycode: Keycode::Up }),
+            Some(ConsoleCommand::HistoryPrev));
+        assert_eq!(c.input_command(input, TextInputCommand::Key { keycode: Keycode::Backquote }),
+            Some(ConsoleCommand::Hide));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Command::parse("  "), Ok(None));
+        assert_eq!(Command::parse("TP 12345"),
+            Ok(Some(Command::Teleport { tile: 12345, elevation: None })));
+        assert_eq!(Command::parse("tp 12345 1"),
+            Ok(Some(Command::Teleport { tile: 12345, elevation: Some(1) })));
+        assert_eq!(Command::parse("give 0x29 3"),
+            Ok(Some(Command::Give { pid: ProtoId::from_packed(0x29).unwrap(), count: 3 })));
+        assert_eq!(Command::parse("name Max  Stone"),
+            Ok(Some(Command::Name { name: "Max Stone".into() })));
+        assert!(Command::parse("name Maximilian Stone").is_err());
+        assert_eq!(Command::parse("appearance HMWARR"),
+            Ok(Some(Command::Appearance { art: Some("hmwarr".into()) })));
+        assert_eq!(Command::parse("appearance"), Ok(Some(Command::Appearance { art: None })));
+        assert_eq!(Command::parse("setgvar 10 -5"),
+            Ok(Some(Command::SetGlobalVar { var: 10, value: -5 })));
+        assert_eq!(Command::parse("spawn 16777217"),
+            Ok(Some(Command::Spawn { pid: ProtoId::from_packed(0x1000001).unwrap(),
+                tile: None })));
+        assert_eq!(Command::parse("killall"), Ok(Some(Command::KillAll)));
+        assert_eq!(Command::parse("language French"),
+            Ok(Some(Command::Language { language: Some("french".into()) })));
+        assert_eq!(Command::parse("language"), Ok(Some(Command::Language { language: None })));
+        assert_eq!(Command::parse("inspect"), Ok(Some(Command::Inspect)));
+        assert_eq!(Command::parse("overlay Sight"),
+            Ok(Some(Command::Overlay { overlay: Some(Overlay::Sight) })));
+        assert_eq!(Command::parse("overlay"), Ok(Some(Command::Overlay { overlay: None })));
+        assert_eq!(Command::parse("freecam"), Ok(Some(Command::FreeCamera)));
+        assert_eq!(Command::parse("bind attack A, Left Shift"),
+            Ok(Some(Command::Bind { action: Action::Attack,
+                keys: vec![Keycode::A, Keycode::LShift] })));
+        assert_eq!(Command::parse("bind pause"),
+            Ok(Some(Command::Bind { action: Action::Pause, keys: vec![] })));
+        assert!(Command::parse("bind foo A").is_err());
+        assert!(Command::parse("bind pause NoSuchKey").is_err());
+        assert_eq!(Command::parse("NoClip"), Ok(Some(Command::NoClip)));
+        assert!(Command::parse("noclip on").is_err());
+        assert!(Command::parse("overlay foo").is_err());
+        assert_eq!(Command::parse("debug"), Ok(Some(Command::Debug(DebugCommand::Toggle))));
+        assert_eq!(Command::parse("debug attach 0x4000002"),
+            Ok(Some(Command::Debug(DebugCommand::Attach {
+                sid: ScriptIid::from_packed(0x4000002).unwrap() }))));
+        assert_eq!(Command::parse("debug break Talk_P_Proc"),
+            Ok(Some(Command::Debug(DebugCommand::AddBreakpoint(
+                Breakpoint::proc("talk_p_proc"))))));
+        assert_eq!(Command::parse("debug breakop gsay_end"),
+            Ok(Some(Command::Debug(DebugCommand::AddBreakpoint(
+                Breakpoint::Opcode(debug::Opcode::GsayEnd))))));
+
+        assert_eq!(Command::parse("tp"), Err("usage: tp <tile> [<elevation>]".into()));
+        assert_eq!(Command::parse("killall 1"), Err("usage: killall".into()));
+        assert!(Command::parse("tp -1").is_err());
+        assert!(Command::parse("give 0xzz").is_err());
+        assert!(Command::parse("give 41 0").is_err());
+        assert_eq!(Command::parse("foo"), Err("unknown command: foo".into()));
+        assert!(Command::parse("debug attach").is_err());
+        assert!(Command::parse("debug attach 0x7000000").is_err());
+        assert_eq!(Command::parse("debug breakop foo"), Err("unknown opcode: foo".into()));
+    }
+
+    #[test]
+    fn complete_() {
+        assert_eq!(complete("t"), ("tp ".into(), vec!["tp"]));
+        assert_eq!(complete("s"), ("s".into(), vec!["setgvar", "spawn"]));
+        assert_eq!(complete(""), ("".into(), COMMANDS.iter().map(|&(n, _)| n).collect()));
+        assert_eq!(complete("x"), ("x".into(), vec![]));
+        assert_eq!(complete("tp 1"), ("tp 1".into(), vec![]));
+    }
+
+    #[test]
+    fn history() {
+        let mut h = History::default();
+        assert_eq!(h.older(), None);
+        h.push("a");
+        h.push("b");
+        h.push("b");
+        h.push(" ");
+        assert_eq!(h.older(), Some("b"));
+        assert_eq!(h.older(), Some("a"));
+        assert_eq!(h.older(), Some("a"));
+        assert_eq!(h.newer(), Some("b"));
+        assert_eq!(h.newer(), None);
+        assert_eq!(h.newer(), None);
+        assert_eq!(h.older(), Some("b"));
+        h.push("c");
+        assert_eq!(h.older(), Some("c"));
+    }
+}
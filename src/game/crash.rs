@@ -0,0 +1,182 @@
+//! Crash reporter. The panic hook writes a report with the build version, the game context at
+//! the time of the panic and the last logged lines to `vault13-crash-<time>.txt` in the working
+//! directory and tells the user where to find it.
+
+use sdl2::messagebox::{self, MessageBoxFlag};
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::asset::script::ProgramId;
+use crate::game::script::ScriptIid;
+use crate::graphics::EPoint;
+use crate::util::logging;
+use crate::vm::ProcedureId;
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::new(Context::default());
+}
+
+/// Whether the report was already written. Only the first panic is reported.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Game state included in the crash report.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Context {
+    pub map: Option<String>,
+    pub dude_pos: Option<EPoint>,
+    /// Script instance and procedure being executed.
+    pub script: Option<Script>,
+}
+
+/// Script procedure being executed. Kept as plain values since it's recorded on every procedure
+/// call, formatted only when the report is written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Script {
+    pub sid: ScriptIid,
+    pub program_id: ProgramId,
+    pub proc_id: ProcedureId,
+}
+
+/// Restores the previously executed script on drop.
+#[must_use]
+pub struct ScriptGuard {
+    prev: Option<Script>,
+}
+
+impl Drop for ScriptGuard {
+    fn drop(&mut self) {
+        let prev = self.prev.take();
+        with(|c| c.script = prev);
+    }
+}
+
+pub fn set_map(map: &str) {
+    with(|c| c.map = Some(map.into()));
+}
+
+pub fn set_dude_pos(pos: Option<EPoint>) {
+    with(|c| c.dude_pos = pos);
+}
+
+/// Records the `script` being executed until the returned guard is dropped.
+pub fn enter_script(script: Script) -> ScriptGuard {
+    let mut prev = None;
+    with(|c| prev = c.script.replace(script));
+    ScriptGuard {
+        prev,
+    }
+}
+
+/// Installs the panic hook. The previous hook runs first.
+pub fn install(version: String) {
+    let prev_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        prev_hook(info);
+        if REPORTED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let context = CONTEXT
+            .try_with(|c| c.try_borrow().map(|c| c.clone()).ok())
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let report = report(&version, &info.to_string(), &context,
+            &logging::recent_lines(), &Backtrace::force_capture().to_string());
+        let path = report_path();
+        let msg = match std::fs::write(&path, report) {
+            Ok(()) => format!("The game has crashed. The crash report is saved to {}.\n\
+                Please attach it to the bug report.", path.display()),
+            Err(e) => format!("The game has crashed. Couldn't save the crash report to {}: {}",
+                path.display(), e),
+        };
+        // Not logging since the panic could happen while logging.
+        eprintln!("{}", msg);
+        let _ = messagebox::show_simple_message_box(MessageBoxFlag::ERROR, "Vault 13 crashed",
+            &msg, None);
+    }));
+}
+
+fn report_path() -> PathBuf {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    PathBuf::from(format!("vault13-crash-{}.txt", time))
+}
+
+fn report(version: &str, panic: &str, context: &Context, log_lines: &[String],
+    backtrace: &str) -> String
+{
+    let mut r = String::new();
+    let or_none = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+    writeln!(r, "vault13 crash report").unwrap();
+    writeln!(r, "version: {}", version).unwrap();
+    writeln!(r, "build: {}", env!("BUILD_TARGET")).unwrap();
+    writeln!(r, "panic: {}", panic).unwrap();
+    writeln!(r).unwrap();
+    writeln!(r, "map: {}", or_none(context.map.clone())).unwrap();
+    writeln!(r, "dude position: {}", or_none(context.dude_pos
+        .map(|p| format!("{}, {} elevation {}", p.point.x, p.point.y, p.elevation)))).unwrap();
+    writeln!(r, "script: {}", or_none(context.script
+        .map(|s| format!("{:?} program #{} proc {}", s.sid, s.program_id.val(), s.proc_id))))
+        .unwrap();
+    writeln!(r).unwrap();
+    writeln!(r, "last log lines:").unwrap();
+    for line in log_lines {
+        writeln!(r, "{}", line).unwrap();
+    }
+    writeln!(r).unwrap();
+    writeln!(r, "backtrace:").unwrap();
+    writeln!(r, "{}", backtrace).unwrap();
+    r
+}
+
+fn with(f: impl FnOnce(&mut Context)) {
+    let _ = CONTEXT.try_with(|c| {
+        if let Ok(mut c) = c.try_borrow_mut() {
+            f(&mut c);
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game::script::ScriptKind;
+
+    #[test]
+    fn context() {
+        set_map("artemple");
+        set_dude_pos(Some((0, (10, 12)).into()));
+        let script = |id, program_id| Script {
+            sid: ScriptIid::new(ScriptKind::Critter, id),
+            program_id: ProgramId::new(program_id).unwrap(),
+            proc_id: 3,
+        };
+        {
+            let _g = enter_script(script(1, 10));
+            {
+                let _g = enter_script(script(2, 20));
+                CONTEXT.with(|c| assert_eq!(c.borrow().script, Some(script(2, 20))));
+                let report = report("", "", &CONTEXT.with(|c| c.borrow().clone()), &[], "");
+                assert!(report.contains("\nscript: "), "{}", report);
+                assert!(report.contains(" program #20 proc 3\n"), "{}", report);
+            }
+            CONTEXT.with(|c| assert_eq!(c.borrow().script, Some(script(1, 10))));
+        }
+        let context = CONTEXT.with(|c| c.borrow().clone());
+        assert_eq!(context, Context {
+            map: Some("artemple".into()),
+            dude_pos: Some((0, (10, 12)).into()),
+            script: None,
+        });
+
+        let report = report("vault13 0.1.0", "boom", &context, &["INFO a - b".into()], "bt");
+        assert!(report.contains("\npanic: boom\n"));
+        assert!(report.contains("\nmap: artemple\n"));
+        assert!(report.contains("\ndude position: 10, 12 elevation 0\n"));
+        assert!(report.contains("\nscript: -\n"));
+        assert!(report.contains("\nlast log lines:\nINFO a - b\n"));
+    }
+}
@@ -0,0 +1,213 @@
+use enum_primitive_derive::Primitive;
+use log::*;
+
+use crate::asset::{CritterAnim, DamageKind, Flag};
+use crate::asset::death_ending::{self, DeathEnding, DEFAULT_NARRATOR};
+use crate::game::object::{DamageFlag, Handle, Objects, SetFrame};
+use crate::game::sequence::ObjSequencer;
+use crate::game::sequence::frame_anim::{FrameAnim, FrameAnimOptions};
+use crate::graphics::Point;
+use crate::graphics::geometry::hex::{self, Direction};
+use crate::sequence::*;
+use crate::sequence::chain::Chain;
+use crate::sequence::event::{Event, PushEvent};
+use crate::game::rng::{random, Stream};
+use crate::util::EnumExt;
+
+/// Damage above which the gory death animations are played.
+const GORY_DEATH_DAMAGE: i32 = 15;
+
+/// Damage above which the most violent death animations are played.
+const MAX_BLOOD_DEATH_DAMAGE: i32 = 45;
+
+/// The `violence_level` preference.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Primitive)]
+pub enum ViolenceLevel {
+    None = 0,
+    Minimal = 1,
+    Normal = 2,
+    MaximumBlood = 3,
+}
+
+impl ViolenceLevel {
+    /// Whether corpses leave blood pools and can be blown apart.
+    pub fn has_blood(self) -> bool {
+        self >= Self::Normal
+    }
+}
+
+/// Picks the death animation of the critter killed by `damage` of `damage_kind`.
+// pick_death
+pub fn pick_death_anim(
+    damage_kind: DamageKind,
+    damage: i32,
+    hit_from_front: bool,
+    violence: ViolenceLevel,
+) -> CritterAnim {
+    use CritterAnim::*;
+
+    let fall = match (hit_from_front, violence.has_blood()) {
+        (true, false) => FallBack,
+        (false, false) => FallFront,
+        (true, true) => FallBackBlood,
+        (false, true) => FallFrontBlood,
+    };
+    if !violence.has_blood() || damage <= GORY_DEATH_DAMAGE {
+        return fall;
+    }
+    let (normal, max_blood) = match damage_kind {
+        DamageKind::Melee => (BigHole, ChunksOfFlesh),
+        DamageKind::Laser => (CharredBody, SlicedInHalf),
+        DamageKind::Fire => (CharredBody, BurnedToNothing),
+        DamageKind::Plasma => (MeltedToNothing, MeltedToNothing),
+        DamageKind::Electric => (Electrify, ElectrifiedToNothing),
+        DamageKind::Explosion => (BigHole, ExplodedToNothing),
+        DamageKind::Emp | DamageKind::Radiation | DamageKind::Poison => return fall,
+    };
+    if violence == ViolenceLevel::MaximumBlood && damage > MAX_BLOOD_DEATH_DAMAGE {
+        max_blood
+    } else {
+        normal
+    }
+}
+
+/// Whether the explosion `damage` blows the corpse apart.
+pub fn destroys_corpse(damage: i32, violence: ViolenceLevel) -> bool {
+    violence == ViolenceLevel::MaximumBlood && damage > MAX_BLOOD_DEATH_DAMAGE
+}
+
+/// Whether the critter at `pos` facing `direction` is hit from the front by something at `from`.
+// is_hit_from_front
+pub fn is_hit_from_front(pos: Point, direction: Direction, from: Point) -> bool {
+    if pos == from {
+        return true;
+    }
+    let to_source = hex::direction(pos, from);
+    let diff = (direction.ordinal() + Direction::len() - to_source.ordinal()) % Direction::len();
+    diff <= 1 || diff == Direction::len() - 1
+}
+
+/// Marks the critter dead and plays its `anim` death animation. After the animation the critter
+/// stays lying on the ground as a corpse. If the critter is the dude `Event::DudeDied` is emitted
+/// once the animation is done or cancelled.
+// critter_kill
+pub fn kill_critter(obj: Handle, anim: CritterAnim, objects: &Objects,
+    obj_sequencer: &mut ObjSequencer)
+{
+    {
+        let mut objo = objects.get_mut(obj);
+        let critter = if let Some(v) = objo.sub.as_critter_mut() {
+            v
+        } else {
+            warn!("kill_critter: {:?} is not a critter", obj);
+            return;
+        };
+        critter.hit_points = 0;
+        critter.combat.damage_flags.insert(DamageFlag::Dead);
+    }
+    debug!("killed {:?}", obj);
+
+    let seq = Chain::new();
+    seq.control()
+        .cancellable(FrameAnim::new(
+            obj,
+            FrameAnimOptions {
+                anim: Some(anim),
+                ..Default::default()
+            },
+        ))
+        .finalizing(LieDead::new(obj, anim));
+    if obj == objects.dude() {
+        seq.control().finalizing(PushEvent::new(Event::DudeDied));
+    }
+    obj_sequencer.replace(obj, seq);
+}
+
+/// Switches the killed critter to the single-frame variant of its death animation and makes it
+/// flat and passable.
+struct LieDead {
+    obj: Handle,
+    anim: CritterAnim,
+    done: bool,
+}
+
+impl LieDead {
+    fn new(obj: Handle, anim: CritterAnim) -> Self {
+        Self {
+            obj,
+            anim,
+            done: false,
+        }
+    }
+}
+
+impl Sequence for LieDead {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        if self.done {
+            return Result::Done;
+        }
+        self.done = true;
+
+        let fid = {
+            let mut obj = ctx.world.objects().get_mut(self.obj);
+            obj.flags.insert(Flag::Flat | Flag::NoBlock);
+            obj.fid.critter()
+                .and_then(|fid| Some(fid.with_anim(self.anim.single_frame()?)))
+                .and_then(|fid| ctx.world.frm_db().critter_anim_or_fallback(fid))
+        };
+        if let Some(fid) = fid {
+            ctx.world.objects().get_mut(self.obj).fid = fid.into();
+            ctx.world.objects_mut().set_frame(self.obj, SetFrame::Index(0));
+        } else {
+            warn!("{:?} has no dead frame for {:?}", self.obj, self.anim);
+        }
+
+        Result::Running(Running::NotLagging)
+    }
+}
+
+/// Picks the narrator of the death screen from the applicable `endings`.
+// endgameSetupDeathEnding
+pub fn pick_narrator(endings: &[DeathEnding], global_vars: &[i32], level: u32) -> String {
+    let applicable: Vec<_> = endings.iter()
+        .filter(|e| e.is_applicable(global_vars, level))
+        .collect();
+    let chance = random(Stream::Misc, 0, death_ending::total_percentage(&applicable) as i32) as u32;
+    death_ending::pick(&applicable, chance)
+        .map(|e| e.narrator.clone())
+        .unwrap_or_else(|| DEFAULT_NARRATOR.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pick_death_anim_() {
+        use CritterAnim::*;
+        use ViolenceLevel::*;
+        let t = pick_death_anim;
+        assert_eq!(t(DamageKind::Explosion, 100, true, Minimal), FallBack);
+        assert_eq!(t(DamageKind::Explosion, 100, false, Minimal), FallFront);
+        assert_eq!(t(DamageKind::Explosion, 10, true, Normal), FallBackBlood);
+        assert_eq!(t(DamageKind::Explosion, 100, true, Normal), BigHole);
+        assert_eq!(t(DamageKind::Explosion, 30, true, MaximumBlood), BigHole);
+        assert_eq!(t(DamageKind::Explosion, 100, true, MaximumBlood), ExplodedToNothing);
+        assert_eq!(t(DamageKind::Emp, 100, false, MaximumBlood), FallFrontBlood);
+    }
+
+    #[test]
+    fn is_hit_from_front_() {
+        let pos = Point::new(10, 10);
+        let t = |direction, from_direction| is_hit_from_front(pos,
+            direction, hex::go(pos, from_direction, 3));
+        assert!(is_hit_from_front(pos, Direction::W, pos));
+        assert!(t(Direction::E, Direction::E));
+        assert!(t(Direction::E, Direction::NE));
+        assert!(t(Direction::E, Direction::SE));
+        assert!(!t(Direction::E, Direction::SW));
+        assert!(!t(Direction::E, Direction::W));
+        assert!(!t(Direction::NE, Direction::SW));
+        assert!(t(Direction::NE, Direction::NW));
+    }
+}
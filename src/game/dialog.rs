@@ -1,31 +1,65 @@
 use bstring::{bstr, BString};
+use std::time::{Duration, Instant};
 
 use crate::asset::frame::FrameId;
 use crate::asset::message::BULLET_STR;
+use crate::asset::sve::{Subtitle, Subtitles};
 use crate::game::object;
+use crate::game::reaction;
 use crate::game::script::ScriptIid;
 use crate::game::world::World;
 use crate::graphics::{Point, Rect};
 use crate::graphics::color::{Rgb15, GREEN};
-use crate::graphics::font::FontKey;
+use crate::graphics::font::{self, FontKey};
 use crate::graphics::sprite::{Sprite, Effect};
 use crate::ui::*;
 use crate::ui::message_panel::{MessagePanel, MouseControl};
 use crate::ui::panel::Panel;
 
+const REPLY_FONT: FontKey = FontKey::antialiased(1);
+
+/// Subtitle frames of the speech are milliseconds.
+const SPEECH_SUBTITLE_FPS: u32 = 1000;
+
 pub struct OptionInfo {
     pub proc_id: Option<u32>,
 }
 
+/// Talking head speech of a reply waiting to be played.
+pub struct Speech {
+    /// Path of the speech file.
+    pub path: String,
+    /// Reply text.
+    pub text: BString,
+}
+
+/// Subtitles of the talking head speech, shown in the reply area in sync with the speech.
+struct SpeechSubtitles {
+    subtitles: Subtitles,
+    fps: u32,
+    start: Instant,
+    /// Index of the currently shown subtitle.
+    shown: Option<usize>,
+}
+
 pub struct Dialog {
     window: Handle,
     reply: Handle,
+    subtitles: Option<SpeechSubtitles>,
+    speech: Option<Speech>,
     options_widget: Handle,
     options: Vec<OptionInfo>,
     sid: ScriptIid,
     saved_camera_origin: Point,
     pub obj: object::Handle,
+    /// Subdirectory of `sound/speech` with the speech of the talking head. `None` if there's no
+    /// talking head.
+    pub speech_dir: Option<String>,
     pub running: bool,
+    /// Mood the talking head greets the dude with, see `reaction::Level::mood()`.
+    pub mood: i32,
+    /// Price change in percent set with `gdialog_set_barter_mod`.
+    pub barter_mod: i32,
 }
 
 impl Dialog {
@@ -36,7 +70,7 @@ impl Dialog {
         ui.new_widget(window, Rect::with_size(0, 480 - 190, 640, 480), None,
             Some(Sprite::new(FrameId::DI_TALK)), Panel::new());
 
-        let reply = MessagePanel::new(ui.fonts().clone(), FontKey::antialiased(1), GREEN);
+        let reply = MessagePanel::new(ui.fonts().clone(), REPLY_FONT, GREEN);
         let reply = ui.new_widget(window, Rect::with_size(135, 235, 382, 47), None, None, reply);
 
         let mut options = MessagePanel::new(ui.fonts().clone(), FontKey::antialiased(1), GREEN);
@@ -67,12 +101,17 @@ impl Dialog {
         Self {
             window,
             reply,
+            subtitles: None,
+            speech: None,
             options_widget,
             options: Vec::new(),
             running: false,
             sid,
             saved_camera_origin,
             obj,
+            speech_dir: None,
+            mood: reaction::Level::Neutral.mood(),
+            barter_mod: 0,
         }
     }
 
@@ -91,6 +130,85 @@ impl Dialog {
         replyw.push_message(BString::concat(&[&b"  "[..], reply.as_ref().as_bytes()]))
     }
 
+    /// Shows the `reply` said by the talking head. If `speech` is not empty, the speech file is
+    /// queued to be played. See `take_speech()`.
+    pub fn say(&mut self, ui: &mut Ui, reply: &bstr, speech: &bstr) {
+        self.stop_subtitles();
+        self.set_reply(ui, reply);
+        self.speech = match &self.speech_dir {
+            Some(dir) if !speech.is_empty() => Some(Speech {
+                path: format!("sound/speech/{}/{}.acm", dir, speech.display())
+                    .to_ascii_lowercase(),
+                text: BString::from(reply.as_bytes()),
+            }),
+            _ => None,
+        };
+    }
+
+    /// Takes the speech queued by `say()`.
+    pub fn take_speech(&mut self) -> Option<Speech> {
+        self.speech.take()
+    }
+
+    // gdialogDisplayMsg
+    /// Shows the reply `text` in pages that fit the reply area, in sync with the speech lasting
+    /// `duration`.
+    pub fn play_speech_subtitles(&mut self, ui: &Ui, text: &bstr, duration: Duration,
+        now: Instant)
+    {
+        let rect = ui.widget_base_ref(self.reply).rect();
+        let font = ui.fonts().get(REPLY_FONT);
+        let lines_per_page = (rect.height() / font.vert_advance()).max(1) as usize;
+        let lines: Vec<_> = font.lines(text, Some(font::Overflow {
+                size: rect.width(),
+                boundary: font::OverflowBoundary::Word,
+                action: font::OverflowAction::Wrap,
+            }))
+            .collect();
+        let pages = lines.chunks(lines_per_page)
+            .map(|page| {
+                let page = page.iter().map(|l| l.as_bytes()).collect::<Vec<_>>().join(&b' ');
+                BString::from(&page[..])
+            })
+            .collect();
+        self.play_subtitles(speech_subtitles(pages, duration), SPEECH_SUBTITLE_FPS, now);
+    }
+
+    /// Starts showing `subtitles` in the reply area replacing the reply text. The subtitle frames
+    /// are counted at `fps` from `now`.
+    pub fn play_subtitles(&mut self, subtitles: Subtitles, fps: u32, now: Instant) {
+        assert!(fps > 0);
+        self.subtitles = Some(SpeechSubtitles {
+            subtitles,
+            fps,
+            start: now,
+            shown: None,
+        });
+    }
+
+    pub fn stop_subtitles(&mut self) {
+        self.subtitles = None;
+    }
+
+    /// Shows the subtitle for the current time, if it changed.
+    pub fn update_subtitles(&mut self, ui: &mut Ui, now: Instant) {
+        let subs = if let Some(v) = self.subtitles.as_mut() {
+            v
+        } else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(subs.start);
+        let frame = (elapsed.as_millis() * subs.fps as u128 / 1000) as u32;
+        let idx = subs.subtitles.index_at(frame);
+        if idx != subs.shown {
+            subs.shown = idx;
+            if let Some(idx) = idx {
+                let text = subs.subtitles.entries()[idx].text.clone();
+                self.set_reply(ui, text);
+            }
+        }
+    }
+
     pub fn clear_options(&mut self, ui: &mut Ui) {
         ui.widget_mut::<MessagePanel>(self.options_widget).clear_messages();
         self.options.clear();
@@ -121,3 +239,33 @@ impl Dialog {
     }
 }
 
+/// Times the speech subtitle `pages` so that each is shown for the part of the speech
+/// `duration` proportional to the page length.
+fn speech_subtitles(pages: Vec<BString>, duration: Duration) -> Subtitles {
+    let total_len = pages.iter().map(|p| p.len()).sum::<usize>().max(1) as u128;
+    let mut len = 0;
+    Subtitles::new(pages.into_iter()
+        .map(|text| {
+            let frame = (duration.as_millis() * len / total_len) as u32;
+            len += text.len() as u128;
+            Subtitle { frame, text }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn speech_subtitles_() {
+        let pages = vec![BString::from(&b"aaa"[..]), BString::from(&b"b"[..])];
+        let subs = speech_subtitles(pages, Duration::from_secs(2));
+        assert_eq!(subs.entries(), &[
+            Subtitle { frame: 0, text: BString::from(&b"aaa"[..]) },
+            Subtitle { frame: 1500, text: BString::from(&b"b"[..]) },
+        ]);
+        assert!(speech_subtitles(Vec::new(), Duration::from_secs(1)).entries().is_empty());
+    }
+}
+
@@ -0,0 +1,142 @@
+//! Drugs: chems that change the stats of the critter taking them for a while.
+//!
+//! Each effect of the drug prototype changes a stat after its delay. The later effects usually
+//! undo the earlier ones, this is how RadAway keeps flushing the rads and Rad-X wears off. The
+//! delays are in game time so the effects also come while resting. Pending effects of the party
+//! follow it to the next map.
+
+use bstring::BString;
+use log::*;
+use slotmap::SecondaryMap;
+use std::cmp;
+
+use crate::asset::Stat;
+use crate::asset::proto::{Drug, DrugEffectModifier};
+use crate::game::GameTime;
+use crate::game::object::{Handle, Objects};
+use crate::game::radiation;
+use crate::game::rng::{random, Stream};
+use crate::game::rpg::Rpg;
+use crate::game::timer::ObjectTimers;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Effect {
+    stat: Stat,
+    modifier: i32,
+}
+
+/// Effects of the taken drugs waiting for their time.
+pub struct DrugEffects {
+    pending: ObjectTimers<Effect>,
+}
+
+impl DrugEffects {
+    pub fn new() -> Self {
+        Self {
+            pending: ObjectTimers::new(),
+        }
+    }
+
+    // item_d_take_drug
+    /// Schedules the effects of the `drug` taken by `obj`. The immediate effects are applied on
+    /// the next update.
+    pub fn take(&mut self, time: GameTime, obj: Handle, drug: &Drug) {
+        for e in &drug.effects {
+            let modifier = match e.modifier {
+                DrugEffectModifier::Fixed(v) => v,
+                DrugEffectModifier::Random(min, max) => random(Stream::Misc, min, max),
+            };
+            // The delay is in game minutes.
+            let time = time.add_decis(e.delay * 600);
+            self.pending.add(time, obj, Effect { stat: e.stat, modifier });
+        }
+        // TODO addiction
+    }
+
+    // queue_find(obj, EVENT_TYPE_DRUG)
+    /// Whether `obj` has drug effects yet to come, that is it's still under the influence.
+    pub fn is_on_drugs(&self, obj: Handle) -> bool {
+        self.pending.contains(obj)
+    }
+
+    /// Drops the pending effects of `obj`.
+    pub fn remove(&mut self, obj: Handle) {
+        self.pending.remove(obj);
+    }
+
+    /// Moves the pending effects to the new handles of the objects that were reinserted into the
+    /// world. Effects of the objects missing in `handle_map` are dropped.
+    pub fn remap(&mut self, handle_map: &SecondaryMap<Handle, Handle>) {
+        self.pending.remap(handle_map);
+    }
+
+    /// Applies the effects whose time has come. Returns messages for the dude.
+    pub fn update(&mut self, time: GameTime, objs: &Objects, rpg: &Rpg) -> Vec<BString> {
+        let mut r = Vec::new();
+        for timer in self.pending.take_due(time) {
+            let (obj, e) = (timer.obj, timer.data);
+            if !objs.contains(obj) || objs.get(obj).sub.as_critter().is_none() {
+                continue;
+            }
+            debug!("drug effect on {:?}: {:?} {:+}", obj, e.stat, e.modifier);
+            match e.stat {
+                Stat::CurrentRad => r.extend(radiation::expose(obj, e.modifier, objs, rpg)),
+                Stat::CurrentHitPoints => {
+                    let max = rpg.stat(Stat::HitPoints, &objs.get(obj), objs);
+                    let mut objo = objs.get_mut(obj);
+                    let critter = objo.sub.as_critter_mut().unwrap();
+                    critter.hit_points =
+                        cmp::max(cmp::min(critter.hit_points + e.modifier, max), 1);
+                }
+                Stat::CurrentPoison => {
+                    let mut objo = objs.get_mut(obj);
+                    let critter = objo.sub.as_critter_mut().unwrap();
+                    critter.poison = (critter.poison + e.modifier).max(0);
+                }
+                stat => rpg.add_bonus_stat(stat, &mut objs.get_mut(obj), e.modifier, objs),
+            }
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+    use crate::asset::proto::{DrugAddiction, DrugEffect};
+
+    #[test]
+    fn pending_effects() {
+        let mut objs = SlotMap::<Handle, ()>::with_key();
+        let obj = objs.insert(());
+        let effect = |delay, modifier| DrugEffect {
+            delay,
+            stat: Stat::Strength,
+            modifier: DrugEffectModifier::Fixed(modifier),
+        };
+        let drug = Drug {
+            effects: vec![effect(0, 2), effect(60, -2)],
+            addiction: DrugAddiction { chance: 0, perk: None, delay: 0 },
+        };
+        let now = GameTime::from_decis(1000);
+        let mut effects = DrugEffects::new();
+        effects.take(now, obj, &drug);
+        assert!(effects.is_on_drugs(obj));
+
+        // The delay is in game minutes.
+        assert_eq!(effects.pending.take_due(now).len(), 1);
+        assert!(effects.pending.take_due(now.add_decis(35999)).is_empty());
+
+        // The effects follow the object reinserted with a new handle.
+        let new_obj = objs.insert(());
+        let mut handle_map = SecondaryMap::new();
+        handle_map.insert(obj, new_obj);
+        effects.remap(&handle_map);
+        assert!(!effects.is_on_drugs(obj));
+        assert!(effects.is_on_drugs(new_obj));
+
+        effects.remove(new_obj);
+        assert!(!effects.is_on_drugs(new_obj));
+    }
+}
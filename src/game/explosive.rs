@@ -0,0 +1,97 @@
+use crate::asset::DamageKind;
+use crate::asset::proto::ProtoId;
+use crate::game::attack;
+use crate::game::object::{Handle, Object, Objects};
+use crate::game::rpg::Rpg;
+use crate::graphics::EPoint;
+use crate::game::rng::RollCheckResult;
+
+/// Explosive timer range and step in seconds.
+pub const TIMER_MIN: u32 = 10;
+pub const TIMER_MAX: u32 = 180;
+pub const TIMER_STEP: u32 = 10;
+pub const TIMER_DEFAULT: u32 = 60;
+
+/// Distance from the explosion center within which objects are damaged.
+pub const RADIUS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Explosive {
+    pub pid: ProtoId,
+    /// Explosive with the timer set.
+    pub active_pid: ProtoId,
+    pub min_damage: i32,
+    pub max_damage: i32,
+}
+
+const EXPLOSIVES: &[Explosive] = &[
+    Explosive {
+        pid: ProtoId::DYNAMITE,
+        active_pid: ProtoId::ACTIVE_DYNAMITE,
+        min_damage: 30,
+        max_damage: 50,
+    },
+    Explosive {
+        pid: ProtoId::PLASTIC_EXPLOSIVE,
+        active_pid: ProtoId::ACTIVE_PLASTIC_EXPLOSIVE,
+        min_damage: 40,
+        max_damage: 80,
+    },
+];
+
+impl Explosive {
+    /// Returns explosive that can be armed.
+    pub fn inactive(pid: ProtoId) -> Option<Self> {
+        EXPLOSIVES.iter().find(|e| e.pid == pid).copied()
+    }
+
+    /// Returns explosive with the timer set.
+    pub fn active(pid: ProtoId) -> Option<Self> {
+        EXPLOSIVES.iter().find(|e| e.active_pid == pid).copied()
+    }
+}
+
+/// Returns the actual game time in seconds before the explosion for the timer set to `seconds`.
+/// Failing the Traps roll makes the explosive go off earlier.
+// obj_use_explosive
+pub fn timer_delay(seconds: u32, roll: RollCheckResult) -> u32 {
+    match roll {
+        RollCheckResult::CriticalFailure => 0,
+        RollCheckResult::Failure => seconds / 2,
+        RollCheckResult::Success | RollCheckResult::CriticalSuccess => seconds,
+    }
+}
+
+/// Returns objects within `RADIUS` from `pos` on the same elevation.
+pub fn objects_in_radius(objs: &Objects, pos: EPoint) -> Vec<Handle> {
+    objs.in_radius(pos, RADIUS)
+}
+
+/// Returns explosion `damage` reduced by the critter's explosion damage threshold and
+/// resistance.
+pub fn critter_damage(damage: i32, critter: &Object, objs: &Objects, rpg: &Rpg) -> i32 {
+    attack::critter_damage(damage, DamageKind::Explosion, critter, objs, rpg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timer_delay_() {
+        use RollCheckResult::*;
+        assert_eq!(timer_delay(60, CriticalSuccess), 60);
+        assert_eq!(timer_delay(60, Success), 60);
+        assert_eq!(timer_delay(60, Failure), 30);
+        assert_eq!(timer_delay(60, CriticalFailure), 0);
+    }
+
+    #[test]
+    fn lookup() {
+        let dynamite = Explosive::inactive(ProtoId::DYNAMITE).unwrap();
+        assert_eq!(dynamite.active_pid, ProtoId::ACTIVE_DYNAMITE);
+        assert_eq!(Explosive::active(ProtoId::ACTIVE_DYNAMITE), Some(dynamite));
+        assert_eq!(Explosive::active(ProtoId::DYNAMITE), None);
+        assert_eq!(Explosive::inactive(ProtoId::ACTIVE_PLASTIC_EXPLOSIVE), None);
+    }
+}
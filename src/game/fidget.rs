@@ -3,6 +3,7 @@ use std::cmp;
 use std::time::{Duration, Instant};
 
 use crate::asset::{CritterAnim, EntityKind, Flag};
+use crate::game::rng::{random, Stream};
 use crate::game::sequence::ObjSequencer;
 use crate::game::sequence::frame_anim::*;
 use crate::game::sequence::stand::Stand;
@@ -10,7 +11,6 @@ use crate::game::world::World;
 use crate::graphics::{EPoint, Point, Rect};
 use crate::graphics::geometry::TileGridView;
 use crate::sequence::chain::Chain;
-use crate::util::random::random;
 
 pub struct Fidget {
     next_time: Instant,
@@ -63,7 +63,7 @@ impl Fidget {
         }
 
         if !objs.is_empty() {
-            let objh = objs[random(0, objs.len() as i32 - 1) as usize];
+            let objh = objs[random(Stream::Misc, 0, objs.len() as i32 - 1) as usize];
 
             if obj_sequencer.is_running(objh) {
                 debug!("fidget: object {:?} already has a running sequence", objh);
@@ -105,7 +105,7 @@ impl Fidget {
         } else {
             cmp::min(cmp::max(20 / obj_count, 1), 7)
         };
-        let next_delay = random(0, 3000) + 1000 * factor as i32;
+        let next_delay = random(Stream::Misc, 0, 3000) + 1000 * factor as i32;
         Duration::from_millis(next_delay as u64)
     }
 }
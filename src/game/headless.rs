@@ -0,0 +1,75 @@
+//! Headless mode: runs a fixed number of game loop ticks without window and input. Ticks advance
+//! the clock by a fixed step and the random generator is seeded with a fixed value so that runs
+//! are reproducible. Intended for integration tests and CI.
+
+use crate::game::benchmark::Status;
+use crate::game::state::GameState;
+
+/// Seed of the random generator used in headless mode.
+pub const SEED: u64 = 13;
+
+pub const DEFAULT_TICKS: u64 = 600;
+
+pub struct Headless {
+    ticks: u64,
+    done: u64,
+}
+
+impl Headless {
+    pub fn new(ticks: u64) -> Self {
+        Self {
+            ticks,
+            done: 0,
+        }
+    }
+
+    pub fn ticks_done(&self) -> u64 {
+        self.done
+    }
+
+    /// Counts a finished tick.
+    pub fn tick(&mut self) -> Status {
+        self.done += 1;
+        if self.done >= self.ticks {
+            Status::Done
+        } else {
+            Status::Running
+        }
+    }
+
+    /// Describes the resulting game state. The output is stable across runs and can be compared
+    /// against the expected one.
+    pub fn summary(&self, state: &GameState) -> String {
+        let world = state.world().borrow();
+        let objs = world.objects();
+        let dude = objs.get(objs.dude());
+        let pos = dude.pos();
+        let critters = world.critters().alive().count();
+        format!("ticks: {}\n\
+                 game time: {}\n\
+                 dude: tile {}, elevation {}\n\
+                 objects: {}\n\
+                 live critters: {}",
+            self.done,
+            world.game_time.as_decis(),
+            world.hex_grid().rect_to_linear_inv(pos.point)
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| "N/A".into()),
+            pos.elevation,
+            objs.iter().count(),
+            critters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tick() {
+        let mut h = Headless::new(2);
+        assert_eq!(h.tick(), Status::Running);
+        assert_eq!(h.tick(), Status::Done);
+        assert_eq!(h.ticks_done(), 2);
+    }
+}
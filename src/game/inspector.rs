@@ -0,0 +1,189 @@
+//! Object inspector window: shows the fields of the object clicked in the inspect mode and edits
+//! them. The mode is toggled with the `inspect` console command.
+
+use crate::game::object::{self, Object, Objects};
+use crate::game::script::Script;
+use crate::game::ui::inspector::InspectorView;
+use crate::graphics::Rect;
+use crate::ui::command::InspectorCommand;
+use crate::ui::*;
+
+/// Editable field of the inspected object.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Field {
+    Fid,
+    Flags,
+    LocalVar(usize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    pub text: String,
+    /// Field the line shows if it's editable.
+    pub field: Option<Field>,
+}
+
+impl Line {
+    fn new(text: String) -> Self {
+        Self {
+            text,
+            field: None,
+        }
+    }
+
+    fn field(field: Field, text: String) -> Self {
+        Self {
+            text,
+            field: Some(field),
+        }
+    }
+}
+
+/// Describes the object `obj`. `script` is the object's script instance with its program name.
+pub fn describe(obj: &Object, objects: &Objects, script: Option<(&Script, &str)>,
+    sequence_running: bool) -> Vec<Line>
+{
+    let mut r = Vec::new();
+    r.push(Line::new(format!("{:?} {:?}", obj.kind(), obj.handle())));
+    r.push(Line::new(format!("PID: {}", obj.proto_id()
+        .map(|pid| format!("0x{:08x} {:?}", pid.pack(), pid))
+        .unwrap_or_else(|| "-".into()))));
+    r.push(Line::field(Field::Fid, format!("FID: 0x{:08x} {:?}", obj.fid.packed(), obj.fid)));
+    r.push(Line::field(Field::Flags, format!("flags: 0x{:08x} {:?}", obj.flags.bits(),
+        obj.flags)));
+    r.push(Line::new(format!("position: {}", obj.try_pos()
+        .map(|p| format!("{}, {} elevation {}", p.point.x, p.point.y, p.elevation))
+        .unwrap_or_else(|| "-".into()))));
+    r.push(Line::new(format!("sequence: {}", if sequence_running { "running" } else { "-" })));
+
+    match (obj.script, script) {
+        (Some((sid, _)), Some((script, name))) => {
+            r.push(Line::new(format!("script: 0x{:08x} {}", sid.pack(), name)));
+            for (i, v) in script.local_vars.iter().enumerate() {
+                r.push(Line::field(Field::LocalVar(i), format!("  LVAR {}: {}", i, v)));
+            }
+        }
+        (Some((sid, _)), None) => r.push(Line::new(format!("script: 0x{:08x} (not instantiated)",
+            sid.pack()))),
+        (None, _) => r.push(Line::new("script: -".into())),
+    }
+
+    if obj.inventory.items.is_empty() {
+        r.push(Line::new("inventory: -".into()));
+    } else {
+        r.push(Line::new("inventory:".into()));
+        for item in &obj.inventory.items {
+            let pid = objects.get(item.object).proto_id();
+            r.push(Line::new(format!("  {} x {:?} {:?}", item.count, pid, item.object)));
+        }
+    }
+    r
+}
+
+pub struct Inspector {
+    window: Option<Handle>,
+    view: Option<Handle>,
+    /// Whether clicking an object inspects it instead of acting on it.
+    armed: bool,
+    obj: Option<object::Handle>,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            view: None,
+            armed: false,
+            obj: None,
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    pub fn set_armed(&mut self, armed: bool) {
+        self.armed = armed;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// The object shown in the window.
+    pub fn obj(&self) -> Option<object::Handle> {
+        self.obj
+    }
+
+    /// Shows the window with the `lines` describing the `obj`.
+    pub fn show(&mut self, obj: object::Handle, lines: Vec<Line>, ui: &mut Ui) {
+        if self.window.is_none() {
+            let rect = Rect::with_size(0, 0, 640, 380);
+            let window = ui.new_window(rect, None);
+            ui.widget_base_mut(window).set_modal(true);
+
+            let view = InspectorView::new(ui.fonts().clone());
+            let view = ui.new_widget(window, rect, None, None, view);
+            ui.set_keyboard_focus(Some(view));
+
+            self.window = Some(window);
+            self.view = Some(view);
+        }
+        self.obj = Some(obj);
+        self.refresh(lines, String::new(), ui);
+    }
+
+    /// Updates the shown `lines` and the `status` line, for example with the edit result.
+    pub fn refresh(&mut self, lines: Vec<Line>, status: String, ui: &mut Ui) {
+        if let Some(view) = self.view {
+            let mut view = ui.widget_mut::<InspectorView>(view);
+            view.set_lines(lines);
+            view.set_status(status);
+        }
+    }
+
+    pub fn hide(&mut self, ui: &mut Ui) {
+        if let Some(window) = self.window.take() {
+            ui.remove(window);
+            self.view = None;
+            self.obj = None;
+        }
+    }
+
+    /// Handles the command from the inspector view. Returns the edited field with the entered
+    /// value.
+    pub fn handle(&mut self, command: InspectorCommand, ui: &mut Ui) -> Option<(Field, String)> {
+        let view = self.view?;
+        match command {
+            InspectorCommand::Apply => ui.widget_mut::<InspectorView>(view).take_edit(),
+            InspectorCommand::Hide => {
+                self.hide(ui);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::EntityKind;
+    use crate::asset::frame::FrameId;
+    use crate::game::world::World;
+    use std::time::Instant;
+
+    #[test]
+    fn describe_() {
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+        let fid = FrameId::new_generic(EntityKind::Scenery, 1).unwrap();
+        let h = objs.create(Some(fid), None, Some((0, (10, 12)).into()), None).handle();
+        let lines = describe(&objs.get(h), objs, None, true);
+        let editable: Vec<_> = lines.iter().filter_map(|l| l.field).collect();
+        assert_eq!(editable, vec![Field::Fid, Field::Flags]);
+        assert!(lines.iter().any(|l| l.text == "position: 10, 12 elevation 0"));
+        assert!(lines.iter().any(|l| l.text == "sequence: running"));
+        assert!(lines.iter().any(|l| l.text == "script: -"));
+        assert!(lines.iter().any(|l| l.text == "inventory: -"));
+    }
+}
@@ -2,17 +2,19 @@ use bstring::{bstr, BString};
 use bstring::bfmt::ToBString;
 use if_chain::if_chain;
 use sdl2::mouse::MouseButton;
+use std::rc::Rc;
 use std::time::Duration;
 
 use crate::asset::*;
 use crate::asset::frame::FrameId;
-use crate::asset::message::{Messages, MessageId};
-use crate::fs::FileSystem;
-use crate::game::object::{self, EquipmentSlot, Hand, Object, InventoryItem};
+use crate::asset::message::{format, Messages, MessageId};
+use crate::asset::message::localization::Localization;
+use crate::error;
+use crate::game::object::{self, EquipmentSlot, Hand, Object, Objects, InventoryItem};
 use crate::game::rpg::Rpg;
 use crate::game::ui::action_menu::{self, Action};
 use crate::game::ui::inventory_list::{self, InventoryList, Scroll, MouseMode};
-use crate::game::ui::move_window::MoveWindow;
+use crate::game::ui::move_window::StackMoveWindow;
 use crate::game::world::WorldRef;
 use crate::graphics::{Point, Rect};
 use crate::graphics::color::{GREEN, RED};
@@ -26,7 +28,6 @@ use crate::ui::command::{move_window, UiCommand, UiCommandData};
 use crate::ui::command::inventory::Command;
 use crate::ui::panel::{self, Panel};
 use crate::ui::sequence::background_anim::BackgroundAnim;
-use crate::util::sprintf;
 
 const MSG_NO_ITEM: MessageId = 14;
 const MSG_DMG: MessageId = 15;
@@ -37,14 +38,14 @@ const MSG_TOTAL_WEIGHT: MessageId = 20;
 const MSG_UNARMED_DMG: MessageId = 24;
 
 pub struct Inventory {
-    msgs: Option<Messages>,
+    msgs: Rc<Messages>,
     world: WorldRef,
     internal: Option<Internal>
 }
 
 impl Inventory {
-    pub fn new(world: WorldRef, fs: &FileSystem, language: &str) -> Self {
-        let msgs = Some(Messages::read_file(fs, language, "game/inventry.msg").unwrap());
+    pub fn new(world: WorldRef, loc: &Localization) -> Self {
+        let msgs = loc.messages("game/inventry.msg").unwrap();
         Self {
             msgs,
             world,
@@ -52,6 +53,13 @@ impl Inventory {
         }
     }
 
+    /// Loads the messages of the current language of `loc`. The open window keeps the old
+    /// texts until it's shown again.
+    pub fn reload_messages(&mut self, loc: &Localization) -> error::Result<()> {
+        self.msgs = loc.messages("game/inventry.msg")?;
+        Ok(())
+    }
+
     pub fn is_visible(&self) -> bool {
         self.internal.is_some()
     }
@@ -77,18 +85,24 @@ impl Inventory {
         self.internal.as_ref().unwrap().examine(obj, description, ui);
     }
 
-    fn show(&mut self, rpg: &Rpg, ui: &mut Ui, ui_sequencer: &mut Sequencer) {
+    /// Updates the inventory window after the owner's inventory was changed outside of it.
+    pub fn sync(&self, rpg: &Rpg, ui: &Ui) {
+        if let Some(v) = self.internal.as_ref() {
+            v.sync_to_ui(rpg, ui);
+        }
+    }
+
+    pub fn show(&mut self, rpg: &Rpg, ui: &mut Ui, ui_sequencer: &mut Sequencer) {
         let owner = self.world.borrow().objects().dude();
         let internal = Internal::new(
-            self.msgs.take().unwrap(), self.world.clone(), owner, ui, ui_sequencer);
+            self.msgs.clone(), self.world.clone(), owner, ui, ui_sequencer);
         internal.sync_mouse_mode_to_ui(ui);
         internal.sync_to_ui(rpg, ui);
         assert!(self.internal.replace(internal).is_none());
     }
 
-    fn hide(&mut self, ui: &mut Ui) {
-        let i = self.internal.take().unwrap();
-        self.msgs = Some(i.hide(ui));
+    pub fn hide(&mut self, ui: &mut Ui) {
+        self.internal.take().unwrap().hide(ui);
     }
 }
 
@@ -99,7 +113,7 @@ enum Slot {
 }
 
 struct Internal {
-    msgs: Messages,
+    msgs: Rc<Messages>,
     world: WorldRef,
     owner: object::Handle,
     win: ui::Handle,
@@ -119,7 +133,7 @@ struct Internal {
     total_weight: ui::Handle,
 
     action_menu: Option<ui::Handle>,
-    move_window: Option<InventoryMoveWindow>,
+    move_window: Option<StackMoveWindow<MovePurpose>>,
 
     owner_image: ui::Handle,
     owner_image_seq: Cancel,
@@ -127,7 +141,7 @@ struct Internal {
 
 impl Internal {
     fn new(
-        msgs: Messages,
+        msgs: Rc<Messages>,
         world: WorldRef,
         owner: object::Handle,
         ui: &mut Ui,
@@ -252,10 +266,9 @@ impl Internal {
         }
     }
 
-    fn hide(self, ui: &mut Ui) -> Messages {
+    fn hide(self, ui: &mut Ui) {
         ui.remove(self.win);
         self.owner_image_seq.cancel();
-        self.msgs
     }
 
     fn sync_to_ui(&self, rpg: &Rpg, ui: &Ui) {
@@ -275,7 +288,7 @@ impl Internal {
         let owner = world.objects().get(self.owner);
         for item in &owner.inventory.items {
             let item_obj = &world.objects().get(item.object);
-            let inv_list_item = Self::make_list_item(item, item_obj);
+            let inv_list_item = make_list_item(item, item_obj);
             match () {
                 _ if item_obj.flags.contains(Flag::Worn) => {
                     assert!(wearing.items().is_empty());
@@ -300,17 +313,6 @@ impl Internal {
         ui.widget_base_mut(self.owner_image).background_mut().unwrap().fid = owner.fid;
     }
 
-    // display_inventory_info
-    fn make_list_item(item: &InventoryItem, obj: &Object) -> inventory_list::Item {
-        let proto = obj.proto().unwrap();
-        let count = obj.total_ammo_count(item.count).unwrap_or(item.count);
-        inventory_list::Item {
-            object: item.object,
-            fid: proto.sub.as_item().unwrap().inventory_fid.unwrap(),
-            count,
-        }
-    }
-
     fn scroll(&self, scroll: Scroll, ui: &Ui) {
         let list = &mut ui.widget_mut::<InventoryList>(self.list);
         list.scroll(scroll);
@@ -492,17 +494,11 @@ impl Internal {
             }
         }
 
-        let mut total_weight = BString::new();
-        if owner.kind() == EntityKind::Critter {
-            let cw = stat(Stat::CarryWeight);
-            let w = owner.inventory.weight(world.objects());
-            // Total Wt: 100/200
-            total_weight.push_str(msg(MSG_TOTAL_WEIGHT));
-            total_weight.push(b' ');
-            total_weight.push_str(w.to_bstring());
-            total_weight.push(b'/');
-            total_weight.push_str(cw.to_bstring());
-        }
+        let total_weight = if owner.kind() == EntityKind::Critter {
+            total_weight_text(owner, &self.msgs, rpg, world.objects())
+        } else {
+            BString::new()
+        };
         let overloaded = owner.is_overloaded(rpg, world.objects());
         {
             let mut w = ui.widget_mut::<Panel>(self.total_weight);
@@ -526,9 +522,10 @@ impl Internal {
         let obj = world.objects().get(obj);
         let weight = obj.item_weight(world.objects()).filter(|&v| v > 0).unwrap_or(0);
         let weight_msg = if weight > 0 {
-            let msg_id = if weight == 1 { 541 } else { 540 };
-            let msg = &world.proto_db().messages().get(msg_id).unwrap().text;
-            sprintf(msg, &[&weight.to_bstring()])
+            // It weighs %d pound(s).
+            let msgs = world.proto_db().messages();
+            let msg = &msgs.get_plural(&[541, 540], weight as i64).unwrap().text;
+            format::format(msg, &[weight.into()])
         } else {
             "".into()
         };
@@ -680,26 +677,20 @@ impl Internal {
             });
         }
 
+        drop(world);
+        let mut world = self.world.borrow_mut();
         for action in actions {
-            let owner = &mut world.objects().get_mut(self.owner);
             match action {
-                Action::MoveTo { item, slot } => {
-                    let mut item = world.objects().get_mut(item);
-                    item.flags.remove(Flag::Worn | Flag::LeftHand | Flag::RightHand);
-                    match slot {
-                        Slot::Inventory => {
-                            let i = owner.inventory.items.iter()
-                                .position(|i| i.object == item.handle()).unwrap();
-                            if i > 0 {
-                                let item = owner.inventory.items.remove(i);
-                                owner.inventory.items.insert(0, item);
-                            }
-                        }
-                        Slot::Equipment(eq_slot) => match eq_slot {
-                            EquipmentSlot::Armor => item.flags.insert(Flag::Worn),
-                            EquipmentSlot::Hand(Hand::Left) => item.flags.insert(Flag::LeftHand),
-                            EquipmentSlot::Hand(Hand::Right) => item.flags.insert(Flag::RightHand),
-                        }
+                Action::MoveTo { item, slot } => match slot {
+                    Slot::Inventory => {
+                        world.objects_mut().restack_inventory_item(self.owner, item);
+                    }
+                    Slot::Equipment(eq_slot) => {
+                        // Only one item of the stack goes to the slot.
+                        let item = world.objects_mut().split_inventory_item(self.owner, item, 1);
+                        let mut item = world.objects().get_mut(item);
+                        item.flags.remove(Flag::Worn | Flag::LeftHand | Flag::RightHand);
+                        item.flags.insert(eq_slot.flag());
                     }
                 }
                 Action::Reload { weapon, max_count } => {
@@ -710,8 +701,8 @@ impl Internal {
                         let ammo = ammo.sub.as_item_mut().unwrap();
                         ammo.ammo_count = ammo.ammo_count.checked_sub(max_count).unwrap();
                     } else {
-                        let win = InventoryMoveWindow::show(
-                            weapon,
+                        let win = StackMoveWindow::show(
+                            MovePurpose::Reload { weapon },
                             &world.objects().get(src_obj),
                             max_count,
                             &self.msgs,
@@ -720,6 +711,7 @@ impl Internal {
                     }
                 }
                 Action::ArmorChange { old_armor, new_armor } => {
+                    let owner = &mut world.objects().get_mut(self.owner);
                     let old_armor = old_armor.map(|obj| world.objects().get(obj));
                     let new_armor = new_armor.map(|obj| world.objects().get(obj));
                     rpg.apply_armor_change(owner, old_armor.as_deref(), new_armor.as_deref(), world.objects());
@@ -737,6 +729,42 @@ impl Internal {
         owner.fid = owner.equipped_fid(world.objects(), rpg);
     }
 
+    // inven_action_cursor
+    fn request_drop(&mut self, item: object::Handle, rpg: &Rpg, ui: &mut Ui) {
+        let count = {
+            let world = self.world.borrow();
+            let owner = world.objects().get(self.owner);
+            owner.inventory.items.iter().find(|i| i.object == item).unwrap().count
+        };
+        if count > 1 {
+            let win = StackMoveWindow::show(
+                MovePurpose::Drop,
+                &self.world.borrow().objects().get(item),
+                count,
+                &self.msgs,
+                ui);
+            assert!(self.move_window.replace(win).is_none());
+        } else {
+            self.drop_item(item, 1, rpg, ui);
+        }
+    }
+
+    /// Drops `count` of `item` onto the owner's hex.
+    fn drop_item(&self, item: object::Handle, count: u32, rpg: &Rpg, ui: &Ui) {
+        {
+            let mut world = self.world.borrow_mut();
+            if world.objects().get(item).flags.contains(Flag::Worn) {
+                let objs = world.objects();
+                let owner = &mut objs.get_mut(self.owner);
+                rpg.apply_armor_change(owner, Some(&objs.get(item)), None, objs);
+            }
+            let pos = world.objects().get(self.owner).pos();
+            world.objects_mut().drop_from_inventory(self.owner, item, count, pos);
+        }
+        self.sync_owner_fid(rpg);
+        self.sync_to_ui(rpg, ui);
+    }
+
     fn unload(&self, weapon: object::Handle, rpg: &Rpg, ui: &Ui) {
         {
             let mut world = self.world.borrow_mut();
@@ -759,8 +787,10 @@ impl Internal {
                 }
                 Command::Action { object, action } => {
                     self.hide_action_menu(ui);
-                    if let Some(Action::Unload) = action {
-                        self.unload(object, rpg, ui);
+                    match action {
+                        Some(Action::Drop) => self.request_drop(object, rpg, ui),
+                        Some(Action::Unload) => self.unload(object, rpg, ui),
+                        _ => {}
                     }
                 }
                 Command::ListDrop { pos, object } => {
@@ -771,11 +801,17 @@ impl Internal {
                 }
             }
             UiCommandData::MoveWindow(move_window::Command::Hide { ok }) => {
-                let win = self.move_window.take().unwrap();
+                // The window can also be shown by the game state to set explosive timer.
+                let win = unwrap_or_return!(self.move_window.take(), Some);
                 if ok {
-                    self.world.borrow_mut().objects_mut()
-                        .reload_weapon_from_inventory(self.owner, win.weapon, win.ammo);
-                    self.sync_to_ui(rpg, ui);
+                    match win.purpose {
+                        MovePurpose::Reload { weapon } => {
+                            self.world.borrow_mut().objects_mut()
+                                .reload_weapon_from_inventory(self.owner, weapon, win.item);
+                            self.sync_to_ui(rpg, ui);
+                        }
+                        MovePurpose::Drop => self.drop_item(win.item, win.win.value(), rpg, ui),
+                    }
                 }
                 win.win.hide(ui);
             }
@@ -787,6 +823,35 @@ impl Internal {
     }
 }
 
+/// Makes the inventory list entry showing the `item` stack. For ammo the count is the total
+/// number of rounds in the stack.
+// display_inventory_info
+pub fn make_list_item(item: &InventoryItem, obj: &Object) -> inventory_list::Item {
+    let proto = obj.proto().unwrap();
+    let count = obj.total_ammo_count(item.count).unwrap_or(item.count);
+    inventory_list::Item {
+        object: item.object,
+        fid: proto.sub.as_item().unwrap().inventory_fid.unwrap(),
+        count,
+    }
+}
+
+/// Formats total weight of the `owner` inventory followed by the carry weight if the `owner` is
+/// a critter: `Total Wt: 100/200`. Applies to the dude as well as party members and corpses.
+pub fn total_weight_text(owner: &Object, msgs: &Messages, rpg: &Rpg, objects: &Objects)
+    -> BString
+{
+    let mut r = BString::new();
+    r.push_str(&msgs.get(MSG_TOTAL_WEIGHT).unwrap().text);
+    r.push(b' ');
+    r.push_str(owner.inventory.weight(objects).to_bstring());
+    if owner.kind() == EntityKind::Critter {
+        r.push(b'/');
+        r.push_str(rpg.stat(Stat::CarryWeight, owner, objects).to_bstring());
+    }
+    r
+}
+
 struct MouseModeToggler;
 
 impl Widget for MouseModeToggler {
@@ -797,26 +862,10 @@ impl Widget for MouseModeToggler {
     }
 }
 
-struct InventoryMoveWindow {
-    weapon: object::Handle,
-    ammo: object::Handle,
-    win: MoveWindow,
-}
-
-impl InventoryMoveWindow {
-    pub fn show(
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum MovePurpose {
+    Reload {
         weapon: object::Handle,
-        ammo: &Object,
-        max: u32,
-        msgs: &Messages,
-        ui: &mut Ui,
-    ) -> Self {
-        let fid = ammo.proto().unwrap().sub.as_item().unwrap().inventory_fid.unwrap();
-        let win = MoveWindow::show(fid, max, msgs, ui);
-        Self {
-            weapon,
-            ammo: ammo.handle(),
-            win,
-        }
-    }
+    },
+    Drop,
 }
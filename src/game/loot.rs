@@ -0,0 +1,311 @@
+use std::rc::Rc;
+
+use crate::asset::{EntityKind, Flag};
+use crate::asset::frame::FrameId;
+use crate::asset::message::Messages;
+use crate::game::inventory::{make_list_item, total_weight_text};
+use crate::game::object;
+use crate::game::rpg::Rpg;
+use crate::game::ui::inventory_list::{InventoryList, Scroll};
+use crate::game::ui::move_window::StackMoveWindow;
+use crate::game::world::WorldRef;
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::{GREEN, RED};
+use crate::graphics::font::{DrawOptions, FontKey, HorzAlign};
+use crate::graphics::sprite::{Anchor, Sprite};
+use crate::ui::{self, Ui, button, Cursor};
+use crate::ui::button::Button;
+use crate::ui::command::{move_window, UiCommand, UiCommandData};
+use crate::ui::command::inventory;
+use crate::ui::command::loot::{Command, Side};
+use crate::ui::panel::{self, Panel};
+
+/// Screen for moving items between the looter's inventory and a corpse.
+// loot_container
+pub struct Loot {
+    msgs: Rc<Messages>,
+    world: WorldRef,
+    looter: object::Handle,
+    target: object::Handle,
+    win: ui::Handle,
+    lists: [SideList; 2],
+    move_window: Option<StackMoveWindow<Side>>,
+}
+
+impl Loot {
+    /// Shows the screen. The `msgs` are the `inventry.msg` messages.
+    pub fn show(
+        msgs: Rc<Messages>,
+        world: WorldRef,
+        looter: object::Handle,
+        target: object::Handle,
+        rpg: &Rpg,
+        ui: &mut Ui,
+    ) -> Self {
+        let win = ui.new_window(Rect::with_size(80, 0, 537, 376),
+            Some(Sprite::new(FrameId::LOOT)));
+        ui.widget_base_mut(win).set_modal(true);
+        ui.widget_base_mut(win).set_cursor(Some(Cursor::Hand));
+
+        let lists = [
+            SideList::new(win, Side::Looter, Point::new(44, 35), Point::new(176, 37),
+                Point::new(109, 56), ui),
+            SideList::new(win, Side::Target, Point::new(422, 35), Point::new(297, 37),
+                Point::new(379, 56), ui),
+        ];
+
+        ui.new_widget(win, Rect::with_size(432, 204, 39, 27), None, None,
+            Button::new(FrameId::INVMAUP, FrameId::INVMADN,
+                Some(UiCommandData::Loot(Command::TakeAll))));
+        ui.new_widget(win, Rect::with_size(476, 331, 15, 16), None, None,
+            Button::new(FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN,
+                Some(UiCommandData::Loot(Command::Hide))));
+
+        let r = Self {
+            msgs,
+            world,
+            looter,
+            target,
+            win,
+            lists,
+            move_window: None,
+        };
+        r.sync_to_ui(rpg, ui);
+        r
+    }
+
+    pub fn hide(self, ui: &mut Ui) {
+        if let Some(v) = self.move_window {
+            v.win.hide(ui);
+        }
+        ui.remove(self.win);
+    }
+
+    /// Handles commands of the screen's widgets. Returns `false` if the looter couldn't carry
+    /// the items.
+    pub fn handle(&mut self, cmd: UiCommand, rpg: &Rpg, ui: &mut Ui) -> bool {
+        let mut r = true;
+        match cmd.data {
+            UiCommandData::Inventory(inventory::Command::ListDrop { pos, object }) => {
+                if let Some(from) = self.side_of_list(cmd.source) {
+                    r = self.handle_list_drop(from, pos, object, rpg, ui);
+                }
+            }
+            UiCommandData::Inventory(inventory::Command::Scroll(scroll)) => {
+                if let Some(side) = self.side_of_list(cmd.source) {
+                    self.scroll(side, scroll, ui);
+                }
+            }
+            UiCommandData::Loot(Command::Scroll { side, scroll }) => {
+                self.scroll(side, scroll, ui);
+            }
+            UiCommandData::Loot(Command::TakeAll) => {
+                r = take_all(&self.world, self.looter, self.target, rpg);
+                self.sync_to_ui(rpg, ui);
+            }
+            UiCommandData::MoveWindow(move_window::Command::Hide { ok }) => {
+                if let Some(win) = self.move_window.take() {
+                    if ok {
+                        r = self.move_items(win.purpose, win.item, win.win.value(), rpg);
+                        self.sync_to_ui(rpg, ui);
+                    }
+                    win.win.hide(ui);
+                }
+            }
+            _ => {}
+        }
+        if let Some(v) = self.move_window.as_mut() {
+            v.win.handle(cmd, ui);
+        }
+        r
+    }
+
+    fn side_of_list(&self, list: ui::Handle) -> Option<Side> {
+        self.lists.iter().find(|l| l.list == list).map(|l| l.side)
+    }
+
+    fn owner(&self, side: Side) -> object::Handle {
+        match side {
+            Side::Looter => self.looter,
+            Side::Target => self.target,
+        }
+    }
+
+    fn side_list(&self, side: Side) -> &SideList {
+        self.lists.iter().find(|l| l.side == side).unwrap()
+    }
+
+    fn handle_list_drop(&mut self,
+        from: Side,
+        pos: Point,
+        item: object::Handle,
+        rpg: &Rpg,
+        ui: &mut Ui,
+    ) -> bool {
+        let to = match from {
+            Side::Looter => Side::Target,
+            Side::Target => Side::Looter,
+        };
+        if ui.widget_at(pos) != Some(self.side_list(to).list) {
+            return true;
+        }
+        let count = {
+            let world = self.world.borrow();
+            let owner = world.objects().get(self.owner(from));
+            owner.inventory.items.iter().find(|i| i.object == item).unwrap().count
+        };
+        if count > 1 {
+            let win = StackMoveWindow::show(from, &self.world.borrow().objects().get(item), count,
+                &self.msgs, ui);
+            assert!(self.move_window.replace(win).is_none());
+            true
+        } else {
+            let r = self.move_items(from, item, 1, rpg);
+            self.sync_to_ui(rpg, ui);
+            r
+        }
+    }
+
+    /// Moves `count` of `item` from the inventory on the `from` side to the other one.
+    fn move_items(&self, from: Side, item: object::Handle, count: u32, rpg: &Rpg) -> bool {
+        let (from, to) = match from {
+            Side::Looter => (self.looter, self.target),
+            Side::Target => (self.target, self.looter),
+        };
+        let mut world = self.world.borrow_mut();
+        if to == self.looter && !world.objects().can_carry(to, item, count, rpg) {
+            return false;
+        }
+        world.objects_mut().move_between_inventories(from, to, item, count);
+        true
+    }
+
+    fn scroll(&self, side: Side, scroll: Scroll, ui: &Ui) {
+        let side = self.side_list(side);
+        let list = &mut ui.widget_mut::<InventoryList>(side.list);
+        list.scroll(scroll);
+        side.update_scroll_buttons(list, ui);
+    }
+
+    fn sync_to_ui(&self, rpg: &Rpg, ui: &Ui) {
+        let world = self.world.borrow();
+        for side in &self.lists {
+            let list = &mut ui.widget_mut::<InventoryList>(side.list);
+            let scroll_idx = list.scroll_idx();
+            list.clear();
+
+            let owner = world.objects().get(self.owner(side.side));
+            for item in &owner.inventory.items {
+                let itemo = world.objects().get(item.object);
+                // The looter's equipped items stay out of reach.
+                if side.side == Side::Looter
+                    && itemo.flags.intersects(Flag::Worn | Flag::LeftHand | Flag::RightHand)
+                {
+                    continue;
+                }
+                list.push(make_list_item(item, &itemo));
+            }
+
+            list.set_scroll_idx(scroll_idx);
+            side.update_scroll_buttons(list, ui);
+
+            ui.widget_base_mut(side.image).background_mut().unwrap().fid = owner.fid;
+
+            let overloaded = owner.kind() == EntityKind::Critter
+                && owner.is_overloaded(rpg, world.objects());
+            let mut weight = ui.widget_mut::<Panel>(side.weight);
+            let weight = weight.text_mut().unwrap();
+            weight.text = total_weight_text(&owner, &self.msgs, rpg, world.objects());
+            weight.color = if overloaded { RED } else { GREEN };
+        }
+    }
+}
+
+/// Moves every item of the `target` the `looter` can carry into the `looter`'s inventory.
+/// Returns `false` if some of the items were too heavy.
+pub fn take_all(world: &WorldRef, looter: object::Handle, target: object::Handle, rpg: &Rpg)
+    -> bool
+{
+    let mut world = world.borrow_mut();
+    let items: Vec<_> = world.objects().get(target).inventory.items.iter()
+        .map(|i| (i.object, i.count))
+        .collect();
+    let mut r = true;
+    for (item, count) in items {
+        if world.objects().can_carry(looter, item, count, rpg) {
+            world.objects_mut().move_between_inventories(target, looter, item, count);
+        } else {
+            r = false;
+        }
+    }
+    r
+}
+
+/// Item list of one side of the screen with its scroll buttons, the owner's image and the total
+/// weight of the owner's inventory.
+struct SideList {
+    side: Side,
+    image: ui::Handle,
+    list: ui::Handle,
+    weight: ui::Handle,
+    scroll_up: ui::Handle,
+    scroll_down: ui::Handle,
+}
+
+impl SideList {
+    fn new(win: ui::Handle, side: Side, image_pos: Point, list_pos: Point, scroll_pos: Point,
+        ui: &mut Ui) -> Self
+    {
+        let mut image = Sprite::new(FrameId::BLANK);
+        image.anchor = Anchor::Center;
+        let image = ui.new_widget(win, Rect::with_size(image_pos.x, image_pos.y, 60, 100), None,
+            Some(image), Panel::new());
+
+        let list = ui.new_widget(win, Rect::with_size(list_pos.x, list_pos.y, 56, 50 * 6),
+            None, None, InventoryList::new(40, 10));
+
+        let mut weight = Panel::new();
+        weight.set_text(Some(panel::Text {
+            text: "".into(),
+            font: FontKey::antialiased(1),
+            color: GREEN,
+            options: DrawOptions {
+                horz_align: HorzAlign::Center,
+                ..Default::default()
+            },
+        }));
+        // Centered under the list.
+        let weight = ui.new_widget(win, Rect::with_size(list_pos.x - 32, list_pos.y + 50 * 6 + 4,
+            120, 1), None, None, weight);
+
+        let mut scroll_up = Button::new(FrameId::INVENTORY_SCROLL_UP_UP,
+            FrameId::INVENTORY_SCROLL_UP_DOWN,
+            Some(UiCommandData::Loot(Command::Scroll { side, scroll: Scroll::Up })));
+        scroll_up.config_mut(button::State::Disabled).background =
+            Some(Sprite::new(FrameId::INVENTORY_SCROLL_UP_DISABLED));
+        let scroll_up = ui.new_widget(win, Rect::with_size(scroll_pos.x, scroll_pos.y, 22, 23),
+            None, None, scroll_up);
+
+        let mut scroll_down = Button::new(FrameId::INVENTORY_SCROLL_DOWN_UP,
+            FrameId::INVENTORY_SCROLL_DOWN_DOWN,
+            Some(UiCommandData::Loot(Command::Scroll { side, scroll: Scroll::Down })));
+        scroll_down.config_mut(button::State::Disabled).background =
+            Some(Sprite::new(FrameId::INVENTORY_SCROLL_DOWN_DISABLED));
+        let scroll_down = ui.new_widget(win,
+            Rect::with_size(scroll_pos.x, scroll_pos.y + 26, 22, 23), None, None, scroll_down);
+
+        Self {
+            side,
+            image,
+            list,
+            weight,
+            scroll_up,
+            scroll_down,
+        }
+    }
+
+    fn update_scroll_buttons(&self, list: &InventoryList, ui: &Ui) {
+        ui.widget_mut::<Button>(self.scroll_up).set_enabled(list.can_scroll(Scroll::Up));
+        ui.widget_mut::<Button>(self.scroll_down).set_enabled(list.can_scroll(Scroll::Down));
+    }
+}
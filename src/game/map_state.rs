@@ -0,0 +1,764 @@
+use bstring::BString;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use enumflags2::BitFlags;
+use enum_primitive_derive::Primitive;
+use log::*;
+use num_traits::FromPrimitive;
+use slotmap::{SecondaryMap, SlotMap};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Error, ErrorKind, prelude::*};
+use std::path::PathBuf;
+
+use crate::asset::Flag;
+use crate::asset::frame::FrameId;
+use crate::asset::map::MapId;
+use crate::asset::proto::{MapExit, ProtoDb, ProtoId, TargetMap, WorldMapKind};
+use crate::asset::script::ProgramId;
+use crate::fs::manifest::Manifest;
+use crate::game::GameTime;
+use crate::game::explosive::Explosive;
+use crate::game::object::*;
+use crate::game::script::{MapScripts, Scripts, ScriptIid};
+use crate::game::timer::ObjectTimers;
+use crate::game::world::World;
+use crate::graphics::{EPoint, Point};
+use crate::graphics::geometry::hex::Direction;
+
+const MAGIC: &[u8; 8] = b"V13MAPS1";
+
+/// Dynamic state of a map that was left: objects with their inventories, positions, frames and
+/// flags, timers of the armed explosives, scripts with their local vars and the map vars. When
+/// the map is entered again it's restored instead of the objects and scripts from the map file.
+pub struct MapState {
+    /// Top-level objects and their positions.
+    objects: Vec<(ObjectGraph, Option<EPoint>)>,
+    /// Timers of the saved objects. The handles are the ones of `objects`.
+    explosive_timers: ObjectTimers<Explosive>,
+    scripts: MapScripts,
+}
+
+impl MapState {
+    /// Takes the objects and their explosive timers out of `world` and saves the state of the
+    /// `scripts`. The dude must be removed by the caller. Temporary objects aren't saved.
+    // map_save_in_game
+    pub fn save(world: &mut World, scripts: &Scripts) -> Self {
+        let objects = take_objects(world.objects_mut());
+        let saved: HashSet<Handle> = objects.iter()
+            .flat_map(|(graph, _)| graph.objects.keys())
+            .collect();
+        let explosive_timers = world.explosive_timers.take_if(|h| saved.contains(&h));
+        debug!("saved map state: {} top-level objects, {} explosive timers",
+            objects.len(), explosive_timers.len());
+        Self {
+            objects,
+            explosive_timers,
+            scripts: scripts.save_map(),
+        }
+    }
+
+    /// Inserts the saved objects and their explosive timers into `world` and replaces
+    /// the `scripts` with the saved ones. The timers that became due while the map was left go off
+    /// on the next update. The objects read from the map file must be removed by the caller.
+    /// If a script can't be loaded the objects are still restored and the error is returned.
+    // map_load_in_game
+    pub fn restore(self, world: &mut World, scripts: &mut Scripts) -> io::Result<()> {
+        let scripts_restored = scripts.restore_map(self.scripts);
+        let objects = world.objects_mut();
+        let (inserted, handle_map) = insert_objects(objects, self.objects);
+        for obj in inserted {
+            let script = objects.get(obj).script;
+            if let Some((sid, _)) = script {
+                if scripts.get(sid).is_some() {
+                    scripts.attach_to_object(sid, obj);
+                }
+            }
+        }
+        let mut explosive_timers = self.explosive_timers;
+        explosive_timers.remap(&handle_map);
+        world.explosive_timers.append(explosive_timers);
+        scripts_restored
+    }
+
+    /// Writes the state. Combat targets, outlines and screen positions of the objects aren't
+    /// written.
+    pub fn write(&self, wr: &mut impl Write) -> io::Result<()> {
+        let mut indices = SecondaryMap::new();
+        let mut graphs = Vec::with_capacity(self.objects.len());
+        for (graph, _) in &self.objects {
+            // The root goes first.
+            let order: Vec<_> = Some(graph.root).into_iter()
+                .chain(graph.objects.keys().filter(|&h| h != graph.root))
+                .collect();
+            for &h in &order {
+                indices.insert(h, indices.len() as u32);
+            }
+            graphs.push(order);
+        }
+
+        wr.write_u32::<BigEndian>(self.objects.len() as u32)?;
+        for ((graph, pos), order) in self.objects.iter().zip(graphs) {
+            write_pos(wr, *pos)?;
+            wr.write_u32::<BigEndian>(order.len() as u32)?;
+            for h in order {
+                write_object(wr, &graph.objects[h], &indices)?;
+            }
+        }
+
+        wr.write_u32::<BigEndian>(self.explosive_timers.len() as u32)?;
+        for timer in self.explosive_timers.iter() {
+            wr.write_u32::<BigEndian>(timer.time.as_decis())?;
+            wr.write_u32::<BigEndian>(indices[timer.obj])?;
+            wr.write_u32::<BigEndian>(timer.data.active_pid.pack())?;
+        }
+
+        self.scripts.write(wr)
+    }
+
+    pub fn read(rd: &mut impl Read, proto_db: &ProtoDb) -> io::Result<Self> {
+        let mut handles = SlotMap::with_key();
+        let mut by_index: Vec<Handle> = Vec::new();
+
+        let count = rd.read_u32::<BigEndian>()?;
+        let mut objects = Vec::new();
+        for _ in 0..count {
+            let pos = read_pos(rd)?;
+            let start = by_index.len();
+            let len = rd.read_u32::<BigEndian>()? as usize;
+            if len == 0 {
+                return Err(invalid_data("object graph without objects"));
+            }
+            for _ in 0..len {
+                by_index.push(handles.insert(()));
+            }
+            let mut graph_objects = SecondaryMap::new();
+            for &h in &by_index[start..] {
+                graph_objects.insert(h, read_object(rd, proto_db, &by_index)?);
+            }
+            objects.push((ObjectGraph {
+                root: by_index[start],
+                objects: graph_objects,
+            }, pos));
+        }
+
+        let count = rd.read_u32::<BigEndian>()?;
+        let mut explosive_timers = ObjectTimers::new();
+        for _ in 0..count {
+            let time = GameTime::from_decis(rd.read_u32::<BigEndian>()?);
+            let obj = read_index(rd, &by_index)?;
+            let pid = ProtoId::read(rd)?;
+            let explosive = Explosive::active(pid)
+                .ok_or_else(|| invalid_data(format!("{:?} is not an armed explosive", pid)))?;
+            explosive_timers.add(time, obj, explosive);
+        }
+
+        let scripts = MapScripts::read(rd)?;
+
+        Ok(Self {
+            objects,
+            explosive_timers,
+            scripts,
+        })
+    }
+}
+
+/// Serialized states of the maps that were left, the save-game map cache. The states are kept in
+/// files in the save dir if it's set and in memory otherwise. Each state is stored with
+/// the manifest of the resource providers it was made with.
+pub struct MapCache {
+    dir: Option<PathBuf>,
+    manifest: Manifest,
+    in_memory: HashMap<MapId, Vec<u8>>,
+}
+
+impl MapCache {
+    pub fn new(manifest: Manifest) -> Self {
+        Self {
+            dir: None,
+            manifest,
+            in_memory: HashMap::new(),
+        }
+    }
+
+    /// Keeps the states in files in `dir`. The states already in memory are moved there.
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        self.dir = Some(dir);
+        for (map_id, data) in std::mem::take(&mut self.in_memory) {
+            if let Err(e) = self.write_file(map_id, &data) {
+                warn!("couldn't save state of map {}: {}", map_id, e);
+            }
+        }
+    }
+
+    // map_save_in_game
+    pub fn put(&mut self, map_id: MapId, state: &MapState) {
+        let write = || -> io::Result<Vec<u8>> {
+            let mut data = Vec::new();
+            data.write_all(MAGIC)?;
+            self.manifest.write(&mut data)?;
+            state.write(&mut data)?;
+            Ok(data)
+        };
+        let r = write().and_then(|data| if self.dir.is_some() {
+            self.write_file(map_id, &data)
+        } else {
+            self.in_memory.insert(map_id, data);
+            Ok(())
+        });
+        if let Err(e) = r {
+            warn!("couldn't save state of map {}: {}", map_id, e);
+        }
+    }
+
+    /// Removes and returns the state of the map. Logs a warning and returns `None` if the state
+    /// can't be read, in which case the map is loaded from the map file. Warns if the state was
+    /// saved with different resource providers.
+    // map_load_in_game
+    pub fn take(&mut self, map_id: MapId, proto_db: &ProtoDb) -> Option<MapState> {
+        let r = if let Some(path) = self.path(map_id) {
+            match File::open(&path) {
+                Ok(f) => {
+                    let r = self.read(&mut BufReader::new(f), proto_db);
+                    if let Err(e) = fs::remove_file(&path) {
+                        warn!("couldn't remove {}: {}", path.display(), e);
+                    }
+                    r
+                }
+                Err(ref e) if e.kind() == ErrorKind::NotFound => return None,
+                Err(e) => Err(e),
+            }
+        } else {
+            let data = self.in_memory.remove(&map_id)?;
+            self.read(&mut Cursor::new(data), proto_db)
+        };
+        match r {
+            Ok(state) => Some(state),
+            Err(e) => {
+                warn!("couldn't read saved state of map {}, loading it from the map file: {}",
+                    map_id, e);
+                None
+            }
+        }
+    }
+
+    /// Removes the states of all maps.
+    pub fn clear(&mut self) {
+        self.in_memory.clear();
+        if let Some(dir) = &self.dir {
+            let entries = match fs::read_dir(dir) {
+                Ok(v) => v,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => return,
+                Err(e) => {
+                    warn!("couldn't clear map cache {}: {}", dir.display(), e);
+                    return;
+                }
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().map(|e| e == "sav").unwrap_or(false) {
+                    if let Err(e) = fs::remove_file(&path) {
+                        warn!("couldn't remove {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn read(&self, rd: &mut impl Read, proto_db: &ProtoDb) -> io::Result<MapState> {
+        let mut magic = [0; 8];
+        rd.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a map state file"));
+        }
+        Manifest::read(rd)?.check(&self.manifest);
+        MapState::read(rd, proto_db)
+    }
+
+    fn path(&self, map_id: MapId) -> Option<PathBuf> {
+        self.dir.as_ref().map(|d| d.join(format!("map{:03}.sav", map_id)))
+    }
+
+    fn write_file(&self, map_id: MapId, data: &[u8]) -> io::Result<()> {
+        let path = self.path(map_id).unwrap();
+        fs::create_dir_all(self.dir.as_ref().unwrap())?;
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(data)?;
+        w.flush()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Primitive)]
+enum SubObjectKind {
+    None = 0,
+    Critter = 1,
+    Item = 2,
+    Key = 3,
+    MapExit = 4,
+    Door = 5,
+    Elevator = 6,
+    Ladder = 7,
+    Stairs = 8,
+}
+
+fn invalid_data(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+fn write_pos(wr: &mut impl Write, pos: Option<EPoint>) -> io::Result<()> {
+    if let Some(pos) = pos {
+        wr.write_i32::<BigEndian>(pos.elevation as i32)?;
+        wr.write_i32::<BigEndian>(pos.point.x)?;
+        wr.write_i32::<BigEndian>(pos.point.y)
+    } else {
+        wr.write_i32::<BigEndian>(-1)
+    }
+}
+
+fn read_pos(rd: &mut impl Read) -> io::Result<Option<EPoint>> {
+    let elevation = rd.read_i32::<BigEndian>()?;
+    if elevation < 0 {
+        return Ok(None);
+    }
+    let x = rd.read_i32::<BigEndian>()?;
+    let y = rd.read_i32::<BigEndian>()?;
+    Ok(Some(EPoint::new(elevation as u32, Point::new(x, y))))
+}
+
+fn read_index(rd: &mut impl Read, handles: &[Handle]) -> io::Result<Handle> {
+    let i = rd.read_u32::<BigEndian>()?;
+    handles.get(i as usize).copied()
+        .ok_or_else(|| invalid_data(format!("object index {} is out of range", i)))
+}
+
+fn write_object(wr: &mut impl Write, obj: &Object, indices: &SecondaryMap<Handle, u32>)
+    -> io::Result<()>
+{
+    wr.write_u32::<BigEndian>(obj.flags.bits())?;
+    wr.write_u32::<BigEndian>(obj.updated_flags.bits())?;
+    wr.write_i32::<BigEndian>(obj.screen_shift.x)?;
+    wr.write_i32::<BigEndian>(obj.screen_shift.y)?;
+    wr.write_u32::<BigEndian>(obj.fid.packed())?;
+    wr.write_u32::<BigEndian>(obj.frame_idx as u32)?;
+    wr.write_u8(obj.direction as u8)?;
+    let light_emitter = obj.light_emitter();
+    wr.write_u32::<BigEndian>(light_emitter.intensity)?;
+    wr.write_u32::<BigEndian>(light_emitter.radius)?;
+    wr.write_i32::<BigEndian>(obj.proto_id().map(|v| v.pack() as i32).unwrap_or(-1))?;
+    if let Some((sid, program_id)) = obj.script {
+        wr.write_i32::<BigEndian>(sid.pack() as i32)?;
+        wr.write_u32::<BigEndian>(program_id.val())?;
+    } else {
+        wr.write_i32::<BigEndian>(-1)?;
+    }
+
+    wr.write_u32::<BigEndian>(obj.inventory.items.len() as u32)?;
+    for item in &obj.inventory.items {
+        wr.write_u32::<BigEndian>(indices[item.object])?;
+        wr.write_u32::<BigEndian>(item.count)?;
+    }
+
+    match &obj.sub {
+        SubObject::None => wr.write_u8(SubObjectKind::None as u8)?,
+        SubObject::Critter(c) => {
+            wr.write_u8(SubObjectKind::Critter as u8)?;
+            wr.write_i32::<BigEndian>(c.hit_points)?;
+            wr.write_i32::<BigEndian>(c.radiation)?;
+            wr.write_i32::<BigEndian>(c.poison)?;
+            wr.write_u32::<BigEndian>(c.combat.damage_flags.bits())?;
+            wr.write_i32::<BigEndian>(c.combat.ai_packet)?;
+            wr.write_i32::<BigEndian>(c.combat.team_id)?;
+            wr.write_i32::<BigEndian>(c.combat.who_hit_me)?;
+            if let Some(dude) = c.try_dude() {
+                wr.write_u8(1)?;
+                wr.write_u16::<BigEndian>(dude.name.len() as u16)?;
+                wr.write_all(dude.name.as_bytes())?;
+                wr.write_u16::<BigEndian>(dude.naked_fidx)?;
+                wr.write_u8(dude.active_hand as u8)?;
+            } else {
+                wr.write_u8(0)?;
+            }
+        }
+        SubObject::Item(item) => {
+            wr.write_u8(SubObjectKind::Item as u8)?;
+            wr.write_u32::<BigEndian>(item.ammo_count)?;
+            wr.write_i32::<BigEndian>(item.ammo_proto.as_ref()
+                .map(|p| p.borrow().id().pack() as i32)
+                .unwrap_or(-1))?;
+        }
+        SubObject::Key(key) => {
+            wr.write_u8(SubObjectKind::Key as u8)?;
+            wr.write_i32::<BigEndian>(key.id)?;
+        }
+        SubObject::MapExit(e) => {
+            wr.write_u8(SubObjectKind::MapExit as u8)?;
+            write_map_exit(wr, e)?;
+        }
+        SubObject::Scenery(Scenery::Door(door)) => {
+            wr.write_u8(SubObjectKind::Door as u8)?;
+            wr.write_u32::<BigEndian>(door.flags.bits())?;
+        }
+        SubObject::Scenery(Scenery::Elevator(elevator)) => {
+            wr.write_u8(SubObjectKind::Elevator as u8)?;
+            wr.write_u32::<BigEndian>(elevator.kind)?;
+            wr.write_u32::<BigEndian>(elevator.level)?;
+        }
+        SubObject::Scenery(Scenery::Ladder(e)) => {
+            wr.write_u8(SubObjectKind::Ladder as u8)?;
+            write_map_exit(wr, e)?;
+        }
+        SubObject::Scenery(Scenery::Stairs(e)) => {
+            wr.write_u8(SubObjectKind::Stairs as u8)?;
+            write_map_exit(wr, e)?;
+        }
+    }
+    Ok(())
+}
+
+/// `handles` are the handles of the objects by their index in the state. The object isn't
+/// positioned.
+fn read_object(rd: &mut impl Read, proto_db: &ProtoDb, handles: &[Handle]) -> io::Result<Object> {
+    let flags = BitFlags::<Flag>::from_bits_truncate(rd.read_u32::<BigEndian>()?);
+    let updated_flags = BitFlags::<UpdatedFlag>::from_bits_truncate(rd.read_u32::<BigEndian>()?);
+    let screen_shift = Point::new(rd.read_i32::<BigEndian>()?, rd.read_i32::<BigEndian>()?);
+    let fid = FrameId::read(rd)?;
+    let frame_idx = rd.read_u32::<BigEndian>()? as usize;
+    let direction = rd.read_u8()?;
+    let direction = Direction::from_u8(direction)
+        .ok_or_else(|| invalid_data(format!("invalid direction {}", direction)))?;
+    let light_emitter = LightEmitter {
+        intensity: rd.read_u32::<BigEndian>()?,
+        radius: rd.read_u32::<BigEndian>()?,
+    };
+    let proto = ProtoId::read_opt(rd)?
+        .map(|pid| proto_db.proto(pid))
+        .transpose()?;
+    let script = if let Some(sid) = ScriptIid::read_opt(rd)? {
+        let program_id = rd.read_u32::<BigEndian>()?;
+        let program_id = ProgramId::new(program_id)
+            .ok_or_else(|| invalid_data(format!("malformed program ID: {}", program_id)))?;
+        Some((sid, program_id))
+    } else {
+        None
+    };
+
+    let count = rd.read_u32::<BigEndian>()?;
+    let mut inventory = Inventory::new();
+    for _ in 0..count {
+        let object = read_index(rd, handles)?;
+        let count = rd.read_u32::<BigEndian>()?;
+        inventory.items.push(InventoryItem { object, count });
+    }
+
+    let kind = rd.read_u8()?;
+    let sub = match SubObjectKind::from_u8(kind) {
+        Some(SubObjectKind::None) => SubObject::None,
+        Some(SubObjectKind::Critter) => {
+            let hit_points = rd.read_i32::<BigEndian>()?;
+            let radiation = rd.read_i32::<BigEndian>()?;
+            let poison = rd.read_i32::<BigEndian>()?;
+            let combat = CritterCombat {
+                damage_flags: BitFlags::from_bits_truncate(rd.read_u32::<BigEndian>()?),
+                ai_packet: rd.read_i32::<BigEndian>()?,
+                team_id: rd.read_i32::<BigEndian>()?,
+                who_hit_me: rd.read_i32::<BigEndian>()?,
+                enemy: None,
+            };
+            let dude = if rd.read_u8()? != 0 {
+                let len = rd.read_u16::<BigEndian>()?;
+                let mut name = vec![0; len as usize];
+                rd.read_exact(&mut name)?;
+                let naked_fidx = rd.read_u16::<BigEndian>()?;
+                let active_hand = match rd.read_u8()? {
+                    0 => Hand::Left,
+                    1 => Hand::Right,
+                    v => return Err(invalid_data(format!("invalid hand {}", v))),
+                };
+                Some(Box::new(Dude {
+                    name: BString::from(&name[..]),
+                    naked_fidx,
+                    active_hand,
+                }))
+            } else {
+                None
+            };
+            SubObject::Critter(Critter {
+                hit_points,
+                radiation,
+                poison,
+                combat,
+                dude,
+            })
+        }
+        Some(SubObjectKind::Item) => {
+            let ammo_count = rd.read_u32::<BigEndian>()?;
+            let ammo_proto = ProtoId::read_opt(rd)?
+                .map(|pid| proto_db.proto(pid))
+                .transpose()?;
+            SubObject::Item(Item {
+                ammo_count,
+                ammo_proto,
+            })
+        }
+        Some(SubObjectKind::Key) => SubObject::Key(Key { id: rd.read_i32::<BigEndian>()? }),
+        Some(SubObjectKind::MapExit) => SubObject::MapExit(read_map_exit(rd)?),
+        Some(SubObjectKind::Door) => SubObject::Scenery(Scenery::Door(Door {
+            flags: BitFlags::from_bits_truncate(rd.read_u32::<BigEndian>()?),
+        })),
+        Some(SubObjectKind::Elevator) => SubObject::Scenery(Scenery::Elevator(Elevator {
+            kind: rd.read_u32::<BigEndian>()?,
+            level: rd.read_u32::<BigEndian>()?,
+        })),
+        Some(SubObjectKind::Ladder) => SubObject::Scenery(Scenery::Ladder(read_map_exit(rd)?)),
+        Some(SubObjectKind::Stairs) => SubObject::Scenery(Scenery::Stairs(read_map_exit(rd)?)),
+        None => return Err(invalid_data(format!("invalid sub-object kind {}", kind))),
+    };
+
+    let mut obj = Object::new(fid, proto, None, sub);
+    obj.flags = flags;
+    obj.updated_flags = updated_flags;
+    obj.screen_shift = screen_shift;
+    obj.frame_idx = frame_idx;
+    obj.direction = direction;
+    obj.set_light_emitter(light_emitter);
+    obj.inventory = inventory;
+    obj.script = script;
+    Ok(obj)
+}
+
+fn write_map_exit(wr: &mut impl Write, e: &MapExit) -> io::Result<()> {
+    let map = match e.map {
+        TargetMap::Map { map_id } => map_id as i32,
+        TargetMap::WorldMap(WorldMapKind::Town) => -1,
+        TargetMap::WorldMap(WorldMapKind::World) => -2,
+        TargetMap::CurrentMap => -3,
+    };
+    wr.write_i32::<BigEndian>(map)?;
+    write_pos(wr, Some(e.pos))?;
+    wr.write_u8(e.direction as u8)
+}
+
+fn read_map_exit(rd: &mut impl Read) -> io::Result<MapExit> {
+    let map = match rd.read_i32::<BigEndian>()? {
+        -3 => TargetMap::CurrentMap,
+        v => TargetMap::decode(v).ok_or_else(|| invalid_data(format!("invalid map {}", v)))?,
+    };
+    let pos = read_pos(rd)?.ok_or_else(|| invalid_data("map exit without position"))?;
+    let direction = rd.read_u8()?;
+    let direction = Direction::from_u8(direction)
+        .ok_or_else(|| invalid_data(format!("invalid direction {}", direction)))?;
+    Ok(MapExit {
+        map,
+        pos,
+        direction,
+    })
+}
+
+fn take_objects(objects: &mut Objects) -> Vec<(ObjectGraph, Option<EPoint>)> {
+    let in_inventory: HashSet<Handle> = objects.iter()
+        .flat_map(|h| objects.get(h).inventory.items.iter()
+            .map(|i| i.object)
+            .collect::<Vec<_>>())
+        .collect();
+    let top_level: Vec<_> = objects.iter()
+        .filter(|h| !in_inventory.contains(h) && !objects.get(*h).flags.contains(Flag::Temp))
+        .collect();
+    top_level.into_iter()
+        .map(|h| {
+            let pos = objects.get(h).try_pos();
+            (objects.remove_deep(h), pos)
+        })
+        .collect()
+}
+
+/// Returns handles of all inserted objects including the inventory items and the map from
+/// the saved handles to the inserted ones.
+fn insert_objects(objects: &mut Objects, saved: Vec<(ObjectGraph, Option<EPoint>)>)
+    -> (Vec<Handle>, SecondaryMap<Handle, Handle>)
+{
+    let mut r = Vec::new();
+    let mut handle_map = SecondaryMap::new();
+    for (mut graph, pos) in saved {
+        graph.objects[graph.root].set_pos(pos);
+        let (root, graph_handle_map) = objects.insert_graph_mapped(graph);
+        handle_map.extend(graph_handle_map);
+        collect_deep(objects, root, &mut r);
+    }
+    (r, handle_map)
+}
+
+fn collect_deep(objects: &Objects, obj: Handle, out: &mut Vec<Handle>) {
+    out.push(obj);
+    let items: Vec<_> = objects.get(obj).inventory.items.iter().map(|i| i.object).collect();
+    for item in items {
+        collect_deep(objects, item, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Instant;
+
+    use crate::asset::EntityKind;
+    use crate::asset::frame::FrameId;
+    use crate::game::world::World;
+    use crate::graphics::Point;
+
+    #[test]
+    fn take_insert_objects() {
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+
+        let fid = FrameId::new_generic(EntityKind::Scenery, 1).unwrap();
+        let item_fid = FrameId::new_generic(EntityKind::Item, 1).unwrap();
+        let pos = EPoint::new(0, Point::new(5, 7));
+        let container = objs.create(Some(fid), None, Some(pos), None).handle();
+        objs.get_mut(container).frame_idx = 2;
+        let item = objs.create(Some(item_fid), None, None, None).handle();
+        objs.move_into_inventory(container, item, 3);
+        let temp = objs.create(Some(fid), None, Some(pos), None).handle();
+        objs.get_mut(temp).flags.insert(Flag::Temp);
+
+        let saved = take_objects(objs);
+        assert_eq!(saved.len(), 1);
+        assert!(!objs.contains(container));
+        assert!(!objs.contains(item));
+        assert!(objs.contains(temp));
+
+        objs.clear();
+        let (restored, handle_map) = insert_objects(objs, saved);
+        assert_eq!(handle_map.get(container), Some(&restored[0]));
+        assert_eq!(handle_map.get(item), Some(&restored[1]));
+        assert_eq!(restored.len(), 2);
+        let container = objs.get(restored[0]);
+        assert_eq!(container.try_pos(), Some(pos));
+        assert_eq!(container.frame_idx, 2);
+        assert_eq!(container.inventory.items.len(), 1);
+        assert_eq!(container.inventory.items[0].object, restored[1]);
+        assert_eq!(container.inventory.items[0].count, 3);
+        assert_eq!(objs.get(restored[1]).try_pos(), None);
+        assert!(objs.at(pos).contains(&restored[0]));
+    }
+    #[test]
+    fn write_read() {
+        use std::rc::Rc;
+        use crate::asset::DoorFlag;
+        use crate::fs::FileSystem;
+        use crate::game::script::ScriptKind;
+
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+
+        let fid = FrameId::new_generic(EntityKind::Scenery, 1).unwrap();
+        let item_fid = FrameId::new_generic(EntityKind::Item, 1).unwrap();
+        let pos = EPoint::new(1, Point::new(5, 7));
+        let door = objs.create(Some(fid), None, Some(pos), None).handle();
+        {
+            let mut door = objs.get_mut(door);
+            door.frame_idx = 3;
+            door.direction = Direction::SW;
+            door.flags.insert(Flag::NoBlock);
+            door.sub = SubObject::Scenery(Scenery::Door(Door { flags: DoorFlag::Locked.into() }));
+            door.script = Some((ScriptIid::new(ScriptKind::Spatial, 5),
+                ProgramId::new(12).unwrap()));
+        }
+        let key = objs.create(Some(item_fid), None, None, None).handle();
+        objs.get_mut(key).sub = SubObject::Key(Key { id: 42 });
+        objs.move_into_inventory(door, key, 2);
+        let critter = objs.create(Some(fid), None, Some(EPoint::new(0, Point::new(1, 2))), None)
+            .handle();
+        objs.get_mut(critter).sub = SubObject::Critter(Critter {
+            hit_points: 7,
+            radiation: 8,
+            poison: 9,
+            combat: Default::default(),
+            dude: Some(Box::new(Dude {
+                name: BString::from(&b"Narg"[..]),
+                naked_fidx: 0x3e,
+                active_hand: Hand::Right,
+            })),
+        });
+
+        let objects = take_objects(objs);
+        let mut explosive_timers = ObjectTimers::new();
+        let explosive = Explosive::active(ProtoId::ACTIVE_DYNAMITE).unwrap();
+        explosive_timers.add(GameTime::from_decis(100), key, explosive);
+        let state = MapState {
+            objects,
+            explosive_timers,
+            scripts: MapScripts::default(),
+        };
+
+        let mut data = Vec::new();
+        state.write(&mut data).unwrap();
+        let proto_db = ProtoDb::mock(Rc::new(FileSystem::mock()));
+        let state = MapState::read(&mut Cursor::new(&data[..]), &proto_db).unwrap();
+        assert_eq!(state.objects.len(), 2);
+        assert_eq!(state.explosive_timers.len(), 1);
+
+        objs.clear();
+        let (restored, handle_map) = insert_objects(objs, state.objects);
+        assert_eq!(restored.len(), 3);
+
+        let door = objs.get(restored[0]);
+        assert_eq!(door.try_pos(), Some(pos));
+        assert_eq!(door.frame_idx, 3);
+        assert_eq!(door.direction, Direction::SW);
+        assert!(door.flags.contains(Flag::NoBlock));
+        assert_eq!(door.is_locked(), Some(true));
+        assert_eq!(door.script.map(|(sid, p)| (sid.pack(), p.val())),
+            Some((ScriptIid::new(ScriptKind::Spatial, 5).pack(), 12)));
+        assert_eq!(door.inventory.items.len(), 1);
+        assert_eq!(door.inventory.items[0].object, restored[1]);
+        assert_eq!(door.inventory.items[0].count, 2);
+
+        let key = objs.get(restored[1]);
+        assert_eq!(key.sub.as_key().unwrap().id, 42);
+        let timer = state.explosive_timers.iter().next().unwrap();
+        assert_eq!(handle_map.get(timer.obj), Some(&restored[1]));
+        assert_eq!(timer.data, explosive);
+
+        let critter = objs.get(restored[2]);
+        let critter = critter.sub.as_critter().unwrap();
+        assert_eq!((critter.hit_points, critter.radiation, critter.poison), (7, 8, 9));
+        assert_eq!(critter.dude().name.as_bytes(), b"Narg");
+        assert_eq!(critter.dude().naked_fidx, 0x3e);
+        assert_eq!(critter.dude().active_hand, Hand::Right);
+
+        assert_eq!(MapState::read(&mut Cursor::new(&data[..data.len() - 1]), &proto_db)
+            .err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+    #[test]
+    fn map_cache() {
+        use std::rc::Rc;
+        use crate::fs::FileSystem;
+        use crate::fs::manifest::ManifestEntry;
+
+        let proto_db = ProtoDb::mock(Rc::new(FileSystem::mock()));
+        let state = || MapState {
+            objects: Vec::new(),
+            explosive_timers: ObjectTimers::new(),
+            scripts: MapScripts::default(),
+        };
+        let manifest = |hash| Manifest {
+            entries: vec![ManifestEntry { name: "master.dat".into(), hash }],
+        };
+
+        let mut cache = MapCache::new(manifest(1));
+        cache.put(3, &state());
+        assert!(cache.take(3, &proto_db).is_some());
+        assert!(cache.take(3, &proto_db).is_none());
+
+        // Saved with other resource providers: warns but still loads.
+        cache.put(3, &state());
+        cache.manifest = manifest(2);
+        assert!(cache.take(3, &proto_db).is_some());
+
+        cache.in_memory.insert(4, b"V13MAPS0".to_vec());
+        assert!(cache.take(4, &proto_db).is_none());
+
+        cache.put(5, &state());
+        cache.clear();
+        assert!(cache.take(5, &proto_db).is_none());
+    }
+}
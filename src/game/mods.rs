@@ -0,0 +1,365 @@
+//! Mod scripts hooking the engine events. This is an extension layer that lives alongside the
+//! original scripts VM, similar to what sfall provides for the original engine.
+//!
+//! Mods are [Rhai](https://rhai.rs) scripts in the `mods` directory of the resource directory,
+//! loaded in the file name order. Top level statements run once when the mod is loaded. Hooks
+//! are optional functions called on the engine events:
+//!
+//! ```text
+//! fn on_map_enter(map)                  // map name, e.g. "artemple"
+//! fn on_combat_start()
+//! fn on_item_use(item_pid, target_pid)  // target_pid is -1 when used on nothing
+//! ```
+//!
+//! Functions available to the hooks:
+//!
+//! ```text
+//! print(text)
+//! get_gvar(var) -> int
+//! set_gvar(var, value)
+//! dude_tile() -> int
+//! dude_elevation() -> int
+//! spawn(pid, tile, elevation)
+//! ```
+//!
+//! Changes requested by the hooks are applied after the hook returns.
+//!
+//! Running the scripts requires the `modding` crate feature.
+
+use log::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::asset::proto::ProtoId;
+
+const EXTENSION: &str = "rhai";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    MapEnter {
+        map: String,
+    },
+    CombatStart,
+    ItemUse {
+        item: ProtoId,
+        target: Option<ProtoId>,
+    },
+}
+
+impl Event {
+    pub fn hook_name(&self) -> &'static str {
+        match self {
+            Event::MapEnter { .. } => "on_map_enter",
+            Event::CombatStart => "on_combat_start",
+            Event::ItemUse { .. } => "on_item_use",
+        }
+    }
+}
+
+/// Game state change requested by a hook.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    SetGlobalVar {
+        var: usize,
+        value: i32,
+    },
+    Spawn {
+        pid: ProtoId,
+        tile: u32,
+        elevation: u32,
+    },
+}
+
+/// Game state visible to the hooks.
+#[derive(Clone, Debug, Default)]
+pub struct Context {
+    pub global_vars: Vec<i32>,
+    pub dude_tile: u32,
+    pub dude_elevation: u32,
+}
+
+/// State shared with the script functions while a hook runs.
+#[derive(Debug, Default)]
+struct HookState {
+    ctx: Context,
+    actions: Vec<Action>,
+}
+
+impl HookState {
+    fn global_var(&self, var: i64) -> i64 {
+        if var >= 0 {
+            if let Some(&v) = self.ctx.global_vars.get(var as usize) {
+                return v as i64;
+            }
+        }
+        warn!("mod: get_gvar: bad global var: {}", var);
+        0
+    }
+
+    fn set_global_var(&mut self, var: i64, value: i64) {
+        let r = if var >= 0 { self.ctx.global_vars.get_mut(var as usize) } else { None };
+        if let Some(v) = r {
+            let value = value as i32;
+            *v = value;
+            self.actions.push(Action::SetGlobalVar { var: var as usize, value });
+        } else {
+            warn!("mod: set_gvar: bad global var: {}", var);
+        }
+    }
+
+    fn spawn(&mut self, pid: i64, tile: i64, elevation: i64) {
+        let proto_id = if pid >= 0 { ProtoId::from_packed(pid as u32) } else { None };
+        match proto_id {
+            Some(proto_id) if tile >= 0 && elevation >= 0 => self.actions.push(Action::Spawn {
+                pid: proto_id,
+                tile: tile as u32,
+                elevation: elevation as u32,
+            }),
+            _ => warn!("mod: spawn: bad arguments: {} {} {}", pid, tile, elevation),
+        }
+    }
+}
+
+/// Returns the mod script files in `dir` sorted by the file name. Missing directory is the
+/// same as empty.
+pub fn find_mod_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut r = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map(|e| e == EXTENSION).unwrap_or(false) {
+            r.push(path);
+        }
+    }
+    r.sort();
+    Ok(r)
+}
+
+#[cfg(feature = "modding")]
+mod engine {
+    use rhai::{Dynamic, Engine, AST};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    struct Mod {
+        name: String,
+        /// Functions only, the top level statements have been run on load.
+        ast: AST,
+    }
+
+    pub struct Mods {
+        engine: Engine,
+        mods: Vec<Mod>,
+        state: Rc<RefCell<HookState>>,
+    }
+
+    impl Mods {
+        pub fn new() -> Self {
+            let state = Rc::new(RefCell::new(HookState::default()));
+            let mut engine = Engine::new();
+            engine.on_print(|s| info!("mod: {}", s));
+            {
+                let state = state.clone();
+                engine.register_fn("get_gvar", move |var: i64| state.borrow().global_var(var));
+            }
+            {
+                let state = state.clone();
+                engine.register_fn("set_gvar", move |var: i64, value: i64| {
+                    state.borrow_mut().set_global_var(var, value)
+                });
+            }
+            {
+                let state = state.clone();
+                engine.register_fn("dude_tile", move || state.borrow().ctx.dude_tile as i64);
+            }
+            {
+                let state = state.clone();
+                engine.register_fn("dude_elevation",
+                    move || state.borrow().ctx.dude_elevation as i64);
+            }
+            {
+                let state = state.clone();
+                engine.register_fn("spawn", move |pid: i64, tile: i64, elevation: i64| {
+                    state.borrow_mut().spawn(pid, tile, elevation)
+                });
+            }
+            Self {
+                engine,
+                mods: Vec::new(),
+                state,
+            }
+        }
+
+        pub fn load(&mut self, name: &str, source: &str) -> Result<(), String> {
+            let ast = self.engine.compile(source).map_err(|e| e.to_string())?;
+            self.engine.run_ast(&ast).map_err(|e| e.to_string())?;
+            self.mods.push(Mod {
+                name: name.into(),
+                ast: ast.clone_functions_only(),
+            });
+            Ok(())
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.mods.is_empty()
+        }
+
+        pub fn run(&mut self, event: &Event, ctx: Context) -> Vec<Action> {
+            let hook = event.hook_name();
+            let args: Vec<Dynamic> = match event {
+                Event::MapEnter { map } => vec![map.clone().into()],
+                Event::CombatStart => vec![],
+                Event::ItemUse { item, target } => vec![
+                    (item.pack() as i64).into(),
+                    target.map(|t| t.pack() as i64).unwrap_or(-1).into(),
+                ],
+            };
+            self.state.borrow_mut().ctx = ctx;
+            for m in &self.mods {
+                let defined = m.ast.iter_functions()
+                    .any(|f| f.name == hook && f.params.len() == args.len());
+                if !defined {
+                    continue;
+                }
+                debug!("running mod hook {} of {}", hook, m.name);
+                let r = self.engine.call_fn::<Dynamic>(&mut rhai::Scope::new(), &m.ast, hook,
+                    args.clone());
+                if let Err(e) = r {
+                    warn!("mod {}: error in {}: {}", m.name, hook, e);
+                }
+            }
+            std::mem::take(&mut self.state.borrow_mut().actions)
+        }
+    }
+}
+
+#[cfg(not(feature = "modding"))]
+mod engine {
+    use super::*;
+
+    pub struct Mods;
+
+    impl Mods {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn load(&mut self, name: &str, _source: &str) -> Result<(), String> {
+            Err(format!("can't load {}: built without modding support (`modding` feature)",
+                name))
+        }
+
+        pub fn is_empty(&self) -> bool {
+            true
+        }
+
+        pub fn run(&mut self, _event: &Event, _ctx: Context) -> Vec<Action> {
+            Vec::new()
+        }
+    }
+}
+
+pub use engine::Mods;
+
+impl Mods {
+    /// Loads all mods found in `dir`. Mods that fail to load are logged and skipped.
+    pub fn load_dir(dir: &Path) -> Self {
+        let mut r = Self::new();
+        let files = find_mod_files(dir).unwrap_or_else(|e| {
+            warn!("couldn't list mods in {}: {}", dir.display(), e);
+            Vec::new()
+        });
+        for path in files {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let result = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| r.load(&name, &source));
+            match result {
+                Ok(()) => info!("loaded mod {}", name),
+                Err(e) => warn!("couldn't load mod {}: {}", path.display(), e),
+            }
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hook_state() {
+        let mut s = HookState::default();
+        s.ctx.global_vars = vec![1, 2, 3];
+        assert_eq!(s.global_var(1), 2);
+        assert_eq!(s.global_var(3), 0);
+        assert_eq!(s.global_var(-1), 0);
+
+        s.set_global_var(1, 5);
+        s.set_global_var(3, 5);
+        assert_eq!(s.global_var(1), 5);
+
+        s.spawn(0x1000001, 12345, 1);
+        s.spawn(0x7f000001, 12345, 1);
+        s.spawn(0x1000001, -1, 1);
+        assert_eq!(s.actions, vec![
+            Action::SetGlobalVar { var: 1, value: 5 },
+            Action::Spawn { pid: ProtoId::from_packed(0x1000001).unwrap(), tile: 12345,
+                elevation: 1 },
+        ]);
+    }
+
+    #[test]
+    fn find_mod_files_() {
+        let dir = std::env::temp_dir().join(format!("vault13-mods-test-{}", std::process::id()));
+        assert_eq!(find_mod_files(&dir).unwrap(), Vec::<PathBuf>::new());
+
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["b.rhai", "a.rhai", "readme.txt"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let files = find_mod_files(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("a.rhai"), dir.join("b.rhai")]);
+    }
+
+    #[cfg(feature = "modding")]
+    #[test]
+    fn run_hooks() {
+        let mut mods = Mods::new();
+        mods.load("a", "
+            let x = 1;
+            fn on_map_enter(map) {
+                if map == \"artemple\" {
+                    set_gvar(0, get_gvar(0) + 1);
+                }
+            }
+        ").unwrap();
+        mods.load("b", "
+            fn on_map_enter(map) {
+                set_gvar(1, get_gvar(0));
+                spawn(0x1000001, dude_tile(), dude_elevation());
+            }
+        ").unwrap();
+        assert!(mods.load("c", "fn (").is_err());
+
+        let ctx = Context {
+            global_vars: vec![10, 0],
+            dude_tile: 100,
+            dude_elevation: 2,
+        };
+        let actions = mods.run(&Event::MapEnter { map: "artemple".into() }, ctx.clone());
+        assert_eq!(actions, vec![
+            Action::SetGlobalVar { var: 0, value: 11 },
+            Action::SetGlobalVar { var: 1, value: 11 },
+            Action::Spawn { pid: ProtoId::from_packed(0x1000001).unwrap(), tile: 100,
+                elevation: 2 },
+        ]);
+        assert_eq!(mods.run(&Event::CombatStart, ctx), vec![]);
+    }
+}
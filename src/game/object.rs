@@ -1,3 +1,4 @@
+use bstring::BString;
 use enumflags2::{bitflags, BitFlags};
 use enum_primitive_derive::Primitive;
 use if_chain::if_chain;
@@ -11,6 +12,7 @@ use crate::asset::*;
 use crate::asset::frame::*;
 use crate::asset::proto::*;
 use crate::asset::script::ProgramId;
+use crate::game::explosive::Explosive;
 use crate::game::rpg::Rpg;
 use crate::game::script::{Scripts, ScriptIid};
 use crate::graphics::{EPoint, Point, Rect};
@@ -83,13 +85,24 @@ pub enum EquipmentSlot {
     Hand(Hand),
 }
 
+impl EquipmentSlot {
+    /// Flag marking the inventory item as being in this slot.
+    pub fn flag(self) -> Flag {
+        match self {
+            EquipmentSlot::Armor => Flag::Worn,
+            EquipmentSlot::Hand(Hand::Left) => Flag::LeftHand,
+            EquipmentSlot::Hand(Hand::Right) => Flag::RightHand,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Hand {
     Left,
     Right,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub struct LightEmitter {
     pub intensity: u32,
     pub radius: u32,
@@ -102,19 +115,22 @@ pub struct Egg {
 }
 
 impl Egg {
-    #[must_use]
-    pub fn hit_test(&self, p: Point, tile_grid: &impl TileGridView, frm_db: &FrameDb) -> bool {
+    /// Returns the screen area where objects can be masked by the egg.
+    pub fn bounds(&self, tile_grid: &impl TileGridView, frm_db: &FrameDb) -> Rect {
         let screen_pos = tile_grid.center_to_screen(self.pos);
         let frms = frm_db.get(self.fid).unwrap();
         let frml = &frms.frame_lists[Direction::NE];
-        let frm = &frml.frames[0];
+        frml.frames[0].bounds_centered(screen_pos, frml.center)
+    }
 
-        let bounds = frm.bounds_centered(screen_pos, frml.center);
+    #[must_use]
+    pub fn hit_test(&self, p: Point, tile_grid: &impl TileGridView, frm_db: &FrameDb) -> bool {
+        let bounds = self.bounds(tile_grid, frm_db);
         if !bounds.contains(p) {
             return false;
         }
         let p = p - bounds.top_left();
-        frm.mask.test(p).unwrap()
+        frm_db.get(self.fid).unwrap().first().mask.test(p).unwrap()
     }
 }
 
@@ -400,6 +416,16 @@ impl Object {
         })
     }
 
+    /// Whether this is a flat decoration of the floor like a blood stain that can't be interacted
+    /// with.
+    #[must_use]
+    pub fn is_decal(&self) -> bool {
+        self.flags.contains(Flag::Flat)
+            && self.script.is_none()
+            && matches!(self.kind(), EntityKind::Scenery | EntityKind::Wall | EntityKind::Misc)
+            && matches!(self.sub, SubObject::None)
+    }
+
     #[must_use]
     pub fn distance(&self, other: &Object) -> Option<u32> {
         let mut r = hex::distance(self.pos?.point, other.pos?.point);
@@ -469,12 +495,24 @@ impl Object {
     // inven_worn
     #[must_use]
     pub fn equipment(&self, slot: EquipmentSlot, objects: &Objects) -> Option<Handle> {
-        let flag = match slot {
-            EquipmentSlot::Armor => Flag::Worn,
-            EquipmentSlot::Hand(Hand::Left) => Flag::LeftHand,
-            EquipmentSlot::Hand(Hand::Right) => Flag::RightHand,
-        };
-        self.find_inventory_item(objects, |o| o.flags.contains(flag))
+        self.find_inventory_item(objects, |o| o.flags.contains(slot.flag()))
+    }
+
+    /// Returns the critter's active hand. Only the dude can switch hands.
+    #[must_use]
+    pub fn active_hand(&self) -> Option<Hand> {
+        let critter = self.sub.as_critter()?;
+        Some(critter.try_dude().map(|d| d.active_hand).unwrap_or(Hand::Left))
+    }
+
+    /// Returns the weapon in the critter's active hand.
+    #[must_use]
+    pub fn active_weapon(&self, objects: &Objects) -> Option<Handle> {
+        let hand = self.active_hand()?;
+        self.equipment(EquipmentSlot::Hand(hand), objects)
+            .filter(|&item| objects.get(item).proto()
+                .map(|p| p.sub.as_weapon().is_some())
+                .unwrap_or(false))
     }
 
     /// Whether this object can be talked to.
@@ -615,7 +653,14 @@ impl Object {
     // critterIsOverfloaded
     #[must_use]
     pub fn is_overloaded(&self, rpg: &Rpg, objects: &Objects) -> bool {
-        rpg.stat(Stat::CarryWeight, self, objects) < self.inventory.weight(objects) as i32
+        self.carry_weight_left(rpg, objects) < 0
+    }
+
+    /// Weight the critter can carry in addition to its inventory. Negative if the critter is
+    /// overloaded.
+    #[must_use]
+    pub fn carry_weight_left(&self, rpg: &Rpg, objects: &Objects) -> i32 {
+        rpg.stat(Stat::CarryWeight, self, objects) - self.inventory.weight(objects) as i32
     }
 
     // item_identical
@@ -627,6 +672,8 @@ impl Object {
             || o.flags.intersects(Flag::Worn | Flag::LeftHand | Flag::RightHand | Flag::Used)
             || !self.inventory.items.is_empty()
             || !o.inventory.items.is_empty()
+            // Each armed explosive has its own timer.
+            || self.proto_id().and_then(Explosive::active).is_some()
         {
             return false;
         }
@@ -669,15 +716,13 @@ impl Object {
                     armor.male_fidx
                 }
             })
+            // Only the dude has art for each armor kind.
+            .filter(|_| dude.is_some())
             .or(dude.map(|d| d.naked_fidx))
             .unwrap_or(self.fid.idx());
 
-        let active_hand = dude.map(|d| d.active_hand).unwrap_or(Hand::Left);
-        let weapon = self.equipment(EquipmentSlot::Hand(active_hand), objects)
-            .and_then(|item| {
-                objects.get(item).proto().unwrap().sub
-                    .as_weapon().map(|w| w.kind)
-            })
+        let weapon = self.active_weapon(objects)
+            .map(|item| objects.get(item).proto().unwrap().sub.as_weapon().unwrap().kind)
             .unwrap_or(WeaponKind::Unarmed);
 
         FrameId::new_critter(Some(self.direction), CritterAnim::Stand, weapon, idx).unwrap()
@@ -835,10 +880,16 @@ pub struct Objects {
     by_pos: Box<[Array2d<Vec<Handle>>]>,
     // Objects not attached to tile (Object::pos is None).
     detached: Vec<Handle>,
+    // Critters attached to tile on each elevation.
+    critters: Box<[Vec<Handle>]>,
     empty_object_handle_vec: Vec<Handle>,
     path_finder: RefCell<PathFinder>,
+    /// Start and directions of the last found path. Shown by the path debug overlay.
+    last_path: RefCell<Option<(EPoint, Vec<Direction>)>>,
     light_grid: Option<Box<LightGrid>>,
     dude: Option<Handle>,
+    /// Whether paths of the dude go through blockers.
+    noclip: bool,
 }
 
 impl Objects {
@@ -864,10 +915,13 @@ impl Objects {
             objects: SecondaryMap::new(),
             by_pos,
             detached: Vec::new(),
+            critters: vec![Vec::new(); elevation_count as usize].into_boxed_slice(),
             empty_object_handle_vec: Vec::new(),
             path_finder,
+            last_path: RefCell::new(None),
             light_grid,
             dude: None,
+            noclip: false,
         }
     }
 
@@ -888,7 +942,11 @@ impl Objects {
             }
         }
         self.detached.clear();
+        for critters in self.critters.iter_mut() {
+            critters.clear();
+        }
         self.light_grid_mut().clear();
+        self.last_path.replace(None);
         self.dude = None;
     }
 
@@ -943,6 +1001,7 @@ impl Objects {
                             ai_packet: p.ai_packet,
                             team_id: p.team_id,
                             who_hit_me: 0,
+                            enemy: None,
                         },
                         dude: None,
                     })
@@ -1018,6 +1077,7 @@ impl Objects {
 
         if obj.is_dude() {
             obj.sub.as_critter_mut().unwrap().dude = Some(Box::new(Dude {
+                name: Dude::DEFAULT_NAME.as_bytes().into(),
                 naked_fidx: 0x3e,
                 active_hand: Hand::Left,
             }));
@@ -1050,6 +1110,14 @@ impl Objects {
     }
 
     pub fn insert_graph(&mut self, graph: ObjectGraph) -> Handle {
+        self.insert_graph_mapped(graph).0
+    }
+
+    /// Same as `insert_graph()` but also returns the map from the old handles of the graph
+    /// objects to the new ones.
+    pub fn insert_graph_mapped(&mut self, graph: ObjectGraph)
+        -> (Handle, SecondaryMap<Handle, Handle>)
+    {
         let mut handle_map = SecondaryMap::new();
 
         for (h, o) in graph.objects {
@@ -1060,7 +1128,7 @@ impl Objects {
         let root = handle_map[graph.root];
         self.fix_handles(root, &|h| handle_map[h]);
 
-        root
+        (root, handle_map)
     }
 
     fn fix_handles(&self, obj: Handle, map: &impl Fn(Handle) -> Handle) {
@@ -1112,6 +1180,44 @@ impl Objects {
             .unwrap()
     }
 
+    /// Returns objects on the hexes within `radius` from `pos` on the same elevation.
+    /// The objects are ordered by hex position.
+    pub fn in_radius(&self, pos: EPoint, radius: u32) -> Vec<Handle> {
+        let r = radius as i32;
+        let rect = Rect::with_size(pos.point.x - r, pos.point.y - r, 2 * r + 1, 2 * r + 1)
+            .intersect(Rect::with_size(0, 0, self.tile_grid.width(), self.tile_grid.height()));
+        // Hex distance is never less than the difference of either coordinate, so scanning the
+        // bounding square is enough.
+        let mut result = Vec::new();
+        for y in rect.top..rect.bottom {
+            for x in rect.left..rect.right {
+                let p = Point::new(x, y);
+                if hex::distance(p, pos.point) <= radius {
+                    result.extend_from_slice(self.at(p.elevated(pos.elevation)));
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns objects placed on the `elevation` ordered by hex position.
+    pub fn on_elevation(&self, elevation: u32) -> impl Iterator<Item=Handle> + '_ {
+        self.by_pos[elevation as usize].as_slice().iter().flatten().cloned()
+    }
+
+    /// Returns critters placed on the `elevation`.
+    pub fn critters(&self, elevation: u32) -> &[Handle] {
+        &self.critters[elevation as usize]
+    }
+
+    /// Returns critters within `radius` from `pos` on the same elevation.
+    pub fn critters_in_radius(&self, pos: EPoint, radius: u32) -> Vec<Handle> {
+        self.critters(pos.elevation).iter()
+            .cloned()
+            .filter(|&h| hex::distance(self.get(h).pos().point, pos.point) <= radius)
+            .collect()
+    }
+
     pub fn get_ref(&self, h: Handle) -> &RefCell<Object> {
         &self.objects[h]
     }
@@ -1124,6 +1230,20 @@ impl Objects {
         self.get_ref(h).borrow_mut()
     }
 
+    pub fn is_noclip(&self) -> bool {
+        self.noclip
+    }
+
+    /// Makes `path()` ignore blockers when finding path for the dude.
+    pub fn set_noclip(&mut self, noclip: bool) {
+        self.noclip = noclip;
+    }
+
+    /// Start and directions of the last path found by `path()`.
+    pub fn last_path(&self) -> Option<(EPoint, Vec<Direction>)> {
+        self.last_path.borrow().clone()
+    }
+
     pub fn light_grid(&self) -> &LightGrid {
         self.light_grid.as_ref().unwrap()
     }
@@ -1211,19 +1331,12 @@ impl Objects {
         self.render0(canvas, elevation, screen_rect, tile_grid, egg, get_light, false);
     }
 
+    /// Renders outlines of the objects in the same order as `render()` draws the objects.
     pub fn render_outlines(&self, canvas: &mut dyn Canvas, elevation: u32, screen_rect: Rect,
             tile_grid: &impl TileGridView) {
-        let hex_rect = Self::get_render_hex_rect(screen_rect, tile_grid);
-        for y in hex_rect.top..hex_rect.bottom {
-            for x in (hex_rect.left..hex_rect.right).rev() {
-                let pos = EPoint {
-                    elevation,
-                    point: Point::new(x, y),
-                };
-                for &objh in self.at(pos) {
-                    let obj = self.get_mut(objh);
-                    obj.render_outline(canvas, &self.frm_db, tile_grid);
-                }
+        for flat in [true, false] {
+            for objh in self.render_order(elevation, screen_rect, tile_grid, flat) {
+                self.get(objh).render_outline(canvas, &self.frm_db, tile_grid);
             }
         }
     }
@@ -1240,6 +1353,13 @@ impl Objects {
         self.update_light_grid(h, 1);
     }
 
+    // obj_set_light
+    pub fn set_light_emitter(&mut self, h: Handle, light_emitter: LightEmitter) {
+        self.update_light_grid(h, -1);
+        self.get_mut(h).light_emitter = light_emitter;
+        self.update_light_grid(h, 1);
+    }
+
     pub fn set_screen_shift(&mut self, h: Handle, shift: Point) {
         let pos = self.remove_from_tile_grid(h);
         self.get_mut(h).screen_shift = shift;
@@ -1282,7 +1402,10 @@ impl Objects {
             let mut obj = self.get_mut(h);
             let mut shift = Point::new(0, 0);
             let fid = if let FrameId::Critter(critter_fid) = obj.fid {
-                if critter_fid.weapon() != WeaponKind::Unarmed {
+                // The shift is only known if the critter has both animations.
+                if critter_fid.weapon() != WeaponKind::Unarmed
+                    && self.frm_db.has_critter_anim(critter_fid, CritterAnim::TakeOut)
+                {
                     let fid = critter_fid
                         .with_anim(CritterAnim::TakeOut)
                         .into();
@@ -1293,18 +1416,19 @@ impl Objects {
 
                     let fid = critter_fid
                         .with_anim(CritterAnim::Stand)
-                        .with_weapon(WeaponKind::Unarmed)
-                        .into();
-                    shift += self.frm_db.get(fid).unwrap().frame_lists[obj.direction].center;
+                        .with_weapon(WeaponKind::Unarmed);
+                    if let Ok(frame_set) = self.frm_db.get(fid.into()) {
+                        shift += frame_set.frame_lists[obj.direction].center;
+                    }
                 }
                 let anim = if critter_fid.anim() == CritterAnim::FireDance {
                     CritterAnim::FireDance
                 } else {
                     CritterAnim::Stand
                 };
-                critter_fid
-                    .with_anim(anim)
-                    .into()
+                self.frm_db.critter_anim_or_fallback(critter_fid.with_anim(anim))
+                    .map(|fid| fid.into())
+                    .unwrap_or(obj.fid)
             } else {
                 obj.fid
             };
@@ -1537,6 +1661,7 @@ impl Objects {
     {
         let o = self.get(obj);
         let from = o.pos?;
+        let noclip = self.noclip && Some(obj) == self.dude;
 
         let (to_point, unblocked_radius) = match to {
             PathTo::Object(to_obj) => {
@@ -1550,7 +1675,9 @@ impl Objects {
                 (to_point, unblocked_radius)
             }
             PathTo::Point { point, neighbor_if_blocked } => {
-                let unblocked_radius = if neighbor_if_blocked && self.has_blocker_at(point.elevated(from.elevation), Some(obj)) {
+                let unblocked_radius = if neighbor_if_blocked && !noclip
+                    && self.has_blocker_at(point.elevated(from.elevation), Some(obj))
+                {
                     1
                 } else {
                     0
@@ -1562,7 +1689,7 @@ impl Objects {
         let mut r = self.path_finder.borrow_mut().find(from.point, to_point, smooth,
             |p| {
                 let p = p.elevated(from.elevation);
-                let blocked =
+                let blocked = !noclip &&
                     // p is not in unblocked_radius
                     hex::try_distance(p.point, to_point, unblocked_radius).map(|d| d < unblocked_radius) != Some(true) &&
                     self.has_blocker_at(p, Some(obj));
@@ -1598,6 +1725,7 @@ impl Objects {
         if let Some(path) = r.as_mut() {
             let l = path.len() - unblocked_radius as usize;
             path.truncate(l);
+            self.last_path.replace(Some((from, path.clone())));
         }
         r
     }
@@ -1608,34 +1736,30 @@ impl Objects {
         self.get(obj).bounds(&self.frm_db, tile_grid, include_outline)
     }
 
+    /// Returns objects on the elevation of `p` whose visible pixels are at `p`. The objects are
+    /// ordered from the topmost to the bottommost as drawn by `render()`.
     pub fn hit_test(&self, p: EPoint, screen_rect: Rect, tile_grid: &impl TileGridView,
         egg: Option<Egg>) -> Vec<(Handle, Hit)>
     {
         let mut r = Vec::new();
-        let hex_rect = Self::get_render_hex_rect(screen_rect, tile_grid);
-        for y in (hex_rect.top..hex_rect.bottom).rev() {
-            for x in hex_rect.left..hex_rect.right {
-                let pos = EPoint {
-                    elevation: p.elevation,
-                    point: Point::new(x, y),
-                };
-                for &objh in self.at(pos).iter().rev() {
-                    let obj = self.get(objh);
+        for flat in [false, true] {
+            let order = self.render_order(p.elevation, screen_rect, tile_grid, flat);
+            for objh in order.into_iter().rev() {
+                let obj = self.get(objh);
 
-                    let mut hit = if let Some(hit) = obj.hit_test(p.point, &self.frm_db, tile_grid) {
-                        hit
-                    } else {
-                        continue;
-                    };
+                let mut hit = if let Some(hit) = obj.hit_test(p.point, &self.frm_db, tile_grid) {
+                    hit
+                } else {
+                    continue;
+                };
 
-                    if let Some(egg) = egg {
-                        if self.is_egg_hit(p.point, &obj, egg, tile_grid) {
-                            hit.with_egg = true;
-                        }
+                if let Some(egg) = egg {
+                    if self.is_egg_hit(p.point, &obj, egg, tile_grid) {
+                        hit.with_egg = true;
                     }
-
-                    r.push((objh, hit));
                 }
+
+                r.push((objh, hit));
             }
         }
         r
@@ -1667,12 +1791,10 @@ impl Objects {
                 let proto = existing_obj.proto().unwrap();
                 if proto.kind() == ExactEntityKind::Item(ItemKind::Ammo) {
                     let mut item = self.get_mut(item);
-                    let max_ammo_count = proto.max_ammo_count().unwrap();
-                    let combined_ammo = existing_obj.ammo_count().unwrap() + item.ammo_count().unwrap();
-                    let full_clips = combined_ammo / max_ammo_count;
-                    let ammo = combined_ammo % max_ammo_count;
+                    let (ammo, extra) = merge_ammo(existing_obj.ammo_count().unwrap(),
+                        item.ammo_count().unwrap(), proto.max_ammo_count().unwrap());
                     item.sub.as_item_mut().unwrap().ammo_count = ammo;
-                    inventory.inventory.items[existing_idx].count += count - 1 + full_clips;
+                    inventory.inventory.items[existing_idx].count += count - 1 + extra;
                 } else {
                     inventory.inventory.items[existing_idx].count += count;
                 }
@@ -1692,6 +1814,222 @@ impl Objects {
         self.set_pos(item, None);
     }
 
+    /// Splits `count` items off the `item` stack in the `owner`'s inventory into a separate stack
+    /// placed before it. Returns the object of the new stack or `item` if the whole stack is
+    /// taken.
+    pub fn split_inventory_item(&mut self, owner: Handle, item: Handle, count: u32) -> Handle {
+        let stack_count = self.inventory_item_count(owner, item);
+        if count >= stack_count {
+            return item;
+        }
+        let copy = self.create_stack_copy(item, None);
+        let mut owner = self.get_mut(owner);
+        let items = &mut owner.inventory.items;
+        let i = items.iter().position(|i| i.object == item).unwrap();
+        items[i].count -= count;
+        items.insert(i, InventoryItem {
+            object: copy,
+            count,
+        });
+        copy
+    }
+
+    /// Puts the `item` stack back to the `owner`'s inventory merging it with the same items.
+    /// This is needed after the item is taken out of the equipment slot.
+    pub fn restack_inventory_item(&mut self, owner: Handle, item: Handle) {
+        let count = self.take_inventory_item(owner, item);
+        self.move_into_inventory(owner, item, count);
+    }
+
+    /// Puts one `item` from the `critter`'s inventory into the equipment `slot`. The item
+    /// previously in the slot goes back to the inventory. Wearing armor updates the critter's
+    /// armor stats and appearance.
+    // inven_wield
+    pub fn wield(&mut self, critter: Handle, item: Handle, slot: EquipmentSlot, rpg: &Rpg) {
+        let old = self.get(critter).equipment(slot, self);
+        if old == Some(item) {
+            return;
+        }
+        let item = self.split_inventory_item(critter, item, 1);
+        {
+            let mut item = self.get_mut(item);
+            item.flags.remove(Flag::Worn | Flag::LeftHand | Flag::RightHand);
+            item.flags.insert(slot.flag());
+        }
+        if slot == EquipmentSlot::Armor {
+            rpg.apply_armor_change(&mut self.get_mut(critter),
+                old.map(|o| self.get(o)).as_deref(),
+                Some(&self.get(item)),
+                self);
+        }
+        if let Some(old) = old {
+            self.restack_inventory_item(critter, old);
+        }
+        self.sync_equipped_fid(critter, rpg);
+    }
+
+    /// Moves the item in the equipment `slot` of the `critter` back to its inventory.
+    // inven_unwield
+    pub fn unwield(&mut self, critter: Handle, slot: EquipmentSlot, rpg: &Rpg) {
+        let old = unwrap_or_return!(self.get(critter).equipment(slot, self), Some);
+        if slot == EquipmentSlot::Armor {
+            rpg.apply_armor_change(&mut self.get_mut(critter), Some(&self.get(old)), None, self);
+        }
+        self.restack_inventory_item(critter, old);
+        self.sync_equipped_fid(critter, rpg);
+    }
+
+    // adjust_fid
+    pub fn sync_equipped_fid(&self, critter: Handle, rpg: &Rpg) {
+        let mut critter = self.get_mut(critter);
+        critter.fid = critter.equipped_fid(self, rpg);
+    }
+
+    /// Moves `count` of `item` from the `from` inventory to the `to` inventory.
+    // item_move_force
+    pub fn move_between_inventories(&mut self, from: Handle, to: Handle, item: Handle,
+        count: u32)
+    {
+        let item = self.split_inventory_item(from, item, count);
+        let count = self.take_inventory_item(from, item);
+        self.move_into_inventory(to, item, count);
+    }
+
+    fn inventory_item_count(&self, owner: Handle, item: Handle) -> u32 {
+        self.get(owner).inventory.items.iter()
+            .find(|i| i.object == item)
+            .expect("item is not in the inventory")
+            .count
+    }
+
+    /// Removes the `item` stack from the `owner`'s inventory leaving the object detached and
+    /// unequipped. Returns the stack count.
+    fn take_inventory_item(&mut self, owner: Handle, item: Handle) -> u32 {
+        let count = {
+            let mut owner = self.get_mut(owner);
+            let items = &mut owner.inventory.items;
+            let i = items.iter().position(|i| i.object == item)
+                .expect("item is not in the inventory");
+            items.remove(i).count
+        };
+        self.get_mut(item).flags.remove(Flag::Worn | Flag::LeftHand | Flag::RightHand);
+        count
+    }
+
+    /// Creates a new object of the same kind as the inventory `item`. Items in a stack other than
+    /// the top one are in their initial state (e.g. full magazines).
+    fn create_stack_copy(&mut self, item: Handle, pos: Option<EPoint>) -> Handle {
+        let (fid, proto) = {
+            let item = self.get(item);
+            (item.fid, item.proto.clone())
+        };
+        self.create(Some(fid), proto, pos, None).handle()
+    }
+
+    /// Removes `count` of `item` from the `inventory` stack. The `item` object is removed from
+    /// the world when the last one is taken.
+    // item_remove_mult
+    pub fn remove_from_inventory(&mut self, inventory: Handle, item: Handle, count: u32) {
+        let last = {
+            let mut inventory = self.get_mut(inventory);
+            let items = &mut inventory.inventory.items;
+            let i = items.iter().position(|i| i.object == item)
+                .expect("item is not in the inventory");
+            if items[i].count > count {
+                items[i].count -= count;
+                false
+            } else {
+                items.remove(i);
+                true
+            }
+        };
+        if last {
+            self.remove_deep(item);
+        }
+    }
+
+    /// Returns `true` if `count` of `item` can be added to the `inventory` without exceeding the
+    /// carry weight. Only critters have the carry weight limit.
+    // item_add_mult
+    pub fn can_carry(&self, inventory: Handle, item: Handle, count: u32, rpg: &Rpg) -> bool {
+        let owner = self.get(inventory);
+        if owner.kind() != EntityKind::Critter {
+            // TODO container size limit.
+            return true;
+        }
+        let weight = self.get(item).item_weight(self).unwrap_or(0) * count;
+        owner.carry_weight_left(rpg, self) >= weight as i32
+    }
+
+    /// Moves `count` of `item` into the `inventory` if it can carry them. Returns `false` if
+    /// the items are too heavy.
+    pub fn try_move_into_inventory(&mut self, inventory: Handle, item: Handle, count: u32,
+        rpg: &Rpg) -> bool
+    {
+        if self.can_carry(inventory, item, count, rpg) {
+            self.move_into_inventory(inventory, item, count);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes `count` of `item` out of the `owner`'s inventory and puts them on the ground at `pos`.
+    /// Every item of the stack becomes a separate object. Returns the dropped objects.
+    // obj_drop
+    pub fn drop_from_inventory(&mut self, owner: Handle, item: Handle, count: u32, pos: EPoint)
+        -> Vec<Handle>
+    {
+        let stack_count = self.inventory_item_count(owner, item);
+        let count = cmp::min(count, stack_count);
+
+        // The rest of the stack are copies of the top item.
+        let copies = if count == stack_count { count - 1 } else { count };
+        let mut r: Vec<_> = (0..copies)
+            .map(|_| self.create_stack_copy(item, Some(pos)))
+            .collect();
+
+        if count < stack_count {
+            let mut owner = self.get_mut(owner);
+            let items = &mut owner.inventory.items;
+            let i = items.iter().position(|i| i.object == item).unwrap();
+            items[i].count -= count;
+        } else {
+            self.take_inventory_item(owner, item);
+            self.set_pos(item, Some(pos));
+            r.push(item);
+        }
+        r
+    }
+
+    /// Removes `obj` and its inventory from the world, taking it out of the owner's inventory
+    /// if needed.
+    // obj_destroy
+    pub fn destroy(&mut self, obj: Handle) {
+        if let Some(owner) = self.owner_of(obj) {
+            let count = self.get(owner).inventory.items.iter()
+                .find(|i| i.object == obj).unwrap().count;
+            self.remove_from_inventory(owner, obj, count);
+        } else {
+            self.remove_deep(obj);
+        }
+    }
+
+    /// Returns object that has `item` in its inventory.
+    pub fn owner_of(&self, item: Handle) -> Option<Handle> {
+        self.iter().find(|&h| self.get(h).inventory.items.iter().any(|i| i.object == item))
+    }
+
+    /// Returns the outermost owner of `item` or the `item` itself if it's not in an inventory.
+    // obj_top_environment
+    pub fn top_owner(&self, item: Handle) -> Handle {
+        let mut r = item;
+        while let Some(owner) = self.owner_of(r) {
+            r = owner;
+        }
+        r
+    }
+
     // item_w_unload
     pub fn unload_weapon(&mut self, weapon: Handle) -> Option<Handle> {
         let (ammo_proto, count) = {
@@ -1773,13 +2111,13 @@ impl Objects {
         }
     }
 
-    fn get_render_hex_rect(screen_rect: Rect, tile_grid: &impl TileGridView) -> Rect {
+    fn get_render_hex_rect(&self, screen_rect: Rect, tile_grid: &impl TileGridView) -> Rect {
         tile_grid.enclose(Rect {
             left: -320,
             top: -190,
             right: screen_rect.width() + 320,
             bottom: screen_rect.height() + 190
-        })
+        }).intersect(Rect::with_size(0, 0, self.tile_grid.width(), self.tile_grid.height()))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -1787,26 +2125,60 @@ impl Objects {
             screen_rect: Rect, tile_grid: &impl TileGridView, egg: Option<Egg>,
             get_light: impl Fn(Option<EPoint>) -> u32,
             flat: bool) {
-        let hex_rect = Self::get_render_hex_rect(screen_rect, tile_grid);
+        for objh in self.render_order(elevation, screen_rect, tile_grid, flat) {
+            let mut obj = self.get_mut(objh);
+            let light = get_light(obj.pos);
+            assert!(light <= 0x10000);
+            obj.render(canvas, light, &self.frm_db, tile_grid, egg);
+        }
+    }
+
+    /// Returns flat or non-flat objects visible in `screen_rect` in the order they must be drawn.
+    /// All flat objects are drawn before all non-flat ones. Within each layer objects are drawn
+    /// in the tile order of their render hex (see `render_hex()`): rows top to bottom, each row
+    /// from the highest `x` to the lowest. Objects of the same hex keep their per-hex order.
+    // obj_render_pre_roof()
+    fn render_order(&self, elevation: u32, screen_rect: Rect, tile_grid: &impl TileGridView,
+            flat: bool) -> Vec<Handle> {
+        let hex_rect = self.get_render_hex_rect(screen_rect, tile_grid);
+        let mut r = Vec::new();
         for y in hex_rect.top..hex_rect.bottom {
             for x in (hex_rect.left..hex_rect.right).rev() {
                 let pos = EPoint {
                     elevation,
                     point: Point::new(x, y),
                 };
+                // Flags can be changed without reinserting the object so the per-hex list
+                // can't be relied upon to have flat objects first.
                 for &objh in self.at(pos) {
-                    let mut obj = self.get_mut(objh);
-                    if flat && !obj.flags.contains(Flag::Flat) {
-                        break;
-                    } else if !flat && obj.flags.contains(Flag::Flat) {
-                        continue;
+                    let obj = self.get(objh);
+                    if obj.flags.contains(Flag::Flat) == flat {
+                        let hex = Self::render_hex(&obj);
+                        // Objects stepping into a hex are drawn over the ones standing there.
+                        let stepping = hex != pos.point;
+                        r.push(((hex.y, -hex.x, stepping), objh));
                     }
-                    let light = get_light(obj.pos);
-                    assert!(light <= 0x10000);
-                    obj.render(canvas, light, &self.frm_db, tile_grid, egg);
                 }
             }
         }
+        // Stable sort to keep the per-hex order.
+        r.sort_by_key(|&(k, _)| k);
+        r.into_iter().map(|(_, h)| h).collect()
+    }
+
+    /// Returns hex that defines the object's place in the draw order.
+    /// Critters stay attached to their hex until a step to the next hex is complete. During the
+    /// step they're drawn in the order of the hex their shifted center is over, otherwise
+    /// walls in the next hex row would overdraw the critter walking in front of them.
+    /// Multi-hex objects are ordered by their center hex as in the original: the hexes around
+    /// the center are blocked by the object so nothing non-flat can stand there.
+    fn render_hex(obj: &Object) -> Point {
+        let pos = obj.pos().point;
+        if obj.kind() == EntityKind::Critter && obj.screen_shift != Point::new(0, 0) {
+            hex::from_screen(hex::center_to_screen(pos) + obj.screen_shift)
+        } else {
+            pos
+        }
     }
 
     fn at_mut(&mut self, pos: EPoint) -> &mut Vec<Handle> {
@@ -1843,6 +2215,11 @@ impl Objects {
 
         // By shift_x, less first.
         shift.x.cmp(&other_shift.x)
+            // Items lying on the ground are drawn under the critters on the same spot.
+            .then_with(|| {
+                let critter = o1.kind() == EntityKind::Critter;
+                critter.cmp(&(o2.kind() == EntityKind::Critter))
+            })
     }
 
     fn insert_into_tile_grid(&mut self, h: Handle, pos: Option<EPoint>, reset_screen_shift: bool) {
@@ -1875,6 +2252,9 @@ impl Objects {
                 }
             };
             self.at_mut(pos).insert(i, h);
+            if self.get(h).kind() == EntityKind::Critter {
+                self.critters[pos.elevation as usize].push(h);
+            }
         } else {
             self.detached.push(h);
         }
@@ -1889,6 +2269,9 @@ impl Objects {
         };
         // TODO maybe use binary_search for detaching.
         list.retain(|&hh| hh != h);
+        if let Some(old_pos) = old_pos {
+            self.critters[old_pos.elevation as usize].retain(|&hh| hh != h);
+        }
         old_pos
     }
 
@@ -1939,12 +2322,23 @@ pub enum SubObject {
     Scenery(Scenery),
 }
 
+/// State specific to the dude. Kept in the dude object so it follows the dude between maps and is
+/// written along with the object when it's serialized. There are no saved games yet, so the name
+/// and appearance last for the session only. Like in the original the appearance is shown by the
+/// dude's art in the inventory and loot screens, there's no separate portrait.
 #[derive(Debug)]
 pub struct Dude {
+    pub name: BString,
+    /// Critter art shown when no armor is worn.
     pub naked_fidx: Idx,
     pub active_hand: Hand,
 }
 
+impl Dude {
+    pub const DEFAULT_NAME: &'static str = "None";
+    pub const MAX_NAME_LEN: usize = 11;
+}
+
 #[derive(Debug)]
 pub struct Critter {
     pub hit_points: i32,
@@ -1994,6 +2388,8 @@ pub struct CritterCombat {
     pub ai_packet: i32,
     pub team_id: i32,
     pub who_hit_me: i32,
+    /// Critter this critter wants to fight. See `game::team`.
+    pub enemy: Option<Handle>,
 }
 
 #[bitflags]
@@ -2065,11 +2461,30 @@ pub struct Key {
     pub id: i32,
 }
 
+/// Merges ammo of the top magazines of two ammo stacks. Returns ammo count of the resulting top
+/// magazine and the number of extra full magazines.
+fn merge_ammo(ammo1: u32, ammo2: u32, max_ammo_count: u32) -> (u32, u32) {
+    let ammo = ammo1 + ammo2;
+    if ammo > max_ammo_count {
+        (ammo - max_ammo_count, 1)
+    } else {
+        (ammo, 0)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::graphics::geometry::hex::View;
 
+    #[test]
+    fn merge_ammo_() {
+        assert_eq!(merge_ammo(3, 4, 10), (7, 0));
+        assert_eq!(merge_ammo(5, 5, 10), (10, 0));
+        assert_eq!(merge_ammo(6, 7, 10), (3, 1));
+        assert_eq!(merge_ammo(10, 10, 10), (10, 1));
+    }
+
     #[test]
     fn bounds() {
         let screen_shift = Point::new(10, 20);
@@ -2081,4 +2496,248 @@ mod test {
             Rect::with_points(Point::new(1, -51), Point::new(30, 12))
                 .translate(base));
     }
+
+    #[test]
+    fn is_decal() {
+        let scenery = FrameId::new_generic(EntityKind::Scenery, 1).unwrap();
+        let critter = FrameId::new_critter(None, CritterAnim::Stand, WeaponKind::Unarmed, 1)
+            .unwrap();
+        let flat = |fid| {
+            let mut obj = Object::new(fid, None, Some((0, (1, 1)).into()), SubObject::None);
+            obj.flags.insert(Flag::Flat);
+            obj
+        };
+
+        assert!(flat(scenery).is_decal());
+        assert!(!Object::new(scenery, None, None, SubObject::None).is_decal());
+        assert!(!flat(critter).is_decal());
+
+        let mut door = flat(scenery);
+        door.sub = SubObject::Scenery(Scenery::Door(Door::default()));
+        assert!(!door.is_decal());
+    }
+
+    #[test]
+    fn in_radius() {
+        use crate::game::world::World;
+        use std::time::Instant;
+
+        let mut world = World::mock(Instant::now());
+        let center = EPoint::new(0, Point::new(50, 50));
+        let near = world.mock_object(EntityKind::Scenery, Some((0, (51, 50))));
+        let near_critter = world.mock_object(EntityKind::Critter, Some((0, (50, 52))));
+        let far_critter = world.mock_object(EntityKind::Critter, Some((0, (50, 60))));
+        let other_elevation = world.mock_object(EntityKind::Critter, Some((1, (50, 50))));
+        let _detached = world.mock_object(EntityKind::Critter, None);
+        let objs = world.objects_mut();
+
+        assert_eq!(objs.in_radius(center, 2), vec![near, near_critter]);
+        assert_eq!(objs.in_radius(center, 0), vec![]);
+        assert_eq!(objs.critters_in_radius(center, 2), vec![near_critter]);
+        assert_eq!(objs.critters_in_radius(center, 10), vec![near_critter, far_critter]);
+        assert_eq!(objs.critters(1), &[other_elevation]);
+
+        objs.set_pos(near_critter, Some(EPoint::new(1, Point::new(0, 0))));
+        assert_eq!(objs.critters(0), &[far_critter]);
+        assert_eq!(objs.critters(1), &[other_elevation, near_critter]);
+        objs.remove(far_critter);
+        assert!(objs.critters(0).is_empty());
+        objs.clear();
+        assert!(objs.critters(1).is_empty());
+    }
+
+    #[test]
+    fn make_standing_without_anims() {
+        use crate::game::world::World;
+        use std::time::Instant;
+
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+        let fid = FrameId::new_critter(None, CritterAnim::Walk, WeaponKind::Rifle, 1).unwrap();
+        let h = objs.create(Some(fid), None, Some((0, (10, 10)).into()), None).handle();
+        objs.make_standing(h);
+        assert_eq!(objs.get(h).fid, fid);
+        assert_eq!(objs.get(h).screen_shift, Point::new(0, 0));
+    }
+
+    #[test]
+    fn drop_from_inventory() {
+        use crate::game::world::World;
+        use std::time::Instant;
+
+        let mut world = World::mock(Instant::now());
+        let critter = FrameId::new_critter(None, CritterAnim::Stand, WeaponKind::Unarmed, 1)
+            .unwrap();
+        let item_fid = FrameId::new_generic(EntityKind::Item, 1).unwrap();
+        world.frm_db().mock_frame(critter, 1, 1, 1);
+        world.frm_db().mock_frame(item_fid, 1, 1, 1);
+        let objs = world.objects_mut();
+        let pos = EPoint::new(0, Point::new(10, 10));
+        let owner = objs.create(Some(critter), None, Some(pos), None).handle();
+        let item = objs.create(Some(item_fid), None, None, None).handle();
+        objs.get_mut(item).flags.insert(Flag::LeftHand);
+        objs.get_mut(owner).inventory.items.push(InventoryItem {
+            object: item,
+            count: 3,
+        });
+
+        let dropped = objs.drop_from_inventory(owner, item, 2, pos);
+        assert_eq!(dropped.len(), 2);
+        assert!(!dropped.contains(&item));
+        assert_eq!(objs.get(owner).inventory.items[0].count, 1);
+
+        assert_eq!(objs.drop_from_inventory(owner, item, 5, pos), vec![item]);
+        assert!(objs.get(owner).inventory.items.is_empty());
+        assert!(!objs.get(item).flags.contains(Flag::LeftHand));
+
+        let item2 = objs.create(Some(item_fid), None, None, None).handle();
+        let other = objs.create(Some(critter), None, Some(pos), None).handle();
+        objs.get_mut(owner).inventory.items.push(InventoryItem {
+            object: item2,
+            count: 5,
+        });
+        let split = objs.split_inventory_item(owner, item2, 2);
+        assert_ne!(split, item2);
+        assert_eq!(objs.get(owner).inventory.items.iter().map(|i| (i.object, i.count))
+            .collect::<Vec<_>>(), vec![(split, 2), (item2, 3)]);
+        assert_eq!(objs.split_inventory_item(owner, split, 2), split);
+
+        objs.move_between_inventories(owner, other, item2, 3);
+        assert_eq!(objs.get(owner).inventory.items.len(), 1);
+        assert_eq!(objs.get(other).inventory.items[0].object, item2);
+        assert_eq!(objs.get(other).inventory.items[0].count, 3);
+
+        // Items are drawn under the critters.
+        assert_eq!(objs.at(pos).len(), 5);
+        assert_eq!(&objs.at(pos)[3..], &[owner, other]);
+    }
+
+    #[test]
+    fn render_order() {
+        use crate::game::world::World;
+        use std::time::Instant;
+
+        let mut world = World::mock(Instant::now());
+        let mut create = |kind, pos| world.mock_object(kind, Some((0, pos)));
+        let wall = create(EntityKind::Scenery, (5, 10));
+        let walker = create(EntityKind::Critter, (10, 10));
+        let shifted_scenery = create(EntityKind::Scenery, (20, 10));
+        let next_row = create(EntityKind::Scenery, (30, 11));
+        let standing = create(EntityKind::Critter, (10, 11));
+        let corpse = create(EntityKind::Critter, (4, 10));
+        let floor = create(EntityKind::Scenery, (6, 9));
+        let objs = world.objects_mut();
+        objs.get_mut(corpse).flags.insert(Flag::Flat);
+        objs.get_mut(floor).flags.insert(Flag::Flat);
+
+        let view = View::default();
+        let screen_rect = Rect::with_size(0, 0, 640, 380);
+        let order = |objs: &Objects, flat| objs.render_order(0, screen_rect, &view, flat);
+
+        assert_eq!(order(objs, true), vec![floor, corpse]);
+        assert_eq!(order(objs, false), vec![shifted_scenery, walker, wall, next_row, standing]);
+
+        // At the end of the step to SE the walker is drawn in the order of the next row and
+        // over the critter standing there. Non-critters are never reordered.
+        objs.get_mut(walker).screen_shift = hex::screen_offset(Direction::SE);
+        objs.get_mut(shifted_scenery).screen_shift = hex::screen_offset(Direction::SE);
+        assert_eq!(order(objs, false), vec![shifted_scenery, wall, next_row, standing, walker]);
+    }
+
+    #[test]
+    fn golden_walls_and_critters() {
+        use crate::asset::palette::read_palette;
+        use crate::fs::FileSystem;
+        use crate::graphics::color::Rgb15;
+        use crate::graphics::color::palette::overlay::PaletteOverlay;
+        use crate::graphics::font::Fonts;
+        use crate::graphics::render::golden::{self, Tolerance};
+        use crate::graphics::render::software::Backend;
+        use crate::util::test::ungz;
+
+        let data = ungz(include_bytes!("../graphics/color/color.pal.gz"));
+        let palette = read_palette(&mut std::io::Cursor::new(&data[..])).unwrap();
+        let backend = Backend::new_offscreen(64, 64, Box::new(palette),
+            PaletteOverlay::standard());
+
+        let fs = Rc::new(FileSystem::mock());
+        let frm_db = FrameDb::mock(fs.clone(), backend.new_texture_factory());
+        let wall = FrameId::new_generic(EntityKind::Wall, 1).unwrap();
+        frm_db.mock_frame(wall, 48, 24, 0x30);
+        let critter = |idx, color| {
+            let fid = FrameId::new_critter(None, CritterAnim::Stand, WeaponKind::Unarmed, idx)
+                .unwrap();
+            frm_db.mock_frame(fid, 16, 32, color);
+            fid
+        };
+        let (behind, in_front, walking) = (critter(1, 0x90), critter(2, 0x50), critter(3, 0xd0));
+
+        let mut objs = Objects::new(TileGrid::default(), 1, Rc::new(frm_db),
+            Rc::new(ProtoDb::mock(fs)));
+        let mut create = |fid, pos: (i32, i32)|
+            objs.create(Some(fid), None, Some((0, pos).into()), None).handle();
+        create(wall, (10, 10));
+        // The wall covers the lower part of the critter behind it.
+        create(behind, (10, 9));
+        create(in_front, (10, 11));
+        // At the end of the step from the row behind the wall into the wall row. Left of the wall
+        // hex so it's in front of the wall and must not be covered by it.
+        let walker = create(walking, (9, 9));
+        objs.set_screen_shift(walker, hex::screen_offset(Direction::SE));
+
+        let mut canvas = backend.into_canvas(Rc::new(Fonts::new()));
+        canvas.clear(Rgb15::new(4, 8, 12));
+        objs.render(&mut *canvas, 0, Rect::with_size(0, 0, 64, 64),
+            &View::new(Point::new(-384, -20)), None, |_| 0x10000);
+        golden::check("walls_and_critters", &canvas.screenshot().unwrap(), Tolerance::EXACT);
+    }
+
+    #[test]
+    fn can_carry() {
+        use crate::game::world::World;
+        use enum_map::EnumMap;
+        use std::time::Instant;
+
+        fn item(objs: &mut Objects, weight: u32) -> Handle {
+            let mut proto = Proto::mock_item(ProtoId::new(EntityKind::Item, 1).unwrap(),
+                SubItem::Misc(MiscItem {
+                    ammo_proto_id: None,
+                    ammo_kind: 0,
+                    max_ammo_count: 0,
+                }));
+            proto.sub.as_item_mut().unwrap().weight = weight;
+            objs.create(None, Some(Rc::new(RefCell::new(proto))), None, None).handle()
+        }
+
+        let rpg = Rpg::mock();
+        let mut world = World::mock(Instant::now());
+        let objs = world.objects_mut();
+        let mut stats = EnumMap::new();
+        stats[Stat::CarryWeight] = 10;
+        let proto = Proto::mock_critter(ProtoId::new(EntityKind::Critter, 2).unwrap(), stats);
+        let critter = objs.create(None, Some(Rc::new(RefCell::new(proto))),
+            Some((0, (50, 50)).into()), Some(&rpg)).handle();
+
+        let rock = item(objs, 4);
+        assert!(objs.can_carry(critter, rock, 2, &rpg));
+        assert!(!objs.can_carry(critter, rock, 3, &rpg));
+
+        assert!(objs.try_move_into_inventory(critter, rock, 2, &rpg));
+        assert_eq!(objs.get(critter).inventory.weight(objs), 8);
+
+        // Picking up is refused over the limit and the inventory is left as is.
+        let boulder = item(objs, 3);
+        assert!(!objs.try_move_into_inventory(critter, boulder, 1, &rpg));
+        assert_eq!(objs.get(critter).inventory.items.len(), 1);
+        assert_eq!(objs.get(critter).inventory.weight(objs), 8);
+
+        let pebble = item(objs, 2);
+        assert!(objs.try_move_into_inventory(critter, pebble, 1, &rpg));
+        assert_eq!(objs.get(critter).carry_weight_left(&rpg, objs), 0);
+        assert!(!objs.get(critter).is_overloaded(&rpg, objs));
+
+        // Only critters have the limit.
+        let bag = item(objs, 0);
+        assert!(objs.can_carry(bag, boulder, 100, &rpg));
+    }
 }
@@ -0,0 +1,301 @@
+use bstring::BString;
+use log::*;
+use std::rc::Rc;
+
+use crate::asset::frame::FrameId;
+use crate::asset::holodisk::{self, Holodisk};
+use crate::asset::message::{MessageId, Messages};
+use crate::asset::message::localization::Localization;
+use crate::asset::proto::ProtoId;
+use crate::asset::quest::{self, Quest};
+use crate::error::{self, ResultExt};
+use crate::game::ui::inventory_list::Scroll;
+use crate::game::ui::quest_list::{self, QuestList};
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::{GREEN, Rgb15};
+use crate::graphics::font::{FontKey, VertAlign};
+use crate::graphics::sprite::Sprite;
+use crate::ui::*;
+use crate::ui::button::{self, Button};
+use crate::ui::command::{PipboyCommand, UiCommandData};
+
+const FONT: FontKey = FontKey::antialiased(1);
+const COMPLETED_COLOR: Rgb15 = unsafe { Rgb15::rgb15_from_packed_unchecked(0x01e0) };
+const DONE_MSG: MessageId = 214;
+
+pub struct Pipboy {
+    quests: Vec<Quest>,
+    quest_msgs: Rc<Messages>,
+    map_msgs: Rc<Messages>,
+    holodisks: Vec<Holodisk>,
+    pipboy_msgs: Rc<Messages>,
+    state: Option<State>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Page {
+    /// Town list or quests of a single town if `town` is set.
+    Status {
+        town: Option<MessageId>,
+    },
+    /// List of added holodisks.
+    Archives,
+    /// Reader of the holodisk with the index in `Pipboy::holodisks`.
+    Holodisk(usize),
+}
+
+struct State {
+    window: Handle,
+    list: Handle,
+    scroll_up: Handle,
+    scroll_down: Handle,
+    done: Handle,
+    page: Page,
+    /// Displayed and completed state of each quest as of the last refresh.
+    quest_states: Vec<(bool, bool)>,
+}
+
+impl Pipboy {
+    pub fn new(loc: &Localization) -> error::Result<Self> {
+        let fs = loc.fs();
+        let quests = quest::read_quests(&mut fs.reader("data/quests.txt")?)
+            .context(|| "reading data/quests.txt")?;
+        let quest_msgs = loc.messages("game/quests.msg")?;
+        let map_msgs = loc.messages("game/map.msg")?;
+        let holodisks = holodisk::read_holodisks(&mut fs.reader("data/holodisk.txt")?)
+            .context(|| "reading data/holodisk.txt")?;
+        let pipboy_msgs = loc.messages("game/pipboy.msg")?;
+        Ok(Self {
+            quests,
+            quest_msgs,
+            map_msgs,
+            holodisks,
+            pipboy_msgs,
+            state: None,
+        })
+    }
+
+    /// Loads the messages of the current language of `loc`. The open window keeps the old
+    /// texts until the page is changed.
+    pub fn reload_messages(&mut self, loc: &Localization) -> error::Result<()> {
+        self.quest_msgs = loc.messages("game/quests.msg")?;
+        self.map_msgs = loc.messages("game/map.msg")?;
+        self.pipboy_msgs = loc.messages("game/pipboy.msg")?;
+        Ok(())
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Returns global var of the holodisk added by using item `pid`. Used for holodisk items that
+    /// have no script to set the var in `use_p_proc`.
+    pub fn holodisk_global_var(&self, pid: ProtoId) -> Option<usize> {
+        self.holodisks.iter()
+            .find(|h| h.item == Some(pid))
+            .map(|h| h.global_var)
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, global_vars: &[i32]) {
+        assert!(self.state.is_none());
+
+        let window = ui.new_window(Rect::with_size(0, 0, 640, 480),
+            Some(Sprite::new(FrameId::PIP)));
+        ui.widget_base_mut(window).set_modal(true);
+
+        let mut new_button = |y, cmd| {
+            ui.new_widget(window, Rect::with_size(53, y, 15, 16), None, None,
+                Button::new(FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN,
+                    Some(UiCommandData::Pipboy(cmd))));
+        };
+        new_button(340, PipboyCommand::Status);
+        new_button(423, PipboyCommand::Archives);
+        new_button(448, PipboyCommand::Hide);
+
+        let list = QuestList::new(ui.fonts().clone(), FONT, GREEN, COMPLETED_COLOR);
+        let list = ui.new_widget(window, Rect::with_size(254, 46, 340, 410), None, None, list);
+
+        let mut new_scroll_button = |y, up, down, disabled, scroll| {
+            let mut b = Button::new(up, down,
+                Some(UiCommandData::Pipboy(PipboyCommand::Scroll(scroll))));
+            b.config_mut(button::State::Disabled).background = Some(Sprite::new(disabled));
+            ui.new_widget(window, Rect::with_size(600, y, 22, 23), None, None, b)
+        };
+        let scroll_up = new_scroll_button(46,
+            FrameId::INVENTORY_SCROLL_UP_UP,
+            FrameId::INVENTORY_SCROLL_UP_DOWN,
+            FrameId::INVENTORY_SCROLL_UP_DISABLED,
+            Scroll::Up);
+        let scroll_down = new_scroll_button(69,
+            FrameId::INVENTORY_SCROLL_DOWN_UP,
+            FrameId::INVENTORY_SCROLL_DOWN_DOWN,
+            FrameId::INVENTORY_SCROLL_DOWN_DISABLED,
+            Scroll::Down);
+
+        let btn_size = ui.frm_db().get(FrameId::SMALL_RED_BUTTON_UP).unwrap().first().size();
+        let mut done = Button::new(FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN,
+            Some(UiCommandData::Pipboy(PipboyCommand::Done)));
+        let done_text = self.pipboy_msgs.get(DONE_MSG)
+            .map(|m| m.text.clone())
+            .unwrap_or_else(|| BString::from(&b"Done"[..]));
+        let mut text = button::Text::new(done_text, FONT);
+        text.pos = Point::new(btn_size.x + 9, 1);
+        text.color = GREEN;
+        text.options.vert_align = VertAlign::Middle;
+        done.set_text(Some(text));
+        let done = ui.new_widget(window, Rect::with_size(254, 456, 90, btn_size.y), None, None,
+            done);
+
+        self.state = Some(State {
+            window,
+            list,
+            scroll_up,
+            scroll_down,
+            done,
+            page: Page::Status { town: None },
+            quest_states: Vec::new(),
+        });
+        self.refresh(ui, global_vars);
+    }
+
+    pub fn hide(&mut self, ui: &mut Ui) {
+        let state = self.state.take().unwrap();
+        ui.remove(state.window);
+    }
+
+    pub fn handle(&mut self, command: PipboyCommand, ui: &mut Ui, global_vars: &[i32]) {
+        match command {
+            PipboyCommand::Hide => self.hide(ui),
+            PipboyCommand::Show => self.show(ui, global_vars),
+            PipboyCommand::Status => {
+                self.state.as_mut().unwrap().page = Page::Status { town: None };
+                self.refresh(ui, global_vars);
+            }
+            PipboyCommand::Town { location } => {
+                self.state.as_mut().unwrap().page = Page::Status { town: Some(location) };
+                self.refresh(ui, global_vars);
+            }
+            PipboyCommand::Archives | PipboyCommand::Done => {
+                self.state.as_mut().unwrap().page = Page::Archives;
+                self.refresh(ui, global_vars);
+            }
+            PipboyCommand::Holodisk { idx } => {
+                self.state.as_mut().unwrap().page = Page::Holodisk(idx);
+                self.refresh(ui, global_vars);
+            }
+            PipboyCommand::Scroll(scroll) => {
+                let state = self.state.as_ref().unwrap();
+                ui.widget_mut::<QuestList>(state.list).scroll(scroll);
+                self.update_scroll_buttons(ui);
+            }
+        }
+    }
+
+    /// Refreshes the status page if a global var change made a quest appear or get completed.
+    pub fn sync(&mut self, ui: &mut Ui, global_vars: &[i32]) {
+        let changed = if let Some(state) = &self.state {
+            matches!(state.page, Page::Status { .. })
+                && state.quest_states != self.quest_states(global_vars)
+        } else {
+            false
+        };
+        if changed {
+            debug!("quest log changed, refreshing pipboy status");
+            self.refresh(ui, global_vars);
+        }
+    }
+
+    fn quest_states(&self, global_vars: &[i32]) -> Vec<(bool, bool)> {
+        self.quests.iter()
+            .map(|q| (q.is_displayed(global_vars), q.is_completed(global_vars)))
+            .collect()
+    }
+
+    /// Returns towns that have at least one displayed quest, in order of `quests.txt`.
+    fn towns(&self, global_vars: &[i32]) -> Vec<MessageId> {
+        let mut r = Vec::new();
+        for quest in &self.quests {
+            if quest.is_displayed(global_vars) && !r.contains(&quest.location) {
+                r.push(quest.location);
+            }
+        }
+        r
+    }
+
+    // pipboy_status, pipboy_print_quests, pipboy_print_holodisks, pipboy_display_holodisk
+    fn refresh(&mut self, ui: &mut Ui, global_vars: &[i32]) {
+        let quest_states = self.quest_states(global_vars);
+        self.state.as_mut().unwrap().quest_states = quest_states;
+        let state = self.state.as_ref().unwrap();
+        ui.widget_base_mut(state.done).set_visible(
+            matches!(state.page, Page::Holodisk(_)));
+        {
+            let mut list = ui.widget_mut::<QuestList>(state.list);
+            list.clear();
+            match state.page {
+                Page::Status { town: Some(town) } => {
+                    if let Some(msg) = self.map_msgs.get(town) {
+                        list.push(&msg.text, quest_list::Style::Quest);
+                        list.push_space();
+                    }
+                    for quest in self.quests.iter()
+                        .filter(|q| q.location == town && q.is_displayed(global_vars))
+                    {
+                        let msg = if let Some(msg) = self.quest_msgs.get(quest.description) {
+                            msg
+                        } else {
+                            warn!("missing quest description message {}", quest.description);
+                            continue;
+                        };
+                        let style = if quest.is_completed(global_vars) {
+                            quest_list::Style::Completed
+                        } else {
+                            quest_list::Style::Quest
+                        };
+                        list.push(&msg.text, style);
+                        list.push_space();
+                    }
+                }
+                Page::Status { town: None } => {
+                    for town in self.towns(global_vars) {
+                        if let Some(msg) = self.map_msgs.get(town) {
+                            list.push(&msg.text, quest_list::Style::Town(town));
+                        } else {
+                            warn!("missing town name message {}", town);
+                        }
+                    }
+                }
+                Page::Archives => {
+                    for (i, disk) in self.holodisks.iter().enumerate()
+                        .filter(|(_, h)| h.is_added(global_vars))
+                    {
+                        if let Some(msg) = self.pipboy_msgs.get(disk.name) {
+                            list.push(&msg.text, quest_list::Style::Holodisk(i));
+                        } else {
+                            warn!("missing holodisk name message {}", disk.name);
+                        }
+                    }
+                }
+                Page::Holodisk(idx) => {
+                    let disk = &self.holodisks[idx];
+                    if let Some(msg) = self.pipboy_msgs.get(disk.name) {
+                        list.push(&msg.text, quest_list::Style::Quest);
+                        list.push_space();
+                    }
+                    for para in disk.paragraphs(&self.pipboy_msgs) {
+                        list.push(&para, quest_list::Style::Quest);
+                        list.push_space();
+                    }
+                }
+            }
+        }
+        self.update_scroll_buttons(ui);
+    }
+
+    fn update_scroll_buttons(&self, ui: &Ui) {
+        let state = self.state.as_ref().unwrap();
+        let list = ui.widget_ref::<QuestList>(state.list);
+        ui.widget_mut::<Button>(state.scroll_up).set_enabled(list.can_scroll(Scroll::Up));
+        ui.widget_mut::<Button>(state.scroll_down).set_enabled(list.can_scroll(Scroll::Down));
+    }
+}
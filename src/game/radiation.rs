@@ -0,0 +1,349 @@
+//! Radiation: exposure, radiation sickness and the Geiger counter.
+//!
+//! Critters accumulate rads (`Stat::CurrentRad`) from the environment and scripts. The amount
+//! is reduced by the radiation resistance. The dude's rads are checked periodically, and when
+//! they reach the next level of radiation sickness its penalties take effect after a delay. The
+//! penalties are lifted when the rads are brought down with RadAway.
+//!
+//! The engine only irradiates the critters standing in the radioactive goo, the other radiation
+//! zones come from the map and object scripts with `radiation_inc`. All the delays are in game
+//! time.
+
+use bstring::BString;
+use log::*;
+
+use crate::asset::message::{MessageId, Messages};
+use crate::asset::message::format;
+use crate::asset::proto::ProtoId;
+use crate::asset::Stat;
+use crate::game::GameTime;
+use crate::game::object::{EquipmentSlot, Hand, Handle, Objects};
+use crate::game::rng::{random, RollCheckResult, Stream};
+use crate::game::rpg::Rpg;
+
+/// How often in game deciseconds the dude's rads are checked for the radiation sickness.
+const CHECK_INTERVAL: u32 = 300;
+/// Range of delay in seconds before a new level of sickness takes effect.
+const ONSET_DELAY_MIN: i32 = 10;
+const ONSET_DELAY_MAX: i32 = 60;
+
+/// How often in game deciseconds the environmental radiation is applied.
+const EXPOSURE_INTERVAL: u32 = 50;
+/// Distance from a radioactive goo puddle within which critters get irradiated.
+const GOO_RADIUS: u32 = 1;
+/// Rads per exposure from a radioactive goo puddle.
+const GOO_RADS: i32 = 10;
+
+// misc.msg
+/// "You have received a large dose of radiation."
+const MSG_LARGE_DOSE: MessageId = 1007;
+/// Geiger counter clicking for small and large doses.
+const MSG_GEIGER_SLOW: MessageId = 1008;
+const MSG_GEIGER_FAST: MessageId = 1009;
+/// Messages of the sickness levels starting from `Level::Minor`.
+const MSG_LEVEL_BASE: MessageId = 1000;
+/// "You feel better." shown when the sickness goes away.
+const MSG_RECOVERED: MessageId = 1006;
+/// "You pass the Geiger counter over your body. The rad counter reads: %d"
+const MSG_GEIGER_READING: MessageId = 8;
+
+/// Level of radiation sickness.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Level {
+    None,
+    Minor,
+    Advanced,
+    Critical,
+    Deadly,
+    Fatal,
+}
+
+/// Stats affected by the radiation sickness.
+const PENALTY_STATS: [Stat; 8] = [
+    Stat::Strength,
+    Stat::Perception,
+    Stat::Endurance,
+    Stat::Charisma,
+    Stat::Intelligence,
+    Stat::Agility,
+    Stat::CurrentHitPoints,
+    Stat::HealRate,
+];
+
+impl Level {
+    const ALL: [Self; 6] = [Level::None, Level::Minor, Level::Advanced, Level::Critical,
+        Level::Deadly, Level::Fatal];
+
+    /// Level the `rads` lead to.
+    pub fn from_rads(rads: i32) -> Self {
+        match rads {
+            i32::MIN..=99 => Level::None,
+            100..=199 => Level::Minor,
+            200..=399 => Level::Advanced,
+            400..=599 => Level::Critical,
+            600..=999 => Level::Deadly,
+            _ => Level::Fatal,
+        }
+    }
+
+    fn next(self) -> Self {
+        Self::ALL.get(self as usize + 1).copied().unwrap_or(self)
+    }
+
+    /// Bonus to the Endurance roll that resists the level.
+    fn endurance_modifier(self) -> i32 {
+        [2, 0, -2, -4, -6, -8][self as usize]
+    }
+
+    /// Stat penalties of the level in the order of `PENALTY_STATS`.
+    fn penalties(self) -> [i32; 8] {
+        match self {
+            //                  ST  PE  EN  CH  IN  AG  HP  HR
+            Level::None     => [ 0,  0,  0,  0,  0,  0,   0,   0],
+            Level::Minor    => [-1,  0,  0,  0,  0,  0,   0,   0],
+            Level::Advanced => [-1,  0,  0,  0,  0, -1,   0,  -3],
+            Level::Critical => [-2,  0, -1,  0,  0, -2,  -5,  -5],
+            Level::Deadly   => [-4, -3, -3, -3, -1, -5, -15, -10],
+            Level::Fatal    => [-6, -5, -5, -5, -3, -6, -20, -10],
+        }
+    }
+}
+
+/// Rads absorbed from `amount` of radiation with `resistance` percents of radiation resistance.
+/// Negative amounts (treatment) aren't affected by the resistance.
+pub fn absorbed(amount: i32, resistance: i32) -> i32 {
+    if amount > 0 {
+        amount - amount * resistance / 100
+    } else {
+        amount
+    }
+}
+
+fn has_active_geiger_counter(obj: Handle, objs: &Objects) -> bool {
+    let objo = objs.get(obj);
+    [Hand::Left, Hand::Right].iter()
+        .filter_map(|&h| objo.equipment(EquipmentSlot::Hand(h), objs))
+        .any(|item| objs.get(item).proto_id() == Some(ProtoId::ACTIVE_GEIGER_COUNTER))
+}
+
+// critter_adjust_rads
+/// Adds `amount` of radiation to the critter `obj`. Returns messages for the dude to see.
+pub fn expose(obj: Handle, amount: i32, objs: &Objects, rpg: &Rpg) -> Vec<BString> {
+    let mut r = Vec::new();
+    if objs.get(obj).sub.as_critter().is_none() {
+        warn!("can't irradiate {:?}: not a critter", obj);
+        return r;
+    }
+    let amount = {
+        let objo = objs.get(obj);
+        absorbed(amount, rpg.stat(Stat::RadResist, &objo, objs))
+    };
+    let is_dude = obj == objs.dude();
+    if is_dude && amount > 0 {
+        let msgs = rpg.misc_msgs();
+        if has_active_geiger_counter(obj, objs) {
+            push_msg(&mut r, msgs, if amount > 5 { MSG_GEIGER_FAST } else { MSG_GEIGER_SLOW });
+        }
+        if amount >= 10 {
+            push_msg(&mut r, msgs, MSG_LARGE_DOSE);
+        }
+    }
+    let mut objo = objs.get_mut(obj);
+    let critter = objo.sub.as_critter_mut().unwrap();
+    critter.radiation = (critter.radiation + amount).max(0);
+    debug!("{:?} absorbed {} rads, {} total", obj, amount, critter.radiation);
+    r
+}
+
+/// Message with the rads of `obj` shown when the Geiger counter is turned on.
+pub fn geiger_reading(obj: Handle, objs: &Objects, rpg: &Rpg) -> Option<BString> {
+    let rads = rpg.stat(Stat::CurrentRad, &objs.get(obj), objs);
+    let m = rpg.misc_msgs().get(MSG_GEIGER_READING)?;
+    Some(format::format(&m.text, &[rads.into()]))
+}
+
+fn push_msg(out: &mut Vec<BString>, msgs: &Messages, id: MessageId) {
+    if let Some(m) = msgs.get(id) {
+        out.push(m.text.clone());
+    } else {
+        warn!("no message {} in misc.msg", id);
+    }
+}
+
+/// Result of the radiation update.
+#[derive(Debug, Default)]
+pub struct Update {
+    pub messages: Vec<BString>,
+    /// The sickness took the dude's last hit points.
+    pub dude_died: bool,
+}
+
+/// Environmental radiation and the dude's radiation sickness.
+pub struct Radiation {
+    /// Level whose penalties are applied to the dude.
+    level: Level,
+    /// Level that takes effect at the time.
+    onset: Option<(GameTime, Level)>,
+    next_check: GameTime,
+    next_exposure: GameTime,
+}
+
+impl Radiation {
+    pub fn new(now: GameTime) -> Self {
+        Self {
+            level: Level::None,
+            onset: None,
+            next_check: now.add_decis(CHECK_INTERVAL),
+            next_exposure: now.add_decis(EXPOSURE_INTERVAL),
+        }
+    }
+
+    /// Level of sickness the dude suffers from.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn update(&mut self, time: GameTime, elevation: u32, objs: &Objects, rpg: &Rpg)
+        -> Update
+    {
+        let mut r = Update::default();
+        if time >= self.next_exposure {
+            self.next_exposure = time.add_decis(EXPOSURE_INTERVAL);
+            for (obj, amount) in environment_exposure(elevation, objs) {
+                let msgs = expose(obj, amount, objs, rpg);
+                r.messages.extend(msgs);
+            }
+        }
+
+        let dude = objs.dude();
+        if objs.get(dude).sub.as_critter().map(|c| c.is_dead()).unwrap_or(true) {
+            return r;
+        }
+
+        if time >= self.next_check {
+            self.next_check = time.add_decis(CHECK_INTERVAL);
+            self.check(time, objs, rpg);
+        }
+
+        match self.onset {
+            Some((t, level)) if time >= t => {
+                self.onset = None;
+                self.set_level(level, objs, rpg, &mut r);
+            }
+            _ => {}
+        }
+        r
+    }
+
+    // critter_check_rads
+    fn check(&mut self, time: GameTime, objs: &Objects, rpg: &Rpg) {
+        let dude = objs.dude();
+        let rads = rpg.stat(Stat::CurrentRad, &objs.get(dude), objs);
+        let mut level = Level::from_rads(rads);
+        if level < self.level {
+            // Treated.
+            self.onset = Some((time, level));
+            return;
+        }
+        if level == Level::None || self.onset.is_some() {
+            return;
+        }
+        let (roll, _) = rpg.roll_check_stat(Stat::Endurance, level.endurance_modifier(),
+            Stream::Misc, &objs.get(dude), objs);
+        if roll == RollCheckResult::Failure {
+            level = level.next();
+        }
+        if level > self.level {
+            let delay = random(Stream::Misc, ONSET_DELAY_MIN, ONSET_DELAY_MAX);
+            debug!("radiation sickness {:?} in {} s ({} rads)", level, delay, rads);
+            self.onset = Some((time.add_decis(delay as u32 * 10), level));
+        }
+    }
+
+    // process_rads
+    fn set_level(&mut self, level: Level, objs: &Objects, rpg: &Rpg, out: &mut Update) {
+        if level == self.level {
+            return;
+        }
+        info!("radiation sickness level changed from {:?} to {:?}", self.level, level);
+        let dude = objs.dude();
+        let old = self.level.penalties();
+        let new = level.penalties();
+        let max_hp = rpg.stat(Stat::HitPoints, &objs.get(dude), objs);
+        {
+            let mut dudeo = objs.get_mut(dude);
+            for ((&stat, &old), &new) in PENALTY_STATS.iter().zip(&old).zip(&new) {
+                let delta = new - old;
+                if delta == 0 {
+                    continue;
+                }
+                if stat == Stat::CurrentHitPoints {
+                    let critter = dudeo.sub.as_critter_mut().unwrap();
+                    critter.hit_points = (critter.hit_points + delta).min(max_hp);
+                } else {
+                    rpg.add_bonus_stat(stat, &mut dudeo, delta, objs);
+                }
+            }
+        }
+
+        let msg = if level > self.level {
+            MSG_LEVEL_BASE + level as MessageId - 1
+        } else {
+            MSG_RECOVERED
+        };
+        push_msg(&mut out.messages, rpg.misc_msgs(), msg);
+
+        self.level = level;
+        out.dude_died = objs.get(dude).sub.as_critter().unwrap().hit_points <= 0;
+    }
+}
+
+/// Critters on the `elevation` standing in the radioactive goo and the rads they get.
+fn environment_exposure(elevation: u32, objs: &Objects) -> Vec<(Handle, i32)> {
+    let mut r = Vec::new();
+    for &h in objs.critters(elevation) {
+        let pos = if let Some(v) = objs.get(h).try_pos() {
+            v
+        } else {
+            continue;
+        };
+        if objs.get(h).sub.as_critter().map(|c| c.is_dead()).unwrap_or(true) {
+            continue;
+        }
+        let in_goo = objs.in_radius(pos, GOO_RADIUS).into_iter()
+            .filter_map(|o| objs.get(o).proto_id())
+            .any(|pid| (ProtoId::RADIOACTIVE_GOO_FIRST..=ProtoId::RADIOACTIVE_GOO_LAST)
+                .contains(&pid));
+        if in_goo {
+            r.push((h, GOO_RADS));
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_rads() {
+        assert_eq!(Level::from_rads(-5), Level::None);
+        assert_eq!(Level::from_rads(99), Level::None);
+        assert_eq!(Level::from_rads(100), Level::Minor);
+        assert_eq!(Level::from_rads(399), Level::Advanced);
+        assert_eq!(Level::from_rads(400), Level::Critical);
+        assert_eq!(Level::from_rads(999), Level::Deadly);
+        assert_eq!(Level::from_rads(1000), Level::Fatal);
+        assert_eq!(Level::Deadly.next(), Level::Fatal);
+        assert_eq!(Level::Fatal.next(), Level::Fatal);
+    }
+
+    #[test]
+    fn absorbed_() {
+        assert_eq!(absorbed(100, 0), 100);
+        assert_eq!(absorbed(100, 35), 65);
+        assert_eq!(absorbed(7, 50), 4);
+        assert_eq!(absorbed(100, 100), 0);
+        assert_eq!(absorbed(-50, 80), -50);
+    }
+}
@@ -0,0 +1,105 @@
+//! Reaction of the critters to the dude.
+//!
+//! The reaction is a value from 0 to 100 where 50 is neutral. It's moved by the dude's charisma,
+//! the Presence perk, karma and being under the influence of drugs. The reaction level decides the
+//! mood the talking heads greet the dude with. The scripts make the same checks themselves to pick
+//! the greeting lines and the dialog options.
+
+use crate::asset::{Perk, Stat};
+use crate::asset::proto::ProtoId;
+use crate::game::object::Objects;
+use crate::game::rpg::Rpg;
+use crate::game::script::GVAR_PLAYER_REPUTATION;
+
+pub const NEUTRAL: i32 = 50;
+/// Reaction points per charisma point above or below 5.
+const CHARISMA_MOD: i32 = 5;
+/// Reaction points per rank of the Presence perk.
+const PRESENCE_MOD: i32 = 10;
+/// Karma points per reaction point.
+const KARMA_PER_POINT: i32 = 50;
+/// Limit of the karma modifier. Karma Beacon doubles the modifier and the limit.
+const MAX_KARMA_MOD: i32 = 10;
+/// Reaction points for being under the influence of drugs.
+const DRUGS_MOD: i32 = -10;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Level {
+    Bad,
+    Neutral,
+    Good,
+}
+
+impl Level {
+    pub fn from_value(v: i32) -> Self {
+        if v <= 25 {
+            Level::Bad
+        } else if v <= 75 {
+            Level::Neutral
+        } else {
+            Level::Good
+        }
+    }
+
+    // HEAD_ANIMATION_*_FIDGET
+    /// Mood of the talking head as in `start_gdialog`.
+    pub fn mood(self) -> i32 {
+        match self {
+            Level::Good => 1,
+            Level::Neutral => 4,
+            Level::Bad => 7,
+        }
+    }
+}
+
+/// Karma part of the reaction. Also lowers or raises the barter prices.
+pub fn karma_modifier(karma: i32, karma_beacon: bool) -> i32 {
+    let r = (karma / KARMA_PER_POINT).clamp(-MAX_KARMA_MOD, MAX_KARMA_MOD);
+    if karma_beacon {
+        r * 2
+    } else {
+        r
+    }
+}
+
+/// Dude's karma as kept in the global vars.
+pub fn karma(global_vars: &[i32]) -> i32 {
+    global_vars.get(GVAR_PLAYER_REPUTATION).copied().unwrap_or(0)
+}
+
+/// Reaction of the critters to the dude.
+pub fn reaction(objs: &Objects, rpg: &Rpg, global_vars: &[i32], on_drugs: bool) -> i32 {
+    let charisma = rpg.stat(Stat::Charisma, &objs.get(objs.dude()), objs);
+    let presence = rpg.perk(Perk::Presence, ProtoId::DUDE) as i32;
+    let karma_beacon = rpg.has_perk(Perk::KarmaBeacon, ProtoId::DUDE);
+    let r = NEUTRAL
+        + (charisma - 5) * CHARISMA_MOD
+        + presence * PRESENCE_MOD
+        + karma_modifier(karma(global_vars), karma_beacon)
+        + if on_drugs { DRUGS_MOD } else { 0 };
+    r.clamp(0, 100)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn level() {
+        let l = |v| Level::from_value(v);
+        assert_eq!(l(0), Level::Bad);
+        assert_eq!(l(25), Level::Bad);
+        assert_eq!(l(NEUTRAL), Level::Neutral);
+        assert_eq!(l(75), Level::Neutral);
+        assert_eq!(l(76), Level::Good);
+    }
+
+    #[test]
+    fn karma_modifier_() {
+        assert_eq!(karma_modifier(0, false), 0);
+        assert_eq!(karma_modifier(120, false), 2);
+        assert_eq!(karma_modifier(-120, true), -4);
+        assert_eq!(karma_modifier(5000, false), MAX_KARMA_MOD);
+        assert_eq!(karma_modifier(5000, true), MAX_KARMA_MOD * 2);
+    }
+}
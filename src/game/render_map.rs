@@ -0,0 +1,64 @@
+//! Rendering of a whole map elevation into a PNG image. Used by the `render-map` subcommand.
+//! The image can be checked against a reference screenshot to catch rendering regressions.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::PathBuf;
+
+use crate::asset::map::ELEVATION_COUNT;
+use crate::game::state::GameState;
+use crate::graphics::color::BLACK;
+use crate::graphics::geometry::camera::Camera;
+use crate::graphics::map::map_screen_rect;
+use crate::graphics::png;
+use crate::graphics::render::Canvas;
+use crate::graphics::render::golden::{self, Tolerance};
+use crate::graphics::{Point, Rect};
+
+pub struct RenderMap {
+    /// Elevation to render. If `None` the elevation the map is entered at is used.
+    pub elevation: Option<u32>,
+    pub draw_roof: bool,
+    pub output: PathBuf,
+    /// PNG image the rendered image must match exactly.
+    pub reference: Option<PathBuf>,
+}
+
+impl RenderMap {
+    /// Renders the currently loaded map to the `canvas` which must be of `map_screen_rect()` size
+    /// and writes the result to the output file. The dude is removed from the map beforehand.
+    pub fn run(&self, state: &GameState, canvas: &mut dyn Canvas) -> io::Result<()> {
+        let mut world = state.world().borrow_mut();
+        let elevation = self.elevation.unwrap_or_else(|| world.elevation());
+        if elevation >= ELEVATION_COUNT || !world.has_elevation(elevation) {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                format!("map has no elevation {}", elevation)));
+        }
+
+        let dude = world.objects().dude();
+        world.objects_mut().set_pos(dude, None);
+
+        let rect = map_screen_rect(world.hex_grid());
+        let camera = Camera {
+            origin: Point::new(-rect.left, -rect.top),
+            viewport: Rect::with_size(0, 0, rect.width(), rect.height()),
+        };
+        canvas.clear(BLACK);
+        world.render_elevation(canvas, &camera, elevation, None, self.draw_roof);
+
+        let shot = canvas.screenshot()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "canvas has no back buffer"))?;
+        let mut wr = BufWriter::new(File::create(&self.output)?);
+        png::write_rgb(&mut wr, shot.width as u32, shot.height as u32, &shot.data)?;
+        wr.flush()?;
+
+        if let Some(path) = &self.reference {
+            let reference = png::read(&mut BufReader::new(File::open(path)?))?;
+            if let Some(diff) = golden::compare(&shot, &reference, Tolerance::EXACT) {
+                return Err(Error::new(ErrorKind::InvalidData,
+                    format!("image differs from reference {}: {}", path.display(), diff)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Random number service. All game randomness goes through here.
+//!
+//! Rolls are split into independent streams so that e.g. an extra roll made by a script doesn't
+//! change the outcome of the following combat. All streams are derived from a single seed which
+//! makes test runs and replays reproducible given the same seed and inputs.
+
+use enum_map::EnumMap;
+use enum_map_derive::Enum;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::cell::RefCell;
+
+use crate::util::EnumExt;
+
+#[derive(Clone, Copy, Debug, Enum, Eq, PartialEq)]
+pub enum Stream {
+    Combat,
+    Encounter,
+    Script,
+    /// Everything else: flavor messages, fidgets, death endings etc.
+    Misc,
+}
+
+struct Streams {
+    seed: u64,
+    rngs: EnumMap<Stream, StdRng>,
+}
+
+impl Streams {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rngs: EnumMap::from(|stream| StdRng::seed_from_u64(stream_seed(seed, stream))),
+        }
+    }
+}
+
+/// Derives seed of the `stream` from the main `seed`.
+fn stream_seed(seed: u64, stream: Stream) -> u64 {
+    // Golden ratio increment as in SplitMix64.
+    seed.wrapping_add((stream.ordinal() as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+thread_local! {
+    static STREAMS: RefCell<Streams> = RefCell::new(Streams::new(rand::random()));
+}
+
+/// Reseeds all streams of the current thread making the following rolls reproducible.
+pub fn set_seed(seed: u64) {
+    STREAMS.with(|s| *s.borrow_mut() = Streams::new(seed));
+}
+
+/// Returns the seed the streams were last seeded with. It's random unless set with `set_seed()`.
+pub fn seed() -> u64 {
+    STREAMS.with(|s| s.borrow().seed)
+}
+
+// roll_random()
+pub fn random(stream: Stream, from_inclusive: i32, to_inclusive: i32) -> i32 {
+    STREAMS.with(|s| s.borrow_mut().rngs[stream].gen_range(from_inclusive..=to_inclusive))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, enum_primitive_derive::Primitive)]
+pub enum RollCheckResult {
+    CriticalFailure = 0,
+    Failure = 1,
+    Success = 2,
+    CriticalSuccess = 3,
+}
+
+impl RollCheckResult {
+    pub fn is_success(self) -> bool {
+        match self {
+            Self::Success | Self::CriticalSuccess => true,
+            Self::Failure | Self::CriticalFailure => false,
+        }
+    }
+
+    pub fn is_critical(self) -> bool {
+        match self {
+            Self::CriticalSuccess | Self::CriticalFailure => true,
+            Self::Success | Self::Failure => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RollChecker {
+    disable_crits: bool,
+    stream: Stream,
+}
+
+impl RollChecker {
+    pub fn new(disable_crits: bool, stream: Stream) -> Self {
+        Self {
+            disable_crits,
+            stream,
+        }
+    }
+
+    pub fn roll_check(self, target: i32, crit: i32) -> (RollCheckResult, i32) {
+        let roll = target - random(self.stream, 1, 100);
+        let r = if roll < 0 {
+            if !self.disable_crits && random(self.stream, 1, 100) <= -roll / 10 {
+                RollCheckResult::CriticalFailure
+            } else {
+                RollCheckResult::Failure
+            }
+        } else if !self.disable_crits && random(self.stream, 1, 100) <= roll / 10 + crit {
+            RollCheckResult::CriticalSuccess
+        } else {
+            RollCheckResult::Success
+        };
+        (r, roll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_seed_() {
+        set_seed(42);
+        assert_eq!(seed(), 42);
+        let a: Vec<_> = (0..10).map(|_| random(Stream::Misc, 1, 100)).collect();
+        set_seed(42);
+        let b: Vec<_> = (0..10).map(|_| random(Stream::Misc, 1, 100)).collect();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|&v| (1..=100).contains(&v)));
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        set_seed(42);
+        let a: Vec<_> = (0..10).map(|_| random(Stream::Combat, 1, 1000)).collect();
+        set_seed(42);
+        for _ in 0..5 {
+            random(Stream::Script, 1, 1000);
+        }
+        let b: Vec<_> = (0..10).map(|_| random(Stream::Combat, 1, 1000)).collect();
+        assert_eq!(a, b);
+    }
+}
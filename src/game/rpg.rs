@@ -6,14 +6,15 @@ use num_traits::clamp;
 use std::cmp;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io;
+use std::rc::Rc;
 
 use crate::asset::{DamageKind, ExactEntityKind, Perk, PCStat, Skill, Stat, Trait};
 use crate::asset::message::{Messages, MessageId};
+use crate::asset::message::localization::Localization;
 use crate::asset::proto::ProtoId;
+use crate::error;
 use crate::game::object::{DamageFlag, EquipmentSlot, Hand, Object, Objects};
-use crate::fs::FileSystem;
-use crate::util::random::*;
+use crate::game::rng::*;
 
 use def::perk::*;
 use def::pc_stat::*;
@@ -47,9 +48,10 @@ impl Default for Tagged {
 }
 
 pub struct Rpg {
-    stat_msgs: Messages,
-    skill_msgs: Messages,
-    perk_msgs: Messages,
+    stat_msgs: Rc<Messages>,
+    skill_msgs: Rc<Messages>,
+    perk_msgs: Rc<Messages>,
+    misc_msgs: Rc<Messages>,
     stat_defs: EnumMap<Stat, StatDef>,
     skill_defs: EnumMap<Skill, SkillDef>,
     perk_defs: EnumMap<Perk, PerkDef>,
@@ -61,41 +63,67 @@ pub struct Rpg {
 }
 
 impl Rpg {
-    pub fn new(fs: &FileSystem, language: &str) -> io::Result<Self> {
-        let stat_msgs = Messages::read_file(fs, language, "game/stat.msg")?;
-        let stat_defs = StatDef::defaults();
-
-        let skill_msgs = Messages::read_file(fs, language, "game/skill.msg")?;
-        let skill_defs = SkillDef::defaults();
-
-        let perk_msgs = Messages::read_file(fs, language, "game/perk.msg")?;
-        let perk_defs = PerkDef::defaults();
-
+    pub fn new(loc: &Localization) -> error::Result<Self> {
+        Ok(Self::with_messages(
+            loc.messages("game/stat.msg")?,
+            loc.messages("game/skill.msg")?,
+            loc.messages("game/perk.msg")?,
+            loc.messages("game/misc.msg")?))
+    }
+
+    /// Rpg with the default definitions and without messages.
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        Self::with_messages(Default::default(), Default::default(), Default::default(),
+            Default::default())
+    }
+
+    fn with_messages(
+        stat_msgs: Rc<Messages>,
+        skill_msgs: Rc<Messages>,
+        perk_msgs: Rc<Messages>,
+        misc_msgs: Rc<Messages>,
+    ) -> Self {
         let mut perks = HashMap::new();
         perks.insert(ProtoId::DUDE, Default::default());
 
         let pc_stat_defs = PCStatDef::defaults();
         let pc_stats = EnumMap::from(|s| pc_stat_defs[s].default);
 
-        Ok(Self {
+        Self {
             stat_msgs,
             skill_msgs,
             perk_msgs,
-            stat_defs,
-            skill_defs,
-            perk_defs,
+            misc_msgs,
+            stat_defs: StatDef::defaults(),
+            skill_defs: SkillDef::defaults(),
+            perk_defs: PerkDef::defaults(),
             traits: Default::default(),
             perks,
             tagged: Default::default(),
             pc_stat_defs,
             pc_stats,
-        })
+        }
+    }
+
+    /// Loads the messages of the current language of `loc`. Used when the language is changed.
+    pub fn reload_messages(&mut self, loc: &Localization) -> error::Result<()> {
+        self.stat_msgs = loc.messages("game/stat.msg")?;
+        self.skill_msgs = loc.messages("game/skill.msg")?;
+        self.perk_msgs = loc.messages("game/perk.msg")?;
+        self.misc_msgs = loc.messages("game/misc.msg")?;
+        Ok(())
     }
 
     pub fn skill_msgs(&self) -> &Messages {
         &self.skill_msgs
     }
 
+    /// Messages of `misc.msg` used by the rules: radiation, poison etc.
+    pub fn misc_msgs(&self) -> &Messages {
+        &self.misc_msgs
+    }
+
     // skill_name
     pub fn skill_name(&self, skill: Skill) -> &bstr {
         &self.skill_msgs.get(SKILL_NAME_MSG_BASE + skill as MessageId).unwrap().text
@@ -301,11 +329,12 @@ impl Rpg {
     pub fn roll_check_stat(&self,
         stat: Stat,
         bonus: i32,
+        stream: Stream,
         obj: &Object,
         objs: &Objects,
     ) -> (RollCheckResult, i32) {
         let level = self.stat(stat, obj, objs) + bonus;
-        let rnd = random(1, 10);
+        let rnd = random(stream, 1, 10);
         let diff = level - rnd;
         let r = if rnd <= level {
             RollCheckResult::Success
@@ -376,8 +405,8 @@ impl Rpg {
     // adjust_ac
     pub fn apply_armor_change(&self,
         obj: &mut Object,
-        new_armor: Option<&Object>,
         old_armor: Option<&Object>,
+        new_armor: Option<&Object>,
         objs: &Objects,
     ) {
         let armor_stat = |obj: Option<&Object>, stat| obj.as_ref().map(|o|
@@ -584,6 +613,13 @@ impl Rpg {
         obj.proto().unwrap().sub.as_critter().unwrap().bonus_stats[stat]
     }
 
+    /// Adds `delta` to the bonus of the `stat`. The current stats (hit points, poison and
+    /// radiation) have no bonus and must be changed directly.
+    pub fn add_bonus_stat(&self, stat: Stat, obj: &mut Object, delta: i32, objs: &Objects) {
+        let v = self.bonus_stat(stat, obj);
+        self.set_bonus_stat(stat, obj, v + delta, objs);
+    }
+
     // stat_set_bonus
     fn set_bonus_stat(&self, stat: Stat, obj: &mut Object, v: i32, objs: &Objects) {
         match stat {
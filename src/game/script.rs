@@ -1,5 +1,5 @@
 use bstring::BString;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use enum_map::EnumMap;
 use enum_map_derive::Enum;
 use enum_primitive_derive::Primitive;
@@ -15,7 +15,10 @@ use crate::asset::map::MapId;
 use crate::asset::proto::ProtoDb;
 use crate::asset::script::ProgramId;
 use crate::asset::script::db::ScriptDb;
-use crate::game::object;
+use crate::game::GameTime;
+use crate::game::crash;
+use crate::game::object::{self, Objects};
+use crate::game::timer::TimerEvents;
 use crate::util::EnumExt;
 use crate::vm::{self, *};
 use crate::vm::value::Value;
@@ -106,6 +109,10 @@ impl ScriptIid {
     pub fn id(self) -> u32 {
         self.0.id()
     }
+
+    pub fn pack(self) -> u32 {
+        self.0.pack()
+    }
 }
 
 impl fmt::Debug for ScriptIid {
@@ -234,6 +241,104 @@ impl NewScripts {
     }
 }
 
+/// Scripts of a map that was left.
+#[derive(Default)]
+pub struct MapScripts {
+    scripts: Vec<(ScriptIid, ProgramId, Box<[i32]>)>,
+    map_sid: Option<ScriptIid>,
+    map_vars: Box<[i32]>,
+    timer_events: TimerEvents,
+}
+
+impl MapScripts {
+    pub fn write(&self, wr: &mut impl Write) -> io::Result<()> {
+        wr.write_u32::<BigEndian>(self.scripts.len() as u32)?;
+        for (sid, program_id, local_vars) in &self.scripts {
+            wr.write_u32::<BigEndian>(sid.pack())?;
+            wr.write_u32::<BigEndian>(program_id.val())?;
+            write_vars(wr, local_vars)?;
+        }
+        wr.write_i32::<BigEndian>(self.map_sid.map(|v| v.pack() as i32).unwrap_or(-1))?;
+        write_vars(wr, &self.map_vars)?;
+        wr.write_u32::<BigEndian>(self.timer_events.len() as u32)?;
+        for e in self.timer_events.iter() {
+            wr.write_u32::<BigEndian>(e.time.as_decis())?;
+            wr.write_u32::<BigEndian>(e.sid.pack())?;
+            wr.write_i32::<BigEndian>(e.info)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(rd: &mut impl Read) -> io::Result<Self> {
+        let count = rd.read_u32::<BigEndian>()?;
+        let mut scripts = Vec::new();
+        for _ in 0..count {
+            let sid = ScriptIid::read(rd)?;
+            let program_id = rd.read_u32::<BigEndian>()?;
+            let program_id = ProgramId::new(program_id)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                    format!("malformed program ID: {}", program_id)))?;
+            let local_vars = read_vars(rd)?;
+            scripts.push((sid, program_id, local_vars));
+        }
+        let map_sid = ScriptIid::read_opt(rd)?;
+        let map_vars = read_vars(rd)?;
+        let count = rd.read_u32::<BigEndian>()?;
+        let mut timer_events = TimerEvents::new();
+        for _ in 0..count {
+            let time = GameTime::from_decis(rd.read_u32::<BigEndian>()?);
+            let sid = ScriptIid::read(rd)?;
+            let info = rd.read_i32::<BigEndian>()?;
+            timer_events.add(time, sid, info);
+        }
+        Ok(Self {
+            scripts,
+            map_sid,
+            map_vars,
+            timer_events,
+        })
+    }
+}
+
+fn write_vars(wr: &mut impl Write, vars: &[i32]) -> io::Result<()> {
+    wr.write_u32::<BigEndian>(vars.len() as u32)?;
+    for &v in vars {
+        wr.write_i32::<BigEndian>(v)?;
+    }
+    Ok(())
+}
+
+fn read_vars(rd: &mut impl Read) -> io::Result<Box<[i32]>> {
+    let count = rd.read_u32::<BigEndian>()?;
+    let mut r = Vec::new();
+    for _ in 0..count {
+        r.push(rd.read_i32::<BigEndian>()?);
+    }
+    Ok(r.into())
+}
+
+/// Script suspended by the VM debugger with the context of its invocation.
+#[derive(Clone, Copy, Debug)]
+struct DebugSuspended {
+    sid: ScriptIid,
+    source_obj: Option<object::Handle>,
+    target_obj: Option<object::Handle>,
+    skill: Option<crate::asset::Skill>,
+    fixed_param: i32,
+}
+
+impl DebugSuspended {
+    fn new(sid: ScriptIid, fixed_param: i32, ctx: &Context) -> Self {
+        Self {
+            sid,
+            source_obj: ctx.source_obj,
+            target_obj: ctx.target_obj,
+            skill: ctx.skill,
+            fixed_param,
+        }
+    }
+}
+
 pub struct Scripts {
     proto_db: Rc<ProtoDb>,
     db: ScriptDb,
@@ -242,7 +347,11 @@ pub struct Scripts {
     scripts: HashMap<ScriptIid, Script>,
     map_sid: Option<ScriptIid>,
     pub vars: Vars,
+    pub timer_events: TimerEvents,
+    /// Fixed param of the procedure being executed.
+    fixed_param: i32,
     suspend_stack: Vec<ScriptIid>,
+    debug_suspended: Option<DebugSuspended>,
 }
 
 impl Scripts {
@@ -255,20 +364,102 @@ impl Scripts {
             scripts: HashMap::new(),
             map_sid: None,
             vars: Vars::new(),
+            timer_events: TimerEvents::new(),
+            fixed_param: 0,
             suspend_stack: Vec::new(),
+            debug_suspended: None,
         }
     }
 
+    /// Invalidates cached script assets affected by change of the file at `path`.
+    pub fn reload_asset(&mut self, path: &str) -> bool {
+        self.db.reload(path)
+    }
+
+    /// Switches the dialog messages to `language`.
+    pub fn set_language(&mut self, language: &str) {
+        self.db.set_language(language);
+    }
+
     pub fn map_sid(&self) -> Option<ScriptIid> {
         self.map_sid
     }
 
+    pub fn vm(&self) -> &Vm {
+        &self.vm
+    }
+
+    /// Returns the script instances with their program names ordered by SID.
+    pub fn instances(&self) -> Vec<(ScriptIid, &str)> {
+        let mut r: Vec<_> = self.scripts.iter()
+            .map(|(&sid, s)| (sid, self.programs[&s.program_id].name()))
+            .collect();
+        r.sort_by_key(|&(sid, _)| sid.pack());
+        r
+    }
+
+    /// Attaches the VM debugger to the script instance. Returns the program name or `None` if
+    /// there's no such instance.
+    pub fn attach_debugger(&mut self, sid: ScriptIid) -> Option<&str> {
+        let script = self.scripts.get(&sid)?;
+        self.vm.attach_debugger(script.program);
+        Some(self.programs[&script.program_id].name())
+    }
+
+    /// Detaches the VM debugger. The script suspended by the debugger runs to the end of the
+    /// invocation.
+    pub fn detach_debugger(&mut self, ctx: &mut Context) {
+        self.vm.detach_debugger();
+        self.resume_debugged(false, ctx);
+        self.vm.debugger().borrow_mut().reset();
+    }
+
+    /// Returns the script instance the VM debugger is attached to.
+    pub fn debugger_sid(&self) -> Option<ScriptIid> {
+        let program = self.vm.debugger_program()?;
+        self.scripts.iter()
+            .find(|(_, s)| s.program == program)
+            .map(|(&sid, _)| sid)
+    }
+
     pub fn reset(&mut self) {
         self.scripts.clear();
         self.map_sid = None;
         self.vars.map_vars = vec![].into();
         self.vars.external_vars.clear();
+        self.timer_events.clear();
         self.suspend_stack.clear();
+        // The script suspended by the debugger is gone along with the rest.
+        if self.debug_suspended.take().is_some() {
+            self.vm.detach_debugger();
+        }
+    }
+
+    /// Saves local vars of the current map scripts, the map vars and the pending timer events.
+    pub fn save_map(&self) -> MapScripts {
+        let mut timer_events = self.timer_events.clone();
+        timer_events.retain(|e| self.scripts.contains_key(&e.sid));
+        MapScripts {
+            scripts: self.scripts.iter()
+                .map(|(&sid, s)| (sid, s.program_id, s.local_vars.clone()))
+                .collect(),
+            map_sid: self.map_sid,
+            map_vars: self.vars.map_vars.clone(),
+            timer_events,
+        }
+    }
+
+    /// Replaces the current scripts with the saved ones. The scripts must be attached to their
+    /// objects by the caller.
+    pub fn restore_map(&mut self, saved: MapScripts) -> io::Result<()> {
+        self.reset();
+        for (sid, program_id, local_vars) in saved.scripts {
+            self.instantiate(sid, program_id, Some(local_vars))?;
+        }
+        self.map_sid = saved.map_sid;
+        self.vars.map_vars = saved.map_vars;
+        self.timer_events = saved.timer_events;
+        Ok(())
     }
 
     pub fn instantiate(&mut self,
@@ -327,6 +518,28 @@ impl Scripts {
         self.scripts.get(&sid)
     }
 
+    pub fn get_mut(&mut self, sid: ScriptIid) -> Option<&mut Script> {
+        self.scripts.get_mut(&sid)
+    }
+
+    /// Removes the script returning its program and local vars. Timer events of the script are
+    /// removed too.
+    pub fn remove(&mut self, sid: ScriptIid) -> Option<(ProgramId, Box<[i32]>)> {
+        self.timer_events.remove(sid);
+        self.scripts.remove(&sid).map(|s| (s.program_id, s.local_vars))
+    }
+
+    /// Instantiates script under an unused SID of the `kind`.
+    pub fn instantiate_new(&mut self,
+        kind: ScriptKind,
+        program_id: ProgramId,
+        local_vars: Option<Box<[i32]>>,
+    ) -> io::Result<ScriptIid> {
+        let sid = NewScripts::new(self).unused_sid(kind);
+        self.instantiate(sid, program_id, local_vars)?;
+        Ok(sid)
+    }
+
     pub fn attach_to_object(&mut self, sid: ScriptIid, obj: object::Handle) {
         self.scripts.get_mut(&sid).unwrap().object = Some(obj);
     }
@@ -334,43 +547,91 @@ impl Scripts {
     pub fn execute_proc(&mut self, sid: ScriptIid, proc_id: ProcedureId,
         ctx: &mut Context) -> InvocationResult
     {
+        if self.is_debug_suspended(sid) {
+            debug!("[{:?}] not executing proc {:?}: suspended by the debugger", sid, proc_id);
+            return InvocationResult::default();
+        }
+        let fixed_param = self.fixed_param;
         let (r, new_scripts) = {
             let new_scripts = NewScripts::new(self);
             let script = self.scripts.get_mut(&sid).unwrap();
             let mut vm_ctx = Self::make_vm_ctx(
                 &mut script.local_vars,
                 &mut self.vars,
+                &mut self.timer_events,
+                self.fixed_param,
                 &mut self.db,
                 new_scripts,
                 &self.proto_db,
                 script.object,
                 ctx);
-            if !script.inited {
+            let init = if !script.inited {
                 debug!("[{:?}#{}:{}] running program initialization code",
                     sid,
                     script.program_id.val(),
                     self.vm.program_state(script.program).program().name());
-                self.vm.run(script.program, &mut vm_ctx).unwrap()
-                    .assert_no_suspend();
                 script.inited = true;
-            }
-            let prg = self.vm.program_state_mut(script.program);
-            debug!("[{:?}#{}:{}] executing proc {:?} ({:?})",
-                sid,
-                script.program_id.val(),
-                prg.program().name(),
-                proc_id,
-                prg.program().proc(proc_id).map(|p| p.name()));
-            let r = prg.execute_proc(proc_id, &mut vm_ctx).unwrap();
-            if r.suspend.is_some() {
-                self.suspend_stack.push(sid);
-            }
+                match self.vm.run(script.program, &mut vm_ctx) {
+                    Ok(r) => Some(*r.assert_no_suspend()),
+                    Err(e) => {
+                        error!("[{:?}] program initialization failed: {:?}", sid, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            let r = match init {
+                // The procedure isn't executed if the initialization is suspended by the
+                // debugger.
+                Some(r) if r.suspend.is_some() => r,
+                _ => {
+                    let prg = self.vm.program_state_mut(script.program);
+                    debug!("[{:?}#{}:{}] executing proc {:?} ({:?})",
+                        sid,
+                        script.program_id.val(),
+                        prg.program().name(),
+                        proc_id,
+                        prg.program().proc(proc_id).map(|p| p.name()));
+                    let _crash_script = crash::enter_script(crash::Script {
+                        sid,
+                        program_id: script.program_id,
+                        proc_id,
+                    });
+                    // Errors are logged by VM with details. Don't let a buggy script take down
+                    // the game.
+                    prg.execute_proc(proc_id, &mut vm_ctx)
+                        .unwrap_or_else(|e| {
+                            error!("[{:?}] procedure {:?} failed: {:?}", sid, proc_id, e);
+                            InvocationResult::default()
+                        })
+                }
+            };
             (r, vm_ctx.new_scripts)
         };
+        match r.suspend {
+            Some(Suspend::GsayEnd) => self.suspend_stack.push(sid),
+            Some(Suspend::Breakpoint) =>
+                self.debug_suspended = Some(DebugSuspended::new(sid, fixed_param, ctx)),
+            None => {}
+        }
         new_scripts.instantiate(self);
+        self.remove_orphans(ctx.world.objects());
         r
     }
 
+    /// Removes scripts attached to objects that were destroyed along with their timer events.
+    /// Suspended scripts are kept until resumed.
+    pub fn remove_orphans(&mut self, objects: &Objects) {
+        let suspend_stack = &self.suspend_stack;
+        let debug_suspended = self.debug_suspended.map(|s| s.sid);
+        self.scripts.retain(|sid, s| s.object.map(|o| objects.contains(o)).unwrap_or(true)
+            || suspend_stack.contains(sid)
+            || debug_suspended == Some(*sid));
+        let scripts = &self.scripts;
+        self.timer_events.retain(|e| scripts.contains_key(&e.sid));
+    }
+
     #[must_use]
     pub fn execute_proc_name(&mut self, sid: ScriptIid, proc: &Rc<BString>,
         ctx: &mut Context)-> Option<InvocationResult>
@@ -400,15 +661,30 @@ impl Scripts {
         Some(self.execute_proc(sid, proc_id, ctx))
     }
 
+    /// Runs `timed_event_p_proc` of the script with the event `info` as the fixed param.
+    pub fn execute_timed_event(&mut self, sid: ScriptIid, info: i32, ctx: &mut Context) {
+        if !self.scripts.contains_key(&sid) {
+            debug!("[{:?}] script of timed event is gone", sid);
+            return;
+        }
+        self.fixed_param = info;
+        let r = self.execute_predefined_proc(sid, PredefinedProc::TimedEvent, ctx);
+        self.fixed_param = 0;
+        if let Some(r) = r {
+            r.assert_no_suspend();
+        }
+    }
+
     pub fn execute_procs(&mut self, proc: PredefinedProc, ctx: &mut Context,
         filter: impl Fn(ScriptIid) -> bool)
     {
         // TODO avoid allocation
         let sids: Vec<_> = self.scripts.keys().cloned().collect();
         for sid in sids {
-            if filter(sid) {
+            // The script could be removed by the previous procedures.
+            if filter(sid) && self.scripts.contains_key(&sid) {
                 if let Some(r) = self.execute_predefined_proc(sid, proc, ctx) {
-                    assert!(r.suspend.is_none(), "can't suspend in {:?}", proc);
+                    r.assert_no_suspend();
                 }
             }
         }
@@ -423,8 +699,9 @@ impl Scripts {
         // MapEnter is ignored since it's executed separately immediately after map loaded.
         if proc != PredefinedProc::MapEnter {
             if let Some(sid) = self.map_sid {
-                self.execute_predefined_proc(sid, proc, ctx)
-                    .map(|r| r.suspend.map(|_| panic!("can't suspend in {:?}", proc)));
+                if let Some(r) = self.execute_predefined_proc(sid, proc, ctx) {
+                    r.assert_no_suspend();
+                }
             }
         }
 
@@ -438,22 +715,77 @@ impl Scripts {
     }
 
     pub fn resume(&mut self, ctx: &mut Context) -> InvocationResult {
+        let sid = self.suspend_stack.pop().unwrap();
+        let r = self.resume_script(sid, ctx);
+        if r.suspend == Some(Suspend::Breakpoint) {
+            self.debug_suspended = Some(DebugSuspended::new(sid, self.fixed_param, ctx));
+        }
+        r
+    }
+
+    /// Whether the script `sid` is suspended by the VM debugger.
+    pub fn is_debug_suspended(&self, sid: ScriptIid) -> bool {
+        self.debug_suspended.map(|s| s.sid) == Some(sid)
+    }
+
+    /// Whether any script is suspended by the VM debugger.
+    pub fn has_debug_suspended(&self) -> bool {
+        self.debug_suspended.is_some()
+    }
+
+    /// Returns the state of the script suspended by the VM debugger.
+    pub fn debug_snapshot(&self) -> Option<vm::debug::Snapshot> {
+        let script = self.scripts.get(&self.debug_suspended?.sid)?;
+        self.vm.program_state(script.program).debug_snapshot(&script.local_vars)
+    }
+
+    /// Resumes the script suspended by the VM debugger in the context of the suspended
+    /// invocation. If `step` is `true` the script is suspended again before the next
+    /// instruction. The result of the invocation is discarded since its caller has already
+    /// finished.
+    pub fn resume_debugged(&mut self, step: bool, ctx: &mut Context) {
+        let suspended = if let Some(v) = self.debug_suspended.take() {
+            v
+        } else {
+            return;
+        };
+        self.vm.debugger().borrow_mut().resume(step);
+        ctx.source_obj = suspended.source_obj;
+        ctx.target_obj = suspended.target_obj;
+        ctx.skill = suspended.skill;
+        self.fixed_param = suspended.fixed_param;
+        let r = self.resume_script(suspended.sid, ctx);
+        match r.suspend {
+            Some(Suspend::GsayEnd) => self.suspend_stack.push(suspended.sid),
+            Some(Suspend::Breakpoint) => self.debug_suspended = Some(suspended),
+            None => {}
+        }
+        self.fixed_param = 0;
+    }
+
+    fn resume_script(&mut self, sid: ScriptIid, ctx: &mut Context) -> InvocationResult {
         let (r, new_scripts) = {
-            let sid = self.suspend_stack.pop().unwrap();
             let new_scripts = NewScripts::new(self);
             let script = self.scripts.get_mut(&sid).unwrap();
             let mut vm_ctx = Self::make_vm_ctx(
                 &mut script.local_vars,
                 &mut self.vars,
+                &mut self.timer_events,
+                self.fixed_param,
                 &mut self.db,
                 new_scripts,
                 &self.proto_db,
                 script.object,
                 ctx);
-            let r = self.vm.program_state_mut(script.program).resume(&mut vm_ctx).unwrap();
+            let r = self.vm.program_state_mut(script.program).resume(&mut vm_ctx)
+                .unwrap_or_else(|e| {
+                    error!("[{:?}] resuming failed: {:?}", sid, e);
+                    InvocationResult::default()
+                });
             (r, vm_ctx.new_scripts)
         };
         new_scripts.instantiate(self);
+        self.remove_orphans(ctx.world.objects());
         r
     }
 
@@ -461,6 +793,8 @@ impl Scripts {
     fn make_vm_ctx<'a>(
         local_vars: &'a mut [i32],
         vars: &'a mut Vars,
+        timer_events: &'a mut TimerEvents,
+        fixed_param: i32,
         script_db: &'a mut ScriptDb,
         new_scripts: NewScripts,
         proto_db: &'a ProtoDb,
@@ -472,6 +806,8 @@ impl Scripts {
             map_vars: &mut vars.map_vars,
             global_vars: &mut vars.global_vars,
             external_vars: &mut vars.external_vars,
+            timer_events,
+            fixed_param,
 
             self_obj,
             source_obj: ctx.source_obj,
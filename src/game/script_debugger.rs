@@ -0,0 +1,130 @@
+//! Script debugger window: shows the state of the script suspended by the VM debugger and lets
+//! it run a single instruction at a time or until the next breakpoint. The breakpoints are set
+//! with the `debug` console command.
+
+use crate::game::ui::script_debugger::ScriptDebuggerView;
+use crate::graphics::Rect;
+use crate::ui::*;
+use crate::vm::debug::Snapshot;
+use crate::vm::Value;
+
+fn values(values: &[Value]) -> String {
+    if values.is_empty() {
+        return "-".into();
+    }
+    values.iter()
+        .map(|v| format!("{:?}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn indexed<T: std::fmt::Debug>(values: &[T]) -> String {
+    if values.is_empty() {
+        return "-".into();
+    }
+    values.iter()
+        .enumerate()
+        .map(|(i, v)| format!("{}={:?}", i, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Describes the state of the suspended program.
+pub fn describe(s: &Snapshot) -> Vec<String> {
+    vec![
+        format!("{}: {}", s.program, s.pause),
+        s.instr.clone(),
+        format!("call stack: {}", s.call_stack),
+        format!("data stack: {}", values(&s.data_stack)),
+        format!("return stack: {}", values(&s.return_stack)),
+        format!("procedure vars: {}", indexed(&s.proc_vars)),
+        format!("program vars: {}", indexed(&s.program_vars)),
+        format!("LVARs: {}", indexed(&s.local_vars)),
+    ]
+}
+
+pub struct ScriptDebugger {
+    window: Option<Handle>,
+    view: Option<Handle>,
+}
+
+impl ScriptDebugger {
+    pub fn new() -> Self {
+        Self {
+            window: None,
+            view: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Shows the window with the `status` lines on top of the `snapshot` of the suspended
+    /// program. Refreshes the window if it's already shown.
+    pub fn show(&mut self, status: Vec<String>, snapshot: Option<Snapshot>, ui: &mut Ui) {
+        if self.window.is_none() {
+            let rect = Rect::with_size(0, 0, 640, 380);
+            let window = ui.new_window(rect, None);
+            ui.widget_base_mut(window).set_modal(true);
+
+            let view = ScriptDebuggerView::new(ui.fonts().clone());
+            let view = ui.new_widget(window, rect, None, None, view);
+            ui.set_keyboard_focus(Some(view));
+
+            self.window = Some(window);
+            self.view = Some(view);
+        }
+
+        let mut lines = status;
+        lines.push(String::new());
+        if let Some(snapshot) = &snapshot {
+            lines.extend(describe(snapshot));
+            lines.push(String::new());
+            lines.push("Right/F10: step, C/F5: continue, Escape: continue and close".into());
+        } else {
+            lines.push("No script is suspended.".into());
+            lines.push(String::new());
+            lines.push("Escape: close".into());
+        }
+        ui.widget_mut::<ScriptDebuggerView>(self.view.unwrap()).set_lines(lines);
+    }
+
+    pub fn hide(&mut self, ui: &mut Ui) {
+        if let Some(window) = self.window.take() {
+            ui.remove(window);
+            self.view = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::debug::{Breakpoint, Opcode, Pause};
+
+    #[test]
+    fn describe_() {
+        let snapshot = Snapshot {
+            program: "test".into(),
+            pause: Pause::Breakpoint(Breakpoint::Opcode(Opcode::Add)),
+            instr: "0x000010  Add".into(),
+            call_stack: "start".into(),
+            data_stack: vec![Value::Int(1), Value::Int(2)],
+            return_stack: vec![],
+            proc_vars: vec![Value::Int(3)],
+            program_vars: vec![],
+            local_vars: vec![4, 5],
+        };
+        assert_eq!(describe(&snapshot), vec![
+            "test: opcode Add hit",
+            "0x000010  Add",
+            "call stack: start",
+            "data stack: Int(1) Int(2)",
+            "return stack: -",
+            "procedure vars: 0=Int(3)",
+            "program vars: -",
+            "LVARs: 0=4 1=5",
+        ]);
+    }
+}
@@ -1,5 +1,8 @@
+pub mod camera;
 pub mod frame_anim;
+pub mod light;
 pub mod move_seq;
+pub mod rotate;
 pub mod stand;
 
 use slotmap::SecondaryMap;
@@ -51,6 +54,17 @@ impl ObjSequencer {
         self.seqs.contains_key(object)
     }
 
+    /// Returns objects that have running sequences.
+    pub fn objects(&self) -> impl Iterator<Item=Handle> + '_ {
+        self.seqs.keys()
+    }
+
+    /// Starts a sequence that isn't bound to any object. Such sequences can't be cancelled
+    /// individually but are stopped by `clear()`.
+    pub fn start(&mut self, seq: impl 'static + Sequence) {
+        self.seqr.start(seq);
+    }
+
     /// Cancels all sequences running for `object`.
     pub fn cancel(&mut self, object: Handle) {
         if let Some(mut seq) = self.seqs.remove(object) {
@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use crate::graphics::Point;
+use crate::sequence::*;
+
+/// Interval between the camera shake offset changes.
+const SHAKE_STEP: Duration = Duration::from_millis(40);
+
+/// Scrolls the camera so the hex at `pos` ends up in the center of the viewport. The duration is
+/// counted from the first update, zero duration centers the camera at once.
+// tile_scroll_to
+pub struct PanTo {
+    pos: Point,
+    duration: Duration,
+    /// Camera origin at the start and at the end of the pan.
+    origins: Option<(Point, Point)>,
+    start: Option<Instant>,
+}
+
+impl PanTo {
+    pub fn new(pos: Point, duration: Duration) -> Self {
+        Self {
+            pos,
+            duration,
+            origins: None,
+            start: None,
+        }
+    }
+}
+
+impl Sequence for PanTo {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        let start = *self.start.get_or_insert(ctx.time);
+        let pos = self.pos;
+        let (from, to) = *self.origins.get_or_insert_with(|| {
+            let mut camera = ctx.world.camera().clone();
+            let from = camera.origin;
+            camera.look_at(pos);
+            (from, camera.origin)
+        });
+        let elapsed = ctx.time.duration_since(start);
+        let camera = ctx.world.camera_mut();
+        if elapsed >= self.duration {
+            camera.origin = to;
+            return Result::Done;
+        }
+        let elapsed = elapsed.as_millis() as i32;
+        let duration = self.duration.as_millis() as i32;
+        camera.origin = from + (to - from) * elapsed / duration;
+        Result::Running(Running::NotLagging)
+    }
+}
+
+/// Shakes the camera with `amplitude` in pixels fading out over the `duration`. The camera
+/// origin is restored when done.
+pub struct Shake {
+    amplitude: i32,
+    duration: Duration,
+    origin: Option<Point>,
+    start: Option<Instant>,
+}
+
+impl Shake {
+    pub fn new(amplitude: i32, duration: Duration) -> Self {
+        Self {
+            amplitude,
+            duration,
+            origin: None,
+            start: None,
+        }
+    }
+
+    fn offset(&self, elapsed: Duration) -> Point {
+        const OFFSETS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+        if elapsed >= self.duration {
+            return Point::new(0, 0);
+        }
+        let elapsed_ms = elapsed.as_millis() as i32;
+        let duration_ms = self.duration.as_millis() as i32;
+        let amplitude = self.amplitude * (duration_ms - elapsed_ms) / duration_ms;
+        let (x, y) = OFFSETS[(elapsed.as_millis() / SHAKE_STEP.as_millis()) as usize % 4];
+        Point::new(x, y) * amplitude
+    }
+}
+
+impl Sequence for Shake {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        let start = *self.start.get_or_insert(ctx.time);
+        let origin = *self.origin.get_or_insert(ctx.world.camera().origin);
+        let elapsed = ctx.time.duration_since(start);
+        ctx.world.camera_mut().origin = origin + self.offset(elapsed);
+        if elapsed >= self.duration {
+            Result::Done
+        } else {
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn pan_to() {
+        let mut ctx = MockContext::new();
+        let from = ctx.world().camera().origin;
+        let mut camera = ctx.world().camera().clone();
+        camera.look_at(Point::new(50, 60));
+        let to = camera.origin;
+
+        let mut seq = PanTo::new(Point::new(50, 60), Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.world().camera().origin, from);
+        assert_eq!(ctx.update(&mut seq, 50), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.world().camera().origin, from + (to - from) / 2);
+        assert_eq!(ctx.update(&mut seq, 100), Result::Done);
+        assert_eq!(ctx.world().camera().origin, to);
+
+        let mut seq = PanTo::new(Point::new(10, 10), Duration::from_millis(0));
+        assert_eq!(ctx.update(&mut seq, 100), Result::Done);
+        camera.look_at(Point::new(10, 10));
+        assert_eq!(ctx.world().camera().origin, camera.origin);
+    }
+
+    #[test]
+    fn shake() {
+        let mut ctx = MockContext::new();
+        let origin = ctx.world().camera().origin;
+
+        let mut seq = Shake::new(10, Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.world().camera().origin, origin + Point::new(10, 0));
+        assert_eq!(ctx.update(&mut seq, 50), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.world().camera().origin, origin + Point::new(-5, 0));
+        assert_eq!(ctx.update(&mut seq, 100), Result::Done);
+        assert_eq!(ctx.world().camera().origin, origin);
+    }
+}
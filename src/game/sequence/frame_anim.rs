@@ -1,8 +1,10 @@
 use enum_map_derive::Enum;
 use if_chain::if_chain;
+use log::*;
+use std::cmp;
 use std::time::{Duration, Instant};
 
-use crate::asset::CritterAnim;
+use crate::asset::{CritterAnim, WeaponKind};
 use crate::game::object::{Handle, SetFrame};
 use crate::game::world::World;
 use crate::sequence::*;
@@ -23,6 +25,10 @@ pub enum AnimDirection {
 #[derive(Clone, Debug)]
 pub struct FrameAnimOptions {
     pub anim: Option<CritterAnim>,
+
+    /// Switches critter to the animation set of this weapon.
+    pub weapon: Option<WeaponKind>,
+
     pub direction: AnimDirection,
 
     /// If `true` makes the animation loop forever.
@@ -36,6 +42,7 @@ impl Default for FrameAnimOptions {
     fn default() -> Self {
         Self {
             anim: None,
+            weapon: None,
             direction: AnimDirection::Forward,
             wrap: false,
             skip: 0,
@@ -60,20 +67,38 @@ impl FrameAnim {
         }
     }
 
-    fn init(&mut self, world: &mut World) {
+    /// Switches critter to the requested animation and weapon or their fallback. Returns
+    /// the number of frames in the object's direction, which is `0` if the frames can't be loaded
+    /// and there's nothing to animate.
+    fn init(&mut self, world: &mut World) -> usize {
         let mut obj = world.objects().get_mut(self.obj);
 
-        obj.fid = if_chain! {
-            if let Some(anim) = self.options.anim;
+        if_chain! {
+            if self.options.anim.is_some() || self.options.weapon.is_some();
             if let Some(fid) = obj.fid.critter();
             then {
-                fid.with_anim(anim).into()
-            } else {
-                obj.fid
+                let anim = self.options.anim.unwrap_or_else(|| fid.anim());
+                let weapon = self.options.weapon.unwrap_or_else(|| fid.weapon());
+                let fid = fid.with_anim(anim).with_weapon(weapon);
+                if let Some(fid) = world.frm_db().critter_anim_or_fallback(fid) {
+                    obj.fid = fid.into();
+                } else {
+                    warn!("{:?} has no {:?}/{:?} animation or its fallbacks", obj.fid, weapon,
+                        anim);
+                }
             }
-        };
+        }
 
-        self.frame_len = Duration::from_millis(1000 / world.frm_db().get(obj.fid).unwrap().fps as u64);
+        match world.frm_db().get(obj.fid) {
+            Ok(frame_set) => {
+                self.frame_len = frame_len(frame_set.fps);
+                frame_set.frame_lists[obj.direction].frames.len()
+            }
+            Err(e) => {
+                warn!("can't animate {:?} with {:?}: {}", self.obj, obj.fid, e);
+                0
+            }
+        }
     }
 }
 
@@ -81,15 +106,16 @@ impl Sequence for FrameAnim {
     fn update(&mut self, ctx: &mut Update) -> Result {
         let set_frame = match self.state {
             State::Started => {
-                self.init(ctx.world);
+                let frame_count = self.init(ctx.world);
+                if frame_count == 0 {
+                    self.state = State::Done;
+                    return Result::Done;
+                }
+                // The fallback animation can be shorter than the skip.
+                let skip = cmp::min(self.options.skip as usize, frame_count - 1);
                 SetFrame::Index(match self.options.direction {
-                    AnimDirection::Forward => self.options.skip as usize,
-                    AnimDirection::Backward => {
-                        let obj = ctx.world.objects().get(self.obj);
-                        let frame_set = ctx.world.frm_db().get(obj.fid).unwrap();
-                        let frames = &frame_set.frame_lists[obj.direction].frames;
-                        frames.len().checked_sub(1 + self.options.skip as usize).unwrap()
-                    }
+                    AnimDirection::Forward => skip,
+                    AnimDirection::Backward => frame_count - 1 - skip,
                 })
             },
             State::Running(last_time) => {
@@ -119,7 +145,7 @@ impl Sequence for FrameAnim {
                             if obj.frame_idx > 0 {
                                 obj.frame_idx -= 1;
                                 false
-                            } else if self.options.wrap {
+                            } else if self.options.wrap && !frames.is_empty() {
                                 obj.frame_idx = frames.len() - 1;
                                 false
                             } else {
@@ -158,4 +184,142 @@ impl Sequence for FrameAnim {
             Running::Lagging
         })
     }
-}
\ No newline at end of file
+}
+
+fn frame_len(fps: u16) -> Duration {
+    Duration::from_millis(1000 / cmp::max(fps, 1) as u64)
+}
+
+/// Moves the object from the first frame `frames` frames forward and back to the first frame
+/// at the frame rate of the FRM file. Used for locked doors.
+pub struct Rattle {
+    obj: Handle,
+    frames: u32,
+    frame_len: Duration,
+    /// Number of frames shown so far and the time the last one was shown.
+    step: Option<(u32, Instant)>,
+}
+
+impl Rattle {
+    pub fn new(obj: Handle, frames: u32) -> Self {
+        Self {
+            obj,
+            frames,
+            frame_len: frame_len(10),
+            step: None,
+        }
+    }
+
+    /// Frame index shown at `step` of the rattle.
+    fn frame_at(frames: u32, step: u32) -> u32 {
+        if step <= frames {
+            step
+        } else {
+            (2 * frames).saturating_sub(step)
+        }
+    }
+}
+
+impl Sequence for Rattle {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        let (step, time) = match self.step {
+            None => {
+                let frame_count = {
+                    let obj = ctx.world.objects().get(self.obj);
+                    match ctx.world.frm_db().get(obj.fid) {
+                        Ok(frame_set) => {
+                            self.frame_len = frame_len(frame_set.fps);
+                            frame_set.frame_lists[obj.direction].frames.len() as u32
+                        }
+                        Err(e) => {
+                            warn!("can't rattle {:?} with {:?}: {}", self.obj, obj.fid, e);
+                            0
+                        }
+                    }
+                };
+                self.frames = cmp::min(self.frames, frame_count.saturating_sub(1));
+                if self.frames == 0 {
+                    return Result::Done;
+                }
+                (0, ctx.time)
+            }
+            Some((step, last_time)) => {
+                if step >= 2 * self.frames {
+                    return Result::Done;
+                }
+                if ctx.time - last_time < self.frame_len {
+                    return Result::Running(Running::NotLagging);
+                }
+                (step + 1, last_time + self.frame_len)
+            }
+        };
+        let frame = Self::frame_at(self.frames, step);
+        ctx.world.objects_mut().set_frame(self.obj, SetFrame::Index(frame as usize));
+        self.step = Some((step, time));
+
+        Result::Running(if ctx.time - time < self.frame_len {
+            Running::NotLagging
+        } else {
+            Running::Lagging
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::EntityKind;
+    use crate::asset::frame::FrameId;
+    use crate::sequence::test::*;
+
+    fn frame_anim(ctx: &mut MockContext, frames: usize, options: FrameAnimOptions)
+        -> (Handle, FrameAnim)
+    {
+        let obj = ctx.world_mut().mock_object(EntityKind::Critter, None);
+        let fid = ctx.world().objects().get(obj).fid;
+        ctx.world().frm_db().mock_frames(fid, frames, 1, 1, 1);
+        (obj, FrameAnim::new(obj, options))
+    }
+
+    fn frame_idx(ctx: &MockContext, obj: Handle) -> usize {
+        ctx.world().objects().get(obj).frame_idx
+    }
+
+    #[test]
+    fn skip_longer_than_anim() {
+        let mut ctx = MockContext::new();
+        let (obj, mut seq) = frame_anim(&mut ctx, 3, FrameAnimOptions {
+            skip: 5,
+            ..Default::default()
+        });
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(frame_idx(&ctx, obj), 2);
+        assert_eq!(ctx.update(&mut seq, 100), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.update(&mut seq, 200), Result::Done);
+
+        let (obj, mut seq) = frame_anim(&mut ctx, 3, FrameAnimOptions {
+            direction: AnimDirection::Backward,
+            skip: 5,
+            ..Default::default()
+        });
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(frame_idx(&ctx, obj), 0);
+    }
+
+    #[test]
+    fn no_frames() {
+        let mut ctx = MockContext::new();
+        let (_, mut seq) = frame_anim(&mut ctx, 0, FrameAnimOptions {
+            direction: AnimDirection::Backward,
+            ..Default::default()
+        });
+        assert_eq!(ctx.update(&mut seq, 0), Result::Done);
+
+        let obj = ctx.world_mut().mock_object(EntityKind::Critter, None);
+        ctx.world().objects().get_mut(obj).fid =
+            FrameId::new_generic(EntityKind::Wall, 1).unwrap();
+        let mut seq = FrameAnim::new(obj, Default::default());
+        assert_eq!(ctx.update(&mut seq, 0), Result::Done);
+        assert_eq!(ctx.update(&mut seq, 100), Result::Done);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::game::object::{Handle, LightEmitter};
+use crate::sequence::*;
+
+/// Changes light emitted by the object.
+pub struct SetLight {
+    obj: Handle,
+    light: LightEmitter,
+    done: bool,
+}
+
+impl SetLight {
+    pub fn new(obj: Handle, light: LightEmitter) -> Self {
+        Self {
+            obj,
+            light,
+            done: false,
+        }
+    }
+}
+
+impl Sequence for SetLight {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        if self.done {
+            Result::Done
+        } else {
+            ctx.world.objects_mut().set_light_emitter(self.obj, self.light);
+            self.done = true;
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
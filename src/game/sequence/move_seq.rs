@@ -1,3 +1,6 @@
+use log::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use crate::asset::CritterAnim;
@@ -14,6 +17,50 @@ enum State {
     Done,
 }
 
+struct RedirectInner {
+    running: bool,
+    to: Option<(PathTo, CritterAnim)>,
+}
+
+/// Changes destination of a running `Move` sequence. The object finishes its current step and
+/// continues to the new destination from the next hex.
+#[derive(Clone)]
+pub struct Redirect(Rc<RefCell<RedirectInner>>);
+
+impl Redirect {
+    fn new() -> Self {
+        Redirect(Rc::new(RefCell::new(RedirectInner {
+            running: true,
+            to: None,
+        })))
+    }
+
+    /// Returns `false` if the `Move` sequence is no longer running (finished or cancelled).
+    pub fn redirect(&self, to: PathTo, anim: CritterAnim) -> bool {
+        let mut inner = self.0.borrow_mut();
+        if inner.running {
+            inner.to = Some((to, anim));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.0.borrow().running
+    }
+
+    fn take(&self) -> Option<(PathTo, CritterAnim)> {
+        self.0.borrow_mut().to.take()
+    }
+
+    fn set_done(&self) {
+        let mut inner = self.0.borrow_mut();
+        inner.running = false;
+        inner.to = None;
+    }
+}
+
 pub struct Move {
     obj: Handle,
     to: PathTo,
@@ -23,6 +70,7 @@ pub struct Move {
     path: Vec<Direction>,
     state: State,
     path_pos: usize,
+    redirect: Redirect,
 }
 
 impl Move {
@@ -36,6 +84,24 @@ impl Move {
             path: Vec::new(),
             state: State::Started,
             path_pos: 0,
+            redirect: Redirect::new(),
+        }
+    }
+
+    /// Returns handle for changing destination of this sequence while it's running.
+    pub fn redirect(&self) -> Redirect {
+        self.redirect.clone()
+    }
+
+    /// Applies pending redirect if any. Returns `true` if the destination has changed.
+    fn apply_redirect(&mut self, world: &World) -> bool {
+        if let Some((to, anim)) = self.redirect.take() {
+            self.to = to;
+            self.anim = anim;
+            self.to_point = Some(self.to_point(world));
+            true
+        } else {
+            false
         }
     }
 
@@ -46,11 +112,12 @@ impl Move {
         if !self.path.is_empty() {
             obj.direction = self.path[self.path_pos];
         }
-        obj.fid = obj.fid
-            .critter()
-            .unwrap()
-            .with_anim(self.anim)
-            .into();
+        let fid = obj.fid.critter().unwrap().with_anim(self.anim);
+        if let Some(fid) = world.frm_db().critter_anim_or_fallback(fid) {
+            obj.fid = fid.into();
+        } else {
+            warn!("{:?} has no {:?} animation or its fallbacks", obj.fid, self.anim);
+        }
 
         if self.state == State::Started {
             obj.frame_idx = 0;
@@ -79,6 +146,19 @@ impl Move {
             obj.direction = hex::direction(pos, self.to_point.unwrap());
         }
         self.state = State::Done;
+        self.redirect.set_done();
+    }
+}
+
+/// Whether a foot touches the ground at the frame. Walk and run cycles consist of two steps
+/// starting at the first and the middle frames.
+fn is_footfall(frame_idx: usize, frame_count: usize) -> bool {
+    frame_idx == 0 || frame_idx == frame_count / 2
+}
+
+impl Drop for Move {
+    fn drop(&mut self) {
+        self.redirect.set_done();
     }
 }
 
@@ -87,6 +167,7 @@ impl Sequence for Move {
     fn update(&mut self, ctx: &mut Update) -> Result {
         match self.state {
             State::Started => {
+                self.apply_redirect(ctx.world);
                 self.rebuild_path(ctx.world);
                 // TODO Do we need to rebuild path if the object moves?
                 self.to_point = Some(self.to_point(ctx.world));
@@ -121,6 +202,13 @@ impl Sequence for Move {
                     }
                 }
 
+                if is_footfall(obj.frame_idx, frames.len()) {
+                    ctx.out.push(Event::Footstep {
+                        obj: self.obj,
+                        pos: obj.pos(),
+                    });
+                }
+
                 (frames[obj.frame_idx].shift, obj.pos())
             };
             let shift = ctx.world.objects_mut().add_screen_shift(self.obj, shift);
@@ -156,11 +244,21 @@ impl Sequence for Move {
             // TODO use door
 
             self.path_pos += 1;
+            let redirected = self.apply_redirect(ctx.world);
+            if redirected {
+                self.rebuild_path(ctx.world);
+                self.path_pos = 0;
+            }
             if self.path_pos >= self.path.len() {
                 self.done(ctx);
                 return Result::Done;
             }
-            ctx.world.objects_mut().add_screen_shift(self.obj, shift);
+            if redirected {
+                // The new path may go in a different direction so start the step from the hex.
+                ctx.world.objects_mut().reset_screen_shift(self.obj);
+            } else {
+                ctx.world.objects_mut().add_screen_shift(self.obj, shift);
+            }
             self.init_step(ctx.world);
         }
         let new_last_time = if let State::Running(last_time) = self.state {
@@ -176,4 +274,16 @@ impl Sequence for Move {
             Running::Lagging
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_footfall_() {
+        let v: Vec<_> = (0..8).filter(|&i| is_footfall(i, 8)).collect();
+        assert_eq!(v, vec![0, 4]);
+        assert!(is_footfall(0, 1));
+    }
+}
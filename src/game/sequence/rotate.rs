@@ -0,0 +1,55 @@
+use crate::game::object::Handle;
+use crate::graphics::Point;
+use crate::graphics::geometry::hex::{self, Direction};
+use crate::sequence::*;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RotateTo {
+    Direction(Direction),
+    /// Turn toward the hex. The direction is computed when the sequence runs.
+    Point(Point),
+    /// Turn toward the object. The direction is computed when the sequence runs.
+    Object(Handle),
+}
+
+/// Turns the object to face the specified direction.
+pub struct Rotate {
+    obj: Handle,
+    to: RotateTo,
+    done: bool,
+}
+
+impl Rotate {
+    pub fn new(obj: Handle, to: RotateTo) -> Self {
+        Self {
+            obj,
+            to,
+            done: false,
+        }
+    }
+}
+
+impl Sequence for Rotate {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        if self.done {
+            return Result::Done;
+        }
+        self.done = true;
+
+        let objs = ctx.world.objects();
+        let pos = if let Some(v) = objs.get(self.obj).pos {
+            v.point
+        } else {
+            return Result::Done;
+        };
+        let direction = match self.to {
+            RotateTo::Direction(v) => Some(v),
+            RotateTo::Point(to) => Some(hex::direction(pos, to)),
+            RotateTo::Object(h) => objs.get(h).pos.map(|to| hex::direction(pos, to.point)),
+        };
+        if let Some(direction) = direction {
+            objs.get_mut(self.obj).direction = direction;
+        }
+        Result::Running(Running::NotLagging)
+    }
+}
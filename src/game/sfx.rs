@@ -0,0 +1,472 @@
+//! Sound effects.
+
+use enum_primitive_derive::Primitive;
+use if_chain::if_chain;
+use log::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::asset::{AttackGroup, CritterAnim, DamageKind, EntityKind, Material};
+use crate::asset::frame::{critter_anim_codes, FrameDb, FrameId};
+use crate::asset::proto::SubProto;
+use crate::fs::FileSystem;
+use crate::game::object::Object;
+use crate::graphics::{EPoint, Point};
+use crate::graphics::geometry::TileGridView;
+use crate::graphics::geometry::camera::Camera;
+
+pub const SFX_DIR: &str = "sound/sfx/";
+const SFX_EXT: &str = ".acm";
+
+/// Action performed on a door or container.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Primitive)]
+pub enum OpenAction {
+    Open = 0,
+    Close = 1,
+    Locked = 2,
+    Unlocked = 3,
+}
+
+impl OpenAction {
+    // snd_lookup_scenery_action
+    fn code(self) -> char {
+        match self {
+            OpenAction::Open => 'O',
+            OpenAction::Close => 'C',
+            OpenAction::Locked => 'L',
+            OpenAction::Unlocked => 'U',
+        }
+    }
+}
+
+// gsnd_build_open_sfx_name
+/// Builds name of the sound effect for opening/closing/rattling door or container `obj`.
+/// Returns `None` if `obj` is neither scenery nor item.
+pub fn open_sfx_name(obj: &Object, action: OpenAction) -> Option<String> {
+    let proto = obj.proto()?;
+    let (prefix, kind, sound_id) = match obj.fid.kind() {
+        EntityKind::Scenery => ('S', "DOORS", proto.sub.as_scenery()?.sound_id),
+        EntityKind::Item => ('I', "CNTNR", proto.sub.as_item()?.sound_id),
+        _ => return None,
+    };
+    Some(build_open_sfx_name(prefix, action, kind, sound_id))
+}
+
+fn build_open_sfx_name(prefix: char, action: OpenAction, kind: &str, sound_id: u8) -> String {
+    let mut r = String::with_capacity(8);
+    r.push(prefix);
+    r.push(action.code());
+    r.push_str(kind);
+    // Zero sound id terminates the C string in the original.
+    if sound_id != 0 {
+        r.push(sound_id as char);
+    }
+    r.make_ascii_uppercase();
+    r
+}
+
+/// Sound made by a weapon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Primitive)]
+pub enum WeaponSound {
+    Ready = 0,
+    Attack = 1,
+    OutOfAmmo = 2,
+    AmmoFlying = 3,
+    Hit = 4,
+}
+
+impl WeaponSound {
+    // snd_lookup_weapon_type
+    fn code(self) -> char {
+        match self {
+            WeaponSound::Ready => 'R',
+            WeaponSound::Attack => 'A',
+            WeaponSound::OutOfAmmo => 'O',
+            WeaponSound::AmmoFlying => 'I',
+            WeaponSound::Hit => 'H',
+        }
+    }
+}
+
+// gsnd_build_weapon_sfx_name
+/// Builds names of the `sound` effect of `weapon` used in `attack_group`. The hit sound depends
+/// on the material of the `target`. The names are ordered from the most specific one: sounds of
+/// the secondary attack fall back to the primary attack. Returns empty list if `weapon` is not
+/// a weapon.
+pub fn weapon_sfx_names(
+    sound: WeaponSound,
+    weapon: &Object,
+    attack_group: AttackGroup,
+    target: Option<&Object>,
+) -> Vec<String> {
+    let proto = if let Some(v) = weapon.proto() {
+        v
+    } else {
+        return Vec::new();
+    };
+    let weapon = if let Some(v) = proto.sub.as_weapon() {
+        v
+    } else {
+        return Vec::new();
+    };
+    let variant = match sound {
+        WeaponSound::Ready | WeaponSound::OutOfAmmo => 1,
+        _ if attack_group == AttackGroup::Primary => 1,
+        _ => 2,
+    };
+    let material = match target {
+        Some(target) if sound == WeaponSound::Hit
+            && weapon.damage_kind != DamageKind::Electric
+            && weapon.damage_kind != DamageKind::Emp =>
+        {
+            Some(target.proto().and_then(|p| match &p.sub {
+                SubProto::Item(v) => Some(v.material),
+                SubProto::Scenery(v) => Some(v.material),
+                SubProto::Wall(v) => Some(v.material),
+                _ => None,
+            }))
+        }
+        _ => None,
+    };
+    let mut r = vec![build_weapon_sfx_name(sound, weapon.sound_id, variant, material)];
+    if variant != 1 {
+        r.push(build_weapon_sfx_name(sound, weapon.sound_id, 1, material));
+    }
+    r
+}
+
+/// `material` is `None` if the sound doesn't depend on the target and `Some(None)` if the target
+/// has no material (e.g. it's a critter).
+fn build_weapon_sfx_name(
+    sound: WeaponSound,
+    sound_id: u8,
+    variant: u8,
+    material: Option<Option<Material>>,
+) -> String {
+    let material = material.map(material_code).unwrap_or('X');
+    let mut r = String::with_capacity(8);
+    r.push('W');
+    r.push(sound.code());
+    // Zero sound id terminates the C string in the original.
+    if sound_id != 0 {
+        r.push(sound_id as char);
+        r.push((b'0' + variant) as char);
+        r.push(material);
+        r.push_str("XX1");
+    }
+    r.make_ascii_uppercase();
+    r
+}
+
+/// Sound made by a critter along with its animation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Primitive)]
+pub enum CharacterSound {
+    Normal = 0,
+    Knockdown = 1,
+    PassOut = 2,
+    Die = 3,
+    /// Unarmed attack hits the target.
+    Contact = 4,
+}
+
+// gsnd_build_character_sfx_name
+/// Builds names of the `sound` effect of critter playing the animation of `fid`. The names are
+/// ordered from the most specific one: dying and passing out fall back to the falling sound.
+/// Returns empty list if `fid` is not a critter or there's no sound for the animation.
+pub fn character_sfx_names(fid: FrameId, sound: CharacterSound, frm_db: &FrameDb) -> Vec<String> {
+    if_chain! {
+        if let Some(base_name) = frm_db.base_name(fid);
+        if let Some(fid) = fid.critter();
+        if let Some(codes) = critter_anim_codes(fid.weapon(), fid.anim());
+        then {
+            build_character_sfx_names(base_name, fid.anim(), codes, sound)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+fn build_character_sfx_names(
+    base_name: &str,
+    anim: CritterAnim,
+    (c1, c2): (char, char),
+    sound: CharacterSound,
+) -> Vec<String> {
+    use CritterAnim::*;
+    let name = |c1| {
+        let mut r = format!("{}{}{}", base_name, c1, c2);
+        r.make_ascii_uppercase();
+        r
+    };
+    match (anim, sound) {
+        (FallBack, CharacterSound::PassOut) | (FallFront, CharacterSound::PassOut) =>
+            vec![name('Y'), name(c1)],
+        (FallBack, CharacterSound::Die) | (FallFront, CharacterSound::Die) =>
+            vec![name('Z'), name(c1)],
+        (ThrowPunch, CharacterSound::Contact) | (KickLeg, CharacterSound::Contact) =>
+            vec![name('Z')],
+        _ => vec![name(c1)],
+    }
+}
+
+/// Builds names of the footstep sound effect of critter playing walk or run animation of `fid`
+/// over the floor of `material`. The material specific `FOOT<material><W|R>` sound falls back to
+/// the critter's own walk/run sound. Returns empty list if `fid` is not a walking critter.
+pub fn footstep_sfx_names(fid: FrameId, material: Option<Material>, frm_db: &FrameDb)
+    -> Vec<String>
+{
+    let name = fid.critter().and_then(|c| build_footstep_sfx_name(c.anim(), material));
+    if let Some(name) = name {
+        let mut r = vec![name];
+        r.extend(character_sfx_names(fid, CharacterSound::Normal, frm_db));
+        r
+    } else {
+        Vec::new()
+    }
+}
+
+fn build_footstep_sfx_name(anim: CritterAnim, material: Option<Material>) -> Option<String> {
+    let anim = match anim {
+        CritterAnim::Walk => 'W',
+        CritterAnim::Running => 'R',
+        _ => return None,
+    };
+    Some(format!("FOOT{}{}", material_code(material), anim))
+}
+
+// Material codes of the weapon hit sounds.
+fn material_code(material: Option<Material>) -> char {
+    use Material::*;
+    match material {
+        Some(Glass) | Some(Metal) | Some(Plastic) => 'M',
+        Some(Wood) => 'W',
+        Some(Dirt) | Some(Stone) | Some(Cement) => 'S',
+        Some(Leather) | None => 'F',
+    }
+}
+
+/// Volume of the sound played at full loudness.
+pub const MAX_VOLUME: u32 = 0x7fff;
+
+/// Pan of the sound heard only in the right channel. The negated value is for the left channel.
+pub const MAX_PAN: i32 = 0x7fff;
+
+/// Horizontal screen distance from the viewport center at which the sound is panned fully.
+const FULL_PAN_DISTANCE: i32 = 640;
+
+/// Screen distance from the viewport center at which the sound fades out completely.
+const AUDIBLE_DISTANCE: i32 = 960;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sound {
+    /// Path of the sound file.
+    pub path: String,
+    /// Position of the sound source in the world. `None` for interface sounds.
+    pub pos: Option<EPoint>,
+    /// Volume in range `[0, MAX_VOLUME]`.
+    pub volume: u32,
+    /// Stereo pan in range `[-MAX_PAN, MAX_PAN]`, zero is center.
+    pub pan: i32,
+}
+
+impl Sound {
+    /// Sound of the file at `path` played at full volume in both channels.
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            pos: None,
+            volume: MAX_VOLUME,
+            pan: 0,
+        }
+    }
+}
+
+/// Returns volume and pan of the sound coming from `screen_pos` as heard by the `camera`.
+fn positional_mix(screen_pos: Point, camera: &Camera) -> (u32, i32) {
+    let d = screen_pos - camera.viewport.center();
+    let distance = ((d.x as f64).hypot(d.y as f64) as i32).min(AUDIBLE_DISTANCE);
+    let volume = MAX_VOLUME * (AUDIBLE_DISTANCE - distance) as u32 / AUDIBLE_DISTANCE as u32;
+    let pan = (d.x * MAX_PAN / FULL_PAN_DISTANCE).max(-MAX_PAN).min(MAX_PAN);
+    (volume, pan)
+}
+
+/// Collects sound effects requested by the game logic and hands them to the audio backend.
+pub struct Sfx {
+    fs: Rc<FileSystem>,
+    /// Resolved paths of the sound files keyed by the uppercase sound name. `None` if the file
+    /// doesn't exist.
+    paths: HashMap<String, Option<String>>,
+    pending: Vec<Sound>,
+}
+
+impl Sfx {
+    pub fn new(fs: Rc<FileSystem>) -> Self {
+        Self {
+            fs,
+            paths: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    // gsound_play_sfx_file
+    /// Plays sound effect `name` from `sound/sfx`. Missing files are ignored.
+    pub fn play(&mut self, name: &str, pos: Option<EPoint>) {
+        self.play_any(&[name], pos);
+    }
+
+    /// Plays the first existing sound effect of `names`. Returns `false` if none of the files
+    /// exist.
+    pub fn play_any(&mut self, names: &[impl AsRef<str>], pos: Option<EPoint>) -> bool {
+        for name in names {
+            if let Some(path) = self.resolve(name.as_ref()) {
+                self.pending.push(Sound {
+                    path,
+                    pos,
+                    volume: MAX_VOLUME,
+                    pan: 0,
+                });
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Plays the sound file at `path`. Used for the sounds outside of `sound/sfx` such as the
+    /// speech.
+    pub fn play_file(&mut self, path: String) {
+        self.pending.push(Sound::new(path));
+    }
+
+    /// Returns path of the sound effect file `name` or `None` if the file doesn't exist.
+    /// The result is cached so missing files are reported only once.
+    pub fn resolve(&mut self, name: &str) -> Option<String> {
+        let fs = &self.fs;
+        self.paths.entry(name.to_ascii_uppercase())
+            .or_insert_with(|| {
+                let path = format!("{}{}{}", SFX_DIR, name, SFX_EXT).to_ascii_lowercase();
+                if fs.exists(&path) {
+                    Some(path)
+                } else {
+                    debug!("sfx not found: {}", path);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    /// Takes the sounds requested since the last call. Sounds coming from the world are panned
+    /// and attenuated by their position relative to the `camera`. Sounds on other elevations
+    /// or too far away to be heard are dropped.
+    pub fn drain(&mut self, camera: &Camera, elevation: u32) -> impl Iterator<Item=Sound> + '_ {
+        let camera = camera.clone();
+        self.pending.drain(..)
+            .filter_map(move |mut sound| {
+                if let Some(pos) = sound.pos {
+                    if pos.elevation != elevation {
+                        return None;
+                    }
+                    let (volume, pan) =
+                        positional_mix(camera.hex().center_to_screen(pos.point), &camera);
+                    sound.volume = sound.volume * volume / MAX_VOLUME;
+                    sound.pan = pan;
+                }
+                if sound.volume > 0 {
+                    Some(sound)
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_open_sfx_name_() {
+        assert_eq!(build_open_sfx_name('S', OpenAction::Open, "DOORS", b'a'), "SODOORSA");
+        assert_eq!(build_open_sfx_name('S', OpenAction::Close, "DOORS", b'1'), "SCDOORS1");
+        assert_eq!(build_open_sfx_name('I', OpenAction::Locked, "CNTNR", b'B'), "ILCNTNRB");
+        assert_eq!(build_open_sfx_name('I', OpenAction::Unlocked, "CNTNR", 0), "IUCNTNR");
+    }
+
+    #[test]
+    fn build_weapon_sfx_name_() {
+        use WeaponSound::*;
+        assert_eq!(build_weapon_sfx_name(Attack, b'a', 1, None), "WAA1XXX1");
+        assert_eq!(build_weapon_sfx_name(Attack, b'K', 2, None), "WAK2XXX1");
+        assert_eq!(build_weapon_sfx_name(Hit, b'a', 1, Some(None)), "WHA1FXX1");
+        assert_eq!(build_weapon_sfx_name(Hit, b'a', 1, Some(Some(Material::Metal))), "WHA1MXX1");
+        assert_eq!(build_weapon_sfx_name(Hit, b'b', 2, Some(Some(Material::Wood))), "WHB2WXX1");
+        assert_eq!(build_weapon_sfx_name(Hit, b'b', 1, Some(Some(Material::Cement))),
+            "WHB1SXX1");
+        assert_eq!(build_weapon_sfx_name(Ready, 0, 1, None), "WR");
+    }
+
+    #[test]
+    fn build_character_sfx_names_() {
+        use CharacterSound::*;
+        let f = |anim, codes, sound| build_character_sfx_names("hmjmps", anim, codes, sound);
+        assert_eq!(f(CritterAnim::ThrowPunch, ('a', 'q'), Normal), vec!["HMJMPSAQ"]);
+        assert_eq!(f(CritterAnim::ThrowPunch, ('a', 'q'), Contact), vec!["HMJMPSZQ"]);
+        assert_eq!(f(CritterAnim::KickLeg, ('a', 'r'), Contact), vec!["HMJMPSZR"]);
+        assert_eq!(f(CritterAnim::FallBack, ('b', 'a'), Die), vec!["HMJMPSZA", "HMJMPSBA"]);
+        assert_eq!(f(CritterAnim::FallFront, ('b', 'b'), PassOut), vec!["HMJMPSYB", "HMJMPSBB"]);
+        assert_eq!(f(CritterAnim::FallFront, ('b', 'b'), Knockdown), vec!["HMJMPSBB"]);
+    }
+
+    #[test]
+    fn build_footstep_sfx_name_() {
+        use CritterAnim::*;
+        assert_eq!(build_footstep_sfx_name(Walk, Some(Material::Wood)).unwrap(), "FOOTWW");
+        assert_eq!(build_footstep_sfx_name(Running, Some(Material::Dirt)).unwrap(), "FOOTSR");
+        assert_eq!(build_footstep_sfx_name(Walk, Some(Material::Metal)).unwrap(), "FOOTMW");
+        assert_eq!(build_footstep_sfx_name(Walk, None).unwrap(), "FOOTFW");
+        assert_eq!(build_footstep_sfx_name(Stand, Some(Material::Wood)), None);
+    }
+
+    #[test]
+    fn positional_mix_() {
+        let camera = Camera {
+            origin: Point::new(0, 0),
+            viewport: crate::graphics::Rect::with_size(0, 0, 640, 380),
+        };
+        let c = camera.viewport.center();
+        assert_eq!(positional_mix(c, &camera), (MAX_VOLUME, 0));
+        assert_eq!(positional_mix(c + Point::new(320, 0), &camera),
+            (MAX_VOLUME * 2 / 3, MAX_PAN / 2));
+        assert_eq!(positional_mix(c - Point::new(320, 0), &camera),
+            (MAX_VOLUME * 2 / 3, -MAX_PAN / 2));
+        assert_eq!(positional_mix(c - Point::new(2000, 0), &camera), (0, -MAX_PAN));
+        assert_eq!(positional_mix(c + Point::new(0, 480), &camera), (MAX_VOLUME / 2, 0));
+    }
+
+    #[test]
+    fn play_any() {
+        let dir = std::env::temp_dir().join(format!("vault13-sfx-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sound/sfx")).unwrap();
+        std::fs::write(dir.join("sound/sfx/wak1xxx1.acm"), "").unwrap();
+        let mut fs = FileSystem::mock();
+        fs.register_provider(crate::fs::stdfs::new_provider(&dir).unwrap());
+        let mut sfx = Sfx::new(Rc::new(fs));
+
+        assert!(sfx.play_any(&["WAK2XXX1", "WAK1XXX1"], None));
+        assert!(!sfx.play_any(&["WAK2XXX1"], None));
+        assert_eq!(sfx.resolve("wak1xxx1"), Some("sound/sfx/wak1xxx1.acm".into()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Cached.
+        assert_eq!(sfx.resolve("WAK1XXX1"), Some("sound/sfx/wak1xxx1.acm".into()));
+        let camera = Camera {
+            origin: Point::new(0, 0),
+            viewport: crate::graphics::Rect::with_size(0, 0, 640, 380),
+        };
+        assert_eq!(sfx.drain(&camera, 0).collect::<Vec<_>>(), vec![Sound {
+            path: "sound/sfx/wak1xxx1.acm".into(),
+            pos: None,
+            volume: MAX_VOLUME,
+            pan: 0,
+        }]);
+    }
+}
@@ -1,9 +1,11 @@
 use enum_map::EnumMap;
 use std::convert::TryInto;
+use std::rc::Rc;
 
 use crate::asset::frame::FrameId;
 use crate::asset::message::{Messages, MessageId};
-use crate::fs::FileSystem;
+use crate::asset::message::localization::Localization;
+use crate::error;
 use crate::game::object;
 use crate::graphics::{Rect, Point};
 use crate::graphics::color::Rgb15;
@@ -56,19 +58,26 @@ pub enum Command {
 }
 
 pub struct Skilldex {
-    msgs: Messages,
+    msgs: Rc<Messages>,
     window: Option<Handle>,
 }
 
 impl Skilldex {
-    pub fn new(fs: &FileSystem, language: &str) -> Self {
-        let msgs = Messages::read_file(fs, language, "game/skilldex.msg").unwrap();
+    pub fn new(loc: &Localization) -> Self {
+        let msgs = loc.messages("game/skilldex.msg").unwrap();
         Self {
             msgs,
             window: None,
         }
     }
 
+    /// Loads the messages of the current language of `loc`. The open window keeps the old
+    /// texts until it's shown again.
+    pub fn reload_messages(&mut self, loc: &Localization) -> error::Result<()> {
+        self.msgs = loc.messages("game/skilldex.msg")?;
+        Ok(())
+    }
+
     pub fn is_visible(&self) -> bool {
         self.window.is_some()
     }
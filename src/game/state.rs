@@ -1,5 +1,6 @@
 use bstring::{bstr, BString};
 use enum_map::{enum_map, EnumMap};
+use enumflags2::BitFlags;
 use if_chain::if_chain;
 use log::*;
 use measure_time::*;
@@ -7,75 +8,166 @@ use sdl2::event::Event as SdlEvent;
 use sdl2::keyboard::Keycode;
 use std::cell::RefCell;
 use std::cmp;
+use std::io;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use crate::asset::frame::{FrameDb, FrameId};
+use crate::asset::acm;
+use crate::asset::death_ending;
+use crate::asset::endgame;
+use crate::asset::frame::{FrameDb, FrameId, Idx};
 use crate::asset::map::db::MapDb;
 use crate::asset::map::{MapId, MapReader, ELEVATION_COUNT};
-use crate::asset::message::{Messages, BULLET};
+use crate::asset::message::{format, MessageId, Messages, BULLET};
+use crate::asset::message::localization::Localization;
 use crate::asset::proto::*;
 use crate::asset::script::db::ScriptDb;
 use crate::asset::{self, *};
+use crate::error;
 use crate::fs::FileSystem;
-use crate::game::dialog::Dialog;
+use crate::game::attack;
+use crate::game::automap::Automap;
+use crate::game::combat::{self, Combat};
+use crate::game::console::{self, Console, DebugCommand};
+use crate::game::crash;
+use crate::game::death;
+use crate::game::dialog::{self, Dialog};
+use crate::game::explosive::{self, Explosive};
+use crate::game::ambient_sfx::AmbientSfx;
 use crate::game::fidget::Fidget;
+use crate::game::inspector::{self, Inspector};
 use crate::game::inventory::Inventory;
+use crate::game::loot::{self, Loot};
+use crate::game::map_state::{MapCache, MapState};
+use crate::game::mods::{self, Mods};
 use crate::game::object::{self, *};
+use crate::game::pipboy::Pipboy;
+use crate::game::radiation::{self, Radiation};
+use crate::game::rng::{random, RollCheckResult, Stream};
 use crate::game::rpg::Rpg;
 use crate::game::script::{self, ScriptKind, Scripts};
-use crate::game::sequence::frame_anim::{AnimDirection, FrameAnim, FrameAnimOptions};
-use crate::game::sequence::move_seq::Move;
+use crate::game::script_debugger::ScriptDebugger;
+use crate::game::sequence::frame_anim::{AnimDirection, FrameAnim, FrameAnimOptions, Rattle};
+use crate::game::sequence::move_seq::{Move, Redirect};
+use crate::game::sequence::rotate::{Rotate, RotateTo};
 use crate::game::sequence::stand::Stand;
 use crate::game::sequence::ObjSequencer;
+use crate::game::sfx::{self, CharacterSound, OpenAction, Sfx, Sound, WeaponSound};
 use crate::game::skilldex::{self, Skilldex};
+use crate::game::team;
 use crate::game::ui::action_menu::{self, Action};
-use crate::game::ui::hud;
+use crate::game::ui::hud::{self, Hud};
+use crate::game::ui::item_chooser::ItemChooser;
+use crate::game::ui::move_window::MoveWindow;
 use crate::game::ui::scroll_area::ScrollArea;
 use crate::game::ui::world::{HexCursorStyle, WorldView};
+use crate::game::wander::Wander;
 use crate::game::world::{ScrollDirection, World, WorldRef};
 use crate::graphics::font::Fonts;
 use crate::graphics::geometry::hex::{self, Direction};
 use crate::graphics::{EPoint, Rect};
+use crate::input::bindings::{self, Bindings};
+use crate::sequence::cancellable::Cancel;
 use crate::sequence::chain::Chain;
 use crate::sequence::event::PushEvent;
+use crate::sequence::fade::Fade;
 use crate::sequence::{self, Sequencer};
 use crate::state::{self, *};
 use crate::ui::command::inventory::Command;
+use crate::ui::command::loot::Command as LootCommand;
 use crate::ui::command::*;
+use crate::ui::message_box::{self, MessageBox};
 use crate::ui::message_panel::MessagePanel;
 use crate::ui::{self, Ui};
-use crate::util::random::random;
-use crate::util::{sprintf, EnumExt};
+use crate::util::{profile, sprintf, EnumExt};
 use crate::vm::{PredefinedProc, Suspend, Vm};
+use crate::vm::debug::Trace;
 
 const SCROLL_STEP: i32 = 10;
 
+/// Duration of the screen fade out and fade in around a `FadedAction`.
+const FADE_DURATION: Duration = Duration::from_millis(500);
+
+/// How often the map scripts get MapUpdate outside combat and dialogs.
+const MAP_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of frames a locked door moves forth and back when someone tries to open it.
+const LOCKED_DOOR_RATTLE_FRAMES: u32 = 1;
+
+/// "Are you sure you want to quit?" in `game/misc.msg`.
+const MSG_ARE_YOU_SURE_YOU_WANT_TO_QUIT: MessageId = 0;
+
 pub struct GameState {
     time: PausableTime,
     fs: Rc<FileSystem>,
     proto_db: Rc<ProtoDb>,
     frm_db: Rc<FrameDb>,
+    fonts: Rc<Fonts>,
     map_db: MapDb,
     world: WorldRef,
     scripts: Scripts,
     obj_sequencer: ObjSequencer,
     fidget: Fidget,
+    wander: Wander,
+    /// When the periodic MapUpdate is due.
+    next_map_update: Instant,
+    radiation: Radiation,
+    sfx: Sfx,
+    ambient_sfx: AmbientSfx,
     message_panel: ui::Handle,
     world_view: ui::Handle,
     dialog: Option<Dialog>,
     shift_key_down: bool,
+    /// Redirects the dude's move sequence started by clicking a hex.
+    dude_move: Option<Redirect>,
+    /// Target picked with the Inventory action. Set while the inventory is shown to choose the
+    /// item to use on it.
+    use_item_target: Option<object::Handle>,
+    /// Explosive being armed and the timer window.
+    explosive_timer: Option<(object::Handle, MoveWindow)>,
+    /// Window for choosing items to pick up from a hex and the critter picking them up.
+    item_chooser: Option<(object::Handle, ItemChooser)>,
+    /// Loot screen shown when the dude uses a corpse.
+    loot: Option<Loot>,
+    /// "Are you sure you want to quit?" message box.
+    quit_confirm: Option<MessageBox>,
+    /// Set when the quit is confirmed, reported to the app in the next update.
+    quit_requested: bool,
     last_picked_obj: Option<object::Handle>,
     object_action_menu: Option<ObjectActionMenu>,
     user_paused: bool,
     map_id: Option<MapId>,
-    in_combat: bool,
+    /// State of the maps visited before.
+    map_cache: MapCache,
+    /// Action waiting for the screen to fade out.
+    faded_action: Option<(FadedAction, Cancel)>,
+    combat: Option<Combat>,
+    combat_events: Vec<combat::Event>,
+    /// If `false` the combat only ends when the player presses End Combat.
+    combat_auto_end: bool,
+    /// Whether subtitles are shown for speech and movies.
+    subtitles: bool,
+    violence_level: death::ViolenceLevel,
+    strict_assets: bool,
+    /// Key bindings shared with the main loop.
+    bindings: Rc<RefCell<Bindings>>,
+    /// Config file the key bindings changed in the console are saved to.
+    bindings_path: Option<PathBuf>,
+    hud: Hud,
     seq_events: Vec<sequence::Event>,
+    loc: Rc<Localization>,
     misc_msgs: Rc<Messages>,
     scroll_areas: EnumMap<ScrollDirection, ui::Handle>,
     rpg: Rpg,
     skilldex: Skilldex,
+    pipboy: Pipboy,
+    automap: Automap,
     inventory: Inventory,
+    console: Console,
+    script_debugger: ScriptDebugger,
+    inspector: Inspector,
+    mods: Mods,
     ui_sequencer: Sequencer,
 }
 
@@ -83,25 +175,25 @@ impl GameState {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         fs: Rc<FileSystem>,
-        language: &str,
+        loc: Rc<Localization>,
         proto_db: Rc<ProtoDb>,
         frm_db: Rc<FrameDb>,
         fonts: Rc<Fonts>,
-        misc_msgs: Rc<Messages>,
         now: Instant,
         ui: &mut Ui,
-    ) -> Self {
+    ) -> error::Result<Self> {
         let time = PausableTime::new(now);
 
         let viewport = Rect::with_size(0, 0, 640, 380);
         let hex_grid = hex::TileGrid::default();
 
-        let critter_names = Messages::read_file(&fs, language, "game/scrname.msg").unwrap();
+        let misc_msgs = loc.messages("game/misc.msg")?;
+        let critter_names = loc.messages("game/scrname.msg")?;
 
-        let map_db = MapDb::new(&fs).unwrap();
+        let map_db = MapDb::new(&fs)?;
         let scripts = Scripts::new(
             proto_db.clone(),
-            ScriptDb::new(fs.clone(), language).unwrap(),
+            ScriptDb::new(fs.clone(), &loc.language())?,
             Vm::default(),
         );
         let world = World::new(
@@ -111,11 +203,15 @@ impl GameState {
             hex_grid,
             viewport,
             now,
-            fonts,
+            fonts.clone(),
         );
+        let radiation = Radiation::new(world.game_time);
         let world = Rc::new(RefCell::new(world));
         let obj_sequencer = ObjSequencer::new(now);
         let fidget = Fidget::new(now);
+        let wander = Wander::new(now);
+        let sfx = Sfx::new(fs.clone());
+        let ambient_sfx = AmbientSfx::new(now);
 
         let world_view_rect = Rect::with_size(0, 0, 640, 379);
         let world_view = {
@@ -128,45 +224,192 @@ impl GameState {
                 WorldView::new(world.clone()),
             )
         };
-        let message_panel = hud::create(ui);
+        let hud = hud::create(&fs, ui);
+        let message_panel = hud.message_panel;
 
         let scroll_areas = Self::create_scroll_areas(Rect::with_size(0, 0, 640, 480), ui);
 
-        let rpg = Rpg::new(&fs, language).unwrap();
+        let rpg = Rpg::new(&loc)?;
+
+        let skilldex = Skilldex::new(&loc);
 
-        let skilldex = Skilldex::new(&fs, language);
+        let pipboy = Pipboy::new(&loc)?;
 
-        let inventory = Inventory::new(world.clone(), &fs, language);
+        let inventory = Inventory::new(world.clone(), &loc);
 
         let ui_sequencer = Sequencer::new(now);
 
-        Self {
+        let map_cache = MapCache::new(fs.manifest());
+
+        Ok(Self {
             time,
             fs,
             frm_db,
             proto_db,
+            fonts,
             map_db,
             world,
             scripts,
             obj_sequencer,
             fidget,
+            wander,
+            next_map_update: now + MAP_UPDATE_INTERVAL,
+            radiation,
+            sfx,
+            ambient_sfx,
             message_panel,
             world_view,
             dialog: None,
             shift_key_down: false,
+            dude_move: None,
+            use_item_target: None,
+            explosive_timer: None,
+            item_chooser: None,
+            loot: None,
+            quit_confirm: None,
+            quit_requested: false,
             last_picked_obj: None,
             object_action_menu: None,
             user_paused: false,
             map_id: None,
-            in_combat: false,
+            map_cache,
+            faded_action: None,
+            combat: None,
+            combat_events: Vec::new(),
+            combat_auto_end: true,
+            subtitles: false,
+            violence_level: death::ViolenceLevel::Normal,
+            strict_assets: false,
+            bindings: Rc::new(RefCell::new(Bindings::default())),
+            bindings_path: None,
+            hud,
             seq_events: Vec::new(),
+            loc,
             misc_msgs,
             scroll_areas,
             rpg,
             skilldex,
+            pipboy,
+            automap: Automap::new(now),
             inventory,
+            console: Console::new(),
+            script_debugger: ScriptDebugger::new(),
+            inspector: Inspector::new(),
+            mods: Mods::new(),
             ui_sequencer,
+        })
+    }
+
+    pub fn set_combat_auto_end(&mut self, v: bool) {
+        self.combat_auto_end = v;
+    }
+
+    pub fn set_subtitles(&mut self, v: bool) {
+        self.subtitles = v;
+    }
+
+    pub fn subtitles(&self) -> bool {
+        self.subtitles
+    }
+
+    pub fn set_violence_level(&mut self, v: death::ViolenceLevel) {
+        self.violence_level = v;
+    }
+
+    pub fn set_strict_assets(&mut self, v: bool) {
+        self.strict_assets = v;
+    }
+
+    /// Keeps the state of the visited maps in files in `dir` instead of memory.
+    pub fn set_save_dir(&mut self, dir: PathBuf) {
+        self.map_cache.set_dir(dir);
+    }
+
+    pub fn bindings(&self) -> &Rc<RefCell<Bindings>> {
+        &self.bindings
+    }
+
+    pub fn set_bindings_path(&mut self, v: Option<PathBuf>) {
+        self.bindings_path = v;
+    }
+
+    pub fn set_mods(&mut self, mods: Mods) {
+        self.mods = mods;
+    }
+
+    pub fn set_vm_trace(&self, trace: Option<Trace>) {
+        self.scripts.vm().set_trace(trace);
+    }
+
+    /// Invalidates cached assets affected by changes of the files at `paths`.
+    /// The changed assets are picked up the next time they're used.
+    pub fn reload_assets(&mut self, paths: &[String]) {
+        for path in paths {
+            let mut reloaded = self.frm_db.reload(path);
+            match self.proto_db.reload(path) {
+                Ok(v) => reloaded |= v,
+                Err(e) => warn!("error reloading protos from {}: {}", path, e),
+            }
+            reloaded |= self.scripts.reload_asset(path);
+            if self.loc.reload(path) {
+                reloaded = true;
+                if let Err(e) = self.reload_messages() {
+                    warn!("error reloading messages from {}: {}", path, e);
+                }
+            }
+            if reloaded {
+                info!("reloaded {}", path);
+            } else {
+                debug!("ignored change of {}", path);
+            }
+        }
+    }
+
+    pub fn language(&self) -> String {
+        self.loc.language()
+    }
+
+    /// Takes the sounds requested since the last call, mixed for the current camera position.
+    pub fn drain_sounds(&mut self) -> Vec<Sound> {
+        let world = self.world.borrow();
+        self.sfx.drain(world.camera(), world.elevation())
+            .inspect(|sound| trace!("sfx: {:?}", sound))
+            .collect()
+    }
+
+    pub fn misc_msgs(&self) -> &Messages {
+        &self.misc_msgs
+    }
+
+    /// Switches the game texts, proto names and the font codepage to `language`. The texts in
+    /// the open windows are updated when the windows are shown again. Localized art keeps the
+    /// language the game was started with.
+    pub fn set_language(&mut self, language: &str) -> error::Result<()> {
+        let old_language = self.loc.language();
+        self.loc.set_language(language)?;
+        let language = self.loc.language();
+        if let Err(e) = self.proto_db.set_language(&language) {
+            self.loc.set_language(&old_language)?;
+            return Err(e.into());
         }
+        self.fonts.set_encoding(self.loc.encoding());
+        self.scripts.set_language(&language);
+        self.reload_messages()
+    }
+
+    /// Picks up the messages of the current language from `loc`.
+    fn reload_messages(&mut self) -> error::Result<()> {
+        self.misc_msgs = self.loc.messages("game/misc.msg")?;
+        self.world.borrow_mut().set_critter_names(self.loc.messages("game/scrname.msg")?);
+        self.rpg.reload_messages(&self.loc)?;
+        self.skilldex.reload_messages(&self.loc)?;
+        self.pipboy.reload_messages(&self.loc)?;
+        self.inventory.reload_messages(&self.loc)?;
+        Ok(())
+    }
+
+    pub fn combat(&self) -> Option<&Combat> {
+        self.combat.as_ref()
     }
 
     pub fn world(&self) -> &RefCell<World> {
@@ -181,20 +424,29 @@ impl GameState {
         &self.time
     }
 
-    pub fn new_game(&mut self) {
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.map_cache.clear();
         self.scripts.vars.global_vars =
-            asset::read_game_global_vars(&mut self.fs.reader("data/vault13.gam").unwrap())
-                .unwrap()
+            asset::read_game_global_vars(&mut self.fs.reader("data/vault13.gam")?)?
                 .into();
 
-        let dude_fid = FrameId::from_packed(0x100003E).unwrap();
+        let game = self.fs.game();
+        let naked_fidx = match game {
+            Game::Fallout1 => self.frm_db.find_id(EntityKind::Critter, game.dude_naked_art())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!(
+                    "no dude art `{}` in art/critters/critters.lst", game.dude_naked_art())))?,
+            // hmwarr
+            Game::Fallout2 => 0x3E,
+        };
+        let dude_fid = FrameId::from_packed(0x1000000 | naked_fidx as u32).unwrap();
         //    let dude_fid = FrameId::from_packed(0x101600A).unwrap();
-        let _ = self.world.borrow_mut().objects_mut().create(
+        self.world.borrow_mut().objects_mut().create(
             Some(dude_fid),
             Some(self.proto_db.dude()),
             Some(Default::default()),
             Some(&self.rpg),
         );
+        self.set_dude_appearance(naked_fidx);
 
         // TODO replace with proper init
         self.world
@@ -221,10 +473,49 @@ impl GameState {
         c.base_stats[Stat::ActionPoints] = 9;
         c.base_stats[Stat::MeleeDmg] = 8;
         c.base_stats[Stat::CarryWeight] = 250;
+
+        Ok(())
+    }
+
+    /// Ends the current game so that `new_game()` can start another one. Removes all objects
+    /// and forgets the visited maps without running the map exit procedures.
+    fn end_game(&mut self, ui: &mut Ui) {
+        self.end_combat(ui);
+        if let Some(dialog) = self.dialog.take() {
+            dialog.hide(ui, &mut self.world.borrow_mut());
+        }
+        self.map_id = None;
+        self.map_cache.clear();
+        self.scripts.reset();
+        self.obj_sequencer.clear();
+        self.world.borrow_mut().reset();
+        self.radiation = Radiation::new(self.world.borrow().game_time);
+        match Rpg::new(&self.loc) {
+            Ok(rpg) => self.rpg = rpg,
+            Err(e) => warn!("couldn't reset the character stats: {}", e),
+        }
+    }
+
+    /// Sets the dude's critter art shown without armor. The art is kept in the dude object
+    /// which travels between the maps, and in the dude's prototype.
+    fn set_dude_appearance(&self, naked_fidx: Idx) {
+        let world = self.world.borrow();
+        let dude = world.objects().dude();
+        world.objects().get_mut(dude).sub.as_critter_mut().unwrap()
+            .dude_mut().naked_fidx = naked_fidx;
+        self.proto_db.dude().borrow_mut().fid =
+            FrameId::new(EntityKind::Critter, None, 0, 0, naked_fidx).unwrap();
+        world.objects().sync_equipped_fid(dude, &self.rpg);
     }
 
     pub fn switch_map(&mut self, map_name: &str, ui: &mut Ui) {
+        self.load_map(map_name, ui);
+        self.run_mod_hook(mods::Event::MapEnter { map: map_name.into() });
+    }
+
+    fn load_map(&mut self, map_name: &str, ui: &mut Ui) {
         debug!("switching map to `{}`", map_name);
+        crash::set_map(map_name);
 
         if let Some(map_id) = self.map_id {
             let ctx = &mut script::Context {
@@ -242,34 +533,83 @@ impl GameState {
             self.scripts.execute_map_procs(PredefinedProc::MapExit, ctx);
         }
 
-        let mut dude_obj = {
+        let (mut dude_obj, party, mut explosive_timers) = {
             let mut world = self.world.borrow_mut();
             let dude_obj = world.objects().dude();
             let dude_obj = world.objects_mut().remove_deep(dude_obj);
+
+            // Party members and their scripts with the timer events go with the dude.
+            let scripts = &mut self.scripts;
+            let party: Vec<_> = world.party().to_vec().into_iter()
+                .map(|obj| {
+                    let script = world.objects().get(obj).script
+                        .and_then(|(sid, _)| {
+                            let timer_events = scripts.timer_events.take(sid);
+                            scripts.remove(sid)
+                                .map(|(program_id, local_vars)|
+                                    (sid.kind(), program_id, local_vars, timer_events))
+                        });
+                    (world.objects_mut().remove_deep(obj), script)
+                })
+                .collect();
+
+            if let Some(map_id) = self.map_id {
+                let state = MapState::save(&mut world, &self.scripts);
+                self.map_cache.put(map_id, &state);
+            }
+            // The rest are timers of the objects carried by the party.
+            let explosive_timers = std::mem::take(&mut world.explosive_timers);
             world.clear();
-            dude_obj
+            (dude_obj, party, explosive_timers)
         };
 
         self.scripts.reset();
         self.obj_sequencer.clear();
 
         // Reinsert the hex cursor. Needs `world` to be not borrowed.
-        ui.widget_mut::<WorldView>(self.world_view)
-            .ensure_hex_cursor();
+        {
+            let mut world_view = ui.widget_mut::<WorldView>(self.world_view);
+            world_view.ensure_hex_cursor();
+            world_view.invalidate_all();
+        }
 
         let world = &mut self.world.borrow_mut();
 
+        let map_path = format!("maps/{}.map", map_name);
         let map = MapReader {
-            reader: &mut self.fs.reader(&format!("maps/{}.map", map_name)).unwrap(),
+            reader: &mut self.fs.reader(&map_path)
+                .unwrap_or_else(|e| panic!("couldn't load map {}: {}", map_name, e)),
             objects: world.objects_mut(),
             proto_db: &self.proto_db,
             frm_db: &self.frm_db,
             scripts: &mut self.scripts,
+            recoverable_errors: None,
+            strict: self.strict_assets,
         }
         .read()
-        .unwrap();
+        .unwrap_or_else(|e| panic!("couldn't load map {}: {}", map_path, e));
 
         self.map_id = Some(map.id);
+        self.wander.clear();
+        self.next_map_update = self.time.time() + MAP_UPDATE_INTERVAL;
+
+        let ambient_sfx = self.map_db.get(map.id)
+            .map(|def| def.ambient_sfx.clone())
+            .unwrap_or_default();
+        self.ambient_sfx.reset(ambient_sfx, self.time.time());
+
+        let restored = if let Some(state) = self.map_cache.take(map.id, &self.proto_db) {
+            debug!("restoring state of map {}", map.id);
+            for &obj in &map.objects {
+                world.objects_mut().remove_deep(obj);
+            }
+            if let Err(e) = state.restore(world, &mut self.scripts) {
+                warn!("error restoring scripts of map {}: {}", map.id, e);
+            }
+            true
+        } else {
+            false
+        };
 
         for elev in &map.sqr_tiles {
             if let Some(ref elev) = elev {
@@ -315,11 +655,39 @@ impl GameState {
             });
             dude_obj.set_pos(Some(map.entrance));
         }
-        let dude_obj = world.objects_mut().insert_graph(dude_obj);
+        // Timers of the carried objects follow them to the new handles.
+        let (dude_obj, mut handle_map) = world.objects_mut().insert_graph_mapped(dude_obj);
 
         world.objects_mut().make_standing(dude_obj);
 
-        {
+        for (mut obj, script) in party {
+            let root = obj.objects.get_mut(obj.root).unwrap();
+            root.set_pos(Some(map.entrance));
+            let sid = if let Some((kind, program_id, local_vars, timer_events)) = script {
+                let sid = self.scripts.instantiate_new(kind, program_id, Some(local_vars))
+                    .unwrap();
+                for e in timer_events {
+                    self.scripts.timer_events.add(e.time, sid, e.info);
+                }
+                root.script = Some((sid, program_id));
+                Some(sid)
+            } else {
+                None
+            };
+            let (obj, obj_handle_map) = world.objects_mut().insert_graph_mapped(obj);
+            handle_map.extend(obj_handle_map);
+            if let Some(sid) = sid {
+                self.scripts.attach_to_object(sid, obj);
+            }
+            world.objects_mut().make_standing(obj);
+            world.add_party_member(obj);
+        }
+        world.sync_party_positions();
+        explosive_timers.remap(&handle_map);
+        world.explosive_timers.append(explosive_timers);
+        world.drug_effects.remap(&handle_map);
+
+        if !restored {
             assert!(!map.savegame);
             let path = format!("maps/{}.gam", map_name);
             self.scripts.vars.map_vars = if self.fs.exists(&path) {
@@ -349,9 +717,11 @@ impl GameState {
             // PredefinedProc::Start for map script is never called.
             // MapEnter in map script is called before anything else.
             if let Some(sid) = self.scripts.map_sid() {
-                self.scripts
+                if let Some(r) = self.scripts
                     .execute_predefined_proc(sid, PredefinedProc::MapEnter, ctx)
-                    .map(|r| r.suspend.map(|_| panic!("can't suspend in MapEnter")));
+                {
+                    r.assert_no_suspend();
+                }
             }
 
             self.scripts
@@ -372,7 +742,11 @@ impl GameState {
             Action::Cancel => {}
             Action::Drop | Action::Unload => unreachable!(),
             Action::Inventory => {
-                // TODO
+                // use_inventory_on
+                let dude = self.world.borrow().objects().dude();
+                self.obj_sequencer.cancel(dude);
+                self.use_item_target = Some(obj);
+                self.inventory.show(&self.rpg, ui, &mut self.ui_sequencer);
             }
             Action::Look => {
                 self.dude_examine_object(obj, ui);
@@ -405,6 +779,12 @@ impl GameState {
         let mut events = std::mem::take(&mut self.seq_events);
         for event in events.drain(..) {
             match event {
+                Attack { attacker, target, attack } => {
+                    self.attack(attacker, target, attack, ctx.ui);
+                }
+                AttackSetup { attacker, target } => {
+                    self.start_combat_with(&[attacker, target], ctx.ui);
+                }
                 ObjectMoved { obj, new_pos, .. } => {
                     let world = self.world.borrow();
                     if obj == world.objects().dude() {
@@ -437,6 +817,28 @@ impl GameState {
                 } => {
                     self.use_skill_on(skill, user, target, ctx.ui);
                 }
+                UseItemOn { user, item, target } => {
+                    self.use_item_on(user, item, target, ctx.ui);
+                }
+                Footstep { obj, pos } => {
+                    let world = self.world.borrow();
+                    let fid = world.objects().get(obj).fid;
+                    let names = sfx::footstep_sfx_names(fid, world.floor_material(pos),
+                        world.frm_db());
+                    self.sfx.play_any(&names, Some(pos));
+                }
+                PlaySfx { names, pos } => {
+                    self.sfx.play_any(&names, pos);
+                }
+                DudeDied => {
+                    self.game_over();
+                }
+                EndgameSlideshow => {
+                    self.endgame_slideshow();
+                }
+                PlayMovie { name } => {
+                    self.run_faded(FadedAction::PlayMovie { name });
+                }
             }
         }
         self.seq_events = events;
@@ -452,7 +854,7 @@ impl GameState {
                     r.push(Action::Rotate);
                 } else {
                     if world.objects().get(objh).can_talk_to() {
-                        if !self.in_combat {
+                        if self.combat.is_none() {
                             r.push(Action::Talk);
                         }
                     } else if !obj
@@ -470,7 +872,7 @@ impl GameState {
                         world.objects().dude(),
                         objh,
                         &self.scripts,
-                        self.in_combat,
+                        self.combat.is_some(),
                     ) {
                         r.push(Action::Push);
                     }
@@ -541,7 +943,7 @@ impl GameState {
                     rpg: &mut self.rpg,
                 });
             then {
-                assert!(r.suspend.is_none(), "can't suspend");
+                r.assert_no_suspend();
                 if r.script_overrides {
                     return None;
                 }
@@ -556,7 +958,7 @@ impl GameState {
             .map(|c| c.is_dead())
             .unwrap_or(false)
         {
-            491 + random(0, 1)
+            491 + random(Stream::Misc, 0, 1)
         } else {
             490
         };
@@ -614,7 +1016,7 @@ impl GameState {
                     rpg: &mut self.rpg,
                 });
             then {
-                assert!(r.suspend.is_none(), "can't suspend");
+                r.assert_no_suspend();
                 r.script_overrides
             } else {
                 false
@@ -724,7 +1126,7 @@ impl GameState {
                     )
                     .and_then(|r| r.suspend)
                 {
-                    None | Some(Suspend::GsayEnd) => {}
+                    None | Some(Suspend::GsayEnd) | Some(Suspend::Breakpoint) => {}
                 }
             }
         } else {
@@ -771,6 +1173,7 @@ impl GameState {
         if used_kind != ExactEntityKind::Scenery(SceneryKind::Stairs) {
             // FIXME must call check_next_to() before running this animation
             let use_anim = if usedo.is_critter_prone()
+                || usedo.kind() == EntityKind::Item
                 || usedo.kind() == EntityKind::Scenery
                     && usedo.proto().unwrap().flags_ext.contains(FlagExt::Prone)
             {
@@ -808,6 +1211,23 @@ impl GameState {
         if !self.check_next_to(user, used, ui) {
             return;
         }
+        let used_kind = self.world.borrow().objects().get(used).kind();
+        match used_kind {
+            EntityKind::Item => {
+                self.pick_up(user, used, ui);
+                return;
+            }
+            EntityKind::Critter => {
+                // TODO steal from the living critters.
+                let dead = self.world.borrow().objects().get(used).sub.as_critter().unwrap()
+                    .is_dead();
+                if dead {
+                    self.loot(user, used, ui);
+                }
+                return;
+            }
+            _ => {}
+        }
         // TODO why different results?
         // if ( user == g_obj_dude )
         //   {
@@ -875,7 +1295,8 @@ impl GameState {
             };
             if !script_overrides && user == world.objects().dude() {
                 if let Some(obj_name) = world.object_name(used) {
-                    let msg = &self.proto_db.messages().get(MSG_YOU_SEE_X).unwrap().text;
+                    let msgs = self.proto_db.messages();
+                    let msg = &msgs.get(MSG_YOU_SEE_X).unwrap().text;
                     let msg = sprintf(msg, &[&obj_name]);
                     self.push_message(&msg, ui);
                 }
@@ -883,166 +1304,995 @@ impl GameState {
         }
     }
 
-    fn use_door(&mut self, user: object::Handle, door: object::Handle, ui: &mut Ui) {
-        let world = &mut self.world.borrow_mut();
-
-        let script = {
-            let dooro = world.objects().get(door);
-            if dooro.is_locked().unwrap() {
-                // TODO sfx
-            }
-            dooro.script
-        };
-
-        if let Some((sid, _)) = script {
-            let script_overrides = self
-                .scripts
-                .execute_predefined_proc(
-                    sid,
-                    PredefinedProc::Use,
-                    &mut script::Context {
-                        world,
-                        obj_sequencer: &mut self.obj_sequencer,
-                        dialog: &mut self.dialog,
-                        ui,
-                        message_panel: self.message_panel,
-                        map_id: self.map_id.unwrap(),
-                        source_obj: Some(user),
-                        target_obj: Some(door),
-                        skill: None,
-                        rpg: &mut self.rpg,
-                    },
-                )
-                .unwrap()
-                .assert_no_suspend()
-                .script_overrides;
-            if script_overrides {
+    // action_get_an_object
+    /// Picks up the `item` lying on the ground. If there are more items on the same hex, the dude
+    /// chooses which of them to take.
+    fn pick_up(&mut self, picker: object::Handle, item: object::Handle, ui: &mut Ui) {
+        let items: Vec<_> = {
+            let world = self.world.borrow();
+            let objs = world.objects();
+            if !Self::can_pick_up(&objs.get(item)) {
                 return;
             }
-        }
-
-        let dooro = world.objects().get(door);
-        let need_open = if dooro.frame_idx > 0 {
-            // Indicates the door is open
-            if world.objects().has_blocker_at(dooro.pos(), None) {
-                let msg = &self
-                    .proto_db
-                    .messages()
-                    .get(MSG_DOORWAY_SEEMS_TO_BE_BLOCKED)
-                    .unwrap()
-                    .text;
-                self.push_message(msg, ui);
-                return;
+            objs.at(objs.get(item).pos()).iter()
+                .copied()
+                .filter(|&h| Self::can_pick_up(&objs.get(h)))
+                .map(|h| (h, objs.get(h).proto().unwrap().sub.as_item().unwrap()
+                    .inventory_fid.unwrap_or(objs.get(h).fid)))
+                .collect()
+        };
+        if items.len() > 1 && picker == self.world.borrow().objects().dude() {
+            let chooser = ItemChooser::show(&items, ui);
+            if let Some((_, old)) = self.item_chooser.replace((picker, chooser)) {
+                old.hide(ui);
             }
-            false
         } else {
-            if dooro
-                .sub
-                .as_scenery()
-                .unwrap()
-                .as_door()
-                .unwrap()
-                .flags
-                .contains(DoorFlag::Open)
-            {
-                return;
-            }
-            true
-        };
-
-        let seq = Chain::new();
-        seq.control()
-            .cancellable(FrameAnim::new(
-                door,
-                FrameAnimOptions {
-                    direction: if need_open {
-                        AnimDirection::Forward
-                    } else {
-                        AnimDirection::Backward
-                    },
-                    skip: 1,
-                    ..Default::default()
-                },
-            ))
-            .finalizing(PushEvent::new(sequence::Event::SetDoorState {
-                door,
-                open: need_open,
-            }));
+            self.pick_up_items(picker, &[item], ui);
+        }
+    }
 
-        self.obj_sequencer.replace(door, seq);
+    fn can_pick_up(obj: &Object) -> bool {
+        obj.kind() == EntityKind::Item
+            && obj.try_pos().is_some()
+            && !obj.flags.contains(Flag::TurnedOff)
+            && obj.proto().map(|p| p.kind() != ExactEntityKind::Item(ItemKind::Container)
+                || p.flags_ext.contains(FlagExt::CanPickup))
+                .unwrap_or(false)
     }
 
-    // set_door_open, set_door_closed, check_door_state
-    fn set_door_state(&mut self, door: object::Handle, open: bool) {
-        let mut world = self.world.borrow_mut();
-        {
-            {
-                let mut dooro = world.objects_mut().get_mut(door);
+    // obj_pickup
+    fn pick_up_items(&mut self, picker: object::Handle, items: &[object::Handle], ui: &mut Ui) {
+        let too_heavy = {
+            let mut world = self.world.borrow_mut();
+            let mut too_heavy = false;
+            for &item in items {
+                // The items may have been taken by someone else while the chooser was shown.
+                if !world.objects().contains(item)
+                    || !Self::can_pick_up(&world.objects().get(item))
                 {
-                    let door = dooro.sub.as_scenery_mut().unwrap().as_door_mut().unwrap();
-                    if open {
-                        door.flags.insert(DoorFlag::Open);
-                    } else {
-                        door.flags.remove(DoorFlag::Open);
-                    }
+                    continue;
                 }
-                if open {
-                    dooro
-                        .flags
-                        .insert(Flag::ShootThru | Flag::LightThru | Flag::NoBlock);
-                } else {
-                    dooro
-                        .flags
-                        .remove(Flag::ShootThru | Flag::LightThru | Flag::NoBlock);
+                if !world.objects_mut().try_move_into_inventory(picker, item, 1, &self.rpg) {
+                    too_heavy = true;
                 }
             }
+            too_heavy && picker == world.objects().dude()
+        };
+        if too_heavy {
+            // You cannot pick that up. You are at your maximum weight capacity.
+            let msg = &self.misc_msgs.get(905).unwrap().text;
+            self.push_message(msg, ui);
+        }
+        self.inventory.sync(&self.rpg, ui);
+    }
 
-            world.objects_mut().set_frame(
-                door,
-                if open {
-                    SetFrame::Last
-                } else {
-                    SetFrame::Index(0)
-                },
-            );
+    /// Shows the loot screen for the dude. Other critters take everything they can carry from
+    /// the `corpse`.
+    // loot_container
+    fn loot(&mut self, looter: object::Handle, corpse: object::Handle, ui: &mut Ui) {
+        if looter == self.world.borrow().objects().dude() {
+            self.obj_sequencer.cancel(looter);
+            let msgs = self.loc.messages("game/inventry.msg").unwrap();
+            let loot = Loot::show(msgs, self.world.clone(), looter, corpse, &self.rpg, ui);
+            if let Some(old) = self.loot.replace(loot) {
+                old.hide(ui);
+            }
+        } else {
+            loot::take_all(&self.world, looter, corpse, &self.rpg);
         }
-        world.objects_mut().rebuild_light_grid();
     }
 
-    // is_next_to
-    fn check_next_to(&mut self, obj1: object::Handle, obj2: object::Handle, ui: &mut Ui) -> bool {
-        if self.world.borrow().objects().distance(obj1, obj2).unwrap() > 1 {
-            let msg = &self.misc_msgs.get(2000).unwrap().text;
+    fn handle_loot(&mut self, command: UiCommand, ui: &mut Ui) {
+        if let UiCommandData::Loot(LootCommand::Hide) = command.data {
+            if let Some(loot) = self.loot.take() {
+                loot.hide(ui);
+            }
+            return;
+        }
+        let loot = unwrap_or_return!(self.loot.as_mut(), Some);
+        if !loot.handle(command, &self.rpg, ui) {
+            // You cannot pick that up. You are at your maximum weight capacity.
+            let msg = &self.misc_msgs.get(905).unwrap().text;
             self.push_message(msg, ui);
-            false
-        } else {
-            true
         }
     }
 
-    fn create_scroll_areas(rect: Rect, ui: &mut Ui) -> EnumMap<ScrollDirection, ui::Handle> {
-        let mut new = |rect, cur, curx| {
-            let win = ui.new_window(rect, None);
-            ui.new_widget(
-                win,
-                Rect::with_size(0, 0, rect.width(), rect.height()),
-                None,
-                None,
-                ScrollArea::new(
-                    cur,
-                    curx,
-                    Duration::from_millis(0),
-                    Duration::from_millis(30),
-                ),
-            )
+    fn handle_item_chooser(&mut self, cmd: ItemChooserCommand, ui: &mut Ui) {
+        let (picker, chooser) = unwrap_or_return!(self.item_chooser.take(), Some);
+        let items = match cmd {
+            ItemChooserCommand::Pick { object } => vec![object],
+            ItemChooserCommand::PickAll => chooser.items().to_vec(),
+            ItemChooserCommand::Cancel => Vec::new(),
         };
-        use ui::Cursor::*;
-        use ScrollDirection::*;
-        enum_map! {
-            N => new(
-                Rect::new(rect.left + 1, rect.top, rect.right - 1, rect.top + 1),
-                ScrollNorth, ScrollNorthX),
-            NE => new(
+        chooser.hide(ui);
+        if self.world.borrow().objects().contains(picker) {
+            self.pick_up_items(picker, &items, ui);
+        }
+    }
+
+    // obj_use_item
+    fn use_inventory_item(&mut self, item: object::Handle, ui: &mut Ui) {
+        let pid = unwrap_or_return!(self.world.borrow().objects().get(item).proto_id(), Some);
+        self.run_mod_hook(mods::Event::ItemUse { item: pid, target: None });
+        if Explosive::inactive(pid).is_some() {
+            let fid = {
+                let world = self.world.borrow();
+                let itemo = world.objects().get(item);
+                let proto = itemo.proto().unwrap();
+                proto.sub.as_item().unwrap().inventory_fid.unwrap_or(itemo.fid)
+            };
+            assert!(self.explosive_timer.is_none());
+            self.explosive_timer = Some((item, MoveWindow::show_timer(fid, ui)));
+            return;
+        }
+        if pid == ProtoId::GEIGER_COUNTER || pid == ProtoId::ACTIVE_GEIGER_COUNTER {
+            self.toggle_geiger_counter(item, ui);
+            return;
+        }
+        if self.world.borrow().objects().get(item).item_kind() == Some(ItemKind::Drug) {
+            let dude = self.world.borrow().objects().dude();
+            self.take_drug(dude, item, ui);
+            return;
+        }
+
+        let (user, script) = {
+            let world = self.world.borrow();
+            let objs = world.objects();
+            (objs.owner_of(item).unwrap_or_else(|| objs.dude()), objs.get(item).script)
+        };
+        if let Some((sid, _)) = script {
+            let world = &mut self.world.borrow_mut();
+            let r = self.scripts.execute_predefined_proc(sid, PredefinedProc::Use,
+                &mut script::Context {
+                    world,
+                    obj_sequencer: &mut self.obj_sequencer,
+                    dialog: &mut self.dialog,
+                    ui,
+                    message_panel: self.message_panel,
+                    map_id: self.map_id.unwrap(),
+                    source_obj: Some(user),
+                    target_obj: Some(item),
+                    skill: None,
+                    rpg: &mut self.rpg,
+                });
+            if let Some(r) = r {
+                // The item script does the rest, e.g. holodisk scripts set the holodisk var.
+                r.assert_no_suspend();
+                return;
+            }
+        }
+        if let Some(global_var) = self.pipboy.holodisk_global_var(pid) {
+            debug!("adding holodisk {:?} to Pip-Boy archives", pid);
+            if let Some(v) = self.scripts.vars.global_vars.get_mut(global_var) {
+                *v = 1;
+            }
+        }
+    }
+
+    // item_m_turn_on, item_m_turn_off
+    fn toggle_geiger_counter(&mut self, item: object::Handle, ui: &mut Ui) {
+        let msgs = {
+            let world = &mut *self.world.borrow_mut();
+            let objs = world.objects_mut();
+            let owner = unwrap_or_return!(objs.owner_of(item), Some);
+            let (pid, hands, name) = {
+                let itemo = objs.get(item);
+                let proto = itemo.proto().unwrap();
+                (proto.id(), itemo.flags & (Flag::LeftHand | Flag::RightHand),
+                    proto.name().map(|s| s.to_owned()).unwrap_or_default())
+            };
+            let on = pid == ProtoId::GEIGER_COUNTER;
+            let new_pid = if on {
+                ProtoId::ACTIVE_GEIGER_COUNTER
+            } else {
+                ProtoId::GEIGER_COUNTER
+            };
+            objs.remove_from_inventory(owner, item, 1);
+            let toggled = objs.create(None, Some(self.proto_db.proto(new_pid).unwrap()),
+                None, None).handle();
+            objs.get_mut(toggled).flags.insert(hands);
+            objs.move_into_inventory(owner, toggled, 1);
+
+            let mut msgs = Vec::new();
+            if owner == objs.dude() {
+                let msg_id = if on { MSG_X_IS_ON } else { MSG_X_IS_OFF };
+                if let Some(m) = self.proto_db.messages().get(msg_id) {
+                    msgs.push(format::format(&m.text, &[(&name).into()]));
+                }
+                if on {
+                    msgs.extend(radiation::geiger_reading(owner, objs, &self.rpg));
+                }
+            }
+            msgs
+        };
+        for msg in &msgs {
+            self.push_message(msg, ui);
+        }
+        self.inventory.sync(&self.rpg, ui);
+    }
+
+    // item_d_take_drug
+    fn take_drug(&mut self, critter: object::Handle, item: object::Handle, ui: &mut Ui) {
+        {
+            let world = &mut *self.world.borrow_mut();
+            let owner = unwrap_or_return!(world.objects().owner_of(item), Some);
+            let pid = unwrap_or_return!(world.objects().get(item).proto_id(), Some);
+            {
+                let proto = unwrap_or_return!(self.proto_db.proto(pid).ok(), Some);
+                let proto = proto.borrow();
+                let drug = unwrap_or_return!(proto.sub.as_item().and_then(|i| i.sub.as_drug()),
+                    Some);
+                debug!("{:?} takes {:?}", critter, pid);
+                let now = world.game_time;
+                world.drug_effects.take(now, critter, drug);
+            }
+            world.objects_mut().remove_from_inventory(owner, item, 1);
+        }
+        self.inventory.sync(&self.rpg, ui);
+    }
+
+    // obj_use_explosive
+    fn arm_explosive(&mut self, item: object::Handle, seconds: u32, ui: &mut Ui) {
+        {
+            let world = &mut *self.world.borrow_mut();
+            let pid = world.objects().get(item).proto_id();
+            let explosive = unwrap_or_return!(pid.and_then(Explosive::inactive), Some);
+            let dude = world.objects().dude();
+
+            let roll = if self.rpg.has_perk(Perk::DemolitionExpert, ProtoId::DUDE) {
+                RollCheckResult::Success
+            } else {
+                let roll_checker = world.game_time.roll_checker(Stream::Misc);
+                self.rpg.roll_check_skill(Skill::Traps, 0, roll_checker,
+                    &world.objects().get(dude), world.objects()).0
+            };
+            let delay = explosive::timer_delay(seconds, roll);
+            debug!("armed {:?} for {} s (timer set to {} s, {:?})", explosive.pid, delay, seconds,
+                roll);
+
+            // Only one item of the stack is armed. Armed explosives don't stack so each keeps its
+            // own timer.
+            let objs = world.objects_mut();
+            objs.remove_from_inventory(dude, item, 1);
+            let armed = objs.create(None, Some(self.proto_db.proto(explosive.active_pid).unwrap()),
+                None, None).handle();
+            objs.move_into_inventory(dude, armed, 1);
+
+            let time = world.game_time.add_decis(delay * 10);
+            world.explosive_timers.add(time, armed, explosive);
+        }
+
+        self.inventory.sync(&self.rpg, ui);
+    }
+
+    /// Explodes the armed explosives whose timers ran out.
+    // queue_process
+    fn fire_explosive_timers(&mut self, ui: &mut Ui) {
+        let timers = {
+            let mut world = self.world.borrow_mut();
+            let now = world.game_time;
+            world.explosive_timers.take_due(now)
+        };
+        for timer in timers {
+            self.explode_item(timer.obj, timer.data, ui);
+        }
+    }
+
+    // queue_do_explosion
+    fn explode_item(&mut self, item: object::Handle, explosive: Explosive, ui: &mut Ui) {
+        let pos = {
+            let world = &mut *self.world.borrow_mut();
+            let objs = world.objects();
+            if !objs.contains(item) {
+                return;
+            }
+            let pos = objs.get(objs.top_owner(item)).try_pos();
+            world.destroy_object(item);
+            pos
+        };
+        if let Some(pos) = pos {
+            self.explode(pos, explosive.min_damage, explosive.max_damage, ui);
+        }
+        self.inventory.sync(&self.rpg, ui);
+    }
+
+    // action_explode
+    fn explode(&mut self, pos: EPoint, min_damage: i32, max_damage: i32, ui: &mut Ui) {
+        debug!("explosion at {:?}", pos);
+        let objs = explosive::objects_in_radius(self.world.borrow().objects(), pos);
+        for obj in objs {
+            let mut corpse_destroyed = false;
+            let script = {
+                let world = self.world.borrow();
+                let objs = world.objects();
+                let (damage, dead) = {
+                    let objo = objs.get(obj);
+                    match objo.sub.as_critter() {
+                        Some(critter) => {
+                            let damage = random(Stream::Combat, min_damage, max_damage);
+                            let damage = explosive::critter_damage(damage, &objo, objs, &self.rpg);
+                            (Some(damage), critter.is_dead())
+                        }
+                        None => (None, false),
+                    }
+                };
+                if dead {
+                    corpse_destroyed = obj != objs.dude()
+                        && death::destroys_corpse(damage.unwrap(), self.violence_level);
+                } else if let Some(damage) = damage {
+                    let killed = {
+                        let mut objo = objs.get_mut(obj);
+                        let critter = objo.sub.as_critter_mut().unwrap();
+                        critter.hit_points -= damage;
+                        debug!("explosion damaged {:?} for {} hit points", obj, damage);
+                        critter.hit_points <= 0
+                    };
+                    if killed {
+                        let anim = {
+                            let objo = objs.get(obj);
+                            let from_front = death::is_hit_from_front(objo.pos().point,
+                                objo.direction, pos.point);
+                            death::pick_death_anim(DamageKind::Explosion, damage, from_front,
+                                self.violence_level)
+                        };
+                        death::kill_critter(obj, anim, objs, &mut self.obj_sequencer);
+                    }
+                }
+                objs.get(obj).script
+            };
+            if corpse_destroyed {
+                self.destroy_corpse(obj);
+                continue;
+            }
+
+            // Breakable scenery destroys itself in damage_p_proc.
+            if let Some((sid, _)) = script {
+                let world = &mut self.world.borrow_mut();
+                if let Some(r) = self.scripts.execute_predefined_proc(
+                    sid,
+                    PredefinedProc::Damage,
+                    &mut script::Context {
+                        world,
+                        obj_sequencer: &mut self.obj_sequencer,
+                        dialog: &mut self.dialog,
+                        ui,
+                        message_panel: self.message_panel,
+                        map_id: self.map_id.unwrap(),
+                        source_obj: None,
+                        target_obj: Some(obj),
+                        skill: None,
+                        rpg: &mut self.rpg,
+                    },
+                ) {
+                    r.assert_no_suspend();
+                }
+            }
+        }
+    }
+
+    // action_use_an_item_on_object
+    fn action_use_item_on(
+        &mut self,
+        user: object::Handle,
+        item: object::Handle,
+        target: object::Handle,
+    ) {
+        let world = self.world.borrow();
+        let objs = world.objects();
+        let usero = objs.get(user);
+        let targeto = objs.get(target);
+
+        let seq = Chain::new();
+
+        let move_anim = if usero.distance(&targeto).unwrap() < 5 {
+            CritterAnim::Walk
+        } else {
+            CritterAnim::Running
+        };
+        seq.control()
+            .cancellable(Move::new(user, PathTo::Object(target), move_anim));
+
+        let use_anim = if targeto.is_critter_prone() {
+            CritterAnim::MagicHandsGround
+        } else {
+            CritterAnim::MagicHandsMiddle
+        };
+        // FIXME must call check_next_to() before running this animation
+        seq.control()
+            .cancellable(FrameAnim::new(
+                user,
+                FrameAnimOptions {
+                    anim: Some(use_anim),
+                    ..Default::default()
+                },
+            ))
+            .cancellable(PushEvent::new(sequence::Event::UseItemOn {
+                user,
+                item,
+                target,
+            }))
+            .finalizing(Stand::new(user));
+
+        self.obj_sequencer.replace(user, seq);
+    }
+
+    // obj_use_item_on
+    fn use_item_on(
+        &mut self,
+        user: object::Handle,
+        item: object::Handle,
+        target: object::Handle,
+        ui: &mut Ui,
+    ) {
+        let pid = self.world.borrow().objects().get(item).proto_id();
+        if let Some(pid) = pid {
+            let target = self.world.borrow().objects().get(target).proto_id();
+            self.run_mod_hook(mods::Event::ItemUse { item: pid, target });
+        }
+
+        // protinst_use_item_on: tools are used through the corresponding skill.
+        if let Some(skill) = pid.and_then(tool_skill) {
+            self.use_skill_on(skill, user, target, ui);
+            return;
+        }
+
+        let script_overrides = {
+            let world = &mut self.world.borrow_mut();
+            let script = world.objects().get(target).script;
+            if let Some((sid, _)) = script {
+                self.scripts
+                    .execute_predefined_proc(
+                        sid,
+                        PredefinedProc::UseObjOn,
+                        &mut script::Context {
+                            world,
+                            obj_sequencer: &mut self.obj_sequencer,
+                            dialog: &mut self.dialog,
+                            ui,
+                            message_panel: self.message_panel,
+                            map_id: self.map_id.unwrap(),
+                            source_obj: Some(user),
+                            // Returned by obj_being_used_with().
+                            target_obj: Some(item),
+                            skill: None,
+                            rpg: &mut self.rpg,
+                        },
+                    )
+                    .map(|r| r.assert_no_suspend().script_overrides)
+                    .unwrap_or(false)
+            } else {
+                false
+            }
+        };
+
+        if !script_overrides {
+            let (is_drug, target_is_critter) = {
+                let world = self.world.borrow();
+                let objs = world.objects();
+                (objs.get(item).item_kind() == Some(ItemKind::Drug),
+                    objs.get(target).kind() == EntityKind::Critter)
+            };
+            if is_drug && target_is_critter {
+                self.take_drug(target, item, ui);
+                return;
+            }
+        }
+
+        if !script_overrides && user == self.world.borrow().objects().dude() {
+            let msgs = self.proto_db.messages();
+            self.push_message(&msgs.get(MSG_THAT_DOES_NOTHING).unwrap().text, ui);
+        }
+    }
+
+    fn use_door(&mut self, user: object::Handle, door: object::Handle, ui: &mut Ui) {
+        // Using the door while it's opening or closing would leave it in a state inconsistent
+        // with its frame.
+        if self.obj_sequencer.is_running(door) {
+            return;
+        }
+
+        let world = &mut self.world.borrow_mut();
+
+        let script = {
+            let dooro = world.objects().get(door);
+            if dooro.is_locked().unwrap() {
+                Self::play_open_sfx(&mut self.sfx, &dooro, OpenAction::Locked);
+                if dooro.frame_idx == 0 {
+                    let seq = Chain::new();
+                    seq.control().cancellable(Rattle::new(door, LOCKED_DOOR_RATTLE_FRAMES));
+                    self.obj_sequencer.replace(door, seq);
+                }
+            }
+            dooro.script
+        };
+
+        if let Some((sid, _)) = script {
+            let script_overrides = self
+                .scripts
+                .execute_predefined_proc(
+                    sid,
+                    PredefinedProc::Use,
+                    &mut script::Context {
+                        world,
+                        obj_sequencer: &mut self.obj_sequencer,
+                        dialog: &mut self.dialog,
+                        ui,
+                        message_panel: self.message_panel,
+                        map_id: self.map_id.unwrap(),
+                        source_obj: Some(user),
+                        target_obj: Some(door),
+                        skill: None,
+                        rpg: &mut self.rpg,
+                    },
+                )
+                .unwrap()
+                .assert_no_suspend()
+                .script_overrides;
+            if script_overrides {
+                return;
+            }
+        }
+
+        let dooro = world.objects().get(door);
+        let need_open = if dooro.frame_idx > 0 {
+            // Indicates the door is open
+            if world.objects().has_blocker_at(dooro.pos(), None) {
+                let msgs = self.proto_db.messages();
+                self.push_message(&msgs.get(MSG_DOORWAY_SEEMS_TO_BE_BLOCKED).unwrap().text, ui);
+                return;
+            }
+            false
+        } else {
+            if dooro
+                .sub
+                .as_scenery()
+                .unwrap()
+                .as_door()
+                .unwrap()
+                .flags
+                .contains(DoorFlag::Open)
+            {
+                return;
+            }
+            true
+        };
+
+        let seq = Chain::new();
+        seq.control()
+            .cancellable(FrameAnim::new(
+                door,
+                FrameAnimOptions {
+                    direction: if need_open {
+                        AnimDirection::Forward
+                    } else {
+                        AnimDirection::Backward
+                    },
+                    skip: 1,
+                    ..Default::default()
+                },
+            ))
+            .finalizing(PushEvent::new(sequence::Event::SetDoorState {
+                door,
+                open: need_open,
+            }));
+
+        let action = if need_open { OpenAction::Open } else { OpenAction::Close };
+        Self::play_open_sfx(&mut self.sfx, &dooro, action);
+        self.obj_sequencer.replace(door, seq);
+    }
+
+    fn play_open_sfx(sfx: &mut Sfx, obj: &Object, action: OpenAction) {
+        if let Some(name) = sfx::open_sfx_name(obj, action) {
+            sfx.play(&name, obj.try_pos());
+        }
+    }
+
+    // set_door_open, set_door_closed, check_door_state
+    fn set_door_state(&mut self, door: object::Handle, mut open: bool) {
+        let mut world = self.world.borrow_mut();
+        if !open {
+            // A critter could have stepped into the doorway while the door was closing.
+            let pos = world.objects().get(door).pos();
+            if world.objects().has_blocker_at(pos, Some(door)) {
+                debug!("doorway of {:?} is blocked, reopening", door);
+                open = true;
+                let dooro = world.objects().get(door);
+                Self::play_open_sfx(&mut self.sfx, &dooro, OpenAction::Open);
+            }
+        }
+        {
+            {
+                let mut dooro = world.objects_mut().get_mut(door);
+                {
+                    let door = dooro.sub.as_scenery_mut().unwrap().as_door_mut().unwrap();
+                    if open {
+                        door.flags.insert(DoorFlag::Open);
+                    } else {
+                        door.flags.remove(DoorFlag::Open);
+                    }
+                }
+                if open {
+                    dooro
+                        .flags
+                        .insert(Flag::ShootThru | Flag::LightThru | Flag::NoBlock);
+                } else {
+                    dooro
+                        .flags
+                        .remove(Flag::ShootThru | Flag::LightThru | Flag::NoBlock);
+                }
+            }
+
+            world.objects_mut().set_frame(
+                door,
+                if open {
+                    SetFrame::Last
+                } else {
+                    SetFrame::Index(0)
+                },
+            );
+        }
+        world.objects_mut().rebuild_light_grid();
+    }
+
+    // is_next_to
+    fn check_next_to(&mut self, obj1: object::Handle, obj2: object::Handle, ui: &mut Ui) -> bool {
+        if self.world.borrow().objects().distance(obj1, obj2).unwrap() > 1 {
+            let msg = &self.misc_msgs.get(2000).unwrap().text;
+            self.push_message(msg, ui);
+            false
+        } else {
+            true
+        }
+    }
+
+    // action_attack
+    fn action_attack(&mut self, attacker: object::Handle, target: object::Handle, ui: &mut Ui) {
+        let seq = {
+            let world = self.world.borrow();
+            let objs = world.objects();
+            let attack = attack::Attack::new(&objs.get(attacker), AttackGroup::Primary, objs);
+
+            let range = attack.range(&objs.get(attacker), objs, &self.rpg);
+            if objs.distance(attacker, target).unwrap() > range {
+                debug!("{:?} is out of range {} of {:?}", target, range, attacker);
+                return;
+            }
+
+            if let Some(combat) = self.combat.as_mut() {
+                if combat.current() != attacker {
+                    debug!("{:?} can't attack out of its turn", attacker);
+                    return;
+                }
+                let ap = attack.action_points(objs);
+                if !combat.spend_action_points(ap, &mut self.combat_events) {
+                    debug!("{:?} doesn't have {} action points to attack", attacker, ap);
+                    return;
+                }
+            }
+
+            let seq = Chain::new();
+            seq.control().cancellable(Rotate::new(attacker, RotateTo::Object(target)));
+
+            // Take out the weapon if the critter isn't holding it already.
+            let weapon_kind = objs.get(attacker).fid.critter().map(|fid| fid.weapon());
+            if attack.weapon_kind != WeaponKind::Unarmed
+                && weapon_kind != Some(attack.weapon_kind)
+            {
+                seq.control().cancellable(FrameAnim::new(
+                    attacker,
+                    FrameAnimOptions {
+                        anim: Some(CritterAnim::TakeOut),
+                        weapon: Some(attack.weapon_kind),
+                        ..Default::default()
+                    },
+                ));
+            }
+
+            let names = if let Some(weapon) = attack.weapon {
+                sfx::weapon_sfx_names(WeaponSound::Attack, &objs.get(weapon), attack.group, None)
+            } else {
+                let fid = Self::attack_fid(&objs.get(attacker), attack);
+                sfx::character_sfx_names(fid, CharacterSound::Normal, world.frm_db())
+            };
+            if !names.is_empty() {
+                seq.control().cancellable(PushEvent::new(sequence::Event::PlaySfx {
+                    names,
+                    pos: objs.get(attacker).try_pos(),
+                }));
+            }
+
+            seq.control()
+                .cancellable(FrameAnim::new(
+                    attacker,
+                    FrameAnimOptions {
+                        anim: Some(attack.anim()),
+                        weapon: Some(attack.weapon_kind),
+                        ..Default::default()
+                    },
+                ))
+                .cancellable(PushEvent::new(sequence::Event::Attack {
+                    attacker,
+                    target,
+                    attack,
+                }))
+                .finalizing(Stand::new(attacker));
+            seq
+        };
+        self.obj_sequencer.replace(attacker, seq);
+        self.handle_combat_events(ui);
+    }
+
+    fn attack(
+        &mut self,
+        attacker: object::Handle,
+        target: object::Handle,
+        attack: attack::Attack,
+        ui: &mut Ui,
+    ) {
+        if attack.kind == AttackKind::Throw {
+            self.throw(attacker, target, attack);
+        }
+
+        {
+            let world = self.world.borrow();
+            let objs = world.objects();
+            if !objs.contains(target) || !objs.contains(attacker) {
+                return;
+            }
+            team::on_attacked(objs, &self.rpg, attacker, target);
+        }
+        self.start_combat_with(&[attacker, target], ui);
+
+        let world = self.world.borrow();
+        let objs = world.objects();
+        if !objs.contains(target) {
+            return;
+        }
+        debug!("{:?} attacked {:?} with {:?}", attacker, target, attack);
+
+        {
+            let targeto = objs.get(target);
+            let names = match attack.weapon {
+                Some(weapon) if objs.contains(weapon) => sfx::weapon_sfx_names(
+                    WeaponSound::Hit, &objs.get(weapon), attack.group, Some(&*targeto)),
+                Some(_) => Vec::new(),
+                None if objs.contains(attacker) => {
+                    let fid = Self::attack_fid(&objs.get(attacker), attack);
+                    sfx::character_sfx_names(fid, CharacterSound::Contact, world.frm_db())
+                }
+                None => Vec::new(),
+            };
+            self.sfx.play_any(&names, targeto.try_pos());
+        }
+
+        if !objs.get(target).sub.as_critter().map(|c| !c.is_dead()).unwrap_or(false) {
+            return;
+        }
+
+        // TODO hit chance modifiers, critical hits and misses, called shots.
+        let damage = {
+            let attackero = objs.get(attacker);
+            let skill = self.rpg.skill(attack.skill(objs), &attackero, objs);
+            if random(Stream::Combat, 1, 100) > skill {
+                debug!("{:?} missed {:?}", attacker, target);
+                return;
+            }
+            let damage = attack.roll_damage(&attackero, objs, &self.rpg);
+            attack::critter_damage(damage, attack.damage_kind(objs), &objs.get(target), objs,
+                &self.rpg)
+        };
+        let killed = {
+            let mut targeto = objs.get_mut(target);
+            let critter = targeto.sub.as_critter_mut().unwrap();
+            critter.hit_points -= damage;
+            debug!("{:?} hit {:?} for {} hit points", attacker, target, damage);
+            critter.hit_points <= 0
+        };
+        let from_front = {
+            let targeto = objs.get(target);
+            death::is_hit_from_front(targeto.pos().point, targeto.direction,
+                objs.get(attacker).pos().point)
+        };
+        if killed {
+            let anim = death::pick_death_anim(attack.damage_kind(objs), damage, from_front,
+                self.violence_level);
+            death::kill_critter(target, anim, objs, &mut self.obj_sequencer);
+        } else if damage > 0 {
+            // The wounded critter flinches.
+            let anim = if from_front {
+                CritterAnim::HitFromFront
+            } else {
+                CritterAnim::HitFromBack
+            };
+            let seq = Chain::new();
+            seq.control()
+                .cancellable(FrameAnim::new(
+                    target,
+                    FrameAnimOptions {
+                        anim: Some(anim),
+                        ..Default::default()
+                    },
+                ))
+                .finalizing(Stand::new(target));
+            self.obj_sequencer.replace(target, seq);
+        }
+    }
+
+    /// Throws the weapon of the `attack` at the `target`. The thrown item lands on the target's hex
+    /// or scatters around it if the Throwing skill roll fails.
+    fn throw(&mut self, attacker: object::Handle, target: object::Handle, attack: attack::Attack) {
+        let world = &mut self.world.borrow_mut();
+        let weapon = unwrap_or_return!(attack.weapon, Some);
+        if !world.objects().contains(attacker) || !world.objects().contains(weapon) {
+            return;
+        }
+        let target_pos = if world.objects().contains(target) {
+            world.objects().get(target).pos()
+        } else {
+            return;
+        };
+
+        // TODO explode grenades on landing.
+        let skill = self.rpg.skill(Skill::Throwing, &world.objects().get(attacker),
+            world.objects());
+        let pos = if random(Stream::Combat, 1, 100) <= skill {
+            target_pos
+        } else {
+            let direction = Direction::from_ordinal(
+                random(Stream::Combat, 0, Direction::len() as i32 - 1) as usize);
+            let distance = random(Stream::Combat, 1, 3) as u32;
+            EPoint::new(target_pos.elevation,
+                world.hex_grid().go_clipped(target_pos.point, direction, distance))
+        };
+        world.objects_mut().drop_from_inventory(attacker, weapon, 1, pos);
+
+        let objs = world.objects();
+        let mut attackero = objs.get_mut(attacker);
+        attackero.fid = attackero.equipped_fid(objs, &self.rpg);
+    }
+
+    /// Returns FID of the `attacker` performing the `attack`.
+    fn attack_fid(attacker: &Object, attack: attack::Attack) -> FrameId {
+        attacker.fid.critter()
+            .map(|fid| fid.with_anim(attack.anim()).with_weapon(attack.weapon_kind).into())
+            .unwrap_or(attacker.fid)
+    }
+
+    // combat_begin
+    pub fn start_combat(&mut self, ui: &mut Ui) {
+        self.start_combat_with(&[], ui);
+    }
+
+    /// Starts combat with the `initiators` or pulls the critters that became hostile into the
+    /// ongoing combat.
+    fn start_combat_with(&mut self, initiators: &[object::Handle], ui: &mut Ui) {
+        if let Some(combat) = self.combat.as_mut() {
+            combat.pull_in(self.world.borrow().objects());
+            return;
+        }
+        {
+            let world = self.world.borrow();
+
+            // Combat interrupts whatever the critters were doing.
+            let critters: Vec<_> = self.obj_sequencer.objects()
+                .filter(|&h| world.objects().get(h).fid.critter().is_some())
+                .collect();
+            for obj in critters {
+                self.obj_sequencer.cancel(obj);
+            }
+
+            self.combat = Some(Combat::new(world.objects(), &self.rpg, initiators,
+                &mut self.combat_events));
+        }
+        self.hud.set_combat_visible(ui, true);
+        self.run_mod_hook(mods::Event::CombatStart);
+        self.handle_combat_events(ui);
+    }
+
+    /// Whether combat should end automatically. The first turn is always played.
+    fn should_auto_end_combat(&self) -> bool {
+        self.combat_auto_end
+            && !self.combat.as_ref().map(|c| c.is_first_turn()).unwrap_or(false)
+            && self.should_end_combat()
+    }
+
+    fn should_end_combat(&self) -> bool {
+        let world = self.world.borrow();
+        self.combat.as_ref()
+            .map(|c| c.should_end(world.objects(), &self.rpg))
+            .unwrap_or(true)
+    }
+
+    // combat_end
+    /// Ends combat on player's request. Refused if there are hostiles around.
+    fn request_end_combat(&mut self, ui: &mut Ui) {
+        if self.should_end_combat() {
+            self.end_combat(ui);
+        } else {
+            debug!("can't end combat: there are hostiles around");
+        }
+    }
+
+    // combat_over
+    pub fn end_combat(&mut self, ui: &mut Ui) {
+        if self.combat.take().is_some() {
+            debug!("combat ended");
+            self.combat_events.clear();
+            self.hud.set_combat_visible(ui, false);
+        }
+    }
+
+    pub fn end_turn(&mut self, ui: &mut Ui) {
+        if let Some(combat) = self.combat.as_mut() {
+            let world = self.world.borrow();
+            combat.end_turn(world.objects(), &self.rpg, &mut self.combat_events);
+        }
+        self.handle_combat_events(ui);
+    }
+
+    fn handle_combat_events(&mut self, ui: &mut Ui) {
+        let dude_obj = self.world.borrow().objects().dude();
+        while !self.combat_events.is_empty() {
+            for event in std::mem::take(&mut self.combat_events) {
+                match event {
+                    combat::Event::TurnStarted { .. } if self.should_auto_end_combat() =>
+                    {
+                        self.end_combat(ui);
+                        return;
+                    }
+                    combat::Event::TurnStarted { obj, action_points } => {
+                        if obj == dude_obj {
+                            self.hud.set_turn(ui, true, self.obj_sequencer.is_running(dude_obj));
+                            self.hud.set_action_points(ui, action_points as u32, 0);
+                        } else if self.combat.as_ref()
+                            .map(|c| c.participants().contains(&dude_obj)) == Some(true)
+                        {
+                            self.hud.set_turn(ui, false, false);
+                            // TODO combat AI, for now the critter just passes its turn.
+                            let world = self.world.borrow();
+                            self.combat.as_mut().unwrap()
+                                .end_turn(world.objects(), &self.rpg, &mut self.combat_events);
+                        } else {
+                            self.combat_events.push(combat::Event::Ended);
+                        }
+                    }
+                    combat::Event::ActionPointsChanged { obj, action_points } => {
+                        if obj == dude_obj {
+                            self.hud.set_action_points(ui, action_points as u32, 0);
+                        }
+                    }
+                    combat::Event::Ended => {
+                        self.end_combat(ui);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn create_scroll_areas(rect: Rect, ui: &mut Ui) -> EnumMap<ScrollDirection, ui::Handle> {
+        let mut new = |rect, cur, curx| {
+            let win = ui.new_window(rect, None);
+            ui.new_widget(
+                win,
+                Rect::with_size(0, 0, rect.width(), rect.height()),
+                None,
+                None,
+                ScrollArea::new(
+                    cur,
+                    curx,
+                    Duration::from_millis(0),
+                    Duration::from_millis(30),
+                ),
+            )
+        };
+        use ui::Cursor::*;
+        use ScrollDirection::*;
+        enum_map! {
+            N => new(
+                Rect::new(rect.left + 1, rect.top, rect.right - 1, rect.top + 1),
+                ScrollNorth, ScrollNorthX),
+            NE => new(
                 Rect::new(rect.right - 1, rect.top, rect.right, rect.top + 1),
                 ScrollNorthEast, ScrollNorthEastX),
             E => new(
@@ -1066,32 +2316,775 @@ impl GameState {
         }
     }
 
-    fn set_dude_pos(&mut self, pos: EPoint, direction: Direction, ui: &mut Ui) {
-        let world = &mut self.world.borrow_mut();
-        let dude_objh = world.objects().dude();
-        let elevation_change = {
-            let mut dude_obj = world.objects_mut().get_mut(dude_objh);
-            dude_obj.direction = direction;
-            dude_obj.pos().elevation != pos.elevation
+    /// Fades out the screen and runs the `action`. The game is paused until the action is run.
+    /// Ignored if another action is already waiting.
+    fn run_faded(&mut self, action: FadedAction) {
+        if self.faded_action.is_some() {
+            debug!("ignoring {:?} while waiting for fade out", action);
+            return;
+        }
+        let (fade_out, done) = Fade::fade_out(FADE_DURATION).cancellable();
+        self.ui_sequencer.start(fade_out);
+        self.faded_action = Some((action, done));
+    }
+
+    /// Fades out the screen and ends the game with the death screen.
+    fn game_over(&mut self) {
+        let endings = self.fs.reader("data/enddeath.txt")
+            .and_then(|mut rd| Ok(death_ending::read_death_endings(&mut rd)?))
+            .unwrap_or_else(|e| {
+                warn!("couldn't read death endings: {}", e);
+                Vec::new()
+            });
+        let level = self.rpg.pc_stat(PCStat::Level) as u32;
+        let narrator = death::pick_narrator(&endings, &self.scripts.vars.global_vars, level);
+        info!("game over, narrator: {}", narrator);
+        self.run_faded(FadedAction::GameOver { narrator });
+    }
+
+    /// Fades out the screen and plays the endgame slideshow.
+    fn endgame_slideshow(&mut self) {
+        let endings = self.fs.reader("data/endgame.txt")
+            .and_then(|mut rd| Ok(endgame::read_endings(&mut rd)?))
+            .unwrap_or_else(|e| {
+                warn!("couldn't read endings: {}", e);
+                Vec::new()
+            });
+        let global_vars = &self.scripts.vars.global_vars;
+        let endings = endings.into_iter()
+            .filter(|e| e.is_applicable(global_vars))
+            .collect();
+        self.run_faded(FadedAction::EndgameSlideshow { endings });
+    }
+
+    // gdialog_setup_speech, lips_play_speech
+    /// Plays the talking head speech. If the subtitles are on, the reply is shown in sync with
+    /// the speech.
+    fn play_speech(&mut self, speech: dialog::Speech, ui: &mut Ui, now: Instant) {
+        let header = self.fs.reader(&speech.path)
+            .and_then(|mut rd| Ok(acm::Header::read(&mut rd)?));
+        let header = match header {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("couldn't read speech {}: {}", speech.path, e);
+                return;
+            }
+        };
+        self.sfx.play_file(speech.path);
+        if self.subtitles {
+            if let Some(dialog) = self.dialog.as_mut() {
+                dialog.play_speech_subtitles(ui, &speech.text, header.duration(), now);
+            }
+        }
+    }
+
+    /// Runs the faded action once the screen is faded out.
+    fn update_faded_action(&mut self, ui: &mut Ui, out: &mut Vec<AppEvent>) {
+        if !self.faded_action.as_ref().map(|(_, done)| done.is_done()).unwrap_or(false) {
+            return;
+        }
+        let (action, _) = self.faded_action.take().unwrap();
+        match action {
+            FadedAction::SwitchMap { map_id, pos, direction } => {
+                if let Some(name) = self.map_db.get(map_id).map(|m| m.name.clone()) {
+                    self.switch_map(&name, ui);
+                    self.set_dude_pos(pos, direction, ui);
+                    self.world.borrow_mut().sync_party_positions();
+                } else {
+                    warn!("can't switch to unknown map {}", map_id);
+                }
+            }
+            FadedAction::EndgameSlideshow { endings } => {
+                // The slideshow fades in by itself. The game fades in once it's done.
+                out.push(AppEvent::EndgameSlideshow { endings });
+                return;
+            }
+            FadedAction::GameOver { narrator } => {
+                // The death screen fades in by itself.
+                out.push(AppEvent::GameOver { narrator });
+                return;
+            }
+            FadedAction::PlayMovie { name } => {
+                // The game fades in once the movie is done.
+                out.push(AppEvent::PlayMovie { name });
+                return;
+            }
+        }
+        self.ui_sequencer.start(Fade::fade_in(FADE_DURATION));
+    }
+
+    fn set_dude_pos(&mut self, pos: EPoint, direction: Direction, ui: &mut Ui) {
+        let world = &mut self.world.borrow_mut();
+        let dude_objh = world.objects().dude();
+        let elevation_change = {
+            let mut dude_obj = world.objects_mut().get_mut(dude_objh);
+            dude_obj.direction = direction;
+            dude_obj.pos().elevation != pos.elevation
+        };
+        world.objects_mut().set_pos(dude_objh, Some(pos));
+        if elevation_change {
+            let ctx = &mut script::Context {
+                ui,
+                world,
+                obj_sequencer: &mut self.obj_sequencer,
+                dialog: &mut self.dialog,
+                message_panel: self.message_panel,
+                map_id: self.map_id.unwrap(),
+                source_obj: None,
+                target_obj: None,
+                skill: None,
+                rpg: &mut self.rpg,
+            };
+            self.scripts
+                .execute_map_procs(PredefinedProc::MapUpdate, ctx);
+        }
+        world.camera_look_at_dude();
+    }
+
+    fn update_chems_and_radiation(&mut self, ui: &mut Ui) {
+        let (mut msgs, rad) = {
+            let world = &mut *self.world.borrow_mut();
+            let msgs = world.update_drug_effects(&self.rpg);
+            let rad = self.radiation.update(world.game_time, world.elevation(), world.objects(),
+                &self.rpg);
+            (msgs, rad)
+        };
+        msgs.extend(rad.messages);
+        for msg in &msgs {
+            self.push_message(msg, ui);
+        }
+        if rad.dude_died {
+            let world = self.world.borrow();
+            let dude = world.objects().dude();
+            let anim = death::pick_death_anim(DamageKind::Radiation, 0, true,
+                self.violence_level);
+            death::kill_critter(dude, anim, world.objects(), &mut self.obj_sequencer);
+        }
+    }
+
+    /// Runs MapUpdate procs of the scripts on the map. Scripts use it to give walk orders and
+    /// other idle behavior to the critters.
+    fn map_update(&mut self, ui: &mut Ui) {
+        self.with_map_script_ctx(ui, |scripts, ctx|
+            scripts.execute_map_procs(PredefinedProc::MapUpdate, ctx));
+    }
+
+    /// Runs the scripts whose timer events are due.
+    // queue_process
+    fn fire_timer_events(&mut self, ui: &mut Ui) {
+        if self.map_id.is_none() {
+            return;
+        }
+        let now = self.world.borrow().game_time;
+        let events = self.scripts.timer_events.take_due(now);
+        if events.is_empty() {
+            return;
+        }
+        self.with_map_script_ctx(ui, |scripts, ctx| {
+            for e in events {
+                scripts.execute_timed_event(e.sid, e.info, ctx);
+            }
+        });
+    }
+
+    /// Calls `f` with the scripts and a context for the procs that aren't run on behalf of any
+    /// source or target object. Returns `None` if no map is loaded.
+    fn with_map_script_ctx<R>(&mut self, ui: &mut Ui,
+        f: impl FnOnce(&mut Scripts, &mut script::Context) -> R) -> Option<R>
+    {
+        let map_id = self.map_id?;
+        let ctx = &mut script::Context {
+            ui,
+            world: &mut self.world.borrow_mut(),
+            obj_sequencer: &mut self.obj_sequencer,
+            dialog: &mut self.dialog,
+            message_panel: self.message_panel,
+            map_id,
+            source_obj: None,
+            target_obj: None,
+            skill: None,
+            rpg: &mut self.rpg,
+        };
+        Some(f(&mut self.scripts, ctx))
+    }
+
+    /// Returns the dude's tile number and elevation.
+    fn dude_tile(&self) -> (u32, u32) {
+        let world = self.world.borrow();
+        let pos = world.objects().get(world.objects().dude()).pos();
+        (world.hex_grid().rect_to_linear_inv(pos.point).unwrap_or(0), pos.elevation)
+    }
+
+    /// Moves the dude to `pos` at once stopping the dude's movement.
+    fn teleport_dude(&mut self, pos: EPoint) {
+        let dude = self.world.borrow().objects().dude();
+        self.obj_sequencer.cancel(dude);
+        self.dude_move = None;
+        let mut world = self.world.borrow_mut();
+        world.objects_mut().reset_screen_shift(dude);
+        world.objects_mut().set_pos(dude, Some(pos));
+    }
+
+    /// Creates object with `pid` on the map. The object's script isn't instantiated.
+    fn spawn_object(&mut self, pid: ProtoId, tile: u32, elevation: u32)
+        -> Result<object::Handle, String>
+    {
+        let proto = self.proto_db.proto(pid)
+            .map_err(|e| format!("couldn't load proto {:?}: {}", pid, e))?;
+        let mut world = self.world.borrow_mut();
+        if tile as usize >= world.hex_grid().len() {
+            return Err(format!("tile out of range: {}", tile));
+        }
+        if elevation >= ELEVATION_COUNT || !world.has_elevation(elevation) {
+            return Err(format!("no such elevation: {}", elevation));
+        }
+        let pos = world.hex_grid().linear_to_rect_inv(tile).elevated(elevation);
+        Ok(world.objects_mut()
+            .create(None, Some(proto), Some(pos), Some(&self.rpg))
+            .handle())
+    }
+
+    fn run_mod_hook(&mut self, event: mods::Event) {
+        if self.mods.is_empty() {
+            return;
+        }
+        let (dude_tile, dude_elevation) = self.dude_tile();
+        let ctx = mods::Context {
+            global_vars: self.scripts.vars.global_vars.to_vec(),
+            dude_tile,
+            dude_elevation,
+        };
+        for action in self.mods.run(&event, ctx) {
+            match action {
+                mods::Action::SetGlobalVar { var, value } => {
+                    self.scripts.vars.global_vars[var] = value;
+                }
+                mods::Action::Spawn { pid, tile, elevation } => {
+                    match self.spawn_object(pid, tile, elevation) {
+                        Ok(obj) => debug!("mod spawned {:?} as {:?}", pid, obj),
+                        Err(e) => warn!("mod couldn't spawn {:?}: {}", pid, e),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Removes the corpse blown apart by explosion leaving its inventory on the ground.
+    fn destroy_corpse(&mut self, corpse: object::Handle) {
+        debug!("explosion destroyed corpse {:?}", corpse);
+        self.obj_sequencer.cancel(corpse);
+        let mut world = self.world.borrow_mut();
+        let objs = world.objects_mut();
+        let (pos, script) = {
+            let corpseo = objs.get(corpse);
+            (corpseo.pos(), corpseo.script)
+        };
+        let items: Vec<_> = objs.get(corpse).inventory.items.iter()
+            .map(|i| (i.object, i.count))
+            .collect();
+        for (item, count) in items {
+            objs.drop_from_inventory(corpse, item, count, pos);
+        }
+        objs.destroy(corpse);
+        if let Some((sid, _)) = script {
+            self.scripts.remove(sid);
+        }
+    }
+
+    fn execute_console_command(&mut self, cmd: console::Command, ui: &mut Ui)
+        -> Result<(), String>
+    {
+        use console::Command::*;
+        match cmd {
+            Appearance { art } => {
+                let (arts, current) = {
+                    let world = self.world.borrow();
+                    let dude = world.objects().get(world.objects().dude());
+                    let female = self.rpg.stat(Stat::Gender, &dude, world.objects()) == 1;
+                    let prefix = if female { "hf" } else { "hm" };
+                    let arts = self.frm_db.find_ids_with_prefix(EntityKind::Critter, prefix);
+                    (arts, dude.sub.as_critter().unwrap().dude().naked_fidx)
+                };
+                if let Some(art) = art {
+                    let idx = arts.iter()
+                        .find(|(_, name)| name.eq_ignore_ascii_case(&art))
+                        .map(|&(idx, _)| idx)
+                        .ok_or_else(|| format!("no such art for the dude: {}", art))?;
+                    self.set_dude_appearance(idx);
+                    self.inventory.sync(&self.rpg, ui);
+                    self.console.print(format!("appearance set to {}", art), ui);
+                } else {
+                    for (idx, name) in arts {
+                        let mark = if idx == current { "*" } else { " " };
+                        self.console.print(format!("{} {}", mark, name), ui);
+                    }
+                }
+            }
+            Bind { action, keys } => {
+                self.bindings.borrow_mut().set_keys(action, &keys);
+                let keys: Vec<_> = keys.iter().map(|k| k.name()).collect();
+                self.console.print(format!("{} bound to: {}", action.name(), keys.join(", ")), ui);
+                let path = self.bindings_path.as_ref()
+                    .ok_or_else(|| "no config file to save the bindings to".to_owned())?;
+                self.bindings.borrow().save(path)
+                    .map_err(|e| format!("couldn't save bindings to {}: {}", path.display(), e))?;
+            }
+            Clear => self.console.clear(ui),
+            Debug(cmd) => self.execute_debug_command(cmd, ui)?,
+            FreeCamera => {
+                let mut world = self.world.borrow_mut();
+                world.free_camera = !world.free_camera;
+                if !world.free_camera {
+                    world.camera_look_at_dude();
+                }
+                self.console.print(if world.free_camera {
+                    "free camera on: click a hex to teleport the dude there"
+                } else {
+                    "free camera off"
+                }, ui);
+            }
+            Inspect => {
+                let armed = !self.inspector.is_armed();
+                self.inspector.set_armed(armed);
+                self.console.print(if armed {
+                    "inspect mode on: click an object to inspect it"
+                } else {
+                    "inspect mode off"
+                }, ui);
+            }
+            Give { pid, count } => {
+                if pid.kind() != EntityKind::Item {
+                    return Err(format!("{:?} is not an item", pid));
+                }
+                let proto = self.proto_db.proto(pid)
+                    .map_err(|e| format!("couldn't load proto {:?}: {}", pid, e))?;
+                {
+                    let mut world = self.world.borrow_mut();
+                    let dude = world.objects().dude();
+                    let item = world.objects_mut()
+                        .create(None, Some(proto), None, Some(&self.rpg))
+                        .handle();
+                    world.objects_mut().move_into_inventory(dude, item, count);
+                }
+                self.console.print(format!("gave {} x {:?}", count, pid), ui);
+            }
+            Help => {
+                for &(_, usage) in console::COMMANDS {
+                    self.console.print(usage.as_bytes(), ui);
+                }
+            }
+            Name { name } => {
+                {
+                    let world = self.world.borrow();
+                    let dude = world.objects().dude();
+                    world.objects().get_mut(dude).sub.as_critter_mut().unwrap()
+                        .dude_mut().name = name.clone().into();
+                }
+                self.inventory.sync(&self.rpg, ui);
+                self.console.print(format!("dude renamed to {}", name), ui);
+            }
+            NoClip => {
+                let mut world = self.world.borrow_mut();
+                let noclip = !world.objects().is_noclip();
+                world.objects_mut().set_noclip(noclip);
+                self.console.print(if noclip { "noclip on" } else { "noclip off" }, ui);
+            }
+            Overlay { overlay } => {
+                let mut world = self.world.borrow_mut();
+                if let Some(overlay) = overlay {
+                    world.debug_overlays.toggle(overlay);
+                    let on = world.debug_overlays.contains(overlay);
+                    self.console.print(format!("{} overlay {}", overlay.name(),
+                        if on { "on" } else { "off" }), ui);
+                } else if world.debug_overlays.is_empty() {
+                    self.console.print("no active overlays", ui);
+                } else {
+                    let names: Vec<_> = world.debug_overlays.iter().map(|o| o.name()).collect();
+                    self.console.print(format!("active overlays: {}", names.join(", ")), ui);
+                }
+            }
+            KillAll => {
+                let mut count = 0;
+                {
+                    let world = self.world.borrow();
+                    let objs = world.objects();
+                    let critters: Vec<_> = world.critters().except(objs.dude()).alive().collect();
+                    let anim = death::pick_death_anim(DamageKind::Melee, 0, true,
+                        self.violence_level);
+                    for obj in critters {
+                        death::kill_critter(obj, anim, objs, &mut self.obj_sequencer);
+                        count += 1;
+                    }
+                }
+                self.console.print(format!("killed {} critters", count), ui);
+            }
+            Language { language } => {
+                if let Some(language) = language {
+                    self.set_language(&language)
+                        .map_err(|e| format!("couldn't switch to {}: {}", language, e))?;
+                }
+                self.console.print(format!("language is {}", self.loc.language()), ui);
+            }
+            Reveal => {
+                ui.widget_mut::<WorldView>(self.world_view).roof_visible = false;
+                let mut world = self.world.borrow_mut();
+                world.ambient_light = 0x10000;
+                let objs = world.objects();
+                for h in objs.iter() {
+                    objs.get_mut(h).flags.insert(Flag::Seen);
+                }
+            }
+            SetGlobalVar { var, value } => {
+                let global_vars = &mut self.scripts.vars.global_vars;
+                let len = global_vars.len();
+                let v = global_vars.get_mut(var)
+                    .ok_or_else(|| format!("global var out of range 0..{}: {}", len, var))?;
+                self.console.print(format!("GVAR {}: {} -> {}", var, *v, value), ui);
+                *v = value;
+            }
+            Spawn { pid, tile } => {
+                let (dude_tile, elevation) = self.dude_tile();
+                let obj = self.spawn_object(pid, tile.unwrap_or(dude_tile), elevation)?;
+                self.console.print(format!("spawned {:?} as {:?}", pid, obj), ui);
+            }
+            Teleport { tile, elevation } => {
+                let pos = {
+                    let world = self.world.borrow();
+                    if tile as usize >= world.hex_grid().len() {
+                        return Err(format!("tile out of range: {}", tile));
+                    }
+                    let dude = world.objects().dude();
+                    let mut pos = world.objects().get(dude).pos();
+                    pos.point = world.hex_grid().linear_to_rect_inv(tile);
+                    if let Some(elevation) = elevation {
+                        if elevation >= ELEVATION_COUNT || !world.has_elevation(elevation) {
+                            return Err(format!("no such elevation: {}", elevation));
+                        }
+                        pos.elevation = elevation;
+                    }
+                    pos
+                };
+                self.teleport_dude(pos);
+                self.world.borrow_mut().camera_look_at_dude();
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_debug_command(&mut self, cmd: DebugCommand, ui: &mut Ui) -> Result<(), String> {
+        match cmd {
+            DebugCommand::Toggle => if self.script_debugger.is_visible() {
+                self.script_debugger.hide(ui);
+            } else {
+                self.console.hide(ui);
+                self.show_script_debugger(ui);
+            }
+            DebugCommand::Scripts => {
+                let attached = self.scripts.debugger_sid();
+                let lines: Vec<_> = self.scripts.instances().into_iter()
+                    .map(|(sid, name)| format!("0x{:08x} {}{}", sid.pack(), name,
+                        if Some(sid) == attached { " (attached)" } else { "" }))
+                    .collect();
+                for line in lines {
+                    self.console.print(line, ui);
+                }
+            }
+            DebugCommand::Attach { sid } => {
+                // Lets the script suspended by the previous attachment run.
+                self.detach_script_debugger(ui);
+                let name = self.scripts.attach_debugger(sid)
+                    .ok_or_else(|| format!("no such script: {:?}", sid))?
+                    .to_owned();
+                self.console.print(format!("attached to {} {:?}", name, sid), ui);
+            }
+            DebugCommand::Detach => self.detach_script_debugger(ui),
+            DebugCommand::AddBreakpoint(breakpoint) => {
+                let msg = format!("breakpoint on {}", breakpoint);
+                if !self.scripts.vm().debugger().borrow_mut().add_breakpoint(breakpoint) {
+                    return Err(format!("{} already exists", msg));
+                }
+                self.console.print(msg, ui);
+            }
+            DebugCommand::ClearBreakpoints => {
+                self.scripts.vm().debugger().borrow_mut().clear_breakpoints();
+            }
+        }
+        Ok(())
+    }
+
+    fn inspector_lines(&self, obj: object::Handle) -> Vec<inspector::Line> {
+        let world = self.world.borrow();
+        let objs = world.objects();
+        let objo = objs.get(obj);
+        let script = objo.script.and_then(|(sid, _)| {
+            let script = self.scripts.get(sid)?;
+            let name = self.scripts.instances().into_iter()
+                .find(|&(s, _)| s == sid)
+                .map(|(_, name)| name)?;
+            Some((script, name))
+        });
+        inspector::describe(&objo, objs, script, self.obj_sequencer.is_running(obj))
+    }
+
+    fn edit_inspected(&mut self, obj: object::Handle, field: inspector::Field, value: &str)
+        -> Result<(), String>
+    {
+        let v = console::parse_int(value, i32::min_value() as i64, u32::max_value() as i64)?;
+        let mut world = self.world.borrow_mut();
+        match field {
+            inspector::Field::Fid => {
+                let fid = FrameId::from_packed(v as u32)
+                    .filter(|&fid| self.frm_db.exists(fid))
+                    .ok_or_else(|| format!("no such FID: {}", value))?;
+                world.objects().get_mut(obj).fid = fid;
+                world.objects_mut().set_frame(obj, SetFrame::Index(0));
+            }
+            inspector::Field::Flags => {
+                world.objects().get_mut(obj).flags = BitFlags::from_bits(v as u32)
+                    .map_err(|_| format!("unknown flags: {}", value))?;
+            }
+            inspector::Field::LocalVar(i) => {
+                let sid = world.objects().get(obj).script
+                    .map(|(sid, _)| sid)
+                    .ok_or("object has no script")?;
+                let script = self.scripts.get_mut(sid).ok_or("script is not instantiated")?;
+                let var = script.local_vars.get_mut(i)
+                    .ok_or_else(|| format!("no such LVAR: {}", i))?;
+                *var = v as i32;
+            }
+        }
+        Ok(())
+    }
+
+    fn show_script_debugger(&mut self, ui: &mut Ui) {
+        let status = self.script_debugger_status();
+        self.script_debugger.show(status, self.scripts.debug_snapshot(), ui);
+    }
+
+    fn handle_script_debugger_command(&mut self, cmd: ScriptDebuggerCommand, ui: &mut Ui) {
+        match cmd {
+            ScriptDebuggerCommand::Continue => self.resume_debugged_script(false, ui),
+            ScriptDebuggerCommand::Hide => {
+                self.resume_debugged_script(false, ui);
+                self.script_debugger.hide(ui);
+                return;
+            }
+            ScriptDebuggerCommand::Step => self.resume_debugged_script(true, ui),
+        }
+        self.show_script_debugger(ui);
+    }
+
+    fn detach_script_debugger(&mut self, ui: &mut Ui) {
+        let map_id = if let Some(v) = self.map_id {
+            v
+        } else {
+            return;
+        };
+        let world = &mut self.world.borrow_mut();
+        self.scripts.detach_debugger(&mut script::Context {
+            world,
+            obj_sequencer: &mut self.obj_sequencer,
+            dialog: &mut self.dialog,
+            message_panel: self.message_panel,
+            ui,
+            map_id,
+            source_obj: None,
+            target_obj: None,
+            skill: None,
+            rpg: &mut self.rpg,
+        });
+    }
+
+    /// Resumes the script suspended by the VM debugger. If `step` is `true` only a single
+    /// instruction is executed.
+    fn resume_debugged_script(&mut self, step: bool, ui: &mut Ui) {
+        let map_id = if let Some(v) = self.map_id {
+            v
+        } else {
+            return;
         };
-        world.objects_mut().set_pos(dude_objh, Some(pos));
-        if elevation_change {
-            let ctx = &mut script::Context {
-                ui,
-                world,
-                obj_sequencer: &mut self.obj_sequencer,
-                dialog: &mut self.dialog,
-                message_panel: self.message_panel,
-                map_id: self.map_id.unwrap(),
-                source_obj: None,
-                target_obj: None,
-                skill: None,
-                rpg: &mut self.rpg,
-            };
-            self.scripts
-                .execute_map_procs(PredefinedProc::MapUpdate, ctx);
+        let world = &mut self.world.borrow_mut();
+        self.scripts.resume_debugged(step, &mut script::Context {
+            world,
+            obj_sequencer: &mut self.obj_sequencer,
+            dialog: &mut self.dialog,
+            message_panel: self.message_panel,
+            ui,
+            map_id,
+            source_obj: None,
+            target_obj: None,
+            skill: None,
+            rpg: &mut self.rpg,
+        });
+    }
+
+    fn script_debugger_status(&self) -> Vec<String> {
+        let attached = self.scripts.debugger_sid()
+            .and_then(|sid| self.scripts.instances().into_iter().find(|&(s, _)| s == sid))
+            .map(|(sid, name)| format!("attached to {} {:?}", name, sid))
+            .unwrap_or_else(|| "not attached".into());
+        let debugger = self.scripts.vm().debugger().borrow();
+        let breakpoints: Vec<_> = debugger.breakpoints().iter()
+            .map(|b| b.to_string())
+            .collect();
+        vec![
+            attached,
+            format!("breakpoints: {}",
+                if breakpoints.is_empty() { "-".into() } else { breakpoints.join(", ") }),
+        ]
+    }
+
+    fn is_game_window_visible(&self) -> bool {
+        self.skilldex.is_visible()
+            || self.pipboy.is_visible()
+            || self.automap.is_visible()
+            || self.inventory.is_visible()
+            || self.loot.is_some()
+            || self.dialog.is_some()
+            || self.console.is_visible()
+            || self.script_debugger.is_visible()
+            || self.inspector.is_visible()
+    }
+
+    /// Handles the action bound to a pressed key. Returns `false` if the action isn't handled
+    /// by the game state.
+    fn handle_key_action(&mut self, action: bindings::Action, ui: &mut Ui) -> bool {
+        use bindings::Action::*;
+        match action {
+            ScrollNorth | ScrollEast | ScrollSouth | ScrollWest => {
+                let dir = match action {
+                    ScrollNorth => ScrollDirection::N,
+                    ScrollEast => ScrollDirection::E,
+                    ScrollSouth => ScrollDirection::S,
+                    ScrollWest => ScrollDirection::W,
+                    _ => unreachable!(),
+                };
+                self.world.borrow_mut().scroll(dir, 1);
+            }
+            Attack => if !self.is_game_window_visible() {
+                self.start_combat(ui);
+                if self.combat.is_some() {
+                    ui.widget_mut::<WorldView>(self.world_view).enter_attack_target_pick_mode();
+                }
+            }
+            ToggleActiveHand | CycleItemMode => {
+                // TODO needs the active item in the hud.
+                debug!("{} is not implemented", action.name());
+            }
+            Inventory => if !self.is_game_window_visible() {
+                self.inventory.show(&self.rpg, ui, &mut self.ui_sequencer);
+            }
+            Skilldex => if !self.is_game_window_visible() {
+                self.show_skilldex(ui, None);
+            }
+            Pipboy => if !self.is_game_window_visible() {
+                self.pipboy.handle(PipboyCommand::Show, ui, &self.scripts.vars.global_vars);
+            }
+            Sneak | Lockpick | Steal | Traps | FirstAid | Doctor | Science | Repair => {
+                if !self.is_game_window_visible() {
+                    let skill = match action {
+                        Sneak => skilldex::Skill::Sneak,
+                        Lockpick => skilldex::Skill::Lockpick,
+                        Steal => skilldex::Skill::Steal,
+                        Traps => skilldex::Skill::Traps,
+                        FirstAid => skilldex::Skill::FirstAid,
+                        Doctor => skilldex::Skill::Doctor,
+                        Science => skilldex::Skill::Science,
+                        Repair => skilldex::Skill::Repair,
+                        _ => unreachable!(),
+                    };
+                    ui.widget_mut::<WorldView>(self.world_view)
+                        .enter_skill_target_pick_mode(skill.into());
+                }
+            }
+            EndTurn => if self.combat.is_some() {
+                self.end_turn(ui);
+            }
+            EndCombat => if self.combat.is_some() {
+                self.request_end_combat(ui);
+            }
+            ToggleConsole => if self.console.is_visible() {
+                self.console.hide(ui);
+            } else if !self.is_game_window_visible() {
+                let keys = self.bindings.borrow().keys(ToggleConsole);
+                self.console.show(ui, keys);
+            }
+            CenterOnDude => self.world.borrow_mut().camera_look_at_dude(),
+            ToggleRoof => {
+                let mut wv = ui.widget_mut::<WorldView>(self.world_view);
+                wv.roof_visible = !wv.roof_visible;
+            }
+            Pause => self.user_paused = !self.user_paused,
+            ElevationUp => {
+                let mut world = self.world.borrow_mut();
+                let dude_obj = world.objects().dude();
+                let new_pos = {
+                    let obj = world.objects().get_mut(dude_obj);
+                    let mut new_pos = obj.pos();
+                    new_pos.elevation += 1;
+                    while new_pos.elevation < ELEVATION_COUNT
+                        && !world.has_elevation(new_pos.elevation)
+                    {
+                        new_pos.elevation += 1;
+                    }
+                    new_pos
+                };
+                if new_pos.elevation < ELEVATION_COUNT && world.has_elevation(new_pos.elevation) {
+                    world.objects_mut().set_pos(dude_obj, Some(new_pos));
+                }
+            }
+            ElevationDown => {
+                let mut world = self.world.borrow_mut();
+                let dude_obj = world.objects().dude();
+                let new_pos = {
+                    let obj = world.objects().get_mut(dude_obj);
+                    let mut new_pos = obj.pos();
+                    if new_pos.elevation > 0 {
+                        new_pos.elevation -= 1;
+                        while new_pos.elevation > 0 && !world.has_elevation(new_pos.elevation) {
+                            new_pos.elevation -= 1;
+                        }
+                    }
+                    new_pos
+                };
+                if world.has_elevation(new_pos.elevation) {
+                    world.objects_mut().set_pos(dude_obj, Some(new_pos));
+                }
+            }
+            AmbientLightDown => {
+                let mut world = self.world.borrow_mut();
+                world.ambient_light = cmp::max(world.ambient_light as i32 - 1000, 0) as u32;
+            }
+            AmbientLightUp => {
+                let mut world = self.world.borrow_mut();
+                world.ambient_light = cmp::min(world.ambient_light + 1000, 0x10000);
+            }
+            Quit => return self.confirm_quit(ui),
+            ToggleDebugInfo => return false,
         }
-        world.camera_look_at_dude();
+        true
+    }
+
+    /// Asks whether to quit. Returns `false` if the question can't be shown so the app quits
+    /// right away.
+    // game_quit_with_confirm
+    fn confirm_quit(&mut self, ui: &mut Ui) -> bool {
+        if self.quit_confirm.is_some() {
+            return true;
+        }
+        let dbox_msgs = match self.loc.messages("game/dbox.msg") {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("couldn't load message box messages: {}", e);
+                return false;
+            }
+        };
+        let text = self.misc_msgs.get(MSG_ARE_YOU_SURE_YOU_WANT_TO_QUIT)
+            .map(|m| m.text.clone())
+            .unwrap_or_else(BString::new);
+        self.quit_confirm = Some(MessageBox::show(message_box::Kind::YesNo, &text, &dbox_msgs,
+            ui));
+        true
     }
 
     fn show_skilldex(&mut self, ui: &mut Ui, target: Option<object::Handle>) {
@@ -1232,7 +3225,7 @@ impl GameState {
             match skill {
                 Skill::FirstAid => {
                     // TODO if !skill_use_slot_available {
-                    let msg_id = 590 + random(0, 2);
+                    let msg_id = 590 + random(Stream::Misc, 0, 2);
                     let msg = &self.rpg.skill_msgs().get(msg_id).unwrap().text;
                     self.push_message(msg, ui);
                     return;
@@ -1241,7 +3234,7 @@ impl GameState {
                 }
                 Skill::Doctor => {
                     // TODO if !skill_use_slot_available {
-                    let msg_id = 590 + random(0, 2);
+                    let msg_id = 590 + random(Stream::Misc, 0, 2);
                     let msg = &self.rpg.skill_msgs().get(msg_id).unwrap().text;
                     self.push_message(msg, ui);
                     return;
@@ -1295,115 +3288,42 @@ impl AppState for GameState {
             } => match map {
                 TargetMap::CurrentMap => {
                     self.set_dude_pos(pos, direction, ctx.ui);
+                    self.world.borrow_mut().sync_party_positions();
                 }
                 TargetMap::Map { map_id } => {
                     if self.map_id.unwrap() != map_id {
-                        let map_def = self.map_db.get(map_id).unwrap();
-                        let name = map_def.name.clone();
-                        self.switch_map(&name, ctx.ui);
+                        self.run_faded(FadedAction::SwitchMap { map_id, pos, direction });
+                    } else {
+                        self.set_dude_pos(pos, direction, ctx.ui);
+                        self.world.borrow_mut().sync_party_positions();
                     }
-                    self.set_dude_pos(pos, direction, ctx.ui);
                 }
                 TargetMap::WorldMap(k) => {
                     warn!("map exit to {:?} is not implemented", k);
                 }
             },
+            AppEvent::SlideshowDone | AppEvent::MovieDone => {
+                self.ui_sequencer.start(Fade::fade_in(FADE_DURATION));
+            }
+            AppEvent::NewGame => {
+                self.end_game(ctx.ui);
+                // The new game fades in once its first map is loaded.
+                self.ui_sequencer.start(Fade::fade_in(FADE_DURATION));
+            }
+            | AppEvent::EndgameSlideshow { .. }
+            | AppEvent::GameOver { .. }
+            | AppEvent::MainMenu
+            | AppEvent::PlayMovie { .. }
+            | AppEvent::Quit
+            => {}
         }
     }
 
     fn handle_input(&mut self, event: &SdlEvent, ui: &mut Ui) -> bool {
-        let mut world = self.world.borrow_mut();
-        match event {
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::Right),
-                ..
-            } => {
-                world.scroll(ScrollDirection::E, 1);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::Left),
-                ..
-            } => {
-                world.scroll(ScrollDirection::W, 1);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::Up),
-                ..
-            } => {
-                world.scroll(ScrollDirection::N, 1);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::Down),
-                ..
-            } => {
-                world.scroll(ScrollDirection::S, 1);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::A),
-                ..
-            } => {
-                let dude_obj = world.objects().dude();
-                let new_pos = {
-                    let obj = world.objects().get_mut(dude_obj);
-                    let mut new_pos = obj.pos();
-                    new_pos.elevation += 1;
-                    while new_pos.elevation < ELEVATION_COUNT
-                        && !world.has_elevation(new_pos.elevation)
-                    {
-                        new_pos.elevation += 1;
-                    }
-                    new_pos
-                };
-                if new_pos.elevation < ELEVATION_COUNT && world.has_elevation(new_pos.elevation) {
-                    world.objects_mut().set_pos(dude_obj, Some(new_pos));
-                }
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::Z),
-                ..
-            } => {
-                let dude_obj = world.objects().dude();
-                let new_pos = {
-                    let obj = world.objects().get_mut(dude_obj);
-                    let mut new_pos = obj.pos();
-                    if new_pos.elevation > 0 {
-                        new_pos.elevation -= 1;
-                        while new_pos.elevation > 0 && !world.has_elevation(new_pos.elevation) {
-                            new_pos.elevation -= 1;
-                        }
-                    }
-                    new_pos
-                };
-                if world.has_elevation(new_pos.elevation) {
-                    world.objects_mut().set_pos(dude_obj, Some(new_pos));
-                }
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::LeftBracket),
-                ..
-            } => {
-                world.ambient_light = cmp::max(world.ambient_light as i32 - 1000, 0) as u32;
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::RightBracket),
-                ..
-            } => {
-                world.ambient_light = cmp::min(world.ambient_light + 1000, 0x10000);
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::R),
-                ..
-            } => {
-                let mut wv = ui.widget_mut::<WorldView>(self.world_view);
-                wv.roof_visible = !wv.roof_visible;
-            }
-            SdlEvent::KeyDown {
-                keycode: Some(Keycode::P),
-                ..
-            } => {
-                self.user_paused = !self.user_paused;
-            }
-
+        if ui.is_input_disabled() {
+            return false;
+        }
+        match *event {
             SdlEvent::KeyDown {
                 keycode: Some(Keycode::LShift),
                 ..
@@ -1420,16 +3340,43 @@ impl AppState for GameState {
                 keycode: Some(Keycode::RShift),
                 ..
             } => self.shift_key_down = false,
+            SdlEvent::KeyDown {
+                keycode: Some(key),
+                ..
+            } => {
+                let action = self.bindings.borrow().action(key);
+                return action.map(|a| self.handle_key_action(a, ui)).unwrap_or(false);
+            }
             _ => return false,
         }
         true
     }
 
+    fn handle_console_command(&mut self, command: ConsoleCommand, ui: &mut Ui) {
+        if let Some(line) = self.console.handle(command, ui) {
+            match console::Command::parse(&line) {
+                Ok(Some(cmd)) => if let Err(e) = self.execute_console_command(cmd, ui) {
+                    self.console.print(e, ui);
+                }
+                Ok(None) => {}
+                Err(e) => self.console.print(e, ui),
+            }
+        }
+    }
+
     fn handle_ui_command(&mut self, command: UiCommand, ui: &mut Ui) {
         self.inventory
             .handle(command, &self.rpg, ui, &mut self.ui_sequencer);
+        self.handle_loot(command, ui);
 
         match command.data {
+            UiCommandData::ObjectPick { kind: ObjectPickKind::DefaultAction, obj: objh }
+                if self.inspector.is_armed() =>
+            {
+                self.console.hide(ui);
+                let lines = self.inspector_lines(objh);
+                self.inspector.show(objh, lines, ui);
+            }
             UiCommandData::ObjectPick { kind, obj: objh } => {
                 let actions = self.actions(objh);
                 let default_action = actions.first().map(|&(a, _)| a);
@@ -1475,34 +3422,57 @@ impl AppState for GameState {
                     ObjectPickKind::Skill(skill) => {
                         self.action_use_skill_on(skill, objh);
                     }
+                    ObjectPickKind::UseItem(item) => {
+                        let user = self.world.borrow().objects().dude();
+                        self.action_use_item_on(user, item, objh);
+                    }
+                    ObjectPickKind::Attack => {
+                        let (dude, is_critter) = {
+                            let world = self.world.borrow();
+                            let objs = world.objects();
+                            (objs.dude(), objs.get(objh).kind() == EntityKind::Critter)
+                        };
+                        if is_critter && objh != dude {
+                            self.action_attack(dude, objh, ui);
+                        }
+                    }
                 }
             }
             UiCommandData::HexPick { action, pos } => {
-                if action {
+                if action && self.world.borrow().free_camera {
+                    self.teleport_dude(pos);
+                } else if action {
                     let dude_objh = self.world.borrow().objects().dude();
 
-                    let seq = Chain::new();
-
                     let anim = if self.shift_key_down {
                         CritterAnim::Walk
                     } else {
                         CritterAnim::Running
                     };
-                    seq.control()
-                        .cancellable(Move::new(
-                            dude_objh,
-                            PathTo::Point {
-                                point: pos.point,
-                                neighbor_if_blocked: true,
-                            },
-                            anim,
-                        ))
-                        .finalizing(Stand::new(dude_objh));
-                    self.obj_sequencer.replace(dude_objh, seq);
+                    let to = PathTo::Point {
+                        point: pos.point,
+                        neighbor_if_blocked: true,
+                    };
+
+                    // If the dude is already walking, finish the current step and continue to
+                    // the new destination from the next hex.
+                    let redirected = self.dude_move.as_ref()
+                        .map(|m| m.redirect(to, anim))
+                        .unwrap_or(false);
+                    if !redirected {
+                        let move_seq = Move::new(dude_objh, to, anim);
+                        self.dude_move = Some(move_seq.redirect());
+
+                        let seq = Chain::new();
+                        seq.control()
+                            .cancellable(move_seq)
+                            .finalizing(Stand::new(dude_objh));
+                        self.obj_sequencer.replace(dude_objh, seq);
+                    }
                 } else {
                     let mut wv = ui.widget_mut::<WorldView>(self.world_view);
                     let dude_obj = self.world.borrow().objects().dude();
-                    wv.hex_cursor_style = if self
+                    wv.hex_cursor_style = if self.world.borrow().free_camera || self
                         .world
                         .borrow()
                         .objects()
@@ -1611,6 +3581,31 @@ impl AppState for GameState {
                     }
                 }
             },
+            UiCommandData::Pipboy(cmd) => {
+                self.pipboy.handle(cmd, ui, &self.scripts.vars.global_vars);
+            }
+            UiCommandData::Automap(cmd) => {
+                if cmd != AutomapCommand::Show || !self.is_game_window_visible() {
+                    let msg = self.automap.handle(cmd, &self.world.borrow(),
+                        self.rpg.misc_msgs(), ui);
+                    if let Some(msg) = msg {
+                        self.push_message(&msg, ui);
+                    }
+                }
+            }
+            UiCommandData::Console(cmd) => self.handle_console_command(cmd, ui),
+            UiCommandData::ScriptDebugger(cmd) => self.handle_script_debugger_command(cmd, ui),
+            UiCommandData::Inspector(cmd) => {
+                if let Some((field, value)) = self.inspector.handle(cmd, ui) {
+                    let obj = self.inspector.obj().unwrap();
+                    let status = match self.edit_inspected(obj, field, &value) {
+                        Ok(()) => format!("set {:?} to {}", field, value),
+                        Err(e) => e,
+                    };
+                    let lines = self.inspector_lines(obj);
+                    self.inspector.refresh(lines, status, ui);
+                }
+            }
             UiCommandData::Inventory(cmd) => match cmd {
                 inventory::Command::Hover { object } => {
                     self.dude_look_at_object(object, ui);
@@ -1628,33 +3623,116 @@ impl AppState for GameState {
                     let descr = BString::join(b'\n', &descr);
                     self.inventory.examine(object, &descr, ui);
                 }
-                Command::Hide => {}
+                inventory::Command::Action {
+                    object,
+                    action: Some(Action::UseHand),
+                } => {
+                    let can_use_on = self.world.borrow().objects().get(object).proto()
+                        .map(|p| p.can_use_on())
+                        .unwrap_or(false);
+                    if let Some(target) = self.use_item_target.take() {
+                        self.inventory.hide(ui);
+                        let user = self.world.borrow().objects().dude();
+                        self.action_use_item_on(user, object, target);
+                    } else if can_use_on {
+                        // The item is used on the target picked with the crosshair.
+                        self.inventory.hide(ui);
+                        ui.widget_mut::<WorldView>(self.world_view)
+                            .enter_item_target_pick_mode(object);
+                    } else {
+                        self.use_inventory_item(object, ui);
+                    }
+                }
+                Command::Hide => {
+                    self.use_item_target = None;
+                }
                 Command::Show => {
                     self.obj_sequencer
                         .cancel(self.world.borrow().objects().dude());
                 }
                 _ => {}
             },
-            UiCommandData::MoveWindow(_) => {}
+            UiCommandData::MoveWindow(cmd) => if let Some((item, mut win)) = self.explosive_timer.take() {
+                win.handle(command, ui);
+                if let move_window::Command::Hide { ok } = cmd {
+                    let seconds = win.value();
+                    win.hide(ui);
+                    if ok {
+                        self.arm_explosive(item, seconds, ui);
+                    }
+                } else {
+                    self.explosive_timer = Some((item, win));
+                }
+            }
+            UiCommandData::Combat(cmd) => match cmd {
+                CombatCommand::EndTurn => self.end_turn(ui),
+                CombatCommand::EndCombat => self.request_end_combat(ui),
+            },
+            UiCommandData::ItemChooser(cmd) => self.handle_item_chooser(cmd, ui),
+            // Handled in handle_loot().
+            UiCommandData::Loot(_) => {}
+            UiCommandData::TextInput(cmd) => {
+                if let Some(cmd) = self.console.input_command(command.source, cmd) {
+                    self.handle_console_command(cmd, ui);
+                }
+            }
+            UiCommandData::MessageBox(cmd) => {
+                if let Some(mbox) = self.quit_confirm.take() {
+                    if mbox.is_source(command.source, ui) {
+                        mbox.hide(ui);
+                        self.quit_requested = cmd == MessageBoxCommand::Yes;
+                    } else {
+                        self.quit_confirm = Some(mbox);
+                    }
+                }
+            }
+            UiCommandData::ScrollList(_) | UiCommandData::MainMenu(_) => {}
         }
     }
 
     fn update(&mut self, mut ctx: state::Update) {
+        if self.quit_requested {
+            self.quit_requested = false;
+            ctx.out.push(AppEvent::Quit);
+        }
+
+        if self.scripts.has_debug_suspended() && !self.script_debugger.is_visible() {
+            self.console.hide(ctx.ui);
+            self.show_script_debugger(ctx.ui);
+        }
+
         self.time.set_paused(
             self.user_paused
                 || self.scripts.can_resume()
+                || self.scripts.has_debug_suspended()
                 || self.skilldex.is_visible()
-                || self.inventory.is_visible(),
+                || self.pipboy.is_visible()
+                || self.automap.is_visible()
+                || self.inventory.is_visible()
+                || self.console.is_visible()
+                || self.script_debugger.is_visible()
+                || self.inspector.is_visible()
+                || self.item_chooser.is_some()
+                || self.loot.is_some()
+                || self.quit_confirm.is_some()
+                || self.faded_action.is_some(),
         );
 
         self.time.update(ctx.delta);
 
+        self.pipboy.sync(ctx.ui, &self.scripts.vars.global_vars);
+
+        crash::set_dude_pos(self.world.borrow().objects().dude_ref().try_pos());
+
         if self.time.is_running() {
             {
                 let mut world = self.world.borrow_mut();
                 world.update(self.time.time());
             }
+            self.fire_timer_events(ctx.ui);
+            self.fire_explosive_timers(ctx.ui);
 
+            let profile = profile::scope("sequences");
             const MAX_ITERS: u32 = 1000;
             for i in 0..MAX_ITERS {
                 assert!(
@@ -1676,12 +3754,35 @@ impl AppState for GameState {
                 }
                 self.handle_seq_events(&mut ctx);
             }
+            drop(profile);
 
             self.fidget.update(
                 self.time.time(),
                 &mut self.world.borrow_mut(),
                 &mut self.obj_sequencer,
             );
+
+            if self.combat.is_none() && self.dialog.is_none() {
+                self.wander.update(
+                    self.time.time(),
+                    &self.world.borrow(),
+                    &mut self.obj_sequencer,
+                );
+                if self.time.time() >= self.next_map_update {
+                    self.next_map_update = self.time.time() + MAP_UPDATE_INTERVAL;
+                    self.map_update(ctx.ui);
+                }
+            }
+
+            self.update_chems_and_radiation(ctx.ui);
+
+            let msg = self.automap.update(self.time.time(), &self.world.borrow(),
+                self.rpg.misc_msgs());
+            if let Some(msg) = msg {
+                self.push_message(&msg, ctx.ui);
+            }
+
+            self.ambient_sfx.update(self.time.time(), &self.world.borrow(), &mut self.sfx);
         } else {
             self.obj_sequencer.sync(&mut sequence::Sync {
                 world: &mut self.world.borrow_mut(),
@@ -1689,13 +3790,31 @@ impl AppState for GameState {
             });
         }
 
+        if let Some(combat) = self.combat.as_ref() {
+            let world = self.world.borrow();
+            let dude_obj = world.objects().dude();
+            self.hud.set_turn(ctx.ui, combat.is_dude_turn(world.objects()),
+                self.obj_sequencer.is_running(dude_obj));
+        }
+
+        let profile = profile::scope("sequences");
         self.ui_sequencer.update(&mut sequence::Update {
             time: ctx.time,
             world: &mut self.world.borrow_mut(),
             ui: ctx.ui,
             out: &mut self.seq_events,
         });
+        drop(profile);
         assert!(self.seq_events.is_empty());
+
+        self.update_faded_action(ctx.ui, ctx.out);
+
+        if let Some(speech) = self.dialog.as_mut().and_then(|d| d.take_speech()) {
+            self.play_speech(speech, ctx.ui, ctx.time);
+        }
+        if let Some(dialog) = self.dialog.as_mut() {
+            dialog.update_subtitles(ctx.ui, ctx.time);
+        }
     }
 }
 
@@ -1743,3 +3862,47 @@ struct ObjectActionMenu {
     menu: ui::Handle,
     obj: object::Handle,
 }
+
+/// Action that is run while the screen is faded out.
+#[derive(Debug)]
+enum FadedAction {
+    /// Exit to another map.
+    SwitchMap {
+        map_id: MapId,
+        pos: EPoint,
+        direction: Direction,
+    },
+    /// A script requested the endgame slideshow.
+    EndgameSlideshow {
+        endings: Vec<endgame::Ending>,
+    },
+    /// The dude died.
+    GameOver {
+        narrator: String,
+    },
+    /// A script requested a movie.
+    PlayMovie {
+        name: String,
+    },
+}
+
+/// Skill used when the tool item is used on an object.
+fn tool_skill(pid: ProtoId) -> Option<Skill> {
+    Some(match pid {
+        | ProtoId::DOCTORS_BAG
+        | ProtoId::PARAMEDICS_BAG
+        => Skill::Doctor,
+        | ProtoId::FIRST_AID_KIT
+        | ProtoId::FIELD_MEDIC_FIRST_AID_KIT
+        => Skill::FirstAid,
+        | ProtoId::LOCKPICKS
+        | ProtoId::EXPANDED_LOCKPICK_SET
+        | ProtoId::ELECTRONIC_LOCKPICKS
+        | ProtoId::ELECTRONIC_LOCKPICKS_MK2
+        => Skill::Lockpick,
+        | ProtoId::MULTI_TOOL
+        | ProtoId::SUPER_TOOL_KIT
+        => Skill::Repair,
+        _ => return None,
+    })
+}
@@ -0,0 +1,221 @@
+//! Teams of critters and who hates whom.
+//!
+//! Every critter belongs to a team (`CritterCombat::team_id`) taken from its proto or map and
+//! changed by scripts with `critter_add_trait`. Members of a team never fight each other. A
+//! critter becomes hostile when it gets an enemy: after being attacked or when a script sets up
+//! an attack with `attack_setup` or `attack_complex`. The critter then hates the whole team of its
+//! enemy, and when one of its team is attacked the team members that notice turn on the attacker
+//! too. This is how the town guards react when the dude attacks the townsfolk.
+
+use log::*;
+
+use crate::game::combat::Combat;
+use crate::game::object::{self, Objects};
+use crate::game::rpg::Rpg;
+
+/// Team of the dude and the party members.
+pub const PLAYER: i32 = 0;
+
+pub fn team(objects: &Objects, obj: object::Handle) -> Option<i32> {
+    if !objects.contains(obj) {
+        return None;
+    }
+    objects.get(obj).sub.as_critter().map(|c| c.combat.team_id)
+}
+
+/// Enemy of the `obj` if it's still on the map.
+pub fn enemy(objects: &Objects, obj: object::Handle) -> Option<object::Handle> {
+    if !objects.contains(obj) {
+        return None;
+    }
+    objects.get(obj).sub.as_critter()
+        .and_then(|c| c.combat.enemy)
+        .filter(|&h| objects.contains(h))
+}
+
+/// Whether `obj` wants to fight `other`: the critters are in different teams and the enemy of
+/// `obj` is `other` or a member of `other`'s team.
+pub fn hates(objects: &Objects, obj: object::Handle, other: object::Handle) -> bool {
+    if obj == other {
+        return false;
+    }
+    let other_team = match (team(objects, obj), team(objects, other)) {
+        (Some(t), Some(o)) if t != o => o,
+        _ => return false,
+    };
+    enemy(objects, obj)
+        .map(|e| e == other || team(objects, e) == Some(other_team))
+        .unwrap_or(false)
+}
+
+/// Whether either of the critters wants to fight the other.
+pub fn is_hostile(objects: &Objects, a: object::Handle, b: object::Handle) -> bool {
+    hates(objects, a, b) || hates(objects, b, a)
+}
+
+/// Makes `obj` hostile to `enemy`. Does nothing if they're in the same team.
+pub fn set_enemy(objects: &Objects, obj: object::Handle, enemy: object::Handle) {
+    if team(objects, obj).is_none() || team(objects, obj) == team(objects, enemy) {
+        return;
+    }
+    if let Some(c) = objects.get_mut(obj).sub.as_critter_mut() {
+        c.combat.enemy = Some(enemy);
+    }
+}
+
+// critter_stop_attacking
+/// Makes `obj` forget its enemy.
+pub fn clear_enemy(objects: &Objects, obj: object::Handle) {
+    if let Some(c) = objects.get_mut(obj).sub.as_critter_mut() {
+        c.combat.enemy = None;
+    }
+}
+
+// combatai_notify_friends
+/// Reaction to the `attacker` attacking the `target`: the target and the active members of its
+/// team on the same elevation that know about the attacker or the target become hostile to the
+/// attacker. Returns the critters that changed their enemy.
+pub fn on_attacked(objects: &Objects, rpg: &Rpg, attacker: object::Handle,
+    target: object::Handle) -> Vec<object::Handle>
+{
+    let mut r = Vec::new();
+    let target_team = match team(objects, target) {
+        Some(t) if team(objects, attacker) != Some(t) => t,
+        _ => return r,
+    };
+    let elevation = if let Some(pos) = objects.get(target).try_pos() {
+        pos.elevation
+    } else {
+        return r;
+    };
+    for &h in objects.critters(elevation) {
+        if team(objects, h) != Some(target_team) || enemy(objects, h) == Some(attacker) {
+            continue;
+        }
+        let notices = h == target
+            || (objects.get(h).sub.as_critter().map(|c| c.is_active()).unwrap_or(false)
+                && (Combat::knows_about(objects, rpg, h, attacker)
+                    || Combat::knows_about(objects, rpg, h, target)));
+        if notices {
+            set_enemy(objects, h, attacker);
+            r.push(h);
+        }
+    }
+    if !r.is_empty() {
+        debug!("{:?} attacked {:?}, team {} turned hostile: {:?}", attacker, target, target_team,
+            r);
+    }
+    r
+}
+
+// combat_begin
+/// Critters on the `elevation` that take part in combat: the `initiators`, members of the
+/// player's team and everyone hostile to any of the participants.
+pub fn combatants(objects: &Objects, elevation: u32, initiators: &[object::Handle])
+    -> Vec<object::Handle>
+{
+    let critters: Vec<_> = objects.critters(elevation).iter()
+        .cloned()
+        .filter(|&h| objects.get(h).sub.as_critter().map(|c| c.is_active()).unwrap_or(false))
+        .collect();
+    let mut r: Vec<_> = critters.iter()
+        .cloned()
+        .filter(|&h| initiators.contains(&h) || team(objects, h) == Some(PLAYER))
+        .collect();
+    let mut i = 0;
+    while i < r.len() {
+        let p = r[i];
+        for &h in &critters {
+            if !r.contains(&h) && is_hostile(objects, h, p) {
+                r.push(h);
+            }
+        }
+        i += 1;
+    }
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use enum_map::EnumMap;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::asset::{EntityKind, Stat};
+    use crate::asset::proto::{Proto, ProtoId};
+    use crate::game::world::World;
+
+    const TOWN: i32 = 1;
+    const RAIDERS: i32 = 2;
+
+    fn critter(world: &mut World, rpg: &Rpg, team_id: i32, pos: (i32, i32)) -> object::Handle {
+        let mut stats = EnumMap::new();
+        stats[Stat::Perception] = 5;
+        let proto = Proto::mock_critter(ProtoId::new(EntityKind::Critter, 2).unwrap(), stats);
+        let h = world.objects_mut().create(None, Some(Rc::new(RefCell::new(proto))),
+            Some((0, pos).into()), Some(rpg)).handle();
+        world.objects().get_mut(h).sub.as_critter_mut().unwrap().combat.team_id = team_id;
+        h
+    }
+
+    #[test]
+    fn guards_turn_on_attacker() {
+        let rpg = Rpg::mock();
+        let mut world = World::mock(Instant::now());
+        let dude = critter(&mut world, &rpg, PLAYER, (50, 50));
+        let companion = critter(&mut world, &rpg, PLAYER, (50, 52));
+        let townsman = critter(&mut world, &rpg, TOWN, (52, 50));
+        let guard = critter(&mut world, &rpg, TOWN, (55, 50));
+        let far_guard = critter(&mut world, &rpg, TOWN, (150, 150));
+        let raider = critter(&mut world, &rpg, RAIDERS, (48, 50));
+        let objects = world.objects();
+
+        assert!(!hates(objects, townsman, dude));
+        assert_eq!(combatants(objects, 0, &[dude]), vec![dude, companion]);
+
+        // Same team.
+        assert!(on_attacked(objects, &rpg, guard, townsman).is_empty());
+
+        assert_eq!(on_attacked(objects, &rpg, dude, townsman), vec![townsman, guard]);
+        assert_eq!(enemy(objects, townsman), Some(dude));
+        assert_eq!(enemy(objects, far_guard), None);
+
+        // The whole player's team is hated.
+        assert!(hates(objects, guard, dude));
+        assert!(hates(objects, guard, companion));
+        assert!(!hates(objects, dude, guard));
+        assert!(is_hostile(objects, dude, guard));
+        assert!(!hates(objects, far_guard, dude));
+        assert!(!hates(objects, raider, dude));
+
+        assert_eq!(combatants(objects, 0, &[dude]), vec![dude, companion, townsman, guard]);
+
+        // Dead critters don't fight.
+        objects.get_mut(guard).sub.as_critter_mut().unwrap().combat.damage_flags
+            .insert(object::DamageFlag::Dead);
+        assert_eq!(combatants(objects, 0, &[dude]), vec![dude, companion, townsman]);
+
+        clear_enemy(objects, townsman);
+        assert!(!hates(objects, townsman, dude));
+        assert_eq!(combatants(objects, 0, &[dude]), vec![dude, companion]);
+    }
+
+    #[test]
+    fn set_enemy_same_team() {
+        let rpg = Rpg::mock();
+        let mut world = World::mock(Instant::now());
+        let a = critter(&mut world, &rpg, TOWN, (50, 50));
+        let b = critter(&mut world, &rpg, TOWN, (51, 50));
+        let c = critter(&mut world, &rpg, RAIDERS, (52, 50));
+        let objects = world.objects();
+
+        set_enemy(objects, a, b);
+        assert_eq!(enemy(objects, a), None);
+        set_enemy(objects, a, c);
+        assert_eq!(enemy(objects, a), Some(c));
+        assert!(hates(objects, a, c));
+        assert!(!hates(objects, c, a));
+    }
+}
@@ -0,0 +1,274 @@
+//! Timed events of the scripts.
+//!
+//! Scripts schedule events with `add_timer_event` and cancel them with `rm_timer_event`. When the
+//! game time of an event comes, `timed_event_p_proc` of the script runs with the event info as the
+//! fixed param. This is how the merchants restock their boxes every day or two. The events are
+//! kept with the map scripts when the map is left, the ones that became due in the meantime fire
+//! as soon as the map is entered again.
+//!
+//! The engine keeps its own timers of objects the same way: the armed explosives and the effects
+//! of the taken drugs count down on the game time and also come when the dude is resting or the
+//! time is advanced by a script. These timers follow the objects carried by the party to the next
+//! map. The explosives left behind are kept with the map state and the overdue ones go off when
+//! the map is entered again, the drug effects of the critters left behind are dropped.
+
+use slotmap::SecondaryMap;
+
+use crate::game::GameTime;
+use crate::game::object::Handle;
+use crate::game::script::ScriptIid;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimerEvent {
+    pub time: GameTime,
+    pub sid: ScriptIid,
+    pub info: i32,
+}
+
+/// Timer events ordered by time. Events with the same time are kept in the order they were added.
+#[derive(Clone, Debug, Default)]
+pub struct TimerEvents {
+    events: Vec<TimerEvent>,
+}
+
+impl TimerEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    // queue_add
+    pub fn add(&mut self, time: GameTime, sid: ScriptIid, info: i32) {
+        let i = self.events.iter().position(|e| e.time > time).unwrap_or(self.events.len());
+        self.events.insert(i, TimerEvent { time, sid, info });
+    }
+
+    // queue_remove
+    /// Removes all events of the script.
+    pub fn remove(&mut self, sid: ScriptIid) {
+        self.events.retain(|e| e.sid != sid);
+    }
+
+    // queue_remove_this
+    /// Removes events of the script that have the `info`.
+    pub fn remove_info(&mut self, sid: ScriptIid, info: i32) {
+        self.events.retain(|e| e.sid != sid || e.info != info);
+    }
+
+    /// Removes and returns all events of the script.
+    pub fn take(&mut self, sid: ScriptIid) -> Vec<TimerEvent> {
+        let (r, rest) = self.events.drain(..).partition(|e| e.sid == sid);
+        self.events = rest;
+        r
+    }
+
+    /// Removes and returns the events that are due at `now`. Events added while processing these
+    /// will be returned by the next call even if they're due already.
+    // queue_process
+    pub fn take_due(&mut self, now: GameTime) -> Vec<TimerEvent> {
+        let i = self.events.iter().position(|e| e.time > now).unwrap_or(self.events.len());
+        self.events.drain(..i).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&TimerEvent> {
+        self.events.iter()
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&TimerEvent) -> bool) {
+        self.events.retain(f);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ObjectTimer<T> {
+    pub time: GameTime,
+    pub obj: Handle,
+    pub data: T,
+}
+
+/// Timers of objects ordered by time. Each object can have any number of timers.
+#[derive(Clone, Debug)]
+pub struct ObjectTimers<T> {
+    timers: Vec<ObjectTimer<T>>,
+}
+
+impl<T> ObjectTimers<T> {
+    pub fn new() -> Self {
+        Self {
+            timers: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn add(&mut self, time: GameTime, obj: Handle, data: T) {
+        let i = self.timers.iter().position(|e| e.time > time).unwrap_or(self.timers.len());
+        self.timers.insert(i, ObjectTimer { time, obj, data });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&ObjectTimer<T>> {
+        self.timers.iter()
+    }
+
+    // queue_find
+    pub fn contains(&self, obj: Handle) -> bool {
+        self.timers.iter().any(|e| e.obj == obj)
+    }
+
+    // queue_remove
+    /// Removes all timers of the object.
+    pub fn remove(&mut self, obj: Handle) {
+        self.timers.retain(|e| e.obj != obj);
+    }
+
+    /// Removes and returns the timers that are due at `now`.
+    pub fn take_due(&mut self, now: GameTime) -> Vec<ObjectTimer<T>> {
+        let i = self.timers.iter().position(|e| e.time > now).unwrap_or(self.timers.len());
+        self.timers.drain(..i).collect()
+    }
+
+    /// Removes and returns the timers of the objects for which `f` returns `true`.
+    pub fn take_if(&mut self, f: impl Fn(Handle) -> bool) -> Self {
+        let (timers, rest) = self.timers.drain(..).partition(|e| f(e.obj));
+        self.timers = rest;
+        Self { timers }
+    }
+
+    /// Moves all timers of `other` into `self`.
+    pub fn append(&mut self, other: Self) {
+        for e in other.timers {
+            self.add(e.time, e.obj, e.data);
+        }
+    }
+
+    /// Moves the timers to the new handles of the objects that were reinserted into the world.
+    /// Timers of the objects missing in `handle_map` are dropped.
+    pub fn remap(&mut self, handle_map: &SecondaryMap<Handle, Handle>) {
+        self.timers = self.timers.drain(..)
+            .filter_map(|mut e| {
+                e.obj = *handle_map.get(e.obj)?;
+                Some(e)
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.timers.clear();
+    }
+}
+
+impl<T> Default for ObjectTimers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+    use crate::game::script::ScriptKind;
+
+    fn t(decis: u32) -> GameTime {
+        GameTime::from_decis(decis)
+    }
+
+    #[test]
+    fn take_due() {
+        let sid1 = ScriptIid::new(ScriptKind::Item, 1);
+        let sid2 = ScriptIid::new(ScriptKind::Critter, 2);
+        let mut q = TimerEvents::new();
+        q.add(t(30), sid1, 1);
+        q.add(t(10), sid2, 2);
+        q.add(t(30), sid2, 3);
+        q.add(t(20), sid1, 4);
+
+        assert_eq!(q.take_due(t(5)), vec![]);
+        assert_eq!(q.take_due(t(20)).iter().map(|e| e.info).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(q.take_due(t(100)).iter().map(|e| e.info).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let sid1 = ScriptIid::new(ScriptKind::Item, 1);
+        let sid2 = ScriptIid::new(ScriptKind::Critter, 2);
+        let mut q = TimerEvents::new();
+        q.add(t(10), sid1, 1);
+        q.add(t(10), sid1, 2);
+        q.add(t(20), sid2, 1);
+
+        q.remove_info(sid1, 1);
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.take(sid2), vec![TimerEvent { time: t(20), sid: sid2, info: 1 }]);
+        q.remove(sid1);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn object_timers() {
+        let mut objs = SlotMap::<Handle, ()>::with_key();
+        let obj1 = objs.insert(());
+        let obj2 = objs.insert(());
+        let mut q = ObjectTimers::new();
+        q.add(t(30), obj1, 1);
+        q.add(t(10), obj2, 2);
+        q.add(t(20), obj1, 3);
+
+        assert!(q.contains(obj1));
+        assert_eq!(q.take_due(t(10)).iter().map(|e| e.data).collect::<Vec<_>>(), vec![2]);
+        assert!(!q.contains(obj2));
+
+        let new_obj1 = objs.insert(());
+        let mut handle_map = SecondaryMap::new();
+        handle_map.insert(obj1, new_obj1);
+        q.add(t(40), obj2, 4);
+        q.remap(&handle_map);
+        assert_eq!(q.len(), 2);
+        assert!(!q.contains(obj1));
+        assert_eq!(q.take_due(t(100)),
+            vec![ObjectTimer { time: t(20), obj: new_obj1, data: 3 },
+                ObjectTimer { time: t(30), obj: new_obj1, data: 1 }]);
+
+        q.add(t(50), obj2, 5);
+        q.remove(obj2);
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn object_timers_take_if_append() {
+        let mut objs = SlotMap::<Handle, ()>::with_key();
+        let obj1 = objs.insert(());
+        let obj2 = objs.insert(());
+        let mut q = ObjectTimers::new();
+        q.add(t(30), obj1, 1);
+        q.add(t(10), obj2, 2);
+        q.add(t(20), obj1, 3);
+
+        let taken = q.take_if(|h| h == obj1);
+        assert_eq!(taken.len(), 2);
+        assert!(!q.contains(obj1));
+        assert!(q.contains(obj2));
+
+        q.append(taken);
+        assert_eq!(q.take_due(t(100)).iter().map(|e| e.data).collect::<Vec<_>>(),
+            vec![2, 3, 1]);
+    }
+}
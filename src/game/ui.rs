@@ -1,6 +1,13 @@
 pub mod action_menu;
+pub mod action_points;
+pub mod automap_view;
+pub mod console;
 pub mod hud;
+pub mod inspector;
 pub mod inventory_list;
+pub mod item_chooser;
 pub mod move_window;
+pub mod quest_list;
+pub mod script_debugger;
 pub mod scroll_area;
 pub mod world;
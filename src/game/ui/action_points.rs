@@ -0,0 +1,68 @@
+use crate::asset::frame::FrameId;
+use crate::graphics::Point;
+use crate::graphics::sprite::Sprite;
+use crate::ui::*;
+
+const MAX_LIGHTS: u32 = 10;
+const LIGHT_SPACING: i32 = 9;
+
+/// The row of action point lights in the interface bar.
+pub struct ActionPoints {
+    state: Option<State>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    /// Lit green lights for available action points and yellow ones for bonus move points.
+    Lit {
+        action_points: u32,
+        bonus: u32,
+    },
+    /// All lights are red, used when it's not the dude's turn.
+    Red,
+}
+
+impl ActionPoints {
+    pub fn new() -> Self {
+        Self {
+            state: None,
+        }
+    }
+
+    // intface_update_move_points
+    pub fn set(&mut self, action_points: u32, bonus: u32) {
+        self.state = Some(State::Lit {
+            action_points: action_points.min(MAX_LIGHTS),
+            bonus: bonus.min(MAX_LIGHTS - action_points.min(MAX_LIGHTS)),
+        });
+    }
+
+    pub fn set_red(&mut self) {
+        self.state = Some(State::Red);
+    }
+
+    pub fn clear(&mut self) {
+        self.state = None;
+    }
+}
+
+impl Widget for ActionPoints {
+    fn render(&mut self, ctx: Render) {
+        let (lit, bonus, fid) = match self.state {
+            Some(State::Lit { action_points, bonus }) => (action_points, bonus, FrameId::HLGRN),
+            Some(State::Red) => (MAX_LIGHTS, 0, FrameId::HLRED),
+            None => return,
+        };
+        let top_left = ctx.base.unwrap().rect().top_left();
+        let mut draw = |i: u32, fid| {
+            Sprite::new_with_pos(fid, top_left + Point::new(i as i32 * LIGHT_SPACING, 0))
+                .render(ctx.canvas, ctx.frm_db);
+        };
+        for i in 0..lit {
+            draw(i, fid);
+        }
+        for i in lit..lit + bonus {
+            draw(i, FrameId::HLYEL);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::Rgb15;
+use crate::ui::*;
+
+/// Size of a hex on the automap in pixels.
+pub const HEX_SIZE: i32 = 2;
+
+/// Map area of the automap window. Every hex is drawn as a small square, the hex columns are
+/// not shifted so the map looks like a square grid.
+pub struct AutomapView {
+    cells: Vec<(Point, Rgb15)>,
+}
+
+impl AutomapView {
+    pub fn new() -> Self {
+        Self {
+            cells: Vec::new(),
+        }
+    }
+
+    /// Replaces the drawn hexes. Later cells are drawn over the earlier ones.
+    pub fn set_cells(&mut self, cells: Vec<(Point, Rgb15)>) {
+        self.cells = cells;
+    }
+}
+
+impl Widget for AutomapView {
+    fn render(&mut self, ctx: Render) {
+        let top_left = ctx.base.unwrap().rect().top_left();
+        for &(p, color) in &self.cells {
+            let rect = Rect::with_size(top_left.x + p.x * HEX_SIZE, top_left.y + p.y * HEX_SIZE,
+                HEX_SIZE, HEX_SIZE);
+            ctx.canvas.fill_rect(rect, color);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use bstring::{bstr, BString};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::graphics::color::{BLACK, GREEN, WHITE};
+use crate::graphics::{Point, Rect};
+use crate::graphics::font::{self, FontKey, Fonts};
+use crate::ui::*;
+use crate::ui::text_input::TextInput;
+
+const FONT: FontKey = FontKey::antialiased(1);
+const PADDING: i32 = 4;
+const PROMPT: &[u8] = b"> ";
+
+/// Output lines and the prompt of the developer console. The input line is a `TextInput`
+/// placed at `input_rect()`.
+pub struct ConsoleView {
+    fonts: Rc<Fonts>,
+    output: VecDeque<BString>,
+}
+
+impl ConsoleView {
+    pub fn new(fonts: Rc<Fonts>) -> Self {
+        Self {
+            fonts,
+            output: VecDeque::new(),
+        }
+    }
+
+    /// Returns the rect of the input line in the view of the `rect`.
+    pub fn input_rect(&self, rect: Rect) -> Rect {
+        let font = self.fonts.get(FONT);
+        let bottom = rect.bottom - PADDING;
+        Rect::new(rect.left + PADDING + font.line_width(PROMPT.into()),
+            bottom - font.vert_advance(), rect.right - PADDING, bottom)
+    }
+
+    /// Creates the input line widget.
+    pub fn new_input(&self) -> TextInput {
+        TextInput::new(self.fonts.clone(), FONT, WHITE, GREEN)
+    }
+
+    pub fn push_output(&mut self, line: BString) {
+        self.output.push_back(line);
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+}
+
+impl Widget for ConsoleView {
+    fn render(&mut self, ctx: Render) {
+        let rect = ctx.base.unwrap().rect;
+        ctx.canvas.fill_rect(rect, BLACK);
+
+        let vert_advance = self.fonts.get(FONT).vert_advance();
+        let x = rect.left + PADDING;
+        let mut y = rect.bottom - PADDING - vert_advance;
+
+        ctx.canvas.draw_text(PROMPT.into(), Point::new(x, y), FONT, WHITE,
+            &font::DrawOptions::default());
+
+        for line in self.output.iter().rev() {
+            y -= vert_advance;
+            if y < rect.top {
+                break;
+            }
+            let line: &bstr = line.as_ref();
+            ctx.canvas.draw_text(line, Point::new(x, y), FONT, GREEN,
+                &font::DrawOptions::default());
+        }
+    }
+}
@@ -1,58 +1,164 @@
+use log::*;
+
 use crate::asset::frame::FrameId;
-use crate::graphics::Rect;
+use crate::error;
+use crate::fs::FileSystem;
+use crate::game::ui::action_points::ActionPoints;
 use crate::graphics::color::GREEN;
 use crate::graphics::font::FontKey;
-use crate::graphics::sprite::Sprite;
 use crate::ui::*;
-use crate::ui::button::Button;
-use crate::ui::command::{inventory, SkilldexCommand, UiCommandData};
+use crate::ui::button::{self, Button};
+use crate::ui::layout::Layout;
+use crate::ui::command::{inventory, AutomapCommand, CombatCommand, PipboyCommand, SkilldexCommand,
+    UiCommandData};
 use crate::ui::message_panel::{MessagePanel, Anchor};
+use crate::ui::panel::Panel;
+
+pub struct Hud {
+    pub message_panel: Handle,
+    action_points: Handle,
+    combat_panel: Handle,
+    turn_lights: Handle,
+    end_turn: Handle,
+    end_combat: Handle,
+}
+
+impl Hud {
+    // intface_end_buttons_enable, intface_end_buttons_disable
+    /// Shows or hides the combat portion of the interface bar.
+    pub fn set_combat_visible(&self, ui: &mut Ui, visible: bool) {
+        for &h in &[self.combat_panel, self.turn_lights, self.end_turn, self.end_combat] {
+            ui.widget_base_mut(h).set_visible(visible);
+        }
+        if !visible {
+            ui.widget_mut::<ActionPoints>(self.action_points).clear();
+        }
+    }
+
+    // intface_end_window_open, intface_end_window_close
+    /// Updates the turn indicator lights and the end turn/end combat buttons.
+    /// The buttons are only enabled during the dude's turn and while the dude is not busy.
+    pub fn set_turn(&self, ui: &mut Ui, dude_turn: bool, dude_busy: bool) {
+        ui.widget_base_mut(self.turn_lights).background_mut().unwrap().fid = if dude_turn {
+            FrameId::ENDLTGRN
+        } else {
+            FrameId::ENDLTRED
+        };
+        let enabled = dude_turn && !dude_busy;
+        ui.widget_mut::<Button>(self.end_turn).set_enabled(enabled);
+        ui.widget_mut::<Button>(self.end_combat).set_enabled(enabled);
+        if !dude_turn {
+            ui.widget_mut::<ActionPoints>(self.action_points).set_red();
+        }
+    }
+
+    pub fn set_action_points(&self, ui: &mut Ui, action_points: u32, bonus: u32) {
+        ui.widget_mut::<ActionPoints>(self.action_points).set(action_points, bonus);
+    }
+}
+
+/// Built-in layout of the interface bar. Can be changed with `ui/hud.json` in the data files.
+/// See `ui::layout` for the format.
+const LAYOUT: &str = r#"{
+    "window": {"rect": [0, 379, 640, 100], "background": "0x06000010"},
+    "widgets": [
+        {"id": "message_panel", "rect": [23, 26, 166, 65]},
+        {"id": "inventory", "rect": [211, 40, 32, 21], "up": "0x0600002f", "down": "0x0600002e"},
+        {"id": "options", "rect": [210, 62, 34, 34], "up": "0x06000012", "down": "0x06000011"},
+        {"id": "attack_mode", "rect": [218, 6, 22, 21], "up": "0x06000006", "down": "0x06000007"},
+        {"id": "skilldex", "rect": [523, 6, 22, 21], "up": "0x06000006", "down": "0x06000007"},
+        {"id": "map", "rect": [526, 40, 41, 19], "up": "0x0600000d", "down": "0x0600000a"},
+        {"id": "character", "rect": [526, 59, 41, 19], "up": "0x06000039", "down": "0x06000038"},
+        {"id": "pipboy", "rect": [526, 78, 41, 19], "up": "0x0600003b", "down": "0x0600003a"},
+        {"id": "attack", "rect": [267, 26, 188, 67], "up": "0x06000020", "down": "0x0600001f"},
+        {"id": "action_points", "rect": [316, 14, 90, 5]},
+        {"id": "combat_panel", "rect": [580, 38, 57, 58], "background": "0x06000068"},
+        {"id": "turn_lights", "rect": [580, 38, 57, 58], "background": "0x0600006e"},
+        {"id": "end_turn", "rect": [590, 43, 38, 22], "up": "0x06000069", "down": "0x0600006a"},
+        {"id": "end_combat", "rect": [590, 65, 38, 22], "up": "0x0600006b", "down": "0x0600006c"}
+    ]
+}"#;
+
+const WIDGETS: &[&str] = &["message_panel", "inventory", "options", "attack_mode", "skilldex",
+    "map", "character", "pipboy", "attack", "action_points", "combat_panel", "turn_lights",
+    "end_turn", "end_combat"];
 
-pub fn create(ui: &mut Ui) -> Handle {
-    let main_hud = ui.new_window(Rect::with_size(0, 379, 640, 100), Some(Sprite::new(FrameId::IFACE)));
+const LAYOUT_PATH: &str = "ui/hud.json";
+
+pub fn create(fs: &FileSystem, ui: &mut Ui) -> Hud {
+    let layout = Layout::load(fs, LAYOUT_PATH, LAYOUT);
+    let main_hud = layout.new_window(ui);
+    match create_widgets(&layout, main_hud, ui) {
+        Ok(hud) => hud,
+        Err(e) => {
+            warn!("couldn't create interface bar from layout {}, using the built-in layout: {}",
+                LAYOUT_PATH, e);
+            ui.remove(main_hud);
+            let layout = Layout::parse(LAYOUT).unwrap();
+            let main_hud = layout.new_window(ui);
+            create_widgets(&layout, main_hud, ui).unwrap()
+        }
+    }
+}
+
+fn create_widgets(layout: &Layout, main_hud: Handle, ui: &mut Ui) -> error::Result<Hud> {
 
     // Message panel.
     let mut mp = MessagePanel::new(ui.fonts().clone(), FontKey::antialiased(1), GREEN);
     mp.set_skew(1);
     mp.set_capacity(Some(100));
     mp.set_anchor(Anchor::Bottom);
-    let message_panel = ui.new_widget(main_hud, Rect::with_size(23, 26, 166, 65), None, None, mp);
-
-    // Inventory button.
-    // Original location is a bit off, at y=41.
-    ui.new_widget(main_hud, Rect::with_size(211, 40, 32, 21), None, None,
-        Button::new(FrameId::INVENTORY_BUTTON_UP, FrameId::INVENTORY_BUTTON_DOWN,
-            Some(UiCommandData::Inventory(inventory::Command::Show))));
-
-    // Options button.
-    ui.new_widget(main_hud, Rect::with_size(210, 62, 34, 34), None, None,
-        Button::new(FrameId::OPTIONS_BUTTON_UP, FrameId::OPTIONS_BUTTON_DOWN, None));
+    let message_panel = layout.new_widget("message_panel", main_hud, mp, ui)?;
 
+    // Original location of the inventory button is a bit off, at y=41.
+    layout.new_button("inventory", main_hud,
+        Some(UiCommandData::Inventory(inventory::Command::Show)), ui)?;
+    layout.new_button("options", main_hud, None, ui)?;
     // Single/burst switch button.
-    ui.new_widget(main_hud, Rect::with_size(218, 6, 22, 21), None, None,
-        Button::new(FrameId::BIG_RED_BUTTON_UP, FrameId::BIG_RED_BUTTON_DOWN, None));
+    layout.new_button("attack_mode", main_hud, None, ui)?;
+    layout.new_button("skilldex", main_hud,
+        Some(UiCommandData::Skilldex(SkilldexCommand::Show)), ui)?;
+    layout.new_button("map", main_hud,
+        Some(UiCommandData::Automap(AutomapCommand::Show)), ui)?;
+    layout.new_button("character", main_hud, None, ui)?;
+    layout.new_button("pipboy", main_hud,
+        Some(UiCommandData::Pipboy(PipboyCommand::Show)), ui)?;
 
-    // Skilldex button.
-    ui.new_widget(main_hud, Rect::with_size(523, 6, 22, 21), None, None,
-        Button::new(FrameId::BIG_RED_BUTTON_UP, FrameId::BIG_RED_BUTTON_DOWN,
-            Some(UiCommandData::Skilldex(SkilldexCommand::Show))));
-
-    // MAP button.
-    ui.new_widget(main_hud, Rect::with_size(526, 40, 41, 19), None, None,
-        Button::new(FrameId::MAP_BUTTON_UP, FrameId::MAP_BUTTON_DOWN, None));
+    // FIXME this should be a custom button with overlay text images.
+    layout.new_button("attack", main_hud, None, ui)?;
 
-    // CHA button.
-    ui.new_widget(main_hud, Rect::with_size(526, 59, 41, 19), None, None,
-        Button::new(FrameId::CHARACTER_BUTTON_UP, FrameId::CHARACTER_BUTTON_DOWN, None));
+    // Action point lights.
+    let action_points = layout.new_widget("action_points", main_hud, ActionPoints::new(), ui)?;
 
-    // PIP button.
-    ui.new_widget(main_hud, Rect::with_size(526, 78, 41, 19), None, None,
-        Button::new(FrameId::PIP_BUTTON_UP, FrameId::PIP_BUTTON_DOWN, None));
+    // Combat panel with end turn/end combat buttons. Hidden outside of combat.
+    let combat_panel = layout.new_widget("combat_panel", main_hud, Panel::new(), ui)?;
+    // Show the last frame of the panel opening animation.
+    if let Some(end_anim) = ui.widget_base_mut(combat_panel).background_mut() {
+        end_anim.frame_idx = ui.frm_db().get(end_anim.fid)
+            .map(|frm| frm.frame_lists[end_anim.direction].frames.len() - 1)
+            .unwrap_or(0);
+    }
+    let turn_lights = layout.new_widget("turn_lights", main_hud, Panel::new(), ui)?;
+    let mut new_end_button = |id, cmd| {
+        let mut b = layout.button(id, Some(UiCommandData::Combat(cmd)))?;
+        b.config_mut(button::State::Disabled).background =
+            b.config(button::State::Up).background;
+        b.set_enabled(false);
+        layout.new_widget(id, main_hud, b, ui)
+    };
+    let end_turn = new_end_button("end_turn", CombatCommand::EndTurn)?;
+    let end_combat = new_end_button("end_combat", CombatCommand::EndCombat)?;
 
-    // Attack button.
-    // FIXME this should be a custom button with overlay text images.
-    ui.new_widget(main_hud, Rect::with_size(267, 26, 188, 67), None, None,
-        Button::new(FrameId::SINGLE_ATTACK_BUTTON_UP, FrameId::SINGLE_ATTACK_BUTTON_DOWN, None));
+    layout.new_decorations(main_hud, WIDGETS, ui);
 
-    message_panel
-}
\ No newline at end of file
+    let hud = Hud {
+        message_panel,
+        action_points,
+        combat_panel,
+        turn_lights,
+        end_turn,
+        end_combat,
+    };
+    hud.set_combat_visible(ui, false);
+    Ok(hud)
+}
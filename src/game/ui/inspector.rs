@@ -0,0 +1,142 @@
+use std::rc::Rc;
+
+use crate::game::inspector::{Field, Line};
+use crate::graphics::color::{BLACK, GREEN, WHITE};
+use crate::graphics::Point;
+use crate::graphics::font::{self, FontKey, Fonts};
+use crate::ui::*;
+use crate::ui::command::{InspectorCommand, UiCommandData};
+
+const FONT: FontKey = FontKey::antialiased(1);
+const PADDING: i32 = 4;
+
+/// Lines describing the inspected object. The editable lines are selected with up and down keys
+/// and the new value is typed in here, applying it is requested via `InspectorCommand`.
+pub struct InspectorView {
+    fonts: Rc<Fonts>,
+    lines: Vec<Line>,
+    /// Index of the selected editable line.
+    selected: Option<usize>,
+    input: String,
+    status: String,
+}
+
+impl InspectorView {
+    pub fn new(fonts: Rc<Fonts>) -> Self {
+        Self {
+            fonts,
+            lines: Vec::new(),
+            selected: None,
+            input: String::new(),
+            status: String::new(),
+        }
+    }
+
+    /// Replaces the lines keeping the selected field if it's still present.
+    pub fn set_lines(&mut self, lines: Vec<Line>) {
+        let field = self.selected_field();
+        self.lines = lines;
+        self.selected = field
+            .and_then(|f| self.lines.iter().position(|l| l.field == Some(f)))
+            .or_else(|| self.lines.iter().position(|l| l.field.is_some()));
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    /// Takes the entered value of the selected field.
+    pub fn take_edit(&mut self) -> Option<(Field, String)> {
+        let field = self.selected_field()?;
+        Some((field, std::mem::take(&mut self.input)))
+    }
+
+    fn selected_field(&self) -> Option<Field> {
+        self.selected.and_then(|i| self.lines.get(i)).and_then(|l| l.field)
+    }
+
+    fn select_next(&mut self, forward: bool) {
+        let cur = if let Some(v) = self.selected {
+            v
+        } else {
+            return;
+        };
+        let editable = |&i: &usize| self.lines[i].field.is_some();
+        let next = if forward {
+            (cur + 1..self.lines.len()).find(editable)
+        } else {
+            (0..cur).rev().find(editable)
+        };
+        if let Some(next) = next {
+            self.selected = Some(next);
+            self.input.clear();
+        }
+    }
+}
+
+impl Widget for InspectorView {
+    fn handle_event(&mut self, mut ctx: HandleEvent) {
+        match ctx.event {
+            Event::KeyDown { keycode: Some(key), .. } => {
+                let cmd = match key {
+                    Keycode::Backspace => {
+                        self.input.pop();
+                        None
+                    }
+                    Keycode::Return | Keycode::KpEnter => Some(InspectorCommand::Apply),
+                    Keycode::Up => {
+                        self.select_next(false);
+                        None
+                    }
+                    Keycode::Down => {
+                        self.select_next(true);
+                        None
+                    }
+                    Keycode::Escape => Some(InspectorCommand::Hide),
+                    _ => None,
+                };
+                if let Some(cmd) = cmd {
+                    ctx.out(UiCommandData::Inspector(cmd));
+                }
+            }
+            Event::TextInput { text } => {
+                self.input.extend(text.chars().filter(|c| c.is_ascii() && !c.is_ascii_control()));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, ctx: Render) {
+        let rect = ctx.base.unwrap().rect;
+        ctx.canvas.fill_rect(rect, BLACK);
+
+        let vert_advance = self.fonts.get(FONT).vert_advance();
+        let x = rect.left + PADDING;
+        let mut y = rect.top + PADDING;
+        let footer = [
+            &self.status[..],
+            "Up/Down: select field, type the value and press Enter to set it, Escape: close",
+        ];
+        let bottom = rect.bottom - PADDING - vert_advance * footer.len() as i32;
+        for (i, line) in self.lines.iter().enumerate() {
+            if y + vert_advance > bottom {
+                break;
+            }
+            let (text, color) = if Some(i) == self.selected {
+                (format!("{} = {}_", line.text, self.input), WHITE)
+            } else {
+                (line.text.clone(), GREEN)
+            };
+            ctx.canvas.draw_text(text.as_bytes().into(), Point::new(x, y), FONT, color,
+                &font::DrawOptions::default());
+            y += vert_advance;
+        }
+
+        let mut y = bottom;
+        for line in &footer {
+            ctx.canvas.draw_text(line.as_bytes().into(), Point::new(x, y), FONT, GREEN,
+                &font::DrawOptions::default());
+            y += vert_advance;
+        }
+    }
+}
@@ -1,7 +1,5 @@
 use bstring::bfmt::ToBString;
 use bstring::BString;
-use std::cmp;
-use std::convert::TryFrom;
 use std::time::{Duration, Instant};
 
 use crate::asset::frame::FrameId;
@@ -14,6 +12,7 @@ use crate::game::ui::action_menu::{Action, Placement};
 use crate::ui::*;
 use crate::ui::command::UiCommandData;
 use crate::ui::command::inventory::Command;
+use crate::ui::scroll_list::ScrollList;
 
 pub struct Item {
     pub object: object::Handle,
@@ -33,42 +32,51 @@ pub enum Scroll {
     Up,
 }
 
+/// List of inventory items. The items are kept in a `ScrollList` that scrolls by single items;
+/// the mouse is handled here according to the `MouseMode`.
 pub struct InventoryList {
-    item_height: i32,
-    item_spacing: i32,
-    items: Vec<Item>,
-    scroll_idx: usize,
+    items: ScrollList<Item>,
     dragging: Option<usize>,
     mouse_mode: MouseMode,
     last_hovered: Option<object::Handle>,
     default_action: Option<Action>,
     action_menu_state: Option<(Instant, usize)>,
-    visible_items: usize,
 }
 
 impl InventoryList {
     pub fn new(item_height: i32, item_spacing: i32) -> Self {
+        let items = ScrollList::new(item_height + item_spacing, move |item: &Item, ctx| {
+            let rect = ctx.rect.with_height(item_height);
+            let mut sprite = Sprite::new(item.fid);
+            sprite.pos = rect.top_left();
+            sprite.effect = Some(Effect::Fit {
+                width: rect.width(),
+                height: rect.height(),
+            });
+            sprite.render(ctx.canvas, ctx.frm_db);
+
+            if item.count > 1 {
+                let s = BString::concat(&[&b"x"[..], item.count.to_bstring().as_bytes()]);
+                ctx.canvas.draw_text(&s, rect.top_left(),
+                    FontKey::antialiased(1), WHITE, &Default::default())
+            }
+        });
         Self {
-            item_height,
-            item_spacing,
-            items: Vec::new(),
-            scroll_idx: 0,
+            items,
             dragging: None,
             mouse_mode: MouseMode::Drag,
             last_hovered: None,
             default_action: None,
             action_menu_state: None,
-            visible_items: 0,
         }
     }
 
     pub fn items(&self) -> &[Item] {
-        &self.items
+        self.items.items()
     }
 
     pub fn clear(&mut self) {
-        self.items.clear();
-        self.scroll_idx = 0;
+        self.items.set_items(Vec::new());
     }
 
     pub fn push(&mut self, item: Item) {
@@ -77,24 +85,24 @@ impl InventoryList {
 
     pub fn can_scroll(&self, scroll: Scroll) -> bool {
         match scroll {
-            Scroll::Down => self.scroll_idx < self.max_scroll_idx(),
-            Scroll::Up => self.scroll_idx > 0,
+            Scroll::Down => self.items.can_scroll_down(),
+            Scroll::Up => self.items.can_scroll_up(),
         }
     }
 
     pub fn scroll_idx(&self) -> usize {
-        self.scroll_idx
+        self.items.scroll_idx()
     }
 
     pub fn scroll(&mut self, scroll: Scroll) {
-        self.set_scroll_idx(match scroll {
-            Scroll::Down => self.scroll_idx + 1,
-            Scroll::Up => self.scroll_idx.saturating_sub(1),
+        self.items.scroll_by(match scroll {
+            Scroll::Down => 1,
+            Scroll::Up => -1,
         });
     }
 
     pub fn set_scroll_idx(&mut self, scroll_idx: usize) {
-        self.scroll_idx = cmp::min(scroll_idx, self.max_scroll_idx());
+        self.items.set_scroll_idx(scroll_idx);
     }
 
     pub fn set_mouse_mode(&mut self, mouse_mode: MouseMode) {
@@ -105,27 +113,18 @@ impl InventoryList {
         self.dragging = None;
     }
 
-    fn max_scroll_idx(&self) -> usize {
-        self.items.len().saturating_sub(self.visible_items)
+    fn item_index_at(&self, rect: Rect, pos: Point) -> Option<usize> {
+        self.items.item_index_at(rect, pos)
     }
 
-    fn item_index_at(&self, rect: Rect, pos: Point) -> Option<usize> {
-        if !rect.contains(pos) {
-            return None;
-        }
-        let i = (pos.y - rect.top) / (self.item_height + self.item_spacing);
-        let i = self.scroll_idx + usize::try_from(i).unwrap();
-        if i < self.items.len() {
-            Some(i)
-        } else {
-            None
-        }
+    fn item(&self, idx: usize) -> &Item {
+        &self.items.items()[idx]
     }
 }
 
 impl Widget for InventoryList {
     fn init(&mut self, ctx: Init) {
-        self.visible_items = (ctx.base.rect().height() / (self.item_height + self.item_spacing)) as usize;
+        self.items.init(ctx);
     }
 
     fn handle_event(&mut self, mut ctx: HandleEvent) {
@@ -137,7 +136,7 @@ impl Widget for InventoryList {
                             self.action_menu_state = Some((ctx.now, idx));
                         }
                         MouseMode::Drag => {
-                            ctx.base.set_cursor(Some(Cursor::Frame(self.items[idx].fid)));
+                            ctx.base.set_cursor(Some(Cursor::Frame(self.item(idx).fid)));
                             ctx.capture();
                             self.dragging = Some(idx);
                         }
@@ -151,14 +150,14 @@ impl Widget for InventoryList {
                         if let Some(idx) = self.item_index_at(ctx.base.rect(), ctx.cursor_pos) {
                             ctx.out(UiCommandData::Inventory(Command::Action {
                                 action: None,
-                                object: self.items[idx].object,
+                                object: self.item(idx).object,
                             }));
                         }
                     }
                     MouseMode::Drag => if let Some(item_index) = self.dragging.take() {
                         ctx.base.set_cursor(None);
                         ctx.release();
-                        let object = self.items[item_index].object;
+                        let object = self.item(item_index).object;
                         ctx.out(UiCommandData::Inventory(Command::ListDrop {
                             pos: ctx.cursor_pos,
                             object,
@@ -169,7 +168,7 @@ impl Widget for InventoryList {
             Event::MouseMove { pos: _ } if self.mouse_mode == MouseMode::Action => {
                 if let Some(idx) = self.item_index_at(ctx.base.rect(), ctx.cursor_pos) {
                     self.default_action = Some(Action::Look);
-                    let object = self.items[idx].object;
+                    let object = self.item(idx).object;
                     if Some(object) != self.last_hovered {
                         self.last_hovered = Some(object);
                         ctx.out(UiCommandData::Inventory(Command::Hover {
@@ -183,6 +182,16 @@ impl Widget for InventoryList {
             Event::MouseLeave => {
                 self.default_action = None;
             }
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                let scroll = match keycode {
+                    Keycode::Up => Scroll::Up,
+                    Keycode::Down => Scroll::Down,
+                    _ => return,
+                };
+                if self.can_scroll(scroll) {
+                    ctx.out(UiCommandData::Inventory(Command::Scroll(scroll)));
+                }
+            }
             Event::Tick => {
                 if let Some((start, item)) = self.action_menu_state {
                     if ctx.now - start >= Duration::from_millis(500) {
@@ -190,7 +199,7 @@ impl Widget for InventoryList {
                         self.action_menu_state = None;
 
                         ctx.out(UiCommandData::Inventory(Command::ActionMenu {
-                            object: self.items[item].object,
+                            object: self.item(item).object,
                         }));
                     }
                 }
@@ -200,30 +209,14 @@ impl Widget for InventoryList {
     }
 
     fn render(&mut self, ctx: Render) {
-        let rect = ctx.base.unwrap().rect();
-
-        let mut item_rect = rect.with_height(self.item_height);
-
-        for item in self.items.iter().skip(self.scroll_idx) {
-            if !rect.contains_rect(item_rect) {
-                break;
-            }
-            let mut sprite = Sprite::new(item.fid);
-            sprite.pos = item_rect.top_left();
-            sprite.effect = Some(Effect::Fit {
-                width: item_rect.width(),
-                height: item_rect.height(),
-            });
-            sprite.render(ctx.canvas, ctx.frm_db);
-
-            if item.count > 1 {
-                let s = BString::concat(&[&b"x"[..], item.count.to_bstring().as_bytes()]);
-                ctx.canvas.draw_text(&s, item_rect.top_left(),
-                    FontKey::antialiased(1), WHITE, &Default::default())
-            }
-
-            item_rect = item_rect.translate(Point::new(0, self.item_height + self.item_spacing));
-        }
+        self.items.render(Render {
+            frm_db: ctx.frm_db,
+            canvas: &mut *ctx.canvas,
+            base: ctx.base,
+            cursor_pos: ctx.cursor_pos,
+            has_mouse_focus: ctx.has_mouse_focus,
+            has_keyboard_focus: ctx.has_keyboard_focus,
+        });
 
         if let Some(default_action) = self.default_action {
             let fid = default_action.icons().0;
@@ -0,0 +1,67 @@
+use crate::asset::frame::FrameId;
+use crate::game::object;
+use crate::graphics::Rect;
+use crate::graphics::sprite::{Anchor, Effect, Sprite};
+use crate::ui::{self, Ui};
+use crate::ui::button::{self, Button};
+use crate::ui::command::{ItemChooserCommand, UiCommandData};
+
+const ITEM_WIDTH: i32 = 50;
+const ITEM_HEIGHT: i32 = 40;
+
+/// Window for choosing which of the items lying on the same hex to pick up.
+pub struct ItemChooser {
+    win: ui::Handle,
+    items: Vec<object::Handle>,
+}
+
+impl ItemChooser {
+    /// Max number of items shown in the window. The rest can still be taken with the All button.
+    pub const MAX_VISIBLE_ITEMS: usize = 6;
+
+    /// Shows the window with `items` given as the object and its inventory FID.
+    pub fn show(items: &[(object::Handle, FrameId)], ui: &mut Ui) -> Self {
+        assert!(!items.is_empty());
+
+        let mut background = Sprite::new(FrameId::MEDIALOG);
+        background.anchor = Anchor::Center;
+        let win = ui.new_window(Rect::with_size(170, 120, 300, 140), Some(background));
+        ui.widget_base_mut(win).set_modal(true);
+
+        let visible = &items[..items.len().min(Self::MAX_VISIBLE_ITEMS)];
+        let left = (300 - ITEM_WIDTH * visible.len() as i32) / 2;
+        for (i, &(object, fid)) in visible.iter().enumerate() {
+            let mut item = Button::new(fid, fid,
+                Some(UiCommandData::ItemChooser(ItemChooserCommand::Pick { object })));
+            for &state in &[button::State::Up, button::State::Down] {
+                item.config_mut(state).background.as_mut().unwrap().effect = Some(Effect::Fit {
+                    width: ITEM_WIDTH - 4,
+                    height: ITEM_HEIGHT - 4,
+                });
+            }
+            ui.new_widget(win, Rect::with_size(left + ITEM_WIDTH * i as i32, 30,
+                ITEM_WIDTH, ITEM_HEIGHT), None, None, item);
+        }
+
+        ui.new_widget(win, Rect::with_size(60, 90, 94, 33), None, None,
+            Button::new(FrameId::BUTTON_ALL_UP, FrameId::BUTTON_ALL_DOWN,
+            Some(UiCommandData::ItemChooser(ItemChooserCommand::PickAll))));
+        ui.new_widget(win, Rect::with_size(200, 98, 15, 16), None, None,
+            Button::new(FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN,
+            Some(UiCommandData::ItemChooser(ItemChooserCommand::Cancel))));
+
+        Self {
+            win,
+            items: items.iter().map(|&(obj, _)| obj).collect(),
+        }
+    }
+
+    pub fn hide(self, ui: &mut Ui) {
+        ui.remove(self.win);
+    }
+
+    /// All items the window was shown for.
+    pub fn items(&self) -> &[object::Handle] {
+        &self.items
+    }
+}
@@ -11,9 +11,20 @@ use crate::ui::button::{Button, Text};
 use bstring::bfmt::ToBString;
 use crate::ui::command::move_window::Command;
 use crate::ui::command::{UiCommand, UiCommandData};
+use crate::game::explosive;
+use crate::game::object::{self, Object};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    MoveItems,
+    SetTimer,
+}
 
 pub struct MoveWindow {
+    kind: Kind,
+    min: u32,
     max: u32,
+    step: u32,
     win: ui::Handle,
     count: ui::Handle,
     value: u32,
@@ -22,22 +33,49 @@ pub struct MoveWindow {
 impl MoveWindow {
     pub fn show(item_fid: FrameId, max: u32, msgs: &Messages, ui: &mut Ui) -> Self {
         assert!(max > 0);
+        Self::show0(Kind::MoveItems, item_fid, Some(msgs), 1, std::cmp::min(max, 99999), 1, 1,
+            ui)
+    }
+
+    /// Shows window for setting the explosive timer. The value is in seconds.
+    // inven_set_timer
+    pub fn show_timer(item_fid: FrameId, ui: &mut Ui) -> Self {
+        Self::show0(Kind::SetTimer, item_fid, None, explosive::TIMER_MIN, explosive::TIMER_MAX,
+            explosive::TIMER_STEP, explosive::TIMER_DEFAULT, ui)
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn show0(
+        kind: Kind,
+        item_fid: FrameId,
+        msgs: Option<&Messages>,
+        min: u32,
+        max: u32,
+        step: u32,
+        value: u32,
+        ui: &mut Ui,
+    ) -> Self {
+        let background = match kind {
+            Kind::MoveItems => FrameId::INVENTORY_MOVE_MULTIPLE_WINDOW,
+            Kind::SetTimer => FrameId::TIMER,
+        };
         let win = ui.new_window(Rect::with_size(140, 80, 259, 162),
-            Some(Sprite::new(FrameId::INVENTORY_MOVE_MULTIPLE_WINDOW)));
+            Some(Sprite::new(background)));
         ui.widget_base_mut(win).set_modal(true);
 
-        let mut header = Panel::new();
-        header.set_text(Some(panel::Text {
-            text: msgs.get(21).unwrap().text.clone(),
-            font: FontKey::antialiased(3),
-            color: Rgb15::from_packed(0x5263),
-            options: DrawOptions {
-                horz_align: HorzAlign::Center,
-                ..Default::default()
-            },
-        }));
-        ui.new_widget(win, Rect::with_size(0, 9, 259, 162), None, None, header);
+        if let Some(msgs) = msgs {
+            let mut header = Panel::new();
+            header.set_text(Some(panel::Text {
+                text: msgs.get(21).unwrap().text.clone(),
+                font: FontKey::antialiased(3),
+                color: Rgb15::from_packed(0x5263),
+                options: DrawOptions {
+                    horz_align: HorzAlign::Center,
+                    ..Default::default()
+                },
+            }));
+            ui.new_widget(win, Rect::with_size(0, 9, 259, 162), None, None, header);
+        }
 
         let mut item = Sprite::new(item_fid);
         item.effect = Some(Effect::Fit {
@@ -63,20 +101,25 @@ impl MoveWindow {
             Button::new(FrameId::SMALL_RED_BUTTON_UP, FrameId::SMALL_RED_BUTTON_DOWN,
             Some(UiCommandData::MoveWindow(Command::Hide { ok: false }))));
 
-        let mut text = Text::new(msgs.get(22).unwrap().text.clone(), FontKey::antialiased(3));
-        text.color = Rgb15::from_packed(0x5263);
-        text.options.horz_align = HorzAlign::Center;
-        text.options.vert_align = VertAlign::Middle;
-        let mut all = Button::new(FrameId::BUTTON_ALL_UP, FrameId::BUTTON_ALL_DOWN,
-            Some(UiCommandData::MoveWindow(Command::Max)));
-        all.set_text(Some(text));
-        ui.new_widget(win, Rect::with_size(121, 80, 94, 33), None, None, all);
+        if let Some(msgs) = msgs {
+            let mut text = Text::new(msgs.get(22).unwrap().text.clone(), FontKey::antialiased(3));
+            text.color = Rgb15::from_packed(0x5263);
+            text.options.horz_align = HorzAlign::Center;
+            text.options.vert_align = VertAlign::Middle;
+            let mut all = Button::new(FrameId::BUTTON_ALL_UP, FrameId::BUTTON_ALL_DOWN,
+                Some(UiCommandData::MoveWindow(Command::Max)));
+            all.set_text(Some(text));
+            ui.new_widget(win, Rect::with_size(121, 80, 94, 33), None, None, all);
+        }
 
         let r = Self {
-            max: std::cmp::min(max, 99999),
+            kind,
+            min,
+            max,
+            step,
             win,
             count,
-            value: 1,
+            value,
         };
         r.sync(ui);
         r
@@ -96,8 +139,8 @@ impl MoveWindow {
                 Command::Hide { .. } => {
                     return;
                 }
-                Command::Inc => std::cmp::min(self.value + 1, self.max),
-                Command::Dec => std::cmp::max(self.value - 1, 1),
+                Command::Inc => std::cmp::min(self.value + self.step, self.max),
+                Command::Dec => std::cmp::max(self.value.saturating_sub(self.step), self.min),
                 Command::Max => self.max,
             };
             if new_value != self.value {
@@ -108,7 +151,29 @@ impl MoveWindow {
     }
 
     fn sync(&self, ui: &Ui) {
-        *ui.widget_mut::<ImageText>(self.count).text_mut() =
-            format!("{:05}", self.value).to_bstring();
+        let text = match self.kind {
+            Kind::MoveItems => format!("{:05}", self.value),
+            Kind::SetTimer => format!("{}:{:02}", self.value / 60, self.value % 60),
+        };
+        *ui.widget_mut::<ImageText>(self.count).text_mut() = text.to_bstring();
+    }
+}
+/// Move window asking how many items of an inventory stack to move. The `purpose` tells what's
+/// done with the chosen count once the window is closed.
+pub struct StackMoveWindow<P> {
+    pub purpose: P,
+    pub item: object::Handle,
+    pub win: MoveWindow,
+}
+
+impl<P> StackMoveWindow<P> {
+    pub fn show(purpose: P, item: &Object, max: u32, msgs: &Messages, ui: &mut Ui) -> Self {
+        let fid = item.proto().unwrap().sub.as_item().unwrap().inventory_fid.unwrap();
+        let win = MoveWindow::show(fid, max, msgs, ui);
+        Self {
+            purpose,
+            item: item.handle(),
+            win,
+        }
     }
-}
\ No newline at end of file
+}
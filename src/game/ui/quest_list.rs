@@ -0,0 +1,160 @@
+use bstring::{bstr, BString};
+use std::rc::Rc;
+
+use crate::asset::message::MessageId;
+use crate::game::ui::inventory_list::Scroll;
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::Rgb15;
+use crate::graphics::font::{self, FontKey, Fonts};
+use crate::ui::*;
+use crate::ui::command::{PipboyCommand, UiCommandData};
+use crate::ui::scroll_list::ScrollList;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Style {
+    /// Town name. Clicking it shows the town quests.
+    Town(MessageId),
+    /// Holodisk name. Clicking it opens the holodisk reader.
+    Holodisk(usize),
+    Quest,
+    /// Completed quest, rendered struck through.
+    Completed,
+}
+
+struct Line {
+    text: BString,
+    width: i32,
+    style: Style,
+}
+
+/// Pip-Boy list of towns, quests of a single town, holodisks or holodisk text.
+/// The lines are kept in a `ScrollList` that scrolls by pages.
+pub struct QuestList {
+    fonts: Rc<Fonts>,
+    font: FontKey,
+    width: i32,
+    lines: ScrollList<Line>,
+}
+
+impl QuestList {
+    pub fn new(fonts: Rc<Fonts>, font: FontKey, color: Rgb15, completed_color: Rgb15) -> Self {
+        let line_height = fonts.get(font).vert_advance();
+        let lines = ScrollList::new(line_height, move |line: &Line, ctx| {
+            let color = if line.style == Style::Completed {
+                completed_color
+            } else {
+                color
+            };
+            let pos = ctx.rect.top_left();
+            ctx.canvas.draw_text(&line.text, pos, font, color, &Default::default());
+            if line.style == Style::Completed {
+                ctx.canvas.fill_rect(
+                    Rect::with_size(pos.x, pos.y + line_height / 2 - 1, line.width, 1), color);
+            }
+        });
+        Self {
+            fonts,
+            font,
+            width: 0,
+            lines,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.set_items(Vec::new());
+    }
+
+    /// Appends `text` word-wrapped to the list width.
+    pub fn push(&mut self, text: &bstr, style: Style) {
+        let font = self.fonts.get(self.font);
+        for line in font.lines(text, Some(font::Overflow {
+            size: self.width,
+            boundary: font::OverflowBoundary::Word,
+            action: font::OverflowAction::Wrap,
+        })) {
+            self.lines.push(Line {
+                text: line.to_owned(),
+                width: font.line_width(line),
+                style,
+            });
+        }
+    }
+
+    /// Appends an empty line.
+    pub fn push_space(&mut self) {
+        self.lines.push(Line {
+            text: BString::new(),
+            width: 0,
+            style: Style::Quest,
+        });
+    }
+
+    pub fn can_scroll(&self, scroll: Scroll) -> bool {
+        match scroll {
+            Scroll::Down => self.lines.can_scroll_down(),
+            Scroll::Up => self.lines.can_scroll_up(),
+        }
+    }
+
+    pub fn scroll(&mut self, scroll: Scroll) {
+        let page = self.lines.visible_items() as i32;
+        self.lines.scroll_by(match scroll {
+            Scroll::Down => page,
+            Scroll::Up => -page,
+        });
+    }
+
+    fn line_at(&self, rect: Rect, pos: Point) -> Option<&Line> {
+        self.lines.item_index_at(rect, pos)
+            .map(|i| &self.lines.items()[i])
+            .filter(|l| pos.x < rect.left + l.width)
+    }
+
+    fn command(style: Style) -> Option<PipboyCommand> {
+        match style {
+            Style::Town(location) => Some(PipboyCommand::Town { location }),
+            Style::Holodisk(idx) => Some(PipboyCommand::Holodisk { idx }),
+            Style::Quest | Style::Completed => None,
+        }
+    }
+}
+
+impl Widget for QuestList {
+    fn init(&mut self, ctx: Init) {
+        self.width = ctx.base.rect().width();
+        self.lines.init(ctx);
+    }
+
+    fn handle_event(&mut self, mut ctx: HandleEvent) {
+        match ctx.event {
+            Event::MouseMove { pos } => {
+                let cursor = self.line_at(ctx.base.rect(), pos)
+                    .and_then(|l| Self::command(l.style))
+                    .map(|_| Cursor::Hand);
+                ctx.base.set_cursor(cursor);
+            }
+            Event::MouseUp { pos, button } if button == MouseButton::Left => {
+                if let Some(cmd) = self.line_at(ctx.base.rect(), pos)
+                    .and_then(|l| Self::command(l.style))
+                {
+                    ctx.out(UiCommandData::Pipboy(cmd));
+                }
+            }
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                let scroll = match keycode {
+                    Keycode::Up | Keycode::PageUp => Scroll::Up,
+                    Keycode::Down | Keycode::PageDown => Scroll::Down,
+                    _ => return,
+                };
+                if self.can_scroll(scroll) {
+                    ctx.out(UiCommandData::Pipboy(PipboyCommand::Scroll(scroll)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, ctx: Render) {
+        self.lines.render(ctx);
+    }
+}
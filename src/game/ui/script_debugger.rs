@@ -0,0 +1,61 @@
+use std::rc::Rc;
+
+use crate::graphics::color::{BLACK, GREEN};
+use crate::graphics::Point;
+use crate::graphics::font::{self, FontKey, Fonts};
+use crate::ui::*;
+use crate::ui::command::{ScriptDebuggerCommand, UiCommandData};
+
+const FONT: FontKey = FontKey::antialiased(1);
+const PADDING: i32 = 4;
+
+/// Text lines describing the script debugger state. The stepping keys are turned into
+/// `ScriptDebuggerCommand`.
+pub struct ScriptDebuggerView {
+    fonts: Rc<Fonts>,
+    lines: Vec<String>,
+}
+
+impl ScriptDebuggerView {
+    pub fn new(fonts: Rc<Fonts>) -> Self {
+        Self {
+            fonts,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn set_lines(&mut self, lines: Vec<String>) {
+        self.lines = lines;
+    }
+}
+
+impl Widget for ScriptDebuggerView {
+    fn handle_event(&mut self, mut ctx: HandleEvent) {
+        if let Event::KeyDown { keycode: Some(key), .. } = ctx.event {
+            let cmd = match key {
+                Keycode::Right | Keycode::F10 => ScriptDebuggerCommand::Step,
+                Keycode::C | Keycode::F5 => ScriptDebuggerCommand::Continue,
+                Keycode::Escape => ScriptDebuggerCommand::Hide,
+                _ => return,
+            };
+            ctx.out(UiCommandData::ScriptDebugger(cmd));
+        }
+    }
+
+    fn render(&mut self, ctx: Render) {
+        let rect = ctx.base.unwrap().rect;
+        ctx.canvas.fill_rect(rect, BLACK);
+
+        let vert_advance = self.fonts.get(FONT).vert_advance();
+        let x = rect.left + PADDING;
+        let mut y = rect.top + PADDING;
+        for line in &self.lines {
+            if y + vert_advance > rect.bottom {
+                break;
+            }
+            ctx.canvas.draw_text(line.as_bytes().into(), Point::new(x, y), FONT, GREEN,
+                &font::DrawOptions::default());
+            y += vert_advance;
+        }
+    }
+}
@@ -1,18 +1,22 @@
+use enumflags2::BitFlags;
 use matches::matches;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
 use crate::asset::Flag;
-use crate::asset::frame::FrameId;
+use crate::asset::frame::{FrameDb, FrameId};
 use crate::game::world::{World, WorldRef};
-use crate::game::object;
-use crate::graphics::{EPoint, Point};
+use crate::game::object::{self, LightEmitter};
+use crate::graphics::{EPoint, Point, Rect};
 use crate::graphics::color;
 use crate::graphics::font::*;
 use crate::graphics::geometry::TileGridView;
+use crate::graphics::geometry::hex::Direction;
 use crate::graphics::render;
 use crate::graphics::sprite::{OutlineStyle, Sprite};
 use crate::ui::*;
 use crate::ui::command::{UiCommandData, ObjectPickKind};
+use crate::util::profile;
 
 use super::action_menu::{Action, Placement};
 
@@ -26,6 +30,10 @@ enum PickMode {
 enum ObjectPickMode {
     Action,
     Skill(crate::asset::Skill),
+    /// Picking target to use the item on.
+    UseItem(object::Handle),
+    /// Picking target to attack.
+    Attack,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -43,6 +51,44 @@ enum PickState {
     },
 }
 
+/// Half size of the area the "blocked" mark is drawn in around the hex cursor center.
+const BLOCKED_MARK_EXTENT: i32 = 16;
+
+/// What the world view looked like when it was last rendered. Used to find out which screen
+/// areas changed since then.
+struct Rendered {
+    scene: Scene,
+    egg: Rect,
+    objects: HashMap<object::Handle, RenderedObject>,
+}
+
+/// Changes to any of these require redrawing of the whole view.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Scene {
+    camera_origin: Point,
+    elevation: u32,
+    roof_visible: bool,
+    ambient_light: u32,
+    /// Floating texts fade out over time so they're redrawn each frame.
+    has_floating_texts: bool,
+    /// Debug overlays reflect the blockers, light and paths that change without the objects
+    /// being invalidated so they're redrawn each frame too.
+    has_debug_overlays: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct RenderedObject {
+    pos: Point,
+    bounds: Rect,
+    fid: FrameId,
+    frame_idx: usize,
+    direction: Direction,
+    flags: BitFlags<Flag>,
+    outline: Option<object::Outline>,
+    light: u32,
+    light_emitter: LightEmitter,
+}
+
 pub struct WorldView {
     world: WorldRef,
     pick_mode: PickMode,
@@ -52,6 +98,10 @@ pub struct WorldView {
     pub roof_visible: bool,
     pick_state: PickState,
     action_menu_state: Option<(Instant, object::Handle)>,
+    /// `None` if the whole view must be redrawn.
+    rendered: Option<Rendered>,
+    /// Screen area of the hex cursor mark or the default action icon drawn above the world.
+    rendered_overlay: Option<Rect>,
 
     /// Icon displayed near the cursor in object pick mode.
     pub default_action_icon: Option<Action>,
@@ -70,10 +120,18 @@ impl WorldView {
             roof_visible: false,
             pick_state: PickState::Idle,
             action_menu_state: None,
+            rendered: None,
+            rendered_overlay: None,
             default_action_icon: None,
         }
     }
 
+    /// Makes the whole view redrawn in the next frame. Must be called when the map changes
+    /// in a way not reflected by the objects, for example when a new map is loaded.
+    pub fn invalidate_all(&mut self) {
+        self.rendered = None;
+    }
+
     pub fn hex_cursor_pos(&self) -> Option<EPoint> {
         if self.pick_mode == PickMode::Hex {
             let world = self.world.borrow();
@@ -96,6 +154,23 @@ impl WorldView {
         self.pick_mode = PickMode::Object(ObjectPickMode::Skill(skill));
     }
 
+    pub fn enter_item_target_pick_mode(&mut self, item: object::Handle) {
+        self.saved_pick_mode = Some(self.pick_mode);
+        self.pick_mode = PickMode::Object(ObjectPickMode::UseItem(item));
+        self.default_action_icon = None;
+        self.update_hex_cursor_visibility(None);
+    }
+
+    pub fn enter_attack_target_pick_mode(&mut self) {
+        if self.pick_mode == PickMode::Object(ObjectPickMode::Attack) {
+            return;
+        }
+        self.saved_pick_mode = Some(self.pick_mode);
+        self.pick_mode = PickMode::Object(ObjectPickMode::Attack);
+        self.default_action_icon = None;
+        self.update_hex_cursor_visibility(None);
+    }
+
     fn insert_hex_cursor(world: &mut World) -> object::Handle {
         let mut hex_cursor = world.objects_mut().create(
             Some(FrameId::MOUSE_HEX_OUTLINE), None, Some(Default::default()), None);
@@ -121,6 +196,85 @@ impl WorldView {
         (pos, changed)
     }
 
+    fn scene(&self, world: &World) -> Scene {
+        Scene {
+            camera_origin: world.camera().origin,
+            elevation: world.elevation(),
+            roof_visible: self.roof_visible,
+            ambient_light: world.ambient_light,
+            has_floating_texts: world.has_floating_texts(),
+            has_debug_overlays: !world.debug_overlays.is_empty(),
+        }
+    }
+
+    fn rendered_objects(world: &World, frm_db: &FrameDb)
+        -> HashMap<object::Handle, RenderedObject>
+    {
+        let objects = world.objects();
+        let hex = world.camera().hex();
+        let elevation = world.elevation();
+        let mut r = HashMap::new();
+        for h in world.objects_on_elevation(elevation) {
+            let obj = objects.get(h);
+            let pos = obj.pos();
+            r.insert(h, RenderedObject {
+                pos: pos.point,
+                // Frame bounds can be off by one pixel due to rounding.
+                bounds: obj.bounds(frm_db, &hex, true).inflate(1, 1),
+                fid: obj.fid,
+                frame_idx: obj.frame_idx,
+                direction: obj.direction,
+                flags: obj.flags,
+                outline: obj.outline,
+                light: objects.light_grid().get_clipped(pos),
+                light_emitter: obj.light_emitter(),
+            });
+        }
+        r
+    }
+
+    fn invalidate_object(ctx: &mut Invalidate, world: &World, obj: &RenderedObject) {
+        ctx.invalidate(obj.bounds);
+        let LightEmitter { intensity, radius } = obj.light_emitter;
+        if intensity > 0 && radius > 0 {
+            // Light changes affect the floor around the emitter. Objects there are invalidated
+            // on their own since their light changes too.
+            let center = world.camera().hex().center_to_screen(obj.pos);
+            let r = radius as i32 + 1;
+            ctx.invalidate(Rect::with_size(center.x, center.y, 0, 0)
+                .inflate(r * 32 + 80, r * 16 + 36));
+        }
+    }
+
+    fn overlay_area(&self, world: &World, frm_db: &FrameDb, cursor_pos: Point, rect: Rect)
+        -> Option<Rect>
+    {
+        match self.pick_mode {
+            PickMode::Hex => if self.hex_cursor_style == HexCursorStyle::Blocked {
+                let hex_cursor = world.objects().get(self.hex_cursor);
+                let pos = hex_cursor.pos();
+                if !hex_cursor.flags.contains(Flag::TurnedOff)
+                    && pos.elevation == world.elevation()
+                {
+                    let center = world.camera().hex().center_to_screen(pos.point);
+                    return Some(Rect::with_size(center.x, center.y, 0, 0)
+                        .inflate(BLOCKED_MARK_EXTENT, BLOCKED_MARK_EXTENT));
+                }
+            }
+            PickMode::Object(ObjectPickMode::Action) => {
+                if let Some(action) = self.default_action_icon {
+                    let pos = Placement::new(1, cursor_pos, rect).rect.top_left();
+                    let sprite = Sprite::new_with_pos(action.icons().0, pos);
+                    return Some(sprite.screen_area(frm_db));
+                }
+            }
+            PickMode::Object(ObjectPickMode::Skill(_))
+            | PickMode::Object(ObjectPickMode::UseItem(_))
+            | PickMode::Object(ObjectPickMode::Attack) => {}
+        }
+        None
+    }
+
     fn update_hex_cursor_visibility(&mut self, force_visible: Option<bool>) {
         let mut world = self.world.borrow_mut();
         let mut cursor = world.objects_mut().get_mut(self.hex_cursor);
@@ -148,7 +302,9 @@ impl Widget for WorldView {
                         self.pick_state = PickState::Pending { start: ctx.now, pos };
                         self.default_action_icon = None;
                     }
-                    PickMode::Object(ObjectPickMode::Skill(_)) => {}
+                    PickMode::Object(ObjectPickMode::Skill(_))
+                    | PickMode::Object(ObjectPickMode::UseItem(_))
+            | PickMode::Object(ObjectPickMode::Attack) => {}
                 }
                 self.update_hex_cursor_visibility(None);
             }
@@ -180,6 +336,14 @@ impl Widget for WorldView {
                                             self.pick_mode = self.saved_pick_mode.take().unwrap();
                                             ObjectPickKind::Skill(skill)
                                         }
+                                        ObjectPickMode::UseItem(item) => {
+                                            self.pick_mode = self.saved_pick_mode.take().unwrap();
+                                            ObjectPickKind::UseItem(item)
+                                        }
+                                        ObjectPickMode::Attack => {
+                                            self.pick_mode = self.saved_pick_mode.take().unwrap();
+                                            ObjectPickKind::Attack
+                                        }
                                     };
                                     ctx.out(UiCommandData::ObjectPick { kind, obj });
                                     if self.pick_mode == PickMode::Hex {
@@ -257,12 +421,62 @@ impl Widget for WorldView {
                 match self.pick_mode {
                     PickMode::Hex => Cursor::Hidden,
                     PickMode::Object(ObjectPickMode::Action) => Cursor::ActionArrow,
-                    PickMode::Object(ObjectPickMode::Skill(_)) => Cursor::CrosshairUse,
+                    PickMode::Object(ObjectPickMode::Skill(_))
+                    | PickMode::Object(ObjectPickMode::UseItem(_))
+                    | PickMode::Object(ObjectPickMode::Attack) => Cursor::CrosshairUse,
                 }
             }));
     }
 
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        let world = self.world.borrow();
+
+        let scene = self.scene(&world);
+        let egg = world.egg().bounds(&world.camera().hex(), ctx.frm_db);
+        let objects = Self::rendered_objects(&world, ctx.frm_db);
+        match &self.rendered {
+            Some(rendered) if rendered.scene == scene && !scene.has_floating_texts
+                && !scene.has_debug_overlays =>
+            {
+                if rendered.egg != egg {
+                    ctx.invalidate(rendered.egg);
+                    ctx.invalidate(egg);
+                }
+                for (h, obj) in &objects {
+                    match rendered.objects.get(h) {
+                        Some(old) if old == obj => {}
+                        old => {
+                            if let Some(old) = old {
+                                Self::invalidate_object(&mut ctx, &world, old);
+                            }
+                            Self::invalidate_object(&mut ctx, &world, obj);
+                        }
+                    }
+                }
+                for (h, old) in &rendered.objects {
+                    if !objects.contains_key(h) {
+                        Self::invalidate_object(&mut ctx, &world, old);
+                    }
+                }
+            }
+            _ => ctx.invalidate_all(),
+        }
+        self.rendered = Some(Rendered {
+            scene,
+            egg,
+            objects,
+        });
+
+        // The overlay is small so it's simply redrawn every frame.
+        let overlay = self.overlay_area(&world, ctx.frm_db, ctx.cursor_pos, ctx.base.rect());
+        for &rect in self.rendered_overlay.iter().chain(overlay.iter()) {
+            ctx.invalidate(rect);
+        }
+        self.rendered_overlay = overlay;
+    }
+
     fn render(&mut self, ctx: Render) {
+        let _profile = profile::scope("world render");
         let world = self.world.borrow();
 
         world.render(ctx.canvas, self.roof_visible);
@@ -291,7 +505,9 @@ impl Widget for WorldView {
                 let pos = Placement::new(1, ctx.cursor_pos, ctx.base.unwrap().rect()).rect.top_left();
                 Sprite::new_with_pos(fid, pos).render(ctx.canvas, ctx.frm_db);
             }
-            PickMode::Object(ObjectPickMode::Skill(_)) => {}
+            PickMode::Object(ObjectPickMode::Skill(_))
+            | PickMode::Object(ObjectPickMode::UseItem(_))
+            | PickMode::Object(ObjectPickMode::Attack) => {}
         }
     }
 }
@@ -0,0 +1,177 @@
+use log::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::asset::{CritterAnim, Flag};
+use crate::game::object::{self, PathTo};
+use crate::game::rng::{random, Stream};
+use crate::game::sequence::ObjSequencer;
+use crate::game::sequence::move_seq::Move;
+use crate::game::sequence::stand::Stand;
+use crate::game::world::World;
+use crate::graphics::{EPoint, Point};
+use crate::graphics::geometry::hex::Direction;
+use crate::sequence::chain::Chain;
+use crate::util::EnumExt;
+
+/// How often the idle critters are checked.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Range of the pause in milliseconds a critter stands still between walks.
+const MIN_PAUSE: i32 = 4000;
+const MAX_PAUSE: i32 = 15000;
+/// Number of attempts to find a free hex to walk to.
+const TARGET_ATTEMPTS: u32 = 4;
+
+struct Wanderer {
+    /// Position the critter wanders around. This is where it was first seen on the map.
+    home: EPoint,
+    next_time: Instant,
+}
+
+/// Makes idle critters with non-zero `wander_radius` in their proto stroll around their home
+/// position. The rest of the critters stand still unless their scripts move them.
+/// Critters busy with a sequence (scripted moves, fidgets etc.) and the dude's party are
+/// left alone.
+///
+/// The stock game data has no such field: the original critters walk around only when their
+/// scripts tell them to (usually from `map_update_p_proc`). So the radius must be given in the
+/// `proto_ext` definitions of a mod, and without them this does nothing.
+pub struct Wander {
+    next_time: Instant,
+    wanderers: HashMap<object::Handle, Wanderer>,
+}
+
+impl Wander {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            next_time: now + UPDATE_INTERVAL,
+            wanderers: HashMap::new(),
+        }
+    }
+
+    /// Forgets the home positions. Must be called when the map is changed.
+    pub fn clear(&mut self) {
+        self.wanderers.clear();
+    }
+
+    pub fn update(&mut self, time: Instant, world: &World, obj_sequencer: &mut ObjSequencer) {
+        if time < self.next_time {
+            return;
+        }
+        self.next_time = time + UPDATE_INTERVAL;
+
+        let objects = world.objects();
+        self.wanderers.retain(|&h, _| objects.contains(h));
+
+        let elevation = world.elevation();
+        for &h in objects.critters(elevation) {
+            let radius = {
+                let obj = objects.get(h);
+                let idle = h != objects.dude()
+                    && !world.party().contains(&h)
+                    && !obj.flags.contains(Flag::TurnedOff)
+                    && obj.sub.as_critter()
+                        .map(|c| c.is_active() && c.combat.enemy.is_none())
+                        .unwrap_or(false);
+                let radius = obj.proto()
+                    .and_then(|p| p.sub.as_critter().map(|c| c.wander_radius))
+                    .unwrap_or(0);
+                if !idle || radius == 0 {
+                    continue;
+                }
+                radius
+            };
+
+            let pos = objects.get(h).pos();
+            let wanderer = self.wanderers.entry(h).or_insert_with(|| Wanderer {
+                home: pos,
+                next_time: time + Self::pause(),
+            });
+            if time < wanderer.next_time || obj_sequencer.is_running(h) {
+                continue;
+            }
+            wanderer.next_time = time + Self::pause();
+            if wanderer.home.elevation != pos.elevation {
+                // Moved by a script or an elevator.
+                wanderer.home = pos;
+            }
+
+            if let Some(point) = Self::target(world, h, pos, wanderer.home, radius) {
+                debug!("wander: {:?} walks from {:?} to {:?}", h, pos.point, point);
+                let seq = Chain::new();
+                seq.control()
+                    .cancellable(Move::new(h,
+                        PathTo::Point { point, neighbor_if_blocked: false }, CritterAnim::Walk))
+                    .finalizing(Stand::new(h));
+                obj_sequencer.replace(h, seq);
+            }
+        }
+    }
+
+    /// Picks a free hex for the critter `obj` at `pos` to walk to within the `radius` around
+    /// its `home`.
+    fn target(world: &World, obj: object::Handle, pos: EPoint, home: EPoint, radius: u32)
+        -> Option<Point>
+    {
+        (0..TARGET_ATTEMPTS)
+            .map(|_| {
+                let direction = Direction::from_ordinal(
+                    random(Stream::Misc, 0, Direction::len() as i32 - 1) as usize);
+                let distance = random(Stream::Misc, 1, radius as i32) as u32;
+                world.hex_grid().go_clipped(home.point, direction, distance)
+            })
+            .find(|&p| p != pos.point
+                && !world.objects().has_blocker_at(p.elevated(home.elevation), Some(obj)))
+    }
+
+    fn pause() -> Duration {
+        Duration::from_millis(random(Stream::Misc, MIN_PAUSE, MAX_PAUSE) as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::EntityKind;
+    use crate::graphics::geometry::hex;
+
+    #[test]
+    fn target_within_radius() {
+        let mut world = World::mock(Instant::now());
+        let h = world.mock_object(EntityKind::Critter, Some((0, (50, 50))));
+        let home = world.objects().get(h).pos();
+        let blockers: Vec<_> = Direction::iter()
+            .filter(|d| d.ordinal() % 2 == 0)
+            .map(|d| world.hex_grid().go_clipped(home.point, d, 2))
+            .collect();
+        for p in &blockers {
+            world.mock_object(EntityKind::Scenery, Some((0, (p.x, p.y))));
+        }
+
+        let mut found = 0;
+        for _ in 0..200 {
+            if let Some(p) = Wander::target(&world, h, home, home, 3) {
+                assert!(p != home.point);
+                assert!(hex::distance(home.point, p) <= 3);
+                assert!(!blockers.contains(&p));
+                found += 1;
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn target_blocked() {
+        let mut world = World::mock(Instant::now());
+        let h = world.mock_object(EntityKind::Critter, Some((0, (50, 50))));
+        let home = world.objects().get(h).pos();
+        for d in Direction::iter() {
+            let p = world.hex_grid().go_clipped(home.point, d, 1);
+            world.mock_object(EntityKind::Critter, Some((0, (p.x, p.y))));
+        }
+
+        for _ in 0..50 {
+            assert_eq!(Wander::target(&world, h, home, home, 1), None);
+        }
+    }
+}
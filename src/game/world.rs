@@ -1,36 +1,50 @@
 pub mod floating_text;
+pub mod overlay;
+pub mod query;
 
 use bstring::{bstr, BString};
 use enum_map::Enum;
+use enumflags2::BitFlags;
 use if_chain::if_chain;
 use log::*;
 use std::cmp;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use crate::asset::EntityKind;
+use crate::asset::{EntityKind, Flag, Material};
 use crate::asset::frame::{FrameId, FrameDb};
 use crate::asset::map::ELEVATION_COUNT;
 use crate::asset::message::Messages;
 use crate::asset::proto::{ProtoDb, ProtoId};
 use crate::game::GameTime;
+use crate::game::car::Car;
+use crate::game::drug::DrugEffects;
+use crate::game::explosive::Explosive;
 use crate::game::object::{self, *};
+use crate::game::rpg::Rpg;
+use crate::game::timer::ObjectTimers;
 use crate::graphics::{EPoint, Point, Rect};
 use crate::graphics::font::Fonts;
 use crate::graphics::geometry::TileGridView;
 use crate::graphics::geometry::camera::Camera;
 use crate::graphics::geometry::hex::{self, Direction};
 use crate::graphics::map::*;
-use crate::graphics::render::Canvas;
-use crate::util::VecExt;
+use crate::graphics::render::{Canvas, TextureHandle};
+use crate::util::{EnumExt, VecExt};
 use crate::util::array2d::Array2d;
 
 use floating_text::FloatingText;
+use overlay::Overlay;
+use query::Query;
 
 // scr_game_init()
 const START_GAME_TIME: GameTime = GameTime::from_decis(302400);
 
 const MAX_FLOATING_TEXTS: usize = 19;
+const FLOATING_TEXT_DELAY: Duration = Duration::from_millis(3_500);
+const FLOATING_TEXT_LINE_DELAY: Duration = Duration::from_millis(1_400);
+/// Play time it takes the game time to advance by one decisecond.
+const GAME_TIME_DECI: Duration = Duration::from_millis(100);
 
 #[derive(Clone, Copy, Debug, Enum, Eq, PartialEq)]
 pub enum ScrollDirection {
@@ -66,24 +80,39 @@ pub type WorldRef = std::rc::Rc<std::cell::RefCell<World>>;
 pub struct World {
     proto_db: Rc<ProtoDb>,
     frm_db: Rc<FrameDb>,
-    critter_names: Messages,
+    critter_names: Rc<Messages>,
     hex_grid: hex::TileGrid,
     camera: Camera,
     sqr_tiles: Vec<Option<Array2d<(u16, u16)>>>,
     objects: Objects,
+    /// Party members that follow the dude between maps. Doesn't include the dude.
+    party: Vec<object::Handle>,
     floating_texts: Vec<FloatingText>,
     update_time: Instant,
+    /// Play time that hasn't yet added up to a decisecond of the game time.
+    game_time_rem: Duration,
     fonts: Rc<Fonts>,
+    hex_mask: TextureHandle,
 
     pub game_time: GameTime,
+    /// Stays with the party when the map changes.
+    pub car: Car,
+    pub drug_effects: DrugEffects,
+    /// Timers of the armed explosives. Follow the carried explosives when the map changes, the
+    /// ones of the explosives left on the map are kept in its `MapState`.
+    pub explosive_timers: ObjectTimers<Explosive>,
     pub ambient_light: u32,
+    pub debug_overlays: BitFlags<Overlay>,
+    /// Whether the camera scrolls freely instead of being kept near the dude and stopped by
+    /// the scroll blockers.
+    pub free_camera: bool,
 }
 
 impl World {
     pub fn new(
         proto_db: Rc<ProtoDb>,
         frm_db: Rc<FrameDb>,
-        critter_names: Messages,
+        critter_names: Rc<Messages>,
         hex_grid: hex::TileGrid,
         viewport: Rect,
         update_time: Instant,
@@ -95,6 +124,7 @@ impl World {
             frm_db.clone(),
             proto_db.clone(),
         );
+        let hex_mask = overlay::hex_mask(frm_db.texture_factory());
         Self {
             proto_db,
             frm_db,
@@ -106,18 +136,67 @@ impl World {
             },
             sqr_tiles: Vec::with_default(ELEVATION_COUNT as usize),
             objects,
+            party: Vec::new(),
             floating_texts: Vec::new(),
             update_time,
+            game_time_rem: Duration::from_secs(0),
             fonts,
+            hex_mask,
             game_time: START_GAME_TIME,
+            car: Car::new(),
+            drug_effects: DrugEffects::new(),
+            explosive_timers: ObjectTimers::new(),
             ambient_light: 0x10000,
+            debug_overlays: BitFlags::empty(),
+            free_camera: false,
         }
     }
 
+    /// Empty world with asset databases that don't have any assets.
+    #[cfg(test)]
+    pub fn mock(now: Instant) -> Self {
+        use crate::fs::FileSystem;
+        use crate::graphics::render::TextureFactory;
+
+        let fs = Rc::new(FileSystem::mock());
+        let proto_db = Rc::new(ProtoDb::mock(fs.clone()));
+        let frm_db = Rc::new(FrameDb::mock(fs, TextureFactory::mock()));
+        Self::new(
+            proto_db,
+            frm_db,
+            Messages::default(),
+            hex::TileGrid::default(),
+            Rect::with_size(0, 0, 640, 380),
+            now,
+            Rc::new(Fonts::new()),
+        )
+    }
+
+    /// Creates object of the `kind` with 1x1 mock art and without proto.
+    #[cfg(test)]
+    pub fn mock_object(&mut self, kind: EntityKind, pos: Option<(u32, (i32, i32))>)
+        -> object::Handle
+    {
+        use crate::asset::{CritterAnim, WeaponKind};
+
+        let fid = if kind == EntityKind::Critter {
+            FrameId::new_critter(None, CritterAnim::Stand, WeaponKind::Unarmed, 1)
+        } else {
+            FrameId::new_generic(kind, 1)
+        }.unwrap();
+        self.frm_db.mock_frame(fid, 1, 1, 1);
+        self.objects.create(Some(fid), None, pos.map(|p| p.into()), None).handle()
+    }
+
     pub fn proto_db(&self) -> &ProtoDb {
         &self.proto_db
     }
 
+    /// Replaces the script names of critters (`game/scrname.msg`).
+    pub fn set_critter_names(&mut self, critter_names: Rc<Messages>) {
+        self.critter_names = critter_names;
+    }
+
     pub fn frm_db(&self) -> &FrameDb {
         &self.frm_db
     }
@@ -142,14 +221,121 @@ impl World {
         &mut self.objects
     }
 
+    /// Objects placed on the `elevation` ordered by hex position.
+    pub fn objects_on_elevation(&self, elevation: u32) -> Query {
+        Query::new(&self.objects, self.objects.on_elevation(elevation))
+    }
+
+    /// Critters placed on any elevation.
+    pub fn critters(&self) -> Query {
+        let objects = &self.objects;
+        Query::new(objects, (0..ELEVATION_COUNT)
+            .flat_map(move |e| objects.critters(e).iter().cloned()))
+    }
+
+    /// Items lying on the ground on any elevation. Items in inventories are not included.
+    pub fn items_on_ground(&self) -> Query {
+        let objects = &self.objects;
+        Query::new(objects, (0..ELEVATION_COUNT).flat_map(move |e| objects.on_elevation(e)))
+            .of_kind(EntityKind::Item)
+    }
+
     pub fn clear(&mut self) {
         for v in &mut self.sqr_tiles {
             *v = None;
         }
         self.objects.clear();
+        self.party.clear();
         self.floating_texts.clear();
     }
 
+    /// Clears the world and resets the game state kept in it for a new game.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.game_time = START_GAME_TIME;
+        self.game_time_rem = Duration::from_secs(0);
+        self.car = Car::new();
+        self.drug_effects = DrugEffects::new();
+        self.explosive_timers = ObjectTimers::new();
+    }
+
+    pub fn party(&self) -> &[object::Handle] {
+        &self.party
+    }
+
+    /// Returns `false` if `obj` is already in the party.
+    // partyMemberAdd
+    pub fn add_party_member(&mut self, obj: object::Handle) -> bool {
+        if obj == self.objects.dude() || self.party.contains(&obj) {
+            return false;
+        }
+        self.party.push(obj);
+        true
+    }
+
+    /// Returns `false` if `obj` is not in the party.
+    // partyMemberRemove
+    pub fn remove_party_member(&mut self, obj: object::Handle) -> bool {
+        let len = self.party.len();
+        self.party.retain(|&h| h != obj);
+        self.party.len() != len
+    }
+
+    /// Destroys `obj` and its inventory. If `obj` is a party member, it leaves the party.
+    // obj_destroy
+    pub fn destroy_object(&mut self, obj: object::Handle) {
+        self.remove_party_member(obj);
+        self.explosive_timers.remove(obj);
+        self.drug_effects.remove(obj);
+        self.objects.destroy(obj);
+    }
+
+    /// Places the living party members behind the dude to the left and right.
+    // partyMemberSyncPosition
+    pub fn sync_party_positions(&mut self) {
+        let (dude_pos, dude_dir) = {
+            let dude = self.objects.get(self.objects.dude());
+            (dude.pos(), dude.direction)
+        };
+        let cw = dude_dir.rotate_cw().rotate_cw();
+        let ccw = dude_dir.rotate_ccw().rotate_ccw();
+        let mut n = 0;
+        for &obj in &self.party {
+            let dead = self.objects.get(obj).sub.as_critter().map(|c| c.is_dead()).unwrap_or(true);
+            if dead {
+                continue;
+            }
+            let direction = if n % 2 != 0 { cw } else { ccw };
+            let distance = (n + 2) / 2;
+            let target = self.hex_grid.go(dude_pos.point, direction, distance)
+                .unwrap_or(dude_pos.point);
+            let pos = self.free_pos_near(target.elevated(dude_pos.elevation), obj)
+                .unwrap_or_else(|| target.elevated(dude_pos.elevation));
+            self.objects.set_pos(obj, Some(pos));
+            self.objects.get_mut(obj).direction = dude_dir;
+            n += 1;
+        }
+    }
+
+    // _objPMAttemptPlacement
+    fn free_pos_near(&self, pos: EPoint, obj: object::Handle) -> Option<EPoint> {
+        const MAX_DISTANCE: u32 = 3;
+        if !self.objects.has_blocker_at(pos, Some(obj)) {
+            return Some(pos);
+        }
+        for distance in 1..=MAX_DISTANCE {
+            for direction in Direction::iter() {
+                if let Some(p) = self.hex_grid.go(pos.point, direction, distance) {
+                    let p = p.elevated(pos.elevation);
+                    if !self.objects.has_blocker_at(p, Some(obj)) {
+                        return Some(p);
+                    }
+                }
+            }
+        }
+        None
+    }
+
     pub fn set_sqr_tiles(&mut self, sqr_tiles: Vec<Option<Array2d<(u16, u16)>>>) {
         assert_eq!(sqr_tiles.len(), ELEVATION_COUNT as usize);
         self.sqr_tiles = sqr_tiles;
@@ -165,6 +351,33 @@ impl World {
         self.sqr_tiles[elevation as usize].is_some()
     }
 
+    /// Returns material of the floor at `pos`: of the flat scenery lying there if any, otherwise
+    /// of the floor tile.
+    pub fn floor_material(&self, pos: EPoint) -> Option<Material> {
+        let scenery = self.objects.at(pos).iter()
+            .filter_map(|&h| {
+                let obj = self.objects.get(h);
+                if !obj.flags.contains(Flag::Flat) {
+                    return None;
+                }
+                let proto = obj.proto()?;
+                let material = proto.sub.as_scenery()?.material;
+                Some(material)
+            })
+            .next();
+        if scenery.is_some() || !self.has_elevation(pos.elevation) {
+            return scenery;
+        }
+
+        // Each square tile covers 2x2 hexes.
+        let sqr = Point::new(pos.point.x / 2, pos.point.y / 2);
+        let (floor, _) = self.sqr_tile(pos.elevation, sqr)?;
+        // Tile prototypes share indexes with the tile art.
+        let proto = self.proto_db.proto(ProtoId::new(EntityKind::SqrTile, floor.into())?).ok()?;
+        let material = proto.borrow().sub.as_sqr_tile()?.material;
+        Some(material)
+    }
+
     pub fn object_bounds(&self, obj: object::Handle, include_outline: bool) -> Rect {
         self.objects.bounds(obj, &self.camera.hex(), include_outline)
     }
@@ -180,38 +393,39 @@ impl World {
     }
 
     // object_under_mouse()
+    /// Picks the object under the screen point `pos` on the current elevation. Living critters
+    /// are preferred over the other objects they overlap. Objects behind an opaque wall and floor
+    /// decals are never picked.
     pub fn pick_object(&self, pos: Point, include_dude: bool) -> Option<object::Handle> {
-        let filter_dude = |oh: &&(object::Handle, object::Hit)| -> bool {
-            include_dude || oh.0 != self.objects().dude()
-        };
-        let hits = self.object_hit_test(pos);
-        let r = hits
-            .iter()
-            .filter(filter_dude)
-            .filter(|(_, h)| !h.translucent && !h.with_egg)
-            .filter(|&&(o, _)| {
-                let obj = self.objects.get(o);
-                if let SubObject::Critter(critter) = &obj.sub {
-                    !critter.combat.damage_flags.intersects(DamageFlag::Dead | DamageFlag::KnockedOut)
-                } else {
-                    true
-                }
-            })
-            .map(|&(o, _)| o)
-            .next();
-        if r.is_none() {
-            hits.iter()
-                .filter(filter_dude)
-                .map(|&(o, _)| o)
-                .next()
-        } else {
-            r
+        let dude = self.objects.dude();
+        let mut hits = self.object_hit_test(pos);
+        hits.retain(|&(o, _)| (include_dude || o != dude) && !self.objects.get(o).is_decal());
+
+        let is_solid = |h: &object::Hit| !h.translucent && !h.with_egg;
+        let wall = hits.iter()
+            .position(|(o, h)| is_solid(h) && self.objects.get(*o).kind() == EntityKind::Wall);
+        if let Some(i) = wall {
+            hits.truncate(i + 1);
         }
+
+        let is_down = |o: object::Handle| self.objects.get(o).sub.as_critter()
+            .map(|c| c.combat.damage_flags.intersects(DamageFlag::Dead | DamageFlag::KnockedOut))
+            .unwrap_or(false);
+        let is_living_critter = |o: object::Handle|
+            self.objects.get(o).kind() == EntityKind::Critter && !is_down(o);
+        hits.iter()
+            .find(|&&(o, ref h)| is_solid(h) && is_living_critter(o))
+            .or_else(|| hits.iter().find(|&&(o, ref h)| is_solid(h) && !is_down(o)))
+            .or_else(|| hits.first())
+            .map(|&(o, _)| o)
     }
 
     // object_name()
     pub fn object_name(&self, obj: object::Handle) -> Option<BString> {
         let obj = self.objects.get(obj);
+        if let Some(dude) = obj.sub.as_critter().and_then(|c| c.try_dude()) {
+            return Some(dude.name.clone());
+        }
         if_chain! {
             if obj.kind() == EntityKind::Critter;
             if let Some((_, prg_id)) = obj.script;
@@ -245,17 +459,43 @@ impl World {
         self.floating_texts.retain(|ft| ft.obj != obj);
     }
 
+    /// Applies the drug effects whose time has come. Returns messages for the dude.
+    pub fn update_drug_effects(&mut self, rpg: &Rpg) -> Vec<BString> {
+        self.drug_effects.update(self.game_time, &self.objects, rpg)
+    }
+
     pub fn update(&mut self, time: Instant) {
+        self.advance_game_time(time.saturating_duration_since(self.update_time));
         self.update_time = time;
         self.expire_floating_texts();
     }
 
+    /// Advances the game time at the pace of the play time.
+    fn advance_game_time(&mut self, elapsed: Duration) {
+        let elapsed = self.game_time_rem + elapsed;
+        let decis = (elapsed.as_millis() / GAME_TIME_DECI.as_millis()) as u32;
+        self.game_time_rem = elapsed - GAME_TIME_DECI * decis;
+        self.game_time = self.game_time.add_decis(decis);
+    }
+
     pub fn render(&self, canvas: &mut dyn Canvas, draw_roof: bool) {
         let elevation = self.elevation();
-        render_floor(canvas, &self.camera.sqr(), self.camera.viewport,
+        self.render_elevation(canvas, &self.camera, elevation, Some(self.egg()), draw_roof);
+
+        overlay::render(canvas, self, self.debug_overlays, &self.hex_mask);
+
+        self.objects().render_outlines(canvas, elevation, self.camera.viewport, &self.camera.hex());
+
+        self.render_floating_texts(canvas);
+    }
+
+    /// Renders floor, objects and optionally roof of the `elevation` as seen with the `camera`.
+    pub fn render_elevation(&self, canvas: &mut dyn Canvas, camera: &Camera, elevation: u32,
+            egg: Option<Egg>, draw_roof: bool) {
+        render_floor(canvas, &camera.sqr(), camera.viewport,
             |p| {
                 let fid = FrameId::new_generic(EntityKind::SqrTile,
-                    self.sqr_tiles[elevation as usize].as_ref().unwrap().get(p.x as usize, p.y as usize).unwrap().0).unwrap();
+                    self.sqr_tile(elevation, p)?.0).unwrap();
                 let frms = self.frm_db.get(fid).unwrap();
                 Some(frms.frame_lists[Direction::NE].frames[0].texture.clone())
             },
@@ -265,8 +505,7 @@ impl World {
             }
         );
 
-        self.objects().render(canvas, elevation, self.camera.viewport, &self.camera.hex(),
-            Some(self.egg()),
+        self.objects().render(canvas, elevation, camera.viewport, &camera.hex(), egg,
             |pos| if let Some(pos) = pos {
                 cmp::max(self.objects().light_grid().get_clipped(pos), self.ambient_light)
             } else {
@@ -274,18 +513,21 @@ impl World {
             });
 
         if draw_roof {
-            render_roof(canvas, &self.camera.sqr(), self.camera.viewport,
+            render_roof(canvas, &camera.sqr(), camera.viewport,
                 |p| {
-                    let id = self.sqr_tiles[elevation as usize].as_ref().unwrap()
-                        .get(p.x as usize, p.y as usize).unwrap().1;
+                    let id = self.sqr_tile(elevation, p)?.1;
                     let fid = FrameId::new_generic(EntityKind::SqrTile, id).unwrap();
                     Some(self.frm_db.get(fid).unwrap().first().texture.clone())
                 });
         }
+    }
 
-        self.objects().render_outlines(canvas, elevation, self.camera.viewport, &self.camera.hex());
-
-        self.render_floating_texts(canvas);
+    fn sqr_tile(&self, elevation: u32, p: Point) -> Option<(u16, u16)> {
+        let tiles = self.sqr_tiles[elevation as usize].as_ref().unwrap();
+        if p.x < 0 || p.y < 0 || p.x >= tiles.width() as i32 || p.y >= tiles.height() as i32 {
+            return None;
+        }
+        tiles.get(p.x as usize, p.y as usize).cloned()
     }
 
     pub fn scroll(&mut self, dir: ScrollDirection, amount: u32) -> u32 {
@@ -307,7 +549,9 @@ impl World {
 
             let new_pos_scr = hex::to_screen(new_pos) + hex::TILE_CENTER;
             let distance = dude_pos_scr - new_pos_scr;
-            if distance.x.abs() >= 480 || distance.y.abs() >= 400 { // TODO make configurable
+            // TODO make configurable
+            let too_far = distance.x.abs() >= 480 || distance.y.abs() >= 400;
+            if too_far && !self.free_camera {
                 break;
             }
 
@@ -315,7 +559,7 @@ impl World {
                 .iter()
                 .any(|&h| self.objects.get(h)
                     .proto_id() == Some(ProtoId::SCROLL_BLOCKER));
-            if blocker {
+            if blocker && !self.free_camera {
                 break;
             }
 
@@ -333,15 +577,21 @@ impl World {
         self.camera.look_at(p);
     }
 
-    fn egg(&self) -> Egg {
+    pub fn egg(&self) -> Egg {
         Egg {
             pos: self.objects.get(self.objects.dude()).pos().point,
             fid: FrameId::EGG,
         }
     }
 
+    pub fn has_floating_texts(&self) -> bool {
+        !self.floating_texts.is_empty()
+    }
+
     fn render_floating_texts(&self, canvas: &mut dyn Canvas) {
-        for floating_text in &self.floating_texts {
+        let mut placed = Vec::with_capacity(self.floating_texts.len());
+        // Newer texts are closer to the objects, older ones are pushed up above them.
+        for floating_text in self.floating_texts.iter().rev() {
             let screen_pos = if let Some(obj) = floating_text.obj {
                 let pos = if let Some(pos) = self.objects.get(obj).try_pos() {
                     pos
@@ -351,21 +601,25 @@ impl World {
                     continue;
                 };
                 if pos.elevation != self.elevation() {
-                    return;
+                    continue;
                 }
                 self.camera.hex().center_to_screen(pos.point) - Point::new(0, 60)
             } else {
                 self.camera.viewport.center()
             };
-            floating_text.render(screen_pos, self.camera.viewport, canvas);
+            let rect = FloatingText::stack(floating_text.rect(screen_pos, self.update_time),
+                &placed);
+            let alpha = FloatingText::alpha(
+                floating_text.expires_at(FLOATING_TEXT_DELAY, FLOATING_TEXT_LINE_DELAY),
+                self.update_time);
+            placed.push(floating_text.render(rect, self.camera.viewport, alpha, canvas));
         }
     }
 
     fn expire_floating_texts(&mut self) {
         let update_time = self.update_time;
         self.floating_texts.retain(|ft| {
-            let expires_at = ft.expires_at(Duration::from_millis(3_500),
-                Duration::from_millis(1_400));
+            let expires_at = ft.expires_at(FLOATING_TEXT_DELAY, FLOATING_TEXT_LINE_DELAY);
             expires_at > update_time
         })
     }
@@ -373,3 +627,42 @@ impl World {
 
 
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn destroyed_party_member_leaves_party() {
+        let mut world = World::mock(Instant::now());
+        let dead = world.mock_object(EntityKind::Critter, Some((0, (50, 50))));
+        let alive = world.mock_object(EntityKind::Critter, Some((0, (51, 50))));
+        world.party.push(dead);
+        world.party.push(alive);
+
+        world.destroy_object(dead);
+        assert!(!world.objects().contains(dead));
+        assert_eq!(world.party(), &[alive]);
+
+        // Map change takes the party members out of the map.
+        for obj in world.party().to_vec() {
+            world.objects_mut().remove_deep(obj);
+        }
+        assert!(!world.objects().contains(alive));
+    }
+
+    #[test]
+    fn destroyed_explosive_timer_is_removed() {
+        let mut world = World::mock(Instant::now());
+        let explosive = Explosive::inactive(ProtoId::DYNAMITE).unwrap();
+        let item1 = world.mock_object(EntityKind::Item, None);
+        let item2 = world.mock_object(EntityKind::Item, None);
+        let time = world.game_time.add_decis(100);
+        world.explosive_timers.add(time, item1, explosive);
+        world.explosive_timers.add(time, item2, explosive);
+
+        world.destroy_object(item1);
+        assert!(!world.explosive_timers.contains(item1));
+        assert!(world.explosive_timers.contains(item2));
+    }
+}
@@ -8,6 +8,13 @@ use crate::graphics::color::*;
 use crate::graphics::font::{self, FontKey, Fonts};
 use crate::graphics::render::{Canvas, Outline};
 
+/// How fast the text scrolls up, in pixels per second.
+const RISE_SPEED: u32 = 8;
+/// Max distance the text scrolls up, in pixels.
+const MAX_RISE: i32 = 24;
+/// The text fades out during this time before expiring.
+const FADE_OUT: Duration = Duration::from_millis(500);
+
 #[derive(Clone, Debug)]
 pub struct Options {
     pub font_key: FontKey,
@@ -60,11 +67,29 @@ impl FloatingText {
         }
     }
 
-    pub fn render(&self, pos: Point, rect: Rect, canvas: &mut dyn Canvas) {
-        let pos = Self::fit(
-            Rect::with_size(pos.x - self.width / 2, pos.y - self.height,
-                self.width, self.height),
-            rect);
+    /// Returns screen rect of the text anchored at `pos` (bottom center) at the `time`.
+    /// The text is scrolled up according to the time elapsed since it was shown.
+    pub fn rect(&self, pos: Point, time: Instant) -> Rect {
+        let rise = Self::rise(time.saturating_duration_since(self.time));
+        Rect::with_size(pos.x - self.width / 2, pos.y - self.height - rise,
+            self.width, self.height)
+    }
+
+    /// Moves the `rect` up until it doesn't overlap any of the `placed` rects.
+    pub fn stack(mut rect: Rect, placed: &[Rect]) -> Rect {
+        // Each step moves the rect strictly up so this always terminates.
+        while let Some(r) = placed.iter().find(|r| r.intersects(rect)) {
+            rect = rect.translate(Point::new(0, r.top - rect.bottom));
+        }
+        rect
+    }
+
+    /// Renders the text in `rect` as returned by `rect()`. The text is shifted to fit
+    /// `bound_rect` if needed. Returns the rect the text was actually rendered at.
+    pub fn render(&self, rect: Rect, bound_rect: Rect, alpha: u8, canvas: &mut dyn Canvas)
+        -> Rect
+    {
+        let pos = Self::fit(rect, bound_rect);
 
         let mut y = pos.y;
         for (line, line_width) in &self.lines {
@@ -73,10 +98,13 @@ impl FloatingText {
                 &font::DrawOptions {
                     outline: self.options.outline_color
                         .map(|color| Outline::Fixed { color, trans_color: None }),
+                    alpha: Some(alpha),
                     ..Default::default()
                 });
             y += self.vert_advance;
         }
+
+        Rect::with_size(pos.x, pos.y, self.width, self.height)
     }
 
     pub fn expires_at(&self, initial_delay: Duration, per_line_delay: Duration) -> Instant {
@@ -84,6 +112,20 @@ impl FloatingText {
         self.time + d
     }
 
+    /// Returns opacity of the text that expires at `expires_at` at the `time`.
+    pub fn alpha(expires_at: Instant, time: Instant) -> u8 {
+        let left = expires_at.saturating_duration_since(time);
+        if left >= FADE_OUT {
+            255
+        } else {
+            (left.as_millis() * 255 / FADE_OUT.as_millis()) as u8
+        }
+    }
+
+    fn rise(elapsed: Duration) -> i32 {
+        cmp::min((elapsed.as_millis() * RISE_SPEED as u128 / 1000) as i32, MAX_RISE)
+    }
+
     fn fit(rect: Rect, bound_rect: Rect) -> Point {
         #[inline(always)]
         fn fit0(lo: i32, hi: i32, bound_lo: i32, bound_hi: i32,
@@ -103,4 +145,41 @@ impl FloatingText {
             rect.height(), 0);
         Point::new(x, y)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rise() {
+        assert_eq!(FloatingText::rise(Duration::from_millis(0)), 0);
+        assert_eq!(FloatingText::rise(Duration::from_millis(1000)), RISE_SPEED as i32);
+        assert_eq!(FloatingText::rise(Duration::from_secs(100)), MAX_RISE);
+    }
+
+    #[test]
+    fn alpha() {
+        let t = Instant::now();
+        let expires_at = t + Duration::from_secs(2);
+        assert_eq!(FloatingText::alpha(expires_at, t), 255);
+        assert_eq!(FloatingText::alpha(expires_at, expires_at - FADE_OUT / 2), 127);
+        assert_eq!(FloatingText::alpha(expires_at, expires_at), 0);
+        assert_eq!(FloatingText::alpha(expires_at, expires_at + FADE_OUT), 0);
+    }
+
+    #[test]
+    fn stack() {
+        let placed = &[
+            Rect::with_size(0, 100, 50, 20),
+            Rect::with_size(0, 80, 50, 20),
+            Rect::with_size(200, 0, 50, 20),
+        ];
+        assert_eq!(FloatingText::stack(Rect::with_size(10, 110, 30, 10), placed),
+            Rect::with_size(10, 70, 30, 10));
+        assert_eq!(FloatingText::stack(Rect::with_size(60, 110, 30, 10), placed),
+            Rect::with_size(60, 110, 30, 10));
+        assert_eq!(FloatingText::stack(Rect::with_size(10, 120, 30, 10), placed),
+            Rect::with_size(10, 120, 30, 10));
+    }
+}
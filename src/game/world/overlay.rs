@@ -0,0 +1,142 @@
+//! Debug overlays that tint the hexes of the world view by the blocking state, light level and
+//! sight blocking, and mark the last computed path.
+
+use enumflags2::{bitflags, BitFlags};
+use std::cmp;
+
+use crate::graphics::{EPoint, Point};
+use crate::graphics::color::Rgb15;
+use crate::graphics::geometry::TileGridView;
+use crate::graphics::geometry::hex::{self, TILE_HEIGHT, TILE_WIDTH};
+use crate::graphics::render::{Canvas, TextureFactory, TextureHandle};
+
+use super::World;
+
+#[bitflags]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Overlay {
+    Blockers    = 1,
+    Light       = 2,
+    Sight       = 4,
+    Path        = 8,
+}
+
+impl Overlay {
+    pub const ALL: [Overlay; 4] = [
+        Overlay::Blockers,
+        Overlay::Light,
+        Overlay::Sight,
+        Overlay::Path,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Overlay::Blockers => "blockers",
+            Overlay::Light => "light",
+            Overlay::Sight => "sight",
+            Overlay::Path => "path",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        Self::ALL.iter().copied().find(|o| o.name() == name)
+    }
+
+    fn color(self) -> Rgb15 {
+        match self {
+            Overlay::Blockers => Rgb15::new(31, 0, 0),
+            Overlay::Light => Rgb15::new(31, 31, 0),
+            Overlay::Sight => Rgb15::new(0, 0, 31),
+            Overlay::Path => Rgb15::new(0, 31, 0),
+        }
+    }
+}
+
+/// Alpha of the overlay hex tint.
+const ALPHA: u8 = 128;
+
+/// Returns hex-shaped mask for `Canvas::draw_masked_color()`.
+pub fn hex_mask(texture_factory: &TextureFactory) -> TextureHandle {
+    let mut data = Vec::with_capacity((TILE_WIDTH * TILE_HEIGHT) as usize);
+    for y in 0..TILE_HEIGHT {
+        for x in 0..TILE_WIDTH {
+            data.push(if hex::tile_contains((x, y).into()) { 7 } else { 0 });
+        }
+    }
+    texture_factory.new_texture(TILE_WIDTH, TILE_HEIGHT, data.into())
+}
+
+/// Tints the hexes in the camera viewport for each of the `overlays`.
+pub fn render(canvas: &mut dyn Canvas, world: &World, overlays: BitFlags<Overlay>,
+    mask: &TextureHandle)
+{
+    if overlays.is_empty() {
+        return;
+    }
+    let elevation = world.elevation();
+    let objs = world.objects();
+    let dude = objs.dude();
+    let hex_view = world.camera().hex();
+    let tiles = hex_view.enclose(world.camera().viewport);
+
+    for y in tiles.top..tiles.bottom {
+        for x in tiles.left..tiles.right {
+            let point = (x, y).into();
+            if !world.hex_grid().is_in_bounds(point) {
+                continue;
+            }
+            let pos = EPoint { elevation, point };
+            for overlay in overlays.iter() {
+                match overlay {
+                    Overlay::Blockers => if objs.has_blocker_at(pos, None) {
+                        draw(canvas, &hex_view, mask, overlay, point, ALPHA);
+                    }
+                    Overlay::Light => {
+                        let light = cmp::max(objs.light_grid().get_clipped(pos),
+                            world.ambient_light);
+                        let alpha = (light.min(0x10000) * ALPHA as u32 / 0x10000) as u8;
+                        draw(canvas, &hex_view, mask, overlay, point, alpha);
+                    }
+                    Overlay::Sight => if objs.is_sight_blocked_at(dude, pos) {
+                        draw(canvas, &hex_view, mask, overlay, point, ALPHA);
+                    }
+                    Overlay::Path => {}
+                }
+            }
+        }
+    }
+
+    if overlays.contains(Overlay::Path) {
+        if let Some((start, path)) = objs.last_path() {
+            if start.elevation == elevation {
+                let mut point = start.point;
+                for dir in path {
+                    point = hex::go(point, dir, 1);
+                    draw(canvas, &hex_view, mask, Overlay::Path, point, ALPHA);
+                }
+            }
+        }
+    }
+}
+
+fn draw(canvas: &mut dyn Canvas, hex_view: &hex::View, mask: &TextureHandle, overlay: Overlay,
+    point: Point, alpha: u8)
+{
+    canvas.draw_masked_color(overlay.color(), None, hex_view.tile_to_screen(point), mask, alpha);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_name() {
+        for &o in &Overlay::ALL {
+            assert_eq!(Overlay::from_name(o.name()), Some(o));
+        }
+        assert_eq!(Overlay::from_name("LIGHT"), Some(Overlay::Light));
+        assert_eq!(Overlay::from_name("foo"), None);
+    }
+}
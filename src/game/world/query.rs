@@ -0,0 +1,106 @@
+use enumflags2::BitFlags;
+
+use crate::asset::{EntityKind, Flag};
+use crate::game::object::{Handle, Object, Objects};
+use crate::graphics::EPoint;
+use crate::graphics::geometry::hex;
+
+/// Iterator over object handles that can be narrowed down with filter combinators:
+///
+/// ```ignore
+/// let targets: Vec<_> = world.critters().at_elevation(0).alive().except(dude).collect();
+/// ```
+pub struct Query<'a> {
+    objects: &'a Objects,
+    iter: Box<dyn Iterator<Item=Handle> + 'a>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(objects: &'a Objects, iter: impl Iterator<Item=Handle> + 'a) -> Self {
+        Self {
+            objects,
+            iter: Box::new(iter),
+        }
+    }
+
+    /// Keeps objects for which `f` returns `true`.
+    pub fn matching(self, f: impl Fn(&Object) -> bool + 'a) -> Self {
+        let objects = self.objects;
+        Self::new(objects, self.iter.filter(move |&h| f(&objects.get(h))))
+    }
+
+    pub fn of_kind(self, kind: EntityKind) -> Self {
+        self.matching(move |o| o.kind() == kind)
+    }
+
+    pub fn except(self, obj: Handle) -> Self {
+        Self::new(self.objects, self.iter.filter(move |&h| h != obj))
+    }
+
+    pub fn at_elevation(self, elevation: u32) -> Self {
+        self.matching(move |o| o.try_pos().map(|p| p.elevation) == Some(elevation))
+    }
+
+    /// Keeps objects within `radius` from `pos` on the same elevation.
+    pub fn within(self, pos: EPoint, radius: u32) -> Self {
+        self.matching(move |o| o.try_pos()
+            .map(|p| p.elevation == pos.elevation && hex::distance(p.point, pos.point) <= radius)
+            .unwrap_or(false))
+    }
+
+    /// Keeps objects that have all of the `flags` set.
+    pub fn with_flags(self, flags: impl Into<BitFlags<Flag>>) -> Self {
+        let flags = flags.into();
+        self.matching(move |o| o.flags.contains(flags))
+    }
+
+    /// Keeps critters that are not dead.
+    pub fn alive(self) -> Self {
+        self.matching(|o| o.sub.as_critter().map(|c| !c.is_dead()).unwrap_or(false))
+    }
+}
+
+impl Iterator for Query<'_> {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::game::world::World;
+    use crate::graphics::Point;
+
+    #[test]
+    fn query() {
+        let mut world = World::mock(Instant::now());
+        let mut create = |kind, pos| world.mock_object(kind, pos);
+        let item = create(EntityKind::Item, Some((0, (10, 10))));
+        let critter = create(EntityKind::Critter, Some((0, (12, 10))));
+        let far_critter = create(EntityKind::Critter, Some((0, (30, 10))));
+        let upstairs_item = create(EntityKind::Item, Some((1, (10, 10))));
+        let upstairs_critter = create(EntityKind::Critter, Some((1, (5, 5))));
+        let _scenery = create(EntityKind::Scenery, Some((0, (11, 10))));
+        let _detached = create(EntityKind::Item, None);
+        world.objects().get_mut(far_critter).flags.insert(Flag::Seen);
+
+        let v = |q: Query| q.collect::<Vec<_>>();
+
+        assert_eq!(v(world.objects_on_elevation(1)), vec![upstairs_critter, upstairs_item]);
+        assert_eq!(v(world.critters()), vec![critter, far_critter, upstairs_critter]);
+        assert_eq!(v(world.items_on_ground()), vec![item, upstairs_item]);
+
+        assert_eq!(v(world.critters().at_elevation(0).except(critter)), vec![far_critter]);
+        assert_eq!(v(world.critters().with_flags(Flag::Seen)), vec![far_critter]);
+        let pos = EPoint::new(0, Point::new(10, 10));
+        assert_eq!(v(world.objects_on_elevation(0).within(pos, 2).of_kind(EntityKind::Critter)),
+            vec![critter]);
+        // Critters created without proto have no critter data.
+        assert_eq!(world.critters().alive().count(), 0);
+    }
+}
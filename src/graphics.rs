@@ -8,6 +8,7 @@ pub mod font;
 pub mod geometry;
 pub mod lighting;
 pub mod map;
+pub mod png;
 pub mod render;
 pub mod sprite;
 
@@ -243,6 +244,26 @@ impl Rect {
         }
     }
 
+    /// Returns the smallest rect containing both `self` and `other`.
+    pub fn union(&self, other: Self) -> Self {
+        Self {
+            left: cmp::min(self.left, other.left),
+            top: cmp::min(self.top, other.top),
+            right: cmp::max(self.right, other.right),
+            bottom: cmp::max(self.bottom, other.bottom),
+        }
+    }
+
+    /// Grows the rect by `dx` on the left and right and by `dy` on the top and bottom.
+    pub fn inflate(&self, dx: i32, dy: i32) -> Self {
+        Self {
+            left: self.left - dx,
+            top: self.top - dy,
+            right: self.right + dx,
+            bottom: self.bottom + dy,
+        }
+    }
+
     pub fn translate(&self, offset: Point) -> Self {
         Self {
             left: self.left + offset.x,
@@ -3,6 +3,8 @@ use super::*;
 #[derive(Debug)]
 pub struct PaletteOverlay {
     ranges: Vec<PaletteOverlayRange>,
+    /// Brightness of the whole palette in [0..128] range: 0 - black, 128 - original colors.
+    brightness: u8,
 }
 
 impl PaletteOverlay {
@@ -10,6 +12,7 @@ impl PaletteOverlay {
         ranges.sort_by_key(|r| r.start);
         Self {
             ranges,
+            brightness: 128,
         }
     }
 
@@ -58,9 +61,30 @@ impl PaletteOverlay {
         }
     }
 
-    pub fn rotate(&mut self, time: Instant) {
+    /// Returns `true` if any of the ranges was rotated.
+    pub fn rotate(&mut self, time: Instant) -> bool {
+        let mut r = false;
         for range in &mut self.ranges {
-            range.rotate(time);
+            r |= range.rotate(time);
+        }
+        r
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        assert!(brightness <= 128);
+        self.brightness = brightness;
+    }
+
+    /// Applies the palette brightness to the `color`.
+    pub fn apply_brightness(&self, color: Rgb18) -> Rgb18 {
+        if self.brightness == 128 {
+            color
+        } else {
+            color.darken(self.brightness)
         }
     }
 }
@@ -73,9 +97,9 @@ struct Rotation {
 }
 
 impl Rotation {
-    fn rotate(&mut self, time: Instant, len: u8) {
+    fn rotate(&mut self, time: Instant, len: u8) -> bool {
         if self.last_time.map(|lt| time - lt < self.period).unwrap_or(false) {
-            return;
+            return false;
         }
         if self.pos == 0 {
             self.pos = len - 1;
@@ -84,6 +108,7 @@ impl Rotation {
         }
         assert!(self.last_time.is_none() || self.last_time.unwrap() <= time);
         self.last_time = Some(time);
+        true
     }
 }
 
@@ -112,8 +137,8 @@ impl PaletteOverlayRange {
         }
     }
 
-    fn rotate(&mut self, time: Instant) {
-        self.rotation.rotate(time, self.colors.len() as u8);
+    fn rotate(&mut self, time: Instant) -> bool {
+        self.rotation.rotate(time, self.colors.len() as u8)
     }
 
     fn get(&self, color_idx: u8) -> Rgb18 {
@@ -150,7 +175,8 @@ mod test {
         assert_eq!(t.get(101), None);
 
         let tm = Instant::now();
-        t.rotate(tm);
+        assert!(t.rotate(tm));
+        assert!(!t.rotate(tm));
 
         assert_eq!(t.get(49), None);
         assert_eq!(t.get(50), Some(Rgb18::new(2, 2, 2)));
@@ -172,4 +198,15 @@ mod test {
         assert_eq!(t.get(51), Some(Rgb18::new(2, 2, 2)));
         assert_eq!(t.get(100), Some(Rgb18::new(5, 5, 5)));
     }
+
+    #[test]
+    fn brightness() {
+        let mut t = PaletteOverlay::new(Vec::new());
+        let c = Rgb18::new(40, 20, 10);
+        assert_eq!(t.apply_brightness(c), c);
+        t.set_brightness(64);
+        assert_eq!(t.apply_brightness(c), Rgb18::new(20, 10, 5));
+        t.set_brightness(0);
+        assert_eq!(t.apply_brightness(c), Rgb18::new(0, 0, 0));
+    }
 }
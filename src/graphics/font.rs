@@ -1,8 +1,10 @@
 use bstring::bstr;
 use enum_map_derive::Enum;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::ops::Range;
 
+use crate::asset::message::encoding::Encoding;
 use crate::graphics::Point;
 use crate::graphics::color::Rgb15;
 use crate::graphics::render::{Canvas, Outline, TextureHandle};
@@ -42,6 +44,13 @@ pub struct Overflow {
     pub action: OverflowAction,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shadow {
+    /// Offset of the shadow relative to the glyph.
+    pub offset: Point,
+    pub color: Rgb15,
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct DrawOptions {
     pub horz_align: HorzAlign,
@@ -49,6 +58,10 @@ pub struct DrawOptions {
     pub dst_color: Option<Rgb15>,
     pub outline: Option<Outline>,
     pub horz_overflow: Option<Overflow>,
+    /// Text opacity in range [0..255] where 0 is fully transparent. `None` is fully opaque.
+    /// The outline is not drawn for mostly transparent text.
+    pub alpha: Option<u8>,
+    pub shadow: Option<Shadow>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -122,6 +135,12 @@ impl Font {
 
     pub fn draw(&self, canvas: &mut dyn Canvas, text: &bstr, pos: Point, color: Rgb15,
             options: &DrawOptions) {
+        let alpha = options.alpha.unwrap_or(255);
+        if alpha == 0 {
+            return;
+        }
+        let outline = options.outline.filter(|_| alpha >= 128);
+
         let mut y = match options.vert_align {
             VertAlign::Top => pos.y,
             VertAlign::Middle => pos.y - self.text_height(text, options.horz_overflow) / 2,
@@ -138,9 +157,15 @@ impl Font {
                 let glyph = &self.glyphs[c as usize];
                 let y = y + self.height - glyph.height;
 
-                canvas.draw_masked_color(color, options.dst_color, Point::new(x, y), &glyph.texture);
+                if let Some(shadow) = options.shadow {
+                    canvas.draw_masked_color(shadow.color, options.dst_color,
+                        Point::new(x, y) + shadow.offset, &glyph.texture, alpha);
+                }
+
+                canvas.draw_masked_color(color, options.dst_color, Point::new(x, y),
+                    &glyph.texture, alpha);
 
-                if let Some(outline) = options.outline {
+                if let Some(outline) = outline {
                     canvas.draw_outline(&glyph.texture, Point::new(x, y), outline);
                 }
                 x += glyph.width + self.horz_spacing;
@@ -284,15 +309,27 @@ pub struct Glyph {
 
 pub struct Fonts {
     fonts: HashMap<FontKey, Font>,
+    encoding: Cell<Encoding>,
 }
 
 impl Fonts {
     pub fn new() -> Self {
         Self {
             fonts: HashMap::new(),
+            encoding: Cell::new(Encoding::default()),
         }
     }
 
+    /// Codepage the glyphs are indexed by.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding.get()
+    }
+
+    /// Can be changed while the fonts are shared to switch the language at runtime.
+    pub fn set_encoding(&self, encoding: Encoding) {
+        self.encoding.set(encoding);
+    }
+
     pub fn insert(&mut self, key: FontKey, font: Font) {
         let existing = self.fonts.insert(key, font);
         assert!(existing.is_none());
@@ -301,4 +338,8 @@ impl Fonts {
     pub fn get(&self, key: FontKey) -> &Font {
         &self.fonts[&key]
     }
+
+    pub fn remove(&mut self, key: FontKey) -> Option<Font> {
+        self.fonts.remove(&key)
+    }
 }
@@ -418,6 +418,11 @@ fn tile_hit_test(p: Point) -> TileHit {
     TileHit::Inside
 }
 
+/// Whether the point `p` relative to the tile's top left corner is inside the tile hex.
+pub fn tile_contains(p: Point) -> bool {
+    tile_hit_test(p) == TileHit::Inside
+}
+
 #[derive(Clone, Debug)]
 pub struct TileGrid {
     // Width in tiles.
@@ -1,4 +1,4 @@
-use crate::graphics::geometry::{hex, TileGridView};
+use crate::graphics::geometry::{hex, sqr, TileGridView};
 use crate::graphics::lighting::light_map::{VERTEX_COUNT, VERTEX_HEXES};
 use crate::graphics::{Point, Rect};
 use crate::graphics::render::{Canvas, TextureHandle};
@@ -17,6 +17,35 @@ pub fn render_roof(canvas: &mut dyn Canvas, stg: &impl TileGridView, rect: Rect,
     render_square_tiles(canvas, stg, rect, ROOF_HEIGHT, get_tex, |_| 0x10000);
 }
 
+/// Returns screen rect that encloses everything rendered on the map with `hex_grid` when the
+/// top left hex tile is at the screen origin.
+pub fn map_screen_rect(hex_grid: &hex::TileGrid) -> Rect {
+    // Room for tall objects and roofs above the top tiles.
+    const TOP_MARGIN: i32 = 240;
+    const MARGIN: i32 = 40;
+
+    // See Camera::sqr().
+    let sqr_offset = Point::new(-16, -2);
+    let hex_w = hex_grid.width();
+    let hex_h = hex_grid.height();
+    let mut points = Vec::with_capacity(16);
+    for &(x, y) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let hex_pos = hex::to_screen(Point::new(x * (hex_w - 1), y * (hex_h - 1)));
+        points.push(hex_pos);
+        points.push(hex_pos + Point::new(hex::TILE_WIDTH, hex::TILE_HEIGHT));
+        let sqr_pos = sqr::to_screen(Point::new(x * (hex_w / 2 - 1), y * (hex_h / 2 - 1)))
+            + sqr_offset;
+        points.push(sqr_pos);
+        points.push(sqr_pos + Point::new(sqr::TILE_WIDTH, sqr::TILE_HEIGHT));
+    }
+    Rect {
+        left: points.iter().map(|p| p.x).min().unwrap() - MARGIN,
+        top: points.iter().map(|p| p.y).min().unwrap() - TOP_MARGIN,
+        right: points.iter().map(|p| p.x).max().unwrap() + MARGIN,
+        bottom: points.iter().map(|p| p.y).max().unwrap() + MARGIN,
+    }
+}
+
 fn render_square_tiles(canvas: &mut dyn Canvas, stg: &impl TileGridView, rect: Rect,
         y_offset: i32,
         mut get_tex: impl FnMut(Point) -> Option<TextureHandle>,
@@ -66,6 +95,24 @@ pub fn is_scroll_limited(pos: Point, dude_pos: Point) -> bool {
 //     || tile_y >= g_map_border_tile_y_max)
 //}
 
+#[cfg(test)]
+mod test {
+    use super::*;
 
-
-
+    #[test]
+    fn map_screen_rect_() {
+        let grid = hex::TileGrid::default();
+        let rect = map_screen_rect(&grid);
+        for &(x, y) in &[(0, 0), (199, 0), (0, 199), (199, 199), (100, 100)] {
+            let p = hex::to_screen(Point::new(x, y));
+            assert!(rect.contains(p));
+            assert!(rect.contains(p + Point::new(hex::TILE_WIDTH, hex::TILE_HEIGHT)));
+        }
+        for &(x, y) in &[(0, 0), (99, 0), (0, 99), (99, 99)] {
+            let p = sqr::to_screen(Point::new(x, y)) - Point::new(16, 2);
+            assert!(rect.contains(p));
+            assert!(rect.contains(p + Point::new(sqr::TILE_WIDTH, sqr::TILE_HEIGHT)));
+        }
+        assert_eq!(rect, Rect { left: -56, top: -1430, right: 8040, bottom: 2450 });
+    }
+}
@@ -0,0 +1,527 @@
+//! Minimal PNG reader and writer for 8-bit depth images.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+use crate::graphics::color::Rgb24;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const COLOR_TYPE_GRAY: u8 = 0;
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_INDEXED: u8 = 3;
+const COLOR_TYPE_GRAY_ALPHA: u8 = 4;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+struct Crc32 {
+    table: [u32; 256],
+    value: u32,
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        let mut table = [0; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 {
+                    0xedb8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+            }
+            *v = c;
+        }
+        Self {
+            table,
+            value: 0xffff_ffff,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.value = 0xffff_ffff;
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.value = self.table[((self.value ^ b as u32) & 0xff) as usize] ^ (self.value >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.value ^ 0xffff_ffff
+    }
+}
+
+struct Writer<'a, W> {
+    w: &'a mut W,
+    crc: Crc32,
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    fn new(w: &'a mut W) -> io::Result<Self> {
+        w.write_all(&SIGNATURE)?;
+        Ok(Self {
+            w,
+            crc: Crc32::new(),
+        })
+    }
+
+    fn chunk(&mut self, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        self.w.write_u32::<BigEndian>(data.len() as u32)?;
+        self.w.write_all(kind)?;
+        self.w.write_all(data)?;
+        self.crc.reset();
+        self.crc.update(kind);
+        self.crc.update(data);
+        self.w.write_u32::<BigEndian>(self.crc.finish())
+    }
+
+    fn header(&mut self, width: u32, height: u32, color_type: u8) -> io::Result<()> {
+        let mut data = Vec::with_capacity(13);
+        data.write_u32::<BigEndian>(width)?;
+        data.write_u32::<BigEndian>(height)?;
+        // Bit depth, color type, compression, filter and interlace methods.
+        data.extend_from_slice(&[8, color_type, 0, 0, 0]);
+        self.chunk(b"IHDR", &data)
+    }
+
+    fn palette(&mut self, palette: &[Rgb24], transparent: Option<u8>) -> io::Result<()> {
+        assert!(!palette.is_empty() && palette.len() <= 256);
+        let data: Vec<_> = palette.iter().flat_map(|c| vec![c.r(), c.g(), c.b()]).collect();
+        self.chunk(b"PLTE", &data)?;
+        if let Some(transparent) = transparent {
+            let mut data = vec![0xff; transparent as usize + 1];
+            data[transparent as usize] = 0;
+            self.chunk(b"tRNS", &data)?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> io::Result<()> {
+        self.chunk(b"IEND", &[])
+    }
+}
+
+/// Compresses rows of `row_len` bytes as PNG image data.
+fn compress(data: &[u8], row_len: usize) -> io::Result<Vec<u8>> {
+    let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+    if row_len > 0 {
+        for row in data.chunks(row_len) {
+            // No filter.
+            enc.write_all(&[0])?;
+            enc.write_all(row)?;
+        }
+    }
+    enc.finish()
+}
+
+/// Writes `width` x `height` image as PNG. The `rgb` contains rows of pixels top to bottom,
+/// 3 bytes per pixel.
+pub fn write_rgb(w: &mut impl Write, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(rgb.len(), width as usize * height as usize * 3);
+
+    let mut w = Writer::new(w)?;
+    w.header(width, height, COLOR_TYPE_RGB)?;
+    w.chunk(b"IDAT", &compress(rgb, width as usize * 3)?)?;
+    w.end()
+}
+
+/// Writes `width` x `height` image with `pixels` being indices into the `palette` as PNG.
+/// If `transparent` is not `None` the pixels with this index are fully transparent.
+pub fn write_indexed(w: &mut impl Write, width: u32, height: u32, palette: &[Rgb24],
+    transparent: Option<u8>, pixels: &[u8]) -> io::Result<()>
+{
+    assert_eq!(pixels.len(), width as usize * height as usize);
+
+    let mut w = Writer::new(w)?;
+    w.header(width, height, COLOR_TYPE_INDEXED)?;
+    w.palette(palette, transparent)?;
+    w.chunk(b"IDAT", &compress(pixels, width as usize)?)?;
+    w.end()
+}
+
+/// Same as `write_indexed()` but writes animated PNG (APNG) with the `frames` shown for
+/// `delay` seconds each (numerator and denominator) and looped forever. Each frame must be of
+/// `width` x `height` size.
+pub fn write_indexed_animated(w: &mut impl Write, width: u32, height: u32, palette: &[Rgb24],
+    transparent: Option<u8>, frames: &[&[u8]], delay: (u16, u16)) -> io::Result<()>
+{
+    assert!(!frames.is_empty());
+
+    let mut w = Writer::new(w)?;
+    w.header(width, height, COLOR_TYPE_INDEXED)?;
+
+    let mut data = Vec::with_capacity(8);
+    data.write_u32::<BigEndian>(frames.len() as u32)?;
+    // Number of plays, 0 is infinite.
+    data.write_u32::<BigEndian>(0)?;
+    w.chunk(b"acTL", &data)?;
+
+    w.palette(palette, transparent)?;
+
+    let mut seq = 0;
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.len(), width as usize * height as usize);
+
+        let mut data = Vec::with_capacity(26);
+        data.write_u32::<BigEndian>(seq)?;
+        data.write_u32::<BigEndian>(width)?;
+        data.write_u32::<BigEndian>(height)?;
+        // X and Y offsets.
+        data.write_u32::<BigEndian>(0)?;
+        data.write_u32::<BigEndian>(0)?;
+        data.write_u16::<BigEndian>(delay.0)?;
+        data.write_u16::<BigEndian>(delay.1)?;
+        // Dispose to transparent black, overwrite the region.
+        data.extend_from_slice(&[1, 0]);
+        w.chunk(b"fcTL", &data)?;
+        seq += 1;
+
+        let compressed = compress(frame, width as usize)?;
+        if i == 0 {
+            w.chunk(b"IDAT", &compressed)?;
+        } else {
+            let mut data = Vec::with_capacity(compressed.len() + 4);
+            data.write_u32::<BigEndian>(seq)?;
+            data.extend_from_slice(&compressed);
+            w.chunk(b"fdAT", &data)?;
+            seq += 1;
+        }
+    }
+
+    w.end()
+}
+
+/// Pixels of a decoded PNG image, row by row top to bottom.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pixels {
+    /// 4 bytes per pixel.
+    Rgba(Vec<u8>),
+    Indexed {
+        palette: Vec<Rgb24>,
+        /// Alpha of the palette entries.
+        alpha: Vec<u8>,
+        pixels: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Pixels,
+}
+
+impl Image {
+    /// Returns color and alpha of the pixel at `x`, `y`.
+    pub fn rgba(&self, x: u32, y: u32) -> (Rgb24, u8) {
+        assert!(x < self.width && y < self.height);
+        let i = (y * self.width + x) as usize;
+        match &self.pixels {
+            Pixels::Rgba(data) => {
+                let p = &data[i * 4..i * 4 + 4];
+                (Rgb24::new(p[0], p[1], p[2]), p[3])
+            }
+            Pixels::Indexed { palette, alpha, pixels } => {
+                let idx = pixels[i] as usize;
+                (palette.get(idx).copied().unwrap_or_else(Rgb24::black),
+                    alpha.get(idx).copied().unwrap_or(0xff))
+            }
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid PNG: {}", msg))
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reverses the per-row filtering of the decompressed image `data`.
+fn unfilter(data: &[u8], width: usize, height: usize, bpp: usize) -> io::Result<Vec<u8>> {
+    let stride = width * bpp;
+    if data.len() < (stride + 1) * height {
+        return Err(invalid_data("image data is truncated"));
+    }
+    let mut r = vec![0; stride * height];
+    for y in 0..height {
+        let src = &data[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let filter = src[0];
+        let src = &src[1..];
+        let (prev, cur) = r.split_at_mut(y * stride);
+        let prev = if y > 0 { &prev[(y - 1) * stride..] } else { &[][..] };
+        let cur = &mut cur[..stride];
+        for i in 0..stride {
+            let a = if i >= bpp { cur[i - bpp] } else { 0 };
+            let b = prev.get(i).copied().unwrap_or(0);
+            let c = if i >= bpp { prev.get(i - bpp).copied().unwrap_or(0) } else { 0 };
+            let pred = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid_data("unknown filter type")),
+            };
+            cur[i] = src[i].wrapping_add(pred);
+        }
+    }
+    Ok(r)
+}
+
+/// Reads non-interlaced PNG image of 8-bit depth.
+pub fn read(rd: &mut impl Read) -> io::Result<Image> {
+    let mut sig = [0; 8];
+    rd.read_exact(&mut sig)?;
+    if sig != SIGNATURE {
+        return Err(invalid_data("bad signature"));
+    }
+
+    let mut crc = Crc32::new();
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut trns = Vec::new();
+    let mut idat = Vec::new();
+    loop {
+        let len = rd.read_u32::<BigEndian>()? as usize;
+        let mut kind = [0; 4];
+        rd.read_exact(&mut kind)?;
+        let mut data = vec![0; len];
+        rd.read_exact(&mut data)?;
+        crc.reset();
+        crc.update(&kind);
+        crc.update(&data);
+        if rd.read_u32::<BigEndian>()? != crc.finish() {
+            return Err(invalid_data("chunk CRC mismatch"));
+        }
+
+        match &kind {
+            b"IHDR" => {
+                if data.len() != 13 {
+                    return Err(invalid_data("bad IHDR chunk"));
+                }
+                let mut r = &data[..];
+                let width = r.read_u32::<BigEndian>()?;
+                let height = r.read_u32::<BigEndian>()?;
+                let (depth, color_type, interlace) = (r[0], r[1], r[4]);
+                if depth != 8 {
+                    return Err(invalid_data("only 8-bit depth is supported"));
+                }
+                if interlace != 0 {
+                    return Err(invalid_data("interlaced images are not supported"));
+                }
+                header = Some((width, height, color_type));
+            }
+            b"PLTE" => {
+                palette = data.chunks_exact(3)
+                    .map(|c| Rgb24::new(c[0], c[1], c[2]))
+                    .collect();
+            }
+            b"tRNS" => trns = data,
+            b"IDAT" => idat.extend_from_slice(&data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, color_type) = header.ok_or_else(|| invalid_data("no IHDR chunk"))?;
+    let channels = match color_type {
+        COLOR_TYPE_GRAY | COLOR_TYPE_INDEXED => 1,
+        COLOR_TYPE_GRAY_ALPHA => 2,
+        COLOR_TYPE_RGB => 3,
+        COLOR_TYPE_RGBA => 4,
+        _ => return Err(invalid_data("unknown color type")),
+    };
+
+    let mut data = Vec::new();
+    ZlibDecoder::new(&idat[..]).read_to_end(&mut data)?;
+    let data = unfilter(&data, width as usize, height as usize, channels)?;
+
+    // Color key for gray and RGB images.
+    let key: Vec<_> = trns.chunks_exact(2).map(|c| c[1]).collect();
+    let pixels = match color_type {
+        COLOR_TYPE_INDEXED => Pixels::Indexed {
+            palette,
+            alpha: trns,
+            pixels: data,
+        },
+        COLOR_TYPE_RGBA => Pixels::Rgba(data),
+        _ => Pixels::Rgba(data.chunks_exact(channels)
+            .flat_map(|p| {
+                let (rgb, a) = match p.len() {
+                    1 => ([p[0]; 3], if key == [p[0]] { 0 } else { 0xff }),
+                    2 => ([p[0]; 3], p[1]),
+                    _ => ([p[0], p[1], p[2]], if key == p { 0 } else { 0xff }),
+                };
+                vec![rgb[0], rgb[1], rgb[2], a]
+            })
+            .collect()),
+    };
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xcbf4_3926);
+    }
+
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], &SIGNATURE);
+        let mut r = Cursor::new(&png[8..]);
+        let mut chunks = Vec::new();
+        while (r.position() as usize) < png.len() - 8 {
+            let len = r.read_u32::<BigEndian>().unwrap() as usize;
+            let mut kind = [0; 4];
+            r.read_exact(&mut kind).unwrap();
+            let mut data = vec![0; len];
+            r.read_exact(&mut data).unwrap();
+            let actual_crc = r.read_u32::<BigEndian>().unwrap();
+            let mut crc = Crc32::new();
+            crc.update(&kind);
+            crc.update(&data);
+            assert_eq!(actual_crc, crc.finish());
+            chunks.push((kind, data));
+        }
+        chunks
+    }
+
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut r = Vec::new();
+        ZlibDecoder::new(data).read_to_end(&mut r).unwrap();
+        r
+    }
+
+    #[test]
+    fn write_rgb_() {
+        let rgb = [1, 2, 3, 4, 5, 6,
+                   7, 8, 9, 10, 11, 12];
+        let mut png = Vec::new();
+        write_rgb(&mut png, 2, 2, &rgb).unwrap();
+
+        let chunks = chunks(&png);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(&chunks[0].0, b"IHDR");
+        assert_eq!(chunks[0].1, &[0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert_eq!(&chunks[1].0, b"IDAT");
+        assert_eq!(inflate(&chunks[1].1), &[0, 1, 2, 3, 4, 5, 6,
+                                            0, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(&chunks[2].0, b"IEND");
+        assert!(chunks[2].1.is_empty());
+        assert_eq!(&png[png.len() - 4..], &[0xae, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn write_indexed_() {
+        let palette = [Rgb24::new(1, 2, 3), Rgb24::new(4, 5, 6), Rgb24::new(7, 8, 9)];
+        let mut png = Vec::new();
+        write_indexed(&mut png, 3, 1, &palette, Some(1), &[2, 1, 0]).unwrap();
+
+        let chunks = chunks(&png);
+        let kinds: Vec<_> = chunks.iter().map(|c| &c.0).collect();
+        assert_eq!(kinds, &[b"IHDR", b"PLTE", b"tRNS", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, &[0, 0, 0, 3, 0, 0, 0, 1, 8, 3, 0, 0, 0]);
+        assert_eq!(chunks[1].1, &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(chunks[2].1, &[0xff, 0]);
+        assert_eq!(inflate(&chunks[3].1), &[0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn write_indexed_animated_() {
+        let palette = [Rgb24::new(1, 2, 3), Rgb24::new(4, 5, 6)];
+        let mut png = Vec::new();
+        let frames = [&[0][..], &[1][..]];
+        write_indexed_animated(&mut png, 1, 1, &palette, None, &frames, (1, 10)).unwrap();
+
+        let chunks = chunks(&png);
+        let kinds: Vec<_> = chunks.iter().map(|c| &c.0).collect();
+        assert_eq!(kinds, &[b"IHDR", b"acTL", b"PLTE", b"fcTL", b"IDAT", b"fcTL", b"fdAT",
+            b"IEND"]);
+        assert_eq!(chunks[1].1, &[0, 0, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(chunks[3].1, &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 1, 0, 10, 1, 0]);
+        assert_eq!(inflate(&chunks[4].1), &[0, 0]);
+        assert_eq!(&chunks[5].1[..4], &[0, 0, 0, 1]);
+        assert_eq!(&chunks[6].1[..4], &[0, 0, 0, 2]);
+        assert_eq!(inflate(&chunks[6].1[4..]), &[0, 1]);
+    }
+
+    #[test]
+    fn read_indexed() {
+        let palette = [Rgb24::new(1, 2, 3), Rgb24::new(4, 5, 6), Rgb24::new(7, 8, 9)];
+        let pixels = [2, 1, 0,
+                      0, 0, 1];
+        let mut png = Vec::new();
+        write_indexed(&mut png, 3, 2, &palette, Some(1), &pixels).unwrap();
+
+        let img = read(&mut Cursor::new(&png)).unwrap();
+        assert_eq!(img, Image {
+            width: 3,
+            height: 2,
+            pixels: Pixels::Indexed {
+                palette: palette.to_vec(),
+                alpha: vec![0xff, 0],
+                pixels: pixels.to_vec(),
+            },
+        });
+        assert_eq!(img.rgba(0, 0), (Rgb24::new(7, 8, 9), 0xff));
+        assert_eq!(img.rgba(2, 1), (Rgb24::new(4, 5, 6), 0));
+    }
+
+    #[test]
+    fn read_rgb() {
+        let rgb = [1, 2, 3, 4, 5, 6,
+                   7, 8, 9, 10, 11, 12];
+        let mut png = Vec::new();
+        write_rgb(&mut png, 2, 2, &rgb).unwrap();
+
+        let img = read(&mut Cursor::new(&png)).unwrap();
+        assert_eq!((img.width, img.height), (2, 2));
+        assert_eq!(img.rgba(1, 1), (Rgb24::new(10, 11, 12), 0xff));
+
+        png[20] ^= 1;
+        assert!(read(&mut Cursor::new(&png)).is_err());
+    }
+
+    #[test]
+    fn unfilter_() {
+        // Sub, Up, Average and Paeth filters with 1 byte per pixel.
+        let data = [1, 1, 2, 3,
+                    2, 1, 1, 1,
+                    3, 2, 2, 2,
+                    4, 1, 1, 1];
+        assert_eq!(unfilter(&data, 3, 4, 1).unwrap(), &[
+            1, 3, 6,
+            2, 4, 7,
+            3, 5, 8,
+            4, 6, 9,
+        ]);
+        assert!(unfilter(&[5, 0], 1, 1, 1).is_err());
+        assert!(unfilter(&[0], 1, 1, 1).is_err());
+    }
+}
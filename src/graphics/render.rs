@@ -1,3 +1,6 @@
+pub mod atlas;
+pub mod golden;
+pub mod null;
 pub mod software;
 
 use bstring::bstr;
@@ -44,6 +47,12 @@ enum TextureFactoryInner {
 }
 
 impl TextureFactory {
+    /// Texture factory not backed by any canvas.
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        TextureFactory(TextureFactoryInner::Software(software::Textures::new()))
+    }
+
     pub fn new_texture(&self, width: i32, height: i32, data: Box<[u8]>) -> TextureHandle {
         match self.0 {
             TextureFactoryInner::Software(ref i) => i.new_texture(width, height, data),
@@ -61,17 +70,73 @@ pub enum Outline {
     Cycled { start: u8, len: u8 },
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AtlasPageInfo {
+    pub width: i32,
+    pub height: i32,
+    pub stats: atlas::PageStats,
+}
+
+/// Contents of the canvas back buffer.
+pub struct Screenshot {
+    pub width: i32,
+    pub height: i32,
+    /// Rows of pixels top to bottom, 3 bytes (red, green, blue) per pixel.
+    pub data: Vec<u8>,
+}
+
 pub trait Canvas {
     fn cleanup(&mut self);
+
+    /// Presents the back buffer and resets the invalidated area.
     fn present(&mut self);
+
+    /// Fits the back buffer into the window after the window was resized or moved to a display
+    /// with different DPI.
+    fn resize(&mut self);
+
+    /// Returns the size of the back buffer in pixels.
+    fn size(&self) -> Point;
+
+    /// Returns the number of window coordinate units a back buffer pixel spans.
+    fn window_scale(&self) -> f64;
+
+    /// Returns position of the back buffer top left corner in the window coordinates.
+    fn window_origin(&self) -> (f64, f64);
+
+    /// Whether the cursor can be shown using the system (hardware) cursor.
+    fn has_hardware_cursor(&self) -> bool;
+
+    /// Sets the image of the system cursor to the `texture` scaled to the window. The `hotspot`
+    /// is the cursor position relative to the texture top left corner.
+    fn set_hardware_cursor(&mut self, texture: &TextureHandle, hotspot: Point)
+        -> Result<(), String>;
+
+    /// Marks the `rect` of the screen as changed so it will be redrawn in the next frame.
+    fn invalidate(&mut self, rect: Rect);
+
+    /// Marks the whole screen as changed.
+    fn invalidate_all(&mut self);
+
+    /// Starts drawing of a new frame. Until the next `present()` all drawing (including
+    /// `clear()`) is clipped to the area invalidated since the last `present()`.
+    fn begin_frame(&mut self);
+
+    /// Returns the back buffer contents or `None` if the canvas doesn't keep one.
+    fn screenshot(&self) -> Option<Screenshot>;
+
     fn update(&mut self, time: Instant);
 
+    /// Sets brightness of the whole screen in [0..128] range: 0 - black, 128 - original colors.
+    fn set_brightness(&mut self, brightness: u8);
+
     fn fonts(&self) -> &Rc<Fonts>;
 
     fn set_clip_rect(&mut self, rect: Rect);
     fn reset_clip_rect(&mut self);
 
     fn clear(&mut self, color: Rgb15);
+    fn fill_rect(&mut self, rect: Rect, color: Rgb15);
 
     fn draw(&mut self, tex: &TextureHandle, pos: Point, light: u32);
     fn draw_multi_light(&mut self, tex: &TextureHandle, pos: Point, lights: &[u32]);
@@ -87,8 +152,9 @@ pub trait Canvas {
     /// `color`. `mask` values are in range [0..7]. Note the meaning here is inverted compared to
     /// `draw_masked()`: 0 is fully transparent `src` (and fully opaque `dst`),
     /// 7 is fully opaque `src` (and fully transparent `dst`).
+    /// The `mask` values are additionally scaled by `alpha` which is in range [0..255].
     fn draw_masked_color(&mut self, src: Rgb15, dst: Option<Rgb15>, pos: Point,
-                         mask: &TextureHandle);
+                         mask: &TextureHandle, alpha: u8);
 
     /// Similar to `draw_masked_color()` but the `mask` specifies combined alpha and lightening
     /// values. This is used for drawing screen glare effect in dialog window.
@@ -101,4 +167,10 @@ pub trait Canvas {
         options: &font::DrawOptions);
 
     fn draw_scaled(&mut self, src: &TextureHandle, dst: Rect);
+
+    /// Returns info about the pages of the atlas small textures are packed into.
+    fn atlas_pages(&self) -> Vec<AtlasPageInfo>;
+
+    /// Draws the whole atlas `page` scaled to `dst`. Used for debugging.
+    fn draw_atlas_page(&mut self, page: usize, dst: Rect);
 }
\ No newline at end of file
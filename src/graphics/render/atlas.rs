@@ -0,0 +1,139 @@
+//! Packing of small textures into shared atlas pages. Having many small textures (interface
+//! pieces, font glyphs, inventory items) in a few big pages reduces per-texture overhead.
+
+use crate::graphics::Point;
+
+/// Size of the (square) atlas page.
+pub const PAGE_SIZE: i32 = 1024;
+
+/// Textures with width or height above this are not put into atlas.
+pub const MAX_ITEM_SIZE: i32 = 128;
+
+/// Whether texture of the given size should be put into atlas.
+pub fn fits(width: i32, height: i32) -> bool {
+    width > 0 && height > 0 && width <= MAX_ITEM_SIZE && height <= MAX_ITEM_SIZE
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PageStats {
+    /// Number of textures in the page.
+    pub count: usize,
+    /// Total area of the textures in the page in pixels.
+    pub used_area: usize,
+}
+
+/// Shelf packer. Rectangles are put left to right into shelves (rows) which are stacked top to
+/// bottom. Space of released rectangles is reclaimed only when the whole page becomes empty.
+#[derive(Debug)]
+pub struct Packer {
+    width: i32,
+    height: i32,
+    shelves: Vec<Shelf>,
+    stats: PageStats,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Shelf {
+    top: i32,
+    height: i32,
+    /// Left of the free space.
+    right: i32,
+}
+
+impl Packer {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            shelves: Vec::new(),
+            stats: PageStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> PageStats {
+        self.stats
+    }
+
+    /// Returns top left position of the allocated rectangle or `None` if there's no room.
+    pub fn insert(&mut self, width: i32, height: i32) -> Option<Point> {
+        assert!(width > 0 && height > 0);
+
+        // The lowest shelf the rectangle fits into.
+        let page_width = self.width;
+        let best = self.shelves.iter()
+            .enumerate()
+            .filter(|(_, s)| s.height >= height && page_width - s.right >= width)
+            .min_by_key(|(_, s)| s.height)
+            .map(|(i, _)| i);
+        let i = if let Some(i) = best {
+            i
+        } else {
+            let top = self.shelves.last().map(|s| s.top + s.height).unwrap_or(0);
+            if top + height > self.height || width > self.width {
+                return None;
+            }
+            self.shelves.push(Shelf {
+                top,
+                height,
+                right: 0,
+            });
+            self.shelves.len() - 1
+        };
+        let shelf = &mut self.shelves[i];
+
+        let r = Point::new(shelf.right, shelf.top);
+        shelf.right += width;
+        self.stats.count += 1;
+        self.stats.used_area += (width * height) as usize;
+        Some(r)
+    }
+
+    /// Releases rectangle previously allocated with `insert()`. Returns `true` if the page became
+    /// empty and all its space is available again.
+    pub fn release(&mut self, width: i32, height: i32) -> bool {
+        assert!(self.stats.count > 0);
+        self.stats.count -= 1;
+        self.stats.used_area -= (width * height) as usize;
+        if self.stats.count == 0 {
+            self.shelves.clear();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_release() {
+        let mut p = Packer::new(10, 10);
+        assert_eq!(p.insert(4, 3), Some(Point::new(0, 0)));
+        assert_eq!(p.insert(4, 5), Some(Point::new(0, 3)));
+        // Goes into the lowest shelf it fits.
+        assert_eq!(p.insert(6, 2), Some(Point::new(4, 0)));
+        assert_eq!(p.insert(6, 4), Some(Point::new(4, 3)));
+        assert_eq!(p.insert(1, 3), None);
+        assert_eq!(p.insert(1, 2), Some(Point::new(0, 8)));
+        assert_eq!(p.insert(11, 1), None);
+        assert_eq!(p.stats(), PageStats { count: 5, used_area: 12 + 20 + 12 + 24 + 2 });
+
+        assert!(!p.release(1, 2));
+        assert!(!p.release(4, 3));
+        assert!(!p.release(4, 5));
+        assert!(!p.release(6, 2));
+        assert!(p.release(6, 4));
+        assert_eq!(p.stats(), PageStats::default());
+        assert_eq!(p.insert(10, 10), Some(Point::new(0, 0)));
+    }
+
+    #[test]
+    fn fits_() {
+        assert!(fits(1, 1));
+        assert!(fits(MAX_ITEM_SIZE, MAX_ITEM_SIZE));
+        assert!(!fits(MAX_ITEM_SIZE + 1, 1));
+        assert!(!fits(0, 10));
+    }
+}
@@ -0,0 +1,167 @@
+//! Comparison of rendered images with reference (golden) images.
+//!
+//! Renderer tests draw a scene offscreen and check the screenshot with `check()` against a PNG
+//! in the `golden` directory next to this file. To create or update the references after an
+//! intended visual change run the tests with `VAULT13_BLESS` environment variable set and
+//! review the changed images before committing them.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::graphics::png::{self, Image};
+use crate::graphics::Rect;
+use super::Screenshot;
+
+/// Allowed difference between the rendered and the reference image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tolerance {
+    /// Max difference of any color channel at which pixels are considered equal.
+    pub channel: u8,
+    /// Max number of pixels that can differ.
+    pub pixels: usize,
+}
+
+impl Tolerance {
+    pub const EXACT: Self = Self {
+        channel: 0,
+        pixels: 0,
+    };
+}
+
+/// Difference between the rendered image and the reference.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Diff {
+    Size {
+        width: u32,
+        height: u32,
+    },
+    Pixels {
+        count: usize,
+        bounds: Rect,
+    },
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Size { width, height } =>
+                write!(f, "reference is {}x{}", width, height),
+            Self::Pixels { count, bounds } =>
+                write!(f, "{} pixels differ within ({}, {})-({}, {})", count,
+                    bounds.left, bounds.top, bounds.right, bounds.bottom),
+        }
+    }
+}
+
+/// Compares `shot` with the `reference` ignoring alpha. Returns `None` if the images match
+/// within the `tolerance`.
+pub fn compare(shot: &Screenshot, reference: &Image, tolerance: Tolerance) -> Option<Diff> {
+    let (width, height) = (shot.width as u32, shot.height as u32);
+    if reference.width != width || reference.height != height {
+        return Some(Diff::Size {
+            width: reference.width,
+            height: reference.height,
+        });
+    }
+    let mut count = 0;
+    let mut bounds: Option<Rect> = None;
+    for y in 0..height {
+        for x in 0..width {
+            let i = (y * width + x) as usize * 3;
+            let (color, _) = reference.rgba(x, y);
+            let differs = shot.data[i..i + 3].iter()
+                .zip(&[color.r(), color.g(), color.b()])
+                .any(|(&a, &b)| (a as i32 - b as i32).abs() > tolerance.channel as i32);
+            if differs {
+                count += 1;
+                let p = Rect::with_size(x as i32, y as i32, 1, 1);
+                bounds = Some(bounds.map(|b| b.union(p)).unwrap_or(p));
+            }
+        }
+    }
+    if count > tolerance.pixels {
+        bounds.map(|bounds| Diff::Pixels {
+            count,
+            bounds,
+        })
+    } else {
+        None
+    }
+}
+
+/// Checks `shot` against the reference image `name`. On mismatch the rendered image is written
+/// to the temp directory and the function panics.
+pub fn check(name: &str, shot: &Screenshot, tolerance: Tolerance) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/graphics/render/golden")
+        .join(name)
+        .with_extension("png");
+    if std::env::var_os("VAULT13_BLESS").is_some() {
+        write(&path, shot);
+        return;
+    }
+    let reference = File::open(&path)
+        .and_then(|f| png::read(&mut BufReader::new(f)))
+        .unwrap_or_else(|e| panic!("couldn't read reference {}: {} \
+            (run with VAULT13_BLESS=1 to create it)", path.display(), e));
+    if let Some(diff) = compare(shot, &reference, tolerance) {
+        let actual = std::env::temp_dir().join(format!("vault13-golden-{}.png", name));
+        write(&actual, shot);
+        panic!("{} differs from reference {}: {}", actual.display(), path.display(), diff);
+    }
+}
+
+fn write(path: &Path, shot: &Screenshot) {
+    let mut wr = BufWriter::new(File::create(path).unwrap());
+    png::write_rgb(&mut wr, shot.width as u32, shot.height as u32, &shot.data).unwrap();
+    wr.flush().unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::png::Pixels;
+    use crate::graphics::Point;
+
+    fn shot(width: i32, height: i32, data: &[u8]) -> Screenshot {
+        Screenshot {
+            width,
+            height,
+            data: data.into(),
+        }
+    }
+
+    fn image(width: u32, height: u32, rgb: &[u8]) -> Image {
+        Image {
+            width,
+            height,
+            pixels: Pixels::Rgba(rgb.chunks(3)
+                .flat_map(|p| vec![p[0], p[1], p[2], 0xff])
+                .collect()),
+        }
+    }
+
+    #[test]
+    fn compare_() {
+        let rgb = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let s = shot(2, 2, rgb);
+        assert_eq!(compare(&s, &image(2, 2, rgb), Tolerance::EXACT), None);
+        assert_eq!(compare(&s, &image(4, 1, rgb), Tolerance::EXACT),
+            Some(Diff::Size { width: 4, height: 1 }));
+
+        let reference = image(2, 2, &[1, 2, 3, 4, 5, 0, 7, 8, 9, 10, 11, 10]);
+        assert_eq!(compare(&s, &reference, Tolerance::EXACT), Some(Diff::Pixels {
+            count: 2,
+            bounds: Rect::with_points(Point::new(1, 0), Point::new(2, 2)),
+        }));
+        assert_eq!(compare(&s, &reference, Tolerance { channel: 2, pixels: 0 }),
+            Some(Diff::Pixels {
+                count: 1,
+                bounds: Rect::with_points(Point::new(1, 0), Point::new(2, 1)),
+            }));
+        assert_eq!(compare(&s, &reference, Tolerance { channel: 2, pixels: 1 }), None);
+        assert_eq!(compare(&s, &reference, Tolerance { channel: 6, pixels: 0 }), None);
+    }
+}
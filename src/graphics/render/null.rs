@@ -0,0 +1,109 @@
+//! Render backend that draws nothing. Used when running without a window.
+
+use super::*;
+use super::software::Textures;
+
+pub struct Backend {
+    textures: Textures,
+}
+
+impl Backend {
+    pub fn new() -> Self {
+        Self {
+            textures: Textures::new(),
+        }
+    }
+
+    pub fn new_texture_factory(&self) -> TextureFactory {
+        TextureFactory(TextureFactoryInner::Software(self.textures.clone()))
+    }
+
+    pub fn into_canvas(self, fonts: Rc<Fonts>) -> Box<dyn Canvas> {
+        Box::new(CanvasImpl {
+            textures: self.textures,
+            fonts,
+        })
+    }
+}
+
+/// Canvas that keeps track of the textures only so they're released as usual.
+struct CanvasImpl {
+    textures: Textures,
+    fonts: Rc<Fonts>,
+}
+
+impl Canvas for CanvasImpl {
+    fn cleanup(&mut self) {
+        self.textures.cleanup();
+    }
+
+    fn present(&mut self) {}
+    fn resize(&mut self) {}
+
+    fn size(&self) -> Point {
+        Point::new(0, 0)
+    }
+
+    fn window_scale(&self) -> f64 {
+        1.0
+    }
+
+    fn window_origin(&self) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+
+    fn has_hardware_cursor(&self) -> bool {
+        false
+    }
+
+    fn set_hardware_cursor(&mut self, _texture: &TextureHandle, _hotspot: Point)
+        -> Result<(), String>
+    {
+        Err("no window".into())
+    }
+
+    fn invalidate(&mut self, _rect: Rect) {}
+    fn invalidate_all(&mut self) {}
+    fn begin_frame(&mut self) {}
+
+    fn screenshot(&self) -> Option<Screenshot> {
+        None
+    }
+
+    fn update(&mut self, _time: Instant) {}
+    fn set_brightness(&mut self, _brightness: u8) {}
+
+    fn fonts(&self) -> &Rc<Fonts> {
+        &self.fonts
+    }
+
+    fn set_clip_rect(&mut self, _rect: Rect) {}
+    fn reset_clip_rect(&mut self) {}
+    fn clear(&mut self, _color: Rgb15) {}
+    fn fill_rect(&mut self, _rect: Rect, _color: Rgb15) {}
+    fn draw(&mut self, _tex: &TextureHandle, _pos: Point, _light: u32) {}
+    fn draw_multi_light(&mut self, _tex: &TextureHandle, _pos: Point, _lights: &[u32]) {}
+
+    fn draw_masked(&mut self, _texture: &TextureHandle, _pos: Point,
+                   _mask: &TextureHandle, _mask_pos: Point,
+                   _light: u32) {}
+
+    fn draw_masked_color(&mut self, _src: Rgb15, _dst: Option<Rgb15>, _pos: Point,
+                         _mask: &TextureHandle, _alpha: u8) {}
+
+    fn draw_highlight(&mut self, _color: Rgb15, _pos: Point, _mask: &TextureHandle) {}
+    fn draw_translucent(&mut self, _tex: &TextureHandle, _pos: Point, _color: Rgb15,
+        _light: u32) {}
+    fn draw_translucent_dark(&mut self, _tex: &TextureHandle, _pos: Point, _color: Rgb15,
+        _light: u32) {}
+    fn draw_outline(&mut self, _tex: &TextureHandle, _pos: Point, _outline: Outline) {}
+    fn draw_text(&mut self, _text: &bstr, _pos: Point, _font: FontKey, _color: Rgb15,
+        _options: &font::DrawOptions) {}
+    fn draw_scaled(&mut self, _src: &TextureHandle, _dst: Rect) {}
+
+    fn atlas_pages(&self) -> Vec<AtlasPageInfo> {
+        self.textures.atlas_pages()
+    }
+
+    fn draw_atlas_page(&mut self, _page: usize, _dst: Rect) {}
+}
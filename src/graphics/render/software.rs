@@ -1,12 +1,19 @@
-use sdl2::pixels::PixelFormatEnum;
+mod blit;
+
+use sdl2::mouse::Cursor as SdlCursor;
+use sdl2::pixels::{Color as SdlColor, PixelFormatEnum};
+use sdl2::rect::Rect as SdlRect;
 use sdl2::render::{Texture as SdlTexture, WindowCanvas};
+use sdl2::surface::Surface;
 use slotmap::{SecondaryMap, SlotMap};
 use std::cmp;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::{Ref, RefCell};
 
 use super::*;
-use crate::graphics::color::Color8;
+use super::atlas::{self, Packer};
+use crate::graphics::color::{Color8, Rgb24};
 use crate::graphics::color::palette::Palette;
 use crate::graphics::color::palette::overlay::PaletteOverlay;
 use crate::graphics::font::{self, FontKey, Fonts};
@@ -14,7 +21,9 @@ use crate::graphics::lighting::light_map::{self, LightMap};
 use crate::graphics::{Point, Rect};
 
 pub struct Backend {
-    canvas: WindowCanvas,
+    canvas: Option<WindowCanvas>,
+    width: i32,
+    height: i32,
     palette: Box<Palette>,
     palette_overlay: PaletteOverlay,
     textures: Textures,
@@ -23,8 +32,25 @@ pub struct Backend {
 impl Backend {
     pub fn new(canvas: WindowCanvas, palette: Box<Palette>,
             palette_overlay: PaletteOverlay) -> Self {
+        let (w, h) = canvas.window().size();
+        Self {
+            canvas: Some(canvas),
+            width: w as i32,
+            height: h as i32,
+            palette,
+            palette_overlay,
+            textures: Textures::new(),
+        }
+    }
+
+    /// Backend without window that renders into memory only. The rendered image can be read
+    /// with `Canvas::screenshot()`.
+    pub fn new_offscreen(width: i32, height: i32, palette: Box<Palette>,
+            palette_overlay: PaletteOverlay) -> Self {
         Self {
-            canvas,
+            canvas: None,
+            width,
+            height,
             palette,
             palette_overlay,
             textures: Textures::new(),
@@ -67,11 +93,85 @@ impl Texture {
     pub fn len(&self) -> usize {
         (self.width * self.height) as usize
     }
+
+    fn view(&self) -> TexView {
+        TexView {
+            width: self.width,
+            height: self.height,
+            stride: self.width,
+            data: &self.data,
+        }
+    }
+}
+
+/// Pixels of a texture that are stored either in its own buffer or in a region of an atlas page.
+#[derive(Clone, Copy)]
+struct TexView<'a> {
+    width: i32,
+    height: i32,
+    /// Distance between rows in `data`.
+    stride: i32,
+    /// Pixels starting from the top left one.
+    data: &'a [u8],
+}
+
+impl<'a> TexView<'a> {
+    fn row(&self, y: i32) -> &'a [u8] {
+        let i = (y * self.stride) as usize;
+        &self.data[i..i + self.width as usize]
+    }
+
+    fn pixel(&self, x: i32, y: i32) -> u8 {
+        self.data[(y * self.stride + x) as usize]
+    }
+}
+
+enum Storage {
+    Owned(Texture),
+    Atlas {
+        page: usize,
+        pos: Point,
+        width: i32,
+        height: i32,
+    },
+}
+
+struct AtlasPage {
+    packer: Packer,
+    data: Box<[u8]>,
+}
+
+impl AtlasPage {
+    fn new() -> Self {
+        Self {
+            packer: Packer::new(atlas::PAGE_SIZE, atlas::PAGE_SIZE),
+            data: vec![0; (atlas::PAGE_SIZE * atlas::PAGE_SIZE) as usize].into_boxed_slice(),
+        }
+    }
+
+    fn view(&self) -> TexView {
+        TexView {
+            width: atlas::PAGE_SIZE,
+            height: atlas::PAGE_SIZE,
+            stride: atlas::PAGE_SIZE,
+            data: &self.data,
+        }
+    }
+
+    fn insert(&mut self, width: i32, height: i32, data: &[u8]) -> Option<Point> {
+        let pos = self.packer.insert(width, height)?;
+        for (y, src) in data.chunks(width as usize).enumerate() {
+            let i = ((pos.y + y as i32) * atlas::PAGE_SIZE + pos.x) as usize;
+            self.data[i..i + width as usize].copy_from_slice(src);
+        }
+        Some(pos)
+    }
 }
 
 struct TexturesInner {
     handles: SlotMap<Key, ()>,
-    textures: SecondaryMap<Key, Texture>,
+    textures: SecondaryMap<Key, Storage>,
+    pages: Vec<AtlasPage>,
     drop_list: Rc<RefCell<Vec<Key>>>,
 }
 
@@ -80,24 +180,74 @@ impl TexturesInner {
         Self {
             handles: SlotMap::with_key(),
             textures: SecondaryMap::new(),
+            pages: Vec::new(),
             drop_list: Rc::new(RefCell::new(Vec::new())),
         }
     }
 
     fn new_texture(&mut self, width: i32, height: i32, data: Box<[u8]>) -> TextureHandle {
+        assert_eq!(data.len(), (width * height) as usize);
         let key = self.handles.insert(());
-        self.textures.insert(key, Texture::new(width, height, data));
+        let storage = self.pack(width, height, &data)
+            .unwrap_or_else(|| Storage::Owned(Texture::new(width, height, data)));
+        self.textures.insert(key, storage);
         TextureHandle(Rc::new(TextureHandleInner {
             key,
             drop_list: self.drop_list.clone(),
         }))
     }
 
+    /// Puts small texture into atlas.
+    fn pack(&mut self, width: i32, height: i32, data: &[u8]) -> Option<Storage> {
+        if !atlas::fits(width, height) {
+            return None;
+        }
+        let found = self.pages.iter_mut()
+            .enumerate()
+            .find_map(|(i, p)| p.insert(width, height, data).map(|pos| (i, pos)));
+        let (page, pos) = if let Some(v) = found {
+            v
+        } else {
+            let mut page = AtlasPage::new();
+            let pos = page.insert(width, height, data)?;
+            self.pages.push(page);
+            (self.pages.len() - 1, pos)
+        };
+        Some(Storage::Atlas {
+            page,
+            pos,
+            width,
+            height,
+        })
+    }
+
+    fn view(&self, key: Key) -> TexView {
+        match self.textures[key] {
+            Storage::Owned(ref t) => t.view(),
+            Storage::Atlas { page, pos, width, height } => {
+                let page = &self.pages[page];
+                TexView {
+                    width,
+                    height,
+                    stride: atlas::PAGE_SIZE,
+                    data: &page.data[(pos.y * atlas::PAGE_SIZE + pos.x) as usize..],
+                }
+            }
+        }
+    }
+
     fn cleanup(&mut self) {
         let mut l = self.drop_list.borrow_mut();
         for key in l.drain(..) {
             self.handles.remove(key);
-            self.textures.remove(key);
+            if let Some(Storage::Atlas { page, width, height, .. }) = self.textures.remove(key) {
+                let page = &mut self.pages[page];
+                if page.packer.release(width, height) {
+                    for b in page.data.iter_mut() {
+                        *b = 0;
+                    }
+                }
+            }
         }
     }
 }
@@ -106,13 +256,23 @@ impl TexturesInner {
 pub(in super) struct Textures(Rc<RefCell<TexturesInner>>);
 
 impl Textures {
-    fn new() -> Self {
+    pub(in super) fn new() -> Self {
         Textures(Rc::new(RefCell::new(TexturesInner::new())))
     }
 
-    fn cleanup(&self) {
+    pub(in super) fn cleanup(&self) {
         self.0.borrow_mut().cleanup();
     }
+
+    pub(in super) fn atlas_pages(&self) -> Vec<AtlasPageInfo> {
+        self.0.borrow().pages.iter()
+            .map(|p| AtlasPageInfo {
+                width: atlas::PAGE_SIZE,
+                height: atlas::PAGE_SIZE,
+                stats: p.packer.stats(),
+            })
+            .collect()
+    }
 }
 
 impl Textures {
@@ -120,44 +280,186 @@ impl Textures {
         self.0.borrow_mut().new_texture(width, height, data)
     }
 
-    fn get(&self, h: &TextureHandle) -> Ref<Texture> {
-        let t = self.0.borrow();
-        Ref::map(t, |t| &t.textures[h.0.key])
+    fn get(&self, h: &TextureHandle) -> TexRef {
+        TexRef {
+            inner: self.0.borrow(),
+            key: h.0.key,
+        }
     }
 }
 
-struct CanvasImpl {
+struct TexRef<'a> {
+    inner: Ref<'a, TexturesInner>,
+    key: Key,
+}
+
+impl TexRef<'_> {
+    fn view(&self) -> TexView {
+        self.inner.view(self.key)
+    }
+}
+
+struct Window {
     canvas: WindowCanvas,
+    texture: SdlTexture,
+    /// RGB pixels of the last presented frame. Only the invalidated area is converted from the
+    /// back buffer on `present()`.
+    rgb_buf: Box<[u8]>,
+    /// Renderer output size in pixels the layout was computed for. It differs from the window
+    /// size on hi-DPI displays.
+    output_size: (u32, u32),
+    /// Area of the renderer output the back buffer is scaled to.
+    dst: Rect,
+    /// Window coordinate units per back buffer pixel.
+    scale: f64,
+    /// Position of `dst` in the window coordinates.
+    origin: (f64, f64),
+    /// System cursors created from the textures by (texture, hotspot, scale).
+    cursors: HashMap<(Key, Point, i32), SdlCursor>,
+    /// Key of the current system cursor in `cursors`.
+    cursor: Option<(Key, Point, i32)>,
+}
+
+impl Window {
+    fn layout(&mut self, width: i32, height: i32) {
+        self.output_size = self.canvas.output_size().unwrap_or((width as u32, height as u32));
+        let (out_w, out_h) = self.output_size;
+        self.dst = letterbox(out_w as i32, out_h as i32, width, height);
+        let (win_w, _) = self.canvas.window().size();
+        let out_to_win = win_w as f64 / out_w.max(1) as f64;
+        self.scale = self.dst.width() as f64 / width as f64 * out_to_win;
+        self.origin = (self.dst.left as f64 * out_to_win, self.dst.top as f64 * out_to_win);
+    }
+}
+
+/// Returns the area of the `out_width` x `out_height` output the `width` x `height` image is
+/// scaled to. The image is centered keeping the aspect ratio. Integer scale is used when the
+/// image fits at least at the original size so the pixels stay crisp.
+fn letterbox(out_width: i32, out_height: i32, width: i32, height: i32) -> Rect {
+    let fit = (out_width as f64 / width as f64).min(out_height as f64 / height as f64);
+    let scale = if fit >= 1.0 { fit.floor() } else { fit };
+    let w = ((width as f64 * scale) as i32).max(1);
+    let h = ((height as f64 * scale) as i32).max(1);
+    Rect::with_size((out_width - w) / 2, (out_height - h) / 2, w, h)
+}
+
+/// Returns ARGB pixels of the `tex` scaled `scale` times. Color 0 is transparent.
+fn cursor_pixels(tex: &TexView, palette: &Palette, scale: i32) -> Vec<u32> {
+    let (w, h) = (tex.width * scale, tex.height * scale);
+    let mut r = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let c = tex.pixel(x / scale, y / scale);
+            r.push(if c == 0 {
+                0
+            } else {
+                let rgb = palette.rgb18(c).scale::<Color8>();
+                u32::from_be_bytes([0xff, rgb.r(), rgb.g(), rgb.b()])
+            });
+        }
+    }
+    r
+}
+
+fn new_cursor(tex: &TexView, palette: &Palette, hotspot: Point, scale: i32)
+    -> Result<SdlCursor, String>
+{
+    if tex.width <= 0 || tex.height <= 0 {
+        return Err("empty cursor image".into());
+    }
+    let pixels = cursor_pixels(tex, palette, scale);
+    let (w, h) = (tex.width * scale, tex.height * scale);
+    let mut surface = Surface::new(w as u32, h as u32, PixelFormatEnum::ARGB8888)?;
+    let pitch = surface.pitch() as usize;
+    surface.with_lock_mut(|data| {
+        for (row, dst) in pixels.chunks(w as usize).zip(data.chunks_mut(pitch)) {
+            for (&pixel, dst) in row.iter().zip(dst.chunks_mut(4)) {
+                dst.copy_from_slice(&pixel.to_ne_bytes());
+            }
+        }
+    });
+    SdlCursor::from_surface(surface, hotspot.x * scale, hotspot.y * scale)
+}
+
+struct CanvasImpl {
+    window: Option<Window>,
     palette: Box<Palette>,
     palette_overlay: PaletteOverlay,
     textures: Textures,
     light_map: LightMap,
     back_buf: Texture,
-    canvas_texture: SdlTexture,
     clip_rect: Rect,
+    /// Area invalidated since the last `present()`.
+    dirty: Rect,
+    /// Area being redrawn in the current frame. All drawing is clipped to it.
+    frame_rect: Rect,
+    /// Whether the palette colors changed since the last `present()`. In this case the whole
+    /// back buffer must be converted to RGB.
+    palette_changed: bool,
+    /// Darkening lookup tables for each light level in [0..128] range.
+    darken_luts: Vec<Option<Box<blit::Lut>>>,
     fonts: Rc<Fonts>,
 }
 
 impl CanvasImpl {
     fn new(backend: Backend, fonts: Rc<Fonts>) -> Self {
-        let (w, h) = backend.canvas.window().size();
-        let canvas_texture = backend.canvas
-            .texture_creator()
-            .create_texture_streaming(PixelFormatEnum::RGB24, w, h)
-            .unwrap();
+        let (w, h) = (backend.width, backend.height);
+        let window = backend.canvas.map(|canvas| {
+            let texture = canvas
+                .texture_creator()
+                .create_texture_streaming(PixelFormatEnum::RGB24, w as u32, h as u32)
+                .unwrap();
+            let mut window = Window {
+                canvas,
+                texture,
+                rgb_buf: vec![0; (w * h * 3) as usize].into(),
+                output_size: (0, 0),
+                dst: Rect::with_size(0, 0, w, h),
+                scale: 1.0,
+                origin: (0.0, 0.0),
+                cursors: HashMap::new(),
+                cursor: None,
+            };
+            window.layout(w, h);
+            window
+        });
         Self {
-            canvas: backend.canvas,
+            window,
             palette: backend.palette,
             palette_overlay: backend.palette_overlay,
             textures: backend.textures,
             light_map: LightMap::new(),
-            back_buf: Texture::new_empty(w as i32, h as i32, 0),
-            canvas_texture,
-            clip_rect: Rect::with_size(0, 0, w as i32, h as i32),
+            back_buf: Texture::new_empty(w, h, 0),
+            clip_rect: Rect::with_size(0, 0, w, h),
+            dirty: Rect::with_size(0, 0, w, h),
+            frame_rect: Rect::with_size(0, 0, w, h),
+            palette_changed: true,
+            darken_luts: (0..=128).map(|_| None).collect(),
             fonts,
         }
     }
 
+    fn darken_lut<'a>(luts: &'a mut [Option<Box<blit::Lut>>], palette: &Palette, light: u8)
+        -> &'a blit::Lut
+    {
+        luts[light as usize]
+            .get_or_insert_with(|| Box::new(blit::Lut::new(|c| palette.darken(c, light))))
+    }
+
+    fn screen_rect(&self) -> Rect {
+        Rect::with_size(0, 0, self.back_buf.width, self.back_buf.height)
+    }
+
+    fn is_empty(rect: Rect) -> bool {
+        rect.width() <= 0 || rect.height() <= 0
+    }
+
+    fn to_rgb(palette: &Palette, palette_overlay: &PaletteOverlay, color_idx: u8) -> Rgb24 {
+        let rgb = palette_overlay.get(color_idx)
+            .unwrap_or_else(|| palette.rgb18(color_idx));
+        palette_overlay.apply_brightness(rgb).scale::<Color8>()
+    }
+
     fn make_translucent(src: u8, dst: u8, trans_color_idx: u8, palette: &Palette,
             grayscale_func: impl Fn(Rgb15) -> u8) -> u8 {
         let alpha = grayscale_func(palette.rgb15(src)) / 4;
@@ -168,6 +470,7 @@ impl CanvasImpl {
             grayscale_func: impl Fn(Rgb15) -> u8) {
         let pal = &self.palette;
         let tex = self.textures.get(tex);
+        let tex = tex.view();
         let light = (light >> 9) as u8;
 
         let color = pal.color_idx(color);
@@ -181,6 +484,35 @@ impl CanvasImpl {
         );
     }
 
+    fn do_draw_scaled(dst_tex: &mut Texture, src: &TexView, dst: Rect, clip_rect: Rect) {
+        let clipped = dst.intersect(clip_rect);
+
+        if Self::is_empty(clipped) {
+            return;
+        }
+
+        let dst_width = dst_tex.width;
+        let dst_buf = &mut dst_tex.data[(dst.top * dst_width + dst.left) as usize..];
+
+        let wf = (src.width << 16) / dst.width();
+        let hf = (src.height << 16) / dst.height();
+        for dst_y in (clipped.top - dst.top)..(clipped.bottom - dst.top) {
+            let src_y = (hf * dst_y) >> 16;
+            for (dst_x, dst) in dst_buf[(dst_width * dst_y) as usize..]
+                .iter_mut()
+                .skip((clipped.left - dst.left) as usize)
+                .take(clipped.width() as usize)
+                .enumerate()
+            {
+                let src_x = (wf * dst_x as i32) >> 16;
+                let src = src.pixel(src_x, src_y);
+                if src != 0 {
+                    *dst = src;
+                }
+            }
+        }
+    }
+
     fn compute_draw_rect(dst: &Texture, dst_x: i32, dst_y: i32,
                          src_width: i32, src_height: i32,
                          clip_rect: Rect) -> (Rect, i32, i32) {
@@ -196,24 +528,42 @@ impl CanvasImpl {
     fn do_draw(
             dst: &mut Texture,
             dst_x: i32, dst_y: i32,
-            src: &Texture,
+            src: &TexView,
             clip_rect: Rect,
             f: impl Fn(&mut u8, i32, i32, i32, i32, u8)) {
+        Self::do_blit(dst, dst_x, dst_y, src, clip_rect, |dst, src, dst_x, dst_y, src_x, src_y| {
+            let mut i = 0;
+            while i < src.len() {
+                i += blit::transparent_prefix(&src[i..]);
+                while i < src.len() && src[i] != 0 {
+                    let x = i as i32;
+                    f(&mut dst[i], dst_x + x, dst_y, src_x + x, src_y, src[i]);
+                    i += 1;
+                }
+            }
+        });
+    }
+
+    /// Calls `f` for each pair of the clipped destination and source rows. The other arguments
+    /// are the destination and source coordinates of the first pixel in the rows.
+    fn do_blit(
+            dst: &mut Texture,
+            dst_x: i32, dst_y: i32,
+            src: &TexView,
+            clip_rect: Rect,
+            f: impl Fn(&mut [u8], &[u8], i32, i32, i32, i32)) {
         let (src_rect, dst_x, mut dst_y) =
             Self::compute_draw_rect(dst, dst_x, dst_y, src.width, src.height, clip_rect);
+        if src_rect.left >= src_rect.right {
+            return;
+        }
+        let len = (src_rect.right - src_rect.left) as usize;
 
         for src_y in src_rect.top..src_rect.bottom {
-            let src = &src.data[(src_y * src.width) as usize..];
-            let dst = &mut dst.data[(dst_y * dst.width) as usize..];
-            let mut dst_x_i = dst_x;
-            for src_x in src_rect.left..src_rect.right {
-                let src_color_idx = src[src_x as usize];
-                if src_color_idx != 0 {
-                    let dst_color_idx = &mut dst[dst_x_i as usize];
-                    f(dst_color_idx, dst_x_i, dst_y, src_x, src_y, src_color_idx);
-                }
-                dst_x_i += 1;
-            }
+            let src_row = &src.row(src_y)[src_rect.left as usize..src_rect.right as usize];
+            let start = (dst_y * dst.width + dst_x) as usize;
+            let dst_row = &mut dst.data[start..start + len];
+            f(dst_row, src_row, dst_x, dst_y, src_rect.left, src_y);
             dst_y += 1;
         }
     }
@@ -225,28 +575,139 @@ impl Canvas for CanvasImpl {
     }
 
     fn present(&mut self) {
-        let pal = &self.palette;
-        let pal_overlay = &self.palette_overlay;
-        let src = &self.back_buf.data;
-        let src_width = self.back_buf.width;
-        self.canvas_texture.with_lock(None, |dst, stride| {
-            for (src_row, dst_row) in src.chunks(src_width as usize).zip(dst.chunks_mut(stride)) {
-                for (&src_pixel, dst_pixel) in src_row.iter().zip(dst_row.chunks_mut(3)) {
-                    let rgb = pal_overlay.get(src_pixel)
-                        .unwrap_or_else(|| pal.rgb18(src_pixel))
-                        .scale::<Color8>();
-                    dst_pixel[0] = rgb.r();
-                    dst_pixel[1] = rgb.g();
-                    dst_pixel[2] = rgb.b();
+        let dirty = if self.palette_changed {
+            self.screen_rect()
+        } else {
+            self.dirty
+        };
+        self.palette_changed = false;
+        self.dirty = Rect::empty();
+        self.frame_rect = self.screen_rect();
+        self.reset_clip_rect();
+
+        let (w, h) = (self.back_buf.width, self.back_buf.height);
+        let window = if let Some(v) = self.window.as_mut() {
+            v
+        } else {
+            return;
+        };
+        // DPI change doesn't necessarily come with a resize event.
+        if window.canvas.output_size().ok() != Some(window.output_size) {
+            window.layout(w, h);
+        }
+        if !Self::is_empty(dirty) {
+            let mut lut = [[0; 3]; 256];
+            for (i, rgb) in lut.iter_mut().enumerate() {
+                let v = Self::to_rgb(&self.palette, &self.palette_overlay, i as u8);
+                *rgb = [v.r(), v.g(), v.b()];
+            }
+            let width = self.back_buf.width as usize;
+            for y in dirty.top..dirty.bottom {
+                let start = y as usize * width + dirty.left as usize;
+                let end = y as usize * width + dirty.right as usize;
+                let src = &self.back_buf.data[start..end];
+                let dst = &mut window.rgb_buf[start * 3..end * 3];
+                for (&src_pixel, dst_pixel) in src.iter().zip(dst.chunks_mut(3)) {
+                    dst_pixel.copy_from_slice(&lut[src_pixel as usize]);
                 }
             }
-        }).unwrap();
-        self.canvas.copy(&self.canvas_texture, None, None).unwrap();
-        self.canvas.present();
+            window.texture.update(None, &window.rgb_buf, width * 3).unwrap();
+        }
+        let dst = window.dst;
+        window.canvas.set_draw_color(SdlColor::RGB(0, 0, 0));
+        window.canvas.clear();
+        window.canvas.copy(&window.texture, None,
+            SdlRect::new(dst.left, dst.top, dst.width() as u32, dst.height() as u32)).unwrap();
+        window.canvas.present();
+    }
+
+    fn resize(&mut self) {
+        let (w, h) = (self.back_buf.width, self.back_buf.height);
+        if let Some(window) = self.window.as_mut() {
+            window.layout(w, h);
+        }
+    }
+
+    fn size(&self) -> Point {
+        Point::new(self.back_buf.width, self.back_buf.height)
+    }
+
+    fn window_scale(&self) -> f64 {
+        self.window.as_ref().map(|w| w.scale).unwrap_or(1.0)
+    }
+
+    fn window_origin(&self) -> (f64, f64) {
+        self.window.as_ref().map(|w| w.origin).unwrap_or((0.0, 0.0))
+    }
+
+    fn has_hardware_cursor(&self) -> bool {
+        self.window.is_some()
+    }
+
+    fn set_hardware_cursor(&mut self, texture: &TextureHandle, hotspot: Point)
+        -> Result<(), String>
+    {
+        let window = self.window.as_mut().ok_or("no window")?;
+        let scale = cmp::max(window.scale.round() as i32, 1);
+        let key = (texture.0.key, hotspot, scale);
+        if window.cursor == Some(key) {
+            return Ok(());
+        }
+        if !window.cursors.contains_key(&key) {
+            let cursor = new_cursor(&self.textures.get(texture).view(), &self.palette,
+                hotspot, scale)?;
+            window.cursors.insert(key, cursor);
+        }
+        window.cursors[&key].set();
+        window.cursor = Some(key);
+        Ok(())
+    }
+
+    fn invalidate(&mut self, rect: Rect) {
+        let rect = rect.intersect(self.screen_rect());
+        if Self::is_empty(rect) {
+            return;
+        }
+        self.dirty = if Self::is_empty(self.dirty) {
+            rect
+        } else {
+            self.dirty.union(rect)
+        };
+    }
+
+    fn invalidate_all(&mut self) {
+        self.dirty = self.screen_rect();
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame_rect = self.dirty;
+        self.reset_clip_rect();
+    }
+
+    fn screenshot(&self) -> Option<Screenshot> {
+        let mut data = Vec::with_capacity(self.back_buf.len() * 3);
+        for &src_pixel in self.back_buf.data.iter() {
+            let rgb = Self::to_rgb(&self.palette, &self.palette_overlay, src_pixel);
+            data.extend_from_slice(&[rgb.r(), rgb.g(), rgb.b()]);
+        }
+        Some(Screenshot {
+            width: self.back_buf.width,
+            height: self.back_buf.height,
+            data,
+        })
     }
 
     fn update(&mut self, time: Instant) {
-        self.palette_overlay.rotate(time);
+        if self.palette_overlay.rotate(time) {
+            self.palette_changed = true;
+        }
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        if brightness != self.palette_overlay.brightness() {
+            self.palette_overlay.set_brightness(brightness);
+            self.palette_changed = true;
+        }
     }
 
     fn fonts(&self) -> &Rc<Fonts> {
@@ -259,27 +720,38 @@ impl Canvas for CanvasImpl {
     }
 
     fn reset_clip_rect(&mut self) {
-        let (w, h) = self.canvas.window().size();
-        self.clip_rect = Rect::with_size(0, 0, w as i32, h as i32);
+        self.clip_rect = self.frame_rect;
     }
 
     fn clear(&mut self, color: Rgb15) {
+        let clip_rect = self.clip_rect;
+        self.clip_rect = self.frame_rect;
+        self.fill_rect(self.frame_rect, color);
+        self.clip_rect = clip_rect;
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: Rgb15) {
+        let rect = rect.intersect(self.clip_rect);
+        if rect.left >= rect.right || rect.top >= rect.bottom {
+            return;
+        }
         let v = self.palette.color_idx(color);
-        for b in self.back_buf.data.iter_mut() {
-            *b = v;
+        let width = self.back_buf.width;
+        for y in rect.top..rect.bottom {
+            let row = (y * width) as usize;
+            for b in &mut self.back_buf.data[row + rect.left as usize..row + rect.right as usize] {
+                *b = v;
+            }
         }
     }
 
     fn draw(&mut self, tex: &TextureHandle, pos: Point, light: u32) {
-        let pal = &self.palette;
+        let lut = Self::darken_lut(&mut self.darken_luts, &self.palette, (light >> 9) as u8);
         let tex = self.textures.get(tex);
-        let light = (light >> 9) as u8;
+        let tex = tex.view();
 
-        Self::do_draw(&mut self.back_buf, pos.x, pos.y, &tex, self.clip_rect,
-            |dst, _, _, _, _, src| {
-                *dst = pal.darken(src, light);
-            }
-        );
+        Self::do_blit(&mut self.back_buf, pos.x, pos.y, &tex, self.clip_rect,
+            |dst, src, _, _, _, _| blit::map_keyed(dst, src, lut));
     }
 
     fn draw_multi_light(&mut self, tex: &TextureHandle, pos: Point, lights: &[u32]) {
@@ -302,6 +774,8 @@ impl Canvas for CanvasImpl {
 
         let tex = self.textures.get(tex);
 
+        let tex = tex.view();
+
         Self::do_draw(&mut self.back_buf, pos.x, pos.y, &tex, self.clip_rect,
             |dst, _, _, src_x, src_y, src| {
                 let light = light_map.get(src_x, src_y + 2 /* as in original */);
@@ -314,7 +788,9 @@ impl Canvas for CanvasImpl {
                    mask: &TextureHandle, mask_pos: Point,
                    light: u32) {
         let tex = self.textures.get(tex);
+        let tex = tex.view();
         let mask = self.textures.get(mask);
+        let mask = mask.view();
 
         let mask_rect = Rect::with_size(mask_pos.x, mask_pos.y, mask.width, mask.height);
         let light = (light >> 9) as u8;
@@ -325,8 +801,7 @@ impl Canvas for CanvasImpl {
             |dst, dst_x, dst_y, _, _, src| {
                 let src = pal.darken(src, light);
                 let mask_v = if mask_rect.contains(Point::new(dst_x, dst_y)) {
-                    let i = (dst_y - mask_pos.y) * mask.width + dst_x - mask_pos.x;
-                    cmp::min(mask.data[i as usize], 128)
+                    cmp::min(mask.pixel(dst_x - mask_pos.x, dst_y - mask_pos.y), 128)
                 } else {
                     0
                 };
@@ -342,15 +817,16 @@ impl Canvas for CanvasImpl {
     }
 
     fn draw_masked_color(&mut self, src: Rgb15, dst: Option<Rgb15>, pos: Point,
-            mask: &TextureHandle) {
+            mask: &TextureHandle, alpha: u8) {
         let mask = self.textures.get(mask);
+        let mask = mask.view();
         let pal = &self.palette;
         let src_color_idx = pal.color_idx(src);
         let dst_color_idx = dst.map(|c| pal.color_idx(c));
 
         Self::do_draw(&mut self.back_buf, pos.x, pos.y, &mask, self.clip_rect,
             |dst, _, _, _, _, src| {
-                let alpha = cmp::min(src, 7);
+                let alpha = (cmp::min(src, 7) as u32 * alpha as u32 / 255) as u8;
                 *dst = pal.alpha_blend(src_color_idx, dst_color_idx.unwrap_or(*dst), alpha);
             }
         );
@@ -358,6 +834,7 @@ impl Canvas for CanvasImpl {
 
     fn draw_highlight(&mut self, color: Rgb15, pos: Point, mask: &TextureHandle) {
         let mask = self.textures.get(mask);
+        let mask = mask.view();
         let pal = &self.palette;
         let color_idx = pal.color_idx(color);
 
@@ -383,6 +860,7 @@ impl Canvas for CanvasImpl {
 
     fn draw_outline(&mut self, tex: &TextureHandle, pos: Point, outline: Outline) {
         let src = self.textures.get(tex);
+        let src = src.view();
         let (mut src_rect, dst_x, dst_y) =
             Self::compute_draw_rect(&self.back_buf, pos.x - 1, pos.y - 1,
                 src.width + 2, src.height + 2,
@@ -418,7 +896,7 @@ impl Canvas for CanvasImpl {
             }
             if src_y >= src_rect.top {
                 let mut outside = true;
-                let src = &src.data[(src_y * src.width) as usize..];
+                let src = src.row(src_y);
                 let dst = &mut self.back_buf.data[(dst_y_i * dst_width) as usize..];
                 let mut dst_x_i = dst_x;
                 for src_x in 0..=src_rect.right {
@@ -467,7 +945,7 @@ impl Canvas for CanvasImpl {
                     }
                 }
                 let src = if src_y < src_rect.bottom {
-                    src.data[(src_y * src.width + src_x) as usize]
+                    src.pixel(src_x, src_y)
                 } else {
                     0
                 };
@@ -505,32 +983,162 @@ impl Canvas for CanvasImpl {
 
     fn draw_scaled(&mut self, src: &TextureHandle, dst: Rect) {
         let src = self.textures.get(src);
+        let src = src.view();
+        Self::do_draw_scaled(&mut self.back_buf, &src, dst, self.clip_rect);
+    }
 
-        let clipped = dst.intersect(self.clip_rect);
+    fn atlas_pages(&self) -> Vec<AtlasPageInfo> {
+        self.textures.atlas_pages()
+    }
 
-        if clipped.is_empty() {
-            return;
+    fn draw_atlas_page(&mut self, page: usize, dst: Rect) {
+        let textures = self.textures.0.borrow();
+        if let Some(page) = textures.pages.get(page) {
+            Self::do_draw_scaled(&mut self.back_buf, &page.view(), dst, self.clip_rect);
         }
+    }
+}
 
-        let dst_buf = &mut self.back_buf.data[(dst.top * self.back_buf.width + dst.left) as usize..];
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::palette::read_palette;
+    use crate::graphics::color::Rgb15;
+    use crate::graphics::font::{DrawOptions, Font, Glyph, Shadow};
+    use crate::graphics::render::golden::{self, Tolerance};
+    use crate::util::test::ungz;
 
-        let wf = (src.width << 16) / dst.width();
-        let hf = (src.height << 16) / dst.height();
-        for dst_y in (clipped.top - dst.top)..(clipped.bottom - dst.top) {
-            let src_y = (hf * dst_y) >> 16;
-            for (dst_x, dst) in dst_buf[(self.back_buf.width * dst_y) as usize..]
-                .iter_mut()
-                .skip((clipped.left - dst.left) as usize)
-                .take((clipped.right - dst.left) as usize)
-                .enumerate()
-            {
-                let src_x = (wf * dst_x as i32) >> 16;
-                let src_i = src_y * src.width + src_x;
-                let src = src.data[src_i as usize];
-                if src != 0 {
-                    *dst = src;
+    const TOLERANCE: Tolerance = Tolerance {
+        channel: 4,
+        pixels: 8,
+    };
+
+    const FONT: FontKey = FontKey::non_antialiased(0);
+
+    #[test]
+    fn letterbox_() {
+        assert_eq!(letterbox(640, 480, 640, 480), Rect::with_size(0, 0, 640, 480));
+        // Hi-DPI.
+        assert_eq!(letterbox(1280, 960, 640, 480), Rect::with_size(0, 0, 1280, 960));
+        assert_eq!(letterbox(1920, 1080, 640, 480), Rect::with_size(320, 60, 1280, 960));
+        assert_eq!(letterbox(800, 480, 640, 480), Rect::with_size(80, 0, 640, 480));
+        assert_eq!(letterbox(320, 480, 640, 480), Rect::with_size(0, 120, 320, 240));
+    }
+
+    #[test]
+    fn cursor_pixels_() {
+        let palette = backend(1, 1).palette;
+        let tex = Texture::new(2, 1, vec![0, 1].into());
+        let rgb = palette.rgb18(1).scale::<Color8>();
+        let c = 0xff00_0000 | ((rgb.r() as u32) << 16) | ((rgb.g() as u32) << 8) | rgb.b() as u32;
+        assert_eq!(cursor_pixels(&tex.view(), &palette, 2), vec![0, 0, c, c, 0, 0, c, c]);
+    }
+
+    fn backend(width: i32, height: i32) -> Backend {
+        let data = ungz(include_bytes!("../color/color.pal.gz"));
+        let palette = read_palette(&mut std::io::Cursor::new(&data[..])).unwrap();
+        Backend::new_offscreen(width, height, Box::new(palette), PaletteOverlay::standard())
+    }
+
+    /// Texture with transparent diagonal stripes. Doesn't use palette cycling colors.
+    fn sprite(textures: &TextureFactory, width: i32, height: i32) -> TextureHandle {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| if (x + 2 * y) % 7 == 0 {
+                0
+            } else {
+                ((x * 13 + y * 29) % 200 + 16) as u8
+            }))
+            .collect();
+        textures.new_texture(width, height, data)
+    }
+
+    /// Font with distinct glyph for every character. Mask values are in [0..8] range.
+    fn font(textures: &TextureFactory) -> Font {
+        let glyphs = (0..256)
+            .map(|c| {
+                let width = 3 + c % 4;
+                let height = 5 + c % 3;
+                let data = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| ((x + y * 2 + c) % 9) as u8))
+                    .collect();
+                Glyph {
+                    width,
+                    height,
+                    texture: textures.new_texture(width, height, data),
                 }
-            }
+            })
+            .collect();
+        Font {
+            height: 7,
+            horz_spacing: 1,
+            vert_spacing: 2,
+            glyphs,
         }
     }
+
+    fn render(name: &str, width: i32, height: i32,
+            f: impl FnOnce(&mut dyn Canvas, &TextureFactory)) {
+        let backend = backend(width, height);
+        let textures = backend.new_texture_factory();
+        let mut fonts = Fonts::new();
+        fonts.insert(FONT, font(&textures));
+        let mut canvas = backend.into_canvas(Rc::new(fonts));
+        f(&mut *canvas, &textures);
+        golden::check(name, &canvas.screenshot().unwrap(), TOLERANCE);
+    }
+
+    #[test]
+    fn golden_sprites() {
+        render("sprites", 64, 48, |canvas, textures| {
+            let tex = sprite(textures, 16, 12);
+            canvas.clear(Rgb15::new(4, 8, 12));
+            for &(x, y, light) in &[
+                (4, 4, 0x10000),
+                (24, 4, 0x8000),
+                (44, 4, 0x2000),
+                (-5, 30, 0x10000),
+                (56, 40, 0xc000),
+            ] {
+                canvas.draw(&tex, Point::new(x, y), light);
+            }
+            canvas.set_clip_rect(Rect::with_size(20, 28, 12, 8));
+            canvas.draw(&tex, Point::new(18, 26), 0x10000);
+            canvas.reset_clip_rect();
+            canvas.draw_scaled(&tex, Rect::with_size(34, 20, 24, 18));
+        });
+    }
+
+    #[test]
+    fn golden_lighting() {
+        render("lighting", 96, 48, |canvas, textures| {
+            let tex = sprite(textures, LightMap::WIDTH, LightMap::HEIGHT - 2);
+            canvas.clear(Rgb15::new(2, 2, 2));
+            canvas.draw_multi_light(&tex, Point::new(8, 4), &[
+                0x10000, 0x8000, 0x4000, 0xc000, 0x2000,
+                0x10000, 0x6000, 0x3000, 0xe000, 0x1000,
+            ]);
+        });
+    }
+
+    #[test]
+    fn golden_text() {
+        render("text", 64, 32, |canvas, _| {
+            canvas.clear(Rgb15::new(6, 4, 2));
+            canvas.draw_text(b"Vault 13"[..].into(), Point::new(2, 2), FONT,
+                Rgb15::new(31, 31, 20), &DrawOptions::default());
+            canvas.draw_text(b"War!"[..].into(), Point::new(4, 14), FONT,
+                Rgb15::new(10, 31, 10), &DrawOptions {
+                    shadow: Some(Shadow {
+                        offset: Point::new(1, 1),
+                        color: Rgb15::new(0, 0, 0),
+                    }),
+                    ..Default::default()
+                });
+            canvas.draw_text(b"never"[..].into(), Point::new(30, 22), FONT,
+                Rgb15::new(31, 10, 10), &DrawOptions {
+                    alpha: Some(128),
+                    ..Default::default()
+                });
+        });
+    }
 }
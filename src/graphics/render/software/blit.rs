@@ -0,0 +1,215 @@
+//! Row blitting primitives of the software renderer. Color index 0 is transparent in all of them.
+//!
+//! SSE2 (always available on x86_64) and NEON (always available on aarch64) are used to process
+//! 16 pixels at once, other targets use the scalar code.
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+const LANES: usize = 16;
+
+/// Maps color indices. Built once per light level and reused.
+pub struct Lut {
+    map: [u8; 256],
+    identity: bool,
+}
+
+impl Lut {
+    pub fn new(f: impl Fn(u8) -> u8) -> Self {
+        let mut map = [0; 256];
+        for (i, v) in map.iter_mut().enumerate() {
+            *v = f(i as u8);
+        }
+        let identity = map.iter().enumerate().all(|(i, &v)| i == v as usize);
+        Self {
+            map,
+            identity,
+        }
+    }
+
+    pub fn get(&self, color_idx: u8) -> u8 {
+        self.map[color_idx as usize]
+    }
+}
+
+/// Copies non-transparent pixels of `src` to `dst`.
+pub fn copy_keyed(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    let done = copy_keyed_simd(dst, src);
+    copy_keyed_scalar(&mut dst[done..], &src[done..]);
+}
+
+/// Maps non-transparent pixels of `src` through `lut` and writes them to `dst`.
+pub fn map_keyed(dst: &mut [u8], src: &[u8], lut: &Lut) {
+    if lut.identity {
+        copy_keyed(dst, src);
+        return;
+    }
+    assert_eq!(dst.len(), src.len());
+    let mut i = 0;
+    while i < src.len() {
+        i += transparent_prefix(&src[i..]);
+        while i < src.len() && src[i] != 0 {
+            dst[i] = lut.get(src[i]);
+            i += 1;
+        }
+    }
+}
+
+/// Returns the number of leading transparent pixels in `src`.
+pub fn transparent_prefix(src: &[u8]) -> usize {
+    let r = transparent_prefix_simd(src);
+    r + src[r..].iter().take_while(|&&v| v == 0).count()
+}
+
+/// Same as `copy_keyed()` but without SIMD. Public for the benchmarks.
+pub fn copy_keyed_scalar(dst: &mut [u8], src: &[u8]) {
+    for (d, &s) in dst.iter_mut().zip(src) {
+        if s != 0 {
+            *d = s;
+        }
+    }
+}
+
+/// Processes whole 16 pixel chunks and returns the number of pixels processed.
+#[cfg(target_arch = "x86_64")]
+fn copy_keyed_simd(dst: &mut [u8], src: &[u8]) -> usize {
+    let n = src.len() / LANES * LANES;
+    let mut i = 0;
+    // Safe: SSE2 is part of the x86_64 baseline and all accesses are within `n`.
+    unsafe {
+        let zero = _mm_setzero_si128();
+        while i < n {
+            let s = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let d = _mm_loadu_si128(dst.as_ptr().add(i) as *const __m128i);
+            let transparent = _mm_cmpeq_epi8(s, zero);
+            // `s` is zero where transparent so OR-ing gives the selected pixels.
+            let r = _mm_or_si128(_mm_and_si128(transparent, d), s);
+            _mm_storeu_si128(dst.as_mut_ptr().add(i) as *mut __m128i, r);
+            i += LANES;
+        }
+    }
+    n
+}
+
+#[cfg(target_arch = "aarch64")]
+fn copy_keyed_simd(dst: &mut [u8], src: &[u8]) -> usize {
+    let n = src.len() / LANES * LANES;
+    let mut i = 0;
+    // Safe: NEON is part of the aarch64 baseline and all accesses are within `n`.
+    unsafe {
+        let zero = vdupq_n_u8(0);
+        while i < n {
+            let s = vld1q_u8(src.as_ptr().add(i));
+            let d = vld1q_u8(dst.as_ptr().add(i));
+            let transparent = vceqq_u8(s, zero);
+            vst1q_u8(dst.as_mut_ptr().add(i), vbslq_u8(transparent, d, s));
+            i += LANES;
+        }
+    }
+    n
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn copy_keyed_simd(_dst: &mut [u8], _src: &[u8]) -> usize {
+    0
+}
+
+/// Returns the number of leading pixels in whole transparent 16 pixel chunks.
+#[cfg(target_arch = "x86_64")]
+fn transparent_prefix_simd(src: &[u8]) -> usize {
+    let n = src.len() / LANES * LANES;
+    let mut i = 0;
+    // Safe: see `copy_keyed_simd()`.
+    unsafe {
+        let zero = _mm_setzero_si128();
+        while i < n {
+            let s = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            if _mm_movemask_epi8(_mm_cmpeq_epi8(s, zero)) != 0xffff {
+                break;
+            }
+            i += LANES;
+        }
+    }
+    i
+}
+
+#[cfg(target_arch = "aarch64")]
+fn transparent_prefix_simd(src: &[u8]) -> usize {
+    let n = src.len() / LANES * LANES;
+    let mut i = 0;
+    // Safe: see `copy_keyed_simd()`.
+    unsafe {
+        while i < n {
+            if vmaxvq_u8(vld1q_u8(src.as_ptr().add(i))) != 0 {
+                break;
+            }
+            i += LANES;
+        }
+    }
+    i
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn transparent_prefix_simd(_src: &[u8]) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern(len: usize, seed: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| {
+                let v = (i * 7 + seed * 13) % 23;
+                // Mix of transparent runs and opaque pixels.
+                if v < 9 || (i / 40) % 3 == 0 { 0 } else { v as u8 * 11 }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn copy_keyed_() {
+        for len in 0..100 {
+            let src = pattern(len, len);
+            let mut expected: Vec<_> = (0..len).map(|i| i as u8 | 1).collect();
+            let mut actual = expected.clone();
+            copy_keyed_scalar(&mut expected, &src);
+            copy_keyed(&mut actual, &src);
+            assert_eq!(actual, expected, "{}", len);
+        }
+    }
+
+    #[test]
+    fn map_keyed_() {
+        let lut = Lut::new(|c| c / 2);
+        assert!(!lut.identity);
+        assert!(Lut::new(|c| c).identity);
+        for len in 0..100 {
+            let src = pattern(len, 1);
+            let mut actual = vec![0xff; len];
+            map_keyed(&mut actual, &src, &lut);
+            let expected: Vec<_> = src.iter()
+                .map(|&c| if c == 0 { 0xff } else { c / 2 })
+                .collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn transparent_prefix_() {
+        assert_eq!(transparent_prefix(&[]), 0);
+        assert_eq!(transparent_prefix(&[1]), 0);
+        for len in 0..70 {
+            for opaque in 0..len {
+                let mut src = vec![0; len];
+                src[opaque] = 5;
+                assert_eq!(transparent_prefix(&src), opaque);
+            }
+            assert_eq!(transparent_prefix(&vec![0; len]), len);
+        }
+    }
+}
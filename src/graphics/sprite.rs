@@ -215,19 +215,33 @@ impl Sprite {
         }
     }
 
+    /// Returns the screen area `render()` can draw to.
+    pub fn screen_area(&self, frm_db: &FrameDb) -> Rect {
+        let frms = frm_db.get(self.fid).unwrap();
+        let frml = &frms.frame_lists[self.direction];
+        let frm = &frml.frames[self.frame_idx];
+
+        match self.effect {
+            Some(Effect::Fit { width, height }) =>
+                Rect::with_size(self.pos.x, self.pos.y, width, height),
+            _ => {
+                let top_left = self.bounds0(frml, frm).top_left();
+                let r = Rect::with_size(top_left.x, top_left.y, frm.width, frm.height);
+                if let Some(Effect::Outline { .. }) = self.effect {
+                    r.inflate(1, 1)
+                } else {
+                    r
+                }
+            }
+        }
+    }
+
     pub fn render(&self, canvas: &mut dyn Canvas, frm_db: &FrameDb) -> Rect {
         let frms = frm_db.get(self.fid).unwrap();
         let frml = &frms.frame_lists[self.direction];
         let frm = &frml.frames[self.frame_idx];
 
-        let bounds = match self.anchor {
-            Anchor::TopLeft => Rect::with_size(self.pos.x, self.pos.y, frm.width, frm.height),
-            Anchor::Center => Rect::with_size(
-                self.pos.x - frm.width / 2,
-                self.pos.y - frm.height / 2,
-                frm.width, frm.height),
-            Anchor::LogicalCenter => frm.bounds_centered(self.pos, frml.center),
-        };
+        let bounds = self.bounds0(frml, frm);
 
         match self.effect {
             Some(Effect::Translucency(trans)) => {
@@ -292,6 +306,17 @@ impl Sprite {
 
         bounds
     }
+
+    fn bounds0(&self, frml: &FrameList, frm: &Frame) -> Rect {
+        match self.anchor {
+            Anchor::TopLeft => Rect::with_size(self.pos.x, self.pos.y, frm.width, frm.height),
+            Anchor::Center => Rect::with_size(
+                self.pos.x - frm.width / 2,
+                self.pos.y - frm.height / 2,
+                frm.width, frm.height),
+            Anchor::LogicalCenter => frm.bounds_centered(self.pos, frml.center),
+        }
+    }
 }
 
 fn fit(r1: Rect, r2: Rect) -> Rect {
@@ -0,0 +1,384 @@
+//! Keyboard bindings: maps SDL keycodes to named game actions.
+//!
+//! The defaults follow the original hotkeys. They can be overridden in the `[keybindings]`
+//! section of `fallout2.cfg`, one entry per action:
+//!
+//! ```ini
+//! [keybindings]
+//! ; <action>=[<key>[,<key>...]]
+//! inventory=I,Tab
+//! pause=
+//! ```
+//!
+//! An entry replaces all default keys of the action, an empty value leaves the action unbound.
+//! Key names are the SDL key names (`SDL_GetKeyName`). The `bind` console command changes the
+//! bindings at runtime and saves them back to the section.
+
+use ini::Ini;
+use log::*;
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const SECTION: &str = "keybindings";
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    ScrollNorth,
+    ScrollEast,
+    ScrollSouth,
+    ScrollWest,
+    Attack,
+    ToggleActiveHand,
+    CycleItemMode,
+    Inventory,
+    Skilldex,
+    Pipboy,
+    Sneak,
+    Lockpick,
+    Steal,
+    Traps,
+    FirstAid,
+    Doctor,
+    Science,
+    Repair,
+    EndTurn,
+    EndCombat,
+    CenterOnDude,
+    ToggleRoof,
+    Pause,
+    ElevationUp,
+    ElevationDown,
+    AmbientLightDown,
+    AmbientLightUp,
+    ToggleConsole,
+    ToggleDebugInfo,
+    ToggleAtlasViewer,
+    ToggleProfiler,
+    ToggleMouseCapture,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 33] = [
+        Action::ScrollNorth,
+        Action::ScrollEast,
+        Action::ScrollSouth,
+        Action::ScrollWest,
+        Action::Attack,
+        Action::ToggleActiveHand,
+        Action::CycleItemMode,
+        Action::Inventory,
+        Action::Skilldex,
+        Action::Pipboy,
+        Action::Sneak,
+        Action::Lockpick,
+        Action::Steal,
+        Action::Traps,
+        Action::FirstAid,
+        Action::Doctor,
+        Action::Science,
+        Action::Repair,
+        Action::EndTurn,
+        Action::EndCombat,
+        Action::CenterOnDude,
+        Action::ToggleRoof,
+        Action::Pause,
+        Action::ElevationUp,
+        Action::ElevationDown,
+        Action::AmbientLightDown,
+        Action::AmbientLightUp,
+        Action::ToggleConsole,
+        Action::ToggleDebugInfo,
+        Action::ToggleAtlasViewer,
+        Action::ToggleProfiler,
+        Action::ToggleMouseCapture,
+        Action::Quit,
+    ];
+
+    /// Name of the action as used in the config.
+    pub fn name(self) -> &'static str {
+        use Action::*;
+        match self {
+            ScrollNorth => "scroll_north",
+            ScrollEast => "scroll_east",
+            ScrollSouth => "scroll_south",
+            ScrollWest => "scroll_west",
+            Attack => "attack",
+            ToggleActiveHand => "toggle_active_hand",
+            CycleItemMode => "cycle_item_mode",
+            Inventory => "inventory",
+            Skilldex => "skilldex",
+            Pipboy => "pipboy",
+            Sneak => "sneak",
+            Lockpick => "lockpick",
+            Steal => "steal",
+            Traps => "traps",
+            FirstAid => "first_aid",
+            Doctor => "doctor",
+            Science => "science",
+            Repair => "repair",
+            EndTurn => "end_turn",
+            EndCombat => "end_combat",
+            CenterOnDude => "center_on_dude",
+            ToggleRoof => "toggle_roof",
+            Pause => "pause",
+            ElevationUp => "elevation_up",
+            ElevationDown => "elevation_down",
+            AmbientLightDown => "ambient_light_down",
+            AmbientLightUp => "ambient_light_up",
+            ToggleConsole => "toggle_console",
+            ToggleDebugInfo => "toggle_debug_info",
+            ToggleAtlasViewer => "toggle_atlas_viewer",
+            ToggleProfiler => "toggle_profiler",
+            ToggleMouseCapture => "toggle_mouse_capture",
+            Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    fn default_keys(self) -> &'static [Keycode] {
+        use Action::*;
+        match self {
+            ScrollNorth => &[Keycode::Up],
+            ScrollEast => &[Keycode::Right],
+            ScrollSouth => &[Keycode::Down],
+            ScrollWest => &[Keycode::Left],
+            Attack => &[Keycode::A],
+            ToggleActiveHand => &[Keycode::B],
+            CycleItemMode => &[Keycode::N],
+            Inventory => &[Keycode::I],
+            Skilldex => &[Keycode::S],
+            Pipboy => &[Keycode::P],
+            Sneak => &[Keycode::Num1],
+            Lockpick => &[Keycode::Num2],
+            Steal => &[Keycode::Num3],
+            Traps => &[Keycode::Num4],
+            FirstAid => &[Keycode::Num5],
+            Doctor => &[Keycode::Num6],
+            Science => &[Keycode::Num7],
+            Repair => &[Keycode::Num8],
+            EndTurn => &[Keycode::Space],
+            EndCombat => &[Keycode::Return],
+            CenterOnDude => &[Keycode::Home],
+            ToggleRoof => &[Keycode::R],
+            Pause => &[Keycode::Pause],
+            ElevationUp => &[Keycode::PageUp],
+            ElevationDown => &[Keycode::PageDown],
+            AmbientLightDown => &[Keycode::LeftBracket],
+            AmbientLightUp => &[Keycode::RightBracket],
+            ToggleConsole => &[Keycode::F12],
+            ToggleDebugInfo => &[Keycode::Backquote],
+            ToggleAtlasViewer => &[Keycode::F11],
+            ToggleProfiler => &[Keycode::F10],
+            ToggleMouseCapture => &[Keycode::F9],
+            Quit => &[Keycode::Escape],
+        }
+    }
+}
+
+/// Mapping of keys to actions. A key is bound to at most one action, an action can have any
+/// number of keys.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    actions: HashMap<Keycode, Action>,
+}
+
+impl Bindings {
+    /// Creates bindings with no keys bound.
+    pub fn new() -> Self {
+        Self {
+            actions: HashMap::new(),
+        }
+    }
+
+    pub fn action(&self, key: Keycode) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+
+    /// Returns keys bound to the `action` ordered by the key code.
+    pub fn keys(&self, action: Action) -> Vec<Keycode> {
+        let mut r: Vec<_> = self.actions.iter()
+            .filter(|&(_, &a)| a == action)
+            .map(|(&k, _)| k)
+            .collect();
+        r.sort_by_key(|&k| k as i32);
+        r
+    }
+
+    /// Binds `key` to `action`. Returns the action the key was previously bound to.
+    pub fn bind(&mut self, key: Keycode, action: Action) -> Option<Action> {
+        self.actions.insert(key, action)
+    }
+
+    /// Returns the action the key was bound to.
+    pub fn unbind(&mut self, key: Keycode) -> Option<Action> {
+        self.actions.remove(&key)
+    }
+
+    /// Removes all keys bound to the `action`.
+    pub fn unbind_action(&mut self, action: Action) {
+        self.actions.retain(|_, &mut a| a != action);
+    }
+
+    /// Replaces the keys bound to the `action` with `keys`. The keys are taken from the actions
+    /// they were bound to.
+    pub fn set_keys(&mut self, action: Action, keys: &[Keycode]) {
+        self.unbind_action(action);
+        for &key in keys {
+            if let Some(old) = self.bind(key, action) {
+                if old != action {
+                    debug!("key {} rebound from {} to {}", key.name(), old.name(), action.name());
+                }
+            }
+        }
+    }
+
+    /// Applies the entries of the `[keybindings]` section over the current bindings.
+    /// Malformed entries are logged and skipped.
+    pub fn read_config(&mut self, ini: &Ini) {
+        if let Some(section) = ini.section(Some(SECTION)) {
+            for (k, v) in section.iter() {
+                match parse_entry(k, v) {
+                    Some((action, keys)) => self.set_keys(action, &keys),
+                    None => warn!("malformed key binding config entry: {}={}", k, v),
+                }
+            }
+        }
+    }
+
+    /// Writes all bindings to the `[keybindings]` section so the runtime changes can be saved.
+    pub fn write_config(&self, ini: &mut Ini) {
+        let mut section = ini.with_section(Some(SECTION));
+        for &action in &Action::ALL {
+            let keys: Vec<_> = self.keys(action).into_iter().map(|k| k.name()).collect();
+            section.set(action.name(), keys.join(","));
+        }
+    }
+
+    /// Writes the bindings to the `[keybindings]` section of the config file at `path`. The
+    /// other sections of the file are kept.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut ini = match Ini::load_from_file(path) {
+            Ok(v) => v,
+            Err(ini::Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ini::new(),
+            Err(ini::Error::Io(e)) => return Err(e),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        };
+        self.write_config(&mut ini);
+        ini.write_to_file(path)
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut r = Self::new();
+        for &action in &Action::ALL {
+            for &key in action.default_keys() {
+                r.bind(key, action);
+            }
+        }
+        r
+    }
+}
+
+/// Parses the `action` name and the comma-separated `value` key list.
+pub fn parse_entry(key: &str, value: &str) -> Option<(Action, Vec<Keycode>)> {
+    let action = Action::from_name(key)?;
+    let value = value.trim();
+    if value.is_empty() {
+        return Some((action, Vec::new()));
+    }
+    let keys = value.split(',')
+        .map(|s| Keycode::from_name(s.trim()))
+        .collect::<Option<Vec<_>>>()?;
+    Some((action, keys))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn action_names() {
+        for &action in &Action::ALL {
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+        assert_eq!(Action::from_name(" First_Aid "), Some(Action::FirstAid));
+        assert_eq!(Action::from_name("foo"), None);
+    }
+
+    #[test]
+    fn default_bindings() {
+        let b = Bindings::default();
+        assert_eq!(b.action(Keycode::A), Some(Action::Attack));
+        assert_eq!(b.action(Keycode::P), Some(Action::Pipboy));
+        assert_eq!(b.keys(Action::ElevationUp), vec![Keycode::PageUp]);
+        assert_eq!(b.keys(Action::ElevationDown), vec![Keycode::PageDown]);
+        assert_eq!(b.keys(Action::Pause), vec![Keycode::Pause]);
+        assert_eq!(b.action(Keycode::N), Some(Action::CycleItemMode));
+        assert_eq!(b.action(Keycode::Num1), Some(Action::Sneak));
+        assert_eq!(b.action(Keycode::Num8), Some(Action::Repair));
+        assert_eq!(b.action(Keycode::Z), None);
+
+        // Every default key is bound once.
+        let count: usize = Action::ALL.iter().map(|a| a.default_keys().len()).sum();
+        assert_eq!(b.actions.len(), count);
+    }
+
+    #[test]
+    fn bind_unbind() {
+        let mut b = Bindings::default();
+        assert_eq!(b.bind(Keycode::Tab, Action::Inventory), None);
+        assert_eq!(b.keys(Action::Inventory), vec![Keycode::Tab, Keycode::I]);
+        assert_eq!(b.bind(Keycode::A, Action::Inventory), Some(Action::Attack));
+        assert_eq!(b.keys(Action::Attack), vec![]);
+        assert_eq!(b.unbind(Keycode::Tab), Some(Action::Inventory));
+        assert_eq!(b.unbind(Keycode::Tab), None);
+        b.unbind_action(Action::Inventory);
+        assert_eq!(b.keys(Action::Inventory), vec![]);
+
+        b.set_keys(Action::Attack, &[Keycode::P, Keycode::F]);
+        assert_eq!(b.keys(Action::Attack), vec![Keycode::F, Keycode::P]);
+        assert_eq!(b.keys(Action::Pipboy), vec![]);
+    }
+
+    #[test]
+    fn read_config() {
+        let ini = Ini::load_from_str("\
+[keybindings]
+inventory = I, Tab
+pause =
+toggle_roof = NoSuchKey
+foo = F
+").unwrap();
+        let mut b = Bindings::default();
+        b.read_config(&ini);
+        assert_eq!(b.keys(Action::Inventory), vec![Keycode::Tab, Keycode::I]);
+        assert_eq!(b.keys(Action::Pause), vec![]);
+        assert_eq!(b.keys(Action::ToggleRoof), vec![Keycode::R]);
+        assert_eq!(b.action(Keycode::F), None);
+    }
+
+    #[test]
+    fn write_config() {
+        let mut b = Bindings::default();
+        b.bind(Keycode::Tab, Action::Inventory);
+        b.unbind_action(Action::Pause);
+        let mut ini = Ini::new();
+        b.write_config(&mut ini);
+        assert_eq!(ini.get_from(Some(SECTION), "inventory"), Some("Tab,I"));
+        assert_eq!(ini.get_from(Some(SECTION), "pause"), Some(""));
+
+        let mut b2 = Bindings::new();
+        b2.read_config(&ini);
+        for &action in &Action::ALL {
+            assert_eq!(b2.keys(action), b.keys(action));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Input recording and replay.
+//!
+//! A replay is the map, the random seed and the input events stamped with the main loop tick they
+//! happened at. While recording or replaying the game clock advances by a fixed step each tick so
+//! replaying the same events results in the same game state.
+//!
+//! Replays are stored in a line based text format:
+//!
+//! ```text
+//! vault13-replay 3
+//! map artemple
+//! seed 13
+//! 120 mouse_move 320 245
+//! 130 mouse_down left
+//! 134 mouse_up left
+//! 140 mouse_wheel -1
+//! 200 key_down 0001 Left Shift
+//! 210 text 6869
+//! 300 end
+//! ```
+//!
+//! Mouse moves have the cursor position on the game screen so the replay doesn't depend on the
+//! window size and the mouse mode. Key events have the hex encoded SDL key modifiers followed by
+//! the SDL key name. Text input is hex encoded.
+
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::{MouseButton, MouseState, MouseWheelDirection};
+use std::io::{self, prelude::*};
+use std::str::FromStr;
+
+use crate::graphics::Point;
+
+const HEADER: &str = "vault13-replay 3";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Input {
+    KeyDown(Keycode, Mod),
+    KeyUp(Keycode, Mod),
+    Text(String),
+    /// Cursor moved to the position on the game screen.
+    MouseMove(Point),
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    /// Wheel scrolled by `y` clicks, positive away from the user.
+    MouseWheel(i32),
+    Quit,
+}
+
+impl Input {
+    /// Returns `None` for the events the game doesn't handle. `cursor_pos` is the cursor
+    /// position on the game screen after handling the `event`.
+    pub fn from_sdl(event: &SdlEvent, cursor_pos: Point) -> Option<Self> {
+        Some(match *event {
+            SdlEvent::KeyDown { keycode: Some(k), keymod, .. } => Input::KeyDown(k, keymod),
+            SdlEvent::KeyUp { keycode: Some(k), keymod, .. } => Input::KeyUp(k, keymod),
+            SdlEvent::TextInput { ref text, .. } => Input::Text(text.clone()),
+            SdlEvent::MouseMotion { .. } => Input::MouseMove(cursor_pos),
+            SdlEvent::MouseButtonDown { mouse_btn, .. } => Input::MouseDown(mouse_btn),
+            SdlEvent::MouseButtonUp { mouse_btn, .. } => Input::MouseUp(mouse_btn),
+            SdlEvent::MouseWheel { y, direction, .. } => {
+                Input::MouseWheel(if direction == MouseWheelDirection::Flipped { -y } else { y })
+            }
+            SdlEvent::Quit { .. } => Input::Quit,
+            _ => return None,
+        })
+    }
+
+    /// Mouse moves are converted to motion events with the cursor position on the game screen
+    /// in `x` and `y` and no relative motion.
+    pub fn to_sdl(&self) -> SdlEvent {
+        match *self {
+            Input::KeyDown(k, keymod) => SdlEvent::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(k),
+                scancode: None,
+                keymod,
+                repeat: false,
+            },
+            Input::KeyUp(k, keymod) => SdlEvent::KeyUp {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(k),
+                scancode: None,
+                keymod,
+                repeat: false,
+            },
+            Input::Text(ref text) => SdlEvent::TextInput {
+                timestamp: 0,
+                window_id: 0,
+                text: text.clone(),
+            },
+            Input::MouseMove(pos) => SdlEvent::MouseMotion {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mousestate: MouseState::from_sdl_state(0),
+                x: pos.x,
+                y: pos.y,
+                xrel: 0,
+                yrel: 0,
+            },
+            Input::MouseDown(mouse_btn) => SdlEvent::MouseButtonDown {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            },
+            Input::MouseUp(mouse_btn) => SdlEvent::MouseButtonUp {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            },
+            Input::MouseWheel(y) => SdlEvent::MouseWheel {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                x: 0,
+                y,
+                direction: MouseWheelDirection::Normal,
+                precise_x: 0.0,
+                precise_y: y as f32,
+            },
+            Input::Quit => SdlEvent::Quit { timestamp: 0 },
+        }
+    }
+
+    fn write(&self, wr: &mut impl Write) -> io::Result<()> {
+        match self {
+            Input::KeyDown(k, m) => write!(wr, "key_down {:04x} {}", m.bits(), k.name()),
+            Input::KeyUp(k, m) => write!(wr, "key_up {:04x} {}", m.bits(), k.name()),
+            Input::Text(text) => {
+                write!(wr, "text ")?;
+                for b in text.bytes() {
+                    write!(wr, "{:02x}", b)?;
+                }
+                Ok(())
+            }
+            Input::MouseMove(pos) => write!(wr, "mouse_move {} {}", pos.x, pos.y),
+            Input::MouseDown(b) => write!(wr, "mouse_down {}", mouse_button_name(*b)),
+            Input::MouseUp(b) => write!(wr, "mouse_up {}", mouse_button_name(*b)),
+            Input::MouseWheel(y) => write!(wr, "mouse_wheel {}", y),
+            Input::Quit => write!(wr, "quit"),
+        }
+    }
+
+    fn parse(kind: &str, args: &str) -> Option<Self> {
+        let ints = || -> Option<Vec<i32>> {
+            args.split_whitespace().map(|s| s.parse().ok()).collect()
+        };
+        let key = || -> Option<(Keycode, Mod)> {
+            let mut parts = args.splitn(2, ' ');
+            let keymod = u16::from_str_radix(parts.next()?, 16).ok()?;
+            let keycode = Keycode::from_name(parts.next()?)?;
+            Some((keycode, Mod::from_bits_truncate(keymod)))
+        };
+        Some(match kind {
+            "key_down" => {
+                let (k, m) = key()?;
+                Input::KeyDown(k, m)
+            }
+            "key_up" => {
+                let (k, m) = key()?;
+                Input::KeyUp(k, m)
+            }
+            "text" => {
+                if args.len() % 2 != 0 {
+                    return None;
+                }
+                let bytes = (0..args.len()).step_by(2)
+                    .map(|i| u8::from_str_radix(args.get(i..i + 2)?, 16).ok())
+                    .collect::<Option<Vec<_>>>()?;
+                Input::Text(String::from_utf8(bytes).ok()?)
+            }
+            "mouse_move" => {
+                let v = ints()?;
+                if v.len() != 2 {
+                    return None;
+                }
+                Input::MouseMove(Point::new(v[0], v[1]))
+            }
+            "mouse_down" => Input::MouseDown(mouse_button_from_name(args)?),
+            "mouse_up" => Input::MouseUp(mouse_button_from_name(args)?),
+            "mouse_wheel" => Input::MouseWheel(parse(args)?),
+            "quit" if args.is_empty() => Input::Quit,
+            _ => return None,
+        })
+    }
+}
+
+const MOUSE_BUTTONS: &[(MouseButton, &str)] = &[
+    (MouseButton::Left, "left"),
+    (MouseButton::Middle, "middle"),
+    (MouseButton::Right, "right"),
+    (MouseButton::X1, "x1"),
+    (MouseButton::X2, "x2"),
+    (MouseButton::Unknown, "unknown"),
+];
+
+fn mouse_button_name(button: MouseButton) -> &'static str {
+    MOUSE_BUTTONS.iter().find(|&&(b, _)| b == button).unwrap().1
+}
+
+fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    MOUSE_BUTTONS.iter().find(|&&(_, n)| n == name).map(|&(b, _)| b)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Replay {
+    pub map: String,
+    pub seed: u64,
+    /// Input events with the ticks they happened at, in order.
+    pub events: Vec<(u64, Input)>,
+    /// Tick the recording ended at.
+    pub end: u64,
+}
+
+impl Replay {
+    pub fn new(map: String, seed: u64) -> Self {
+        Self {
+            map,
+            seed,
+            events: Vec::new(),
+            end: 0,
+        }
+    }
+
+    pub fn read(rd: &mut impl BufRead) -> io::Result<Self> {
+        let mut lines = rd.lines().enumerate()
+            .map(|(i, l)| l.map(|l| (i + 1, l)))
+            .filter(|l| l.as_ref().map(|(_, l)| !l.trim().is_empty()).unwrap_or(true));
+        let mut next_line = || -> io::Result<(usize, String)> {
+            lines.next().transpose()?.ok_or_else(|| error("unexpected end of replay"))
+        };
+
+        let (_, header) = next_line()?;
+        if header.trim() != HEADER {
+            return Err(error(format!("unsupported replay format: {}", header.trim())));
+        }
+        let mut header_value = |key: &str| -> io::Result<String> {
+            let (i, line) = next_line()?;
+            let mut parts = line.trim().splitn(2, ' ');
+            if parts.next() != Some(key) {
+                return Err(error(format!("line {}: expected `{}`", i, key)));
+            }
+            Ok(parts.next().unwrap_or("").trim().into())
+        };
+        let map = header_value("map")?;
+        let seed = header_value("seed")?;
+        let seed = parse(&seed).ok_or_else(|| error(format!("invalid seed: {}", seed)))?;
+
+        let mut r = Self::new(map, seed);
+        loop {
+            let (i, line) = next_line()?;
+            let line = line.trim();
+            let bad_line = || error(format!("line {}: malformed event: {}", i, line));
+            let mut parts = line.splitn(3, ' ');
+            let tick: u64 = parts.next().and_then(parse).ok_or_else(bad_line)?;
+            if tick < r.events.last().map(|&(t, _)| t).unwrap_or(0) {
+                return Err(error(format!("line {}: tick goes backwards", i)));
+            }
+            let kind = parts.next().ok_or_else(bad_line)?;
+            let args = parts.next().unwrap_or("");
+            if kind == "end" {
+                r.end = tick;
+                break;
+            }
+            let input = Input::parse(kind, args).ok_or_else(bad_line)?;
+            r.events.push((tick, input));
+        }
+        Ok(r)
+    }
+
+    pub fn write(&self, wr: &mut impl Write) -> io::Result<()> {
+        writeln!(wr, "{}", HEADER)?;
+        writeln!(wr, "map {}", self.map)?;
+        writeln!(wr, "seed {}", self.seed)?;
+        for (tick, input) in &self.events {
+            write!(wr, "{} ", tick)?;
+            input.write(wr)?;
+            writeln!(wr)?;
+        }
+        writeln!(wr, "{} end", self.end)
+    }
+}
+
+fn parse<T: FromStr>(s: &str) -> Option<T> {
+    s.parse().ok()
+}
+
+fn error(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Records input events into a replay.
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    pub fn new(map: String, seed: u64) -> Self {
+        Self {
+            replay: Replay::new(map, seed),
+        }
+    }
+
+    /// Records the `event` after it's been handled. `cursor_pos` is the resulting cursor
+    /// position on the game screen.
+    pub fn record(&mut self, tick: u64, event: &SdlEvent, cursor_pos: Point) {
+        if let Some(input) = Input::from_sdl(event, cursor_pos) {
+            self.replay.events.push((tick, input));
+        }
+    }
+
+    pub fn finish(mut self, tick: u64) -> Replay {
+        self.replay.end = tick;
+        self.replay
+    }
+}
+
+/// Feeds the events of a replay back tick by tick.
+pub struct Player {
+    replay: Replay,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next: 0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Whether the replay has reached its end at the `tick`.
+    pub fn is_done(&self, tick: u64) -> bool {
+        tick >= self.replay.end
+    }
+
+    /// Returns the events that happened at the `tick`. Must be called with increasing ticks.
+    pub fn events(&mut self, tick: u64) -> Vec<SdlEvent> {
+        let mut r = Vec::new();
+        while let Some((t, input)) = self.replay.events.get(self.next) {
+            if *t > tick {
+                break;
+            }
+            r.push(input.to_sdl());
+            self.next += 1;
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay {
+            map: "artemple".into(),
+            seed: 13,
+            events: vec![
+                (1, Input::MouseMove(Point::new(320, 245))),
+                (1, Input::MouseDown(MouseButton::Left)),
+                (4, Input::MouseUp(MouseButton::Right)),
+                (10, Input::KeyDown(Keycode::LShift, Mod::LSHIFTMOD)),
+                (11, Input::KeyUp(Keycode::A, Mod::LSHIFTMOD | Mod::NUMMOD)),
+                (12, Input::MouseWheel(-2)),
+                (20, Input::Text("hi there".into())),
+                (21, Input::Quit),
+            ],
+            end: 30,
+        }
+    }
+
+    #[test]
+    fn write_read() {
+        let mut buf = Vec::new();
+        replay().write(&mut buf).unwrap();
+        assert_eq!(Replay::read(&mut &buf[..]).unwrap(), replay());
+    }
+
+    #[test]
+    fn sdl_round_trip() {
+        for (_, input) in replay().events {
+            let event = input.to_sdl();
+            let cursor_pos = if let SdlEvent::MouseMotion { x, y, .. } = event {
+                Point::new(x, y)
+            } else {
+                Point::new(0, 0)
+            };
+            assert_eq!(Input::from_sdl(&event, cursor_pos).as_ref(), Some(&input));
+        }
+
+        // The resulting cursor position is recorded rather than the motion.
+        let motion = SdlEvent::MouseMotion {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            mousestate: MouseState::from_sdl_state(0),
+            x: 1000,
+            y: 900,
+            xrel: -3,
+            yrel: 5,
+        };
+        assert_eq!(Input::from_sdl(&motion, Point::new(10, 20)),
+            Some(Input::MouseMove(Point::new(10, 20))));
+    }
+
+    #[test]
+    fn read_errors() {
+        let read = |s: &str| Replay::read(&mut s.as_bytes()).unwrap_err().to_string();
+        assert_eq!(read("foo\n"), "unsupported replay format: foo");
+        assert_eq!(read("vault13-replay 2\n"), "unsupported replay format: vault13-replay 2");
+        assert_eq!(read("vault13-replay 3\nmap a\nseed x\n"), "invalid seed: x");
+        assert_eq!(read("vault13-replay 3\nmap a\nseed 1\n5 jump\n"),
+            "line 4: malformed event: 5 jump");
+        assert_eq!(read("vault13-replay 3\nmap a\nseed 1\n5 mouse_move 1\n"),
+            "line 4: malformed event: 5 mouse_move 1");
+        assert_eq!(read("vault13-replay 3\nmap a\nseed 1\n5 quit\n4 quit\n"),
+            "line 5: tick goes backwards");
+        assert_eq!(read("vault13-replay 3\nmap a\nseed 1\n5 quit\n"),
+            "unexpected end of replay");
+    }
+
+    #[test]
+    fn player() {
+        let mut p = Player::new(replay());
+        assert!(p.events(0).is_empty());
+        let e = p.events(1);
+        assert_eq!(e.len(), 2);
+        assert_eq!(Input::from_sdl(&e[1], Point::new(0, 0)),
+            Some(Input::MouseDown(MouseButton::Left)));
+        assert_eq!(p.events(9).len(), 1);
+        assert_eq!(p.events(25).len(), 5);
+        assert!(!p.is_done(29));
+        assert!(p.is_done(30));
+    }
+}
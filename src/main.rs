@@ -11,9 +11,12 @@
 mod macros;
 
 mod asset;
+mod audio;
+mod error;
 mod fs;
 mod game;
 mod graphics;
+mod input;
 mod sequence;
 mod state;
 mod ui;
@@ -21,31 +24,50 @@ mod util;
 mod vm;
 
 use log::*;
-use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Root};
-use log4rs::Config;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use num_traits::FromPrimitive;
+use sdl2::event::{Event, WindowEvent};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use crate::asset::font::load_fonts;
 use crate::asset::frame::{FrameDb, FrameId};
-use crate::asset::message::Messages;
+use crate::asset::message::localization::Localization;
+use crate::asset::message::encoding::Encoding;
 use crate::asset::palette::read_palette;
 use crate::asset::proto::ProtoDb;
 use crate::asset::EntityKind;
+use crate::audio::Audio;
+use crate::error::ResultExt;
+use crate::fs::watch::Watcher;
+use crate::game::benchmark::Benchmark;
+use crate::game::death::ViolenceLevel;
+use crate::game::headless::{self, Headless};
+use crate::game::mods::Mods;
+use crate::game::render_map::RenderMap;
+use crate::game::sfx::Sound;
 use crate::game::state::GameState;
 use crate::game::ui::world::WorldView;
 use crate::graphics::color::palette::overlay::PaletteOverlay;
 use crate::graphics::color::{BLACK, GREEN};
-use crate::graphics::font::{self, FontKey};
+use crate::graphics::font::{self, FontKey, Fonts};
 use crate::graphics::geometry::sqr;
 use crate::graphics::geometry::TileGridView;
-use crate::graphics::render::software::Backend;
-use crate::graphics::{EPoint, Point};
-use crate::state::{AppState, HandleAppEvent, Update};
+use crate::graphics::render::{null, software, Canvas, TextureFactory};
+use crate::graphics::{EPoint, Point, Rect};
+use crate::input::bindings::Action as KeyAction;
+use crate::input::replay::{Player, Recorder, Replay};
+use crate::state::{AppEvent, AppState, HandleAppEvent, Update};
+use crate::state::death::{self, DeathScreen};
+use crate::state::main_menu::MainMenu;
+use crate::state::movie::MoviePlayer;
+use crate::state::slideshow::Slideshow;
 use crate::ui::Ui;
+use crate::util::{logging, profile};
+use crate::util::telemetry::StartupReport;
+use crate::vm::debug::Trace;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const GIT_HASH: &str = env!("GIT_HASH");
@@ -54,6 +76,10 @@ const GIT_TIMESTAMP: &str = env!("GIT_TIMESTAMP");
 const GIT_DATE: &str = env!("GIT_DATE");
 const GIT_VERSION_STATUS: &str = env!("GIT_VERSION_STATUS");
 
+const RESOURCE_DIR_HELP: &str = "One or more resource directories where master.dat, critter.dat \
+    and patchXXX.dat can be found, separated with `:` (`;` on Windows) and listed from the highest \
+    priority. `auto` detects Steam or GOG install";
+
 fn version() -> String {
     let (dev, dirty) = match GIT_VERSION_STATUS {
         "Stable" => ("", ""),
@@ -75,22 +101,281 @@ fn args() -> clap::App<'static, 'static> {
     use clap::*;
 
     App::new(format!("Vault 13 {} ({})", VERSION, GIT_DATE))
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("RESOURCE_DIR")
-            .help("One or more resource directories where master.dat, critter.dat and patchXXX.dat \
-                   can be found")
+            .help(RESOURCE_DIR_HELP)
             .required_unless("version"))
         .arg(Arg::with_name("MAP")
             .help("Map name to load. For example: artemple")
-            .required_unless("version"))
+            .required_unless_one(&["version", "benchmark", "disasm", "replay"]))
         .arg(Arg::with_name("version")
             .short("v")
             .long("version")
             .help("Prints version information"))
+        .arg(Arg::with_name("benchmark")
+            .long("benchmark")
+            .value_name("MAP")
+            .help("Runs a scripted camera tour and combat on MAP with fixed random seed \
+                   and reports frame times on exit")
+            .takes_value(true))
+        .arg(Arg::with_name("disasm")
+            .long("disasm")
+            .value_name("SCRIPT")
+            .help("Prints disassembly of SCRIPT (for example: artemple) and exits")
+            .takes_value(true))
+        .arg(Arg::with_name("headless")
+            .long("headless")
+            .help("Runs the game loop without window and input at a fixed tick rate and with \
+                   fixed random seed, prints the resulting game state summary on exit"))
+        .arg(Arg::with_name("ticks")
+            .long("ticks")
+            .value_name("N")
+            .help("Number of game loop ticks to run in headless mode (600 by default)")
+            .requires("headless")
+            .takes_value(true))
+        .arg(Arg::with_name("record")
+            .long("record")
+            .value_name("FILE")
+            .help("Records input events and random seed to FILE for replaying")
+            .conflicts_with("replay")
+            .takes_value(true))
+        .arg(Arg::with_name("replay")
+            .long("replay")
+            .value_name("FILE")
+            .help("Replays input events recorded in FILE on the recorded map. Combine with \
+                   --headless to replay without window")
+            .takes_value(true))
+        .arg(Arg::with_name("game")
+            .long("game")
+            .value_name("GAME")
+            .help("Game whose data is in RESOURCE_DIR. Fallout 1 data can only be browsed, it can't \
+                   be played through: map exits and the Fallout 1 map, script and world map \
+                   differences aren't supported")
+            .possible_values(asset::Game::NAMES)
+            .global(true)
+            .takes_value(true))
+        .arg(Arg::with_name("strict-assets")
+            .long("strict-assets")
+            .help("Fails loading maps that deviate from the format (unknown version, unknown flag \
+                   bits, mismatching object counts) instead of warning and working around")
+            .global(true))
+        .arg(Arg::with_name("rng-seed")
+            .long("rng-seed")
+            .value_name("SEED")
+            .help("Seeds the random generator with SEED making the game rolls reproducible")
+            .takes_value(true))
+        .arg(Arg::with_name("startup-report")
+            .long("startup-report")
+            .value_name("FILE")
+            .help("Writes startup timing breakdown as JSON to FILE (use - for stdout)")
+            .takes_value(true))
+        .arg(Arg::with_name("cache-dir")
+            .long("cache-dir")
+            .value_name("DIR")
+            .help("Stores indexes of the resource files in DIR to speed up subsequent startups")
+            .takes_value(true))
+        .arg(Arg::with_name("save-dir")
+            .long("save-dir")
+            .value_name("DIR")
+            .help("Keeps the save-game data, such as the state of the visited maps, in DIR")
+            .takes_value(true))
+        .arg(Arg::with_name("hot-reload")
+            .long("hot-reload")
+            .help("Watches loose files in the data directory and reloads changed art, protos \
+                   and messages without restarting"))
+        .subcommand(SubCommand::with_name("dat")
+            .about("Inspects DAT2 archives (master.dat, critter.dat, patch000.dat)")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(dat_subcommand("list")
+                .about("Lists entries of ARCHIVE matching PATTERN with their sizes"))
+            .subcommand(dat_subcommand("extract")
+                .about("Extracts entries of ARCHIVE matching PATTERN")
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("DIR")
+                    .help("Directory to extract the entries to")
+                    .required(true)
+                    .takes_value(true))))
+        .subcommand(SubCommand::with_name("render-map")
+            .about("Renders floor, roof and objects of the whole MAP elevation into a PNG image")
+            .arg(Arg::with_name("RESOURCE_DIR")
+                .help(RESOURCE_DIR_HELP)
+                .required(true))
+            .arg(Arg::with_name("MAP")
+                .help("Map name to render. For example: artemple")
+                .required(true))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("PNG file to write the image to")
+                .required(true)
+                .takes_value(true))
+            .arg(Arg::with_name("elevation")
+                .long("elevation")
+                .value_name("N")
+                .help("Elevation to render (0-2). The map entrance elevation by default")
+                .takes_value(true))
+            .arg(Arg::with_name("no-roof")
+                .long("no-roof")
+                .help("Doesn't render roof tiles"))
+            .arg(Arg::with_name("reference")
+                .long("reference")
+                .value_name("FILE")
+                .help("Reference PNG image to compare the rendered image with. \
+                       Exits with error if the images differ")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("dump")
+            .about("Dumps game databases")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("protos")
+                .about("Dumps fields of all prototypes as CSV (or JSON with --json)")
+                .arg(Arg::with_name("RESOURCE_DIR")
+                    .help(RESOURCE_DIR_HELP)
+                    .required(true))
+                .arg(Arg::with_name("kind")
+                    .long("kind")
+                    .value_name("KIND")
+                    .help("Dumps only prototypes of KIND. All prototypes by default")
+                    .possible_values(&["item", "critter", "scenery", "wall", "tile", "misc"])
+                    .takes_value(true))
+                .arg(Arg::with_name("json")
+                    .long("json")
+                    .help("Writes JSON instead of CSV"))
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("FILE")
+                    .help("File to write the dump to instead of stdout")
+                    .takes_value(true))))
+        .subcommand(SubCommand::with_name("check")
+            .about("Loads MAP and reports dangling references, unloadable frames, blocked exit \
+                    grids and script procedure mismatches. Exits with status 1 if any problems \
+                    are found")
+            .arg(Arg::with_name("RESOURCE_DIR")
+                .help(RESOURCE_DIR_HELP)
+                .required(true))
+            .arg(Arg::with_name("MAP")
+                .help("Map name to check (for example: artemple) or `all` to check all maps \
+                       defined in maps.txt")
+                .required(true)))
+        .subcommand(SubCommand::with_name("frm")
+            .about("Inspects FRM sprite files")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("export")
+                .about("Exports FRM as PNG sprite sheet with sidecar JSON describing directions \
+                        and frame offsets")
+                .arg(Arg::with_name("RESOURCE_DIR")
+                    .help(RESOURCE_DIR_HELP)
+                    .required(true))
+                .arg(Arg::with_name("FRM")
+                    .help("FID (for example: 0x01000008) or FRM file path (for example: \
+                           art/critters/hmjmpsaa.frm)")
+                    .required(true))
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("DIR")
+                    .help("Directory to write the files to. Current directory by default")
+                    .takes_value(true))
+                .arg(Arg::with_name("animated")
+                    .long("animated")
+                    .help("Also writes animated PNG for each direction")))
+            .subcommand(SubCommand::with_name("import")
+                .about("Builds FRM from PNG sprite sheet and sidecar JSON as written by export, \
+                        quantizing the colors to the game palette")
+                .arg(Arg::with_name("RESOURCE_DIR")
+                    .help(RESOURCE_DIR_HELP)
+                    .required(true))
+                .arg(Arg::with_name("JSON")
+                    .help("Sidecar JSON file referring to the sprite sheet")
+                    .required(true))
+                .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_name("FILE")
+                    .help("FRM file to write")
+                    .required(true)
+                    .takes_value(true))))
         .after_help(
             "EXAMPLE:\n\
-          \x20   vault13 /path/to/fallout2 artemple")
+          \x20   vault13 /path/to/fallout2 artemple\n\
+          \x20   vault13 render-map /path/to/fallout2 artemple -o artemple.png\n\
+          \x20   vault13 check /path/to/fallout2 all\n\
+          \x20   vault13 dat extract /path/to/fallout2/master.dat 'maps/*' -o out\n\
+          \x20   vault13 frm export /path/to/fallout2 art/critters/hmjmpsaa.frm --animated\n\
+          \x20   vault13 frm import /path/to/fallout2 hmjmpsaa.json -o hmjmpsaa.frm")
+}
+
+fn dat_subcommand(name: &'static str) -> clap::App<'static, 'static> {
+    use clap::*;
+
+    SubCommand::with_name(name)
+        .arg(Arg::with_name("ARCHIVE")
+            .help("DAT2 archive file")
+            .required(true))
+        .arg(Arg::with_name("PATTERN")
+            .help("Entry path pattern with * and ? wildcards, for example: art/critters/*.frm. \
+                   All entries by default"))
+}
+
+fn dat_command(args: &clap::ArgMatches) -> io::Result<()> {
+    use crate::fs::dat::extract::{self, Pattern};
+
+    let (cmd, args) = args.subcommand();
+    let args = args.unwrap();
+    let archive = fs::dat::v2::Dat::new(args.value_of("ARCHIVE").unwrap())?;
+    let pattern = args.value_of("PATTERN").map(Pattern::new).unwrap_or_else(Pattern::any);
+    match cmd {
+        "list" => extract::write_list(&extract::list(&archive, &pattern),
+            &mut io::stdout().lock()),
+        "extract" => {
+            let out_dir = Path::new(args.value_of("output").unwrap());
+            let count = extract::extract(&archive, &pattern, out_dir)?;
+            info!("Extracted {} entries to {}", count, out_dir.display());
+            Ok(())
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Draws texture atlas `page` with its stats in the bottom right corner of the screen.
+fn draw_atlas_page(canvas: &mut dyn Canvas, page: usize) {
+    const SIZE: i32 = 256;
+
+    let pages = canvas.atlas_pages();
+    let info = if let Some(v) = pages.get(page) {
+        v
+    } else {
+        return;
+    };
+    let screen = canvas.size();
+    let dst = Rect::with_size(screen.x - SIZE - 2, screen.y - SIZE - 2, SIZE, SIZE);
+    canvas.fill_rect(Rect::with_size(dst.left - 1, dst.top - 1, SIZE + 2, SIZE + 2), GREEN);
+    canvas.fill_rect(dst, BLACK);
+    canvas.draw_atlas_page(page, dst);
+
+    let used = info.stats.used_area * 100 / (info.width * info.height) as usize;
+    let msg = format!("atlas page {}/{}: {} textures, {}% used",
+        page + 1, pages.len(), info.stats.count, used);
+    canvas.draw_text(
+        msg.as_bytes().into(),
+        Point::new(dst.left, dst.top - 12),
+        FontKey::antialiased(1),
+        GREEN,
+        &font::DrawOptions {
+            dst_color: Some(BLACK),
+            ..Default::default()
+        });
 }
 
+/// How often the loose data directories are checked for changes in `--hot-reload` mode.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frame duration the main loop is throttled to.
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
 struct Timer {
     time: Instant,
     last: Instant,
@@ -116,6 +401,132 @@ impl Timer {
     }
 }
 
+fn disassemble_script(fs: &fs::FileSystem, name: &str) -> io::Result<()> {
+    let name = name.to_lowercase();
+    let name = name.strip_suffix(".int").unwrap_or(&name);
+    let mut code = Vec::new();
+    fs.reader(&format!("scripts/{}.int", name))?.read_to_end(&mut code)?;
+    let program = vm::Vm::default().load(name.into(), code.into())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+            format!("error loading program {}: {:?}", name, e)))?;
+    vm::debug::write_disassembly(&program, &mut io::stdout().lock())
+}
+
+fn dump_protos(fs: &Rc<fs::FileSystem>, language: &str, args: &clap::ArgMatches)
+    -> io::Result<()>
+{
+    use crate::asset::proto::dump::{self, Format};
+
+    let proto_db = ProtoDb::new(fs.clone(), language)?;
+    let kinds: Vec<_> = match args.value_of("kind") {
+        Some("item") => vec![EntityKind::Item],
+        Some("critter") => vec![EntityKind::Critter],
+        Some("scenery") => vec![EntityKind::Scenery],
+        Some("wall") => vec![EntityKind::Wall],
+        Some("tile") => vec![EntityKind::SqrTile],
+        Some("misc") => vec![EntityKind::Misc],
+        Some(_) => unreachable!(),
+        None => crate::asset::proto::proto_entity_kinds().collect(),
+    };
+    let format = if args.is_present("json") { Format::Json } else { Format::Csv };
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match args.value_of("output") {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    });
+    dump::dump(&proto_db, &kinds, format, &mut out)?;
+    out.flush()
+}
+
+/// Checks the maps printing the problems found. Returns the total number of problems.
+fn check_maps(fs: &Rc<fs::FileSystem>, language: &str, args: &clap::ArgMatches)
+    -> io::Result<usize>
+{
+    use crate::game::check::Checker;
+
+    let proto_db = Rc::new(ProtoDb::new(fs.clone(), language)?);
+    let texture_factory = null::Backend::new().new_texture_factory();
+    let frm_db = Rc::new(FrameDb::new(fs.clone(), language, texture_factory)?);
+    let checker = Checker::new(fs.clone(), language, proto_db, frm_db,
+        args.is_present("strict-assets"))?;
+
+    let map = args.value_of("MAP").unwrap().to_lowercase();
+    let maps = if map == "all" {
+        checker.map_names()
+    } else {
+        vec![map.strip_suffix(".map").unwrap_or(&map).into()]
+    };
+
+    let out = &mut io::stdout();
+    let mut count = 0;
+    for map in &maps {
+        let problems = checker.check(map)?;
+        for p in &problems {
+            writeln!(out, "{}: {}", map, p)?;
+        }
+        count += problems.len();
+    }
+    info!("Checked {} map(s), found {} problem(s)", maps.len(), count);
+    Ok(count)
+}
+
+fn export_frm(fs: &Rc<fs::FileSystem>, language: &str, args: &clap::ArgMatches)
+    -> io::Result<()>
+{
+    use crate::asset::frame::{export, Frm};
+
+    let frm_arg = args.value_of("FRM").unwrap();
+    let fid = if let Some(hex) = frm_arg.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        frm_arg.parse().ok()
+    };
+    let (frm, name) = if let Some(fid) = fid {
+        let fid = FrameId::from_packed(fid)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                format!("invalid FID: {}", frm_arg)))?;
+        let texture_factory = null::Backend::new().new_texture_factory();
+        let frm_db = FrameDb::new(fs.clone(), language, texture_factory)?;
+        let name = frm_db.name(fid)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+                format!("no name exists for FID: {}", frm_arg)))?;
+        (frm_db.read_frm(fid)?, name)
+    } else {
+        let name = frm_arg.rsplit(&['/', '\\'][..]).next().unwrap().to_owned();
+        (Frm::read(&mut fs.reader(frm_arg)?)?, name)
+    };
+
+    let name = name.to_lowercase();
+    let base_name = name.strip_suffix(".frm").unwrap_or(&name);
+    let out_dir = Path::new(args.value_of("output").unwrap_or("."));
+    let pal = read_palette(&mut fs.reader("color.pal")?)?;
+    export::export(&frm, &pal, out_dir, base_name, args.is_present("animated"))?;
+    info!("Exported {} to {}", name, out_dir.display());
+    Ok(())
+}
+
+fn import_frm(fs: &fs::FileSystem, args: &clap::ArgMatches) -> io::Result<()> {
+    use crate::asset::frame::import;
+
+    let pal = read_palette(&mut fs.reader("color.pal")?)?;
+    let frm = import::import_file(Path::new(args.value_of("JSON").unwrap()), &pal)?;
+    let output = args.value_of("output").unwrap();
+    let mut wr = BufWriter::new(File::create(output)?);
+    frm.write(&mut wr)?;
+    wr.flush()?;
+    info!("Written {}", output);
+    Ok(())
+}
+
+fn read_replay(path: &str) -> io::Result<Replay> {
+    Replay::read(&mut BufReader::new(File::open(path)?))
+}
+
+fn write_replay(path: &str, replay: &Replay) -> io::Result<()> {
+    let mut wr = BufWriter::new(File::create(path)?);
+    replay.write(&mut wr)?;
+    wr.flush()
+}
+
 fn log_sdl_info() {
     info!("SDL version: {}", sdl2::version::version());
     info!("Video drivers:");
@@ -147,34 +558,215 @@ fn log_sdl_info() {
     }
 }
 
+/// Captures or releases the mouse. Relative `mode` is used only while the mouse is captured.
+/// The system cursor is shown while the mouse is released or when it replaces the game cursor.
+fn set_mouse_capture(mouse: Option<&sdl2::mouse::MouseUtil>, ui: &mut Ui, mode: ui::MouseMode,
+    captured: bool)
+{
+    let relative = mode == ui::MouseMode::Relative && captured;
+    if let Some(mouse) = mouse {
+        mouse.set_relative_mouse_mode(relative);
+        mouse.show_cursor(!captured || (ui.hardware_cursor() && !relative));
+    }
+    ui.set_mouse_mode(if relative { ui::MouseMode::Relative } else { ui::MouseMode::Absolute });
+}
+
+/// Logs the error that prevents the game from starting and exits.
+fn fatal(what: &str, e: impl std::fmt::Display) -> ! {
+    error!("{}: {}", what, e);
+    std::process::exit(1);
+}
+
 fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
-    if std::env::var("RUST_LOG") == Err(std::env::VarError::NotPresent) {
-        std::env::set_var("RUST_LOG", "vault13=info");
-    }
 
-    let stdout = ConsoleAppender::builder().build();
-    let config = Config::builder()
-        .appender(Appender::builder().build("stdout", Box::new(stdout)))
-        .build(Root::builder().appender("stdout").build(LevelFilter::Debug))
-        .unwrap();
-    let _handle = log4rs::init_config(config).unwrap();
+    let _log_handle = logging::init();
+    game::crash::install(version());
 
     info!("Version: {}", version());
     info!("Build: {}", env!("BUILD_TARGET"));
 
-    let fs = Rc::new(fs::FileSystem::new(&args().get_matches()));
+    let mut startup = StartupReport::new();
+
+    let matches = args().get_matches();
+
+    if matches.is_present("version") {
+        println!("{}", version());
+        return;
+    }
+
+    if let ("dat", Some(args)) = matches.subcommand() {
+        if let Err(e) = dat_command(args) {
+            error!("dat command failed: {}", e);
+        }
+        return;
+    }
+
+    // Subcommand that runs the game setup uses the same argument names as the game itself.
+    let args = match matches.subcommand() {
+        ("render-map", Some(args)) => args,
+        ("dump", Some(args)) => args.subcommand_matches("protos").unwrap(),
+        ("check", Some(args)) => args,
+        ("frm", Some(args)) => args.subcommand().1.unwrap(),
+        _ => &matches,
+    };
+
+    let fs = startup.measure("dat indexing", || fs::FileSystem::new(args))
+        .unwrap_or_else(|e| fatal("couldn't set up the resource dirs", e));
+    let fs = Rc::new(fs);
+
+    debug!("loading ini file");
+    let config_file = fs.game().config_file();
+    let fallout2_config = fs.properties(config_file)
+        .unwrap_or_else(|e| fatal("couldn't read the game config", e));
+    let language = fallout2_config
+        .get_from_or(Some("system"), "language", "deutsch")
+        .trim();
+    debug!("language is {}", language);
 
     let map_name: String;
+    let startup_report_path: Option<String>;
+    let mut benchmark: Option<Benchmark>;
+    let mut headless: Option<Headless>;
+    let rng_seed: Option<u64>;
+    let record_path: Option<String>;
+    let mut player: Option<Player>;
+    let mods_dir: PathBuf;
+    let render_map: Option<RenderMap>;
+    let watchers: Vec<Watcher>;
+    let cache_dir: Option<PathBuf>;
+    let save_dir: Option<PathBuf>;
     {
-        let args = &args().get_matches();
+        if let ("dump", Some(_)) = matches.subcommand() {
+            if let Err(e) = dump_protos(&fs, language, args) {
+                error!("couldn't dump protos: {}", e);
+            }
+            return;
+        }
 
-        if args.is_present("version") {
-            println!("{}", version());
+        if let ("check", Some(_)) = matches.subcommand() {
+            match check_maps(&fs, language, args) {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    error!("couldn't check maps: {}", e);
+                    std::process::exit(2);
+                }
+            }
             return;
         }
 
-        let s = args.value_of("MAP").unwrap().to_lowercase();
+        if let ("frm", Some(frm_args)) = matches.subcommand() {
+            let r = if frm_args.subcommand_name() == Some("export") {
+                export_frm(&fs, language, args)
+            } else {
+                import_frm(&fs, args)
+            };
+            if let Err(e) = r {
+                error!("couldn't {} FRM: {}", frm_args.subcommand_name().unwrap(), e);
+            }
+            return;
+        }
+
+        if let Some(name) = args.value_of("disasm") {
+            if let Err(e) = disassemble_script(&fs, name) {
+                error!("couldn't disassemble {}: {}", name, e);
+            }
+            return;
+        }
+
+        startup_report_path = args.value_of("startup-report").map(|s| s.into());
+
+        cache_dir = args.value_of("cache-dir").map(|s| s.into());
+        save_dir = args.value_of("save-dir").map(|s| s.into());
+
+        watchers = if args.is_present("hot-reload") {
+            fs.loose_dirs().iter().map(|d| Watcher::new(d, WATCH_POLL_INTERVAL)).collect()
+        } else {
+            Vec::new()
+        };
+        for w in &watchers {
+            info!("watching {} for changes", w.root().display());
+        }
+
+        mods_dir = fs.root_dirs()[0].join("mods");
+
+        benchmark = args.value_of("benchmark").map(|_| Benchmark::new(false));
+
+        record_path = args.value_of("record").map(|s| s.into());
+
+        player = if let Some(path) = args.value_of("replay") {
+            match read_replay(path) {
+                Ok(v) => Some(Player::new(v)),
+                Err(e) => {
+                    error!("couldn't read replay {}: {}", path, e);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        headless = if !args.is_present("headless") {
+            None
+        } else if let Some(p) = &player {
+            Some(Headless::new(p.replay().end))
+        } else {
+            let ticks = args.value_of("ticks")
+                .map(|s| s.parse().map_err(|_| s))
+                .unwrap_or(Ok(headless::DEFAULT_TICKS));
+            match ticks {
+                Ok(v) => Some(Headless::new(v)),
+                Err(s) => {
+                    error!("invalid number of ticks: {}", s);
+                    return;
+                }
+            }
+        };
+
+        render_map = if let ("render-map", Some(_)) = matches.subcommand() {
+            let elevation = args.value_of("elevation")
+                .map(|s| s.parse().map_err(|_| s))
+                .transpose();
+            match elevation {
+                Ok(elevation) => Some(RenderMap {
+                    elevation,
+                    draw_roof: !args.is_present("no-roof"),
+                    output: args.value_of("output").unwrap().into(),
+                    reference: args.value_of("reference").map(|s| s.into()),
+                }),
+                Err(s) => {
+                    error!("invalid elevation: {}", s);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        rng_seed = if let Some(p) = &player {
+            Some(p.replay().seed)
+        } else if let Some(s) = args.value_of("rng-seed") {
+            match s.parse() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    error!("invalid random seed: {}", s);
+                    return;
+                }
+            }
+        } else if benchmark.is_some() {
+            Some(game::benchmark::SEED)
+        } else if headless.is_some() || render_map.is_some() {
+            Some(headless::SEED)
+        } else {
+            None
+        };
+
+        let s = player.as_ref().map(|p| &p.replay().map[..])
+            .or_else(|| args.value_of("benchmark"))
+            .or_else(|| args.value_of("MAP"))
+            .unwrap()
+            .to_lowercase();
         map_name = if s.ends_with(".map") {
             s[..s.len() - 4].into()
         } else {
@@ -182,60 +774,107 @@ fn main() {
         };
     }
 
-    debug!("loading ini file");
-    let read_conf_result = fs.properties("fallout2.cfg");
-    let fallout2_config = match read_conf_result {
-        Ok(ini) => ini,
-        Err(error) => panic!("can't open file fallout2.cfg: {:?}", error),
-    };
-    let language = fallout2_config
-        .get_from_or(Some("system"), "language", "deutsch")
-        .trim();
-    debug!("language is {}", language);
-
-    let proto_db = Rc::new(ProtoDb::new(fs.clone(), language).unwrap());
-
-    let pal = read_palette(&mut fs.reader("color.pal").unwrap()).unwrap();
+    let proto_db = startup.measure("proto db",
+        || ProtoDb::with_cache_dir(fs.clone(), language, cache_dir.as_deref()))
+        .unwrap_or_else(|e| fatal("couldn't load prototypes", e));
+    let proto_db = Rc::new(proto_db);
 
-    log_sdl_info();
+    let pal = startup.measure("palette", || fs.reader("color.pal")
+            .and_then(|mut rd| read_palette(&mut rd).context(|| "reading color.pal")))
+        .unwrap_or_else(|e| fatal("couldn't load palette", e));
 
-    let sdl = sdl2::init().unwrap();
-    let mut event_pump = sdl.event_pump().unwrap();
-    let video = sdl.video().unwrap();
-    info!("Using video driver: {}", video.current_video_driver());
+    // SDL is initialized only when there's a window. Kept alive until exit.
+    let _sdl: Option<sdl2::Sdl>;
+    let mouse: Option<sdl2::mouse::MouseUtil>;
+    let text_input: Option<sdl2::keyboard::TextInputUtil>;
+    let clipboard: Option<sdl2::clipboard::ClipboardUtil>;
+    let mut audio: Option<Audio>;
+    let mut event_pump: Option<sdl2::EventPump>;
+    let texture_factory: TextureFactory;
+    let into_canvas: Box<dyn FnOnce(Rc<Fonts>) -> Box<dyn Canvas>>;
+    if render_map.is_some() {
+        info!("Rendering map offscreen");
+        _sdl = None;
+        mouse = None;
+        text_input = None;
+        clipboard = None;
+        audio = None;
+        event_pump = None;
+        let rect = graphics::map::map_screen_rect(&graphics::geometry::hex::TileGrid::default());
+        let gfx_backend = software::Backend::new_offscreen(rect.width(), rect.height(),
+            Box::new(pal), PaletteOverlay::standard());
+        texture_factory = gfx_backend.new_texture_factory();
+        into_canvas = Box::new(move |fonts| gfx_backend.into_canvas(fonts));
+    } else if headless.is_some() {
+        info!("Running headless");
+        _sdl = None;
+        mouse = None;
+        text_input = None;
+        clipboard = None;
+        audio = None;
+        event_pump = None;
+        let gfx_backend = null::Backend::new();
+        texture_factory = gfx_backend.new_texture_factory();
+        into_canvas = Box::new(move |fonts| gfx_backend.into_canvas(fonts));
+    } else {
+        log_sdl_info();
 
-    let window = video
-        .window("Vault 13", 640, 480)
-        .position_centered()
-        .allow_highdpi()
-        .build()
-        .unwrap();
+        let sdl = sdl2::init().unwrap_or_else(|e| fatal("couldn't initialize SDL", e));
+        event_pump = Some(sdl.event_pump()
+            .unwrap_or_else(|e| fatal("couldn't create SDL event pump", e)));
+        let video = sdl.video().unwrap_or_else(|e| fatal("couldn't initialize SDL video", e));
+        info!("Using video driver: {}", video.current_video_driver());
 
-    let mouse = sdl.mouse();
-    mouse.set_relative_mouse_mode(true);
+        let window = video
+            .window("Vault 13", 640, 480)
+            .position_centered()
+            .allow_highdpi()
+            .resizable()
+            .build()
+            .unwrap_or_else(|e| fatal("couldn't create window", e));
 
-    let canvas = window.into_canvas().build().unwrap();
-    info!("Using render driver: {}", canvas.info().name);
+        // Keep the pixels crisp when the back buffer is scaled up.
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+        let canvas = window.into_canvas().build()
+            .unwrap_or_else(|e| fatal("couldn't create renderer", e));
+        info!("Using render driver: {}", canvas.info().name);
 
-    let gfx_backend: Backend = Backend::new(canvas, Box::new(pal), PaletteOverlay::standard());
-    let texture_factory = gfx_backend.new_texture_factory();
+        let gfx_backend = software::Backend::new(canvas, Box::new(pal),
+            PaletteOverlay::standard());
+        texture_factory = gfx_backend.new_texture_factory();
+        into_canvas = Box::new(move |fonts| gfx_backend.into_canvas(fonts));
+        mouse = Some(sdl.mouse());
+        text_input = Some(video.text_input());
+        clipboard = Some(video.clipboard());
+        audio = Audio::new(&sdl, fs.clone())
+            .map_err(|e| warn!("couldn't initialize audio, playing without sound: {}", e))
+            .ok();
+        _sdl = Some(sdl);
+    }
 
-    let frm_db = Rc::new(FrameDb::new(fs.clone(), language, texture_factory.clone()).unwrap());
+    let frm_db = startup.measure("frame db",
+        || FrameDb::new(fs.clone(), language, texture_factory.clone()))
+        .unwrap_or_else(|e| fatal("couldn't load art lists", e));
+    let frm_db = Rc::new(frm_db);
 
     // Load all interface frame sets.
-    for id in 0.. {
-        let fid = FrameId::new_generic(EntityKind::Interface, id).unwrap();
-        if frm_db.name(fid).is_none() {
-            break;
-        }
-        if let Err(e) = frm_db.get(fid) {
-            warn!("couldn't load interface frame set {:?}: {}", fid, e);
+    startup.measure("interface frms", || {
+        for id in 0.. {
+            let fid = FrameId::new_generic(EntityKind::Interface, id).unwrap();
+            if frm_db.name(fid).is_none() {
+                break;
+            }
+            if let Err(e) = frm_db.get(fid) {
+                warn!("couldn't load interface frame set {:?}: {}", fid, e);
+            }
         }
-    }
+    });
 
-    let fonts = Rc::new(load_fonts(&fs, &texture_factory));
+    let ttf_fonts = asset::font::ttf::read_config(&fallout2_config);
+    let fonts = Rc::new(startup.measure("fonts", || load_fonts(&fs, &texture_factory, &ttf_fonts,
+        Encoding::for_language(language))));
 
-    let mut canvas = gfx_backend.into_canvas(fonts.clone());
+    let mut canvas = into_canvas(fonts.clone());
     let canvas = canvas.as_mut();
 
     let start = Instant::now();
@@ -245,57 +884,252 @@ fn main() {
     ui.set_cursor(ui::Cursor::Arrow);
     ui.set_cursor_pos(Point::new(640 / 2, 480 / 2));
 
-    let misc_msgs = Rc::new(Messages::read_file(&fs, language, "game/misc.msg").unwrap());
+    // [input] mouse_mode=relative|absolute
+    // [input] hardware_cursor=0|1 - use the system cursor in the absolute mode to reduce latency.
+    let mouse_mode = fallout2_config.get_from_or(Some("input"), "mouse_mode", "relative");
+    let mouse_mode = ui::MouseMode::from_name(mouse_mode).unwrap_or_else(|| {
+        warn!("invalid mouse_mode in [input]: {}", mouse_mode);
+        ui::MouseMode::Relative
+    });
+    let hardware_cursor = fallout2_config
+        .get_from_or(Some("input"), "hardware_cursor", "0")
+        .trim() == "1";
+    if hardware_cursor && !canvas.has_hardware_cursor() {
+        info!("Hardware cursor isn't supported, using software cursor");
+    }
+    ui.set_hardware_cursor(hardware_cursor && canvas.has_hardware_cursor());
+    if let Some(clipboard) = clipboard {
+        ui.set_clipboard(Box::new(clipboard));
+    }
+    // Window area of the focused text input the IME candidate window was placed at.
+    let mut ime_rect = None;
+    // The mouse is released while the window is unfocused or on user request.
+    let mut mouse_capture_wanted = true;
+    let mut window_focused = true;
+    set_mouse_capture(mouse.as_ref(), ui, mouse_mode, true);
+
+    let loc = Rc::new(Localization::new(fs.clone(), language));
+    loc.messages("game/misc.msg")
+        .unwrap_or_else(|e| fatal("couldn't load messages", e));
     let mut state = GameState::new(
         fs.clone(),
-        language,
+        loc,
         proto_db,
         frm_db,
         fonts,
-        misc_msgs,
         start,
         ui,
-    );
+    ).unwrap_or_else(|e| fatal("couldn't initialize the game", e));
+
+    let combat_manual_end = fallout2_config
+        .get_from_or(Some("preferences"), "combat_manual_end", "0")
+        .trim() == "1";
+    state.set_combat_auto_end(!combat_manual_end);
+    let subtitles = fallout2_config
+        .get_from_or(Some("preferences"), "subtitles", "0")
+        .trim() == "1";
+    state.set_subtitles(subtitles);
+    let violence_level = fallout2_config
+        .get_from_or(Some("preferences"), "violence_level", "2")
+        .trim()
+        .parse()
+        .ok()
+        .and_then(ViolenceLevel::from_i32)
+        .unwrap_or(ViolenceLevel::Normal);
+    state.set_violence_level(violence_level);
+    state.set_strict_assets(args.is_present("strict-assets"));
+    if let Some(dir) = save_dir {
+        state.set_save_dir(dir);
+    }
+    state.bindings().borrow_mut().read_config(&fallout2_config);
+    state.set_bindings_path(fs.properties_provider(config_file).map(|p| p.path().to_path_buf()));
+    let bindings = state.bindings().clone();
+    state.set_mods(startup.measure("mods", || Mods::load_dir(&mods_dir)));
+    state.set_vm_trace(Trace::from_env().or_else(||
+        Trace::parse(fallout2_config.get_from_or(Some("debug"), "vm_trace", ""))));
+    if let Some(seed) = rng_seed {
+        game::rng::set_seed(seed);
+    }
+    info!("Random seed: {}", game::rng::seed());
+    let mut recorder = record_path.as_ref()
+        .map(|_| Recorder::new(map_name.clone(), game::rng::seed()));
+    if let Err(e) = state.new_game() {
+        error!("couldn't start a new game: {}", e);
+        std::process::exit(1);
+    }
+    startup.measure("first map", || state.switch_map(&map_name, ui));
+
+    if let Some(r) = render_map {
+        match r.run(&state, canvas) {
+            Ok(()) => info!("Rendered map {} to {}", map_name, r.output.display()),
+            Err(e) => {
+                error!("couldn't render map {}: {}", map_name, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    startup.log();
+    if let Some(path) = startup_report_path {
+        let json = startup.to_json();
+        if path == "-" {
+            println!("{}", json);
+        } else if let Err(e) = std::fs::write(&path, json) {
+            warn!("couldn't write startup report to {}: {}", path, e);
+        }
+    }
 
-    state.new_game();
-    state.switch_map(&map_name, ui);
+    let mut draw_debug = false;
+    // Texture atlas page shown in the debug overlay.
+    let mut atlas_page: Option<usize> = None;
 
-    let mut draw_debug = true;
+    // Full-screen state that replaces the game state while shown.
+    let mut screen: Option<Box<dyn AppState>> = None;
 
     let ui_commands = &mut Vec::new();
     let app_events = &mut Vec::new();
 
+    // Recording and replaying require the game clock to be independent of the frame rate.
+    let fixed_step = headless.is_some() || recorder.is_some() || player.is_some();
+    let mut tick: u64 = 0;
+
     'running: loop {
+        let frame_start = Instant::now();
+
+        let changed: Vec<_> = watchers.iter().flat_map(|w| w.poll()).collect();
+        if !changed.is_empty() {
+            state.reload_assets(&changed);
+        }
+
+        if let Some(b) = benchmark.as_mut() {
+            if b.update(timer.time(), &mut state, ui) == game::benchmark::Status::Done {
+                break 'running;
+            }
+        }
+
         // Handle app events.
 
         for event in app_events.drain(..) {
-            state.handle_app_event(HandleAppEvent { event, ui });
+            match event {
+                AppEvent::EndgameSlideshow { endings } => {
+                    screen = Some(Box::new(Slideshow::new(&fs, &state.language(), &endings,
+                        state.subtitles(), ui)));
+                }
+                AppEvent::GameOver { narrator } => {
+                    screen = Some(Box::new(DeathScreen::new(&fs, &state.language(), &narrator,
+                        state.subtitles(), ui)));
+                    if let Some(audio) = audio.as_mut() {
+                        audio.play(&Sound::new(death::narration_path(&narrator)));
+                    }
+                }
+                AppEvent::MainMenu => {
+                    if let Some(audio) = audio.as_mut() {
+                        audio.stop();
+                    }
+                    screen = Some(Box::new(MainMenu::new(state.misc_msgs(), ui)));
+                }
+                AppEvent::NewGame => {
+                    screen = None;
+                    state.handle_app_event(HandleAppEvent { event, ui });
+                    if let Err(e) = state.new_game() {
+                        error!("couldn't start a new game: {}", e);
+                        break 'running;
+                    }
+                    state.switch_map(&map_name, ui);
+                }
+                AppEvent::PlayMovie { name } => {
+                    screen = Some(Box::new(MoviePlayer::new(&fs, &state.language(), &name,
+                        state.subtitles(), ui)));
+                }
+                AppEvent::Quit => break 'running,
+                AppEvent::SlideshowDone | AppEvent::MovieDone => {
+                    screen = None;
+                    state.handle_app_event(HandleAppEvent { event, ui });
+                }
+                _ => state.handle_app_event(HandleAppEvent { event, ui }),
+            }
         }
+        let screen_shown = screen.is_some();
+        let app_state: &mut dyn AppState = if let Some(v) = screen.as_mut() {
+            v.as_mut()
+        } else {
+            &mut state
+        };
 
         // Handle input.
 
-        for event in event_pump.poll_iter() {
-            let mut handled = ui.handle_input(ui::HandleInput {
-                now: timer.time(),
-                event: &event,
-                out: ui_commands,
-            });
-            if !handled {
-                handled = state.handle_input(&event, ui);
+        let mut events: Vec<_> = event_pump.iter_mut().flat_map(|p| p.poll_iter()).collect();
+        if player.as_ref().map(|p| p.is_done(tick)).unwrap_or(false) {
+            info!("Replay finished at tick {}", tick);
+            player = None;
+        }
+        if let Some(p) = player.as_mut() {
+            // Live input is ignored while replaying except for closing the window.
+            if events.iter().any(|e| matches!(e, Event::Quit { .. })) {
+                break 'running;
+            }
+            events = p.events(tick);
+        }
+
+        for event in events {
+            let mut handled = match event {
+                // Replayed mouse moves have the cursor position on the game screen.
+                Event::MouseMotion { x, y, .. } if player.is_some() =>
+                    ui.move_cursor(timer.time(), Point::new(x, y), ui_commands),
+                _ => ui.handle_input(ui::HandleInput {
+                    now: timer.time(),
+                    event: &event,
+                    out: ui_commands,
+                }),
+            };
+            if let Some(r) = recorder.as_mut() {
+                r.record(tick, &event, ui.cursor_pos());
+            }
+            // Clicks on the full-screen state window are handled by the ui but they're also
+            // meant for the state.
+            if !handled || screen_shown {
+                handled = app_state.handle_input(&event, ui) || handled;
             }
             if !handled {
                 match event {
                     Event::KeyDown {
-                        keycode: Some(Keycode::Backquote),
+                        keycode: Some(key),
+                        ..
+                    } => match bindings.borrow().action(key) {
+                        Some(KeyAction::ToggleDebugInfo) => draw_debug = !draw_debug,
+                        Some(KeyAction::ToggleAtlasViewer) => {
+                            // Cycles through the pages and then hides the viewer.
+                            let next = atlas_page.map(|p| p + 1).unwrap_or(0);
+                            atlas_page = Some(next).filter(|&p| p < canvas.atlas_pages().len());
+                        }
+                        // The frame time breakdown is shown in the debug info.
+                        Some(KeyAction::ToggleProfiler) => {
+                            profile::set_enabled(!profile::is_enabled());
+                        }
+                        Some(KeyAction::ToggleMouseCapture) => {
+                            mouse_capture_wanted = !mouse_capture_wanted;
+                            set_mouse_capture(mouse.as_ref(), ui, mouse_mode,
+                                mouse_capture_wanted && window_focused);
+                        }
+                        Some(KeyAction::Quit) => break 'running,
+                        _ => {}
+                    }
+                    // Release the mouse when switching to another window.
+                    Event::Window {
+                        win_event: win_event @ (WindowEvent::FocusGained | WindowEvent::FocusLost),
                         ..
                     } => {
-                        draw_debug = !draw_debug;
+                        window_focused = matches!(win_event, WindowEvent::FocusGained);
+                        set_mouse_capture(mouse.as_ref(), ui, mouse_mode,
+                            mouse_capture_wanted && window_focused);
                     }
-                    Event::Quit { .. }
-                    | Event::KeyDown {
-                        keycode: Some(Keycode::Escape),
+                    // DPI changes are reported as size changes since the drawable size changes.
+                    Event::Window {
+                        win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..),
                         ..
-                    } => break 'running,
+                    } => canvas.resize(),
+                    Event::Quit { .. } => break 'running,
                     _ => {}
                 }
             }
@@ -303,30 +1137,62 @@ fn main() {
 
         // Update.
 
+        let update_profile = profile::scope("update");
         ui.update(timer.time(), ui_commands);
 
         for event in ui_commands.drain(..) {
-            state.handle_ui_command(event, ui);
+            app_state.handle_ui_command(event, ui);
         }
 
-        state.update(Update {
+        app_state.update(Update {
             time: timer.time(),
             delta: timer.delta(),
             ui,
             out: app_events,
         });
 
+        for sound in state.drain_sounds() {
+            if let Some(audio) = audio.as_mut() {
+                audio.play(&sound);
+            }
+        }
+
         ui.sync();
 
+        let text_input_rect = ui.text_input_rect();
+        if text_input_rect != ime_rect {
+            ime_rect = text_input_rect;
+            if let (Some(util), Some(r)) = (text_input.as_ref(), ime_rect) {
+                util.set_rect(sdl2::rect::Rect::new(r.left, r.top,
+                    r.width() as u32, r.height() as u32));
+            }
+        }
+
         canvas.update(timer.time());
+        drop(update_profile);
 
         // Render
 
+        ui.invalidate(canvas);
+        if draw_debug && !screen_shown {
+            // Debug info is drawn above everything and changes every frame.
+            canvas.invalidate_all();
+        }
+        canvas.begin_frame();
         canvas.clear(BLACK);
 
-        ui.render(canvas);
+        {
+            let _profile = profile::scope("ui render");
+            let hardware_cursor = ui.hardware_cursor();
+            ui.render(canvas);
+            // Hide the system cursor if the ui fell back to drawing the cursor.
+            if hardware_cursor && !ui.hardware_cursor() {
+                set_mouse_capture(mouse.as_ref(), ui, mouse_mode,
+                    mouse_capture_wanted && window_focused);
+            }
+        }
 
-        if draw_debug {
+        if draw_debug && !screen_shown {
             let world = state.world().borrow();
             let world_view = ui.widget_ref::<WorldView>(state.world_view());
             let (mouse_hex_pos, mouse_sqr_pos) =
@@ -345,7 +1211,7 @@ fn main() {
                 let dude_obj = world.objects().get(world.objects().dude());
                 (dude_obj.pos().point, dude_obj.direction)
             };
-            let msg = format!(
+            let mut msg = format!(
                 "mouse: {}, {}\n\
                  mouse hex: {}, {} ({})\n\
                  mouse sqr: {}, {} ({})\n\
@@ -378,6 +1244,10 @@ fn main() {
                 world.ambient_light,
                 state.time().is_paused(),
             );
+            if profile::is_enabled() {
+                msg.push_str("\n\n");
+                msg.push_str(&profile::format_breakdown(&profile::breakdown()));
+            }
             canvas.draw_text(
                 msg.as_bytes().into(),
                 Point::new(2, 1),
@@ -392,13 +1262,52 @@ fn main() {
                     ..Default::default()
                 },
             );
+
+            if let Some(page) = atlas_page {
+                draw_atlas_page(canvas, page);
+            }
         }
 
-        canvas.present();
+        {
+            let _profile = profile::scope("present");
+            canvas.present();
+        }
+        ui.set_window_mapping(canvas.window_origin(), canvas.window_scale());
         canvas.cleanup();
+        profile::end_frame();
 
-        std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        tick += 1;
 
-        timer.tick(Instant::now());
+        if let Some(h) = headless.as_mut() {
+            if h.tick() == game::benchmark::Status::Done {
+                break 'running;
+            }
+        } else if let Some(b) = benchmark.as_mut() {
+            b.record_frame(frame_start.elapsed());
+        } else {
+            std::thread::sleep(FRAME_DURATION);
+        }
+
+        timer.tick(if fixed_step {
+            timer.time() + FRAME_DURATION
+        } else {
+            Instant::now()
+        });
+    }
+
+    if let (Some(r), Some(path)) = (recorder, record_path) {
+        let replay = r.finish(tick);
+        match write_replay(&path, &replay) {
+            Ok(()) => info!("Recorded {} input events in {} ticks to {}",
+                replay.events.len(), replay.end, path),
+            Err(e) => error!("couldn't write replay to {}: {}", path, e),
+        }
+    }
+
+    if let Some(b) = benchmark {
+        b.frame_times().log();
+    }
+    if let Some(h) = headless {
+        println!("{}", h.summary(&state));
     }
 }
@@ -1,6 +1,10 @@
 pub mod cancellable;
 pub mod chain;
+pub mod delay;
 pub mod event;
+pub mod fade;
+pub mod join;
+pub mod then;
 
 use std::time::Instant;
 
@@ -43,6 +47,20 @@ pub trait Sequence {
     {
         cancellable::Cancellable::new(self)
     }
+
+    /// Runs `next` after this sequence is finished.
+    fn then<T: Sequence>(self, next: T) -> then::Then<Self, T>
+        where Self: Sized
+    {
+        then::Then::new(self, next)
+    }
+
+    /// Runs `other` in parallel with this sequence.
+    fn join(self, other: impl 'static + Sequence) -> join::Join
+        where Self: 'static + Sized
+    {
+        join::Join::new().with(self).with(other)
+    }
 }
 
 impl<T: Sequence + ?Sized> Sequence for Box<T> {
@@ -125,4 +143,120 @@ fn update_while_lagging(mut seq: impl AsMut<dyn Sequence>, ctx: &mut Update) ->
             Result::Done => NoLagResult::Done,
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::cell::RefCell;
+    use std::mem;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::asset::frame::FrameDb;
+    use crate::fs::FileSystem;
+    use crate::graphics::font::Fonts;
+    use crate::graphics::render::TextureFactory;
+    use crate::ui::Ui;
+
+    pub struct MockContext {
+        start: Instant,
+        world: World,
+        ui: Ui,
+        out: Vec<Event>,
+    }
+
+    impl MockContext {
+        pub fn new() -> Self {
+            let start = Instant::now();
+            let frm_db = FrameDb::mock(Rc::new(FileSystem::mock()), TextureFactory::mock());
+            Self {
+                start,
+                world: World::mock(start),
+                ui: Ui::new(Rc::new(frm_db), Rc::new(Fonts::new()), 640, 480),
+                out: Vec::new(),
+            }
+        }
+
+        pub fn ui(&self) -> &Ui {
+            &self.ui
+        }
+
+        pub fn world(&self) -> &World {
+            &self.world
+        }
+
+        pub fn world_mut(&mut self) -> &mut World {
+            &mut self.world
+        }
+
+        /// Updates `seq` at `millis` milliseconds since the context creation.
+        pub fn update(&mut self, seq: &mut impl Sequence, millis: u64) -> Result {
+            seq.update(&mut Update {
+                time: self.start + Duration::from_millis(millis),
+                world: &mut self.world,
+                ui: &mut self.ui,
+                out: &mut self.out,
+            })
+        }
+    }
+
+    /// Records updates of `Step` sequences.
+    #[derive(Clone, Default)]
+    pub struct Log(Rc<RefCell<Vec<&'static str>>>);
+
+    impl Log {
+        pub fn new() -> Self {
+            Default::default()
+        }
+
+        /// Returns names logged since the last call.
+        pub fn take(&self) -> Vec<&'static str> {
+            mem::take(&mut *self.0.borrow_mut())
+        }
+
+        /// Sequence that logs `name` on each of `count` updates and finishes on the next update.
+        pub fn step(&self, name: &'static str, count: u32) -> Step {
+            Step {
+                log: self.clone(),
+                name,
+                count,
+                lagging: false,
+            }
+        }
+
+        /// Sequence that logs `name` on each update and never finishes. It is lagging for
+        /// the first `count` updates.
+        pub fn lagging(&self, name: &'static str, count: u32) -> Step {
+            Step {
+                log: self.clone(),
+                name,
+                count,
+                lagging: true,
+            }
+        }
+    }
+
+    pub struct Step {
+        log: Log,
+        name: &'static str,
+        count: u32,
+        lagging: bool,
+    }
+
+    impl Sequence for Step {
+        fn update(&mut self, _ctx: &mut Update) -> Result {
+            if self.count == 0 && !self.lagging {
+                return Result::Done;
+            }
+            self.log.0.borrow_mut().push(self.name);
+            if self.count > 0 {
+                self.count -= 1;
+                if self.lagging {
+                    return Result::Running(Running::Lagging);
+                }
+            }
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
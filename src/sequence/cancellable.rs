@@ -3,7 +3,8 @@ use std::rc::Rc;
 
 use super::*;
 
-#[derive(Debug)]
+/// Handle for cancelling a `Cancellable` sequence. Can be cloned to share the handle.
+#[derive(Clone, Debug)]
 pub struct Cancel(Rc<Cell<bool>>);
 
 impl Cancel {
@@ -11,7 +12,7 @@ impl Cancel {
         Cancel(Rc::new(Cell::new(false)))
     }
 
-    pub fn cancel(self) {
+    pub fn cancel(&self) {
         self.set_done();
     }
 
@@ -23,10 +24,6 @@ impl Cancel {
         !self.is_done()
     }
 
-    fn clone(&self) -> Self {
-        Cancel(self.0.clone())
-    }
-
     fn set_done(&self) {
         self.0.set(true)
     }
@@ -61,4 +58,30 @@ impl<T: Sequence> Sequence for Cancellable<T> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn cancel() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+
+        let (mut seq, cancel) = log.step("a", 1).cancellable();
+        assert!(cancel.is_running());
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.update(&mut seq, 10), Result::Done);
+        assert!(cancel.is_done());
+
+        let (mut seq, cancel) = log.step("b", 3).cancellable();
+        let cancel2 = cancel.clone();
+        assert_eq!(ctx.update(&mut seq, 20), Result::Running(Running::NotLagging));
+        cancel2.cancel();
+        assert!(cancel.is_done());
+        assert_eq!(ctx.update(&mut seq, 30), Result::Done);
+        assert_eq!(log.take(), vec!["a", "b"]);
+    }
+}
@@ -159,6 +159,8 @@ impl Sequence for Chain {
                     }
                 }
                 State::Finalizing => {
+                    // Drop cancelled sequences.
+                    self.cancellable.clear();
                     let r = match self.finalizing.front_mut().map(|seq| seq.update(ctx)) {
                         Some(r @ Result::Running(_)) => r,
                         Some(Result::Done) => {
@@ -180,4 +182,32 @@ impl Sequence for Chain {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn cancel() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+
+        let mut seq = Chain::new();
+        seq.control()
+            .cancellable(log.step("a", 5))
+            .cancellable(log.step("b", 1))
+            .finalizing(log.step("f", 1));
+        let control = seq.control().clone();
+
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a"]);
+
+        control.cancel();
+        assert_eq!(ctx.update(&mut seq, 10), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["f"]);
+        assert_eq!(ctx.update(&mut seq, 20), Result::Done);
+        assert!(log.take().is_empty());
+    }
+}
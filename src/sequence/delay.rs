@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use super::*;
+
+/// Does nothing for the specified duration. The duration is counted from the first update.
+pub struct Delay {
+    duration: Duration,
+    start: Option<Instant>,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            start: None,
+        }
+    }
+}
+
+impl Sequence for Delay {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        let start = *self.start.get_or_insert(ctx.time);
+        if ctx.time.duration_since(start) >= self.duration {
+            Result::Done
+        } else {
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn delay() {
+        let mut ctx = MockContext::new();
+        let mut seq = Delay::new(Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 50), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.update(&mut seq, 149), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.update(&mut seq, 150), Result::Done);
+
+        let mut seq = Delay::new(Duration::from_millis(0));
+        assert_eq!(ctx.update(&mut seq, 150), Result::Done);
+    }
+}
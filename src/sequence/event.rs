@@ -5,11 +5,40 @@ use super::*;
 
 #[derive(Clone, Debug)]
 pub enum Event {
+    /// Attack animation is done and the target gets hit.
+    Attack {
+        attacker: object::Handle,
+        target: object::Handle,
+        attack: crate::game::attack::Attack,
+    },
+    /// A script made `attacker` hostile to `target`, combat should start or pull them in.
+    AttackSetup {
+        attacker: object::Handle,
+        target: object::Handle,
+    },
+    /// The dude's death animation is done.
+    DudeDied,
+    /// A script requested the endgame slideshow.
+    EndgameSlideshow,
+    /// Foot of the walking critter touches the ground.
+    Footstep {
+        obj: object::Handle,
+        pos: EPoint,
+    },
     ObjectMoved {
         obj: object::Handle,
         old_pos: EPoint,
         new_pos: EPoint,
     },
+    /// A script requested the movie `name` from `art/cuts`.
+    PlayMovie {
+        name: String,
+    },
+    /// Play the first existing sound effect of `names` from `sound/sfx`.
+    PlaySfx {
+        names: Vec<String>,
+        pos: Option<EPoint>,
+    },
     SetDoorState {
         door: object::Handle,
         open: bool,
@@ -22,6 +51,11 @@ pub enum Event {
         user: object::Handle,
         used: object::Handle,
     },
+    UseItemOn {
+        user: object::Handle,
+        item: object::Handle,
+        target: object::Handle,
+    },
     UseSkill {
         skill: crate::asset::Skill,
         user: object::Handle,
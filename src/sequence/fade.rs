@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use super::*;
+
+/// Changes the screen brightness from the current one to `to` over the specified duration.
+/// The duration is counted from the first update. Brightness is in [0..128] range: 0 - black,
+/// 128 - original colors.
+///
+/// To wait for the fade to finish make it `cancellable()` and check the returned handle.
+// palette_fade_to
+pub struct Fade {
+    from: Option<u8>,
+    to: u8,
+    duration: Duration,
+    start: Option<Instant>,
+}
+
+impl Fade {
+    pub fn to(brightness: u8, duration: Duration) -> Self {
+        assert!(brightness <= 128);
+        Self {
+            from: None,
+            to: brightness,
+            duration,
+            start: None,
+        }
+    }
+
+    /// Fades the screen to black.
+    pub fn fade_out(duration: Duration) -> Self {
+        Self::to(0, duration)
+    }
+
+    /// Fades the screen to the original colors.
+    pub fn fade_in(duration: Duration) -> Self {
+        Self::to(128, duration)
+    }
+}
+
+impl Sequence for Fade {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        let start = *self.start.get_or_insert(ctx.time);
+        let from = *self.from.get_or_insert(ctx.ui.brightness());
+        let elapsed = ctx.time.duration_since(start);
+        ctx.ui.set_brightness(brightness(from, self.to, elapsed, self.duration));
+        if elapsed >= self.duration {
+            Result::Done
+        } else {
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
+
+/// Returns brightness at `elapsed` time of the fade from `from` to `to` brightness.
+pub fn brightness(from: u8, to: u8, elapsed: Duration, duration: Duration) -> u8 {
+    if elapsed >= duration {
+        return to;
+    }
+    let (from, to) = (from as i64, to as i64);
+    let r = from + (to - from) * elapsed.as_millis() as i64 / duration.as_millis() as i64;
+    r as u8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn fade() {
+        let mut ctx = MockContext::new();
+
+        let mut seq = Fade::fade_out(Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 10), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 128);
+        assert_eq!(ctx.update(&mut seq, 60), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 64);
+        assert_eq!(ctx.update(&mut seq, 110), Result::Done);
+        assert_eq!(ctx.ui().brightness(), 0);
+
+        let mut seq = Fade::fade_in(Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 200), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 0);
+        assert_eq!(ctx.update(&mut seq, 275), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 96);
+        assert_eq!(ctx.update(&mut seq, 400), Result::Done);
+        assert_eq!(ctx.ui().brightness(), 128);
+
+        let mut seq = Fade::fade_out(Duration::from_millis(0));
+        assert_eq!(ctx.update(&mut seq, 400), Result::Done);
+        assert_eq!(ctx.ui().brightness(), 0);
+
+        // Starts from the current brightness.
+        let mut seq = Fade::to(64, Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 500), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 0);
+        let mut seq2 = Fade::fade_in(Duration::from_millis(100));
+        assert_eq!(ctx.update(&mut seq, 550), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 32);
+        assert_eq!(ctx.update(&mut seq2, 550), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.update(&mut seq2, 600), Result::Running(Running::NotLagging));
+        assert_eq!(ctx.ui().brightness(), 80);
+    }
+
+    #[test]
+    fn brightness_() {
+        let d = Duration::from_millis(500);
+        assert_eq!(brightness(0, 128, Duration::from_millis(0), d), 0);
+        assert_eq!(brightness(0, 128, d / 2, d), 64);
+        assert_eq!(brightness(128, 0, d / 2, d), 64);
+        assert_eq!(brightness(128, 0, d * 2, d), 0);
+        assert_eq!(brightness(128, 0, d, Duration::from_millis(0)), 0);
+    }
+
+    #[test]
+    fn wait() {
+        let mut ctx = MockContext::new();
+        let (mut seq, done) = Fade::fade_out(Duration::from_millis(100)).cancellable();
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert!(done.is_running());
+        assert_eq!(ctx.update(&mut seq, 100), Result::Done);
+        assert!(done.is_done());
+    }
+}
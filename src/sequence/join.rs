@@ -0,0 +1,111 @@
+use super::*;
+
+struct Entry {
+    seq: Option<Box<dyn Sequence>>,
+    lagging: bool,
+}
+
+/// Runs sequences in parallel. Finishes when all the sequences are finished.
+pub struct Join {
+    entries: Vec<Entry>,
+    lagging: bool,
+}
+
+impl Join {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            lagging: false,
+        }
+    }
+
+    pub fn with(mut self, seq: impl 'static + Sequence) -> Self {
+        self.push(seq);
+        self
+    }
+
+    pub fn push(&mut self, seq: impl 'static + Sequence) {
+        self.entries.push(Entry {
+            seq: Some(Box::new(seq)),
+            lagging: false,
+        });
+    }
+}
+
+impl Sequence for Join {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        // When lagging only the lagging sequences must be updated.
+        let only_lagging = self.lagging;
+        self.lagging = false;
+        for entry in &mut self.entries {
+            let seq = if let Some(v) = entry.seq.as_mut() {
+                v
+            } else {
+                continue;
+            };
+            if only_lagging && !entry.lagging {
+                continue;
+            }
+            entry.lagging = false;
+            match seq.update(ctx) {
+                Result::Running(Running::Lagging) => {
+                    entry.lagging = true;
+                    self.lagging = true;
+                }
+                Result::Running(Running::NotLagging) => {}
+                Result::Done => entry.seq = None,
+            }
+        }
+        if self.entries.iter().all(|e| e.seq.is_none()) {
+            Result::Done
+        } else if self.lagging {
+            Result::Running(Running::Lagging)
+        } else {
+            Result::Running(Running::NotLagging)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::test::*;
+
+    #[test]
+    fn join() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+        let mut seq = log.step("a", 1)
+            .join(log.step("b", 3))
+            .with(log.step("c", 2));
+
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a", "b", "c"]);
+        assert_eq!(ctx.update(&mut seq, 10), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["b", "c"]);
+        assert_eq!(ctx.update(&mut seq, 20), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["b"]);
+        assert_eq!(ctx.update(&mut seq, 30), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["b"]);
+        assert_eq!(ctx.update(&mut seq, 40), Result::Done);
+        assert!(log.take().is_empty());
+
+        assert_eq!(ctx.update(&mut Join::new(), 50), Result::Done);
+    }
+
+    #[test]
+    fn join_lagging() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+        let mut seq = log.lagging("a", 1)
+            .join(log.step("b", 2));
+
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::Lagging));
+        assert_eq!(log.take(), vec!["a", "b"]);
+        // Only the lagging sequence is updated until it catches up.
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a"]);
+        assert_eq!(ctx.update(&mut seq, 10), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+
+/// Runs the first sequence and then the second one. Created by `Sequence::then()`.
+pub struct Then<A, B> {
+    first: Option<A>,
+    second: B,
+}
+
+impl<A: Sequence, B: Sequence> Then<A, B> {
+    pub(in super) fn new(first: A, second: B) -> Self {
+        Self {
+            first: Some(first),
+            second,
+        }
+    }
+}
+
+impl<A: Sequence, B: Sequence> Sequence for Then<A, B> {
+    fn update(&mut self, ctx: &mut Update) -> Result {
+        if let Some(first) = self.first.as_mut() {
+            match first.update(ctx) {
+                r @ Result::Running(_) => return r,
+                Result::Done => self.first = None,
+            }
+        }
+        self.second.update(ctx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::delay::Delay;
+    use crate::sequence::test::*;
+    use std::time::Duration;
+
+    #[test]
+    fn then() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+        let mut seq = log.step("a", 2)
+            .then(Delay::new(Duration::from_millis(100)))
+            .then(log.step("b", 1));
+
+        assert_eq!(ctx.update(&mut seq, 0), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a"]);
+        assert_eq!(ctx.update(&mut seq, 10), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["a"]);
+        // `a` finishes and the delay starts right away.
+        assert_eq!(ctx.update(&mut seq, 20), Result::Running(Running::NotLagging));
+        assert!(log.take().is_empty());
+        assert_eq!(ctx.update(&mut seq, 119), Result::Running(Running::NotLagging));
+        assert!(log.take().is_empty());
+        assert_eq!(ctx.update(&mut seq, 120), Result::Running(Running::NotLagging));
+        assert_eq!(log.take(), vec!["b"]);
+        assert_eq!(ctx.update(&mut seq, 130), Result::Done);
+        assert!(log.take().is_empty());
+    }
+}
@@ -1,10 +1,19 @@
+pub mod death;
 mod event;
+pub mod main_menu;
+pub mod movie;
+pub mod slideshow;
 
+use bstring::BString;
 use sdl2::event::Event as SdlEvent;
 use std::time::{Duration, Instant};
 
+use crate::graphics::Rect;
+use crate::graphics::color::WHITE;
+use crate::graphics::font::{self, DrawOptions, FontKey, HorzAlign};
 use crate::ui::command::UiCommand;
-use crate::ui::Ui;
+use crate::ui::panel::{self, Panel};
+use crate::ui::{self, Ui};
 
 pub use event::AppEvent;
 
@@ -26,3 +35,27 @@ pub trait AppState {
     fn handle_ui_command(&mut self, command: UiCommand, ui: &mut Ui);
     fn update(&mut self, ctx: Update);
 }
+
+/// Duration of the screen fades in full-screen states.
+const FADE_DURATION: Duration = Duration::from_millis(500);
+
+/// Creates a panel in the bottom part of the full-screen `window` with word-wrapped narration
+/// subtitles.
+fn new_subtitle_panel(window: ui::Handle, text: BString, ui: &mut Ui) -> ui::Handle {
+    let mut text_panel = Panel::new();
+    text_panel.set_text(Some(panel::Text {
+        text,
+        font: FontKey::antialiased(1),
+        color: WHITE,
+        options: DrawOptions {
+            horz_align: HorzAlign::Center,
+            horz_overflow: Some(font::Overflow {
+                size: 0,
+                boundary: font::OverflowBoundary::Word,
+                action: font::OverflowAction::Wrap,
+            }),
+            ..Default::default()
+        },
+    }));
+    ui.new_widget(window, Rect::new(40, 400, 600, 470), None, None, text_panel)
+}
@@ -0,0 +1,142 @@
+use bstring::BString;
+use log::*;
+use sdl2::event::Event as SdlEvent;
+use std::cmp;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::asset::frame::FrameId;
+use crate::fs::FileSystem;
+use crate::graphics::Rect;
+use crate::graphics::sprite::Sprite;
+use crate::sequence::fade;
+use crate::ui::command::UiCommand;
+use crate::ui::{self, Ui};
+
+use super::*;
+
+/// How long the death screen is shown unless skipped.
+const SHOW_DURATION: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    FadeIn,
+    Show,
+    FadeOut,
+    Done,
+}
+
+/// Death screen shown when the game is over. Shows the death image with the narration subtitles
+/// until a key or mouse button is pressed. `AppEvent::MainMenu` is emitted when done.
+// main_death_scene
+pub struct DeathScreen {
+    window: ui::Handle,
+    phase: Phase,
+    phase_start: Option<Instant>,
+}
+
+impl DeathScreen {
+    /// Expects the screen to be faded out.
+    pub fn new(fs: &FileSystem, language: &str, narrator: &str, subtitles: bool, ui: &mut Ui)
+        -> Self
+    {
+        let window = ui.new_window(Rect::with_size(0, 0, 640, 480),
+            Some(Sprite::new(FrameId::DEATH)));
+        ui.widget_base_mut(window).set_modal(true);
+
+        if subtitles {
+            if let Some(text) = read_text(fs, language, narrator) {
+                new_subtitle_panel(window, text, ui);
+            }
+        }
+
+        ui.set_brightness(0);
+
+        Self {
+            window,
+            phase: Phase::FadeIn,
+            phase_start: None,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == Phase::Done
+    }
+
+    fn set_phase(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.phase_start = None;
+    }
+
+    fn skip(&mut self) {
+        match self.phase {
+            Phase::FadeIn | Phase::Show => self.set_phase(Phase::FadeOut),
+            Phase::FadeOut | Phase::Done => {}
+        }
+    }
+}
+
+impl AppState for DeathScreen {
+    fn handle_app_event(&mut self, _ctx: HandleAppEvent) {}
+
+    fn handle_input(&mut self, event: &SdlEvent, _ui: &mut Ui) -> bool {
+        match event {
+            SdlEvent::KeyDown { .. } | SdlEvent::MouseButtonDown { .. } => {
+                self.skip();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_ui_command(&mut self, _command: UiCommand, _ui: &mut Ui) {}
+
+    fn update(&mut self, ctx: Update) {
+        let start = *self.phase_start.get_or_insert(ctx.time);
+        let elapsed = ctx.time.duration_since(start);
+        match self.phase {
+            Phase::FadeIn => {
+                ctx.ui.set_brightness(fade::brightness(0, 128, elapsed, FADE_DURATION));
+                if elapsed >= FADE_DURATION {
+                    self.set_phase(Phase::Show);
+                }
+            }
+            Phase::Show => if elapsed >= SHOW_DURATION {
+                self.set_phase(Phase::FadeOut);
+            }
+            Phase::FadeOut => {
+                // Skipping during the fade in continues from the current brightness.
+                let brightness = cmp::min(ctx.ui.brightness(),
+                    fade::brightness(128, 0, elapsed, FADE_DURATION));
+                ctx.ui.set_brightness(brightness);
+                if elapsed >= FADE_DURATION {
+                    ctx.ui.remove(self.window);
+                    self.set_phase(Phase::Done);
+                    ctx.out.push(AppEvent::MainMenu);
+                }
+            }
+            Phase::Done => {}
+        }
+    }
+}
+
+/// Returns path of the narration speech of `narrator`.
+pub fn narration_path(narrator: &str) -> String {
+    format!("sound/speech/narrator/{}.acm", narrator)
+}
+
+/// Reads the narration subtitles from `text/<language>/cuts/<narrator>.txt`. Line breaks are
+/// replaced with spaces since the text is word-wrapped when drawn.
+fn read_text(fs: &FileSystem, language: &str, narrator: &str) -> Option<BString> {
+    let path = format!("text/{}/cuts/{}.txt", language, narrator);
+    let mut data = Vec::new();
+    if let Err(e) = fs.reader(&path).and_then(|mut rd| Ok(rd.read_to_end(&mut data)?)) {
+        warn!("couldn't read death screen text {}: {}", path, e);
+        return None;
+    }
+    let text: Vec<_> = data.into_iter()
+        .filter(|&c| c != b'\r')
+        .map(|c| if c == b'\n' { b' ' } else { c })
+        .collect();
+    Some(BString::from(&text[..]))
+}
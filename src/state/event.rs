@@ -1,12 +1,36 @@
+use crate::asset::endgame::Ending;
 use crate::asset::proto::TargetMap;
 use crate::graphics::EPoint;
 use crate::graphics::geometry::hex::Direction;
 
 #[derive(Clone, Eq, Debug, PartialEq)]
 pub enum AppEvent {
+    /// Play the endgame slideshow with the applicable `endings`.
+    EndgameSlideshow {
+        endings: Vec<Ending>,
+    },
+    /// The game is over and the death screen is to be shown.
+    GameOver {
+        /// Base name of the death narration.
+        narrator: String,
+    },
+    /// Show the main menu.
+    MainMenu,
     MapExit {
         map: TargetMap,
         pos: EPoint,
         direction: Direction,
     },
-}
\ No newline at end of file
+    /// The movie is over.
+    MovieDone,
+    /// Start a new game from the main menu.
+    NewGame,
+    /// Play the movie `name` from `art/cuts`.
+    PlayMovie {
+        name: String,
+    },
+    /// Exit the application.
+    Quit,
+    /// The endgame slideshow is over.
+    SlideshowDone,
+}
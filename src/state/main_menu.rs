@@ -0,0 +1,100 @@
+use sdl2::event::Event as SdlEvent;
+use sdl2::keyboard::Keycode;
+use std::time::Instant;
+
+use crate::asset::frame::FrameId;
+use crate::asset::message::Messages;
+use crate::graphics::{Point, Rect};
+use crate::graphics::color::Rgb15;
+use crate::graphics::sprite::Sprite;
+use crate::sequence::fade;
+use crate::ui::button::{self, Button};
+use crate::ui::command::{MainMenuCommand, UiCommand, UiCommandData};
+use crate::ui::{self, Ui};
+
+use super::*;
+
+const TEXT_FONT: FontKey = FontKey::antialiased(4);
+const TEXT_COLOR: Rgb15 = unsafe { Rgb15::rgb15_from_packed_unchecked(0x5263) };
+
+/// Main menu shown after the death screen. Only starting a new game and exiting are supported.
+/// Emits `AppEvent::NewGame` or `AppEvent::Quit` when done.
+// main_menu_create, main_menu_loop
+pub struct MainMenu {
+    window: ui::Handle,
+    fade_start: Option<Instant>,
+    choice: Option<MainMenuCommand>,
+}
+
+impl MainMenu {
+    /// Expects the screen to be faded out. `misc_msgs` are the `game/misc.msg` messages used for
+    /// the button labels.
+    pub fn new(misc_msgs: &Messages, ui: &mut Ui) -> Self {
+        let window = ui.new_window(Rect::with_size(0, 0, 640, 480),
+            Some(Sprite::new(FrameId::MAINMENU)));
+        ui.widget_base_mut(window).set_modal(true);
+
+        let btn_size = ui.frm_db().get(FrameId::MENUUP).unwrap().first().size();
+        // NEW GAME, EXIT
+        let buttons = [(10, MainMenuCommand::NewGame), (14, MainMenuCommand::Exit)];
+        for (i, &(msg_id, command)) in buttons.iter().enumerate() {
+            let mut btn = Button::new(FrameId::MENUUP, FrameId::MENUDOWN,
+                Some(UiCommandData::MainMenu(command)));
+            let label = misc_msgs.get(msg_id).map(|m| m.text.clone()).unwrap_or_default();
+            let mut text = button::Text::new(label, TEXT_FONT);
+            text.pos = Point::new(0, 1);
+            text.color = TEXT_COLOR;
+            text.options.horz_align = HorzAlign::Center;
+            btn.set_text(Some(text));
+            // The label is centered at x = 126.
+            ui.new_widget(window, Rect::with_size(30, 19 + 41 * i as i32, 192, btn_size.y),
+                None, None, btn);
+        }
+
+        ui.set_brightness(0);
+
+        Self {
+            window,
+            fade_start: None,
+            choice: None,
+        }
+    }
+}
+
+impl AppState for MainMenu {
+    fn handle_app_event(&mut self, _ctx: HandleAppEvent) {}
+
+    fn handle_input(&mut self, event: &SdlEvent, _ui: &mut Ui) -> bool {
+        let choice = match event {
+            SdlEvent::KeyDown { keycode: Some(Keycode::N), .. } => MainMenuCommand::NewGame,
+            SdlEvent::KeyDown { keycode: Some(Keycode::E | Keycode::Escape), .. } =>
+                MainMenuCommand::Exit,
+            _ => return false,
+        };
+        self.choice.get_or_insert(choice);
+        true
+    }
+
+    fn handle_ui_command(&mut self, command: UiCommand, _ui: &mut Ui) {
+        if let UiCommandData::MainMenu(choice) = command.data {
+            self.choice.get_or_insert(choice);
+        }
+    }
+
+    fn update(&mut self, ctx: Update) {
+        if let Some(choice) = self.choice.take() {
+            ctx.ui.remove(self.window);
+            ctx.ui.set_brightness(0);
+            ctx.out.push(match choice {
+                MainMenuCommand::NewGame => AppEvent::NewGame,
+                MainMenuCommand::Exit => AppEvent::Quit,
+            });
+            return;
+        }
+        let start = *self.fade_start.get_or_insert(ctx.time);
+        let elapsed = ctx.time.duration_since(start);
+        if elapsed <= FADE_DURATION {
+            ctx.ui.set_brightness(fade::brightness(0, 128, elapsed, FADE_DURATION));
+        }
+    }
+}
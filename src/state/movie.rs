@@ -0,0 +1,116 @@
+use log::*;
+use sdl2::event::Event as SdlEvent;
+use std::time::{Duration, Instant};
+
+use crate::asset::mve::MveInfo;
+use crate::asset::sve::Subtitles;
+use crate::fs::FileSystem;
+use crate::graphics::Rect;
+use crate::graphics::color::WHITE;
+use crate::graphics::font::FontKey;
+use crate::ui::command::UiCommand;
+use crate::ui::subtitle_view::SubtitleView;
+use crate::ui::{self, Ui};
+
+use super::*;
+
+/// Plays a movie from `art/cuts` with the subtitles from `text/<language>/cuts`. Only the timing
+/// of the MVE file is read, the video and audio aren't decoded yet so the screen stays black
+/// while the subtitles are shown. Any key or mouse button stops the movie.
+/// `AppEvent::MovieDone` is emitted when done.
+// gmovie_play
+pub struct MoviePlayer {
+    window: ui::Handle,
+    subtitles: Option<ui::Handle>,
+    frame_duration: Duration,
+    frame_count: u32,
+    start: Option<Instant>,
+    done: bool,
+}
+
+impl MoviePlayer {
+    /// Expects the screen to be faded out. Subtitles are read only if `subtitles` is `true`.
+    pub fn new(fs: &FileSystem, language: &str, name: &str, subtitles: bool, ui: &mut Ui)
+        -> Self
+    {
+        let path = format!("art/cuts/{}.mve", name);
+        let info = fs.reader(&path).and_then(|mut rd| Ok(MveInfo::read(&mut rd)?));
+        let (frame_duration, frame_count) = match info {
+            Ok(info) => (info.frame_duration, info.frame_count),
+            Err(e) => {
+                warn!("couldn't read movie {}: {}", path, e);
+                (Duration::from_secs(0), 0)
+            }
+        };
+
+        let window = ui.new_window(Rect::with_size(0, 0, 640, 480), None);
+        ui.widget_base_mut(window).set_modal(true);
+
+        let subtitles = if subtitles && frame_count > 0 {
+            match Subtitles::read_file(fs, language, &format!("cuts/{}.sve", name)) {
+                Ok(subs) => Some(ui.new_widget(window, Rect::new(40, 400, 600, 470), None, None,
+                    SubtitleView::new(subs, FontKey::antialiased(1), WHITE))),
+                Err(e) => {
+                    debug!("no subtitles for movie {}: {}", name, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        ui.set_brightness(128);
+
+        Self {
+            window,
+            subtitles,
+            frame_duration,
+            frame_count,
+            start: None,
+            done: false,
+        }
+    }
+
+    fn finish(&mut self, ui: &mut Ui, out: &mut Vec<AppEvent>) {
+        ui.remove(self.window);
+        ui.set_brightness(0);
+        self.done = true;
+        out.push(AppEvent::MovieDone);
+    }
+}
+
+impl AppState for MoviePlayer {
+    fn handle_app_event(&mut self, _ctx: HandleAppEvent) {}
+
+    fn handle_input(&mut self, event: &SdlEvent, _ui: &mut Ui) -> bool {
+        match event {
+            SdlEvent::KeyDown { .. } | SdlEvent::MouseButtonDown { .. } => {
+                self.frame_count = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_ui_command(&mut self, _command: UiCommand, _ui: &mut Ui) {}
+
+    fn update(&mut self, ctx: Update) {
+        if self.done {
+            return;
+        }
+        let start = *self.start.get_or_insert(ctx.time);
+        let elapsed = ctx.time.saturating_duration_since(start);
+        let frame = if self.frame_duration > Duration::from_secs(0) {
+            (elapsed.as_micros() / self.frame_duration.as_micros()) as u32
+        } else {
+            0
+        };
+        if frame >= self.frame_count {
+            self.finish(ctx.ui, ctx.out);
+            return;
+        }
+        if let Some(subtitles) = self.subtitles {
+            ctx.ui.widget_mut::<SubtitleView>(subtitles).set_frame(frame);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use bstring::BString;
+use log::*;
+use sdl2::event::Event as SdlEvent;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use crate::asset::EntityKind;
+use crate::asset::endgame::{self, Ending};
+use crate::asset::frame::FrameId;
+use crate::fs::FileSystem;
+use crate::graphics::Rect;
+use crate::sequence::fade;
+use crate::ui::command::UiCommand;
+use crate::ui::slide_view::SlideView;
+use crate::ui::{self, Ui};
+
+use super::*;
+
+/// Reading speed used to time the subtitles since there's no speech to sync them with.
+const CHARS_PER_SECOND: u64 = 15;
+
+/// How long a slide without subtitles is shown.
+const DEFAULT_SLIDE_DURATION: Duration = Duration::from_secs(3);
+
+const SCREEN_WIDTH: i32 = 640;
+
+struct Slide {
+    fid: FrameId,
+    /// Panning direction if the image is wider than the screen.
+    pan_direction: Option<i32>,
+    lines: Vec<BString>,
+}
+
+impl Slide {
+    fn duration(&self) -> Duration {
+        if self.lines.is_empty() {
+            DEFAULT_SLIDE_DURATION
+        } else {
+            self.lines.iter().map(|l| line_duration(l.as_bytes())).sum()
+        }
+    }
+
+    /// Returns index of the subtitle line shown at `elapsed` time.
+    fn line_at(&self, elapsed: Duration) -> Option<usize> {
+        let mut end = Duration::from_secs(0);
+        for (i, line) in self.lines.iter().enumerate() {
+            end += line_duration(line.as_bytes());
+            if elapsed < end {
+                return Some(i);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Phase {
+    FadeIn,
+    Show,
+    FadeOut,
+    Done,
+}
+
+/// Endgame slideshow: a sequence of stills with the narration subtitles. Each slide fades in,
+/// stays for the time the subtitles take to read and fades out. Any key or mouse button skips
+/// to the next slide. `AppEvent::SlideshowDone` is emitted after the last slide.
+// endgamePlaySlideshow
+pub struct Slideshow {
+    window: ui::Handle,
+    image: Option<ui::Handle>,
+    text: Option<ui::Handle>,
+    slides: Vec<Slide>,
+    current: usize,
+    line: Option<usize>,
+    /// Start and end image offsets of a panned slide.
+    pan: Option<(i32, i32)>,
+    phase: Phase,
+    phase_start: Option<Instant>,
+}
+
+impl Slideshow {
+    /// Expects the screen to be faded out. Subtitles are read only if `subtitles` is `true`.
+    pub fn new(fs: &FileSystem, language: &str, endings: &[Ending], subtitles: bool, ui: &mut Ui)
+        -> Self
+    {
+        // TODO play the narration speech when there's an audio backend.
+        let slides = endings.iter()
+            .filter_map(|ending| {
+                let fid = FrameId::new_generic(EntityKind::Interface, ending.art);
+                let fid = if let Some(v) = fid {
+                    v
+                } else {
+                    warn!("invalid ending art: {:?}", ending);
+                    return None;
+                };
+                let lines = if subtitles {
+                    read_subtitles(fs, language, &ending.narrator)
+                } else {
+                    Vec::new()
+                };
+                Some(Slide {
+                    fid,
+                    pan_direction: if ending.is_panning() { Some(ending.direction) } else { None },
+                    lines,
+                })
+            })
+            .collect();
+
+        let window = ui.new_window(Rect::with_size(0, 0, SCREEN_WIDTH, 480), None);
+        ui.widget_base_mut(window).set_modal(true);
+
+        ui.set_brightness(0);
+
+        let mut r = Self {
+            window,
+            image: None,
+            text: None,
+            slides,
+            current: 0,
+            line: None,
+            pan: None,
+            phase: Phase::FadeIn,
+            phase_start: None,
+        };
+        r.show_slide(ui);
+        r
+    }
+
+    fn show_slide(&mut self, ui: &mut Ui) {
+        if let Some(h) = self.image.take() {
+            ui.remove(h);
+        }
+        self.set_line(None, ui);
+        self.pan = None;
+
+        let slide = if let Some(v) = self.slides.get(self.current) {
+            v
+        } else {
+            return;
+        };
+        debug!("showing endgame slide {:?}", slide.fid);
+        self.image = Some(ui.new_widget(self.window, Rect::with_size(0, 0, SCREEN_WIDTH, 480),
+            None, None, SlideView::new(slide.fid)));
+        if let Some(direction) = slide.pan_direction {
+            let width = ui.frm_db().get(slide.fid).map(|f| f.first().width).unwrap_or(0);
+            let max_offset = cmp::max(width - SCREEN_WIDTH, 0);
+            self.pan = Some(if direction < 0 { (max_offset, 0) } else { (0, max_offset) });
+        }
+        self.update_pan(Duration::from_secs(0), ui);
+    }
+
+    fn set_line(&mut self, line: Option<usize>, ui: &mut Ui) {
+        if let Some(h) = self.text.take() {
+            ui.remove(h);
+        }
+        self.line = line;
+        if let Some(line) = line {
+            let text = self.slides[self.current].lines[line].clone();
+            self.text = Some(new_subtitle_panel(self.window, text, ui));
+        }
+    }
+
+    fn update_pan(&mut self, elapsed: Duration, ui: &mut Ui) {
+        if let (Some((from, to)), Some(image)) = (self.pan, self.image) {
+            let duration = self.slides[self.current].duration().as_millis() as i64;
+            let elapsed = cmp::min(elapsed.as_millis() as i64, duration);
+            let offset = from as i64 + (to - from) as i64 * elapsed / cmp::max(duration, 1);
+            ui.widget_mut::<SlideView>(image).set_offset(offset as i32);
+        }
+    }
+
+    fn set_phase(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.phase_start = None;
+    }
+
+    fn skip(&mut self) {
+        match self.phase {
+            Phase::FadeIn | Phase::Show => self.set_phase(Phase::FadeOut),
+            Phase::FadeOut | Phase::Done => {}
+        }
+    }
+}
+
+impl AppState for Slideshow {
+    fn handle_app_event(&mut self, _ctx: HandleAppEvent) {}
+
+    fn handle_input(&mut self, event: &SdlEvent, _ui: &mut Ui) -> bool {
+        match event {
+            SdlEvent::KeyDown { .. } | SdlEvent::MouseButtonDown { .. } => {
+                self.skip();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_ui_command(&mut self, _command: UiCommand, _ui: &mut Ui) {}
+
+    fn update(&mut self, ctx: Update) {
+        if self.phase != Phase::Done && self.current >= self.slides.len() {
+            ctx.ui.remove(self.window);
+            self.set_phase(Phase::Done);
+            ctx.out.push(AppEvent::SlideshowDone);
+            return;
+        }
+        let start = *self.phase_start.get_or_insert(ctx.time);
+        let elapsed = ctx.time.duration_since(start);
+        match self.phase {
+            Phase::FadeIn => {
+                ctx.ui.set_brightness(fade::brightness(0, 128, elapsed, FADE_DURATION));
+                if elapsed >= FADE_DURATION {
+                    self.set_phase(Phase::Show);
+                }
+            }
+            Phase::Show => {
+                let slide = &self.slides[self.current];
+                let line = slide.line_at(elapsed);
+                let done = elapsed >= slide.duration();
+                if line != self.line {
+                    self.set_line(line, ctx.ui);
+                }
+                self.update_pan(elapsed, ctx.ui);
+                if done {
+                    self.set_phase(Phase::FadeOut);
+                }
+            }
+            Phase::FadeOut => {
+                // Skipping during the fade in continues from the current brightness.
+                let brightness = cmp::min(ctx.ui.brightness(),
+                    fade::brightness(128, 0, elapsed, FADE_DURATION));
+                ctx.ui.set_brightness(brightness);
+                if elapsed >= FADE_DURATION {
+                    self.current += 1;
+                    self.show_slide(ctx.ui);
+                    self.set_phase(Phase::FadeIn);
+                }
+            }
+            Phase::Done => {}
+        }
+    }
+}
+
+/// Returns how long the subtitle line is shown.
+fn line_duration(line: &[u8]) -> Duration {
+    Duration::from_millis(line.len() as u64 * 1000 / CHARS_PER_SECOND)
+}
+
+fn read_subtitles(fs: &FileSystem, language: &str, narrator: &str) -> Vec<BString> {
+    let path = format!("text/{}/cuts/{}.txt", language, narrator);
+    fs.reader(&path)
+        .and_then(|mut rd| Ok(endgame::read_subtitles(&mut rd)?))
+        .unwrap_or_else(|e| {
+            warn!("couldn't read endgame subtitles {}: {}", path, e);
+            Vec::new()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slide_timing() {
+        let slide = Slide {
+            fid: FrameId::DEATH,
+            pan_direction: None,
+            lines: vec![
+                BString::from(&b"fifteen chars.."[..]),
+                BString::from(&b"thirty characters, two seconds"[..]),
+            ],
+        };
+        assert_eq!(slide.duration(), Duration::from_secs(3));
+        assert_eq!(slide.line_at(Duration::from_millis(0)), Some(0));
+        assert_eq!(slide.line_at(Duration::from_millis(999)), Some(0));
+        assert_eq!(slide.line_at(Duration::from_millis(1000)), Some(1));
+        assert_eq!(slide.line_at(Duration::from_millis(2999)), Some(1));
+        assert_eq!(slide.line_at(Duration::from_millis(3000)), None);
+
+        let slide = Slide {
+            fid: FrameId::DEATH,
+            pan_direction: None,
+            lines: Vec::new(),
+        };
+        assert_eq!(slide.duration(), DEFAULT_SLIDE_DURATION);
+        assert_eq!(slide.line_at(Duration::from_millis(0)), None);
+    }
+}
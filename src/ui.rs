@@ -1,23 +1,36 @@
 pub mod button;
 pub mod command;
 pub mod image_text;
+pub mod layout;
+pub mod message_box;
 pub mod message_panel;
+pub mod nine_patch;
 pub mod panel;
+pub mod scroll_list;
 pub mod sequence;
+pub mod slide_view;
+pub mod subtitle_view;
+pub mod text_input;
 
 pub use sdl2::mouse::MouseButton;
 pub use sdl2::keyboard::Keycode;
 
 use downcast_rs::{Downcast, impl_downcast};
 use enum_map_derive::Enum;
+use log::*;
 use sdl2::event::{Event as SdlEvent};
+use sdl2::clipboard::ClipboardUtil;
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseWheelDirection;
 use slotmap::{SecondaryMap, SlotMap};
 use std::cell::{Ref, RefCell, RefMut};
+use std::mem;
 use std::rc::Rc;
 use std::time::Instant;
 
 use crate::asset::frame::{FrameId, FrameDb};
 use crate::graphics::{Point, Rect};
+use crate::graphics::color::{GREEN, Rgb15};
 use crate::graphics::font::Fonts;
 use crate::graphics::render::Canvas;
 use crate::graphics::sprite::{Sprite, Anchor};
@@ -28,6 +41,7 @@ use crate::util::VecExt;
 pub enum Event {
     KeyDown {
         keycode: Option<Keycode>,
+        keymod: Mod,
     },
     MouseDown {
         pos: Point,
@@ -41,6 +55,20 @@ pub enum Event {
         pos: Point,
         button: MouseButton,
     },
+    /// Mouse wheel scroll. Positive `y` is away from the user.
+    MouseWheel {
+        pos: Point,
+        y: i32,
+    },
+    /// Text typed on the keyboard. Sent to the keyboard target like `KeyDown`.
+    TextInput {
+        text: String,
+    },
+    /// Text being composed by the input method. Replaced by the next `TextEditing` and cleared
+    /// by `TextInput` with the final text.
+    TextEditing {
+        text: String,
+    },
     Tick,
 }
 
@@ -128,6 +156,61 @@ new_handle_type! {
     pub struct Handle;
 }
 
+/// How the mouse motion moves the cursor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MouseMode {
+    /// The mouse is captured and the cursor is moved by the relative motion.
+    Relative,
+    /// The cursor follows the system cursor position in the window.
+    Absolute,
+}
+
+impl MouseMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "relative" => Some(MouseMode::Relative),
+            "absolute" => Some(MouseMode::Absolute),
+            _ => None,
+        }
+    }
+}
+
+/// Access to the system clipboard for the widgets.
+pub trait Clipboard {
+    fn text(&self) -> Option<String>;
+    fn set_text(&mut self, text: &str);
+}
+
+/// Clipboard that is local to the process. Used when there's no system clipboard.
+#[derive(Default)]
+pub struct LocalClipboard(Option<String>);
+
+impl Clipboard for LocalClipboard {
+    fn text(&self) -> Option<String> {
+        self.0.clone()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.0 = Some(text.into());
+    }
+}
+
+impl Clipboard for ClipboardUtil {
+    fn text(&self) -> Option<String> {
+        if self.has_clipboard_text() {
+            self.clipboard_text().ok()
+        } else {
+            None
+        }
+    }
+
+    fn set_text(&mut self, text: &str) {
+        if let Err(e) = self.set_clipboard_text(text) {
+            warn!("couldn't set clipboard text: {}", e);
+        }
+    }
+}
+
 pub struct HandleInput<'a> {
     pub now: Instant,
     pub event: &'a SdlEvent,
@@ -151,6 +234,35 @@ pub struct Ui {
     simulate_mouse_move: bool,
     mouse_focus: Option<Handle>,
     keyboard_focus: Option<Handle>,
+    /// Whether the keyboard focus outline is drawn. Set when the focus is moved with the keyboard
+    /// and cleared on mouse click.
+    focus_visible: bool,
+    /// Screen brightness in [0..128] range: 0 - black, 128 - original colors.
+    brightness: u8,
+    /// If `true` the input isn't delivered to widgets, mouse only moves the cursor.
+    input_disabled: bool,
+    /// Screen areas of the widgets added, removed or altered since the last `invalidate()`.
+    invalidated: RefCell<Vec<Rect>>,
+    /// Widgets borrowed with `widget_mut()` since the last `invalidate()`.
+    altered: RefCell<Vec<Handle>>,
+    /// Mouse focus as of the last `invalidate()`.
+    rendered_mouse_focus: Option<Handle>,
+    /// Widget with the focus outline as of the last `invalidate()`.
+    rendered_focus_outline: Option<Handle>,
+    /// Screen areas of the cursors as of the last `invalidate()`.
+    rendered_cursors: Vec<Rect>,
+    mouse_mode: MouseMode,
+    /// Whether the cursor is shown using the system cursor in the absolute mouse mode instead of
+    /// being drawn.
+    hardware_cursor: bool,
+    /// Position of the screen top left corner in the window coordinates.
+    window_origin: (f64, f64),
+    /// Window coordinate units per screen pixel. Relative mouse motion is divided by it so the
+    /// cursor speed doesn't depend on the window size.
+    window_scale: f64,
+    /// Fractional part of the scaled mouse motion carried over to the next motion.
+    motion_remainder: (f64, f64),
+    clipboard: Box<dyn Clipboard>,
 }
 
 impl Ui {
@@ -170,6 +282,69 @@ impl Ui {
             simulate_mouse_move: false,
             mouse_focus: None,
             keyboard_focus: None,
+            focus_visible: false,
+            brightness: 128,
+            input_disabled: false,
+            invalidated: RefCell::new(Vec::new()),
+            altered: RefCell::new(Vec::new()),
+            rendered_mouse_focus: None,
+            rendered_focus_outline: None,
+            rendered_cursors: Vec::new(),
+            mouse_mode: MouseMode::Relative,
+            hardware_cursor: false,
+            window_origin: (0.0, 0.0),
+            window_scale: 1.0,
+            motion_remainder: (0.0, 0.0),
+            clipboard: Box::new(LocalClipboard::default()),
+        }
+    }
+
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = clipboard;
+    }
+
+    /// Returns the window area of the focused `TextInput` widget. The input method candidate
+    /// window is placed near it.
+    pub fn text_input_rect(&self) -> Option<Rect> {
+        let h = self.keyboard_focus?;
+        if self.widgets[h].borrow().downcast_ref::<text_input::TextInput>().is_none() {
+            return None;
+        }
+        let rect = self.widget_bases[h].borrow().rect;
+        let (ox, oy) = self.window_origin;
+        let map = |v: i32, o: f64| (v as f64 * self.window_scale + o).round() as i32;
+        Some(Rect::new(map(rect.left, ox), map(rect.top, oy),
+            map(rect.right, ox), map(rect.bottom, oy)))
+    }
+
+    pub fn mouse_mode(&self) -> MouseMode {
+        self.mouse_mode
+    }
+
+    pub fn set_mouse_mode(&mut self, mode: MouseMode) {
+        self.mouse_mode = mode;
+        self.motion_remainder = (0.0, 0.0);
+    }
+
+    pub fn hardware_cursor(&self) -> bool {
+        self.hardware_cursor
+    }
+
+    pub fn set_hardware_cursor(&mut self, enabled: bool) {
+        self.hardware_cursor = enabled;
+    }
+
+    fn uses_hardware_cursor(&self) -> bool {
+        self.hardware_cursor && self.mouse_mode == MouseMode::Absolute
+    }
+
+    /// Sets placement of the screen in the window: position of the screen top left corner and
+    /// the number of window coordinate units per screen pixel.
+    pub fn set_window_mapping(&mut self, origin: (f64, f64), scale: f64) {
+        if scale > 0.0 && (origin, scale) != (self.window_origin, self.window_scale) {
+            self.window_origin = origin;
+            self.window_scale = scale;
+            self.motion_remainder = (0.0, 0.0);
         }
     }
 
@@ -181,6 +356,26 @@ impl Ui {
         &self.frm_db
     }
 
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets the screen brightness used for fading. See `sequence::fade::Fade`.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        assert!(brightness <= 128);
+        self.brightness = brightness;
+    }
+
+    pub fn is_input_disabled(&self) -> bool {
+        self.input_disabled
+    }
+
+    /// Disables or enables the player input. Used by scripted cutscenes.
+    // game_ui_disable, game_ui_enable
+    pub fn set_input_disabled(&mut self, disabled: bool) {
+        self.input_disabled = disabled;
+    }
+
     pub fn new_window(&mut self, rect: Rect, background: Option<Sprite>) -> Handle {
         let h = self.insert_widget(None, Base {
             window: true,
@@ -190,10 +385,12 @@ impl Ui {
             visible: true,
             listener: false,
             modal: false,
+            focusable: false,
         }, Box::new(Window {
             widgets: Vec::new(),
         }));
         self.windows_order.push(h);
+        self.invalidated.get_mut().push(rect);
 
         self.simulate_mouse_move = true;
 
@@ -215,7 +412,9 @@ impl Ui {
         if self.keyboard_focus == Some(handle) {
             self.keyboard_focus = None;
         }
-        self.widget_bases.remove(handle);
+        if let Some(base) = self.widget_bases.remove(handle) {
+            self.invalidated.get_mut().push(base.into_inner().rect);
+        }
 
         let widg = widg.borrow();
         if let Some(win) = widg.downcast_ref::<Window>() {
@@ -257,19 +456,29 @@ impl Ui {
             visible: true,
             listener: false,
             modal: false,
+            focusable: false,
         }, Box::new(widget));
+        self.invalidated.get_mut().push(rect);
 
         self.simulate_mouse_move = true;
 
         h
     }
 
+    pub fn has_widget(&self, handle: Handle) -> bool {
+        self.widgets.contains_key(handle)
+    }
+
     pub fn widget_base_ref(&self, handle: Handle) -> Ref<Base> {
         self.widget_bases[handle].borrow()
     }
 
+    /// Note this invalidates the whole widget area since any of the properties affecting
+    /// the rendering can be changed.
     pub fn widget_base_mut(&self, handle: Handle) -> RefMut<Base> {
-        self.widget_bases[handle].borrow_mut()
+        let base = self.widget_bases[handle].borrow_mut();
+        self.invalidated.borrow_mut().push(base.rect);
+        base
     }
 
     pub fn widget(&self, handle: Handle) -> &RefCell<Box<dyn Widget>> {
@@ -280,7 +489,9 @@ impl Ui {
         Ref::map(self.widget(handle).borrow(), |w| w.downcast_ref::<T>().unwrap())
     }
 
+    /// The widget is reported as altered in the next `Widget::invalidate()` call.
     pub fn widget_mut<T: Widget>(&self, handle: Handle) -> RefMut<T> {
+        self.altered.borrow_mut().push(handle);
         RefMut::map(self.widget(handle).borrow_mut(), |w| w.downcast_mut::<T>().unwrap())
     }
 
@@ -342,6 +553,86 @@ impl Ui {
         self.keyboard_focus = widget;
     }
 
+    /// Moves the keyboard focus to the next (or previous if `backward` is `true`) focusable
+    /// widget of the topmost modal window, wrapping around. Returns `false` if there are no
+    /// focusable widgets.
+    pub fn focus_next(&mut self, backward: bool) -> bool {
+        let focusable = self.focusable_widgets();
+        if focusable.is_empty() {
+            return false;
+        }
+        let cur = self.keyboard_focus.and_then(|h| focusable.iter().position(|&w| w == h));
+        let len = focusable.len();
+        let i = match (cur, backward) {
+            (Some(i), false) => (i + 1) % len,
+            (Some(i), true) => (i + len - 1) % len,
+            (None, false) => 0,
+            (None, true) => len - 1,
+        };
+        self.keyboard_focus = Some(focusable[i]);
+        self.focus_visible = true;
+        true
+    }
+
+    /// Returns visible focusable widgets of the topmost visible window in the tab order.
+    /// The focus navigation is only available in modal windows so the game key bindings keep
+    /// working while the HUD is on top.
+    fn focusable_widgets(&self) -> Vec<Handle> {
+        let winh = if let Some(&h) = self.windows_order.iter().rev()
+            .find(|&&h| self.widget_bases[h].borrow().visible)
+        {
+            h
+        } else {
+            return Vec::new();
+        };
+        if !self.widget_bases[winh].borrow().modal {
+            return Vec::new();
+        }
+        let win = self.widgets[winh].borrow();
+        let win = win.downcast_ref::<Window>().unwrap();
+        win.widgets.iter()
+            .copied()
+            .filter(|&h| {
+                let base = self.widget_bases[h].borrow();
+                base.visible && base.focusable
+            })
+            .collect()
+    }
+
+    /// Whether clicking the `widget` moves the keyboard focus to it. Only focusable widgets of
+    /// modal windows or of the window already having the focus take it.
+    fn takes_focus_on_click(&self, widget: Handle) -> bool {
+        if !self.widget_bases[widget].borrow().focusable {
+            return false;
+        }
+        let win = if let Some(v) = self.window_of(widget) {
+            v
+        } else {
+            return false;
+        };
+        self.widget_bases[win].borrow().modal
+            || self.keyboard_focus.and_then(|h| self.window_of(h)) == Some(win)
+    }
+
+    /// Whether the `Tab` key should move the focus rather than be delivered to the keyboard
+    /// target. Widgets that aren't focusable or have nowhere to move the focus to (like the
+    /// console input line) receive `Tab` themselves.
+    fn is_tab_navigation(&self) -> bool {
+        self.capture.is_none() && match self.keyboard_focus {
+            Some(h) => self.widget_bases[h].borrow().focusable
+                && self.focusable_widgets().iter().any(|&w| w != h),
+            None => true,
+        }
+    }
+
+    fn focus_outline(&self) -> Option<Handle> {
+        self.keyboard_focus.filter(|&h| self.focus_visible
+            && self.widget_bases.get(h).map(|b| {
+                let b = b.borrow();
+                b.visible && b.focusable
+            }).unwrap_or(false))
+    }
+
     /// Limits cursor position to the specified `rect`.
     pub fn set_cursor_constraint(&mut self, rect: Rect) {
         if self.cursor_constraints.len() == 1 {
@@ -374,6 +665,7 @@ impl Ui {
             capture: &mut self.capture,
             out,
             cursor_pos: self.cursor_pos,
+            clipboard: self.clipboard.as_mut(),
         });
     }
 
@@ -396,11 +688,40 @@ impl Ui {
     }
 
     pub fn handle_input(&mut self, ctx: HandleInput) -> bool {
+        if self.input_disabled {
+            if let SdlEvent::MouseMotion { x, y, xrel, yrel, .. } = *ctx.event {
+                self.update_cursor_pos_motion(Point::new(x, y), Point::new(xrel, yrel));
+            }
+            return false;
+        }
         let listener = self.find_listener();
         match *ctx.event {
-            SdlEvent::KeyDown { keycode, .. } => {
+            SdlEvent::KeyDown { keycode, keymod, .. } => {
+                if keycode == Some(Keycode::Tab) && self.is_tab_navigation() {
+                    let backward = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    if self.focus_next(backward) {
+                        return true;
+                    }
+                }
                 if let Some(target) = self.keyboard_event_target() {
-                    self.widget_handle_event(ctx.now, target, Event::KeyDown { keycode }, ctx.out);
+                    self.widget_handle_event(ctx.now, target,
+                        Event::KeyDown { keycode, keymod }, ctx.out);
+                } else {
+                    return false;
+                }
+            }
+            SdlEvent::TextInput { ref text, .. } => {
+                if let Some(target) = self.keyboard_event_target() {
+                    self.widget_handle_event(ctx.now, target,
+                        Event::TextInput { text: text.clone() }, ctx.out);
+                } else {
+                    return false;
+                }
+            }
+            SdlEvent::TextEditing { ref text, .. } => {
+                if let Some(target) = self.keyboard_event_target() {
+                    self.widget_handle_event(ctx.now, target,
+                        Event::TextEditing { text: text.clone() }, ctx.out);
                 } else {
                     return false;
                 }
@@ -415,12 +736,21 @@ impl Ui {
                 } else {
                     return false;
                 };
+                self.focus_visible = false;
+                if self.takes_focus_on_click(target) {
+                    self.keyboard_focus = Some(target);
+                } else if self.keyboard_focus.map(|h| self.widget_bases[h].borrow().focusable)
+                    .unwrap_or(false)
+                {
+                    // Otherwise a clicked HUD button would keep the keys away from the game.
+                    self.keyboard_focus = None;
+                }
                 self.widget_handle_event(ctx.now, target, event, ctx.out);
             }
-            SdlEvent::MouseMotion { xrel, yrel, .. } => {
+            SdlEvent::MouseMotion { x, y, xrel, yrel, .. } => {
                 self.simulate_mouse_move = false;
 
-                self.update_cursor_pos_rel(Point::new(xrel, yrel));
+                self.update_cursor_pos_motion(Point::new(x, y), Point::new(xrel, yrel));
                 if !self.fire_mouse_move(ctx.now, ctx.out) {
                     return false;
                 }
@@ -434,11 +764,35 @@ impl Ui {
                 self.widget_handle_event(ctx.now, target,
                     Event::MouseUp { pos: self.cursor_pos, button: mouse_btn }, ctx.out);
             }
+            SdlEvent::MouseWheel { y, direction, .. } => {
+                let y = if direction == MouseWheelDirection::Flipped { -y } else { y };
+                let target = if let Some(h) = self.update_mouse_focus(ctx.now, ctx.out) {
+                    h
+                } else {
+                    return false;
+                };
+                self.widget_handle_event(ctx.now, target,
+                    Event::MouseWheel { pos: self.cursor_pos, y }, ctx.out);
+            }
             _ => return false,
         }
         true
     }
 
+    /// Moves the cursor to `pos` on the game screen as if the mouse was moved there, regardless
+    /// of the mouse mode. Used to replay the recorded input. Returns `true` if the move was
+    /// handled by a widget.
+    pub fn move_cursor(&mut self, now: Instant, pos: Point, out: &mut Vec<command::UiCommand>)
+        -> bool
+    {
+        self.update_cursor_pos_abs(pos);
+        if self.input_disabled {
+            return false;
+        }
+        self.simulate_mouse_move = false;
+        self.fire_mouse_move(now, out)
+    }
+
     pub fn update(&mut self, now: Instant, out: &mut Vec<command::UiCommand>) {
         if self.simulate_mouse_move {
             self.simulate_mouse_move = false;
@@ -461,7 +815,66 @@ impl Ui {
         }
     }
 
+    /// Reports the screen areas that will look different in the next `render()` to the `canvas`.
+    pub fn invalidate(&mut self, canvas: &mut dyn Canvas) {
+        let mut rects = mem::replace(self.invalidated.get_mut(), Vec::new());
+        let altered = mem::replace(self.altered.get_mut(), Vec::new());
+
+        if self.mouse_focus != self.rendered_mouse_focus {
+            for &h in self.rendered_mouse_focus.iter().chain(self.mouse_focus.iter()) {
+                if let Some(base) = self.widget_bases.get(h) {
+                    rects.push(base.borrow().rect);
+                }
+            }
+            self.rendered_mouse_focus = self.mouse_focus;
+        }
+
+        let focus_outline = self.focus_outline();
+        if focus_outline != self.rendered_focus_outline {
+            for &h in self.rendered_focus_outline.iter().chain(focus_outline.iter()) {
+                if let Some(base) = self.widget_bases.get(h) {
+                    rects.push(base.borrow().rect.inflate(FOCUS_OUTLINE_WIDTH, FOCUS_OUTLINE_WIDTH));
+                }
+            }
+            self.rendered_focus_outline = focus_outline;
+        }
+
+        // Windows themselves are invalidated only when added, removed or altered.
+        for &winh in &self.windows_order {
+            if !self.widget_bases[winh].borrow().visible {
+                continue;
+            }
+            let win = self.widgets[winh].borrow();
+            let win = win.downcast_ref::<Window>().unwrap();
+            for &widgh in &win.widgets {
+                let base = self.widget_bases[widgh].borrow();
+                if !base.visible {
+                    continue;
+                }
+                self.widgets[widgh].borrow_mut().invalidate(Invalidate {
+                    frm_db: &self.frm_db,
+                    base: &base,
+                    cursor_pos: self.cursor_pos,
+                    altered: altered.contains(&widgh),
+                    rects: &mut rects,
+                });
+            }
+        }
+
+        let cursors = self.cursor_areas();
+        if cursors != self.rendered_cursors {
+            rects.extend(self.rendered_cursors.iter().chain(&cursors));
+            self.rendered_cursors = cursors;
+        }
+
+        for rect in rects {
+            canvas.invalidate(rect);
+        }
+    }
+
     pub fn render(&mut self, canvas: &mut dyn Canvas) {
+        canvas.set_brightness(self.brightness);
+        let focus_outline = self.focus_outline();
         for &winh in &self.windows_order {
             let mut win = self.widgets[winh].borrow_mut();
             let win = win.downcast_mut::<Window>().unwrap();
@@ -478,6 +891,7 @@ impl Ui {
                     base: None,
                     cursor_pos: self.cursor_pos,
                     has_mouse_focus,
+                    has_keyboard_focus: false,
                 });
             }
             win.render(Render {
@@ -486,6 +900,7 @@ impl Ui {
                 base: Some(&self.widget_bases[winh].borrow()),
                 cursor_pos: self.cursor_pos,
                 has_mouse_focus,
+                has_keyboard_focus: false,
             });
             for &widgh in &win.widgets {
                 let has_mouse_focus = self.mouse_focus == Some(widgh);
@@ -500,6 +915,7 @@ impl Ui {
                         base: Some(&self.widget_bases[winh].borrow()),
                         cursor_pos: self.cursor_pos,
                         has_mouse_focus,
+                        has_keyboard_focus: self.keyboard_focus == Some(widgh),
                     });
                 }
                 self.widgets[widgh].borrow_mut().render(Render {
@@ -508,7 +924,11 @@ impl Ui {
                     base: Some(&self.widget_bases[widgh].borrow()),
                     cursor_pos: self.cursor_pos,
                     has_mouse_focus,
+                    has_keyboard_focus: self.keyboard_focus == Some(widgh),
                 });
+                if focus_outline == Some(widgh) {
+                    draw_focus_outline(self.widget_bases[widgh].borrow().rect, canvas);
+                }
             }
         }
 
@@ -517,6 +937,19 @@ impl Ui {
         }
 
         let cursor = self.effective_cursor();
+        if self.uses_hardware_cursor() {
+            let top_left = self.cursor_sprite(cursor, self.cursor_pos)
+                .screen_area(&self.frm_db)
+                .top_left();
+            let texture = &self.frm_db.get(cursor.fid()).unwrap().first().texture;
+            match canvas.set_hardware_cursor(texture, self.cursor_pos - top_left) {
+                Ok(()) => return,
+                Err(e) => {
+                    warn!("couldn't set hardware cursor, falling back to software cursor: {}", e);
+                    self.hardware_cursor = false;
+                }
+            }
+        }
         self.draw_cursor(cursor, self.cursor_pos, canvas);
     }
 
@@ -551,12 +984,30 @@ impl Ui {
         self.cursor
     }
 
-    fn draw_cursor(&self, cursor: Cursor, pos: Point, canvas: &mut dyn Canvas) {
+    fn cursor_sprite(&self, cursor: Cursor, pos: Point) -> Sprite {
         let fid = cursor.fid();
         let (offset, anchor) = cursor.placement(&self.frm_db);
         let mut sprite = Sprite::new_with_pos(fid, pos + offset);
         sprite.anchor = anchor;
-        sprite.render(canvas, &self.frm_db);
+        sprite
+    }
+
+    fn draw_cursor(&self, cursor: Cursor, pos: Point, canvas: &mut dyn Canvas) {
+        self.cursor_sprite(cursor, pos).render(canvas, &self.frm_db);
+    }
+
+    /// Returns screen areas of the cursor ghost (if any) and the cursor unless it's shown by the
+    /// system.
+    fn cursor_areas(&self) -> Vec<Rect> {
+        let mut r = Vec::with_capacity(2);
+        if let Some((pos, cursor)) = self.cursor_ghost {
+            r.push(self.cursor_sprite(cursor, pos).screen_area(&self.frm_db));
+        }
+        if !self.uses_hardware_cursor() {
+            r.push(self.cursor_sprite(self.effective_cursor(), self.cursor_pos)
+                .screen_area(&self.frm_db));
+        }
+        r
     }
 
     /// Returns (widget, widget's window) or (window, window).
@@ -610,7 +1061,24 @@ impl Ui {
         }
     }
 
+    /// Moves the cursor according to the mouse motion to the window position `pos` by `rel`.
+    fn update_cursor_pos_motion(&mut self, pos: Point, rel: Point) {
+        match self.mouse_mode {
+            MouseMode::Relative => self.update_cursor_pos_rel(rel),
+            MouseMode::Absolute => {
+                let (ox, oy) = self.window_origin;
+                let x = ((pos.x as f64 - ox) / self.window_scale).floor() as i32;
+                let y = ((pos.y as f64 - oy) / self.window_scale).floor() as i32;
+                self.update_cursor_pos_abs(Point::new(x, y));
+            }
+        }
+    }
+
     fn update_cursor_pos_rel(&mut self, rel: Point) {
+        let x = rel.x as f64 / self.window_scale + self.motion_remainder.0;
+        let y = rel.y as f64 / self.window_scale + self.motion_remainder.1;
+        self.motion_remainder = (x.fract(), y.fract());
+        let rel = Point::new(x.trunc() as i32, y.trunc() as i32);
         let abs = self.cursor_pos + rel;
         self.update_cursor_pos_abs(abs);
     }
@@ -639,6 +1107,18 @@ impl Ui {
     }
 }
 
+const FOCUS_OUTLINE_WIDTH: i32 = 1;
+const FOCUS_OUTLINE_COLOR: Rgb15 = GREEN;
+
+fn draw_focus_outline(rect: Rect, canvas: &mut dyn Canvas) {
+    let r = rect.inflate(FOCUS_OUTLINE_WIDTH, FOCUS_OUTLINE_WIDTH);
+    let w = FOCUS_OUTLINE_WIDTH;
+    canvas.fill_rect(r.with_height(w), FOCUS_OUTLINE_COLOR);
+    canvas.fill_rect(Rect::new(r.left, r.bottom - w, r.right, r.bottom), FOCUS_OUTLINE_COLOR);
+    canvas.fill_rect(r.with_width(w), FOCUS_OUTLINE_COLOR);
+    canvas.fill_rect(Rect::new(r.right - w, r.top, r.right, r.bottom), FOCUS_OUTLINE_COLOR);
+}
+
 pub struct Window {
     widgets: Vec<Handle>,
 }
@@ -647,6 +1127,9 @@ impl Widget for Window {
     fn handle_event(&mut self, _ctx: HandleEvent) {
     }
 
+    fn invalidate(&mut self, _ctx: Invalidate) {
+    }
+
     fn render(&mut self, _ctx: Render) {
     }
 }
@@ -664,6 +1147,8 @@ pub struct Base {
     /// Currently only `MouseDown` is routed to the listener.
     listener: bool,
     modal: bool,
+    /// Whether the widget can receive the keyboard focus with `Tab`/`Shift+Tab` navigation.
+    focusable: bool,
 }
 
 impl Base {
@@ -712,6 +1197,15 @@ impl Base {
         assert!(self.window);
         self.modal = v;
     }
+
+    pub fn is_focusable(&self) -> bool {
+        self.focusable
+    }
+
+    pub fn set_focusable(&mut self, v: bool) {
+        assert!(!self.window);
+        self.focusable = v;
+    }
 }
 
 impl Widget for Base {
@@ -737,6 +1231,7 @@ pub struct HandleEvent<'a> {
     capture: &'a mut Option<Handle>,
     pub out: &'a mut Vec<command::UiCommand>,
     pub cursor_pos: Point,
+    pub clipboard: &'a mut dyn Clipboard,
 }
 
 impl HandleEvent<'_> {
@@ -766,6 +1261,28 @@ pub struct Render<'a> {
     pub base: Option<&'a Base>,
     pub cursor_pos: Point,
     pub has_mouse_focus: bool,
+    pub has_keyboard_focus: bool,
+}
+
+pub struct Invalidate<'a> {
+    pub frm_db: &'a FrameDb,
+    pub base: &'a Base,
+    pub cursor_pos: Point,
+    /// Whether the widget was borrowed with `Ui::widget_mut()` since the last frame.
+    pub altered: bool,
+    rects: &'a mut Vec<Rect>,
+}
+
+impl Invalidate<'_> {
+    /// Marks the `rect` of the screen as changed.
+    pub fn invalidate(&mut self, rect: Rect) {
+        self.rects.push(rect);
+    }
+
+    /// Marks the whole widget area as changed.
+    pub fn invalidate_all(&mut self) {
+        self.rects.push(self.base.rect);
+    }
 }
 
 pub struct Init<'a> {
@@ -786,6 +1303,13 @@ pub trait Widget: Downcast {
     /// Should be used to sync the directly altered widget state to the UI.
     fn sync(&mut self, _ctx: Sync) {}
 
+    /// Called after `sync()` and before `render()` to report the screen areas that will look
+    /// different compared to the previous `render()`. Only these areas are redrawn.
+    /// The default implementation invalidates the whole widget every frame.
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        ctx.invalidate_all();
+    }
+
     fn render(&mut self, _ctx: Render) {}
 }
 
@@ -43,7 +43,11 @@ impl Text {
 pub struct Button {
     configs: EnumMap<State, Config>,
     command: Option<UiCommandData>,
+    /// Commands sent on key presses while the button has the keyboard focus.
+    key_commands: Vec<(Keycode, UiCommandData)>,
     state: State,
+    /// The state the button was last rendered in.
+    rendered_state: Option<State>,
 }
 
 impl Button {
@@ -64,7 +68,9 @@ impl Button {
                 },
             },
             command,
+            key_commands: Vec::new(),
             state: State::Up,
+            rendered_state: None,
         }
     }
 
@@ -81,6 +87,12 @@ impl Button {
         self.configs[State::Up].text = text;
     }
 
+    /// Sets the commands sent when the keys are pressed while the button has the keyboard
+    /// focus. Used for shortcuts of the window the button belongs to.
+    pub fn set_key_commands(&mut self, key_commands: Vec<(Keycode, UiCommandData)>) {
+        self.key_commands = key_commands;
+    }
+
     pub fn set_enabled(&mut self, enabled: bool) {
         self.state = if enabled {
             State::Up
@@ -115,11 +127,37 @@ impl Widget for Button {
                 }
                 ctx.release();
             }
+            Event::KeyDown { keycode: Some(keycode), .. }
+                if self.key_commands.iter().any(|&(k, _)| k == keycode) =>
+            {
+                let cmd = self.key_commands.iter().find(|&&(k, _)| k == keycode).unwrap().1;
+                ctx.out(cmd);
+            }
+            Event::KeyDown { keycode: Some(Keycode::Return), .. }
+            | Event::KeyDown { keycode: Some(Keycode::KpEnter), .. }
+            | Event::KeyDown { keycode: Some(Keycode::Space), .. }
+                if self.state != State::Disabled =>
+            {
+                if let Some(cmd) = self.command {
+                    ctx.out(cmd);
+                }
+            }
             _ => {}
         }
     }
 
+    fn sync(&mut self, ctx: Sync) {
+        ctx.base.set_focusable(self.state != State::Disabled && self.command.is_some());
+    }
+
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        if ctx.altered || self.rendered_state != Some(self.state) {
+            ctx.invalidate_all();
+        }
+    }
+
     fn render(&mut self, ctx: Render) {
+        self.rendered_state = Some(self.state);
         let config = &self.configs[self.state];
         let base_rect = ctx.base.unwrap().rect;
         if let Some(mut background) = config.background {
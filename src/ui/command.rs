@@ -27,9 +27,21 @@ pub enum UiCommandData {
         id: u32,
     },
     Scroll,
+    Combat(CombatCommand),
     Skilldex(SkilldexCommand),
+    Pipboy(PipboyCommand),
+    Automap(AutomapCommand),
+    Console(ConsoleCommand),
+    ScriptDebugger(ScriptDebuggerCommand),
+    Inspector(InspectorCommand),
     Inventory(inventory::Command),
+    Loot(loot::Command),
     MoveWindow(move_window::Command),
+    ItemChooser(ItemChooserCommand),
+    TextInput(TextInputCommand),
+    ScrollList(ScrollListCommand),
+    MessageBox(MessageBoxCommand),
+    MainMenu(MainMenuCommand),
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -38,6 +50,14 @@ pub enum ObjectPickKind {
     DefaultAction,
     ActionMenu,
     Skill(crate::asset::Skill),
+    UseItem(crate::game::object::Handle),
+    Attack,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CombatCommand {
+    EndTurn,
+    EndCombat,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -50,6 +70,105 @@ pub enum SkilldexCommand {
     },
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PipboyCommand {
+    Hide,
+    Show,
+    Status,
+    Town {
+        location: crate::asset::message::MessageId,
+    },
+    Archives,
+    /// Opens reader of the holodisk with index `idx` in `holodisk.txt`.
+    Holodisk {
+        idx: usize,
+    },
+    /// Closes the holodisk reader.
+    Done,
+    Scroll(crate::game::ui::inventory_list::Scroll),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AutomapCommand {
+    Hide,
+    Show,
+    /// Switches the motion sensor scanner on or off.
+    Scanner,
+    /// Switches between showing only the walls and showing the scenery too.
+    Detail,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ItemChooserCommand {
+    Pick {
+        object: object::Handle,
+    },
+    PickAll,
+    Cancel,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextInputCommand {
+    /// Enter was pressed.
+    Submit,
+    /// Escape was pressed.
+    Cancel,
+    /// One of the keys set with `TextInput::set_command_keys()` was pressed.
+    Key {
+        keycode: Keycode,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScrollListCommand {
+    /// Item `idx` was selected with the mouse or keyboard.
+    Select {
+        idx: usize,
+    },
+    /// Item `idx` was double-clicked or Enter was pressed on it.
+    Activate {
+        idx: usize,
+    },
+}
+
+/// Result of `message_box::MessageBox`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MessageBoxCommand {
+    Done,
+    Yes,
+    No,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MainMenuCommand {
+    NewGame,
+    Exit,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConsoleCommand {
+    Complete,
+    Execute,
+    Hide,
+    HistoryNext,
+    HistoryPrev,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptDebuggerCommand {
+    /// Resumes the suspended script until the next breakpoint.
+    Continue,
+    Hide,
+    /// Resumes the suspended script for a single instruction.
+    Step,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InspectorCommand {
+    Apply,
+    Hide,
+}
+
 pub mod inventory {
     use super::*;
     use crate::game::ui::action_menu::Action;
@@ -78,6 +197,26 @@ pub mod inventory {
     }
 }
 
+pub mod loot {
+    use crate::game::ui::inventory_list::Scroll;
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Side {
+        Looter,
+        Target,
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Command {
+        Hide,
+        Scroll {
+            side: Side,
+            scroll: Scroll,
+        },
+        TakeAll,
+    }
+}
+
 pub mod move_window {
     #[derive(Clone, Copy, Debug, Eq, PartialEq)]
     pub enum Command {
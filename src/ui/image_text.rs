@@ -27,7 +27,9 @@ impl ImageText {
     }
 
     pub fn big_numbers() -> Self {
-        Self::standard_digits(FrameId::BIG_NUMBERS, 14)
+        let mut r = Self::standard_digits(FrameId::BIG_NUMBERS, 14);
+        r.chars.insert(b':', Rect::with_size(14 * 12, 0, 14, 0xffff));
+        r
     }
 
     pub fn text(&self) -> &BString {
@@ -40,6 +42,12 @@ impl ImageText {
 }
 
 impl Widget for ImageText {
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        if ctx.altered {
+            ctx.invalidate_all();
+        }
+    }
+
     fn render(&mut self, ctx: Render) {
         let frm = ctx.frm_db.get(self.fid).unwrap();
         let tex = &frm.first().texture;
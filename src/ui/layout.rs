@@ -0,0 +1,335 @@
+//! Interface screens described in JSON data files so they can be re-skinned or rearranged
+//! without recompiling.
+//!
+//! A layout describes a window and its widgets. Widgets are identified by `id`, the screen code
+//! looks up the widgets it knows and gives them behavior. Widgets with ids unknown to the screen
+//! are created as plain images which allows adding decorations. A decoration with `tile` repeats
+//! the image over its rect, with `insets` given as `[left, top, right, bottom]` the borders of
+//! the image are kept and only the middle is repeated. Rects are given as
+//! `[left, top, width, height]` relative to the window; FIDs either as numbers or as hex strings:
+//!
+//! ```text
+//! {
+//!     "window": {"rect": [0, 379, 640, 100], "background": "0x06000010"},
+//!     "widgets": [
+//!         {"id": "inventory", "rect": [211, 40, 32, 21], "up": "0x0600002f", "down": "0x0600002e"},
+//!         {"id": "options", "visible": false},
+//!         {"id": "frame", "rect": [0, 0, 200, 60], "tile": "0x06000010", "insets": [4, 4, 4, 4]}
+//!     ]
+//! }
+//! ```
+//!
+//! A layout loaded from the data files is applied over the built-in one: the fields given in
+//! the file replace the fields of the built-in widget with the same id.
+
+use log::*;
+use std::convert::TryInto;
+use std::io::{self, Error, ErrorKind, Read};
+
+use crate::asset::frame::FrameId;
+use crate::error;
+use crate::fs::FileSystem;
+use crate::graphics::Rect;
+use crate::graphics::sprite::Sprite;
+use crate::ui::button::Button;
+use crate::ui::command::UiCommandData;
+use crate::ui::nine_patch::{Insets, NinePatch};
+use crate::ui::panel::Panel;
+use crate::ui::{Handle, Ui, Widget};
+use crate::util::json::{self, Value};
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WidgetDef {
+    pub id: String,
+    pub rect: Option<Rect>,
+    pub background: Option<FrameId>,
+    /// Images of the button in the up and down states.
+    pub up: Option<FrameId>,
+    pub down: Option<FrameId>,
+    /// Image repeated over the rect of a decoration.
+    pub tile: Option<FrameId>,
+    /// Borders of the `tile` image that aren't repeated.
+    pub insets: Option<Insets>,
+    pub visible: Option<bool>,
+}
+
+impl WidgetDef {
+    fn merge(&mut self, other: &WidgetDef) {
+        self.rect = other.rect.or(self.rect);
+        self.background = other.background.or(self.background);
+        self.up = other.up.or(self.up);
+        self.down = other.down.or(self.down);
+        self.tile = other.tile.or(self.tile);
+        self.insets = other.insets.or(self.insets);
+        self.visible = other.visible.or(self.visible);
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible.unwrap_or(true)
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Layout {
+    pub window: WidgetDef,
+    /// Widgets in the order they're created (and drawn).
+    pub widgets: Vec<WidgetDef>,
+}
+
+impl Layout {
+    pub fn parse(s: &str) -> io::Result<Self> {
+        let root = json::parse(s)?;
+        let window = root.get("window")
+            .map(parse_widget)
+            .transpose()?
+            .unwrap_or_default();
+        let widgets = if let Some(v) = root.get("widgets") {
+            v.as_array()
+                .ok_or_else(|| error("expected array of widgets"))?
+                .iter()
+                .map(parse_widget)
+                .collect::<io::Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        for (i, w) in widgets.iter().enumerate() {
+            if w.id.is_empty() {
+                return Err(error(&format!("widget #{} has no id", i)));
+            }
+        }
+        Ok(Self {
+            window,
+            widgets,
+        })
+    }
+
+    /// Parses the `builtin` layout and applies the layout file at `path` over it if the file
+    /// exists. Errors in the file are logged and the file is ignored.
+    pub fn load(fs: &FileSystem, path: &str, builtin: &str) -> Self {
+        let mut r = Self::parse(builtin).unwrap();
+        let s = match fs.reader(path) {
+            Ok(mut rd) => {
+                let mut s = String::new();
+                if let Err(e) = rd.read_to_string(&mut s) {
+                    warn!("couldn't read layout {}: {}", path, e);
+                    return r;
+                }
+                s
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => return r,
+            Err(e) => {
+                warn!("couldn't read layout {}: {}", path, e);
+                return r;
+            }
+        };
+        match Self::parse(&s) {
+            Ok(l) => {
+                info!("using layout {}", path);
+                r.apply(&l);
+            }
+            Err(e) => warn!("couldn't parse layout {}: {}", path, e),
+        }
+        r
+    }
+
+    /// Replaces the fields of `self` with the ones given in `other`. Widgets not present in
+    /// `self` are appended.
+    pub fn apply(&mut self, other: &Layout) {
+        self.window.merge(&other.window);
+        for w in &other.widgets {
+            if let Some(existing) = self.widgets.iter_mut().find(|e| e.id == w.id) {
+                existing.merge(w);
+            } else {
+                self.widgets.push(w.clone());
+            }
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&WidgetDef> {
+        self.widgets.iter().find(|w| w.id == id)
+    }
+
+    fn widget_def(&self, id: &str) -> error::Result<&WidgetDef> {
+        self.get(id)
+            .ok_or_else(|| error::Error::invalid_data(format!("no widget `{}` in layout", id)))
+    }
+
+    /// Creates the window.
+    pub fn new_window(&self, ui: &mut Ui) -> Handle {
+        let win = ui.new_window(self.window.rect.unwrap_or_else(Rect::empty),
+            self.window.background.map(Sprite::new));
+        ui.widget_base_mut(win).set_visible(self.window.is_visible());
+        win
+    }
+
+    /// Creates `widget` in the `win` as described by the widget `id`.
+    /// Fails if there's no such widget in the layout.
+    pub fn new_widget(&self, id: &str, win: Handle, widget: impl 'static + Widget, ui: &mut Ui)
+        -> error::Result<Handle>
+    {
+        let def = self.widget_def(id)?;
+        Ok(Self::new_widget0(def, win, widget, ui))
+    }
+
+    fn new_widget0(def: &WidgetDef, win: Handle, widget: impl 'static + Widget, ui: &mut Ui)
+        -> Handle
+    {
+        let h = ui.new_widget(win, def.rect.unwrap_or_else(Rect::empty), None,
+            def.background.map(Sprite::new), widget);
+        if !def.is_visible() {
+            ui.widget_base_mut(h).set_visible(false);
+        }
+        h
+    }
+
+    /// Creates button widget `id` using the up and down images from the layout.
+    pub fn new_button(&self, id: &str, win: Handle, command: Option<UiCommandData>, ui: &mut Ui)
+        -> error::Result<Handle>
+    {
+        let button = self.button(id, command)?;
+        self.new_widget(id, win, button, ui)
+    }
+
+    /// Returns button with the up and down images of the widget `id`.
+    pub fn button(&self, id: &str, command: Option<UiCommandData>) -> error::Result<Button> {
+        let def = self.widget_def(id)?;
+        let up = def.up.unwrap_or(FrameId::BLANK);
+        Ok(Button::new(up, def.down.unwrap_or(up), command))
+    }
+
+    /// Creates widgets whose ids are not in `known` as image panels, or as nine-patches if
+    /// the `tile` is given.
+    pub fn new_decorations(&self, win: Handle, known: &[&str], ui: &mut Ui) {
+        for def in &self.widgets {
+            if known.contains(&def.id.as_str()) {
+                continue;
+            }
+            if let Some(tile) = def.tile {
+                let np = if let Some(insets) = def.insets {
+                    NinePatch::nine_slice(tile, insets)
+                } else {
+                    NinePatch::tiled(tile)
+                };
+                Self::new_widget0(def, win, np, ui);
+            } else {
+                Self::new_widget0(def, win, Panel::new(), ui);
+            }
+        }
+    }
+}
+
+fn parse_widget(v: &Value) -> io::Result<WidgetDef> {
+    let members = if let Value::Object(v) = v {
+        v
+    } else {
+        return Err(error("expected widget object"));
+    };
+    let mut r = WidgetDef::default();
+    for (name, v) in members {
+        let field_error = |e: String| error(&format!("invalid value of field `{}`: {}", name, e));
+        match name.as_str() {
+            "id" => r.id = v.as_str()
+                .ok_or_else(|| field_error("expected string".into()))?
+                .into(),
+            "rect" => r.rect = Some(rect(v).map_err(field_error)?),
+            "background" => r.background = Some(fid(v).map_err(field_error)?),
+            "up" => r.up = Some(fid(v).map_err(field_error)?),
+            "down" => r.down = Some(fid(v).map_err(field_error)?),
+            "tile" => r.tile = Some(fid(v).map_err(field_error)?),
+            "insets" => r.insets = Some(insets(v).map_err(field_error)?),
+            "visible" => r.visible = Some(if let Value::Bool(v) = *v {
+                v
+            } else {
+                return Err(field_error("expected boolean".into()));
+            }),
+            _ => return Err(error(&format!("unknown field `{}`", name))),
+        }
+    }
+    Ok(r)
+}
+
+fn int(v: &Value) -> Option<i64> {
+    match v {
+        Value::String(s) if s.starts_with("0x") => i64::from_str_radix(&s[2..], 16).ok(),
+        _ => v.as_i64(),
+    }
+}
+
+fn rect(v: &Value) -> Result<Rect, String> {
+    let a = v.as_array()
+        .filter(|a| a.len() == 4)
+        .ok_or("expected [left, top, width, height]")?;
+    let mut r = [0; 4];
+    for (r, v) in r.iter_mut().zip(a) {
+        *r = int(v).and_then(|v| v.try_into().ok()).ok_or("expected integer")?;
+    }
+    Ok(Rect::with_size(r[0], r[1], r[2], r[3]))
+}
+
+fn insets(v: &Value) -> Result<Insets, String> {
+    let a = v.as_array()
+        .filter(|a| a.len() == 4)
+        .ok_or("expected [left, top, right, bottom]")?;
+    let mut r = [0; 4];
+    for (r, v) in r.iter_mut().zip(a) {
+        *r = int(v).and_then(|v| v.try_into().ok()).filter(|&v| v >= 0)
+            .ok_or("expected non-negative integer")?;
+    }
+    Ok(Insets { left: r[0], top: r[1], right: r[2], bottom: r[3] })
+}
+
+fn fid(v: &Value) -> Result<FrameId, String> {
+    int(v)
+        .and_then(|v| v.try_into().ok())
+        .and_then(FrameId::from_packed)
+        .ok_or_else(|| "malformed FID".into())
+}
+
+fn error(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_apply() {
+        let mut l = Layout::parse(r#"{
+            "window": {"rect": [0, 379, 640, 100], "background": "0x06000010"},
+            "widgets": [
+                {"id": "a", "rect": [1, 2, 3, 4], "up": "0x06000006", "down": "0x06000007"},
+                {"id": "b", "rect": [5, 6, 7, 8]}
+            ]
+        }"#).unwrap();
+        assert_eq!(l.window.rect, Some(Rect::with_size(0, 379, 640, 100)));
+        assert_eq!(l.window.background, Some(FrameId::IFACE));
+        let a = l.get("a").unwrap();
+        assert_eq!(a.up, Some(FrameId::BIG_RED_BUTTON_UP));
+        assert_eq!(a.down, Some(FrameId::BIG_RED_BUTTON_DOWN));
+
+        l.apply(&Layout::parse(r#"{
+            "widgets": [
+                {"id": "b", "visible": false},
+                {"id": "c", "rect": [0, 0, 1, 1], "background": "0x06000006"},
+                {"id": "d", "rect": [0, 0, 9, 9], "tile": "0x06000010", "insets": [1, 2, 3, 4]}
+            ]
+        }"#).unwrap());
+        let b = l.get("b").unwrap();
+        assert_eq!(b.rect, Some(Rect::with_size(5, 6, 7, 8)));
+        assert!(!b.is_visible());
+        assert_eq!(l.widgets.iter().map(|w| w.id.as_str()).collect::<Vec<_>>(),
+            vec!["a", "b", "c", "d"]);
+        let d = l.get("d").unwrap();
+        assert_eq!(d.tile, Some(FrameId::IFACE));
+        assert_eq!(d.insets, Some(Insets { left: 1, top: 2, right: 3, bottom: 4 }));
+        assert!(l.button("a", None).is_ok());
+        assert!(l.button("e", None).is_err());
+
+        assert!(Layout::parse(r#"{"widgets": [{"rect": [0, 0, 1, 1]}]}"#).is_err());
+        assert!(Layout::parse(r#"{"widgets": [{"id": "a", "rect": [0, 0]}]}"#).is_err());
+        assert!(Layout::parse(r#"{"widgets": [{"id": "a", "foo": 1}]}"#).is_err());
+        assert!(Layout::parse(r#"{"widgets": [{"id": "a", "insets": [0, -1, 0, 0]}]}"#)
+            .is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use bstring::{bstr, BString};
+
+use crate::asset::message::Messages;
+use crate::graphics::color::Rgb15;
+use crate::graphics::font::{self, DrawOptions, FontKey, HorzAlign, VertAlign};
+use crate::ui::button::{Button, Text};
+use crate::ui::command::{MessageBoxCommand, UiCommandData};
+use crate::ui::panel::{self, Panel};
+use super::*;
+
+const WIDTH: i32 = 300;
+const HEIGHT: i32 = 140;
+const BUTTON_WIDTH: i32 = 80;
+const BUTTON_HEIGHT: i32 = 16;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// Message with the Done button. Reports `MessageBoxCommand::Done`.
+    Message,
+    /// Question with Yes and No buttons. Reports `MessageBoxCommand::Yes` or `No`.
+    YesNo,
+}
+
+/// Modal window with a message and one or two buttons. The first button takes the keyboard
+/// focus and Tab moves it between the buttons. Enter and Space press the focused button,
+/// Y confirms and Escape and N decline regardless of the focus.
+/// The result is reported as `UiCommandData::MessageBox` with the source inside the window,
+/// use `is_source()` to tell apart commands of different message boxes.
+// dialog_out
+pub struct MessageBox {
+    win: Handle,
+    prev_keyboard_focus: Option<Handle>,
+}
+
+impl MessageBox {
+    /// Shows the message box with `text` centered and word-wrapped. `msgs` are the
+    /// `game/dbox.msg` messages used for the button labels.
+    pub fn show(kind: Kind, text: &bstr, msgs: &Messages, ui: &mut Ui) -> Self {
+        let mut background = Sprite::new(FrameId::MEDIALOG);
+        background.anchor = Anchor::Center;
+        let win = ui.new_window(Rect::with_size((640 - WIDTH) / 2, (480 - HEIGHT) / 2 - 40,
+            WIDTH, HEIGHT), Some(background));
+        ui.widget_base_mut(win).set_modal(true);
+
+        let color = Rgb15::from_packed(0x5263);
+        let mut panel = Panel::new();
+        panel.set_text(Some(panel::Text {
+            text: text.to_owned(),
+            font: FontKey::antialiased(1),
+            color,
+            options: DrawOptions {
+                horz_align: HorzAlign::Center,
+                vert_align: VertAlign::Middle,
+                horz_overflow: Some(font::Overflow {
+                    size: 0,
+                    boundary: font::OverflowBoundary::Word,
+                    action: font::OverflowAction::Wrap,
+                }),
+                ..Default::default()
+            },
+        }));
+        ui.new_widget(win, Rect::with_size(20, 20, WIDTH - 40, 70), None, None, panel);
+
+        let buttons: &[(u32, MessageBoxCommand)] = match kind {
+            // DONE
+            Kind::Message => &[(100, MessageBoxCommand::Done)],
+            // YES, NO
+            Kind::YesNo => &[(101, MessageBoxCommand::Yes), (102, MessageBoxCommand::No)],
+        };
+        let key_commands: Vec<_> = match kind {
+            Kind::Message => vec![(Keycode::Escape, MessageBoxCommand::Done)],
+            Kind::YesNo => vec![
+                (Keycode::Y, MessageBoxCommand::Yes),
+                (Keycode::N, MessageBoxCommand::No),
+                (Keycode::Escape, MessageBoxCommand::No),
+            ],
+        }.into_iter().map(|(k, cmd)| (k, UiCommandData::MessageBox(cmd))).collect();
+        let total_width = BUTTON_WIDTH * buttons.len() as i32;
+        let mut x = (WIDTH - total_width) / 2;
+        let mut first_button = None;
+        for &(msg_id, cmd) in buttons {
+            let mut button = Button::new(FrameId::SMALL_RED_BUTTON_UP,
+                FrameId::SMALL_RED_BUTTON_DOWN, Some(UiCommandData::MessageBox(cmd)));
+            let label = msgs.get(msg_id).map(|m| m.text.clone()).unwrap_or_else(BString::new);
+            let mut text = Text::new(label, FontKey::antialiased(3));
+            text.pos = Point::new(20, 0);
+            text.color = color;
+            button.set_text(Some(text));
+            button.set_key_commands(key_commands.clone());
+            let button = ui.new_widget(win, Rect::with_size(x, 100, BUTTON_WIDTH, BUTTON_HEIGHT),
+                None, None, button);
+            first_button.get_or_insert(button);
+            x += BUTTON_WIDTH;
+        }
+
+        let prev_keyboard_focus = ui.keyboard_focus();
+        ui.set_keyboard_focus(first_button);
+
+        Self {
+            win,
+            prev_keyboard_focus,
+        }
+    }
+
+    /// Removes the window and gives the keyboard focus back to the widget that had it before.
+    pub fn hide(self, ui: &mut Ui) {
+        ui.remove(self.win);
+        if let Some(h) = self.prev_keyboard_focus {
+            if ui.keyboard_focus().is_none() && ui.has_widget(h) {
+                ui.set_keyboard_focus(Some(h));
+            }
+        }
+    }
+
+    /// Whether the command was sent by a widget of this message box.
+    pub fn is_source(&self, source: Handle, ui: &Ui) -> bool {
+        source == self.win || ui.window_of(source) == Some(self.win)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sdl2::event::Event as SdlEvent;
+    use sdl2::keyboard::Mod;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::fs::FileSystem;
+    use crate::graphics::font::Fonts;
+    use crate::graphics::render::TextureFactory;
+    use crate::ui::command::UiCommand;
+
+    fn new_ui() -> Ui {
+        let frm_db = FrameDb::mock(Rc::new(FileSystem::mock()), TextureFactory::mock());
+        Ui::new(Rc::new(frm_db), Rc::new(Fonts::new()), 640, 480)
+    }
+
+    fn press(ui: &mut Ui, keycode: Keycode, keymod: Mod) -> Vec<UiCommand> {
+        let mut out = Vec::new();
+        ui.handle_input(HandleInput {
+            now: Instant::now(),
+            event: &SdlEvent::KeyDown {
+                timestamp: 0,
+                window_id: 0,
+                keycode: Some(keycode),
+                scancode: None,
+                keymod,
+                repeat: false,
+            },
+            out: &mut out,
+        });
+        out
+    }
+
+    fn results(out: Vec<UiCommand>) -> Vec<MessageBoxCommand> {
+        out.into_iter()
+            .filter_map(|c| match c.data {
+                UiCommandData::MessageBox(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn yes_no() {
+        let mut ui = new_ui();
+        let msgs = Messages::default();
+        let mbox = MessageBox::show(Kind::YesNo, (&b"Quit?"[..]).into(), &msgs, &mut ui);
+        ui.sync();
+
+        let yes = ui.keyboard_focus().unwrap();
+        assert!(mbox.is_source(yes, &ui));
+        assert_eq!(results(press(&mut ui, Keycode::Return, Mod::NOMOD)),
+            vec![MessageBoxCommand::Yes]);
+
+        assert!(results(press(&mut ui, Keycode::Tab, Mod::NOMOD)).is_empty());
+        let no = ui.keyboard_focus().unwrap();
+        assert_ne!(no, yes);
+        assert!(mbox.is_source(no, &ui));
+        assert_eq!(results(press(&mut ui, Keycode::Space, Mod::NOMOD)),
+            vec![MessageBoxCommand::No]);
+
+        // The shortcuts work whichever button has the focus.
+        assert_eq!(results(press(&mut ui, Keycode::Y, Mod::NOMOD)), vec![MessageBoxCommand::Yes]);
+        press(&mut ui, Keycode::Tab, Mod::LSHIFTMOD);
+        assert_eq!(ui.keyboard_focus(), Some(yes));
+        assert_eq!(results(press(&mut ui, Keycode::N, Mod::NOMOD)), vec![MessageBoxCommand::No]);
+        assert_eq!(results(press(&mut ui, Keycode::Escape, Mod::NOMOD)),
+            vec![MessageBoxCommand::No]);
+        assert!(results(press(&mut ui, Keycode::A, Mod::NOMOD)).is_empty());
+
+        let win = ui.window_of(yes).unwrap();
+        mbox.hide(&mut ui);
+        assert!(!ui.has_widget(win));
+        assert_eq!(ui.keyboard_focus(), None);
+    }
+
+    #[test]
+    fn message() {
+        let mut ui = new_ui();
+        let msgs = Messages::default();
+        let mbox = MessageBox::show(Kind::Message, (&b"Done."[..]).into(), &msgs, &mut ui);
+        ui.sync();
+
+        let done = ui.keyboard_focus().unwrap();
+        assert!(mbox.is_source(done, &ui));
+        // Tab has nowhere to go.
+        press(&mut ui, Keycode::Tab, Mod::NOMOD);
+        assert_eq!(ui.keyboard_focus(), Some(done));
+        assert_eq!(results(press(&mut ui, Keycode::Return, Mod::NOMOD)),
+            vec![MessageBoxCommand::Done]);
+        assert_eq!(results(press(&mut ui, Keycode::Escape, Mod::NOMOD)),
+            vec![MessageBoxCommand::Done]);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::graphics::render::TextureHandle;
+use super::*;
+
+/// Widths of the fixed borders of a nine-slice image.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Insets {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Insets {
+    pub fn uniform(v: i32) -> Self {
+        Self {
+            left: v,
+            top: v,
+            right: v,
+            bottom: v,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// The whole image is repeated starting at the top left corner.
+    Tile,
+    /// The corners are drawn once, the edges are repeated along the sides and the middle part
+    /// is repeated over the interior.
+    NineSlice(Insets),
+}
+
+/// Fills the widget area with an interface FRM so panels of arbitrary size can be built from the
+/// fixed-size art.
+pub struct NinePatch {
+    fid: FrameId,
+    mode: Mode,
+}
+
+impl NinePatch {
+    pub fn new(fid: FrameId, mode: Mode) -> Self {
+        Self {
+            fid,
+            mode,
+        }
+    }
+
+    pub fn tiled(fid: FrameId) -> Self {
+        Self::new(fid, Mode::Tile)
+    }
+
+    pub fn nine_slice(fid: FrameId, insets: Insets) -> Self {
+        Self::new(fid, Mode::NineSlice(insets))
+    }
+
+    pub fn fid(&self) -> FrameId {
+        self.fid
+    }
+
+    pub fn set_fid(&mut self, fid: FrameId) {
+        self.fid = fid;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+}
+
+/// Repeats the `src` part of the texture over `dst`. Parts of the tiles outside of `dst` are
+/// clipped.
+fn draw_tiled(canvas: &mut dyn Canvas, tex: &TextureHandle, src: Rect, dst: Rect) {
+    if src.width() <= 0 || src.height() <= 0 || dst.width() <= 0 || dst.height() <= 0 {
+        return;
+    }
+    let mut y = dst.top;
+    while y < dst.bottom {
+        let mut x = dst.left;
+        while x < dst.right {
+            let tile = Rect::with_size(x, y, src.width(), src.height());
+            canvas.set_clip_rect(tile.intersect(dst));
+            canvas.draw(tex, Point::new(x - src.left, y - src.top), 0x10000);
+            x += src.width();
+        }
+        y += src.height();
+    }
+}
+
+impl Widget for NinePatch {
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        if ctx.altered {
+            ctx.invalidate_all();
+        }
+    }
+
+    fn render(&mut self, ctx: Render) {
+        let frm = if let Ok(v) = ctx.frm_db.get(self.fid) {
+            v
+        } else {
+            return;
+        };
+        let dst = ctx.base.unwrap().rect();
+        let frame = frm.first();
+        let tex = &frame.texture;
+        let src = Rect::with_size(0, 0, frame.width, frame.height);
+
+        match self.mode {
+            Mode::Tile => draw_tiled(ctx.canvas, tex, src, dst),
+            Mode::NineSlice(insets) => {
+                for (src, dst) in &slices(src, dst, insets) {
+                    draw_tiled(ctx.canvas, tex, *src, *dst);
+                }
+            }
+        }
+
+        ctx.canvas.reset_clip_rect();
+    }
+}
+
+/// Splits the image `src` and the widget area `dst` into the nine parts by `insets`.
+/// Returns pairs of the source part and the area it's repeated over, row by row. The areas are
+/// clipped to `dst` in case `dst` is smaller than the borders.
+fn slices(src: Rect, dst: Rect, insets: Insets) -> [(Rect, Rect); 9] {
+    let i = insets;
+    // Column and row boundaries in the source image and in the widget area.
+    let sx = [src.left, src.left + i.left, src.right - i.right, src.right];
+    let sy = [src.top, src.top + i.top, src.bottom - i.bottom, src.bottom];
+    let dx = [dst.left, dst.left + i.left, dst.right - i.right, dst.right];
+    let dy = [dst.top, dst.top + i.top, dst.bottom - i.bottom, dst.bottom];
+    let mut r = [(Rect::empty(), Rect::empty()); 9];
+    for (k, r) in r.iter_mut().enumerate() {
+        let (row, col) = (k / 3, k % 3);
+        *r = (Rect::new(sx[col], sy[row], sx[col + 1], sy[row + 1]),
+            Rect::new(dx[col], dy[row], dx[col + 1], dy[row + 1]).intersect(dst));
+    }
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slices_() {
+        let src = Rect::with_size(0, 0, 30, 20);
+        let dst = Rect::with_size(100, 50, 200, 80);
+        let insets = Insets { left: 5, top: 4, right: 6, bottom: 3 };
+        let r = slices(src, dst, insets);
+        let (srcs, dsts): (Vec<_>, Vec<_>) = r.iter().cloned().unzip();
+        assert_eq!(srcs, vec![
+            Rect::new(0, 0, 5, 4), Rect::new(5, 0, 24, 4), Rect::new(24, 0, 30, 4),
+            Rect::new(0, 4, 5, 17), Rect::new(5, 4, 24, 17), Rect::new(24, 4, 30, 17),
+            Rect::new(0, 17, 5, 20), Rect::new(5, 17, 24, 20), Rect::new(24, 17, 30, 20),
+        ]);
+        assert_eq!(dsts, vec![
+            Rect::new(100, 50, 105, 54), Rect::new(105, 50, 294, 54),
+            Rect::new(294, 50, 300, 54),
+            Rect::new(100, 54, 105, 127), Rect::new(105, 54, 294, 127),
+            Rect::new(294, 54, 300, 127),
+            Rect::new(100, 127, 105, 130), Rect::new(105, 127, 294, 130),
+            Rect::new(294, 127, 300, 130),
+        ]);
+
+        // The borders don't fit: the middle part is empty and nothing is drawn outside.
+        let dst = Rect::with_size(0, 0, 8, 5);
+        for (_, d) in &slices(src, dst, insets) {
+            assert!(d.is_empty() || dst.intersect(*d) == *d, "{:?}", d);
+        }
+        assert!(slices(src, dst, insets)[4].1.is_empty());
+    }
+}
@@ -39,6 +39,12 @@ impl Panel {
 }
 
 impl Widget for Panel {
+    fn invalidate(&mut self, mut ctx: Invalidate) {
+        if ctx.altered {
+            ctx.invalidate_all();
+        }
+    }
+
     fn render(&mut self, ctx: Render) {
         if let Some(text) = self.text() {
             let rect = ctx.base.unwrap().rect;
@@ -0,0 +1,502 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use crate::graphics::color::Rgb15;
+use crate::ui::command::{ScrollListCommand, UiCommandData};
+use super::*;
+
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+const MIN_THUMB_HEIGHT: i32 = 8;
+
+/// Context for rendering a single list item.
+pub struct RenderItem<'a> {
+    pub frm_db: &'a FrameDb,
+    pub canvas: &'a mut dyn Canvas,
+    /// Screen area of the item excluding the slider.
+    pub rect: Rect,
+    pub selected: bool,
+}
+
+/// Vertical slider drawn along the right edge of the list.
+#[derive(Clone, Copy, Debug)]
+pub struct Slider {
+    pub width: i32,
+    /// Thumb image. If `None` the thumb is filled with `color`.
+    pub thumb: Option<FrameId>,
+    pub color: Rgb15,
+}
+
+struct ThumbDrag {
+    /// Offset of the cursor from the thumb top.
+    offset: i32,
+}
+
+/// List of `T` items of the same height with selection, mouse wheel and optional slider.
+/// Items are drawn with the `render_item` callback.
+/// Selection changes are reported as `ScrollListCommand::Select`, double-click and Enter on the
+/// selected item as `ScrollListCommand::Activate`.
+pub struct ScrollList<T> {
+    items: Vec<T>,
+    item_height: i32,
+    render_item: Box<dyn Fn(&T, RenderItem)>,
+    slider: Option<Slider>,
+    scroll_idx: usize,
+    selected: Option<usize>,
+    visible_items: usize,
+    thumb_drag: Option<ThumbDrag>,
+    last_click: Option<(Instant, usize)>,
+}
+
+impl<T: 'static> ScrollList<T> {
+    pub fn new(item_height: i32, render_item: impl 'static + Fn(&T, RenderItem)) -> Self {
+        assert!(item_height > 0);
+        Self {
+            items: Vec::new(),
+            item_height,
+            render_item: Box::new(render_item),
+            slider: None,
+            scroll_idx: 0,
+            selected: None,
+            visible_items: 0,
+            thumb_drag: None,
+            last_click: None,
+        }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Appends the item keeping the scroll position and selection.
+    pub fn push(&mut self, item: T) {
+        self.items.push(item);
+    }
+
+    /// Replaces the items resetting the scroll position and selection.
+    pub fn set_items(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.scroll_idx = 0;
+        self.selected = None;
+        self.thumb_drag = None;
+        self.last_click = None;
+    }
+
+    pub fn set_slider(&mut self, slider: Option<Slider>) {
+        self.slider = slider;
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn selected_item(&self) -> Option<&T> {
+        self.selected.map(|i| &self.items[i])
+    }
+
+    /// Selects the item `idx` scrolling it into view.
+    pub fn set_selected(&mut self, idx: Option<usize>) {
+        self.selected = idx.filter(|&i| i < self.items.len());
+        if let Some(i) = self.selected {
+            if i < self.scroll_idx {
+                self.scroll_idx = i;
+            } else if i >= self.scroll_idx + self.visible_items {
+                self.scroll_idx = i + 1 - self.visible_items;
+            }
+        }
+    }
+
+    pub fn scroll_idx(&self) -> usize {
+        self.scroll_idx
+    }
+
+    pub fn set_scroll_idx(&mut self, scroll_idx: usize) {
+        self.scroll_idx = cmp::min(scroll_idx, self.max_scroll_idx());
+    }
+
+    pub fn can_scroll_up(&self) -> bool {
+        self.scroll_idx > 0
+    }
+
+    pub fn can_scroll_down(&self) -> bool {
+        self.scroll_idx < self.max_scroll_idx()
+    }
+
+    /// Number of items that fit in the list. Known after the widget is initialized.
+    pub fn visible_items(&self) -> usize {
+        self.visible_items
+    }
+
+    fn max_scroll_idx(&self) -> usize {
+        self.items.len().saturating_sub(self.visible_items)
+    }
+
+    /// Scrolls by `delta` items, negative being up.
+    pub fn scroll_by(&mut self, delta: i32) {
+        let i = if delta < 0 {
+            self.scroll_idx.saturating_sub(delta.unsigned_abs() as usize)
+        } else {
+            self.scroll_idx + delta as usize
+        };
+        self.set_scroll_idx(i);
+    }
+
+    fn items_rect(&self, rect: Rect) -> Rect {
+        if let Some(slider) = self.slider {
+            rect.with_width(rect.width() - slider.width)
+        } else {
+            rect
+        }
+    }
+
+    fn track_rect(&self, rect: Rect) -> Option<Rect> {
+        let slider = self.slider?;
+        Some(Rect::new(rect.right - slider.width, rect.top, rect.right, rect.bottom))
+    }
+
+    fn thumb_rect(&self, rect: Rect) -> Option<Rect> {
+        let track = self.track_rect(rect)?;
+        let track_height = track.height();
+        let height = if self.items.is_empty() {
+            track_height
+        } else {
+            let h = track_height as usize * cmp::min(self.visible_items, self.items.len())
+                / self.items.len();
+            cmp::max(h as i32, cmp::min(MIN_THUMB_HEIGHT, track_height))
+        };
+        let max_scroll_idx = self.max_scroll_idx();
+        let top = if max_scroll_idx == 0 {
+            0
+        } else {
+            ((track_height - height) as usize * self.scroll_idx / max_scroll_idx) as i32
+        };
+        Some(track.with_height(height).translate(Point::new(0, top)))
+    }
+
+    /// Returns index of the item at screen `pos` in the list of `rect`.
+    pub fn item_index_at(&self, rect: Rect, pos: Point) -> Option<usize> {
+        let rect = self.items_rect(rect);
+        if !rect.contains(pos) {
+            return None;
+        }
+        let i = ((pos.y - rect.top) / self.item_height) as usize;
+        if i >= self.visible_items {
+            return None;
+        }
+        Some(self.scroll_idx + i).filter(|&i| i < self.items.len())
+    }
+
+    fn drag_thumb(&mut self, rect: Rect, y: i32, offset: i32) {
+        let (track, thumb) = match (self.track_rect(rect), self.thumb_rect(rect)) {
+            (Some(t), Some(th)) => (t, th),
+            _ => return,
+        };
+        let range = track.height() - thumb.height();
+        if range <= 0 {
+            return;
+        }
+        let top = (y - offset - track.top).clamp(0, range);
+        let max_scroll_idx = self.max_scroll_idx();
+        // Round to the nearest position.
+        let i = (top as usize * max_scroll_idx + range as usize / 2) / range as usize;
+        self.set_scroll_idx(i);
+    }
+
+    fn select(&mut self, idx: usize, ctx: &mut HandleEvent) {
+        if self.selected != Some(idx) {
+            self.set_selected(Some(idx));
+            ctx.out(UiCommandData::ScrollList(ScrollListCommand::Select { idx }));
+        }
+    }
+}
+
+impl<T: 'static> Widget for ScrollList<T> {
+    fn init(&mut self, ctx: Init) {
+        self.visible_items = (ctx.base.rect().height() / self.item_height) as usize;
+        ctx.base.set_focusable(true);
+    }
+
+    fn handle_event(&mut self, mut ctx: HandleEvent) {
+        let rect = ctx.base.rect();
+        match ctx.event {
+            Event::MouseDown { pos, button } if button == MouseButton::Left => {
+                if let Some(thumb) = self.thumb_rect(rect).filter(|r| r.contains(pos)) {
+                    self.thumb_drag = Some(ThumbDrag { offset: pos.y - thumb.top });
+                    ctx.capture();
+                } else if let Some(thumb) = self.track_rect(rect)
+                    .filter(|r| r.contains(pos))
+                    .and_then(|_| self.thumb_rect(rect))
+                {
+                    let page = self.visible_items as i32;
+                    self.scroll_by(if pos.y < thumb.top { -page } else { page });
+                } else if let Some(idx) = self.item_index_at(rect, pos) {
+                    self.select(idx, &mut ctx);
+                    let double_click = self.last_click
+                        .map(|(t, i)| i == idx && ctx.now - t <= DOUBLE_CLICK_INTERVAL)
+                        .unwrap_or(false);
+                    if double_click {
+                        self.last_click = None;
+                        ctx.out(UiCommandData::ScrollList(ScrollListCommand::Activate { idx }));
+                    } else {
+                        self.last_click = Some((ctx.now, idx));
+                    }
+                }
+            }
+            Event::MouseMove { pos } => if let Some(ThumbDrag { offset }) = self.thumb_drag {
+                self.drag_thumb(rect, pos.y, offset);
+            }
+            Event::MouseUp { button, .. } if button == MouseButton::Left => {
+                if self.thumb_drag.take().is_some() {
+                    ctx.release();
+                }
+            }
+            Event::MouseWheel { y, .. } => {
+                self.scroll_by(-y);
+            }
+            Event::KeyDown { keycode: Some(keycode), .. } => {
+                let last = self.items.len().saturating_sub(1);
+                let page = cmp::max(self.visible_items, 1);
+                let cur = self.selected;
+                let idx = match keycode {
+                    Keycode::Up => cur.map(|i| i.saturating_sub(1)).unwrap_or(0),
+                    Keycode::Down => cur.map(|i| cmp::min(i + 1, last)).unwrap_or(0),
+                    Keycode::PageUp => cur.map(|i| i.saturating_sub(page)).unwrap_or(0),
+                    Keycode::PageDown => cur.map(|i| cmp::min(i + page, last)).unwrap_or(0),
+                    Keycode::Home => 0,
+                    Keycode::End => last,
+                    Keycode::Return | Keycode::KpEnter => {
+                        if let Some(idx) = cur {
+                            ctx.out(UiCommandData::ScrollList(ScrollListCommand::Activate { idx }));
+                        }
+                        return;
+                    }
+                    _ => return,
+                };
+                if !self.items.is_empty() {
+                    self.select(idx, &mut ctx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, ctx: Render) {
+        let rect = ctx.base.unwrap().rect();
+        let items_rect = self.items_rect(rect);
+
+        let mut item_rect = items_rect.with_height(self.item_height);
+        for (i, item) in self.items.iter().enumerate()
+            .skip(self.scroll_idx)
+            .take(self.visible_items)
+        {
+            ctx.canvas.set_clip_rect(item_rect);
+            (self.render_item)(item, RenderItem {
+                frm_db: ctx.frm_db,
+                canvas: ctx.canvas,
+                rect: item_rect,
+                selected: self.selected == Some(i),
+            });
+            item_rect = item_rect.translate(Point::new(0, self.item_height));
+        }
+        ctx.canvas.reset_clip_rect();
+
+        if let (Some(slider), Some(thumb)) = (self.slider, self.thumb_rect(rect)) {
+            if let Some(fid) = slider.thumb {
+                Sprite::new_with_pos(fid, thumb.top_left()).render(ctx.canvas, ctx.frm_db);
+            } else {
+                ctx.canvas.fill_rect(thumb, slider.color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sdl2::event::Event as SdlEvent;
+    use sdl2::keyboard::Mod;
+    use sdl2::mouse::MouseWheelDirection;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::fs::FileSystem;
+    use crate::graphics::color::WHITE;
+    use crate::graphics::font::Fonts;
+    use crate::graphics::render::TextureFactory;
+    use crate::ui::command::UiCommand;
+
+    /// Creates a list of 30 items with 10 of them visible and a 10 pixel wide slider at
+    /// `(190, 100, 200, 200)`.
+    fn new_list() -> (Ui, Handle) {
+        let frm_db = FrameDb::mock(Rc::new(FileSystem::mock()), TextureFactory::mock());
+        let mut ui = Ui::new(Rc::new(frm_db), Rc::new(Fonts::new()), 640, 480);
+        let win = ui.new_window(Rect::with_size(100, 100, 100, 100), None);
+        // Clicked widgets take the keyboard focus only in modal windows.
+        ui.widget_base_mut(win).set_modal(true);
+        let mut list = ScrollList::new(10, |_: &u32, _| {});
+        list.set_items((0..30).collect());
+        list.set_slider(Some(Slider { width: 10, thumb: None, color: WHITE }));
+        let list = ui.new_widget(win, Rect::with_size(0, 0, 100, 100), None, None, list);
+        (ui, list)
+    }
+
+    fn input(ui: &mut Ui, now: Instant, event: SdlEvent) -> Vec<ScrollListCommand> {
+        let mut out = Vec::new();
+        ui.handle_input(HandleInput {
+            now,
+            event: &event,
+            out: &mut out,
+        });
+        results(out)
+    }
+
+    fn results(out: Vec<UiCommand>) -> Vec<ScrollListCommand> {
+        out.into_iter()
+            .filter_map(|c| match c.data {
+                UiCommandData::ScrollList(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn move_to(ui: &mut Ui, now: Instant, pos: Point) {
+        let mut out = Vec::new();
+        ui.move_cursor(now, pos, &mut out);
+    }
+
+    fn click(ui: &mut Ui, now: Instant, pos: Point) -> Vec<ScrollListCommand> {
+        move_to(ui, now, pos);
+        let mut r = input(ui, now, mouse_button(true));
+        r.extend(input(ui, now, mouse_button(false)));
+        r
+    }
+
+    fn mouse_button(down: bool) -> SdlEvent {
+        if down {
+            SdlEvent::MouseButtonDown {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn: MouseButton::Left,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            }
+        } else {
+            SdlEvent::MouseButtonUp {
+                timestamp: 0,
+                window_id: 0,
+                which: 0,
+                mouse_btn: MouseButton::Left,
+                clicks: 1,
+                x: 0,
+                y: 0,
+            }
+        }
+    }
+
+    fn key(keycode: Keycode) -> SdlEvent {
+        SdlEvent::KeyDown {
+            timestamp: 0,
+            window_id: 0,
+            keycode: Some(keycode),
+            scancode: None,
+            keymod: Mod::NOMOD,
+            repeat: false,
+        }
+    }
+
+    fn wheel(y: i32) -> SdlEvent {
+        SdlEvent::MouseWheel {
+            timestamp: 0,
+            window_id: 0,
+            which: 0,
+            x: 0,
+            y,
+            direction: MouseWheelDirection::Normal,
+            precise_x: 0.0,
+            precise_y: y as f32,
+        }
+    }
+
+    fn scroll_idx(ui: &Ui, list: Handle) -> usize {
+        ui.widget_ref::<ScrollList<u32>>(list).scroll_idx()
+    }
+
+    #[test]
+    fn select() {
+        let (mut ui, list) = new_list();
+        let now = Instant::now();
+        assert_eq!(ui.widget_ref::<ScrollList<u32>>(list).visible_items(), 10);
+
+        assert_eq!(click(&mut ui, now, Point::new(150, 125)),
+            vec![ScrollListCommand::Select { idx: 2 }]);
+        assert_eq!(click(&mut ui, now, Point::new(150, 125)),
+            vec![ScrollListCommand::Activate { idx: 2 }]);
+        // Too slow for a double-click.
+        let later = now + DOUBLE_CLICK_INTERVAL * 2;
+        assert_eq!(click(&mut ui, later, Point::new(150, 129)), vec![]);
+        // The slider isn't part of the items.
+        assert_eq!(click(&mut ui, later, Point::new(195, 125)), vec![]);
+
+        assert_eq!(input(&mut ui, now, key(Keycode::Down)),
+            vec![ScrollListCommand::Select { idx: 3 }]);
+        assert_eq!(input(&mut ui, now, key(Keycode::Return)),
+            vec![ScrollListCommand::Activate { idx: 3 }]);
+        assert_eq!(input(&mut ui, now, key(Keycode::End)),
+            vec![ScrollListCommand::Select { idx: 29 }]);
+        assert_eq!(scroll_idx(&ui, list), 20);
+        assert_eq!(input(&mut ui, now, key(Keycode::Down)), vec![]);
+        assert_eq!(input(&mut ui, now, key(Keycode::Home)),
+            vec![ScrollListCommand::Select { idx: 0 }]);
+        assert_eq!(scroll_idx(&ui, list), 0);
+    }
+
+    #[test]
+    fn wheel_scroll() {
+        let (mut ui, list) = new_list();
+        let now = Instant::now();
+        move_to(&mut ui, now, Point::new(150, 150));
+
+        input(&mut ui, now, wheel(-3));
+        assert_eq!(scroll_idx(&ui, list), 3);
+        input(&mut ui, now, wheel(1));
+        assert_eq!(scroll_idx(&ui, list), 2);
+        input(&mut ui, now, wheel(5));
+        assert_eq!(scroll_idx(&ui, list), 0);
+        input(&mut ui, now, wheel(-100));
+        assert_eq!(scroll_idx(&ui, list), 20);
+
+        // Scrolling doesn't change the selection.
+        assert_eq!(click(&mut ui, now, Point::new(150, 100)),
+            vec![ScrollListCommand::Select { idx: 20 }]);
+        input(&mut ui, now, wheel(2));
+        assert_eq!(ui.widget_ref::<ScrollList<u32>>(list).selected(), Some(20));
+    }
+
+    #[test]
+    fn thumb_drag() {
+        let (mut ui, list) = new_list();
+        let now = Instant::now();
+        // The thumb is 33 pixels high and can move by 67 pixels.
+        move_to(&mut ui, now, Point::new(195, 110));
+        input(&mut ui, now, mouse_button(true));
+
+        move_to(&mut ui, now, Point::new(195, 143));
+        assert_eq!(scroll_idx(&ui, list), 10);
+        // The thumb follows the cursor outside of the list.
+        move_to(&mut ui, now, Point::new(300, 400));
+        assert_eq!(scroll_idx(&ui, list), 20);
+        move_to(&mut ui, now, Point::new(300, 0));
+        assert_eq!(scroll_idx(&ui, list), 0);
+        move_to(&mut ui, now, Point::new(195, 177));
+        assert_eq!(scroll_idx(&ui, list), 20);
+
+        assert_eq!(input(&mut ui, now, mouse_button(false)), vec![]);
+        move_to(&mut ui, now, Point::new(195, 110));
+        assert_eq!(scroll_idx(&ui, list), 20);
+
+        // Clicking the track above the thumb scrolls by a page.
+        click(&mut ui, now, Point::new(195, 110));
+        assert_eq!(scroll_idx(&ui, list), 10);
+        assert_eq!(ui.widget_ref::<ScrollList<u32>>(list).selected(), None);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::asset::frame::FrameId;
+use crate::graphics::Point;
+
+use super::*;
+
+/// Shows an image that can be wider than the widget and scrolled horizontally.
+pub struct SlideView {
+    fid: FrameId,
+    offset: i32,
+}
+
+impl SlideView {
+    pub fn new(fid: FrameId) -> Self {
+        Self {
+            fid,
+            offset: 0,
+        }
+    }
+
+    /// Sets horizontal offset of the image.
+    pub fn set_offset(&mut self, offset: i32) {
+        self.offset = offset;
+    }
+}
+
+impl Widget for SlideView {
+    fn render(&mut self, ctx: Render) {
+        let frm = if let Ok(v) = ctx.frm_db.get(self.fid) {
+            v
+        } else {
+            return;
+        };
+        let rect = ctx.base.unwrap().rect;
+        ctx.canvas.set_clip_rect(rect);
+        ctx.canvas.draw(&frm.first().texture, Point::new(rect.left - self.offset, rect.top),
+            0x10000);
+        ctx.canvas.reset_clip_rect();
+    }
+}
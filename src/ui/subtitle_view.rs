@@ -0,0 +1,51 @@
+use crate::asset::sve::Subtitles;
+use crate::graphics::Point;
+use crate::graphics::color::Rgb15;
+use crate::graphics::font::{self, DrawOptions, FontKey, HorzAlign};
+
+use super::*;
+
+/// Shows movie subtitles centered and word-wrapped to the widget width.
+pub struct SubtitleView {
+    subtitles: Subtitles,
+    font: FontKey,
+    color: Rgb15,
+    frame: u32,
+}
+
+impl SubtitleView {
+    pub fn new(subtitles: Subtitles, font: FontKey, color: Rgb15) -> Self {
+        Self {
+            subtitles,
+            font,
+            color,
+            frame: 0,
+        }
+    }
+
+    /// Sets the movie frame being shown.
+    pub fn set_frame(&mut self, frame: u32) {
+        self.frame = frame;
+    }
+}
+
+impl Widget for SubtitleView {
+    fn render(&mut self, ctx: Render) {
+        let text = if let Some(v) = self.subtitles.at(self.frame) {
+            v
+        } else {
+            return;
+        };
+        let rect = ctx.base.unwrap().rect();
+        ctx.canvas.draw_text(text, Point::new(rect.center().x, rect.top), self.font, self.color,
+            &DrawOptions {
+                horz_align: HorzAlign::Center,
+                horz_overflow: Some(font::Overflow {
+                    size: rect.width(),
+                    boundary: font::OverflowBoundary::Word,
+                    action: font::OverflowAction::Wrap,
+                }),
+                ..Default::default()
+            });
+    }
+}
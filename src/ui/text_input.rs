@@ -0,0 +1,390 @@
+use bstring::bstr;
+use std::cmp;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::asset::message::encoding::Encoding;
+use crate::graphics::color::Rgb15;
+use crate::graphics::font::{DrawOptions, Font, FontKey, Fonts};
+use crate::ui::command::{TextInputCommand, UiCommandData};
+use super::*;
+
+const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Single-line text entry. Enter and Escape are reported as `TextInputCommand`.
+/// The text is kept in the encoding of the fonts so only the characters it can represent are
+/// accepted.
+pub struct TextInput {
+    fonts: Rc<Fonts>,
+    font: FontKey,
+    color: Rgb15,
+    selection_color: Rgb15,
+    /// Text in the fonts encoding, one byte per character.
+    text: Vec<u8>,
+    max_len: Option<usize>,
+    /// Cursor position as the index of the character it's before.
+    cursor: usize,
+    /// The other end of the selection, the cursor being the first.
+    anchor: Option<usize>,
+    /// Text being composed by the input method, shown at the cursor.
+    composition: String,
+    /// Index of the first visible character.
+    scroll: usize,
+    /// Start of the current blink period. The cursor is visible in the first half.
+    blink_start: Option<Instant>,
+    cursor_visible: bool,
+    selecting: bool,
+    command_keys: Vec<Keycode>,
+}
+
+impl TextInput {
+    pub fn new(fonts: Rc<Fonts>, font: FontKey, color: Rgb15, selection_color: Rgb15) -> Self {
+        Self {
+            fonts,
+            font,
+            color,
+            selection_color,
+            text: Vec::new(),
+            max_len: None,
+            cursor: 0,
+            anchor: None,
+            composition: String::new(),
+            scroll: 0,
+            blink_start: None,
+            cursor_visible: true,
+            selecting: false,
+            command_keys: Vec::new(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.encoding().decode(&self.text)
+    }
+
+    /// Replaces the text and moves the cursor to its end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.cursor = 0;
+        self.anchor = None;
+        self.scroll = 0;
+        self.insert(text);
+    }
+
+    pub fn take_text(&mut self) -> String {
+        let r = self.text();
+        self.set_text("");
+        r
+    }
+
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.max_len = max_len;
+        if let Some(max_len) = max_len {
+            self.text.truncate(max_len);
+            self.cursor = cmp::min(self.cursor, max_len);
+            self.anchor = self.anchor.map(|a| cmp::min(a, max_len));
+        }
+    }
+
+    /// Sets the keys that are reported as `TextInputCommand::Key` instead of being handled by
+    /// the input.
+    pub fn set_command_keys(&mut self, keys: Vec<Keycode>) {
+        self.command_keys = keys;
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|&a| a != self.cursor)?;
+        Some(cmp::min(anchor, self.cursor)..cmp::max(anchor, self.cursor))
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.len();
+    }
+
+    /// Replaces the selection (if any) with `text` at the cursor. Control characters,
+    /// characters the fonts encoding can't represent and characters exceeding `max_len` are
+    /// dropped.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        let room = self.max_len.map(|m| m.saturating_sub(self.text.len())).unwrap_or(usize::MAX);
+        let encoding = self.encoding();
+        let text: Vec<u8> = text.chars()
+            .filter(|c| !c.is_control())
+            .filter_map(|c| encoding.encode_char(c))
+            .take(room)
+            .collect();
+        self.text.splice(self.cursor..self.cursor, text.iter().copied());
+        self.cursor += text.len();
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.fonts.encoding()
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        if let Some(sel) = self.selection() {
+            self.text.drain(sel.clone());
+            self.cursor = sel.start;
+            self.anchor = None;
+            true
+        } else {
+            self.anchor = None;
+            false
+        }
+    }
+
+    fn move_cursor(&mut self, pos: usize, select: bool) {
+        if select {
+            if self.anchor.is_none() {
+                self.anchor = Some(self.cursor);
+            }
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = cmp::min(pos, self.text.len());
+    }
+
+    fn prev_word(&self) -> usize {
+        let b = &self.text;
+        let mut i = self.cursor;
+        while i > 0 && b[i - 1] == b' ' {
+            i -= 1;
+        }
+        while i > 0 && b[i - 1] != b' ' {
+            i -= 1;
+        }
+        i
+    }
+
+    fn next_word(&self) -> usize {
+        let b = &self.text;
+        let mut i = self.cursor;
+        while i < b.len() && b[i] != b' ' {
+            i += 1;
+        }
+        while i < b.len() && b[i] == b' ' {
+            i += 1;
+        }
+        i
+    }
+
+    fn font(&self) -> &Font {
+        self.fonts.get(self.font)
+    }
+
+    fn width(&self, range: Range<usize>) -> i32 {
+        let s: &bstr = self.text[range].into();
+        self.font().line_width(s)
+    }
+
+    /// Returns the character index nearest to the screen `x`.
+    fn index_at(&self, rect: Rect, x: i32) -> usize {
+        let x = x - rect.left;
+        for i in self.scroll..self.text.len() {
+            let left = self.width(self.scroll..i);
+            let right = self.width(self.scroll..i + 1);
+            if x < (left + right) / 2 {
+                return i;
+            }
+        }
+        self.text.len()
+    }
+
+    /// Adjusts `scroll` so the cursor is inside the widget.
+    fn scroll_to_cursor(&mut self, width: i32) {
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        }
+        while self.scroll < self.cursor && self.width(self.scroll..self.cursor) >= width {
+            self.scroll += 1;
+        }
+    }
+
+    fn reset_blink(&mut self) {
+        self.blink_start = None;
+        self.cursor_visible = true;
+    }
+
+    fn handle_key(&mut self, keycode: Keycode, keymod: Mod, ctx: &mut HandleEvent)
+        -> Option<TextInputCommand>
+    {
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        if self.command_keys.contains(&keycode) {
+            return Some(TextInputCommand::Key { keycode });
+        }
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => return Some(TextInputCommand::Submit),
+            Keycode::Escape => return Some(TextInputCommand::Cancel),
+            Keycode::Left => {
+                let pos = if ctrl {
+                    self.prev_word()
+                } else if !shift && self.selection().is_some() {
+                    self.selection().unwrap().start
+                } else {
+                    self.cursor.saturating_sub(1)
+                };
+                self.move_cursor(pos, shift);
+            }
+            Keycode::Right => {
+                let pos = if ctrl {
+                    self.next_word()
+                } else if !shift && self.selection().is_some() {
+                    self.selection().unwrap().end
+                } else {
+                    self.cursor + 1
+                };
+                self.move_cursor(pos, shift);
+            }
+            Keycode::Home => self.move_cursor(0, shift),
+            Keycode::End => self.move_cursor(self.text.len(), shift),
+            Keycode::Backspace => if !self.delete_selection() && self.cursor > 0 {
+                let start = if ctrl { self.prev_word() } else { self.cursor - 1 };
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Keycode::Delete => if !self.delete_selection() && self.cursor < self.text.len() {
+                let end = if ctrl { self.next_word() } else { self.cursor + 1 };
+                self.text.drain(self.cursor..end);
+            }
+            Keycode::A if ctrl => self.select_all(),
+            Keycode::C | Keycode::X if ctrl => if let Some(sel) = self.selection() {
+                ctx.clipboard.set_text(&self.encoding().decode(&self.text[sel]));
+                if keycode == Keycode::X {
+                    self.delete_selection();
+                }
+            }
+            Keycode::V if ctrl => if let Some(text) = ctx.clipboard.text() {
+                self.insert(&text);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl Widget for TextInput {
+    fn handle_event(&mut self, mut ctx: HandleEvent) {
+        match ctx.event {
+            Event::KeyDown { keycode: Some(keycode), keymod } => {
+                self.reset_blink();
+                if let Some(cmd) = self.handle_key(keycode, keymod, &mut ctx) {
+                    ctx.out(UiCommandData::TextInput(cmd));
+                }
+            }
+            Event::TextInput { text } => {
+                self.reset_blink();
+                self.composition.clear();
+                self.insert(&text);
+            }
+            Event::TextEditing { text } => {
+                self.composition = text;
+            }
+            Event::MouseDown { pos, button } if button == MouseButton::Left => {
+                self.reset_blink();
+                let i = self.index_at(ctx.base.rect(), pos.x);
+                self.move_cursor(i, false);
+                self.anchor = Some(i);
+                self.selecting = true;
+                ctx.capture();
+            }
+            Event::MouseMove { pos } if self.selecting => {
+                let i = self.index_at(ctx.base.rect(), pos.x);
+                self.cursor = i;
+            }
+            Event::MouseUp { button, .. } if button == MouseButton::Left && self.selecting => {
+                self.selecting = false;
+                ctx.release();
+            }
+            Event::Tick => {
+                let start = *self.blink_start.get_or_insert(ctx.now);
+                let period = 2 * CURSOR_BLINK_INTERVAL.as_millis();
+                self.cursor_visible = (ctx.now - start).as_millis() % period
+                    < CURSOR_BLINK_INTERVAL.as_millis();
+            }
+            _ => {}
+        }
+    }
+
+    fn sync(&mut self, ctx: Sync) {
+        ctx.base.set_focusable(true);
+        self.scroll_to_cursor(ctx.base.rect().width());
+    }
+
+    fn render(&mut self, ctx: Render) {
+        let rect = ctx.base.unwrap().rect();
+        ctx.canvas.set_clip_rect(rect);
+
+        let x_of = |i: usize| rect.left + self.width(self.scroll..cmp::max(i, self.scroll));
+        if let Some(sel) = self.selection() {
+            let left = x_of(sel.start);
+            let right = x_of(sel.end);
+            ctx.canvas.fill_rect(Rect::new(left, rect.top, right, rect.bottom),
+                self.selection_color);
+        }
+
+        let text: &bstr = self.text[self.scroll..self.cursor].into();
+        ctx.canvas.draw_text(text, rect.top_left(), self.font, self.color,
+            &DrawOptions::default());
+        let mut x = x_of(self.cursor);
+        if !self.composition.is_empty() {
+            // Pre-edit text is shown underlined at the cursor.
+            let comp = self.encoding().encode(&self.composition);
+            let comp: &bstr = comp.as_ref();
+            let width = self.font().line_width(comp);
+            ctx.canvas.draw_text(comp, Point::new(x, rect.top), self.font, self.color,
+                &DrawOptions::default());
+            let y = rect.top + self.font().vert_advance();
+            ctx.canvas.fill_rect(Rect::new(x, y - 1, x + width, y), self.color);
+            x += width;
+        }
+        let text: &bstr = self.text[self.cursor..].into();
+        ctx.canvas.draw_text(text, Point::new(x, rect.top), self.font, self.color,
+            &DrawOptions::default());
+
+        if self.cursor_visible && ctx.has_keyboard_focus {
+            let cursor_x = x_of(self.cursor);
+            ctx.canvas.fill_rect(
+                Rect::new(cursor_x, rect.top, cursor_x + 1, rect.top + self.font().vert_advance()),
+                self.color);
+        }
+
+        ctx.canvas.reset_clip_rect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graphics::color::WHITE;
+
+    fn new(encoding: Encoding) -> TextInput {
+        let fonts = Fonts::new();
+        fonts.set_encoding(encoding);
+        TextInput::new(Rc::new(fonts), FontKey::antialiased(1), WHITE, WHITE)
+    }
+
+    #[test]
+    fn insert() {
+        let mut t = new(Encoding::Cp1252);
+        t.insert("Grüße\t€ Привет");
+        assert_eq!(t.text(), "Grüße€ ");
+        assert_eq!(t.cursor(), 7);
+
+        let mut t = new(Encoding::Cp866);
+        t.set_max_len(Some(8));
+        t.insert("Ёж € Привет");
+        assert_eq!(t.text(), "Ёж  Прив");
+        assert_eq!(t.take_text(), "Ёж  Прив");
+        assert_eq!(t.text(), "");
+    }
+}
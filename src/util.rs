@@ -1,5 +1,8 @@
 pub mod array2d;
-pub mod random;
+pub mod json;
+pub mod logging;
+pub mod profile;
+pub mod telemetry;
 #[cfg(test)]
 pub mod test;
 
@@ -0,0 +1,249 @@
+//! Minimal JSON reader for tool sidecar files.
+
+use std::io::{self, Error, ErrorKind};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Object members in the order of appearance.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns value of the object member `key`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        if let Value::Object(members) = self {
+            members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number if it's an integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        if let &Value::Number(v) = self {
+            Some(v as i64).filter(|&i| i as f64 == v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::String(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        if let Value::Array(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn parse(s: &str) -> io::Result<Value> {
+    let mut p = Parser {
+        s: s.as_bytes(),
+        pos: 0,
+    };
+    let r = p.value()?;
+    p.skip_ws();
+    if p.pos < p.s.len() {
+        return Err(p.error("trailing characters"));
+    }
+    Ok(r)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{} at offset {}", msg, self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_ws();
+        self.s.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> io::Result<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn literal(&mut self, lit: &str, value: Value) -> io::Result<Value> {
+        if self.s[self.pos..].starts_with(lit.as_bytes()) {
+            self.pos += lit.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Value> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected member name"));
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(c) if c == b'-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> io::Result<Value> {
+        let start = self.pos;
+        while self.pos < self.s.len()
+            && matches!(self.s[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos]).ok()
+            .and_then(|s| s.parse().ok())
+            .map(Value::Number)
+            .ok_or_else(|| self.error("invalid number"))
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let r = self.s.get(self.pos..self.pos + 4)
+            .and_then(|s| std::str::from_utf8(s).ok())
+            .and_then(|s| u32::from_str_radix(s, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(r)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        // Skip the opening quote.
+        self.pos += 1;
+        let mut r = Vec::new();
+        loop {
+            let c = *self.s.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let c = *self.s.get(self.pos)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let c = match c {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.s[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10)
+                                    + low.wrapping_sub(0xdc00);
+                            }
+                            std::char::from_u32(code)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    let mut buf = [0; 4];
+                    r.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                c => r.push(c),
+            }
+        }
+        String::from_utf8(r).map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_() {
+        let v = parse(r#" {"a": [1, -2.5, true, null], "b": {"c": "x\"é\n"}, "d": []} "#)
+            .unwrap();
+        assert_eq!(v.get("a").unwrap().as_array().unwrap(), &[
+            Value::Number(1.0), Value::Number(-2.5), Value::Bool(true), Value::Null][..]);
+        assert_eq!(v.get("a").unwrap().as_array().unwrap()[0].as_i64(), Some(1));
+        assert_eq!(v.get("a").unwrap().as_array().unwrap()[1].as_i64(), None);
+        assert_eq!(v.get("b").unwrap().get("c").unwrap().as_str(), Some("x\"\u{e9}\n"));
+        assert_eq!(v.get("d"), Some(&Value::Array(vec![])));
+        assert_eq!(v.get("e"), None);
+
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("\"abc").is_err());
+    }
+}
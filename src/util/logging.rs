@@ -0,0 +1,291 @@
+//! Logger setup. Configured in the `[log]` section of `vault13.cfg` in the working directory:
+//!
+//! ```ini
+//! [log]
+//! ; Default level followed by per-module levels. Module paths are relative to the crate.
+//! filter=debug,vm=trace,graphics=warn
+//! ; Log file written in addition to the console. It's rolled over when it grows larger than
+//! ; file_size KiB, file_count rolled over files are kept.
+//! file=vault13.log
+//! file_size=1024
+//! file_count=3
+//! ```
+//!
+//! The `VAULT13_LOG` environment variable overrides the `filter`.
+//!
+//! The last `RECENT_LINES` logged lines are also kept in memory for the crash reports.
+
+use ini::Ini;
+use log::{LevelFilter, Record};
+use log4rs::append::Append;
+use log4rs::append::console::ConsoleAppender;
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
+use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
+use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
+use log4rs::config::{Appender, Logger, Root};
+use log4rs::Config;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+pub const CONFIG_FILE: &str = "vault13.cfg";
+pub const FILTER_ENV: &str = "VAULT13_LOG";
+
+/// Number of the last logged lines kept in memory.
+pub const RECENT_LINES: usize = 200;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+const SECTION: &str = "log";
+const CRATE: &str = "vault13";
+
+/// Level filters parsed from `<level>,<module>=<level>,...`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    pub default: LevelFilter,
+    /// Levels of the modules given by the full module paths.
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut r = Self::default();
+        for entry in s.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            let (module, level) = if let Some(i) = entry.find('=') {
+                (Some(entry[..i].trim()), entry[i + 1..].trim())
+            } else {
+                (None, entry)
+            };
+            let level = LevelFilter::from_str(level)
+                .map_err(|_| format!("invalid log level: {}", level))?;
+            if let Some(module) = module {
+                if module.is_empty() {
+                    return Err(format!("missing module name: {}", entry));
+                }
+                let module = if module == CRATE || module.starts_with("vault13::") {
+                    module.to_owned()
+                } else {
+                    format!("{}::{}", CRATE, module)
+                };
+                r.modules.push((module, level));
+            } else {
+                r.default = level;
+            }
+        }
+        Ok(r)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            default: LevelFilter::Debug,
+            modules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Settings {
+    pub filter: Filter,
+    pub file: Option<PathBuf>,
+    /// Size in bytes the log file is rolled over at.
+    pub file_size: u64,
+    /// Number of the rolled over files to keep.
+    pub file_count: u32,
+}
+
+impl Settings {
+    /// Reads the `[log]` section of `ini`. The `env_filter` takes precedence over the `filter`
+    /// entry.
+    pub fn from_ini(ini: &Ini, env_filter: Option<&str>) -> Result<Self, String> {
+        let mut r = Self::default();
+        let section = ini.section(Some(SECTION));
+        let get = |key: &str| section.and_then(|s| s.get(key)).map(|v| v.trim());
+        if let Some(filter) = env_filter.or_else(|| get("filter")) {
+            r.filter = Filter::parse(filter)?;
+        }
+        r.file = get("file").filter(|v| !v.is_empty()).map(PathBuf::from);
+        if let Some(v) = get("file_size") {
+            let kib: u64 = v.parse().ok().filter(|&v| v > 0)
+                .ok_or_else(|| format!("invalid file_size: {}", v))?;
+            r.file_size = kib * 1024;
+        }
+        if let Some(v) = get("file_count") {
+            r.file_count = v.parse().map_err(|_| format!("invalid file_count: {}", v))?;
+        }
+        Ok(r)
+    }
+
+    /// Reads the settings from `CONFIG_FILE` and the environment. Returns the warnings to log
+    /// once the logger is set up. The defaults are used if the config can't be read.
+    fn load() -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let ini = match File::open(CONFIG_FILE) {
+            Ok(f) => Ini::read_from(&mut BufReader::new(f)).unwrap_or_else(|e| {
+                warnings.push(format!("couldn't parse {}: {}", CONFIG_FILE, e));
+                Ini::new()
+            }),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ini::new(),
+            Err(e) => {
+                warnings.push(format!("couldn't read {}: {}", CONFIG_FILE, e));
+                Ini::new()
+            }
+        };
+        let env_filter = std::env::var(FILTER_ENV).ok();
+        let settings = Self::from_ini(&ini, env_filter.as_deref()).unwrap_or_else(|e| {
+            warnings.push(format!("invalid log settings in {} or {}: {}",
+                CONFIG_FILE, FILTER_ENV, e));
+            Self::default()
+        });
+        (settings, warnings)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            filter: Filter::default(),
+            file: None,
+            file_size: 1024 * 1024,
+            file_count: 3,
+        }
+    }
+}
+
+/// Sets up the console and the optional file loggers.
+pub fn init() -> log4rs::Handle {
+    let (mut settings, mut warnings) = Settings::load();
+    let config = config(&settings).unwrap_or_else(|e| {
+        warnings.push(format!("couldn't set up the log file: {}", e));
+        settings.file = None;
+        config(&settings).unwrap()
+    });
+    let handle = log4rs::init_config(config).unwrap();
+    for w in warnings {
+        log::warn!("{}", w);
+    }
+    if let Some(file) = &settings.file {
+        log::info!("logging to {}", file.display());
+    }
+    handle
+}
+
+/// Returns the last logged lines, oldest first. Returns nothing if the lines are being appended
+/// by the current thread, so it's safe to call from a panic hook.
+pub fn recent_lines() -> Vec<String> {
+    RECENT.try_lock()
+        .map(|lines| lines.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Appender that keeps the last `RECENT_LINES` lines in `RECENT`.
+#[derive(Debug)]
+struct Recent;
+
+impl Append for Recent {
+    fn append(&self, record: &Record) -> anyhow::Result<()> {
+        let line = format!("{} {} - {}", record.level(), record.target(), record.args());
+        if let Ok(mut lines) = RECENT.lock() {
+            if lines.len() == RECENT_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+fn config(settings: &Settings) -> Result<Config, String> {
+    let mut appenders = vec!["stdout", "recent"];
+    let mut config = Config::builder()
+        .appender(Appender::builder().build("stdout",
+            Box::new(ConsoleAppender::builder().build())))
+        .appender(Appender::builder().build("recent", Box::new(Recent)));
+    if let Some(file) = &settings.file {
+        let roller = FixedWindowRoller::builder()
+            .build(&format!("{}.{{}}", file.display()), settings.file_count)
+            .map_err(|e| e.to_string())?;
+        let policy = CompoundPolicy::new(
+            Box::new(SizeTrigger::new(settings.file_size)),
+            Box::new(roller));
+        let appender = RollingFileAppender::builder()
+            .build(file, Box::new(policy))
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+        config = config.appender(Appender::builder().build("file", Box::new(appender)));
+        appenders.push("file");
+    }
+    for (module, level) in &settings.filter.modules {
+        config = config.logger(Logger::builder().build(module, *level));
+    }
+    config.build(Root::builder().appenders(appenders).build(settings.filter.default))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filter_parse() {
+        assert_eq!(Filter::parse("info, vm=trace,graphics::render=WARN,vault13::fs=off"),
+            Ok(Filter {
+                default: LevelFilter::Info,
+                modules: vec![
+                    ("vault13::vm".into(), LevelFilter::Trace),
+                    ("vault13::graphics::render".into(), LevelFilter::Warn),
+                    ("vault13::fs".into(), LevelFilter::Off),
+                ],
+            }));
+        assert_eq!(Filter::parse(""), Ok(Filter::default()));
+        assert!(Filter::parse("vm=loud").is_err());
+        assert!(Filter::parse("=info").is_err());
+    }
+
+    #[test]
+    fn recent_lines_() {
+        for i in 0..RECENT_LINES + 2 {
+            Recent.append(&Record::builder()
+                .args(format_args!("line {}", i))
+                .level(log::Level::Info)
+                .target("vault13::test")
+                .build()).unwrap();
+        }
+        let lines = recent_lines();
+        assert_eq!(lines.len(), RECENT_LINES);
+        assert_eq!(lines[0], "INFO vault13::test - line 2");
+        assert_eq!(lines.last().unwrap(),
+            &format!("INFO vault13::test - line {}", RECENT_LINES + 1));
+    }
+
+    #[test]
+    fn from_ini() {
+        let ini = Ini::load_from_str("\
+            [log]\n\
+            filter=warn,vm=trace\n\
+            file=vault13.log\n\
+            file_size=64\n\
+            file_count=5\n").unwrap();
+        let s = Settings::from_ini(&ini, None).unwrap();
+        assert_eq!(s, Settings {
+            filter: Filter::parse("warn,vm=trace").unwrap(),
+            file: Some("vault13.log".into()),
+            file_size: 64 * 1024,
+            file_count: 5,
+        });
+
+        let s = Settings::from_ini(&ini, Some("error")).unwrap();
+        assert_eq!(s.filter, Filter::parse("error").unwrap());
+
+        assert_eq!(Settings::from_ini(&Ini::new(), None), Ok(Settings::default()));
+
+        let ini = Ini::load_from_str("[log]\nfile_size=0\n").unwrap();
+        assert!(Settings::from_ini(&ini, None).is_err());
+    }
+}
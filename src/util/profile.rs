@@ -0,0 +1,191 @@
+//! Lightweight frame profiler. The code is instrumented with `scope()` guards that accumulate time
+//! spent in the named scope during the frame. Time of the nested scopes is excluded from the
+//! enclosing one so the breakdown adds up to the total. The breakdown is averaged over
+//! `WINDOW` frames to keep the numbers readable on screen.
+//!
+//! The profiler is per thread and does nothing until enabled.
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Number of frames the breakdown is averaged over.
+pub const WINDOW: u32 = 30;
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::default());
+}
+
+/// Guard that records the time since its creation to the scope on drop.
+#[must_use]
+pub struct Scope {
+    active: bool,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.active {
+            PROFILER.with(|p| p.borrow_mut().exit(Instant::now()));
+        }
+    }
+}
+
+/// Starts timing of the `name` scope which lasts until the returned guard is dropped.
+pub fn scope(name: &'static str) -> Scope {
+    let active = PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if p.enabled {
+            p.enter(name, Instant::now());
+        }
+        p.enabled
+    });
+    Scope { active }
+}
+
+pub fn is_enabled() -> bool {
+    PROFILER.with(|p| p.borrow().enabled)
+}
+
+/// Enables or disables the profiling. Disabling discards the collected data.
+pub fn set_enabled(enabled: bool) {
+    PROFILER.with(|p| {
+        let mut p = p.borrow_mut();
+        if !enabled {
+            *p = Profiler::default();
+        }
+        p.enabled = enabled;
+    });
+}
+
+/// Ends the current frame. Must be called outside of any scope.
+pub fn end_frame() {
+    PROFILER.with(|p| p.borrow_mut().end_frame());
+}
+
+/// Returns the average time per frame spent in each scope, in the order the scopes were first
+/// entered. Empty until the first `WINDOW` frames are profiled.
+pub fn breakdown() -> Vec<(&'static str, Duration)> {
+    PROFILER.with(|p| p.borrow().breakdown.clone())
+}
+
+/// Formats the breakdown as lines of scope name and milliseconds.
+pub fn format_breakdown(breakdown: &[(&'static str, Duration)]) -> String {
+    let mut r = String::new();
+    let width = breakdown.iter().map(|(n, _)| n.len()).max().unwrap_or(0).max(5);
+    for &(name, d) in breakdown {
+        writeln!(r, "{:w$} {:6.2} ms", name, millis(d), w = width).unwrap();
+    }
+    let total = breakdown.iter().map(|&(_, d)| d).sum();
+    write!(r, "{:w$} {:6.2} ms", "total", millis(total), w = width).unwrap();
+    r
+}
+
+#[derive(Default)]
+struct Profiler {
+    enabled: bool,
+    stack: Vec<OpenScope>,
+    /// Time accumulated in the current window.
+    totals: Vec<(&'static str, Duration)>,
+    frames: u32,
+    breakdown: Vec<(&'static str, Duration)>,
+}
+
+struct OpenScope {
+    name: &'static str,
+    start: Instant,
+    /// Time spent in the nested scopes.
+    nested: Duration,
+}
+
+impl Profiler {
+    fn enter(&mut self, name: &'static str, now: Instant) {
+        self.stack.push(OpenScope {
+            name,
+            start: now,
+            nested: Duration::from_secs(0),
+        });
+    }
+
+    fn exit(&mut self, now: Instant) {
+        let scope = if let Some(v) = self.stack.pop() {
+            v
+        } else {
+            // The profiler was re-enabled while the scope was open.
+            return;
+        };
+        let elapsed = now - scope.start;
+        if let Some(parent) = self.stack.last_mut() {
+            parent.nested += elapsed;
+        }
+        self.add(scope.name, elapsed.checked_sub(scope.nested).unwrap_or_default());
+    }
+
+    fn add(&mut self, name: &'static str, duration: Duration) {
+        if let Some(e) = self.totals.iter_mut().find(|(n, _)| *n == name) {
+            e.1 += duration;
+        } else {
+            self.totals.push((name, duration));
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        debug_assert!(self.stack.is_empty());
+        self.frames += 1;
+        if self.frames == WINDOW {
+            self.breakdown = self.totals.iter().map(|&(n, d)| (n, d / WINDOW)).collect();
+            for e in &mut self.totals {
+                e.1 = Duration::from_secs(0);
+            }
+            self.frames = 0;
+        }
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nested_scopes_() {
+        let mut p = Profiler {
+            enabled: true,
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        let ms = |v| t0 + Duration::from_millis(v);
+        for _ in 0..WINDOW {
+            p.enter("update", ms(0));
+            p.enter("scripts", ms(1));
+            p.exit(ms(4));
+            p.exit(ms(5));
+            p.enter("render", ms(5));
+            p.exit(ms(7));
+            p.end_frame();
+        }
+        assert_eq!(p.breakdown, vec![
+            ("update", Duration::from_millis(2)),
+            ("scripts", Duration::from_millis(3)),
+            ("render", Duration::from_millis(2)),
+        ]);
+        assert_eq!(format_breakdown(&p.breakdown),
+            "update    2.00 ms\nscripts   3.00 ms\nrender    2.00 ms\ntotal     7.00 ms");
+    }
+
+    #[test]
+    fn disabled() {
+        set_enabled(false);
+        {
+            let _s = scope("update");
+        }
+        end_frame();
+        assert!(breakdown().is_empty());
+        assert!(!is_enabled());
+    }
+}
@@ -0,0 +1,162 @@
+use log::*;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Breakdown of time spent in the individual startup phases.
+#[derive(Debug, Default)]
+pub struct StartupReport {
+    phases: Vec<Phase>,
+}
+
+#[derive(Debug)]
+struct Phase {
+    name: &'static str,
+    duration: Duration,
+}
+
+impl StartupReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` and records its duration under the `name` phase.
+    pub fn measure<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let r = f();
+        self.record(name, start.elapsed());
+        r
+    }
+
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        if let Some(phase) = self.phases.iter_mut().find(|p| p.name == name) {
+            phase.duration += duration;
+        } else {
+            self.phases.push(Phase { name, duration });
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|p| p.duration).sum()
+    }
+
+    pub fn log(&self) {
+        info!("Startup timings:");
+        for phase in &self.phases {
+            info!("  {}: {:.3} ms", phase.name, millis(phase.duration));
+        }
+        info!("  total: {:.3} ms", millis(self.total()));
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut r = String::new();
+        r.push_str("{\"phases\":[");
+        for (i, phase) in self.phases.iter().enumerate() {
+            if i > 0 {
+                r.push(',');
+            }
+            write!(r, "{{\"name\":\"{}\",\"ms\":{:.3}}}", phase.name, millis(phase.duration))
+                .unwrap();
+        }
+        write!(r, "],\"total_ms\":{:.3}}}", millis(self.total())).unwrap();
+        r
+    }
+}
+
+/// Collects frame times and computes their statistics.
+#[derive(Debug, Default)]
+pub struct FrameTimes {
+    frames: Vec<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FrameTimeSummary {
+    pub count: usize,
+    pub avg: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        self.frames.push(duration);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Returns `None` if no frames were recorded.
+    pub fn summary(&self) -> Option<FrameTimeSummary> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let mut sorted = self.frames.clone();
+        sorted.sort();
+        // Nearest-rank percentile.
+        let percentile = |p: usize| sorted[((sorted.len() * p + 99) / 100).max(1) - 1];
+        Some(FrameTimeSummary {
+            count: sorted.len(),
+            avg: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: *sorted.last().unwrap(),
+        })
+    }
+
+    pub fn log(&self) {
+        if let Some(s) = self.summary() {
+            info!("Frame times over {} frames: avg {:.3} ms, p50 {:.3} ms, p95 {:.3} ms, \
+                p99 {:.3} ms, max {:.3} ms",
+                s.count, millis(s.avg), millis(s.p50), millis(s.p95), millis(s.p99),
+                millis(s.max));
+        } else {
+            info!("No frame times recorded");
+        }
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn to_json() {
+        let mut r = StartupReport::new();
+        assert_eq!(r.to_json(), r#"{"phases":[],"total_ms":0.000}"#);
+
+        r.record("palette", Duration::from_micros(1500));
+        r.record("fonts", Duration::from_millis(2));
+        r.record("palette", Duration::from_micros(500));
+        assert_eq!(r.total(), Duration::from_millis(4));
+        assert_eq!(r.to_json(),
+            r#"{"phases":[{"name":"palette","ms":2.000},{"name":"fonts","ms":2.000}],"total_ms":4.000}"#);
+    }
+
+    #[test]
+    fn frame_times() {
+        let mut f = FrameTimes::new();
+        assert_eq!(f.summary(), None);
+        for i in (1..=100).rev() {
+            f.record(Duration::from_millis(i));
+        }
+        assert_eq!(f.summary(), Some(FrameTimeSummary {
+            count: 100,
+            avg: Duration::from_micros(50500),
+            p50: Duration::from_millis(50),
+            p95: Duration::from_millis(95),
+            p99: Duration::from_millis(99),
+            max: Duration::from_millis(100),
+        }));
+    }
+}
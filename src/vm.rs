@@ -84,9 +84,22 @@
 //! whole game session duration (single savegame).
 //!
 //! Stored in `save.dat`. Defined in `vault13.gam`.
+//!
+//! ## sfall global variables
+//!
+//! Instructions: `set_sfall_global`, `get_sfall_global_int`, `get_sfall_global_float`.
+//! Visibility: `game`.
+//! Persistent: no.
+//! Identifier type: `string` or `int`.
+//! Value type: `int` or `float`.
+//!
+//! Extension variables introduced by sfall. Used by mods, often to share sfall array IDs
+//! between programs. Unlike in sfall they're not saved yet.
 
+pub mod debug;
 mod error;
 mod instruction;
+mod sfall;
 mod stack;
 pub mod value;
 
@@ -96,6 +109,7 @@ use enumflags2::{bitflags, BitFlags};
 use log::*;
 use matches::matches;
 use slotmap::{SecondaryMap, SlotMap};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
 use std::io::{self, Cursor};
@@ -105,6 +119,7 @@ use std::time::Duration;
 
 use crate::game::object;
 use crate::game::script::{NewScripts, ScriptKind};
+use crate::util::profile;
 
 use instruction::{instruction_map, Instruction, Opcode};
 use stack::{Stack, StackId};
@@ -179,6 +194,8 @@ impl fmt::Display for PredefinedProc {
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Suspend {
     GsayEnd,
+    /// Suspended by the debugger before executing an instruction.
+    Breakpoint,
 }
 
 /// Result of program invocation.
@@ -195,9 +212,13 @@ pub struct InvocationResult {
 }
 
 impl InvocationResult {
+    /// Panics if the invocation was suspended by the script. Suspending by the debugger is
+    /// allowed anywhere: the caller sees the invocation as finished and the rest of it runs
+    /// when the debugger resumes the program.
     pub fn assert_no_suspend(&self) -> &Self {
-        if let Some(s) = self.suspend {
-            panic!("unexpected suspend: {:?}", s);
+        match self.suspend {
+            None | Some(Suspend::Breakpoint) => {}
+            Some(s) => panic!("unexpected suspend: {:?}", s),
         }
         self
     }
@@ -216,6 +237,12 @@ pub struct Context<'a> {
     /// External variables.
     pub external_vars: &'a mut HashMap<Rc<BString>, Option<Value>>,
 
+    /// Pending timer events of the scripts.
+    pub timer_events: &'a mut crate::game::timer::TimerEvents,
+
+    /// Fixed param of the procedure: info of the timer event for `timed_event_p_proc`.
+    pub fixed_param: i32,
+
     pub self_obj: Option<object::Handle>,
     pub source_obj: Option<object::Handle>,
     pub target_obj: Option<object::Handle>,
@@ -235,6 +262,7 @@ pub struct Context<'a> {
 pub struct VmConfig {
     instructions: HashMap<u16, Instruction>,
     max_stack_len: usize,
+    trace: RefCell<Option<debug::Trace>>,
 }
 
 impl Default for VmConfig {
@@ -242,10 +270,36 @@ impl Default for VmConfig {
         Self {
             instructions: instruction_map(),
             max_stack_len: 2000,
+            trace: RefCell::new(None),
         }
     }
 }
 
+impl VmConfig {
+    fn is_traced(&self, program: &str) -> bool {
+        self.trace.borrow().as_ref().map(|t| t.matches(program)).unwrap_or(false)
+    }
+}
+
+/// Number of values an instruction pops from and pushes to the data stack.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct StackEffect {
+    pops: usize,
+    pushes: usize,
+}
+
+/// Replaces the values popped by a failed instruction with zeroes as the instruction result.
+/// `data_len` is the stack length before the instruction.
+fn recover_data_stack(data_stack: &mut Stack<DataStackId>, effect: StackEffect, data_len: usize)
+    -> Result<()>
+{
+    data_stack.truncate(data_len - effect.pops)?;
+    for _ in 0..effect.pushes {
+        data_stack.push(Value::Int(0))?;
+    }
+    Ok(())
+}
+
 pub struct StringMap {
     map: HashMap<usize, Rc<BString>>,
 }
@@ -351,6 +405,13 @@ impl Program {
         self.procs.by_id.get(id as usize)
     }
 
+    /// Returns procedure whose body contains code position `pos`.
+    pub fn proc_at(&self, pos: usize) -> Option<&Procedure> {
+        self.procs.by_id.iter()
+            .filter(|p| p.body_pos <= pos)
+            .max_by_key(|p| p.body_pos)
+    }
+
     pub fn proc_id(&self, name: &Rc<BString>) -> Option<ProcedureId> {
         self.procs.by_name.get(name).cloned()
     }
@@ -485,10 +546,14 @@ pub struct ProgramState {
     instr_state: instruction::State,
     /// Stack of code positions where suspend requested.
     suspend_stack: Vec<usize>,
+    /// Procedure call stack of the current invocation. Maintained only while tracing or debugging.
+    call_stack: debug::CallStack,
+    /// Debugger attached to this program instance.
+    debugger: Option<Rc<RefCell<debug::Debugger>>>,
 }
 
 impl ProgramState {
-    fn new(program: Rc<Program>) -> Self {
+    fn new(program: Rc<Program>, sfall: Rc<RefCell<sfall::Sfall>>) -> Self {
         let data_stack = Stack::new(program.config.max_stack_len);
         let return_stack = Stack::new(program.config.max_stack_len);
 
@@ -500,8 +565,10 @@ impl ProgramState {
             return_stack,
             base: None,
             global_base: None,
-            instr_state: instruction::State::new(),
+            instr_state: instruction::State::new(sfall),
             suspend_stack: Vec::new(),
+            call_stack: debug::CallStack::new(),
+            debugger: None,
         }
     }
 
@@ -518,26 +585,59 @@ impl ProgramState {
     }
 
     fn run(&mut self, ctx: &mut Context) -> Result<InvocationResult> {
+        let stack_lens = self.stack_lens();
+        self.run0(ctx, stack_lens)
+    }
+
+    /// On error the stacks are truncated to `stack_lens` so the program can be invoked again.
+    fn run0(&mut self, ctx: &mut Context, stack_lens: (usize, usize))
+        -> Result<InvocationResult>
+    {
+        let _profile = profile::scope("scripts");
         self.instr_state.script_overrides = false;
         let suspend = loop {
             match self.step(ctx) {
                 Ok(r) => {
                     if let Some(s) = r {
-                        debug!("suspending at 0x{:x}: {:?}", self.code_pos, s);
+                        debug!("{}: suspending at 0x{:x}: {:?}",
+                            self.log_fields(ctx.self_obj), self.code_pos, s);
                         self.suspend_stack.push(self.code_pos);
                         break Some(s);
                     }
                 }
                 Err(ref e) if matches!(e, Error::Halted) => break None,
-                Err(e) => return Err(e),
+                Err(e) => {
+                    error!("{}: aborting on error: {:?}; data stack top: {:?}",
+                        self.log_fields(ctx.self_obj), e,
+                        self.data_stack.tail(STACK_SNAPSHOT_LEN));
+                    let (data_len, return_len) = stack_lens;
+                    if data_len <= self.data_stack.len() {
+                        self.data_stack.truncate(data_len).unwrap();
+                    }
+                    if return_len <= self.return_stack.len() {
+                        self.return_stack.truncate(return_len).unwrap();
+                    }
+                    self.end_invocation(false);
+                    return Err(e);
+                }
             }
         };
+        self.end_invocation(suspend.is_some());
         Ok(InvocationResult {
             suspend,
             script_overrides: self.instr_state.script_overrides,
         })
     }
 
+    fn end_invocation(&mut self, suspended: bool) {
+        self.instr_state.sfall.borrow_mut().arrays.free_temp();
+        if !suspended {
+            if let Some(debugger) = &self.debugger {
+                debugger.borrow_mut().end_invocation();
+            }
+        }
+    }
+
     pub fn program(&self) -> &Program {
         &self.program
     }
@@ -549,6 +649,9 @@ impl ProgramState {
             .ok_or(Error::BadProcedureId(id))?
             .body_pos;
 
+        let stack_lens = self.stack_lens();
+        self.call_stack.clear();
+
         // setupCallWithReturnVal()
         self.return_stack.push(Value::Int(self.code_pos as i32))?;
         // TODO How important is this? The value varies in different call places.
@@ -561,7 +664,7 @@ impl ProgramState {
 
         self.code_pos = proc_pos;
 
-        self.run(ctx)
+        self.run0(ctx, stack_lens)
     }
 
     pub fn can_resume(&self) -> bool {
@@ -577,12 +680,120 @@ impl ProgramState {
         trace!("code_pos: 0x{:04x}", self.code_pos);
         let opcode_pos = self.code_pos;
         let instr = self.next_instruction()?;
-        self.opcode = Some((instr.opcode(), opcode_pos));
-        let suspend = instr.execute(instruction::Context {
+        let opcode = instr.opcode();
+        self.opcode = Some((opcode, opcode_pos));
+        if self.debugger.is_some() && self.debug_break(opcode, opcode_pos) {
+            // The program is resumed at this instruction.
+            self.code_pos = opcode_pos;
+            return Ok(Some(Suspend::Breakpoint));
+        }
+        if self.program.config.is_traced(&self.program.name) {
+            self.trace(opcode_pos);
+        }
+        let data_len = self.data_stack.len();
+        let r = instr.execute(instruction::Context {
             prg: self,
             ext: ctx,
-        })?;
-        Ok(suspend)
+        });
+        if !opcode.is_game() {
+            return r;
+        }
+        match r {
+            Err(Error::BadValue(bad_value)) => self.recover(bad_value, instr.stack_effect(),
+                data_len, ctx.self_obj),
+            r => r,
+        }
+    }
+
+    /// Attempts to recover from a game instruction that failed because of bad arguments.
+    /// The remaining arguments are discarded and zeroes are pushed as the instruction result.
+    /// This is possible only if the instruction stack effect is known.
+    fn recover(&mut self, bad_value: BadValue, effect: Option<StackEffect>, data_len: usize,
+        self_obj: Option<object::Handle>) -> Result<Option<Suspend>>
+    {
+        let effect = effect
+            .filter(|e| e.pops <= data_len && data_len - e.pops <= self.data_stack.len())
+            .ok_or(Error::BadValue(bad_value))?;
+        warn!("{}: bad argument ({:?}), using default result; data stack top: {:?}",
+            self.log_fields(self_obj), bad_value, self.data_stack.tail(STACK_SNAPSHOT_LEN));
+        recover_data_stack(&mut self.data_stack, effect, data_len)?;
+        Ok(None)
+    }
+
+    /// Logs the instruction at `pos` that is about to be executed.
+    fn trace(&mut self, pos: usize) {
+        if let Some(proc) = debug::proc_id_at(&self.program, pos) {
+            self.call_stack.update(proc);
+        }
+        let instr = debug::decode(self.code(), pos)
+            .map(|i| i.display(&self.program))
+            .unwrap_or_else(|e| format!("0x{:06x}  <{:?}>", pos, e));
+        info!(target: "vault13::vm::trace", "{}: {}: {}; data stack top: {:?}",
+            self.program.name, self.call_stack.display(&self.program), instr,
+            self.data_stack.tail(STACK_SNAPSHOT_LEN));
+    }
+
+    /// Checks the instruction at `pos` against the attached debugger. Returns `true` if the
+    /// program must be suspended before the instruction.
+    fn debug_break(&mut self, opcode: Opcode, pos: usize) -> bool {
+        if let Some(proc) = debug::proc_id_at(&self.program, pos) {
+            self.call_stack.update(proc);
+        }
+        let proc = self.program.procs.by_id.iter()
+            .find(|p| p.body_pos == pos)
+            .map(|p| p.name());
+        self.debugger.as_ref().unwrap().borrow_mut().check(&self.program.name, opcode, proc)
+    }
+
+    /// Returns the state of the program suspended by the attached debugger. `local_vars` are
+    /// the LVARs of the script instance.
+    pub fn debug_snapshot(&self, local_vars: &[i32]) -> Option<debug::Snapshot> {
+        let pause = self.debugger.as_ref()?.borrow().pause()?.clone();
+        let pos = *self.suspend_stack.last()?;
+        let instr = debug::decode(self.code(), pos)
+            .map(|i| i.display(&self.program))
+            .unwrap_or_else(|e| format!("0x{:06x}  <{:?}>", pos, e));
+        let stack_range = |start: Option<usize>, end: usize| -> Vec<Value> {
+            start
+                .filter(|&start| start <= end)
+                .map(|start| (start..end.min(start + debug::MAX_SNAPSHOT_VARS))
+                    .filter_map(|i| self.data_stack.get(i).ok().cloned())
+                    .collect())
+                .unwrap_or_default()
+        };
+        let data_len = self.data_stack.len();
+        Some(debug::Snapshot {
+            program: self.program.name.clone(),
+            pause,
+            instr,
+            call_stack: self.call_stack.display(&self.program),
+            data_stack: self.data_stack.tail(debug::MAX_SNAPSHOT_VARS).to_vec(),
+            return_stack: self.return_stack.tail(debug::MAX_SNAPSHOT_VARS).to_vec(),
+            proc_vars: stack_range(self.base, data_len),
+            program_vars: stack_range(self.global_base, self.base.unwrap_or(data_len)),
+            local_vars: local_vars.to_vec(),
+        })
+    }
+
+    fn stack_lens(&self) -> (usize, usize) {
+        (self.data_stack.len(), self.return_stack.len())
+    }
+
+    /// Describes the current execution point for diagnostics.
+    /// Fields identifying the script context in the log messages:
+    /// `program=<name> proc=<name> opcode=<opcode> pos=<pos> self_obj=<handle>`.
+    fn log_fields(&self, self_obj: Option<object::Handle>) -> String {
+        let mut r = format!("program={}", self.program.name);
+        if let Some((opcode, pos)) = self.opcode {
+            let proc = self.program.proc_at(pos)
+                .map(|p| p.name().display().to_string())
+                .unwrap_or_else(|| "<init>".into());
+            r += &format!(" proc={} opcode={:?} pos=0x{:04x}", proc, opcode, pos);
+        }
+        if let Some(obj) = self_obj {
+            r += &format!(" self_obj={:?}", obj);
+        }
+        r
     }
 
     fn next_instruction(&mut self) -> Result<Instruction> {
@@ -685,6 +896,9 @@ impl ProgramState {
     }
 }
 
+/// Number of topmost data stack values to log for diagnostics.
+const STACK_SNAPSHOT_LEN: usize = 8;
+
 pub struct DataStackId;
 
 impl StackId for DataStackId {
@@ -706,6 +920,8 @@ pub struct Vm {
     config: Rc<VmConfig>,
     program_handles: SlotMap<Handle, ()>,
     program_states: SecondaryMap<Handle, ProgramState>,
+    sfall: Rc<RefCell<sfall::Sfall>>,
+    debugger: Rc<RefCell<debug::Debugger>>,
 }
 
 impl Vm {
@@ -714,6 +930,8 @@ impl Vm {
             config,
             program_handles: SlotMap::with_key(),
             program_states: SecondaryMap::new(),
+            sfall: Rc::new(RefCell::new(sfall::Sfall::new())),
+            debugger: Rc::new(RefCell::new(debug::Debugger::new())),
         }
     }
 
@@ -722,12 +940,41 @@ impl Vm {
     }
 
     pub fn insert(&mut self, program: Rc<Program>) -> Handle {
-        let program_state = ProgramState::new(program);
+        let program_state = ProgramState::new(program, self.sfall.clone());
         let h = self.program_handles.insert(());
         self.program_states.insert(h, program_state);
         h
     }
 
+    /// Enables tracing of the programs selected by `trace` or disables tracing if `None`.
+    pub fn set_trace(&self, trace: Option<debug::Trace>) {
+        *self.config.trace.borrow_mut() = trace;
+    }
+
+    pub fn debugger(&self) -> &RefCell<debug::Debugger> {
+        &self.debugger
+    }
+
+    /// Returns the program instance the debugger is attached to.
+    pub fn debugger_program(&self) -> Option<Handle> {
+        self.program_states.iter()
+            .find(|(_, s)| s.debugger.is_some())
+            .map(|(h, _)| h)
+    }
+
+    /// Attaches the debugger to the `program` instance detaching it from the previous one.
+    pub fn attach_debugger(&mut self, program: Handle) {
+        self.detach_debugger();
+        self.program_state_mut(program).debugger = Some(self.debugger.clone());
+    }
+
+    pub fn detach_debugger(&mut self) {
+        for s in self.program_states.values_mut() {
+            s.debugger = None;
+        }
+        self.debugger.borrow_mut().reset();
+    }
+
     pub fn run(&mut self, program: Handle, ctx: &mut Context) -> Result<InvocationResult> {
         self.program_state_mut(program).run(ctx)
     }
@@ -750,3 +997,35 @@ impl Default for Vm {
         Self::new(Default::default())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use instruction::instructions::INSTRUCTIONS;
+
+    #[test]
+    fn game_instructions_declare_stack_effect() {
+        for instr in &INSTRUCTIONS[..] {
+            assert_eq!(instr.stack_effect().is_some(), instr.opcode().is_game(),
+                "{:?}", instr.opcode());
+        }
+    }
+
+    #[test]
+    fn recover_on_first_call() {
+        // Nothing has been executed with this config yet.
+        let config = VmConfig::default();
+        let effect = config.instructions[&(Opcode::TileDistance as u16)].stack_effect().unwrap();
+        assert_eq!(effect, StackEffect { pops: 2, pushes: 1 });
+
+        let mut stack = Stack::<DataStackId>::new(10);
+        for &v in &[42, 1, 2] {
+            stack.push(Value::Int(v)).unwrap();
+        }
+        let data_len = stack.len();
+        // The instruction failed on its first argument.
+        stack.pop().unwrap();
+        recover_data_stack(&mut stack, effect, data_len).unwrap();
+        assert_eq!(stack.tail(10), &[Value::Int(42), Value::Int(0)]);
+    }
+}
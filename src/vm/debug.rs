@@ -0,0 +1,534 @@
+//! Script debugging facilities: bytecode disassembler and execution tracer.
+//!
+//! Tracing logs every executed instruction of the selected programs together with the inline
+//! operand, the top of the data stack and the procedure call stack. It's enabled with the
+//! `VAULT13_VM_TRACE` environment variable or `vm_trace` key in the `[debug]` section of
+//! `fallout2.cfg`. The value is either `1` to trace all programs or a comma separated list of
+//! program names, e.g. `artemple,obj_dude`.
+//!
+//! Tracing is very verbose and slows down script execution considerably.
+//!
+//! The debugger is attached to a single program instance. When a breakpoint is hit the program
+//! is suspended before the instruction and the invocation returns to the caller with
+//! `Suspend::Breakpoint`. The suspended program can be inspected and then resumed either for a
+//! single instruction or until the next breakpoint.
+
+use byteorder::{BigEndian, ByteOrder};
+use log::*;
+use num_traits::FromPrimitive;
+use std::fmt::{self, Write as FmtWrite};
+use std::io::{self, Write};
+
+use super::*;
+use crate::util::EnumExt;
+
+pub use super::instruction::Opcode;
+
+pub const TRACE_ENV_VAR: &str = "VAULT13_VM_TRACE";
+
+/// Maximum number of stack values and variables of each kind in a debugger snapshot.
+pub(super) const MAX_SNAPSHOT_VARS: usize = 32;
+
+/// Inline operand of an instruction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Int(i32),
+    Float(f32),
+    /// Offset in the string or name table.
+    String(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instr {
+    pub pos: usize,
+    /// Raw opcode value, useful when `opcode` is unknown.
+    pub raw_opcode: u16,
+    pub opcode: Option<Opcode>,
+    pub operand: Option<Operand>,
+}
+
+impl Instr {
+    /// Length in bytes of the encoded instruction.
+    pub fn size(&self) -> usize {
+        Opcode::SIZE + if self.operand.is_some() { 4 } else { 0 }
+    }
+
+    /// Formats the instruction resolving string operands against the `program` tables.
+    pub fn display(&self, program: &Program) -> String {
+        let mut r = format!("0x{:06x}  ", self.pos);
+        if let Some(opcode) = self.opcode {
+            write!(r, "{:?}", opcode).unwrap();
+        } else {
+            write!(r, "<unknown 0x{:04x}>", self.raw_opcode).unwrap();
+        }
+        match self.operand {
+            Some(Operand::Int(v)) => write!(r, " {}", v).unwrap(),
+            Some(Operand::Float(v)) => write!(r, " {}", v).unwrap(),
+            Some(Operand::String(id)) => {
+                // Whether the string table or the name table is referred depends on the
+                // instruction consuming the value. The string table is assumed if possible.
+                if let Some(s) = program.strings.get(id) {
+                    write!(r, " {:?}", s.display().to_string()).unwrap();
+                } else if let Some(s) = program.names.get(id) {
+                    write!(r, " <{}>", s.display()).unwrap();
+                } else {
+                    write!(r, " #{}", id).unwrap();
+                }
+            }
+            None => {}
+        }
+        r
+    }
+}
+
+/// Decodes instruction at `pos` in `code`. Unknown opcodes are decoded as instructions without
+/// operand.
+pub fn decode(code: &[u8], pos: usize) -> Result<Instr> {
+    if pos + Opcode::SIZE > code.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    let raw_opcode = BigEndian::read_u16(&code[pos..]);
+    let opcode = Opcode::from_u16(raw_opcode);
+    let operand_pos = pos + Opcode::SIZE;
+    let read_i32 = || if operand_pos + 4 <= code.len() {
+        Ok(BigEndian::read_i32(&code[operand_pos..]))
+    } else {
+        Err(Error::UnexpectedEof)
+    };
+    let operand = match opcode {
+        Some(Opcode::ConstShort) | Some(Opcode::ConstLong) => Some(Operand::Int(read_i32()?)),
+        Some(Opcode::ConstFloat) => Some(Operand::Float(f32::from_bits(read_i32()? as u32))),
+        Some(Opcode::ConstString) => Some(Operand::String(read_i32()? as usize)),
+        _ => None,
+    };
+    Ok(Instr {
+        pos,
+        raw_opcode,
+        opcode,
+        operand,
+    })
+}
+
+/// Returns code range of the procedure body. The body is assumed to last until the start of
+/// the next procedure body or the end of code.
+pub fn proc_range(program: &Program, proc: ProcedureId) -> Option<std::ops::Range<usize>> {
+    let start = program.proc(proc)?.body_pos;
+    let end = program.procs.by_id.iter()
+        .map(|p| p.body_pos)
+        .filter(|&p| p > start)
+        .min()
+        .unwrap_or_else(|| program.code.len());
+    Some(start..end.min(program.code.len()))
+}
+
+/// Disassembles body of the procedure. Decoding stops at the first truncated instruction.
+pub fn disassemble(program: &Program, proc: ProcedureId) -> Result<Vec<Instr>> {
+    let range = proc_range(program, proc).ok_or(Error::BadProcedureId(proc))?;
+    let mut r = Vec::new();
+    let mut pos = range.start;
+    while pos < range.end {
+        let instr = match decode(&program.code[..range.end], pos) {
+            Ok(v) => v,
+            Err(Error::UnexpectedEof) => break,
+            Err(e) => return Err(e),
+        };
+        pos += instr.size();
+        r.push(instr);
+    }
+    Ok(r)
+}
+
+/// Writes disassembly of all procedures of the `program`.
+pub fn write_disassembly(program: &Program, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "; {}", program.name())?;
+    for (id, proc) in program.procs.by_id.iter().enumerate() {
+        writeln!(out)?;
+        writeln!(out, "procedure {} {}({}) ; flags: {:?}",
+            id, proc.name().display(), proc.arg_count, proc.flags)?;
+        match disassemble(program, id as ProcedureId) {
+            Ok(instrs) => for instr in instrs {
+                writeln!(out, "    {}", instr.display(program))?;
+            }
+            Err(e) => writeln!(out, "    ; error: {:?}", e)?,
+        }
+    }
+    Ok(())
+}
+
+/// Selects programs to trace.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Trace {
+    /// Lowercase program names without extension. `None` means all programs.
+    programs: Option<Vec<String>>,
+}
+
+impl Trace {
+    pub fn all() -> Self {
+        Self {
+            programs: None,
+        }
+    }
+
+    /// Parses the trace config value. Returns `None` if tracing is disabled.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        match s {
+            "" | "0" => None,
+            "1" | "*" => Some(Self::all()),
+            _ => Some(Self {
+                programs: Some(s.split(',')
+                    .map(|s| program_name(s.trim()))
+                    .filter(|s| !s.is_empty())
+                    .collect()),
+            }),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        std::env::var(TRACE_ENV_VAR).ok().and_then(|v| Self::parse(&v))
+    }
+
+    pub fn matches(&self, program: &str) -> bool {
+        if let Some(programs) = &self.programs {
+            let program = program_name(program);
+            programs.iter().any(|p| p == &program)
+        } else {
+            true
+        }
+    }
+}
+
+fn program_name(s: &str) -> String {
+    let s = s.to_ascii_lowercase();
+    match s.strip_suffix(".int") {
+        Some(v) => v.into(),
+        None => s,
+    }
+}
+
+/// Procedure call stack reconstructed from the code positions of executed instructions.
+/// Entering a procedure which is not on the stack is considered a call, entering one that is on
+/// the stack - a return to it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CallStack {
+    procs: Vec<ProcedureId>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn procs(&self) -> &[ProcedureId] {
+        &self.procs
+    }
+
+    pub fn clear(&mut self) {
+        self.procs.clear();
+    }
+
+    /// Updates the stack with the procedure the current instruction belongs to.
+    /// Returns `true` if the stack has changed.
+    pub fn update(&mut self, proc: ProcedureId) -> bool {
+        if self.procs.last() == Some(&proc) {
+            false
+        } else {
+            if let Some(i) = self.procs.iter().position(|&p| p == proc) {
+                self.procs.truncate(i + 1);
+            } else {
+                self.procs.push(proc);
+            }
+            true
+        }
+    }
+
+    pub fn display(&self, program: &Program) -> String {
+        let names: Vec<_> = self.procs.iter()
+            .map(|&id| program.proc(id)
+                .map(|p| p.name().display().to_string())
+                .unwrap_or_else(|| id.to_string()))
+            .collect();
+        if names.is_empty() {
+            "<init>".into()
+        } else {
+            names.join(" > ")
+        }
+    }
+}
+
+/// Returns ID of the procedure whose body contains code position `pos`.
+pub fn proc_id_at(program: &Program, pos: usize) -> Option<ProcedureId> {
+    program.procs.by_id.iter()
+        .enumerate()
+        .filter(|(_, p)| p.body_pos <= pos)
+        .max_by_key(|(_, p)| p.body_pos)
+        .map(|(i, _)| i as ProcedureId)
+}
+
+/// Finds opcode by its name ignoring case and underscores, e.g. `get_array` or `GetArray`.
+pub fn opcode_by_name(name: &str) -> Option<Opcode> {
+    let normalize = |s: &str| -> String {
+        s.chars().filter(|&c| c != '_').map(|c| c.to_ascii_lowercase()).collect()
+    };
+    let name = normalize(name);
+    Opcode::iter().find(|o| normalize(&format!("{:?}", o)) == name)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Breakpoint {
+    /// Breaks on entering the procedure with the name (lowercase).
+    Proc(String),
+    /// Breaks before executing instruction with the opcode.
+    Opcode(Opcode),
+}
+
+impl Breakpoint {
+    pub fn proc(name: &str) -> Self {
+        Breakpoint::Proc(name.to_ascii_lowercase())
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Proc(v) => write!(f, "procedure {}", v),
+            Breakpoint::Opcode(v) => write!(f, "opcode {:?}", v),
+        }
+    }
+}
+
+/// Why the program is suspended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pause {
+    Breakpoint(Breakpoint),
+    /// A single instruction has been executed.
+    Step,
+}
+
+impl fmt::Display for Pause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pause::Breakpoint(v) => write!(f, "{} hit", v),
+            Pause::Step => f.write_str("stepped"),
+        }
+    }
+}
+
+/// State of the suspended program before executing the next instruction.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub program: String,
+    pub pause: Pause,
+    pub instr: String,
+    pub call_stack: String,
+    /// Topmost values of the data stack, the top is the last.
+    pub data_stack: Vec<Value>,
+    /// Topmost values of the return stack, the top is the last.
+    pub return_stack: Vec<Value>,
+    /// Arguments and variables of the current procedure.
+    pub proc_vars: Vec<Value>,
+    /// Program global variables.
+    pub program_vars: Vec<Value>,
+    /// Persistent local variables (LVARs) of the script instance.
+    pub local_vars: Vec<i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// Set while the attached program is suspended.
+    pause: Option<Pause>,
+    /// Whether to suspend before the next instruction regardless of the breakpoints.
+    stepping: bool,
+    /// Whether the program has just been resumed. The instruction it was suspended at isn't
+    /// checked again.
+    resumed: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Returns `false` if the breakpoint already exists.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        if self.breakpoints.contains(&breakpoint) {
+            false
+        } else {
+            self.breakpoints.push(breakpoint);
+            true
+        }
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn pause(&self) -> Option<&Pause> {
+        self.pause.as_ref()
+    }
+
+    /// Returns the breakpoint hit by the instruction. `proc` is the name of the procedure if
+    /// the instruction is the first one in the procedure body.
+    pub fn hit(&self, opcode: Opcode, proc: Option<&bstr>) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|b| match b {
+            Breakpoint::Proc(name) => proc
+                .map(|p| p.as_bytes().eq_ignore_ascii_case(name.as_bytes()))
+                .unwrap_or(false),
+            Breakpoint::Opcode(o) => *o == opcode,
+        })
+    }
+
+    /// Called before executing an instruction. Returns `true` if the program must be suspended
+    /// before the instruction.
+    pub fn check(&mut self, program: &str, opcode: Opcode, proc: Option<&bstr>) -> bool {
+        if std::mem::replace(&mut self.resumed, false) {
+            return false;
+        }
+        let pause = if self.stepping {
+            Pause::Step
+        } else if let Some(b) = self.hit(opcode, proc) {
+            Pause::Breakpoint(b.clone())
+        } else {
+            return false;
+        };
+        debug!("debugger: {} in {}", pause, program);
+        self.pause = Some(pause);
+        true
+    }
+
+    /// Lets the suspended program run. If `step` is `true` the program is suspended again
+    /// before the next instruction.
+    pub fn resume(&mut self, step: bool) {
+        self.pause = None;
+        self.stepping = step;
+        self.resumed = true;
+    }
+
+    /// Called when the program invocation has finished without suspending. Stepping doesn't
+    /// continue into the next invocation.
+    pub fn end_invocation(&mut self) {
+        self.stepping = false;
+        self.resumed = false;
+    }
+
+    /// Forgets the suspended state.
+    pub fn reset(&mut self) {
+        self.pause = None;
+        self.stepping = false;
+        self.resumed = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_() {
+        let code = [
+            0x80, 0x39,
+            0xc0, 0x01, 0xff, 0xff, 0xff, 0xfe,
+            0xa0, 0x01, 0x3f, 0xc0, 0x00, 0x00,
+            0x90, 0x01, 0x00, 0x00, 0x00, 0x06,
+            0x7f, 0xff,
+            0xc0, 0x01, 0x00,
+        ];
+        let instr = |pos, raw_opcode, opcode, operand| Instr { pos, raw_opcode, opcode, operand };
+        let mut pos = 0;
+        let mut next = || {
+            let r = decode(&code, pos);
+            if let Ok(i) = &r {
+                pos += i.size();
+            }
+            r
+        };
+        assert_eq!(next(), Ok(instr(0, 0x8039, Some(Opcode::Add), None)));
+        assert_eq!(next(), Ok(instr(2, 0xc001, Some(Opcode::ConstLong), Some(Operand::Int(-2)))));
+        assert_eq!(next(), Ok(instr(8, 0xa001, Some(Opcode::ConstFloat),
+            Some(Operand::Float(1.5)))));
+        assert_eq!(next(), Ok(instr(14, 0x9001, Some(Opcode::ConstString),
+            Some(Operand::String(6)))));
+        assert_eq!(next(), Ok(instr(20, 0x7fff, None, None)));
+        assert_eq!(next(), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn trace_parse() {
+        assert_eq!(Trace::parse(""), None);
+        assert_eq!(Trace::parse(" 0 "), None);
+        assert_eq!(Trace::parse("1"), Some(Trace::all()));
+
+        let t = Trace::parse("ArTemple, obj_dude.int,").unwrap();
+        assert!(t.matches("artemple"));
+        assert!(t.matches("OBJ_DUDE.INT"));
+        assert!(!t.matches("arcave"));
+        assert!(Trace::all().matches("arcave"));
+    }
+
+    #[test]
+    fn call_stack() {
+        let mut s = CallStack::new();
+        assert!(s.update(1));
+        assert!(!s.update(1));
+        assert!(s.update(2));
+        assert!(s.update(3));
+        assert_eq!(s.procs(), &[1, 2, 3]);
+        assert!(s.update(1));
+        assert_eq!(s.procs(), &[1]);
+        s.clear();
+        assert_eq!(s.procs(), &[] as &[ProcedureId]);
+    }
+
+    #[test]
+    fn opcode_by_name_() {
+        assert_eq!(opcode_by_name("get_array"), Some(Opcode::GetArray));
+        assert_eq!(opcode_by_name("GSAYEND"), Some(Opcode::GsayEnd));
+        assert_eq!(opcode_by_name("foo"), None);
+    }
+
+    #[test]
+    fn debugger() {
+        let mut d = Debugger::new();
+        assert!(d.add_breakpoint(Breakpoint::proc("Talk_P_Proc")));
+        assert!(d.add_breakpoint(Breakpoint::Opcode(Opcode::Add)));
+        assert!(!d.add_breakpoint(Breakpoint::proc("talk_p_proc")));
+
+        let talk: &bstr = b"talk_p_proc"[..].into();
+        let start: &bstr = b"start"[..].into();
+        assert_eq!(d.hit(Opcode::Sub, Some(talk)), Some(&Breakpoint::proc("talk_p_proc")));
+        assert_eq!(d.hit(Opcode::Add, None), Some(&Breakpoint::Opcode(Opcode::Add)));
+        assert_eq!(d.hit(Opcode::Sub, Some(start)), None);
+        assert_eq!(d.hit(Opcode::Sub, None), None);
+
+        assert!(d.check("test", Opcode::Sub, Some(talk)));
+        assert_eq!(d.pause(), Some(&Pause::Breakpoint(Breakpoint::proc("talk_p_proc"))));
+
+        // The instruction the program is resumed at doesn't hit the breakpoint again.
+        d.resume(false);
+        assert_eq!(d.pause(), None);
+        assert!(!d.check("test", Opcode::Sub, Some(talk)));
+        assert!(!d.check("test", Opcode::Sub, None));
+        assert!(d.check("test", Opcode::Add, None));
+
+        d.resume(true);
+        assert!(!d.check("test", Opcode::Add, None));
+        assert!(d.check("test", Opcode::Sub, None));
+        assert_eq!(d.pause(), Some(&Pause::Step));
+        d.resume(true);
+        assert!(!d.check("test", Opcode::Sub, None));
+        d.end_invocation();
+        assert!(!d.check("test", Opcode::Sub, None));
+
+        assert!(d.check("test", Opcode::Add, None));
+        d.reset();
+        assert_eq!(d.pause(), None);
+
+        d.clear_breakpoints();
+        assert!(d.breakpoints().is_empty());
+    }
+}
@@ -3,24 +3,77 @@ mod impls;
 use enum_map_derive::Enum;
 use enum_primitive_derive::Primitive;
 use std::collections::HashMap;
+use std::time::Duration;
 
 use super::*;
+use super::sfall::Sfall;
 use crate::game::object;
+use crate::sequence::Sequence;
 use crate::sequence::chain::Chain;
+use crate::sequence::delay::Delay;
+use crate::sequence::join::Join;
+
+/// Animation steps of an object registered by `reg_anim_*` instructions.
+#[derive(Default)]
+pub struct AnimSteps {
+    /// Each group starts after the previous one is done. Steps of a group run in parallel.
+    groups: Vec<Join>,
+}
+
+impl AnimSteps {
+    /// Appends a step. Positive `delay` is the time in milliseconds to wait after the previous
+    /// step. Negative `delay` runs the step along with the previous step.
+    pub fn push(&mut self, delay: i32, seq: impl 'static + Sequence) {
+        if delay < 0 {
+            if let Some(group) = self.groups.last_mut() {
+                group.push(seq);
+                return;
+            }
+        }
+        let group = if delay > 0 {
+            Join::new().with(Delay::new(Duration::from_millis(delay as u64)).then(seq))
+        } else {
+            Join::new().with(seq)
+        };
+        self.groups.push(group);
+    }
+
+    pub fn into_chain(self) -> Chain {
+        let chain = Chain::new();
+        for group in self.groups {
+            chain.control().cancellable(group);
+        }
+        chain
+    }
+}
 
 pub struct State {
-    pub sequences: SecondaryMap<object::Handle, Chain>,
+    /// Animation steps registered by `reg_anim_*` instructions since `reg_anim_begin()`.
+    /// Started on `reg_anim_end()`.
+    pub sequences: SecondaryMap<object::Handle, AnimSteps>,
+
+    /// Whether `reg_anim_begin()` was called and wasn't yet ended with `reg_anim_end()`.
+    pub reg_anim: bool,
 
     /// Keeps the `script_overrides` flag state.
     /// It is cleared on each invocation of the program initialization code or a procedure.
     pub script_overrides: bool,
+
+    /// Whether `game_loaded()` has been called by the program.
+    pub game_loaded_checked: bool,
+
+    /// sfall extension state shared by all programs.
+    pub sfall: Rc<RefCell<Sfall>>,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(sfall: Rc<RefCell<Sfall>>) -> Self {
         Self {
             sequences: Default::default(),
+            reg_anim: false,
             script_overrides: false,
+            game_loaded_checked: false,
+            sfall,
         }
     }
 }
@@ -373,6 +426,36 @@ pub enum Opcode {
     TerminateCombat             = 0x8153,
     DebugMsg                    = 0x8154,
     CritterStopAttacking        = 0x8155,
+
+    // sfall extensions.
+    GameLoaded                  = 0x8164,
+    SetGlobalScriptRepeat       = 0x819a,
+    SetGlobalScriptType         = 0x819b,
+    SetSfallGlobal              = 0x819d,
+    GetSfallGlobalInt           = 0x819e,
+    GetSfallGlobalFloat         = 0x819f,
+    RegisterHook                = 0x8207,
+    CreateArray                 = 0x8239,
+    SetArray                    = 0x823a,
+    GetArray                    = 0x823b,
+    FreeArray                   = 0x823c,
+    LenArray                    = 0x823d,
+    ResizeArray                 = 0x823e,
+    TempArray                   = 0x823f,
+    FixArray                    = 0x8240,
+    StringSplit                 = 0x8241,
+    Atoi                        = 0x8243,
+    Atof                        = 0x8244,
+    ScanArray                   = 0x8245,
+    Substr                      = 0x824e,
+    Strlen                      = 0x824f,
+    Sprintf                     = 0x8250,
+    Typeof                      = 0x8253,
+    ArrayKey                    = 0x8256,
+    RegAnimLight                = 0x825d,
+    RegAnimTurnTowards          = 0x8260,
+    RegisterHookProc            = 0x8262,
+
     ConstString                 = 0x9001,
     ConstFloat                  = 0xa001,
     ConstLong                   = 0xc001,
@@ -380,13 +463,30 @@ pub enum Opcode {
 
 impl Opcode {
     pub const SIZE: usize = 2;
+
+    /// Whether this is a game instruction as opposed to the core interpreter instruction.
+    /// Game instructions don't affect control flow.
+    pub fn is_game(self) -> bool {
+        let v = self as u16;
+        v >= Opcode::GiveExpPoints as u16 && v < Opcode::ConstString as u16
+    }
 }
 
+/// Defines instruction. Game instructions also declare how many values they pop from and push
+/// to the data stack.
 macro_rules! is {
     ($opcode:expr, $handler:expr) => {
         Instruction {
             opcode: $opcode,
-            handler: $handler
+            handler: $handler,
+            stack_effect: None,
+        }
+    };
+    ($opcode:expr, $pops:expr, $pushes:expr, $handler:expr) => {
+        Instruction {
+            opcode: $opcode,
+            handler: $handler,
+            stack_effect: Some(StackEffect { pops: $pops, pushes: $pushes }),
         }
     };
 }
@@ -395,6 +495,9 @@ macro_rules! i {
     ($opcode:expr, $handler:expr) => {
         is!($opcode, |ctx| { $handler(ctx).map(|_| None) })
     };
+    ($opcode:expr, $pops:expr, $pushes:expr, $handler:expr) => {
+        is!($opcode, $pops, $pushes, |ctx| { $handler(ctx).map(|_| None) })
+    };
 }
 
 pub(in super) mod instructions {
@@ -403,7 +506,7 @@ pub(in super) mod instructions {
     use self::impls::*;
 
     pub static INSTRUCTIONS: [Instruction; enum_len!(Opcode)] = [
-        i!(ActionBeingUsed,             action_being_used),
+        i!(ActionBeingUsed,             0, 1, action_being_used),
         i!(Activateregion,              unimplemented),
         i!(Add,                         add),
         i!(Addbutton,                   unimplemented),
@@ -413,27 +516,30 @@ pub(in super) mod instructions {
         i!(Addbuttonrightproc,          unimplemented),
         i!(Addbuttontext,               unimplemented),
         i!(Addkey,                      unimplemented),
-        i!(AddMultObjsToInven,          add_mult_objs_to_inven),
+        i!(AddMultObjsToInven,          3, 0, add_mult_objs_to_inven),
         i!(Addnamedevent,               unimplemented),
         i!(Addnamedhandler,             unimplemented),
-        i!(AddObjToInven,               add_obj_to_inven),
+        i!(AddObjToInven,               2, 0, add_obj_to_inven),
         i!(Addregion,                   unimplemented),
         i!(Addregionflag,               unimplemented),
         i!(Addregionproc,               unimplemented),
         i!(Addregionrightproc,          unimplemented),
-        i!(AddTimerEvent,               add_timer_event),
+        i!(AddTimerEvent,               3, 0, add_timer_event),
         i!(And,                         and),
-        i!(Anim,                        anim),
-        i!(AnimActionFrame,             unimplemented),
-        i!(AnimateMoveObjToTile,        unimplemented),
-        i!(AnimateStandObj,             unimplemented),
-        i!(AnimateStandReverseObj,      unimplemented),
-        i!(AnimBusy,                    unimplemented),
-        i!(ArtAnim,                     unimplemented),
+        i!(Anim,                        3, 0, anim),
+        i!(AnimActionFrame,             2, 1, unimplemented),
+        i!(AnimateMoveObjToTile,        3, 0, animate_move_obj_to_tile),
+        i!(AnimateStandObj,             1, 0, animate_stand_obj),
+        i!(AnimateStandReverseObj,      1, 0, animate_stand_reverse_obj),
+        i!(AnimBusy,                    1, 1, unimplemented),
+        i!(ArrayKey,                    2, 1, array_key),
+        i!(ArtAnim,                     1, 1, unimplemented),
         i!(AToD,                        atod),
-        i!(Attack,                      unimplemented),
-        i!(Attack80dd,                  unimplemented),
-        i!(AttackSetup,                 unimplemented),
+        i!(Atof,                        1, 1, atof),
+        i!(Atoi,                        1, 1, atoi),
+        i!(Attack,                      8, 0, attack_complex),
+        i!(Attack80dd,                  8, 0, unimplemented),
+        i!(AttackSetup,                 2, 0, attack_setup),
         i!(Bwand,                       bwand),
         i!(Bwnot,                       bwnot),
         i!(Bwor,                        bwor),
@@ -447,67 +553,68 @@ pub(in super) mod instructions {
         i!(CheckArgCount,               unimplemented),
         i!(Checkregion,                 unimplemented),
         i!(Clearnamed,                  unimplemented),
-        i!(CombatDifficulty,            unimplemented),
-        i!(CombatIsInitialized,         combat_is_initialized),
+        i!(CombatDifficulty,            0, 1, unimplemented),
+        i!(CombatIsInitialized,         0, 1, combat_is_initialized),
         i!(ConstFloat,                  const_float),
         i!(ConstLong,                   const_int),
         i!(ConstShort,                  const_int),
         i!(ConstString,                 const_string),
-        i!(CreateObjectSid,             create_object_sid),
+        i!(CreateArray,                 2, 1, create_array),
+        i!(CreateObjectSid,             4, 1, create_object_sid),
         i!(Createwin,                   unimplemented),
         i!(CriticalDone,                noop),
         i!(CriticalDone804b,            noop),
         i!(CriticalStart,               noop),
         i!(CriticalStart804a,           noop),
-        i!(CritterAddTrait,             critter_add_trait),
-        i!(CritterAttemptPlacement,     critter_attempt_placement),
-        i!(CritterDamage,               unimplemented),
-        i!(CritterHeal,                 unimplemented),
-        i!(CritterInjure,               unimplemented),
-        i!(CritterInvenObj,             critter_inven_obj),
-        i!(CritterIsFleeing,            unimplemented),
-        i!(CritterModSkill,             unimplemented),
-        i!(CritterRmTrait,              unimplemented),
-        i!(CritterSetFleeState,         unimplemented),
-        i!(CritterState,                unimplemented),
-        i!(CritterStopAttacking,        unimplemented),
-        i!(CurMapIndex,                 cur_map_index),
-        i!(DaysSinceVisited,            unimplemented),
-        i!(DebugMsg,                    debug_msg),
+        i!(CritterAddTrait,             4, 1, critter_add_trait),
+        i!(CritterAttemptPlacement,     3, 1, critter_attempt_placement),
+        i!(CritterDamage,               3, 0, unimplemented),
+        i!(CritterHeal,                 2, 0, unimplemented),
+        i!(CritterInjure,               2, 0, unimplemented),
+        i!(CritterInvenObj,             2, 1, critter_inven_obj),
+        i!(CritterIsFleeing,            1, 1, unimplemented),
+        i!(CritterModSkill,             3, 1, unimplemented),
+        i!(CritterRmTrait,              4, 1, unimplemented),
+        i!(CritterSetFleeState,         2, 0, unimplemented),
+        i!(CritterState,                1, 1, unimplemented),
+        i!(CritterStopAttacking,        1, 0, critter_stop_attacking),
+        i!(CurMapIndex,                 0, 1, cur_map_index),
+        i!(DaysSinceVisited,            0, 1, unimplemented),
+        i!(DebugMsg,                    1, 0, debug_msg),
         i!(Deletebutton,                unimplemented),
         i!(Deletekey,                   unimplemented),
         i!(Deleteregion,                unimplemented),
         i!(Deletewin,                   unimplemented),
-        i!(DestroyMultObjs,             unimplemented),
-        i!(DestroyObject,               destroy_object),
+        i!(DestroyMultObjs,             2, 1, unimplemented),
+        i!(DestroyObject,               1, 0, destroy_object),
         i!(Detach,                      unimplemented),
-        i!(DialogueReaction,            unimplemented),
-        i!(DialogueSystemEnter,         unimplemented),
-        i!(DifficultyLevel,             unimplemented),
+        i!(DialogueReaction,            1, 0, unimplemented),
+        i!(DialogueSystemEnter,         0, 0, unimplemented),
+        i!(DifficultyLevel,             0, 1, unimplemented),
         i!(Display,                     unimplemented),
         i!(Displaygfx,                  unimplemented),
-        i!(DisplayMsg,                  display_msg),
+        i!(DisplayMsg,                  1, 0, display_msg),
         i!(Displayraw,                  unimplemented),
         i!(Div,                         div),
-        i!(DoCheck,                     do_check),
-        i!(DropObj,                     unimplemented),
+        i!(DoCheck,                     3, 1, do_check),
+        i!(DropObj,                     1, 0, unimplemented),
         i!(DToA,                        dtoa),
-        i!(DudeObj,                     dude_obj),
+        i!(DudeObj,                     0, 1, dude_obj),
         i!(Dump,                        unimplemented),
         i!(Dup,                         dup),
-        i!(Elevation,                   elevation),
-        i!(EndDialogue,                 end_dialogue),
-        i!(EndgameMovie,                unimplemented),
-        i!(EndgameSlideshow,            unimplemented),
+        i!(Elevation,                   1, 1, elevation),
+        i!(EndDialogue,                 0, 0, end_dialogue),
+        i!(EndgameMovie,                0, 0, unimplemented),
+        i!(EndgameSlideshow,            0, 0, endgame_slideshow),
         i!(Equal,                       equal),
         i!(Exec,                        unimplemented),
         i!(Exit,                        unimplemented),
         i!(ExitProg,                    exit_prog),
-        i!(Explosion,                   unimplemented),
+        i!(Explosion,                   3, 0, unimplemented),
         i!(ExportProc,                  unimplemented),
         i!(ExportVar,                   export_var),
-        i!(Fadein,                      unimplemented),
-        i!(Fadeout,                     unimplemented),
+        i!(Fadein,                      fade_in),
+        i!(Fadeout,                     fade_out),
         i!(Fetch,                       fetch),
         i!(FetchExternal,               fetch_external),
         i!(FetchGlobal,                 fetch_global),
@@ -515,105 +622,112 @@ pub(in super) mod instructions {
         i!(Fillrect,                    unimplemented),
         i!(Fillwin,                     unimplemented),
         i!(Fillwin3X3,                  unimplemented),
-        i!(FixedParam,                  unimplemented),
-        i!(FloatMsg,                    float_msg),
+        i!(FixArray,                    1, 0, fix_array),
+        i!(FixedParam,                  0, 1, fixed_param),
+        i!(FloatMsg,                    3, 0, float_msg),
         i!(Floor,                       unimplemented),
         i!(Fork,                        unimplemented),
         i!(Format,                      unimplemented),
-        i!(GameTicks,                   game_ticks),
-        i!(GameTime,                    game_time),
-        i!(GameTimeAdvance,             unimplemented),
-        i!(GameTimeHour,                game_time_hour),
-        i!(GameTimeInSeconds,           game_time_in_seconds),
-        i!(GameUiDisable,               unimplemented),
-        i!(GameUiEnable,                unimplemented),
-        i!(GameUiIsDisabled,            unimplemented),
-        i!(GdialogBarter,               gdialog_barter),
-        i!(GdialogSetBarterMod,         gdialog_set_barter_mod),
-        i!(GetCritterStat,              get_critter_stat),
-        i!(GetDay,                      get_day),
-        i!(GetMonth,                    get_month),
-        i!(GetPcStat,                   unimplemented),
-        i!(GetPoison,                   unimplemented),
-        i!(GfadeIn,                     unimplemented),
-        i!(GfadeOut,                    unimplemented),
-        i!(GiqOption,                   giq_option),
-        i!(GiveExpPoints,               give_exp_points),
-        i!(GlobalVar,                   global_var),
+        i!(FreeArray,                   1, 0, free_array),
+        i!(GameLoaded,                  0, 1, game_loaded),
+        i!(GameTicks,                   1, 1, game_ticks),
+        i!(GameTime,                    0, 1, game_time),
+        i!(GameTimeAdvance,             1, 0, game_time_advance),
+        i!(GameTimeHour,                0, 1, game_time_hour),
+        i!(GameTimeInSeconds,           0, 1, game_time_in_seconds),
+        i!(GameUiDisable,               0, 0, game_ui_disable),
+        i!(GameUiEnable,                0, 0, game_ui_enable),
+        i!(GameUiIsDisabled,            0, 1, game_ui_is_disabled),
+        i!(GdialogBarter,               1, 0, gdialog_barter),
+        i!(GdialogSetBarterMod,         1, 0, gdialog_set_barter_mod),
+        i!(GetArray,                    2, 1, get_array),
+        i!(GetCritterStat,              2, 1, get_critter_stat),
+        i!(GetDay,                      0, 1, get_day),
+        i!(GetMonth,                    0, 1, get_month),
+        i!(GetPcStat,                   1, 1, unimplemented),
+        i!(GetPoison,                   1, 1, unimplemented),
+        i!(GetSfallGlobalFloat,         1, 1, get_sfall_global_float),
+        i!(GetSfallGlobalInt,           1, 1, get_sfall_global_int),
+        i!(GfadeIn,                     1, 0, gfade_in),
+        i!(GfadeOut,                    1, 0, gfade_out),
+        i!(GiqOption,                   5, 0, giq_option),
+        i!(GiveExpPoints,               1, 0, give_exp_points),
+        i!(GlobalVar,                   1, 1, global_var),
         i!(Gotoxy,                      unimplemented),
         i!(Greater,                     greater),
         i!(GreaterEqual,                greater_equal),
-        is!(GsayEnd,                    gsay_end),
-        i!(GsayMessage,                 gsay_message),
-        i!(GsayOption,                  unimplemented),
-        i!(GsayReply,                   gsay_reply),
-        i!(GsayStart,                   gsay_start),
-        i!(HasSkill,                    has_skill),
-        i!(HasTrait,                    has_trait),
+        is!(GsayEnd,                    0, 0, gsay_end),
+        i!(GsayMessage,                 3, 0, gsay_message),
+        i!(GsayOption,                  4, 0, unimplemented),
+        i!(GsayReply,                   2, 0, gsay_reply),
+        i!(GsayStart,                   0, 0, gsay_start),
+        i!(HasSkill,                    2, 1, has_skill),
+        i!(HasTrait,                    3, 1, has_trait),
         i!(Hidemouse,                   unimplemented),
-        i!(HowMuch,                     unimplemented),
+        i!(HowMuch,                     1, 1, unimplemented),
         i!(If,                          if_),
-        i!(InvenCmds,                   unimplemented),
-        i!(InvenUnwield,                unimplemented),
-        i!(IsCritical,                  is_critical),
-        i!(IsSuccess,                   is_success),
-        i!(ItemCapsAdjust,              unimplemented),
-        i!(ItemCapsTotal,               item_caps_total),
-        i!(JamLock,                     jam_lock),
+        i!(InvenCmds,                   3, 1, unimplemented),
+        i!(InvenUnwield,                1, 0, inven_unwield),
+        i!(IsCritical,                  1, 1, is_critical),
+        i!(IsSuccess,                   1, 1, is_success),
+        i!(ItemCapsAdjust,              2, 1, unimplemented),
+        i!(ItemCapsTotal,               1, 1, item_caps_total),
+        i!(JamLock,                     1, 0, jam_lock),
         i!(Jmp,                         jmp),
-        i!(KillCritter,                 unimplemented),
-        i!(KillCritterType,             unimplemented),
+        i!(KillCritter,                 2, 0, kill_critter),
+        i!(KillCritterType,             2, 0, unimplemented),
+        i!(LenArray,                    1, 1, len_array),
         i!(Less,                        less),
         i!(LessEqual,                   less_equal),
-        i!(LoadMap,                     unimplemented),
+        i!(LoadMap,                     2, 0, unimplemented),
         i!(Loadpalettetable,            unimplemented),
-        i!(LocalVar,                    local_var),
+        i!(LocalVar,                    1, 1, local_var),
         i!(LookupStringProc,            unimplemented),
-        i!(MapVar,                      map_var),
-        i!(MarkAreaKnown,               unimplemented),
-        i!(MessageStr,                  message_str),
-        i!(Metarule,                    metarule),
-        i!(Metarule3,                   metarule3),
+        i!(MapVar,                      1, 1, map_var),
+        i!(MarkAreaKnown,               3, 0, unimplemented),
+        i!(MessageStr,                  2, 1, message_str),
+        i!(Metarule,                    2, 1, metarule),
+        i!(Metarule3,                   4, 1, metarule3),
         i!(Mod,                         mod_),
         i!(Mouseshape,                  unimplemented),
-        i!(MoveObjInvenToObj,           move_obj_inven_to_obj),
-        i!(MoveTo,                      move_to),
+        i!(MoveObjInvenToObj,           2, 0, move_obj_inven_to_obj),
+        i!(MoveTo,                      3, 1, move_to),
         i!(Movieflags,                  unimplemented),
         i!(Mul,                         mul),
         i!(Negate,                      negate),
-        i!(Noop80d1,                    noop),
+        i!(Noop80d1,                    0, 0, noop),
         i!(Noop8000,                    noop),
         i!(Not,                         not),
         i!(NotEqual,                    not_equal),
-        i!(ObjArtFid,                   obj_art_fid),
-        i!(ObjBeingUsedWith,            unimplemented),
-        i!(ObjCanHearObj,               unimplemented),
-        i!(ObjCanSeeObj,                obj_can_see_obj),
-        i!(ObjCarryingPidObj,           unimplemented),
-        i!(ObjClose,                    unimplemented),
-        i!(ObjIsCarryingObjPid,         obj_is_carrying_obj_pid),
-        i!(ObjIsLocked,                 obj_is_locked),
-        i!(ObjIsOpen,                   obj_is_open),
-        i!(ObjItemSubtype,              unimplemented),
-        i!(ObjLock,                     obj_lock),
-        i!(ObjName,                     obj_name),
-        i!(ObjOnScreen,                 obj_on_screen),
-        i!(ObjOpen,                     unimplemented),
-        i!(ObjPid,                      obj_pid),
-        i!(ObjSetLightLevel,            unimplemented),
-        i!(ObjType,                     unimplemented),
-        i!(ObjUnlock,                   obj_unlock),
+        i!(ObjArtFid,                   1, 1, obj_art_fid),
+        i!(ObjBeingUsedWith,            0, 1, obj_being_used_with),
+        i!(ObjCanHearObj,               2, 1, unimplemented),
+        i!(ObjCanSeeObj,                2, 1, obj_can_see_obj),
+        i!(ObjCarryingPidObj,           2, 1, unimplemented),
+        i!(ObjClose,                    1, 0, unimplemented),
+        i!(ObjIsCarryingObjPid,         2, 1, obj_is_carrying_obj_pid),
+        i!(ObjIsLocked,                 1, 1, obj_is_locked),
+        i!(ObjIsOpen,                   1, 1, obj_is_open),
+        i!(ObjItemSubtype,              1, 1, unimplemented),
+        i!(ObjLock,                     1, 0, obj_lock),
+        i!(ObjName,                     1, 1, obj_name),
+        i!(ObjOnScreen,                 1, 1, obj_on_screen),
+        i!(ObjOpen,                     1, 0, unimplemented),
+        i!(ObjPid,                      1, 1, obj_pid),
+        i!(ObjSetLightLevel,            3, 0, obj_set_light_level),
+        i!(ObjType,                     1, 1, unimplemented),
+        i!(ObjUnlock,                   1, 0, obj_unlock),
         i!(Or,                          or),
-        i!(OverrideMapStart,            override_map_start),
-        i!(PartyAdd,                    unimplemented),
-        i!(PartyMemberObj,              party_member_obj),
-        i!(PartyRemove,                 unimplemented),
-        i!(PickupObj,                   unimplemented),
-        i!(PlayGmovie,                  unimplemented),
+        i!(OverrideMapStart,            4, 0, override_map_start),
+        i!(PartyAdd,                    1, 0, party_add),
+        i!(PartyMemberObj,              1, 1, party_member_obj),
+        i!(PartyRemove,                 1, 0, party_remove),
+        i!(PickupObj,                   1, 0, unimplemented),
+        i!(PlayGmovie,                  1, 0, play_gmovie),
         i!(Playmovie,                   unimplemented),
         i!(Playmovierect,               unimplemented),
-        i!(PlaySfx,                     unimplemented),
-        i!(Poison,                      unimplemented),
+        i!(PlaySfx,                     1, 0, unimplemented),
+        i!(Poison,                      2, 0, unimplemented),
         i!(Pop,                         pop),
         i!(PopAddress,                  unimplemented),
         i!(PopBase,                     pop_base),
@@ -630,30 +744,35 @@ pub(in super) mod instructions {
         i!(PopToBase,                   pop_to_base),
         i!(Print,                       unimplemented),
         i!(Printrect,                   unimplemented),
-        i!(ProtoData,                   unimplemented),
+        i!(ProtoData,                   2, 1, unimplemented),
         i!(PushBase,                    push_base),
-        i!(RadiationDec,                unimplemented),
-        i!(RadiationInc,                unimplemented),
-        i!(Random,                      random),
-        i!(ReactionInfluence,           unimplemented),
+        i!(RadiationDec,                2, 0, radiation_dec),
+        i!(RadiationInc,                2, 0, radiation_inc),
+        i!(Random,                      2, 1, random),
+        i!(ReactionInfluence,           3, 1, unimplemented),
         i!(Refreshmouse,                unimplemented),
-        i!(RegAnimAnimate,              unimplemented),
-        i!(RegAnimAnimateForever,       reg_anim_animate_forever),
-        i!(RegAnimAnimateReverse,       unimplemented),
-        i!(RegAnimFunc,                 reg_anim_func),
-        i!(RegAnimObjMoveToObj,         unimplemented),
-        i!(RegAnimObjMoveToTile,        unimplemented),
-        i!(RegAnimObjRunToObj,          unimplemented),
-        i!(RegAnimObjRunToTile,         unimplemented),
-        i!(RegAnimPlaySfx,              unimplemented),
+        i!(RegAnimAnimate,              3, 0, reg_anim_animate),
+        i!(RegAnimAnimateForever,       2, 0, reg_anim_animate_forever),
+        i!(RegAnimAnimateReverse,       3, 0, reg_anim_animate_reverse),
+        i!(RegAnimFunc,                 2, 0, reg_anim_func),
+        i!(RegAnimLight,                3, 0, reg_anim_light),
+        i!(RegAnimObjMoveToObj,         3, 0, reg_anim_obj_move_to_obj),
+        i!(RegAnimObjMoveToTile,        3, 0, reg_anim_obj_move_to_tile),
+        i!(RegAnimObjRunToObj,          3, 0, reg_anim_obj_run_to_obj),
+        i!(RegAnimObjRunToTile,         3, 0, reg_anim_obj_run_to_tile),
+        i!(RegAnimPlaySfx,              3, 0, unimplemented),
+        i!(RegAnimTurnTowards,          3, 0, reg_anim_turn_towards),
+        i!(RegisterHook,                1, 0, register_hook),
+        i!(RegisterHookProc,            2, 0, register_hook_proc),
+        i!(ResizeArray,                 2, 0, resize_array),
         i!(Resizewin,                   unimplemented),
-        i!(RmMultObjsFromInven,         unimplemented),
-        i!(RmObjFromInven,              unimplemented),
-        i!(RmTimerEvent,                rm_timer_event),
-        i!(RollDice,                    unimplemented),
-        i!(RollVsSkill,                 roll_vs_skill),
-        i!(RotationToTile,              rotation_to_tile),
-        i!(RunningBurningGuy,           unimplemented),
+        i!(RmMultObjsFromInven,         3, 1, unimplemented),
+        i!(RmObjFromInven,              2, 0, unimplemented),
+        i!(RmTimerEvent,                1, 0, rm_timer_event),
+        i!(RollDice,                    2, 1, unimplemented),
+        i!(RollVsSkill,                 3, 1, roll_vs_skill),
+        i!(RotationToTile,              2, 1, rotation_to_tile),
+        i!(RunningBurningGuy,           0, 1, unimplemented),
         i!(Sayborder,                   unimplemented),
         i!(Sayend,                      unimplemented),
         i!(Saygetlastpos,               unimplemented),
@@ -677,75 +796,86 @@ pub(in super) mod instructions {
         i!(Saystart,                    unimplemented),
         i!(Saystartpos,                 unimplemented),
         i!(Scalewin,                    unimplemented),
-        i!(ScriptAction,                unimplemented),
-        i!(ScriptOverrides,             script_overrides),
-        i!(ScrReturn,                   unimplemented),
+        i!(ScanArray,                   2, 1, scan_array),
+        i!(ScriptAction,                0, 1, unimplemented),
+        i!(ScriptOverrides,             0, 0, script_overrides),
+        i!(ScrReturn,                   1, 0, unimplemented),
         i!(Selectfilelist,              unimplemented),
         i!(Selectwin,                   unimplemented),
-        i!(SelfObj,                     self_obj),
-        i!(SetCritterStat,              unimplemented),
-        i!(SetExitGrids,                unimplemented),
+        i!(SelfObj,                     0, 1, self_obj),
+        i!(SetArray,                    3, 0, set_array),
+        i!(SetCritterStat,              3, 1, unimplemented),
+        i!(SetExitGrids,                5, 0, unimplemented),
         i!(Setfont,                     unimplemented),
         i!(SetGlobal,                   set_global),
         i!(Setglobalmousefunc,          unimplemented),
-        i!(SetGlobalVar,                set_global_var),
+        i!(SetGlobalScriptRepeat,       1, 0, set_global_script_repeat),
+        i!(SetGlobalScriptType,         1, 0, set_global_script_type),
+        i!(SetGlobalVar,                2, 0, set_global_var),
         i!(Sethighlightcolor,           unimplemented),
-        i!(SetLightLevel,               set_light_level),
-        i!(SetLocalVar,                 set_local_var),
-        i!(SetMapMusic,                 unimplemented),
-        i!(SetMapStart,                 unimplemented),
-        i!(SetMapVar,                   set_map_var),
-        i!(SetObjVisibility,            set_obj_visibility),
+        i!(SetLightLevel,               1, 0, set_light_level),
+        i!(SetLocalVar,                 2, 0, set_local_var),
+        i!(SetMapMusic,                 2, 0, unimplemented),
+        i!(SetMapStart,                 4, 0, unimplemented),
+        i!(SetMapVar,                   2, 0, set_map_var),
+        i!(SetObjVisibility,            2, 0, set_obj_visibility),
         i!(Setoneoptpause,              unimplemented),
+        i!(SetSfallGlobal,              2, 0, set_sfall_global),
         i!(Settextcolor,                unimplemented),
         i!(Settextflags,                unimplemented),
-        i!(SfxBuildAmbientName,         unimplemented),
-        i!(SfxBuildCharName,            unimplemented),
-        i!(SfxBuildInterfaceName,       unimplemented),
-        i!(SfxBuildItemName,            unimplemented),
-        i!(SfxBuildOpenName,            unimplemented),
-        i!(SfxBuildSceneryName,         unimplemented),
-        i!(SfxBuildWeaponName,          unimplemented),
+        i!(SfxBuildAmbientName,         1, 1, unimplemented),
+        i!(SfxBuildCharName,            3, 1, sfx_build_char_name),
+        i!(SfxBuildInterfaceName,       1, 1, unimplemented),
+        i!(SfxBuildItemName,            1, 1, unimplemented),
+        i!(SfxBuildOpenName,            2, 1, sfx_build_open_name),
+        i!(SfxBuildSceneryName,         3, 1, unimplemented),
+        i!(SfxBuildWeaponName,          4, 1, sfx_build_weapon_name),
         i!(Showmouse,                   unimplemented),
         i!(Showwin,                     unimplemented),
         i!(Signalnamed,                 unimplemented),
-        i!(SkillContest,                unimplemented),
+        i!(SkillContest,                3, 1, unimplemented),
         i!(Sounddelete,                 unimplemented),
         i!(Soundpause,                  unimplemented),
         i!(Soundplay,                   unimplemented),
         i!(Soundresume,                 unimplemented),
         i!(Soundrewind,                 unimplemented),
         i!(Soundstop,                   unimplemented),
-        i!(SourceObj,                   source_obj),
+        i!(SourceObj,                   0, 1, source_obj),
         i!(Spawn,                       unimplemented),
-        i!(StartGdialog,                start_gdialog),
+        i!(Sprintf,                     2, 1, sprintf),
+        i!(StartGdialog,                5, 0, start_gdialog),
         i!(Stopmovie,                   unimplemented),
         i!(StopProg,                    unimplemented),
         i!(Store,                       store),
         i!(StoreExternal,               store_external),
         i!(StoreGlobal,                 store_global),
+        i!(StringSplit,                 2, 1, string_split),
+        i!(Strlen,                      1, 1, strlen),
         i!(Sub,                         sub),
+        i!(Substr,                      3, 1, substr),
         i!(Swap,                        swap),
         i!(Swapa,                       swapa),
-        i!(TargetObj,                   target_obj),
-        i!(TerminateCombat,             unimplemented),
-        i!(TileContainsObjPid,          tile_contains_pid_obj),
-        i!(TileContainsPidObj,          tile_contains_pid_obj),
-        i!(TileDistance,                tile_distance),
-        i!(TileDistanceObjs,            tile_distance_objs),
-        i!(TileInTileRect,              tile_in_tile_rect),
-        i!(TileIsVisible,               unimplemented),
-        i!(TileNum,                     tile_num),
-        i!(TileNumInDirection,          tile_num_in_direction),
+        i!(TargetObj,                   0, 1, target_obj),
+        i!(TempArray,                   2, 1, temp_array),
+        i!(TerminateCombat,             0, 0, unimplemented),
+        i!(TileContainsObjPid,          3, 1, tile_contains_pid_obj),
+        i!(TileContainsPidObj,          3, 1, tile_contains_pid_obj),
+        i!(TileDistance,                2, 1, tile_distance),
+        i!(TileDistanceObjs,            2, 1, tile_distance_objs),
+        i!(TileInTileRect,              5, 1, tile_in_tile_rect),
+        i!(TileIsVisible,               1, 1, unimplemented),
+        i!(TileNum,                     1, 1, tile_num),
+        i!(TileNumInDirection,          3, 1, tile_num_in_direction),
         i!(Tokenize,                    unimplemented),
-        i!(UseObj,                      unimplemented),
-        i!(UseObjOnObj,                 unimplemented),
-        i!(UsingSkill,                  unimplemented),
+        i!(Typeof,                      1, 1, typeof_),
+        i!(UseObj,                      1, 0, unimplemented),
+        i!(UseObjOnObj,                 2, 0, use_obj_on_obj),
+        i!(UsingSkill,                  2, 1, unimplemented),
         i!(Wait,                        unimplemented),
         i!(While,                       while_),
-        i!(WieldObjCritter,             unimplemented),
-        i!(WmAreaSetPos,                unimplemented),
-        i!(WorldMap,                    unimplemented),
+        i!(WieldObjCritter,             2, 0, wield_obj_critter),
+        i!(WmAreaSetPos,                3, 0, unimplemented),
+        i!(WorldMap,                    0, 0, unimplemented),
     ];
 }
 
@@ -763,6 +893,9 @@ pub type Handler = fn(Context) -> Result<Option<Suspend>>;
 pub struct Instruction {
     opcode: Opcode,
     handler: Handler,
+    /// Data stack effect of a game instruction. Used to keep the stack balanced when the
+    /// instruction fails on bad arguments.
+    stack_effect: Option<StackEffect>,
 }
 
 impl Instruction {
@@ -770,7 +903,73 @@ impl Instruction {
         self.opcode
     }
 
+    pub(super) fn stack_effect(&self) -> Option<StackEffect> {
+        self.stack_effect
+    }
+
     pub fn execute(&self, ctx: Context) -> Result<Option<Suspend>> {
         (self.handler)(ctx)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sequence::{self, test::*};
+
+    #[test]
+    fn reg_anim_opcodes() {
+        let map = instruction_map();
+        for &(opcode, code) in &[
+            (Opcode::RegAnimLight, 0x825d),
+            (Opcode::RegAnimTurnTowards, 0x8260),
+        ] {
+            let instr = map[&code];
+            assert_eq!(instr.opcode(), opcode);
+            assert_eq!(instr.stack_effect(), Some(StackEffect { pops: 3, pushes: 0 }));
+        }
+        assert!(!map.contains_key(&0x8266));
+    }
+
+    #[test]
+    fn anim_steps_delay() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+        let mut steps = AnimSteps::default();
+        steps.push(0, log.step("a", 1));
+        steps.push(100, log.step("b", 1));
+        let mut chain = steps.into_chain();
+
+        assert!(matches!(ctx.update(&mut chain, 0), sequence::Result::Running(_)));
+        assert_eq!(log.take(), vec!["a"]);
+        // "a" is done, the delay starts.
+        ctx.update(&mut chain, 10);
+        ctx.update(&mut chain, 105);
+        assert!(log.take().is_empty());
+        ctx.update(&mut chain, 110);
+        assert_eq!(log.take(), vec!["b"]);
+    }
+
+    #[test]
+    fn anim_steps_parallel() {
+        let mut ctx = MockContext::new();
+        let log = Log::new();
+        let mut steps = AnimSteps::default();
+        // Negative delay of the first step has no previous step to run along with.
+        steps.push(-1, log.step("a", 1));
+        steps.push(0, log.step("b", 2));
+        steps.push(-1, log.step("c", 1));
+        steps.push(0, log.step("d", 1));
+        let mut chain = steps.into_chain();
+
+        ctx.update(&mut chain, 0);
+        assert_eq!(log.take(), vec!["a"]);
+        ctx.update(&mut chain, 10);
+        assert_eq!(log.take(), vec!["b", "c"]);
+        ctx.update(&mut chain, 20);
+        assert_eq!(log.take(), vec!["b"]);
+        // The group is done when both "b" and "c" are done.
+        ctx.update(&mut chain, 30);
+        assert_eq!(log.take(), vec!["d"]);
+    }
+}
@@ -1,9 +1,11 @@
 #[macro_use] mod macros;
 mod core;
 mod game;
+mod sfall;
 
 pub use self::core::*;
 pub use self::game::*;
+pub use self::sfall::*;
 
 use super::Context;
 use super::value::*;
@@ -6,20 +6,38 @@ use num_traits::FromPrimitive;
 use static_assertions::const_assert;
 use std::cmp;
 use std::convert::{TryFrom, TryInto};
+use std::time::Duration;
 
 use super::*;
-use crate::asset::{ExactEntityKind, Flag, Perk, Skill, Stat, Trait};
+use crate::asset::{AttackGroup, CritterAnim, ExactEntityKind, Flag, ItemKind, Perk, Skill, Stat,
+    Trait, WeaponKind};
+use crate::asset::frame::FrameId;
+use crate::asset::mve;
 use crate::asset::proto::ProtoId;
 use crate::asset::script::ProgramId;
+use crate::game::death;
 use crate::game::dialog::Dialog;
+use crate::game::object::{EquipmentSlot, Hand, LightEmitter, PathTo, SetFrame};
+use crate::game::radiation;
+use crate::game::reaction;
 use crate::game::script::ScriptPid;
+use crate::game::team;
+use crate::game::sequence::camera::{PanTo, Shake};
+use crate::game::sequence::frame_anim::{AnimDirection, FrameAnim, FrameAnimOptions};
+use crate::game::sequence::light::SetLight;
+use crate::game::sequence::move_seq::Move;
+use crate::game::sequence::rotate::{Rotate, RotateTo};
+use crate::game::sfx::{self, CharacterSound, OpenAction, WeaponSound};
 use crate::game::world::floating_text;
 use crate::graphics::{EPoint, Point};
 use crate::graphics::color::*;
 use crate::graphics::font::FontKey;
 use crate::graphics::geometry::hex::Direction;
+use crate::sequence::{self, Sequence};
 use crate::sequence::chain::Chain;
-use crate::util::random::{random as rand, RollCheckResult};
+use crate::sequence::event::PushEvent;
+use crate::vm::instruction::AnimSteps;
+use crate::game::rng::{random as rand, RollCheckResult, Stream};
 
 /// This is also known as "trait" by `has_trait()`, `critter_add_trait` etc instructions.
 #[derive(Clone, Copy, Debug, Enum, Eq, PartialEq, Primitive)]
@@ -58,6 +76,19 @@ fn resolve_script_msg(msg: Value, program_id: ProgramId, ctx: &mut Context) -> R
     })
 }
 
+/// Returns name of the speech file of the script message `msg` or empty string if there's no
+/// speech.
+fn script_msg_speech(msg: &Value, program_id: ProgramId, ctx: &mut Context) -> BString {
+    if let &Value::Int(msg_id) = msg {
+        if let Some(msg) = ctx.ext.script_db.messages(program_id).ok()
+            .and_then(|msgs| msgs.get(msg_id).map(|m| m.audio.clone()))
+        {
+            return msg;
+        }
+    }
+    BString::new()
+}
+
 fn to_tile_num(ctx: &Context, p: Point) -> Option<i32> {
     ctx.ext.world.hex_grid().rect_to_linear_inv(p).map(|v| v as i32)
 }
@@ -68,6 +99,57 @@ fn from_tile_num(ctx: &Context, tile_num: i32) -> Option<Point> {
         .map(|v| ctx.ext.world.hex_grid().linear_to_rect_inv(v))
 }
 
+fn is_critter(ctx: &Context, obj: object::Handle) -> bool {
+    ctx.ext.world.objects().get(obj).fid.critter().is_some()
+}
+
+/// Converts the script animation `code` into the animation to request for `obj`. Non-critters
+/// don't have animation sets so `None` is returned for them. Unknown codes are logged and also
+/// result in `None` so the object keeps its current animation instead of failing the script.
+fn critter_anim(ctx: &Context, obj: object::Handle, code: i32) -> Option<CritterAnim> {
+    if !is_critter(ctx, obj) {
+        return None;
+    }
+    let r = CritterAnim::from_i32(code);
+    if r.is_none() {
+        log_error!(ctx.prg, format!("{:?} requested unknown animation {}", obj, code));
+    }
+    r
+}
+
+/// Appends `seq` to the animation chain of `obj` registered in the current
+/// `reg_anim_begin()`..`reg_anim_end()` session.
+fn reg_anim(ctx: &mut Context, obj: object::Handle, delay: i32,
+    seq: impl 'static + Sequence)
+{
+    if !ctx.prg.instr_state.reg_anim {
+        warn!("{:?}: called outside of reg_anim_begin()/reg_anim_end(), ignoring",
+            ctx.prg.opcode.unwrap().0);
+        return;
+    }
+    let seqs = &mut ctx.prg.instr_state.sequences;
+    if !seqs.contains_key(obj) {
+        seqs.insert(obj, AnimSteps::default());
+    }
+    seqs[obj].push(delay, seq);
+}
+
+/// Starts `seq` for `obj` as if registered in its own `reg_anim_begin()`..`reg_anim_end()`
+/// session. If `interrupt` is `false` and `obj` is already animating, does nothing and returns
+/// `false`.
+fn start_anim(ctx: &mut Context, obj: object::Handle, interrupt: bool,
+    seq: impl 'static + Sequence) -> bool
+{
+    if !interrupt && ctx.ext.obj_sequencer.is_running(obj) {
+        debug!("{:?}: object {:?} is busy", ctx.prg.opcode.unwrap().0, obj);
+        return false;
+    }
+    let chain = Chain::new();
+    chain.control().cancellable(seq);
+    ctx.ext.obj_sequencer.replace(obj, chain);
+    true
+}
+
 #[derive(Clone, Copy, Debug, Enum, Eq, Hash, Ord, PartialEq, PartialOrd, Primitive)]
 enum Metarule {
     SignalEndGame   = 13,
@@ -110,6 +192,13 @@ enum Metarule3 {
     AiGetChemUseValue   = 109,
     WmCarIsOutOfGas     = 110,
     MapTargetLoadArea   = 111,
+
+    // Extensions for scripted cutscenes.
+
+    /// Scrolls the camera to center on the tile: `(tile_num, millis, _)`.
+    CameraPanTo         = 200,
+    /// Shakes the camera: `(amplitude, millis, _)`.
+    CameraShake         = 201,
 }
 
 pub fn action_being_used(ctx: Context) -> Result<()> {
@@ -160,22 +249,165 @@ pub fn add_timer_event(ctx: Context) -> Result<()> {
         .ok_or(Error::BadValue(BadValue::Content))?;
 
     log_a3!(ctx.prg, obj, time, info);
-    log_stub!(ctx.prg);
+
+    if let Some((sid, _)) = ctx.ext.world.objects().get(obj).script {
+        let time = ctx.ext.world.game_time.add_decis(cmp::max(time, 0) as u32);
+        ctx.ext.timer_events.add(time, sid, info);
+    } else {
+        log_error!(ctx.prg, "object doesn't have script");
+    }
 
     Ok(())
 }
 
-pub fn anim(ctx: Context) -> Result<()> {
-    let direction = ctx.prg.data_stack.pop()?.into_int()?;
-    let direction = Direction::from_i32(direction)
-        .ok_or(Error::BadValue(BadValue::Content))?;
+const ANIM_SET_ROTATION: i32 = 1000;
+const ANIM_SET_FRAME: i32 = 1010;
 
+pub fn anim(mut ctx: Context) -> Result<()> {
+    let arg = ctx.prg.data_stack.pop()?.into_int()?;
     let anim = ctx.prg.data_stack.pop()?.into_int()?;
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
         .ok_or(Error::BadValue(BadValue::Content))?;
 
-    log_a3!(ctx.prg, obj, anim, direction);
-    log_stub!(ctx.prg);
+    log_a3!(ctx.prg, obj, anim, arg);
+
+    match anim {
+        ANIM_SET_ROTATION => {
+            if let Some(direction) = Direction::from_i32(arg) {
+                ctx.ext.world.objects().get_mut(obj).direction = direction;
+            } else {
+                warn!("anim: invalid rotation {}", arg);
+            }
+        }
+        ANIM_SET_FRAME => {
+            let frame_count = {
+                let obj = ctx.ext.world.objects().get(obj);
+                ctx.ext.world.frm_db().get(obj.fid)
+                    .map(|fs| fs.frame_lists[obj.direction].frames.len())
+                    .unwrap_or(0)
+            };
+            match usize::try_from(arg) {
+                Ok(i) if i < frame_count => {
+                    ctx.ext.world.objects_mut().set_frame(obj, SetFrame::Index(i));
+                }
+                _ => warn!("anim: invalid frame index {}", arg),
+            }
+        }
+        _ => {
+            let anim = critter_anim(&ctx, obj, anim);
+            let direction = if arg == 0 {
+                AnimDirection::Forward
+            } else {
+                AnimDirection::Backward
+            };
+            start_anim(&mut ctx, obj, false, FrameAnim::new(obj,
+                FrameAnimOptions { anim, direction, ..Default::default() }));
+        }
+    }
+
+    Ok(())
+}
+
+const ANIMATE_RUN: i32 = 0x1;
+const ANIMATE_INTERRUPT: i32 = 0x10;
+
+pub fn animate_move_obj_to_tile(mut ctx: Context) -> Result<()> {
+    let flags = ctx.prg.data_stack.pop()?.into_int()?;
+    let tile_num = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a3!(ctx.prg, obj, tile_num, flags);
+
+    let obj = if let Some(v) = obj {
+        v
+    } else {
+        log_error!(ctx.prg, "object is null");
+        return Ok(());
+    };
+    let point = if let Some(v) = from_tile_num(&ctx, tile_num) {
+        v
+    } else {
+        log_error!(ctx.prg, "invalid tile num");
+        return Ok(());
+    };
+    if !is_critter(&ctx, obj) {
+        warn!("animate_move_obj_to_tile: {:?} is not a critter", obj);
+        return Ok(());
+    }
+    let anim = if flags & ANIMATE_RUN != 0 {
+        CritterAnim::Running
+    } else {
+        CritterAnim::Walk
+    };
+    start_anim(&mut ctx, obj, flags & ANIMATE_INTERRUPT != 0, Move::new(obj,
+        PathTo::Point { point, neighbor_if_blocked: true }, anim));
+
+    Ok(())
+}
+
+fn animate_stand_obj0(mut ctx: Context, direction: AnimDirection) -> Result<()> {
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?.or(ctx.ext.self_obj);
+
+    log_a1!(ctx.prg, obj);
+
+    if let Some(obj) = obj {
+        let anim = if is_critter(&ctx, obj) { Some(CritterAnim::Stand) } else { None };
+        start_anim(&mut ctx, obj, false, FrameAnim::new(obj,
+            FrameAnimOptions { anim, direction, ..Default::default() }));
+    }
+
+    Ok(())
+}
+
+pub fn animate_stand_obj(ctx: Context) -> Result<()> {
+    animate_stand_obj0(ctx, AnimDirection::Forward)
+}
+
+pub fn animate_stand_reverse_obj(ctx: Context) -> Result<()> {
+    animate_stand_obj0(ctx, AnimDirection::Backward)
+}
+
+/// Makes `attacker` hostile to `target` and asks the game state to start combat or pull them
+/// into the ongoing one. The event isn't bound to the attacker so its running animation (if
+/// any) is kept.
+fn setup_attack(ctx: &mut Context, attacker: object::Handle, target: object::Handle) {
+    team::set_enemy(ctx.ext.world.objects(), attacker, target);
+    ctx.ext.obj_sequencer.start(PushEvent::new(sequence::Event::AttackSetup { attacker, target }));
+}
+
+// op_attack
+/// `attack_complex(who, called_shot, num_attacks, bonus, min_damage, max_damage,
+/// attacker_results, target_results)`: `self_obj` attacks `who`. Only the hostility part is
+/// implemented, the attack modifiers are ignored.
+pub fn attack_complex(mut ctx: Context) -> Result<()> {
+    for _ in 0..7 {
+        ctx.prg.data_stack.pop()?.into_int()?;
+    }
+    let target = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    let attacker = ctx.ext.self_obj.ok_or(Error::BadValue(BadValue::Content))?;
+
+    log_a2!(ctx.prg, attacker, target);
+
+    if attacker != target {
+        setup_attack(&mut ctx, attacker, target);
+    }
+
+    Ok(())
+}
+
+// op_attack_setup
+pub fn attack_setup(mut ctx: Context) -> Result<()> {
+    let target = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    let attacker = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+
+    log_a2!(ctx.prg, attacker, target);
+
+    if attacker != target {
+        setup_attack(&mut ctx, attacker, target);
+    }
 
     Ok(())
 }
@@ -249,7 +481,45 @@ pub fn critter_add_trait(ctx: Context) -> Result<()> {
     ctx.prg.data_stack.push(r.into())?;
 
     log_a4r1!(ctx.prg, obj, kind, sub_kind, value, r);
-    log_stub!(ctx.prg);
+
+    let obj = if let Some(obj) = obj {
+        obj
+    } else {
+        log_error!(ctx.prg, "object is null");
+        return Ok(());
+    };
+    if Attribute::from_i32(kind) != Some(Attribute::Object) {
+        log_stub!(ctx.prg);
+        return Ok(());
+    }
+    let objects = ctx.ext.world.objects();
+    let enemy = {
+        let mut o = objects.get_mut(obj);
+        let critter = if let Some(c) = o.sub.as_critter_mut() {
+            c
+        } else {
+            log_error!(ctx.prg, "object is not a Critter");
+            return Ok(());
+        };
+        match ObjectTrait::from_i32(sub_kind) {
+            Some(ObjectTrait::AiPacket) => {
+                critter.combat.ai_packet = value;
+                return Ok(());
+            }
+            Some(ObjectTrait::TeamId) => {
+                critter.combat.team_id = value;
+                critter.combat.enemy
+            }
+            _ => {
+                log_stub!(ctx.prg);
+                return Ok(());
+            }
+        }
+    };
+    // The new teammates are not enemies.
+    if enemy.and_then(|e| team::team(objects, e)) == Some(value) {
+        team::clear_enemy(objects, obj);
+    }
     Ok(())
 }
 
@@ -280,6 +550,18 @@ pub fn critter_inven_obj(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_critter_stop_attacking
+pub fn critter_stop_attacking(ctx: Context) -> Result<()> {
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+
+    log_a1!(ctx.prg, obj);
+
+    team::clear_enemy(ctx.ext.world.objects(), obj);
+
+    Ok(())
+}
+
 pub fn cur_map_index(ctx: Context) -> Result<()> {
     let r = ctx.ext.map_id;
     ctx.prg.data_stack.push(r.try_into().unwrap())?;
@@ -287,10 +569,21 @@ pub fn cur_map_index(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_destroy_object
 pub fn destroy_object(ctx: Context) -> Result<()> {
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
     log_a1!(ctx.prg, obj);
-    log_stub!(ctx.prg);
+
+    if let Some(obj) = obj {
+        if obj == ctx.ext.world.objects().dude() {
+            warn!("destroy_object: can't destroy dude");
+        } else if ctx.ext.world.objects().contains(obj) {
+            // The object's script is removed once the procedure returns.
+            ctx.ext.obj_sequencer.cancel(obj);
+            ctx.ext.world.destroy_object(obj);
+        }
+    }
+
     Ok(())
 }
 
@@ -322,7 +615,7 @@ pub fn do_check(ctx: Context) -> Result<()> {
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
 
     let r = if let Some(obj) = obj {
-        let (r, _) = ctx.ext.rpg.roll_check_stat(stat, bonus,
+        let (r, _) = ctx.ext.rpg.roll_check_stat(stat, bonus, Stream::Script,
             &ctx.ext.world.objects().get(obj), ctx.ext.world.objects());
         r
     } else {
@@ -351,6 +644,54 @@ pub fn end_dialogue(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_endgame_slideshow
+pub fn endgame_slideshow(ctx: Context) -> Result<()> {
+    log_!(ctx.prg);
+
+    // The slideshow is played by the game state once the dude's current actions are cancelled.
+    let dude = ctx.ext.world.objects().dude();
+    let seq = Chain::new();
+    seq.control().finalizing(PushEvent::new(sequence::Event::EndgameSlideshow));
+    ctx.ext.obj_sequencer.replace(dude, seq);
+
+    Ok(())
+}
+
+// op_play_gmovie
+pub fn play_gmovie(ctx: Context) -> Result<()> {
+    let movie = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, movie);
+
+    let name = usize::try_from(movie).ok()
+        .and_then(|i| mve::GAME_MOVIES.get(i))
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    // The movie is played by the game state in the next update.
+    ctx.ext.obj_sequencer.start(PushEvent::new(sequence::Event::PlayMovie {
+        name: name.to_string(),
+    }));
+
+    Ok(())
+}
+
+/// Sets the screen brightness. The procedure runs to completion within a single frame so the
+/// fade isn't animated and the `time` argument is ignored.
+fn fade(ctx: Context, brightness: u8) -> Result<()> {
+    let time = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, time);
+    ctx.ext.ui.set_brightness(brightness);
+    Ok(())
+}
+
+// op_fadein
+pub fn fade_in(ctx: Context) -> Result<()> {
+    fade(ctx, 128)
+}
+
+// op_fadeout
+pub fn fade_out(ctx: Context) -> Result<()> {
+    fade(ctx, 0)
+}
+
 #[derive(Clone, Copy, Debug, Enum, Eq, PartialEq, Primitive)]
 #[repr(u32)]
 enum FloatingTextStyle {
@@ -387,10 +728,10 @@ pub fn float_msg(ctx: Context) -> Result<()> {
         use FloatingTextStyle::*;
         let style = style.unwrap_or(Normal);
 
-        if style == Sequential {
+        let style = if style == Sequential {
             // In original it's true sequential, but random is easier to implement and
             // should provide the same features.
-            FloatingTextStyle::from_i32(rand(-1, 12)).unwrap()
+            FloatingTextStyle::from_i32(rand(Stream::Script, -1, 12)).unwrap()
         } else {
             style
         };
@@ -435,6 +776,28 @@ fn floating_text_style() {
     }
 }
 
+// op_game_ui_disable
+pub fn game_ui_disable(ctx: Context) -> Result<()> {
+    ctx.ext.ui.set_input_disabled(true);
+    log_!(ctx.prg);
+    Ok(())
+}
+
+// op_game_ui_enable
+pub fn game_ui_enable(ctx: Context) -> Result<()> {
+    ctx.ext.ui.set_input_disabled(false);
+    log_!(ctx.prg);
+    Ok(())
+}
+
+// op_game_ui_is_disabled
+pub fn game_ui_is_disabled(ctx: Context) -> Result<()> {
+    let r = ctx.ext.ui.is_input_disabled();
+    ctx.prg.data_stack.push(r.into())?;
+    log_r1!(ctx.prg, r);
+    Ok(())
+}
+
 pub fn game_ticks(ctx: Context) -> Result<()> {
     let v = ctx.prg.data_stack.pop()?.into_int()?;
 
@@ -453,6 +816,22 @@ pub fn game_time(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+pub fn game_time_advance(ctx: Context) -> Result<()> {
+    let decis = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, decis);
+    // Due timer events are fired on the next update.
+    let game_time = &mut ctx.ext.world.game_time;
+    *game_time = game_time.add_decis(cmp::max(decis, 0) as u32);
+    Ok(())
+}
+
+pub fn fixed_param(ctx: Context) -> Result<()> {
+    let r = ctx.ext.fixed_param;
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_r1!(ctx.prg, r);
+    Ok(())
+}
+
 pub fn game_time_hour(ctx: Context) -> Result<()> {
     let time = ctx.ext.world.game_time;
     let r = 100 * time.hour() as u32 + time.minute() as u32;
@@ -468,10 +847,10 @@ pub fn game_time_in_seconds(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_gdialog_barter
 pub fn gdialog_barter(ctx: Context) -> Result<()> {
-    let r = 0;
-    ctx.prg.data_stack.push(r.into())?;
-    log_r1!(ctx.prg, r);
+    let barter_mod = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, barter_mod);
     log_stub!(ctx.prg);
     Ok(())
 }
@@ -479,8 +858,13 @@ pub fn gdialog_barter(ctx: Context) -> Result<()> {
 pub fn gdialog_set_barter_mod(ctx: Context) -> Result<()> {
     let val = ctx.prg.data_stack.pop()?.into_int()?;
 
+    if let Some(dialog) = ctx.ext.dialog.as_mut() {
+        dialog.barter_mod = val;
+    } else {
+        log_error!(ctx.prg, "not in dialog");
+    }
+
     log_a1!(ctx.prg, val);
-    log_stub!(ctx.prg);
     Ok(())
 }
 
@@ -488,10 +872,18 @@ pub fn get_critter_stat(ctx: Context) -> Result<()> {
     let stat = Stat::from_i32(ctx.prg.data_stack.pop()?.coerce_into_int()?)
         .ok_or(Error::BadValue(BadValue::Content))?;
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
-    let r = 5;
+    let objs = ctx.ext.world.objects();
+    let r = match obj {
+        Some(h) if objs.get(h).sub.as_critter().is_some() => {
+            ctx.ext.rpg.stat(stat, &objs.get(h), objs)
+        }
+        _ => {
+            log_error!(ctx.prg, "object is null or not a critter");
+            -1
+        }
+    };
     ctx.prg.data_stack.push(Value::Int(r))?;
     log_a2r1!(ctx.prg, obj, stat, r);
-    log_stub!(ctx.prg);
     Ok(())
 }
 
@@ -509,6 +901,16 @@ pub fn get_month(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_gfade_in
+pub fn gfade_in(ctx: Context) -> Result<()> {
+    fade(ctx, 128)
+}
+
+// op_gfade_out
+pub fn gfade_out(ctx: Context) -> Result<()> {
+    fade(ctx, 0)
+}
+
 pub fn giq_option(mut ctx: Context) -> Result<()> {
     // FIXME display reaction with Empathy perk.
     let reaction = ctx.prg.data_stack.pop()?.into_int()?;
@@ -519,8 +921,11 @@ pub fn giq_option(mut ctx: Context) -> Result<()> {
 
     let msg = resolve_script_msg(msg, program_id, &mut ctx)?;
 
-    let iq = 5_i32; // FIXME stat_level_(g_obj_dude, STAT_INT);
-    let smooth_talker = 0; // FIXME perk_level_(g_obj_dude, PERK_smooth_talker);
+    let iq = {
+        let objs = ctx.ext.world.objects();
+        ctx.ext.rpg.stat(Stat::Intelligence, &objs.get(objs.dude()), objs)
+    };
+    let smooth_talker = ctx.ext.rpg.perk(Perk::SmoothTalker, ProtoId::DUDE) as i32;
     let iq = iq + smooth_talker;
 
     // FIXME proc can also be a string
@@ -554,13 +959,14 @@ pub fn gsay_message(mut ctx: Context) -> Result<()> {
     let msg = ctx.prg.data_stack.pop()?;
     let program_id = pop_program_id(&mut ctx)?;
 
+    let speech = script_msg_speech(&msg, program_id, &mut ctx);
     let reply = resolve_script_msg(msg, program_id, &mut ctx)?;
-    let option = &ctx.ext.proto_db.messages().get(650).unwrap().text;
+    let option = ctx.ext.proto_db.messages().get(650).unwrap().text.clone();
 
     assert!(ctx.ext.dialog.is_some());
 
     let dialog = ctx.ext.dialog.as_mut().unwrap();
-    dialog.set_reply(ctx.ext.ui, &*reply);
+    dialog.say(ctx.ext.ui, &reply, &speech);
     dialog.clear_options(ctx.ext.ui);
     dialog.add_option(ctx.ext.ui, option, None);
 
@@ -587,18 +993,12 @@ pub fn gsay_reply(mut ctx: Context) -> Result<()> {
     let reply = ctx.prg.data_stack.pop()?;
     let program_id = pop_program_id(&mut ctx)?;
 
-    let reply_str = match reply {
-        Value::Int(msg_id) => {
-            let msgs = ctx.ext.script_db.messages(program_id).unwrap();
-            Rc::new(msgs.get(msg_id).unwrap().text.clone())
-        },
-        Value::String(s) => s.resolve(ctx.prg.strings())?,
-        _ => return Err(Error::BadValue(BadValue::Content)),
-    };
+    let speech = script_msg_speech(&reply, program_id, &mut ctx);
+    let reply_str = resolve_script_msg(reply, program_id, &mut ctx)?;
 
     assert!(ctx.ext.dialog.is_some());
     let dialog = ctx.ext.dialog.as_mut().unwrap();
-    dialog.set_reply(ctx.ext.ui, &*reply_str);
+    dialog.say(ctx.ext.ui, &reply_str, &speech);
     dialog.clear_options(ctx.ext.ui);
 
     log_a2!(ctx.prg, reply_str, program_id);
@@ -685,6 +1085,23 @@ pub fn has_trait(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_inven_unwield
+pub fn inven_unwield(ctx: Context) -> Result<()> {
+    let critter = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+
+    log_a1!(ctx.prg, critter);
+
+    let hand = ctx.ext.world.objects().get(critter).active_hand();
+    if let Some(hand) = hand {
+        ctx.ext.world.objects_mut().unwield(critter, EquipmentSlot::Hand(hand), ctx.ext.rpg);
+    } else {
+        warn!("{:?}: {:?} is not a critter", ctx.prg.opcode.unwrap().0, critter);
+    }
+
+    Ok(())
+}
+
 pub fn item_caps_total(ctx: Context) -> Result<()> {
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
     let r = 0;
@@ -730,6 +1147,30 @@ pub fn jam_lock(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_kill_critter
+pub fn kill_critter(ctx: Context) -> Result<()> {
+    let death_frame = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+    log_a2!(ctx.prg, obj, death_frame);
+
+    if let Some(obj) = obj {
+        let anim = CritterAnim::from_i32(death_frame)
+            .filter(|a| a.single_frame().is_some())
+            .unwrap_or(CritterAnim::FallBack);
+        let dead = ctx.ext.world.objects().get(obj).sub.as_critter().map(|c| c.is_dead());
+        match dead {
+            Some(false) => death::kill_critter(obj, anim, ctx.ext.world.objects(),
+                ctx.ext.obj_sequencer),
+            Some(true) => {}
+            None => log_error!(ctx.prg, "object is not a critter"),
+        }
+    } else {
+        log_error!(ctx.prg, "object is null");
+    }
+
+    Ok(())
+}
+
 pub fn message_str(mut ctx: Context) -> Result<()> {
     let msg_id = ctx.prg.data_stack.pop()?.into_int()?;
     let program_id = pop_program_id(&mut ctx)?;
@@ -756,14 +1197,31 @@ pub fn metarule(ctx: Context) -> Result<()> {
             SignalEndGame   => 0.into(),
             TestFirstrun    => 1.into(),
             Elevator        => 0.into(),
-            PartyCount      => 0.into(),
+            PartyCount      => {
+                stub = false;
+                // Includes the dude.
+                (ctx.ext.world.party().len() as i32 + 1).into()
+            }
             AreaKnown       => 1.into(),
-            WhoOnDrugs      => 0.into(),
+            WhoOnDrugs      => {
+                stub = false;
+                let obj = arg.coerce_into_object()?
+                    .ok_or(Error::BadValue(BadValue::Content))?;
+                ctx.ext.world.drug_effects.is_on_drugs(obj).into()
+            }
             MapKnown        => 1.into(),
             IsLoadgame      => 0.into(),
             CarCurrentTown  => 0.into(),
-            GiveCarToParty  => 0.into(),
-            GiveCarGas      => 0.into(),
+            GiveCarToParty  => {
+                stub = false;
+                ctx.ext.world.car.give_to_party(ctx.ext.map_id);
+                0.into()
+            }
+            GiveCarGas      => {
+                stub = false;
+                ctx.ext.world.car.add_fuel(arg.coerce_into_int()?);
+                0.into()
+            }
             SkillCheckTag   => {
                 stub = false;
                 ctx.ext.rpg.is_tagged(Skill::from_i32(arg.coerce_into_int()?)
@@ -779,8 +1237,15 @@ pub fn metarule(ctx: Context) -> Result<()> {
             WDamageType     => 0.into(),
             CritterBarters  => 0.into(),
             CritterKillType => 0.into(),
-            CarTrunkSetAnim => 0.into(),
-            CarTrunkGetAnim => 0.into(),
+            CarTrunkSetAnim => {
+                stub = false;
+                ctx.ext.world.car.set_trunk_anim(arg.coerce_into_int()?);
+                0.into()
+            }
+            CarTrunkGetAnim => {
+                stub = false;
+                ctx.ext.world.car.trunk_anim().into()
+            }
         }
     } else {
         error!("unknown Metarule ID {}", id);
@@ -809,9 +1274,19 @@ pub fn metarule3(ctx: Context) -> Result<()> {
 
     use self::Metarule3::*;
     let mr = Metarule3::from_i32(id);
+    let mut stub = true;
     let r = if let Some(mr) = mr {
         match mr {
-            ClrFixedTimedEvents => 0,
+            ClrFixedTimedEvents => {
+                stub = false;
+                let obj = v1.clone().coerce_into_object()?;
+                let info = v2.clone().coerce_into_int()?;
+                let script = obj.and_then(|obj| ctx.ext.world.objects().get(obj).script);
+                if let Some((sid, _)) = script {
+                    ctx.ext.timer_events.remove_info(sid, info);
+                }
+                0
+            }
             MarkSubtile         => 0,
             SetWmMusic          => 0,
             GetKillCount        => 0,
@@ -819,10 +1294,38 @@ pub fn metarule3(ctx: Context) -> Result<()> {
             WmSubtileState      => 0,
             TileGetNextCritter  => 0,
             ArtSetBaseFidNum    => 0,
-            TileSetCenter       => 0,
+            TileSetCenter       => {
+                stub = false;
+                if let Some(pos) = from_tile_num(&ctx, v1.clone().coerce_into_int()?) {
+                    ctx.ext.world.camera_mut().look_at(pos);
+                } else {
+                    log_error!(ctx.prg, "invalid tile number");
+                }
+                0
+            }
             AiGetChemUseValue   => 0,
-            WmCarIsOutOfGas     => 0,
+            WmCarIsOutOfGas     => {
+                stub = false;
+                ctx.ext.world.car.is_out_of_fuel() as i32
+            }
             MapTargetLoadArea   => 0,
+            CameraPanTo         => {
+                stub = false;
+                let millis = millis_arg(v2.clone())?;
+                if let Some(pos) = from_tile_num(&ctx, v1.clone().coerce_into_int()?) {
+                    ctx.ext.obj_sequencer.start(PanTo::new(pos, millis));
+                } else {
+                    log_error!(ctx.prg, "invalid tile number");
+                }
+                0
+            }
+            CameraShake         => {
+                stub = false;
+                let amplitude = v1.clone().coerce_into_int()?;
+                let millis = millis_arg(v2.clone())?;
+                ctx.ext.obj_sequencer.start(Shake::new(amplitude, millis));
+                0
+            }
         }
     } else {
         error!("unknown Metarule3 ID {}", id);
@@ -836,11 +1339,19 @@ pub fn metarule3(ctx: Context) -> Result<()> {
     } else {
         log_a4r1!(ctx.prg, id, v1, v2, v3, ctx.prg.data_stack.top().unwrap());
     }
-    log_stub!(ctx.prg);
+    if stub {
+        log_stub!(ctx.prg);
+    }
 
     Ok(())
 }
 
+fn millis_arg(v: Value) -> Result<Duration> {
+    let v = u64::try_from(v.coerce_into_int()?)
+        .map_err(|_| Error::BadValue(BadValue::Content))?;
+    Ok(Duration::from_millis(v))
+}
+
 pub fn move_obj_inven_to_obj(ctx: Context) -> Result<()> {
     let dst = ctx.prg.data_stack.pop()?.coerce_into_object()?;
     let src = ctx.prg.data_stack.pop()?.coerce_into_object()?;
@@ -853,7 +1364,22 @@ pub fn move_obj_inven_to_obj(ctx: Context) -> Result<()> {
     }
 
     log_a2!(ctx.prg, src, dst);
-    log_stub!(ctx.prg);
+
+    // item_move_all
+    if let (Some(src), Some(dst)) = (src, dst) {
+        let objs = ctx.ext.world.objects_mut();
+        for &slot in &[EquipmentSlot::Armor,
+            EquipmentSlot::Hand(Hand::Left), EquipmentSlot::Hand(Hand::Right)]
+        {
+            objs.unwield(src, slot, ctx.ext.rpg);
+        }
+        let items: Vec<_> = objs.get(src).inventory.items.iter()
+            .map(|i| (i.object, i.count))
+            .collect();
+        for (item, count) in items {
+            objs.move_between_inventories(src, dst, item, count);
+        }
+    }
 
     Ok(())
 }
@@ -885,6 +1411,13 @@ pub fn obj_art_fid(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+/// Returns the item being used on `self_obj` in `use_obj_on_p_proc`.
+pub fn obj_being_used_with(ctx: Context) -> Result<()> {
+    ctx.prg.data_stack.push(ctx.ext.target_obj.into())?;
+    log_r1!(ctx.prg, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
 pub fn obj_can_see_obj(ctx: Context) -> Result<()> {
     let obj2 = ctx.prg.data_stack.pop()?.coerce_into_object()?;
     let obj1 = ctx.prg.data_stack.pop()?.coerce_into_object()?;
@@ -1010,6 +1543,24 @@ pub fn obj_unlock(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+pub fn obj_set_light_level(ctx: Context) -> Result<()> {
+    let radius = ctx.prg.data_stack.pop()?.into_int()?;
+    let intensity = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a3!(ctx.prg, obj, intensity, radius);
+
+    if let Some(obj) = obj {
+        let intensity = cmp::max(cmp::min(intensity, 100), 0) as u32 * 0x10000 / 100;
+        let radius = cmp::max(radius, 0) as u32;
+        ctx.ext.world.objects_mut().set_light_emitter(obj, LightEmitter { intensity, radius });
+    } else {
+        log_error!(ctx.prg, "object is null");
+    }
+
+    Ok(())
+}
+
 pub fn override_map_start(ctx: Context) -> Result<()> {
     let direction = ctx.prg.data_stack.pop()?.into_int()?;
     let direction = Direction::from_i32(direction)
@@ -1035,22 +1586,85 @@ pub fn override_map_start(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// op_party_add
+pub fn party_add(ctx: Context) -> Result<()> {
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+    log_a1!(ctx.prg, obj);
+
+    if let Some(obj) = obj {
+        if !ctx.ext.world.add_party_member(obj) {
+            debug!("party_add: {:?} is already in party", obj);
+        }
+    }
+
+    Ok(())
+}
+
+// op_party_member_obj
 pub fn party_member_obj(ctx: Context) -> Result<()> {
     let pid = ctx.prg.data_stack.pop()?.into_int()?;
-    let r = Value::Object(None);
-    ctx.prg.data_stack.push(r)?;
+
+    let world = &ctx.ext.world;
+    let objs = world.objects();
+    let r = ProtoId::from_packed(pid as u32)
+        .and_then(|pid| std::iter::once(objs.dude())
+            .chain(world.party().iter().copied())
+            .find(|&obj| objs.get(obj).proto_id() == Some(pid)));
+    ctx.prg.data_stack.push(Value::Object(r))?;
+
     log_a1r1!(ctx.prg, pid, ctx.prg.data_stack.top().unwrap());
-    log_stub!(ctx.prg);
+
+    Ok(())
+}
+
+// op_party_remove
+pub fn party_remove(ctx: Context) -> Result<()> {
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+    log_a1!(ctx.prg, obj);
+
+    if let Some(obj) = obj {
+        if !ctx.ext.world.remove_party_member(obj) {
+            debug!("party_remove: {:?} is not in party", obj);
+        }
+    }
+
     Ok(())
 }
 
+fn adjust_radiation(ctx: Context, sign: i32) -> Result<()> {
+    use crate::ui::message_panel::MessagePanel;
+
+    let amount = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    log_a2!(ctx.prg, obj, amount);
+
+    let msgs = radiation::expose(obj, sign * amount, ctx.ext.world.objects(), ctx.ext.rpg);
+    let mut mp = ctx.ext.ui.widget_mut::<MessagePanel>(ctx.ext.message_panel);
+    for msg in msgs {
+        mp.push_message(BString::concat(&[crate::asset::message::BULLET_STR, msg.as_bytes()]));
+    }
+
+    Ok(())
+}
+
+// op_radiation_dec
+pub fn radiation_dec(ctx: Context) -> Result<()> {
+    adjust_radiation(ctx, -1)
+}
+
+// op_radiation_inc
+pub fn radiation_inc(ctx: Context) -> Result<()> {
+    adjust_radiation(ctx, 1)
+}
+
 pub fn random(ctx: Context) -> Result<()> {
     let to_incl = ctx.prg.data_stack.pop()?.into_int()?;
     let from_incl = ctx.prg.data_stack.pop()?.into_int()?;
 
     // TODO check if vcr_status() == 2 condition in orginal is important.
 
-    let r = rand(from_incl, to_incl);
+    let r = rand(Stream::Script, from_incl, to_incl);
     ctx.prg.data_stack.push(r.into())?;
 
     log_a2r1!(ctx.prg, from_incl, to_incl, r);
@@ -1058,26 +1672,47 @@ pub fn random(ctx: Context) -> Result<()> {
     Ok(())
 }
 
-pub fn reg_anim_animate_forever(ctx: Context) -> Result<()> {
-    use crate::asset::CritterAnim;
-    use crate::game::sequence::frame_anim::*;
+fn reg_anim_animate0(mut ctx: Context, direction: AnimDirection) -> Result<()> {
+    let delay = ctx.prg.data_stack.pop()?.into_int()?;
+    let anim_code = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
 
-    let critter_anim = CritterAnim::from_i32(ctx.prg.data_stack.pop()?.into_int()?)
-        .ok_or(Error::BadValue(BadValue::Content))?;
+    log_a3!(ctx.prg, obj, anim_code, delay);
+
+    if let Some(obj) = obj {
+        let anim = critter_anim(&ctx, obj, anim_code);
+        reg_anim(&mut ctx, obj, delay, FrameAnim::new(obj,
+            FrameAnimOptions { anim, direction, ..Default::default() }));
+    }
+
+    Ok(())
+}
+
+pub fn reg_anim_animate(ctx: Context) -> Result<()> {
+    reg_anim_animate0(ctx, AnimDirection::Forward)
+}
+
+pub fn reg_anim_animate_reverse(ctx: Context) -> Result<()> {
+    reg_anim_animate0(ctx, AnimDirection::Backward)
+}
+
+pub fn reg_anim_animate_forever(ctx: Context) -> Result<()> {
+    let anim_code = ctx.prg.data_stack.pop()?.into_int()?;
     let obj = ctx.prg.data_stack.pop()?.into_object()?;
     if let Some(obj) = obj {
+        let anim = critter_anim(&ctx, obj, anim_code);
         if !ctx.ext.obj_sequencer.is_running(obj) &&
             !ctx.prg.instr_state.sequences.contains_key(obj)
         {
-            let seq = Chain::new();
-            seq.control().cancellable(FrameAnim::new(obj,
-                FrameAnimOptions { anim: Some(critter_anim), wrap: true, ..Default::default() }));
-            ctx.prg.instr_state.sequences.insert(obj, seq);
+            let mut steps = AnimSteps::default();
+            steps.push(0, FrameAnim::new(obj,
+                FrameAnimOptions { anim, wrap: true, ..Default::default() }));
+            ctx.prg.instr_state.sequences.insert(obj, steps);
         } else {
             debug!("reg_anim_animate_forever: object {:?} already has running sequence", obj);
         }
     }
-    log_a2!(ctx.prg, obj, critter_anim);
+    log_a2!(ctx.prg, obj, anim_code);
     Ok(())
 }
 
@@ -1089,16 +1724,18 @@ pub fn reg_anim_func(ctx: Context) -> Result<()> {
     match op {
         RegAnimFuncOp::Begin => {
             let flags = arg.into_int()?;
-            if !seqs.is_empty() {
+            if ctx.prg.instr_state.reg_anim || !seqs.is_empty() {
                 warn!("RegAnimFunc(Begin, ...): previous session wasn't ended properly with RegAnimFunc(End)");
             }
             seqs.clear();
+            ctx.prg.instr_state.reg_anim = true;
             log_a2!(ctx.prg, op, flags);
         }
         RegAnimFuncOp::End => {
-            for (objh, seq) in seqs.drain() {
-                ctx.ext.obj_sequencer.replace(objh, seq);
+            for (objh, steps) in seqs.drain() {
+                ctx.ext.obj_sequencer.replace(objh, steps.into_chain());
             }
+            ctx.prg.instr_state.reg_anim = false;
             log_a2!(ctx.prg, op, arg);
         }
         RegAnimFuncOp::Clear => {
@@ -1112,12 +1749,92 @@ pub fn reg_anim_func(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+// sfall
+pub fn reg_anim_light(mut ctx: Context) -> Result<()> {
+    let delay = ctx.prg.data_stack.pop()?.into_int()?;
+    let radius = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a3!(ctx.prg, obj, radius, delay);
+
+    if let Some(obj) = obj {
+        let light = LightEmitter {
+            radius: cmp::max(cmp::min(radius, 8), 0) as u32,
+            ..ctx.ext.world.objects().get(obj).light_emitter()
+        };
+        reg_anim(&mut ctx, obj, delay, SetLight::new(obj, light));
+    }
+
+    Ok(())
+}
+
+fn reg_anim_obj_move0(mut ctx: Context, anim: CritterAnim) -> Result<()> {
+    let delay = ctx.prg.data_stack.pop()?.into_int()?;
+    let to = ctx.prg.data_stack.pop()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a3!(ctx.prg, obj, to, delay);
+
+    let to = match to {
+        Value::Int(tile_num) => from_tile_num(&ctx, tile_num)
+            .map(|point| PathTo::Point { point, neighbor_if_blocked: true }),
+        to => to.coerce_into_object()?.map(PathTo::Object),
+    };
+    if let (Some(obj), Some(to)) = (obj, to) {
+        if is_critter(&ctx, obj) {
+            reg_anim(&mut ctx, obj, delay, Move::new(obj, to, anim));
+        } else {
+            warn!("{:?}: {:?} is not a critter", ctx.prg.opcode.unwrap().0, obj);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn reg_anim_obj_move_to_obj(ctx: Context) -> Result<()> {
+    reg_anim_obj_move0(ctx, CritterAnim::Walk)
+}
+
+pub fn reg_anim_obj_move_to_tile(ctx: Context) -> Result<()> {
+    reg_anim_obj_move0(ctx, CritterAnim::Walk)
+}
+
+pub fn reg_anim_obj_run_to_obj(ctx: Context) -> Result<()> {
+    reg_anim_obj_move0(ctx, CritterAnim::Running)
+}
+
+pub fn reg_anim_obj_run_to_tile(ctx: Context) -> Result<()> {
+    reg_anim_obj_move0(ctx, CritterAnim::Running)
+}
+
+// sfall
+pub fn reg_anim_turn_towards(mut ctx: Context) -> Result<()> {
+    let delay = ctx.prg.data_stack.pop()?.into_int()?;
+    let to = ctx.prg.data_stack.pop()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a3!(ctx.prg, obj, to, delay);
+
+    let to = match to {
+        Value::Int(tile_num) => from_tile_num(&ctx, tile_num).map(RotateTo::Point),
+        to => to.coerce_into_object()?.map(RotateTo::Object),
+    };
+    if let (Some(obj), Some(to)) = (obj, to) {
+        reg_anim(&mut ctx, obj, delay, Rotate::new(obj, to));
+    }
+
+    Ok(())
+}
+
 pub fn rm_timer_event(ctx: Context) -> Result<()> {
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
         .ok_or(Error::BadValue(BadValue::Content))?;
 
     log_a1!(ctx.prg, obj);
-    log_stub!(ctx.prg);
+
+    if let Some((sid, _)) = ctx.ext.world.objects().get(obj).script {
+        ctx.ext.timer_events.remove(sid);
+    }
 
     Ok(())
 }
@@ -1155,6 +1872,84 @@ pub fn set_obj_visibility(ctx: Context) -> Result<()> {
     Ok(())
 }
 
+pub fn sfx_build_char_name(ctx: Context) -> Result<()> {
+    let sound = ctx.prg.data_stack.pop()?.into_int()?;
+    let anim_code = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+
+    let fid = if_chain! {
+        if let Some(anim) = critter_anim(&ctx, obj, anim_code);
+        if let Some(fid) = ctx.ext.world.objects().get(obj).fid.critter();
+        then {
+            let fid = fid.with_anim(anim);
+            // For TakeOut the sound argument is the weapon being taken out.
+            if anim == CritterAnim::TakeOut {
+                WeaponKind::from_i32(sound).map(|w| fid.with_weapon(w))
+            } else {
+                Some(fid)
+            }
+        } else {
+            None
+        }
+    };
+    let name = fid
+        .and_then(|fid| {
+            let sound = CharacterSound::from_i32(sound).unwrap_or(CharacterSound::Normal);
+            sfx::character_sfx_names(fid.into(), sound, ctx.ext.world.frm_db())
+                .into_iter().next()
+        })
+        .unwrap_or_default();
+
+    ctx.prg.data_stack.push(Value::from(BString::from(name.as_bytes())))?;
+    log_a3r1!(ctx.prg, obj, anim_code, sound, ctx.prg.data_stack.top().unwrap());
+
+    Ok(())
+}
+
+pub fn sfx_build_open_name(ctx: Context) -> Result<()> {
+    let action = ctx.prg.data_stack.pop()?.into_int()?;
+    let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    let action_ = OpenAction::from_i32(action).ok_or(Error::BadValue(BadValue::Content))?;
+
+    let name = sfx::open_sfx_name(&ctx.ext.world.objects().get(obj), action_)
+        .unwrap_or_default();
+
+    ctx.prg.data_stack.push(Value::from(BString::from(name.as_bytes())))?;
+    log_a2r1!(ctx.prg, obj, action, ctx.prg.data_stack.top().unwrap());
+
+    Ok(())
+}
+
+pub fn sfx_build_weapon_name(ctx: Context) -> Result<()> {
+    let target = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+    let hit_mode = ctx.prg.data_stack.pop()?.into_int()?;
+    let weapon = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    let sound = ctx.prg.data_stack.pop()?.into_int()?;
+    let sound_ = WeaponSound::from_i32(sound).ok_or(Error::BadValue(BadValue::Content))?;
+
+    // Left and right hand primary modes and punch.
+    let attack_group = if matches!(hit_mode, 0 | 2 | 4) {
+        AttackGroup::Primary
+    } else {
+        AttackGroup::Secondary
+    };
+    let name = {
+        let objs = ctx.ext.world.objects();
+        let targeto = target.map(|t| objs.get(t));
+        sfx::weapon_sfx_names(sound_, &objs.get(weapon), attack_group, targeto.as_deref())
+            .into_iter().next()
+            .unwrap_or_default()
+    };
+
+    ctx.prg.data_stack.push(Value::from(BString::from(name.as_bytes())))?;
+    log_a4r1!(ctx.prg, sound, weapon, hit_mode, target, ctx.prg.data_stack.top().unwrap());
+
+    Ok(())
+}
+
 pub fn start_gdialog(mut ctx: Context) -> Result<()> {
     let background = ctx.prg.data_stack.pop()?.into_int()?;
     let head_id = ctx.prg.data_stack.pop()?.into_int()?;
@@ -1163,11 +1958,26 @@ pub fn start_gdialog(mut ctx: Context) -> Result<()> {
     let program_id = pop_program_id(&mut ctx)?;
 
     // TODO disallow in combat state
-    // TODO handle head_id
+    // TODO show the talking head
     // TODO check for can_talk() (or can_talk_now()?)
 
     assert!(ctx.ext.dialog.is_none());
-    *ctx.ext.dialog = Some(Dialog::show(ctx.ext.ui, ctx.ext.world, objh));
+    let mut dialog = Dialog::show(ctx.ext.ui, ctx.ext.world, objh);
+    // gdialogSetupSpeech
+    dialog.speech_dir = u16::try_from(head_id).ok()
+        .and_then(|idx| FrameId::new_head(0, 0, idx))
+        .and_then(|fid| ctx.ext.world.frm_db().base_name(fid))
+        .map(|name| name.to_ascii_lowercase());
+    dialog.mood = if reaction == -1 {
+        // Let the engine decide.
+        let objs = ctx.ext.world.objects();
+        let on_drugs = ctx.ext.world.drug_effects.is_on_drugs(objs.dude());
+        let value = reaction::reaction(objs, ctx.ext.rpg, ctx.ext.global_vars, on_drugs);
+        reaction::Level::from_value(value).mood()
+    } else {
+        reaction
+    };
+    *ctx.ext.dialog = Some(dialog);
 
     log_a5!(ctx.prg, program_id, objh, reaction, head_id, background);
 
@@ -1244,7 +2054,7 @@ pub fn roll_vs_skill(ctx: Context) -> Result<()> {
     let obj = ctx.prg.data_stack.pop()?.coerce_into_object()?;
 
     let r = if let Some(obj) = obj {
-        let roll_checker = ctx.ext.world.game_time.roll_checker();
+        let roll_checker = ctx.ext.world.game_time.roll_checker(Stream::Script);
         let (r, _) = ctx.ext.rpg.roll_check_skill(skill, bonus, roll_checker,
             &ctx.ext.world.objects().get(obj), ctx.ext.world.objects());
         r
@@ -1345,3 +2155,63 @@ pub fn tile_num_in_direction(ctx: Context) -> Result<()> {
 
     Ok(())
 }
+
+// op_use_obj_on_obj
+pub fn use_obj_on_obj(mut ctx: Context) -> Result<()> {
+    let target = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+    let item = ctx.prg.data_stack.pop()?.coerce_into_object()?;
+
+    log_a2!(ctx.prg, item, target);
+
+    let user = ctx.ext.self_obj;
+    if let (Some(user), Some(item), Some(target)) = (user, item, target) {
+        if is_critter(&ctx, user) {
+            // action_use_an_item_on_object
+            let seq = Move::new(user, PathTo::Object(target), CritterAnim::Walk)
+                .then(PushEvent::new(sequence::Event::UseItemOn { user, item, target }));
+            start_anim(&mut ctx, user, true, seq);
+        } else {
+            // TODO obj_use_item_on() for non-critter self_obj
+            warn!("{:?}: {:?} is not a critter", ctx.prg.opcode.unwrap().0, user);
+        }
+    }
+
+    Ok(())
+}
+
+// op_wield_obj_critter
+pub fn wield_obj_critter(ctx: Context) -> Result<()> {
+    let item = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+    let critter = ctx.prg.data_stack.pop()?.coerce_into_object()?
+        .ok_or(Error::BadValue(BadValue::Content))?;
+
+    log_a2!(ctx.prg, critter, item);
+
+    let objs = ctx.ext.world.objects();
+    let slot = {
+        let critter_obj = objs.get(critter);
+        let item_obj = objs.get(item);
+        let hand = if let Some(hand) = critter_obj.active_hand() {
+            hand
+        } else {
+            log_error!(ctx.prg, "not a critter");
+            return Ok(());
+        };
+        if !critter_obj.inventory.items.iter().any(|i| i.object == item) {
+            log_error!(ctx.prg, "item is not in the critter's inventory");
+            return Ok(());
+        }
+        match item_obj.proto().map(|p| p.kind()) {
+            Some(ExactEntityKind::Item(ItemKind::Armor)) => EquipmentSlot::Armor,
+            Some(ExactEntityKind::Item(_)) => EquipmentSlot::Hand(hand),
+            _ => {
+                log_error!(ctx.prg, "not an item");
+                return Ok(());
+            }
+        }
+    };
+    ctx.ext.world.objects_mut().wield(critter, item, slot, ctx.ext.rpg);
+
+    Ok(())
+}
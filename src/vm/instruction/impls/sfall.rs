@@ -0,0 +1,283 @@
+//! Instructions of the sfall script extender. Only the commonly used subset is implemented so
+//! that mod scripts compiled with sfall opcodes can run. Hook registration is accepted but the
+//! hooks are never invoked.
+
+use log::*;
+
+use super::*;
+use crate::vm::sfall::{self as ext, FormatArg, GlobalKey};
+
+fn pop_global_key(ctx: &mut Context) -> Result<GlobalKey> {
+    Ok(match ctx.prg.data_stack.pop()? {
+        Value::Int(v) => GlobalKey::Id(v),
+        Value::String(v) => GlobalKey::Name(v.resolve(ctx.prg.strings())?),
+        _ => return Err(Error::BadValue(BadValue::Type)),
+    })
+}
+
+fn pop_string(ctx: &mut Context) -> Result<Rc<BString>> {
+    ctx.prg.data_stack.pop()?.into_string(ctx.prg.strings())
+}
+
+fn new_array(ctx: Context, temp: bool) -> Result<()> {
+    let flags = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let len = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let r = ctx.prg.instr_state.sfall.borrow_mut().arrays.create(len, temp);
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a2r1!(ctx.prg, len, flags, r);
+    Ok(())
+}
+
+fn warn_no_array(ctx: &Context, id: i32) {
+    warn!("{:?}: array {} doesn't exist", ctx.prg.opcode.unwrap().0, id);
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub fn array_key(ctx: Context) -> Result<()> {
+    let index = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    let r = if index >= 0 {
+        ctx.prg.instr_state.sfall.borrow().arrays.key(id, index as usize)
+    } else {
+        None
+    }.unwrap_or(Value::Int(0));
+    ctx.prg.data_stack.push(r)?;
+    log_a2r1!(ctx.prg, id, index, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
+pub fn atof(mut ctx: Context) -> Result<()> {
+    let s = pop_string(&mut ctx)?;
+    let r = ext::atof(s.as_bytes());
+    ctx.prg.data_stack.push(Value::Float(r))?;
+    log_a1r1!(ctx.prg, s, r);
+    Ok(())
+}
+
+pub fn atoi(mut ctx: Context) -> Result<()> {
+    let s = pop_string(&mut ctx)?;
+    let r = ext::atoi(s.as_bytes());
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a1r1!(ctx.prg, s, r);
+    Ok(())
+}
+
+pub fn create_array(ctx: Context) -> Result<()> {
+    new_array(ctx, false)
+}
+
+pub fn fix_array(ctx: Context) -> Result<()> {
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, id);
+    if !ctx.prg.instr_state.sfall.borrow_mut().arrays.fix(id) {
+        warn_no_array(&ctx, id);
+    }
+    Ok(())
+}
+
+pub fn free_array(ctx: Context) -> Result<()> {
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, id);
+    if !ctx.prg.instr_state.sfall.borrow_mut().arrays.free(id) {
+        warn_no_array(&ctx, id);
+    }
+    Ok(())
+}
+
+pub fn game_loaded(ctx: Context) -> Result<()> {
+    let r = !ctx.prg.instr_state.game_loaded_checked;
+    ctx.prg.instr_state.game_loaded_checked = true;
+    ctx.prg.data_stack.push(r.into())?;
+    log_r1!(ctx.prg, r);
+    Ok(())
+}
+
+pub fn get_array(ctx: Context) -> Result<()> {
+    let key = ctx.prg.data_stack.pop()?.resolved(ctx.prg.strings())?;
+    let array = ctx.prg.data_stack.pop()?;
+    let r = match array {
+        // sfall allows indexing strings by char.
+        Value::String(s) => {
+            let s = s.resolve(ctx.prg.strings())?;
+            let i = key.clone().coerce_into_int()?;
+            let c: &[u8] = if i >= 0 { ext::substr(s.as_bytes(), i, 1) } else { &[] };
+            Value::from(BString::from(c))
+        }
+        _ => {
+            let id = array.into_int()?;
+            let r = ctx.prg.instr_state.sfall.borrow().arrays.get(id, &key).cloned();
+            r.unwrap_or(Value::Int(0))
+        }
+    };
+    ctx.prg.data_stack.push(r)?;
+    log_a1r1!(ctx.prg, key, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
+pub fn get_sfall_global_float(mut ctx: Context) -> Result<()> {
+    let key = pop_global_key(&mut ctx)?;
+    let r = f32::from_bits(ctx.prg.instr_state.sfall.borrow().global(&key) as u32);
+    ctx.prg.data_stack.push(Value::Float(r))?;
+    log_a1r1!(ctx.prg, key, r);
+    Ok(())
+}
+
+pub fn get_sfall_global_int(mut ctx: Context) -> Result<()> {
+    let key = pop_global_key(&mut ctx)?;
+    let r = ctx.prg.instr_state.sfall.borrow().global(&key);
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a1r1!(ctx.prg, key, r);
+    Ok(())
+}
+
+pub fn len_array(ctx: Context) -> Result<()> {
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    let r = ctx.prg.instr_state.sfall.borrow().arrays.len(id).map(|v| v as i32).unwrap_or(-1);
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a1r1!(ctx.prg, id, r);
+    Ok(())
+}
+
+pub fn register_hook(ctx: Context) -> Result<()> {
+    let hook = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, hook);
+    log_stub!(ctx.prg);
+    Ok(())
+}
+
+pub fn register_hook_proc(ctx: Context) -> Result<()> {
+    let proc_id = ctx.prg.data_stack.pop()?.into_int()?;
+    let hook = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a2!(ctx.prg, hook, proc_id);
+    log_stub!(ctx.prg);
+    Ok(())
+}
+
+pub fn resize_array(ctx: Context) -> Result<()> {
+    let len = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a2!(ctx.prg, id, len);
+    if len < 0 {
+        // Negative lengths are sort modes in sfall.
+        warn!("{:?}: array sorting is not supported", ctx.prg.opcode.unwrap().0);
+        return Ok(());
+    }
+    if !ctx.prg.instr_state.sfall.borrow_mut().arrays.resize(id, len as usize) {
+        warn_no_array(&ctx, id);
+    }
+    Ok(())
+}
+
+pub fn scan_array(ctx: Context) -> Result<()> {
+    let value = ctx.prg.data_stack.pop()?.resolved(ctx.prg.strings())?;
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    let r = ctx.prg.instr_state.sfall.borrow().arrays.scan(id, &value)
+        .unwrap_or(Value::Int(-1));
+    ctx.prg.data_stack.push(r)?;
+    log_a2r1!(ctx.prg, id, value, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
+pub fn set_array(ctx: Context) -> Result<()> {
+    let value = ctx.prg.data_stack.pop()?.resolved(ctx.prg.strings())?;
+    let key = ctx.prg.data_stack.pop()?.resolved(ctx.prg.strings())?;
+    let id = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a3!(ctx.prg, id, key, value);
+    let ok = ctx.prg.instr_state.sfall.borrow_mut().arrays.set(id, key.clone(), value);
+    if !ok {
+        warn!("{:?}: can't set element {:?} of array {}", ctx.prg.opcode.unwrap().0, key, id);
+    }
+    Ok(())
+}
+
+pub fn set_global_script_repeat(ctx: Context) -> Result<()> {
+    let frames = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, frames);
+    log_stub!(ctx.prg);
+    Ok(())
+}
+
+pub fn set_global_script_type(ctx: Context) -> Result<()> {
+    let kind = ctx.prg.data_stack.pop()?.into_int()?;
+    log_a1!(ctx.prg, kind);
+    log_stub!(ctx.prg);
+    Ok(())
+}
+
+pub fn set_sfall_global(mut ctx: Context) -> Result<()> {
+    let value = match ctx.prg.data_stack.pop()? {
+        Value::Int(v) => v,
+        Value::Float(v) => v.to_bits() as i32,
+        _ => return Err(Error::BadValue(BadValue::Type)),
+    };
+    let key = pop_global_key(&mut ctx)?;
+    log_a2!(ctx.prg, key, value);
+    ctx.prg.instr_state.sfall.borrow_mut().globals.insert(key, value);
+    Ok(())
+}
+
+pub fn sprintf(ctx: Context) -> Result<()> {
+    let arg = ctx.prg.data_stack.pop()?;
+    let fmt = ctx.prg.data_stack.pop()?.into_string(ctx.prg.strings())?;
+    let arg_str;
+    let format_arg = match arg.clone() {
+        Value::Int(v) => FormatArg::Int(v),
+        Value::Float(v) => FormatArg::Float(v),
+        Value::String(v) => {
+            arg_str = v.resolve(ctx.prg.strings())?;
+            FormatArg::String(arg_str.as_bytes())
+        }
+        Value::Object(_) => return Err(Error::BadValue(BadValue::Type)),
+    };
+    let r = ext::sprintf(fmt.as_bytes(), format_arg);
+    ctx.prg.data_stack.push(Value::from(BString::from(&r[..])))?;
+    log_a2r1!(ctx.prg, fmt, arg, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
+pub fn string_split(mut ctx: Context) -> Result<()> {
+    let sep = pop_string(&mut ctx)?;
+    let s = pop_string(&mut ctx)?;
+    let parts = ext::split(s.as_bytes(), sep.as_bytes()).into_iter()
+        .map(|p| Value::from(BString::from(p)))
+        .collect();
+    let r = ctx.prg.instr_state.sfall.borrow_mut().arrays.create_list(parts);
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a2r1!(ctx.prg, s, sep, r);
+    Ok(())
+}
+
+pub fn strlen(mut ctx: Context) -> Result<()> {
+    let s = pop_string(&mut ctx)?;
+    let r = s.as_bytes().len() as i32;
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a1r1!(ctx.prg, s, r);
+    Ok(())
+}
+
+pub fn substr(mut ctx: Context) -> Result<()> {
+    let len = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let start = ctx.prg.data_stack.pop()?.coerce_into_int()?;
+    let s = pop_string(&mut ctx)?;
+    let r = BString::from(ext::substr(s.as_bytes(), start, len));
+    ctx.prg.data_stack.push(Value::from(r))?;
+    log_a3r1!(ctx.prg, s, start, len, ctx.prg.data_stack.top().unwrap());
+    Ok(())
+}
+
+pub fn temp_array(ctx: Context) -> Result<()> {
+    new_array(ctx, true)
+}
+
+pub fn typeof_(ctx: Context) -> Result<()> {
+    let v = ctx.prg.data_stack.pop()?;
+    let r = match v.kind() {
+        ValueKind::Int | ValueKind::Object => 1,
+        ValueKind::Float => 2,
+        ValueKind::String => 3,
+    };
+    ctx.prg.data_stack.push(Value::Int(r))?;
+    log_a1r1!(ctx.prg, v, r);
+    Ok(())
+}
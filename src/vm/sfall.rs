@@ -0,0 +1,542 @@
+//! State of the sfall script extensions: sfall globals and arrays.
+//!
+//! Unlike the program state this is shared by all programs of the VM. Scripts commonly pass
+//! array IDs to each other through the sfall globals.
+
+use bstring::BString;
+use log::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::value::Value;
+
+/// Key of a sfall global variable. sfall accepts either a name (by convention 8 chars long)
+/// or a number.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum GlobalKey {
+    Name(Rc<BString>),
+    Id(i32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ArrayData {
+    List(Vec<Value>),
+    /// Associative array, keeps the insertion order.
+    Map(Vec<(Value, Value)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Array {
+    data: ArrayData,
+    /// Temporary arrays are freed when the program invocation is done unless fixed with
+    /// `fix_array()`.
+    temp: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Arrays {
+    arrays: HashMap<i32, Array>,
+    next_id: i32,
+}
+
+impl Arrays {
+    /// Creates new array and returns its ID. Negative `len` creates associative array.
+    // create_array
+    pub fn create(&mut self, len: i32, temp: bool) -> i32 {
+        let data = if len < 0 {
+            ArrayData::Map(Vec::new())
+        } else {
+            ArrayData::List(vec![Value::Int(0); len as usize])
+        };
+        // Zero is the invalid array ID.
+        self.next_id += 1;
+        let id = self.next_id;
+        self.arrays.insert(id, Array { data, temp });
+        id
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        self.arrays.contains_key(&id)
+    }
+
+    pub fn free(&mut self, id: i32) -> bool {
+        self.arrays.remove(&id).is_some()
+    }
+
+    pub fn fix(&mut self, id: i32) -> bool {
+        if let Some(a) = self.arrays.get_mut(&id) {
+            a.temp = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn free_temp(&mut self) {
+        self.arrays.retain(|_, a| !a.temp);
+    }
+
+    pub fn len(&self, id: i32) -> Option<usize> {
+        self.arrays.get(&id).map(|a| match &a.data {
+            ArrayData::List(v) => v.len(),
+            ArrayData::Map(v) => v.len(),
+        })
+    }
+
+    /// Returns value at `key` or `None` if there's no such array or key.
+    pub fn get(&self, id: i32, key: &Value) -> Option<&Value> {
+        match &self.arrays.get(&id)?.data {
+            ArrayData::List(v) => list_index(key).and_then(|i| v.get(i)),
+            ArrayData::Map(v) => v.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+        }
+    }
+
+    /// Sets value at `key`. List arrays can't grow this way, the `key` must be in bounds.
+    pub fn set(&mut self, id: i32, key: Value, value: Value) -> bool {
+        let a = if let Some(a) = self.arrays.get_mut(&id) {
+            a
+        } else {
+            return false;
+        };
+        match &mut a.data {
+            ArrayData::List(v) => {
+                if let Some(e) = list_index(&key).and_then(move |i| v.get_mut(i)) {
+                    *e = value;
+                    true
+                } else {
+                    false
+                }
+            }
+            ArrayData::Map(v) => {
+                if let Some(e) = v.iter_mut().find(|(k, _)| k == &key) {
+                    e.1 = value;
+                } else {
+                    v.push((key, value));
+                }
+                true
+            }
+        }
+    }
+
+    /// Changes length of the array. New list elements are zeroes, associative arrays can only
+    /// shrink.
+    pub fn resize(&mut self, id: i32, len: usize) -> bool {
+        if let Some(a) = self.arrays.get_mut(&id) {
+            match &mut a.data {
+                ArrayData::List(v) => v.resize(len, Value::Int(0)),
+                ArrayData::Map(v) => v.truncate(len),
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns key of the element at `index`. For lists this is the `index` itself.
+    pub fn key(&self, id: i32, index: usize) -> Option<Value> {
+        match &self.arrays.get(&id)?.data {
+            ArrayData::List(v) => if index < v.len() {
+                Some(Value::Int(index as i32))
+            } else {
+                None
+            }
+            ArrayData::Map(v) => v.get(index).map(|(k, _)| k.clone()),
+        }
+    }
+
+    /// Returns key of the first element equal to `value`.
+    pub fn scan(&self, id: i32, value: &Value) -> Option<Value> {
+        match &self.arrays.get(&id)?.data {
+            ArrayData::List(v) => v.iter().position(|v| v == value).map(|i| Value::Int(i as i32)),
+            ArrayData::Map(v) => v.iter().find(|(_, v)| v == value).map(|(k, _)| k.clone()),
+        }
+    }
+
+    /// Creates temporary list array with the `values`.
+    pub fn create_list(&mut self, values: Vec<Value>) -> i32 {
+        let id = self.create(0, true);
+        self.arrays.get_mut(&id).unwrap().data = ArrayData::List(values);
+        id
+    }
+}
+
+fn list_index(key: &Value) -> Option<usize> {
+    match *key {
+        Value::Int(i) if i >= 0 => Some(i as usize),
+        Value::Float(f) if f >= 0.0 => Some(f as usize),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Sfall {
+    /// sfall global variables. Float values are stored as their bit pattern, the same as sfall
+    /// does it.
+    pub globals: HashMap<GlobalKey, i32>,
+    pub arrays: Arrays,
+}
+
+impl Sfall {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn global(&self, key: &GlobalKey) -> i32 {
+        self.globals.get(key).copied().unwrap_or_else(|| {
+            debug!("sfall global {:?} is not set", key);
+            0
+        })
+    }
+}
+
+/// Negative `start` is counted from the end of the string. Zero `len` means to the end of
+/// the string, negative - that many chars are cut off from the end.
+pub fn substr(s: &[u8], start: i32, len: i32) -> &[u8] {
+    let slen = s.len() as i32;
+    let start = if start < 0 { (slen + start).max(0) } else { start.min(slen) };
+    let end = if len == 0 {
+        slen
+    } else if len < 0 {
+        slen + len
+    } else {
+        start.saturating_add(len).min(slen)
+    };
+    if end <= start {
+        &[]
+    } else {
+        &s[start as usize..end as usize]
+    }
+}
+
+/// Empty `sep` splits into single chars.
+// string_split
+pub fn split<'a>(s: &'a [u8], sep: &[u8]) -> Vec<&'a [u8]> {
+    if sep.is_empty() {
+        return s.chunks(1).collect();
+    }
+    let mut r = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i + sep.len() <= s.len() {
+        if &s[i..i + sep.len()] == sep {
+            r.push(&s[start..i]);
+            i += sep.len();
+            start = i;
+        } else {
+            i += 1;
+        }
+    }
+    r.push(&s[start..]);
+    r
+}
+
+/// Parses integer prefix of the string the same way as `strtol()` with base 0 does.
+/// Returns 0 if there's no number.
+pub fn atoi(s: &[u8]) -> i32 {
+    let s = trim_start(s);
+    let (neg, s) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (radix, s) = if s.len() > 2 && s[0] == b'0' && (s[1] == b'x' || s[1] == b'X') {
+        (16, &s[2..])
+    } else if s.len() > 1 && s[0] == b'0' {
+        (8, &s[1..])
+    } else {
+        (10, s)
+    };
+    let mut r: i64 = 0;
+    for &c in s {
+        let d = match (c as char).to_digit(radix) {
+            Some(d) => d as i64,
+            None => break,
+        };
+        r = (r * radix as i64 + d).min(i32::MAX as i64 + 1);
+    }
+    let r = if neg { -r } else { r };
+    r.max(i32::MIN as i64).min(i32::MAX as i64) as i32
+}
+
+/// Parses float prefix of the string. Returns 0 if there's no number.
+pub fn atof(s: &[u8]) -> f32 {
+    let s = trim_start(s);
+    let mut end = 0;
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    let mut seen_exp = false;
+    let mut r = 0.0;
+    while end < s.len() {
+        let c = s[end];
+        let ok = match c {
+            b'0'..=b'9' => {
+                seen_digit = true;
+                true
+            }
+            b'+' | b'-' => end == 0 || s[end - 1] == b'e' || s[end - 1] == b'E',
+            b'.' if !seen_dot && !seen_exp => {
+                seen_dot = true;
+                true
+            }
+            b'e' | b'E' if seen_digit && !seen_exp => {
+                seen_exp = true;
+                true
+            }
+            _ => false,
+        };
+        if !ok {
+            break;
+        }
+        end += 1;
+        // Keep the longest valid prefix, e.g. "1e" and "1." are parsed as "1".
+        if let Ok(v) = std::str::from_utf8(&s[..end]).unwrap().parse() {
+            r = v;
+        }
+    }
+    r
+}
+
+fn trim_start(s: &[u8]) -> &[u8] {
+    let i = s.iter().position(|c| !c.is_ascii_whitespace()).unwrap_or(s.len());
+    &s[i..]
+}
+
+/// Argument of `sprintf()`.
+#[derive(Clone, Copy, Debug)]
+pub enum FormatArg<'a> {
+    Int(i32),
+    Float(f32),
+    String(&'a [u8]),
+}
+
+/// Formats a single argument according to the printf-like format string. Supported are the
+/// `d i u x X o c s f e g %` conversions with the flags `-` and `0`, width and precision.
+/// Conversions after the first one are left as is.
+pub fn sprintf(fmt: &[u8], arg: FormatArg) -> Vec<u8> {
+    let mut r = Vec::with_capacity(fmt.len());
+    let mut arg = Some(arg);
+    let mut i = 0;
+    while i < fmt.len() {
+        let c = fmt[i];
+        i += 1;
+        if c != b'%' {
+            r.push(c);
+            continue;
+        }
+        if fmt.get(i) == Some(&b'%') {
+            r.push(b'%');
+            i += 1;
+            continue;
+        }
+        let spec_start = i - 1;
+
+        let mut left = false;
+        let mut zero = false;
+        while let Some(&c) = fmt.get(i) {
+            match c {
+                b'-' => left = true,
+                b'0' => zero = true,
+                b'+' | b' ' | b'#' => {}
+                _ => break,
+            }
+            i += 1;
+        }
+        let width = parse_num(fmt, &mut i);
+        let precision = if fmt.get(i) == Some(&b'.') {
+            i += 1;
+            Some(parse_num(fmt, &mut i).unwrap_or(0))
+        } else {
+            None
+        };
+        while let Some(b'h') | Some(b'l') = fmt.get(i) {
+            i += 1;
+        }
+        let conv = if let Some(&c) = fmt.get(i) {
+            i += 1;
+            c
+        } else {
+            r.extend_from_slice(&fmt[spec_start..]);
+            break;
+        };
+
+        let arg = if let Some(arg) = arg.take() {
+            arg
+        } else {
+            r.extend_from_slice(&fmt[spec_start..i]);
+            continue;
+        };
+        let int = || match arg {
+            FormatArg::Int(v) => v,
+            FormatArg::Float(v) => v as i32,
+            FormatArg::String(s) => atoi(s),
+        };
+        let float = || match arg {
+            FormatArg::Int(v) => v as f32,
+            FormatArg::Float(v) => v,
+            FormatArg::String(s) => atof(s),
+        };
+        let s: Vec<u8> = match conv {
+            b'd' | b'i' => int().to_string().into_bytes(),
+            b'u' => (int() as u32).to_string().into_bytes(),
+            b'x' => format!("{:x}", int()).into_bytes(),
+            b'X' => format!("{:X}", int()).into_bytes(),
+            b'o' => format!("{:o}", int()).into_bytes(),
+            b'c' => vec![int() as u8],
+            b'f' | b'F' => format!("{:.*}", precision.unwrap_or(6), float()).into_bytes(),
+            b'e' | b'E' => {
+                let s = format!("{:.*e}", precision.unwrap_or(6), float());
+                (if conv == b'E' { s.to_uppercase() } else { s }).into_bytes()
+            }
+            b'g' | b'G' => float().to_string().into_bytes(),
+            b's' => {
+                let s = match arg {
+                    FormatArg::Int(v) => v.to_string().into_bytes(),
+                    FormatArg::Float(v) => format!("{:.5}", v).into_bytes(),
+                    FormatArg::String(s) => s.to_vec(),
+                };
+                match precision {
+                    Some(p) if p < s.len() => s[..p].to_vec(),
+                    _ => s,
+                }
+            }
+            _ => {
+                warn!("sprintf: unsupported conversion: {}",
+                    String::from_utf8_lossy(&fmt[spec_start..i]));
+                r.extend_from_slice(&fmt[spec_start..i]);
+                continue;
+            }
+        };
+
+        let pad = width.unwrap_or(0).saturating_sub(s.len());
+        if left {
+            r.extend_from_slice(&s);
+            r.resize(r.len() + pad, b' ');
+        } else if zero && conv != b's' && conv != b'c' {
+            let sign_len = if s.first() == Some(&b'-') { 1 } else { 0 };
+            let (sign, digits) = s.split_at(sign_len);
+            r.extend_from_slice(sign);
+            r.resize(r.len() + pad, b'0');
+            r.extend_from_slice(digits);
+        } else {
+            r.resize(r.len() + pad, b' ');
+            r.extend_from_slice(&s);
+        }
+    }
+    r
+}
+
+fn parse_num(s: &[u8], i: &mut usize) -> Option<usize> {
+    let start = *i;
+    while s.get(*i).map(|c| c.is_ascii_digit()).unwrap_or(false) {
+        *i += 1;
+    }
+    if *i > start {
+        std::str::from_utf8(&s[start..*i]).unwrap().parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arrays() {
+        let mut a = Arrays::default();
+        let list = a.create(2, false);
+        let map = a.create(-1, true);
+        assert!(list != 0 && map != 0 && list != map);
+
+        assert_eq!(a.len(list), Some(2));
+        assert_eq!(a.get(list, &Value::Int(1)), Some(&Value::Int(0)));
+        assert!(a.set(list, Value::Int(1), Value::Int(42)));
+        assert!(!a.set(list, Value::Int(2), Value::Int(42)));
+        assert_eq!(a.get(list, &Value::Int(1)), Some(&Value::Int(42)));
+        assert_eq!(a.get(list, &Value::Int(-1)), None);
+        assert_eq!(a.scan(list, &Value::Int(42)), Some(Value::Int(1)));
+        assert!(a.resize(list, 3));
+        assert_eq!(a.len(list), Some(3));
+        assert_eq!(a.key(list, 2), Some(Value::Int(2)));
+        assert_eq!(a.key(list, 3), None);
+
+        assert_eq!(a.len(map), Some(0));
+        assert!(a.set(map, "b".into(), Value::Int(1)));
+        assert!(a.set(map, "a".into(), Value::Int(2)));
+        assert!(a.set(map, "b".into(), Value::Int(3)));
+        assert_eq!(a.len(map), Some(2));
+        assert_eq!(a.get(map, &"b".into()), Some(&Value::Int(3)));
+        assert_eq!(a.get(map, &"c".into()), None);
+        assert_eq!(a.key(map, 1), Some(Value::from("a")));
+        assert_eq!(a.scan(map, &Value::Int(2)), Some(Value::from("a")));
+        assert!(a.resize(map, 1));
+        assert_eq!(a.get(map, &"a".into()), None);
+
+        let temp = a.create_list(vec![Value::Int(1)]);
+        assert!(a.fix(map));
+        a.free_temp();
+        assert!(a.contains(map));
+        assert!(!a.contains(temp));
+        assert!(a.free(list));
+        assert!(!a.free(list));
+        assert_eq!(a.len(list), None);
+    }
+
+    #[test]
+    fn substr_() {
+        let s = b"abcdef";
+        assert_eq!(substr(s, 1, 2), b"bc");
+        assert_eq!(substr(s, 1, 0), b"bcdef");
+        assert_eq!(substr(s, -2, 0), b"ef");
+        assert_eq!(substr(s, 1, -2), b"bcd");
+        assert_eq!(substr(s, 4, 10), b"ef");
+        assert_eq!(substr(s, 10, 1), b"");
+        assert_eq!(substr(s, 4, -3), b"");
+    }
+
+    #[test]
+    fn split_() {
+        assert_eq!(split(b"a,bc,,d", b","), vec![&b"a"[..], b"bc", b"", b"d"]);
+        assert_eq!(split(b"a::b", b"::"), vec![&b"a"[..], b"b"]);
+        assert_eq!(split(b"abc", b""), vec![&b"a"[..], b"b", b"c"]);
+        assert_eq!(split(b"", b","), vec![&b""[..]]);
+    }
+
+    #[test]
+    fn atoi_() {
+        assert_eq!(atoi(b"123"), 123);
+        assert_eq!(atoi(b"  -45abc"), -45);
+        assert_eq!(atoi(b"0x1F"), 31);
+        assert_eq!(atoi(b"010"), 8);
+        assert_eq!(atoi(b"abc"), 0);
+        assert_eq!(atoi(b"99999999999"), i32::MAX);
+    }
+
+    #[test]
+    fn atof_() {
+        assert_eq!(atof(b"1.5"), 1.5);
+        assert_eq!(atof(b" -2.25x"), -2.25);
+        assert_eq!(atof(b"1e2"), 100.0);
+        assert_eq!(atof(b"1e"), 1.0);
+        assert_eq!(atof(b"x"), 0.0);
+    }
+
+    #[test]
+    fn sprintf_() {
+        fn f(fmt: &str, arg: FormatArg) -> String {
+            String::from_utf8(sprintf(fmt.as_bytes(), arg)).unwrap()
+        }
+        assert_eq!(f("hp: %d/%d", FormatArg::Int(5)), "hp: 5/%d");
+        assert_eq!(f("%5d|%-5d|%05d", FormatArg::Int(-42)), "  -42|%-5d|%05d");
+        assert_eq!(f("%05d", FormatArg::Int(-42)), "-0042");
+        assert_eq!(f("%x %%", FormatArg::Int(255)), "ff %");
+        assert_eq!(f("%.2f", FormatArg::Float(2.5)), "2.50");
+        assert_eq!(f("%f", FormatArg::Int(1)), "1.000000");
+        assert_eq!(f("[%s]", FormatArg::String(b"abc")), "[abc]");
+        assert_eq!(f("[%-4s]", FormatArg::String(b"ab")), "[ab  ]");
+        assert_eq!(f("[%.1s]", FormatArg::String(b"ab")), "[a]");
+        assert_eq!(f("%c", FormatArg::Int(65)), "A");
+        assert_eq!(f("%d", FormatArg::String(b"12")), "12");
+        assert_eq!(f("50%", FormatArg::Int(1)), "50%");
+    }
+}
@@ -37,6 +37,11 @@ impl<Id: StackId> Stack<Id> {
         self.vec.last()
     }
 
+    /// Returns up to `n` topmost values, the top one is the last.
+    pub fn tail(&self, n: usize) -> &[Value] {
+        &self.vec[self.len().saturating_sub(n)..]
+    }
+
     pub fn push(&mut self, value: Value) -> Result<()> {
         trace!("{}: pushing {:?}", Id::VALUE, value);
         if self.len() < self.max_len {
@@ -82,4 +87,28 @@ impl<Id: StackId> fmt::Debug for Stack<Id> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Stack(id=\"{}\", max_len={}, values={:?})", Id::VALUE, self.max_len, self.vec)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestId;
+
+    impl StackId for TestId {
+        const VALUE: &'static str = "test";
+    }
+
+    #[test]
+    fn tail() {
+        let mut s = Stack::<TestId>::new(10);
+        for i in 0..4 {
+            s.push(Value::Int(i)).unwrap();
+        }
+        s.pop().unwrap();
+        s.pop().unwrap();
+        s.push(Value::Int(10)).unwrap();
+        assert_eq!(s.tail(2), &[Value::Int(1), Value::Int(10)]);
+        assert_eq!(s.tail(10).len(), 3);
+    }
+}